@@ -0,0 +1,57 @@
+//! Shared bench fixtures
+use eva_common::value::Value;
+use eva_common::{ItemKind, OID};
+use std::collections::BTreeMap;
+
+pub const OID_COUNT: usize = 100_000;
+
+/// OID strings spread over a realistic group tree (plant/area/item)
+pub fn oid_strings(count: usize) -> Vec<String> {
+    (0..count)
+        .map(|i| {
+            let kind = match i % 4 {
+                0 => ItemKind::Sensor,
+                1 => ItemKind::Unit,
+                2 => ItemKind::Lvar,
+                _ => ItemKind::Lmacro,
+            };
+            format!("{}:plant{}/area{}/item{}", kind, i % 10, i % 100, i)
+        })
+        .collect()
+}
+
+pub fn oids(count: usize) -> Vec<OID> {
+    oid_strings(count)
+        .iter()
+        .map(|s| s.parse().unwrap())
+        .collect()
+}
+
+/// A map-of-seqs value nested `depth` levels deep, with a few scalars on each level
+pub fn deep_value(depth: usize) -> Value {
+    let mut value = Value::F64(25.5);
+    for level in 0..depth {
+        let mut m = BTreeMap::new();
+        m.insert(Value::String("level".to_owned()), Value::U64(level as u64));
+        m.insert(
+            Value::String("name".to_owned()),
+            Value::String(format!("node{}", level)),
+        );
+        m.insert(
+            Value::String("tags".to_owned()),
+            Value::Seq(vec![Value::String("a".to_owned()), Value::Bool(true)]),
+        );
+        m.insert(Value::String("data".to_owned()), Value::Seq(vec![value]));
+        value = Value::Map(m);
+    }
+    value
+}
+
+/// JSON path to the innermost scalar of [`deep_value`]
+pub fn deep_value_path(depth: usize) -> String {
+    let mut path = "$".to_owned();
+    for _ in 0..depth {
+        path.push_str(".data[0]");
+    }
+    path
+}
@@ -0,0 +1,88 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use eva_common::acl::OIDMaskList;
+use eva_common::events::{RawStateEvent, RawStateEventOwned, ReplicationStateEvent};
+use eva_common::payload::{pack, unpack};
+use eva_common::value::Value;
+use eva_common::{IEID, OID};
+
+mod fixtures;
+
+const DEPTH: usize = 32;
+
+fn bench_oid(c: &mut Criterion) {
+    let strings = fixtures::oid_strings(fixtures::OID_COUNT);
+    let oids = fixtures::oids(fixtures::OID_COUNT);
+    c.bench_function("oid_parse_100k", |b| {
+        b.iter(|| {
+            for s in &strings {
+                black_box(s.parse::<OID>().unwrap());
+            }
+        });
+    });
+    c.bench_function("oid_format_100k", |b| {
+        b.iter(|| {
+            for oid in &oids {
+                black_box(oid.to_string());
+            }
+        });
+    });
+}
+
+fn bench_acl(c: &mut Criterion) {
+    let oids = fixtures::oids(fixtures::OID_COUNT);
+    let masks = OIDMaskList::from_str_list(&[
+        "sensor:plant1/#",
+        "unit:plant2/area12/+",
+        "lvar:+/area5/#",
+        "lmacro:plant9/area99/item99",
+    ])
+    .unwrap();
+    c.bench_function("oid_mask_list_matches_100k", |b| {
+        b.iter(|| oids.iter().filter(|oid| masks.matches(oid)).count());
+    });
+}
+
+fn bench_value(c: &mut Criterion) {
+    let value = fixtures::deep_value(DEPTH);
+    let packed = pack(&value).unwrap();
+    c.bench_function("value_msgpack_pack", |b| {
+        b.iter(|| pack(black_box(&value)).unwrap());
+    });
+    c.bench_function("value_msgpack_unpack", |b| {
+        b.iter(|| unpack::<Value>(black_box(&packed)).unwrap());
+    });
+    let path = fixtures::deep_value_path(DEPTH);
+    c.bench_function("value_jp_lookup", |b| {
+        b.iter(|| value.jp_lookup(black_box(&path)).unwrap().unwrap());
+    });
+}
+
+fn bench_events(c: &mut Criterion) {
+    let value = Value::F64(25.5);
+    let raw = RawStateEvent::new(1, &value);
+    let packed = pack(&raw).unwrap();
+    c.bench_function("raw_state_event_pack", |b| {
+        b.iter(|| pack(black_box(&raw)).unwrap());
+    });
+    c.bench_function("raw_state_event_unpack", |b| {
+        b.iter(|| unpack::<RawStateEventOwned>(black_box(&packed)).unwrap());
+    });
+    let repl = ReplicationStateEvent::new(
+        1,
+        fixtures::deep_value(4),
+        None,
+        IEID::new(1, 1_000_000),
+        1_700_000_000.5,
+        "node1",
+    );
+    let packed = pack(&repl).unwrap();
+    c.bench_function("replication_state_event_pack", |b| {
+        b.iter(|| pack(black_box(&repl)).unwrap());
+    });
+    c.bench_function("replication_state_event_unpack", |b| {
+        b.iter(|| unpack::<ReplicationStateEvent>(black_box(&packed)).unwrap());
+    });
+}
+
+criterion_group!(benches, bench_oid, bench_acl, bench_value, bench_events);
+criterion_main!(benches);
@@ -0,0 +1,36 @@
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use eva_common::value::Value;
+use std::fmt::Write as _;
+
+fn sample() -> Vec<u8> {
+    let mut s = String::from("[");
+    for i in 0..1000 {
+        if i > 0 {
+            s.push(',');
+        }
+        write!(
+            s,
+            r#"{{"oid":"sensor:tests/s{i}","status":1,"value":{i}.5,"t":1700000000.{i},"tags":["a","b"],"meta":{{"unit":"C","ok":true}}}}"#
+        )
+        .unwrap();
+    }
+    s.push(']');
+    s.into_bytes()
+}
+
+fn bench_json(c: &mut Criterion) {
+    let data = sample();
+    c.bench_function("json_serde", |b| {
+        b.iter(|| Value::from_json_slice(black_box(&data)).unwrap());
+    });
+    c.bench_function("json_fast", |b| {
+        b.iter_batched_ref(
+            || data.clone(),
+            |buf| Value::from_json_slice_fast(black_box(buf)).unwrap(),
+            BatchSize::SmallInput,
+        );
+    });
+}
+
+criterion_group!(benches, bench_json);
+criterion_main!(benches);
@@ -0,0 +1,372 @@
+//! Derive macros for EVA ICS v4 commons, use them via the "derive" feature of eva-common
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::parse::ParseStream;
+use syn::{
+    parse_macro_input, Attribute, Data, DeriveInput, Error, Expr, ExprArray, ExprLit, Fields,
+    FnArg, GenericArgument, ImplItem, ItemImpl, Lit, LitStr, Meta, PathArguments, ReturnType, Type,
+};
+
+fn doc_string(attrs: &[Attribute]) -> String {
+    attrs
+        .iter()
+        .filter(|a| a.path().is_ident("doc"))
+        .filter_map(|a| match &a.meta {
+            Meta::NameValue(nv) => match &nv.value {
+                Expr::Lit(ExprLit {
+                    lit: Lit::Str(s), ..
+                }) => Some(s.value().trim().to_owned()),
+                _ => None,
+            },
+            _ => None,
+        })
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Skips a value of an unknown attribute key
+fn skip_meta_value(input: ParseStream) -> syn::Result<()> {
+    if input.peek(syn::Token![=]) {
+        input.parse::<syn::Token![=]>()?;
+        input.parse::<Expr>()?;
+    } else if input.peek(syn::token::Paren) {
+        let content;
+        syn::parenthesized!(content in input);
+        content.parse::<TokenStream2>()?;
+    }
+    Ok(())
+}
+
+#[derive(Default)]
+struct SerdeAttrs {
+    rename: Option<String>,
+    default: bool,
+    skip: bool,
+    flatten: bool,
+}
+
+fn serde_attrs(attrs: &[Attribute]) -> syn::Result<SerdeAttrs> {
+    let mut result = SerdeAttrs::default();
+    for attr in attrs.iter().filter(|a| a.path().is_ident("serde")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("rename") && meta.input.peek(syn::Token![=]) {
+                let s: LitStr = meta.value()?.parse()?;
+                result.rename = Some(s.value());
+            } else if meta.path.is_ident("default") {
+                result.default = true;
+                skip_meta_value(meta.input)?;
+            } else if meta.path.is_ident("skip") || meta.path.is_ident("skip_deserializing") {
+                result.skip = true;
+            } else if meta.path.is_ident("flatten") {
+                result.flatten = true;
+            } else {
+                skip_meta_value(meta.input)?;
+            }
+            Ok(())
+        })?;
+    }
+    Ok(result)
+}
+
+#[derive(Default)]
+struct EapiAttrs {
+    kind: Option<LitStr>,
+    choices: Option<ExprArray>,
+    default: Option<Expr>,
+}
+
+fn eapi_attrs(attrs: &[Attribute]) -> syn::Result<EapiAttrs> {
+    let mut result = EapiAttrs::default();
+    for attr in attrs.iter().filter(|a| a.path().is_ident("eapi")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("kind") {
+                result.kind = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("choices") {
+                result.choices = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("default") {
+                result.default = Some(meta.value()?.parse()?);
+            } else {
+                return Err(meta.error("unsupported eapi attribute"));
+            }
+            Ok(())
+        })?;
+    }
+    Ok(result)
+}
+
+fn last_segment(ty: &Type) -> Option<&syn::PathSegment> {
+    match ty {
+        Type::Path(p) => p.path.segments.last(),
+        Type::Reference(r) => last_segment(&r.elem),
+        _ => None,
+    }
+}
+
+fn option_inner(ty: &Type) -> Option<&Type> {
+    let seg = last_segment(ty)?;
+    if seg.ident != "Option" {
+        return None;
+    }
+    let PathArguments::AngleBracketed(args) = &seg.arguments else {
+        return None;
+    };
+    match args.args.first()? {
+        GenericArgument::Type(t) => Some(t),
+        _ => None,
+    }
+}
+
+fn param_kind(name: &str) -> Option<TokenStream2> {
+    let variant = match name {
+        "any" => quote!(Any),
+        "string" => quote!(String),
+        "number" => quote!(Number),
+        "bool" => quote!(Bool),
+        "oid" => quote!(Oid),
+        "mask" => quote!(Mask),
+        "duration" => quote!(Duration),
+        _ => return None,
+    };
+    Some(quote!(::eva_common::services::ParamKind::#variant))
+}
+
+fn infer_kind(ty: &Type) -> TokenStream2 {
+    let kind = last_segment(ty).map_or("any", |seg| match seg.ident.to_string().as_str() {
+        "String" | "str" => "string",
+        "u8" | "u16" | "u32" | "u64" | "usize" | "i8" | "i16" | "i32" | "i64" | "isize" | "f32"
+        | "f64" => "number",
+        "bool" => "bool",
+        "OID" => "oid",
+        "OIDMask" => "mask",
+        "Duration" => "duration",
+        _ => "any",
+    });
+    param_kind(kind).unwrap()
+}
+
+/// Generates the parameter schema for a method params structure. The parameter types are
+/// inferred from the field types and can be overridden with `#[eapi(kind = "...")]`, choices and
+/// defaults are set with `#[eapi(choices = [...], default = ...)]`, doc comments are used as
+/// descriptions. Fields of `Option` type or with `#[serde(default)]` are optional.
+#[proc_macro_derive(EapiParams, attributes(eapi))]
+pub fn derive_eapi_params(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    eapi_params(&input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn eapi_params(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let Data::Struct(data) = &input.data else {
+        return Err(Error::new_spanned(
+            input,
+            "EapiParams can be derived for structs only",
+        ));
+    };
+    let container = serde_attrs(&input.attrs)?;
+    let mut params = Vec::new();
+    match &data.fields {
+        Fields::Named(fields) => {
+            for field in &fields.named {
+                let serde = serde_attrs(&field.attrs)?;
+                if serde.skip {
+                    continue;
+                }
+                let ty = &field.ty;
+                if serde.flatten {
+                    params.push(quote! {
+                        params.extend(<#ty as ::eva_common::services::EapiParams>::eapi_params());
+                    });
+                    continue;
+                }
+                let name = serde.rename.unwrap_or_else(|| {
+                    let ident = field.ident.as_ref().unwrap().to_string();
+                    ident.strip_prefix("r#").unwrap_or(&ident).to_owned()
+                });
+                let eapi = eapi_attrs(&field.attrs)?;
+                let inner = option_inner(ty);
+                let kind = if let Some(kind) = eapi.kind {
+                    param_kind(&kind.value())
+                        .ok_or_else(|| Error::new_spanned(&kind, "unsupported param kind"))?
+                } else {
+                    infer_kind(inner.unwrap_or(ty))
+                };
+                let ctor = if inner.is_some() || serde.default || container.default {
+                    quote!(optional)
+                } else {
+                    quote!(required)
+                };
+                let mut info = quote!(::eva_common::services::MethodParamInfo::#ctor(#kind));
+                let doc = doc_string(&field.attrs);
+                if !doc.is_empty() {
+                    info.extend(quote!(.description(#doc)));
+                }
+                if let Some(choices) = eapi.choices {
+                    info.extend(quote!(.choices(#choices)));
+                }
+                if let Some(default) = eapi.default {
+                    info.extend(quote!(.default_value(#default)));
+                }
+                params.push(quote! {
+                    params.insert(#name.to_owned(), #info);
+                });
+            }
+        }
+        Fields::Unit => {}
+        Fields::Unnamed(_) => {
+            return Err(Error::new_spanned(
+                input,
+                "EapiParams requires a struct with named fields",
+            ));
+        }
+    }
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::eva_common::services::EapiParams for #ident #ty_generics
+            #where_clause
+        {
+            fn eapi_params() -> ::std::collections::HashMap<
+                ::std::string::String,
+                ::eva_common::services::MethodParamInfo,
+            > {
+                #[allow(unused_mut)]
+                let mut params = ::std::collections::HashMap::new();
+                #(#params)*
+                params
+            }
+        }
+    })
+}
+
+/// Marks a handler method inside an `#[eapi_service]` impl block. The method name can be set
+/// with `#[eapi_method(name = "...")]`
+#[proc_macro_attribute]
+pub fn eapi_method(_args: TokenStream, input: TokenStream) -> TokenStream {
+    input
+}
+
+fn is_eapi_method(attr: &Attribute) -> bool {
+    attr.path()
+        .segments
+        .last()
+        .is_some_and(|s| s.ident == "eapi_method")
+}
+
+/// Generates `eapi_methods()` (the method table for `ServiceInfo`) and `eapi_dispatch()` (the
+/// call dispatcher) from `#[eapi_method]` handlers of the impl block
+///
+/// A handler takes `&self` and optionally a params structure, which implements `EapiParams` and
+/// `Deserialize`, and returns a result with a serializable value.
+#[proc_macro_attribute]
+pub fn eapi_service(_args: TokenStream, input: TokenStream) -> TokenStream {
+    let mut item = parse_macro_input!(input as ItemImpl);
+    match eapi_service_impl(&mut item) {
+        Ok(generated) => quote!(#item #generated).into(),
+        Err(e) => e.into_compile_error().into(),
+    }
+}
+
+fn eapi_service_impl(item: &mut ItemImpl) -> syn::Result<TokenStream2> {
+    let mut methods = Vec::new();
+    let mut arms = Vec::new();
+    for impl_item in &mut item.items {
+        let ImplItem::Fn(f) = impl_item else {
+            continue;
+        };
+        let Some(pos) = f.attrs.iter().position(is_eapi_method) else {
+            continue;
+        };
+        let attr = f.attrs.remove(pos);
+        let mut name = f.sig.ident.to_string();
+        if matches!(attr.meta, Meta::List(_)) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("name") {
+                    let s: LitStr = meta.value()?.parse()?;
+                    name = s.value();
+                    Ok(())
+                } else {
+                    Err(meta.error("unsupported eapi_method attribute"))
+                }
+            })?;
+        }
+        let mut inputs = f.sig.inputs.iter();
+        if !matches!(inputs.next(), Some(FnArg::Receiver(_))) {
+            return Err(Error::new_spanned(&f.sig, "EAPI handlers must take &self"));
+        }
+        let params_ty = match inputs.next() {
+            Some(FnArg::Typed(p)) => Some(&p.ty),
+            Some(FnArg::Receiver(_)) => unreachable!(),
+            None => None,
+        };
+        if inputs.next().is_some() {
+            return Err(Error::new_spanned(
+                &f.sig,
+                "EAPI handlers take at most one params argument",
+            ));
+        }
+        if matches!(f.sig.output, ReturnType::Default) {
+            return Err(Error::new_spanned(
+                &f.sig,
+                "EAPI handlers must return a result",
+            ));
+        }
+        let ident = &f.sig.ident;
+        let call_await = f.sig.asyncness.map(|_| quote!(.await));
+        let description = doc_string(&f.attrs);
+        let (params, call) = if let Some(ty) = params_ty {
+            (
+                quote!(<#ty as ::eva_common::services::EapiParams>::eapi_params()),
+                quote! {
+                    let params: #ty = ::eva_common::services::eapi_unpack_params(payload)?;
+                    let result = self.#ident(params)#call_await?;
+                },
+            )
+        } else {
+            (
+                quote!(::std::collections::HashMap::new()),
+                quote!(let result = self.#ident()#call_await?;),
+            )
+        };
+        methods.push(quote! {
+            ::eva_common::services::ServiceMethod {
+                name: #name.to_owned(),
+                description: #description.to_owned(),
+                params: #params,
+            }
+        });
+        arms.push(quote! {
+            #name => {
+                #call
+                ::eva_common::services::eapi_pack_result(&result)
+            }
+        });
+    }
+    let self_ty = &item.self_ty;
+    let (impl_generics, _, where_clause) = item.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics #self_ty #where_clause {
+            /// EAPI methods, generated from the handlers
+            pub fn eapi_methods() -> ::std::vec::Vec<::eva_common::services::ServiceMethod> {
+                ::std::vec![#(#methods),*]
+            }
+            /// Calls a handler, returns `MethodNotFound` error for unknown methods
+            #[allow(unused_variables)]
+            pub async fn eapi_dispatch(
+                &self,
+                method: &str,
+                payload: &[u8],
+            ) -> ::eva_common::EResult<::std::option::Option<::std::vec::Vec<u8>>> {
+                match method {
+                    #(#arms)*
+                    _ => Err(::eva_common::Error::new(
+                        ::eva_common::ErrorKind::MethodNotFound,
+                        method,
+                    )),
+                }
+            }
+        }
+    })
+}
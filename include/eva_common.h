@@ -0,0 +1,76 @@
+/*
+ * EVA ICS common types C ABI, see src/ffi.rs for the conventions
+ *
+ * Functions, returning intptr_t, return the number of bytes required for the output (nothing is
+ * written if the buffer is too small) or a negative EVA_ERR_* code
+ */
+#ifndef EVA_COMMON_H
+#define EVA_COMMON_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define EVA_FFI_VERSION 1
+#define EVA_FFI_VERSION_MIN 1
+
+#define EVA_ITEM_KIND_UNIT 100
+#define EVA_ITEM_KIND_SENSOR 101
+#define EVA_ITEM_KIND_LVAR 200
+#define EVA_ITEM_KIND_COUNTER 201
+#define EVA_ITEM_KIND_LMACRO 300
+#define EVA_ITEM_KIND_PROG 301
+#define EVA_ITEM_KIND_ALARM 400
+
+#define EVA_ERR_NOT_FOUND (-32001)
+#define EVA_ERR_ACCESS_DENIED (-32002)
+#define EVA_ERR_OTHER (-32004)
+#define EVA_ERR_NOT_READY (-32005)
+#define EVA_ERR_UNSUPPORTED (-32006)
+#define EVA_ERR_CORE_ERROR (-32007)
+#define EVA_ERR_TIMEOUT (-32008)
+#define EVA_ERR_INVALID_DATA (-32009)
+#define EVA_ERR_FUNC_FAILED (-32010)
+#define EVA_ERR_ABORTED (-32011)
+#define EVA_ERR_ALREADY_EXISTS (-32012)
+#define EVA_ERR_BUSY (-32013)
+#define EVA_ERR_METHOD_NOT_IMPLEMENTED (-32014)
+#define EVA_ERR_TOKEN_RESTRICTED (-32015)
+#define EVA_ERR_IO (-32016)
+#define EVA_ERR_REGISTRY (-32017)
+#define EVA_ERR_EVAHI_AUTH_REQUIRED (-32018)
+#define EVA_ERR_ACCESS_DENIED_MORE_DATA_REQUIRED (-32022)
+#define EVA_ERR_METHOD_NOT_FOUND (-32601)
+#define EVA_ERR_INVALID_PARAMS (-32602)
+#define EVA_ERR_BUS_CLIENT_NOT_REGISTERED (-32113)
+#define EVA_ERR_BUS_DATA (-32114)
+#define EVA_ERR_BUS_IO (-32115)
+#define EVA_ERR_BUS_OTHER (-32116)
+#define EVA_ERR_BUS_NOT_SUPPORTED (-32117)
+#define EVA_ERR_BUS_BUSY (-32118)
+#define EVA_ERR_BUS_NOT_DELIVERED (-32119)
+#define EVA_ERR_BUS_TIMEOUT (-32120)
+#define EVA_ERR_BUS_ACCESS (-32121)
+
+uint16_t eva_ffi_version(void);
+int32_t eva_ffi_negotiate(uint16_t max_version);
+
+intptr_t eva_oid_parse(const uint8_t *s, size_t s_len, uint16_t *kind, uint8_t *buf,
+                       size_t buf_len);
+intptr_t eva_oid_format(uint16_t kind, const uint8_t *full_id, size_t full_id_len, uint8_t *buf,
+                        size_t buf_len);
+intptr_t eva_oid_to_path(const uint8_t *s, size_t s_len, uint8_t *buf, size_t buf_len);
+
+intptr_t eva_error_message(int16_t code, uint8_t *buf, size_t buf_len);
+
+intptr_t eva_pack_json(const uint8_t *json, size_t json_len, uint8_t *buf, size_t buf_len);
+intptr_t eva_unpack_json(const uint8_t *data, size_t data_len, uint8_t *buf, size_t buf_len);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
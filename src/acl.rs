@@ -61,6 +61,101 @@ impl PathMask {
             true
         }
     }
+    fn captures<'a>(&self, path: &'a str, captures: &mut Vec<&'a str>) -> bool {
+        let Some(ref chunks) = self.chunks else {
+            captures.push(path);
+            return true;
+        };
+        let mut rest = Some(path);
+        for m_chunk in chunks {
+            let Some(r) = rest else {
+                return false;
+            };
+            if is_str_wildcard(m_chunk) {
+                captures.push(r);
+                return true;
+            }
+            let (i_chunk, next) = match r.split_once('/') {
+                Some((chunk, next)) => (chunk, Some(next)),
+                None => (r, None),
+            };
+            if is_str_any(m_chunk) {
+                captures.push(i_chunk);
+            } else if i_chunk != m_chunk {
+                return false;
+            }
+            rest = next;
+        }
+        rest.is_none()
+    }
+    /// Returns true if all paths, matched by the other mask, are matched by this one. Formula and
+    /// regex chunks cover equal chunks only
+    fn covers(&self, other: &PathMask) -> bool {
+        let Some(ref chunks) = self.chunks else {
+            return true;
+        };
+        let Some(ref other_chunks) = other.chunks else {
+            return false;
+        };
+        let mut o_m = other_chunks.iter();
+        for m_chunk in chunks {
+            let Some(o_chunk) = o_m.next() else {
+                return false;
+            };
+            if is_str_wildcard(m_chunk) {
+                return true;
+            }
+            if is_str_wildcard(o_chunk) || !(is_str_any(m_chunk) || m_chunk == o_chunk) {
+                return false;
+            }
+        }
+        o_m.next().is_none()
+    }
+    /// Returns a mask, which matches paths, matched by both masks, or None if the masks are
+    /// disjoint. Formula and regex chunks are not evaluated, so the result may be wider than the
+    /// exact intersection if the masks contain ones
+    fn intersect(&self, other: &PathMask) -> Option<PathMask> {
+        let (Some(chunks), Some(other_chunks)) = (&self.chunks, &other.chunks) else {
+            return Some(if self.is_any() { other } else { self }.clone());
+        };
+        let mut result = Vec::with_capacity(chunks.len().max(other_chunks.len()));
+        let mut m_m = chunks.iter();
+        let mut o_m = other_chunks.iter();
+        loop {
+            match (m_m.next(), o_m.next()) {
+                (None, None) => break,
+                (Some(m_chunk), Some(o_chunk)) => {
+                    if is_str_wildcard(m_chunk) {
+                        result.push(o_chunk.clone());
+                        result.extend(o_m.cloned());
+                        break;
+                    } else if is_str_wildcard(o_chunk) {
+                        result.push(m_chunk.clone());
+                        result.extend(m_m.cloned());
+                        break;
+                    } else if is_str_any(m_chunk) || is_formula_or_regex(o_chunk) {
+                        result.push(o_chunk.clone());
+                    } else if is_str_any(o_chunk)
+                        || is_formula_or_regex(m_chunk)
+                        || m_chunk == o_chunk
+                    {
+                        result.push(m_chunk.clone());
+                    } else {
+                        return None;
+                    }
+                }
+                _ => return None,
+            }
+        }
+        Some(PathMask {
+            chunks: Some(result),
+        })
+    }
+}
+
+#[inline]
+fn is_formula_or_regex(chunk: &str) -> bool {
+    chunk.starts_with(OID_MASK_PREFIX_FORMULA) || chunk.starts_with(OID_MASK_PREFIX_REGEX)
 }
 
 impl AsRef<PathMask> for PathMask {
@@ -250,15 +345,24 @@ impl FromStr for PathMask {
     }
 }
 
+/// Prefix of exclusion entries in OID mask lists
+pub const OID_MASK_EXCLUDE_PREFIX: char = '!';
+
+/// OID mask list
+///
+/// The list may contain exclusion entries, prefixed with "!" (e.g. "!sensor:env/secret/#"). An
+/// OID is matched if it is matched by any mask and not matched by any exclusion.
 #[derive(Debug, Clone, Default)]
 pub struct OIDMaskList {
     oid_masks: HashSet<OIDMask>,
     acl_map: AclMap,
+    exclusions: HashSet<OIDMask>,
+    exclusion_map: AclMap,
 }
 
 impl PartialEq for OIDMaskList {
     fn eq(&self, other: &Self) -> bool {
-        self.oid_masks == other.oid_masks
+        self.oid_masks == other.oid_masks && self.exclusions == other.exclusions
     }
 }
 
@@ -269,10 +373,14 @@ impl Serialize for OIDMaskList {
     where
         S: Serializer,
     {
-        let mut seq = serializer.serialize_seq(Some(self.oid_masks.len()))?;
+        let mut seq =
+            serializer.serialize_seq(Some(self.oid_masks.len() + self.exclusions.len()))?;
         for element in &self.oid_masks {
             seq.serialize_element(&element.to_string())?;
         }
+        for element in &self.exclusions {
+            seq.serialize_element(&format!("{}{}", OID_MASK_EXCLUDE_PREFIX, element))?;
+        }
         seq.end()
     }
 }
@@ -282,8 +390,8 @@ impl<'de> Deserialize<'de> for OIDMaskList {
     where
         D: Deserializer<'de>,
     {
-        let masks: HashSet<OIDMask> = Deserialize::deserialize(deserializer)?;
-        Ok(OIDMaskList::new(masks))
+        let masks: Vec<String> = Deserialize::deserialize(deserializer)?;
+        OIDMaskList::from_string_list(&masks).map_err(serde::de::Error::custom)
     }
 }
 
@@ -307,45 +415,59 @@ impl OIDMaskList {
         for mask in &oid_masks {
             acl_map.insert(&mask.as_path());
         }
-        Self { oid_masks, acl_map }
+        Self {
+            oid_masks,
+            acl_map,
+            exclusions: HashSet::new(),
+            exclusion_map: create_acl_map(),
+        }
     }
     #[inline]
     pub fn new0(oid_mask: OIDMask) -> Self {
-        let mut acl_map = create_acl_map();
-        acl_map.insert(&oid_mask.as_path());
         let mut oid_masks = HashSet::new();
         oid_masks.insert(oid_mask);
-        Self { oid_masks, acl_map }
+        Self::new(oid_masks)
     }
     #[inline]
     pub fn new_any() -> Self {
-        let mut acl_map = create_acl_map();
-        acl_map.insert(crate::WILDCARD[0]);
-        let mut oid_masks = HashSet::new();
-        oid_masks.insert(OIDMask::new_any());
-        Self { oid_masks, acl_map }
+        Self::new0(OIDMask::new_any())
     }
+    /// Sets exclusion masks
+    pub fn with_exclusions(mut self, exclusions: HashSet<OIDMask>) -> Self {
+        let mut exclusion_map = create_acl_map();
+        for mask in &exclusions {
+            exclusion_map.insert(&mask.as_path());
+        }
+        self.exclusions = exclusions;
+        self.exclusion_map = exclusion_map;
+        self
+    }
+    /// Parses masks, entries prefixed with "!" are parsed as exclusions
     pub fn from_str_list(s_masks: &[&str]) -> EResult<Self> {
         let mut oid_masks = HashSet::new();
+        let mut exclusions = HashSet::new();
         for s in s_masks {
-            oid_masks.insert(s.parse()?);
+            if let Some(x) = s.strip_prefix(OID_MASK_EXCLUDE_PREFIX) {
+                exclusions.insert(x.parse()?);
+            } else {
+                oid_masks.insert(s.parse()?);
+            }
         }
-        Ok(Self::new(oid_masks))
+        Ok(Self::new(oid_masks).with_exclusions(exclusions))
     }
+    /// Parses masks, entries prefixed with "!" are parsed as exclusions
     pub fn from_string_list(s_masks: &[String]) -> EResult<Self> {
-        let mut oid_masks = HashSet::new();
-        for s in s_masks {
-            oid_masks.insert(s.parse()?);
-        }
-        Ok(Self::new(oid_masks))
+        Self::from_str_list(&s_masks.iter().map(String::as_str).collect::<Vec<&str>>())
     }
     #[inline]
     pub fn matches(&self, oid: &OID) -> bool {
-        self.acl_map.matches(oid.as_path())
+        self.acl_map.matches(oid.as_path()) && !self.exclusion_map.matches(oid.as_path())
     }
+    /// Exclusions are checked for the mask path only, partially excluded masks are matched
     #[inline]
     pub fn matches_mask(&self, mask: &OIDMask) -> bool {
-        self.acl_map.matches(&mask.as_path())
+        let path = mask.as_path();
+        self.acl_map.matches(&path) && !self.exclusion_map.matches(&path)
     }
     #[inline]
     pub fn is_empty(&self) -> bool {
@@ -360,8 +482,21 @@ impl OIDMaskList {
         &mut self.oid_masks
     }
     #[inline]
+    pub fn exclusions(&self) -> &HashSet<OIDMask> {
+        &self.exclusions
+    }
+    /// Exclusions are returned prefixed with "!"
+    #[inline]
     pub fn as_string_vec(&self) -> Vec<String> {
-        self.oid_masks.iter().map(ToString::to_string).collect()
+        self.oid_masks
+            .iter()
+            .map(ToString::to_string)
+            .chain(
+                self.exclusions
+                    .iter()
+                    .map(|m| format!("{}{}", OID_MASK_EXCLUDE_PREFIX, m)),
+            )
+            .collect()
     }
     pub fn try_from_iter<I, T>(values: I) -> EResult<Self>
     where
@@ -375,9 +510,106 @@ impl OIDMaskList {
         }
         Ok(Self::new(res))
     }
+    /// Re-roots all masks and exclusions under the group prefix
+    pub fn with_group_prefix(&self, prefix: &str) -> EResult<Self> {
+        let list: Self = self
+            .oid_masks
+            .iter()
+            .map(|m| m.with_group_prefix(prefix))
+            .collect::<EResult<_>>()?;
+        Ok(list.with_exclusions(
+            self.exclusions
+                .iter()
+                .map(|m| m.with_group_prefix(prefix))
+                .collect::<EResult<_>>()?,
+        ))
+    }
+    /// Strips the group prefix from all masks and exclusions
+    pub fn strip_group_prefix(&self, prefix: &str) -> EResult<Self> {
+        let list: Self = self
+            .oid_masks
+            .iter()
+            .map(|m| m.strip_group_prefix(prefix))
+            .collect::<EResult<_>>()?;
+        Ok(list.with_exclusions(
+            self.exclusions
+                .iter()
+                .map(|m| m.strip_group_prefix(prefix))
+                .collect::<EResult<_>>()?,
+        ))
+    }
     pub fn iter(&self) -> hash_set::Iter<'_, OIDMask> {
         <&Self as IntoIterator>::into_iter(self)
     }
+    /// Returns true if the mask is covered by any mask of the list (see [`OIDMask::covers`]) and
+    /// does not intersect with exclusions
+    pub fn covers(&self, mask: &OIDMask) -> bool {
+        self.oid_masks.iter().any(|m| m.covers(mask))
+            && !self.exclusions.iter().any(|x| x.intersect(mask).is_some())
+    }
+    /// Returns true if each mask of the list is covered by the other one. Exclusions of the list
+    /// are not taken into account, so the check may return false negatives for such lists
+    pub fn is_subset_of(&self, other: &OIDMaskList) -> bool {
+        self.oid_masks.iter().all(|m| other.covers(m))
+    }
+    /// Returns masks, covered by other masks of the list (sorted). Of equivalent masks (such as
+    /// "+" and "?" chunks), all but the smallest one are returned
+    pub fn redundant(&self) -> Vec<&OIDMask> {
+        let mut result: Vec<&OIDMask> = self
+            .oid_masks
+            .iter()
+            .filter(|mask| {
+                self.oid_masks
+                    .iter()
+                    .any(|m| m != *mask && m.covers(mask) && (!mask.covers(m) || m < *mask))
+            })
+            .collect();
+        result.sort();
+        result
+    }
+    /// Returns the list without redundant masks, exclusions are kept as-is
+    pub fn minimize(&self) -> Self {
+        let redundant = self.redundant();
+        self.oid_masks
+            .iter()
+            .filter(|m| !redundant.contains(m))
+            .cloned()
+            .collect::<Self>()
+            .with_exclusions(self.exclusions.clone())
+    }
+    /// Returns a minimized list of masks, matching OIDs, matched by any of the lists
+    ///
+    /// Exclusions are kept only if they do not intersect with masks of the other list, so the
+    /// result may be wider than the exact union
+    pub fn union(&self, other: &OIDMaskList) -> Self {
+        let disjoint = |x: &&OIDMask, list: &OIDMaskList| {
+            list.oid_masks.iter().all(|m| m.intersect(x).is_none())
+        };
+        self.oid_masks
+            .iter()
+            .chain(other.oid_masks.iter())
+            .cloned()
+            .collect::<Self>()
+            .with_exclusions(
+                self.exclusions
+                    .iter()
+                    .filter(|x| disjoint(x, other))
+                    .chain(other.exclusions.iter().filter(|x| disjoint(x, self)))
+                    .cloned()
+                    .collect(),
+            )
+            .minimize()
+    }
+    /// Returns a minimized list of masks, matching OIDs, matched by both lists. The result may be
+    /// wider than the exact intersection (see [`OIDMask::intersect`])
+    pub fn intersection(&self, other: &OIDMaskList) -> Self {
+        self.oid_masks
+            .iter()
+            .flat_map(|m| other.oid_masks.iter().filter_map(|o| m.intersect(o)))
+            .collect::<Self>()
+            .with_exclusions(self.exclusions.union(&other.exclusions).cloned().collect())
+            .minimize()
+    }
 }
 
 impl<'a> IntoIterator for &'a OIDMaskList {
@@ -407,6 +639,8 @@ impl AsRef<OIDMaskList> for OIDMaskList {
 #[derive(Debug, Clone, Eq)]
 pub struct OIDMask {
     kind: Option<ItemKind>,
+    /// the raw kind name for [`ItemKind::Other`]
+    other_kind: Option<String>,
     path: PathMask,
 }
 
@@ -430,6 +664,25 @@ impl OIDMask {
     pub fn kind(&self) -> Option<ItemKind> {
         self.kind
     }
+    /// Kind name as in the mask string, differs from the kind for unknown kinds only
+    #[inline]
+    pub fn kind_str(&self) -> Option<&str> {
+        self.kind
+            .as_ref()
+            .map(|kind| self.other_kind.as_deref().unwrap_or(kind.as_str()))
+    }
+    #[inline]
+    fn kind_matches(&self, oid: &OID) -> bool {
+        match self.kind {
+            None => true,
+            Some(ItemKind::Other) => self.other_kind.as_deref() == Some(oid.kind_str()),
+            Some(kind) => kind == oid.kind(),
+        }
+    }
+    #[inline]
+    fn same_kind(&self, other: &OIDMask) -> bool {
+        self.kind == other.kind && self.other_kind == other.other_kind
+    }
     /// A special case, when OID mask can be converted to "wildcard OID" - an OID, where id is the
     /// wildcard symbol. Wildcard OIDs are special types of OIDs, which are fully compatible with
     /// majority of ACL checkers and can be used to obtain data from various database sources,
@@ -444,7 +697,12 @@ impl OIDMask {
                     }
                 }
             }
-            Ok(OID::new0_unchecked(kind, &self.path.to_string())?)
+            Ok(OID::_new0(
+                kind,
+                self.other_kind.as_deref().unwrap_or(kind.as_str()),
+                &self.path.to_string(),
+                None,
+            )?)
         } else {
             Err(Error::invalid_data(ERR_INVALID_OID_MASK_OP))
         }
@@ -458,6 +716,7 @@ impl OIDMask {
                     let kind: ItemKind = s.parse()?;
                     Ok(OIDMask {
                         kind: Some(kind),
+                        other_kind: None,
                         path: PathMask::new_any(),
                     })
                 },
@@ -478,6 +737,7 @@ impl OIDMask {
                         OIDMask::check(p)?;
                         Ok(OIDMask {
                             kind,
+                            other_kind: None,
                             path: p.parse()?,
                         })
                     }
@@ -492,17 +752,9 @@ impl OIDMask {
     #[inline]
     pub fn as_path(&self) -> String {
         if self.path.chunks.is_some() {
-            format!(
-                "{}/{}",
-                if let Some(ref kind) = self.kind {
-                    kind.as_str()
-                } else {
-                    "+"
-                },
-                self.path
-            )
-        } else if let Some(ref kind) = self.kind {
-            format!("{}/#", kind.as_str())
+            format!("{}/{}", self.kind_str().unwrap_or("+"), self.path)
+        } else if let Some(kind) = self.kind_str() {
+            format!("{}/#", kind)
         } else {
             "#".to_owned()
         }
@@ -518,43 +770,157 @@ impl OIDMask {
     pub fn new_any() -> Self {
         OIDMask {
             kind: None,
+            other_kind: None,
             path: PathMask::new_any(),
         }
     }
+    /// Re-roots the mask under the group prefix, e.g. unit:tests/# with the prefix "tenant1"
+    /// becomes unit:tenant1/tests/#. The "any" mask (#) becomes +:tenant1/#
+    pub fn with_group_prefix(&self, prefix: &str) -> EResult<Self> {
+        let prefix = OID::check_group_prefix(prefix)?;
+        let mut mask: OIDMask = format!("+:{}/{}", prefix, self.path).parse()?;
+        mask.kind = self.kind;
+        mask.other_kind.clone_from(&self.other_kind);
+        Ok(mask)
+    }
+    /// Strips the group prefix, the reverse operation for [`OIDMask::with_group_prefix`]
+    pub fn strip_group_prefix(&self, prefix: &str) -> EResult<Self> {
+        let prefix = OID::check_group_prefix(prefix)?;
+        let path = self.path.to_string();
+        let stripped = path
+            .strip_prefix(prefix)
+            .and_then(|s| s.strip_prefix('/'))
+            .filter(|s| !s.is_empty())
+            .ok_or_else(|| {
+                Error::invalid_data(format!(
+                    "OID mask {} is not under the prefix {}",
+                    self, prefix
+                ))
+            })?;
+        let mut mask: OIDMask = format!("+:{}", stripped).parse()?;
+        mask.kind = self.kind;
+        mask.other_kind.clone_from(&self.other_kind);
+        Ok(mask)
+    }
     pub fn matches(&self, oid: &OID) -> bool {
-        let oid_tp = oid.kind();
         let sp = oid.full_id().split('/');
-        if let Some(mask_tp) = self.kind {
-            if mask_tp != oid_tp {
-                return false;
-            }
+        if !self.kind_matches(oid) {
+            return false;
         }
         if self.path.matches_split(&mut sp.clone()) {
             return true;
         }
         false
     }
+    /// Matches the OID and returns parts of its full id, matched by "+" chunks and the "#" one,
+    /// in the mask order
+    pub fn match_captures<'a>(&self, oid: &'a OID) -> Option<Captures<'a>> {
+        if !self.kind_matches(oid) {
+            return None;
+        }
+        let mut values = Vec::new();
+        self.path
+            .captures(oid.full_id(), &mut values)
+            .then_some(Captures { values })
+    }
+    /// Returns true if all OIDs, matched by the other mask, are matched by this one
+    ///
+    /// Formula and regex chunks are not evaluated and cover equal chunks only, so the check may
+    /// return false negatives for such masks
+    pub fn covers(&self, other: &OIDMask) -> bool {
+        if self.kind.is_some() && !self.same_kind(other) {
+            return false;
+        }
+        self.path.covers(&other.path)
+    }
+    /// Returns a mask, matching OIDs, matched by both masks, or None if the masks are disjoint
+    ///
+    /// Formula and regex chunks are not evaluated, so the result may be wider than the exact
+    /// intersection if the masks contain ones
+    pub fn intersect(&self, other: &OIDMask) -> Option<OIDMask> {
+        let source = match (self.kind, other.kind) {
+            (Some(_), Some(_)) if !self.same_kind(other) => return None,
+            (Some(_), _) => self,
+            _ => other,
+        };
+        Some(OIDMask {
+            kind: source.kind,
+            other_kind: source.other_kind.clone(),
+            path: self.path.intersect(&other.path)?,
+        })
+    }
+}
+
+/// Parts of an OID, captured by [`OIDMask::match_captures`]
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Captures<'a> {
+    values: Vec<&'a str>,
+}
+
+impl<'a> Captures<'a> {
+    /// Gets a capture by its number, starting from 1
+    #[inline]
+    pub fn get(&self, n: usize) -> Option<&'a str> {
+        n.checked_sub(1).and_then(|i| self.values.get(i)).copied()
+    }
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = &'a str> + '_ {
+        self.values.iter().copied()
+    }
+    /// Replaces "{N}" placeholders in the template with captures, e.g. "site/{1}/temperature"
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if the template refers to a missing capture
+    pub fn expand(&self, template: &str) -> EResult<String> {
+        let mut result = String::with_capacity(template.len());
+        let mut rest = template;
+        while let Some(pos) = rest.find('{') {
+            result.push_str(&rest[..pos]);
+            rest = &rest[pos + 1..];
+            let end = rest
+                .find('}')
+                .ok_or_else(|| Error::invalid_params(format!("unclosed capture: {}", template)))?;
+            let n: usize = rest[..end].parse().map_err(|_| {
+                Error::invalid_params(format!("invalid capture {}: {}", &rest[..end], template))
+            })?;
+            let value = self.get(n).ok_or_else(|| {
+                Error::invalid_params(format!("capture {} not found: {}", n, template))
+            })?;
+            result.push_str(value);
+            rest = &rest[end + 1..];
+        }
+        result.push_str(rest);
+        Ok(result)
+    }
 }
 
 impl PartialEq for OIDMask {
     fn eq(&self, other: &Self) -> bool {
-        self.kind == other.kind && self.path == other.path
+        self.same_kind(other) && self.path == other.path
     }
 }
 
 impl Ord for OIDMask {
     fn cmp(&self, other: &Self) -> Ordering {
-        if self.kind == other.kind {
-            self.path.cmp(&other.path)
-        } else {
-            self.kind.cmp(&other.kind)
-        }
+        self.kind
+            .cmp(&other.kind)
+            .then_with(|| self.other_kind.cmp(&other.other_kind))
+            .then_with(|| self.path.cmp(&other.path))
     }
 }
 
 impl fmt::Display for OIDMask {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        if let Some(kind) = self.kind {
+        if let Some(kind) = self.kind_str() {
             write!(f, "{}:{}", kind, self.path)
         } else if self.path.is_any() {
             write!(f, "#")
@@ -574,6 +940,7 @@ impl From<OID> for OIDMask {
     fn from(oid: OID) -> Self {
         OIDMask {
             kind: Some(oid.kind()),
+            other_kind: (oid.kind() == ItemKind::Other).then(|| oid.kind_str().to_owned()),
             path: oid.full_id().parse().unwrap(),
         }
     }
@@ -581,11 +948,7 @@ impl From<OID> for OIDMask {
 
 impl From<OID> for OIDMaskList {
     fn from(oid: OID) -> Self {
-        let mask = OIDMask {
-            kind: Some(oid.kind()),
-            path: oid.full_id().parse().unwrap(),
-        };
-        mask.into()
+        OIDMask::from(oid).into()
     }
 }
 
@@ -626,6 +989,7 @@ impl_oidmask_from_str!(&&str);
 impl Hash for OIDMask {
     fn hash<H: Hasher>(&self, hasher: &mut H) {
         self.kind.map_or(0, |v| v as u16).hash(hasher);
+        self.other_kind.hash(hasher);
         self.path.hash(hasher);
     }
 }
@@ -735,6 +1099,26 @@ fn is_false(val: &bool) -> bool {
     !val
 }
 
+/// Deny lists can not contain exclusions
+fn check_deny_items(items: &AclItemsPvt) -> EResult<()> {
+    if let Some(x) = items.items.exclusions().iter().next() {
+        return Err(Error::invalid_data(format!(
+            "exclusions are not allowed in ACL deny lists: {}{}",
+            OID_MASK_EXCLUDE_PREFIX, x
+        )));
+    }
+    Ok(())
+}
+
+fn de_deny_items<'de, D>(deserializer: D) -> Result<AclItemsPvt, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let items = AclItemsPvt::deserialize(deserializer)?;
+    check_deny_items(&items).map_err(serde::de::Error::custom)?;
+    Ok(items)
+}
+
 /// The default ACL, used by most of services. Can be overriden with a custom one
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Acl {
@@ -745,9 +1129,9 @@ pub struct Acl {
     read: AclItemsPvt,
     #[serde(default)]
     write: AclItemsPvt,
-    #[serde(default)]
+    #[serde(default, deserialize_with = "de_deny_items")]
     deny_read: AclItemsPvt,
-    #[serde(default, alias = "deny")]
+    #[serde(default, alias = "deny", deserialize_with = "de_deny_items")]
     deny_write: AclItemsPvt,
     #[serde(default)]
     ops: HashSet<Op>,
@@ -761,15 +1145,32 @@ impl Acl {
     pub fn id(&self) -> &str {
         &self.id
     }
+    /// Returns masks of items, allowed and denied for reading. Exclusions of the read/write
+    /// lists are returned as deny masks (unless the other list covers them)
     pub fn get_items_allow_deny_reading(&self) -> (Vec<String>, Vec<String>) {
         if self.admin {
             (vec!["#".to_owned()], vec![])
         } else {
-            let mut allow: HashSet<String> = self.read.items.as_string_vec().into_iter().collect();
-            let allow_write: HashSet<String> =
-                self.write.items.as_string_vec().into_iter().collect();
-            allow.extend(allow_write);
-            let deny: HashSet<String> = self.deny_read.items.as_string_vec().into_iter().collect();
+            let allow: HashSet<String> = self
+                .read
+                .items
+                .oid_masks()
+                .iter()
+                .chain(self.write.items.oid_masks())
+                .map(ToString::to_string)
+                .collect();
+            let mut deny: HashSet<String> =
+                self.deny_read.items.as_string_vec().into_iter().collect();
+            for (items, other) in [(&self.read, &self.write), (&self.write, &self.read)] {
+                deny.extend(
+                    items
+                        .items
+                        .exclusions()
+                        .iter()
+                        .filter(|x| !other.items.covers(x))
+                        .map(ToString::to_string),
+                );
+            }
             (allow.into_iter().collect(), deny.into_iter().collect())
         }
     }
@@ -928,9 +1329,141 @@ impl Acl {
     }
 }
 
+#[cfg(feature = "payload")]
+const COMPACT_ACL_VERSION: u8 = 1;
+
+/// Compact ACL layout: a msgpack array with mask strings, interned into a single table. Item,
+/// pvt and rpvt masks of read, write, deny_read and deny_write sections are stored as table
+/// indexes
+#[cfg(feature = "payload")]
+#[derive(Serialize, Deserialize)]
+struct CompactAcl<'a> {
+    version: u8,
+    #[serde(borrow)]
+    id: std::borrow::Cow<'a, str>,
+    admin: bool,
+    #[serde(borrow)]
+    strings: Vec<std::borrow::Cow<'a, str>>,
+    sections: [[Vec<u32>; 3]; 4],
+    ops: Vec<Op>,
+    meta: Option<Value>,
+    #[serde(borrow)]
+    from: Vec<std::borrow::Cow<'a, str>>,
+}
+
+#[cfg(feature = "payload")]
+impl Acl {
+    /// Packs the ACL into the compact binary form, which is much smaller and faster to process
+    /// than the regular one, e.g. for hot replication
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn to_compact_bytes(&self) -> EResult<Vec<u8>> {
+        use std::borrow::Cow;
+        use std::collections::HashMap;
+        let mut strings: Vec<Cow<str>> = Vec::new();
+        let mut index: HashMap<String, u32> = HashMap::new();
+        let mut intern = |s: String| {
+            *index.entry(s).or_insert_with_key(|s| {
+                strings.push(Cow::Owned(s.clone()));
+                (strings.len() - 1) as u32
+            })
+        };
+        let mut sections: [[Vec<u32>; 3]; 4] = Default::default();
+        for (section, items) in
+            sections
+                .iter_mut()
+                .zip([&self.read, &self.write, &self.deny_read, &self.deny_write])
+        {
+            section[0] = items
+                .items
+                .as_string_vec()
+                .into_iter()
+                .map(&mut intern)
+                .collect();
+            section[1] = items
+                .pvt
+                .acl_map
+                .list()
+                .into_iter()
+                .map(|s| intern(s.to_owned()))
+                .collect();
+            section[2] = items
+                .rpvt
+                .acl_map
+                .list()
+                .into_iter()
+                .map(|s| intern(s.to_owned()))
+                .collect();
+        }
+        let compact = CompactAcl {
+            version: COMPACT_ACL_VERSION,
+            id: Cow::Borrowed(&self.id),
+            admin: self.admin,
+            strings,
+            sections,
+            ops: self.ops.iter().copied().collect(),
+            meta: self.meta.clone(),
+            from: self
+                .from
+                .iter()
+                .map(|s| Cow::Borrowed(s.as_str()))
+                .collect(),
+        };
+        rmp_serde::to_vec(&compact).map_err(Into::into)
+    }
+    /// Unpacks the ACL from the compact binary form, see [`Acl::to_compact_bytes`]
+    pub fn from_compact_bytes(data: &[u8]) -> EResult<Self> {
+        let compact: CompactAcl = rmp_serde::from_slice(data)?;
+        if compact.version != COMPACT_ACL_VERSION {
+            return Err(Error::invalid_data(format!(
+                "unsupported compact ACL version: {}",
+                compact.version
+            )));
+        }
+        let strs = |idx: &[u32]| -> EResult<Vec<&str>> {
+            idx.iter()
+                .map(|i| {
+                    compact
+                        .strings
+                        .get(*i as usize)
+                        .map(AsRef::as_ref)
+                        .ok_or_else(|| Error::invalid_data("compact ACL: invalid string index"))
+                })
+                .collect()
+        };
+        let mut sections = Vec::with_capacity(4);
+        for [items, pvt, rpvt] in &compact.sections {
+            sections.push(AclItemsPvt {
+                items: OIDMaskList::from_str_list(&strs(items)?)?,
+                pvt: PathMaskList::from_str_list(&strs(pvt)?),
+                rpvt: PathMaskList::from_str_list(&strs(rpvt)?),
+            });
+        }
+        for section in sections.iter().skip(2) {
+            check_deny_items(section)?;
+        }
+        let mut sections = sections.into_iter();
+        let mut next_section = || sections.next().unwrap_or_default();
+        Ok(Self {
+            id: compact.id.into_owned(),
+            admin: compact.admin,
+            read: next_section(),
+            write: next_section(),
+            deny_read: next_section(),
+            deny_write: next_section(),
+            ops: compact.ops.into_iter().collect(),
+            meta: compact.meta,
+            from: compact
+                .from
+                .into_iter()
+                .map(std::borrow::Cow::into_owned)
+                .collect(),
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{Acl, OIDMask, OIDMaskList, PathMask, PathMaskList};
+    use super::{Acl, OIDMask, OIDMaskList, Op, PathMask, PathMaskList};
     use crate::{ItemKind, OID};
 
     #[test]
@@ -995,6 +1528,34 @@ mod tests {
         assert_eq!(mask.chunks.unwrap(), ["data", "#"]);
     }
 
+    #[test]
+    fn test_oid_mask_unknown_kind() {
+        let oid = OID::parse_lenient("foo:a/b").unwrap();
+        let mask = OIDMask::from(oid.clone());
+        assert_eq!(mask.kind(), Some(ItemKind::Other));
+        assert_eq!(mask.kind_str(), Some("foo"));
+        assert_eq!(mask.to_string(), "foo:a/b");
+        assert_eq!(mask.as_path(), "foo/a/b");
+        assert!(mask.matches(&oid));
+        assert!(!mask.matches(&OID::parse_lenient("bar:a/b").unwrap()));
+        assert!(!mask.matches(&"sensor:a/b".parse().unwrap()));
+        assert!(mask
+            .match_captures(&OID::parse_lenient("bar:a/b").unwrap())
+            .is_none());
+        assert_ne!(mask, OIDMask::from(OID::parse_lenient("bar:a/b").unwrap()));
+        assert!(mask
+            .intersect(&OIDMask::from(OID::parse_lenient("bar:a/b").unwrap()))
+            .is_none());
+        assert!(!mask.covers(&OIDMask::from(OID::parse_lenient("bar:a/b").unwrap())));
+        let prefixed = mask.with_group_prefix("t1").unwrap();
+        assert_eq!(prefixed.to_string(), "foo:t1/a/b");
+        assert_eq!(prefixed.strip_group_prefix("t1").unwrap(), mask);
+        assert_eq!(mask.to_wildcard_oid().unwrap(), oid);
+        let list = OIDMaskList::from(oid.clone());
+        assert!(list.matches(&oid));
+        assert!(!list.matches(&OID::parse_lenient("bar:a/b").unwrap()));
+    }
+
     #[test]
     fn test_path_mask_list() {
         let p =
@@ -1121,4 +1682,166 @@ mod tests {
             assert!(!acl.check_rpvt_read(&format!("node3/{pfx}res")));
         }
     }
+
+    #[cfg(feature = "payload")]
+    #[test]
+    fn test_acl_compact() {
+        let acl: Acl = serde_json::from_str(
+            r#"{
+        "id": "op",
+        "read": {"items": ["sensor:env/#", "!sensor:env/secret/#"], "pvt": ["dash/#"]},
+        "write": {"items": ["unit:env/#"], "pvt": ["dash/#"]},
+        "deny_write": {"items": ["unit:env/main"]},
+        "ops": ["log", "moderator"],
+        "meta": {"admin_of": ["env"]},
+        "from": ["op", "operators"]
+        }"#,
+        )
+        .unwrap();
+        let packed = acl.to_compact_bytes().unwrap();
+        assert!(packed.len() < crate::payload::pack(&acl).unwrap().len());
+        let unpacked = Acl::from_compact_bytes(&packed).unwrap();
+        assert_eq!(unpacked.id(), "op");
+        assert_eq!(unpacked.from(), acl.from());
+        assert_eq!(unpacked.meta(), acl.meta());
+        assert_eq!(unpacked.read.items, acl.read.items);
+        assert_eq!(unpacked.deny_write.items, acl.deny_write.items);
+        assert!(unpacked.check_op(Op::Moderator));
+        assert!(!unpacked.check_op(Op::Supervisor));
+        assert!(unpacked.check_item_read(&"sensor:env/t1".parse().unwrap()));
+        assert!(!unpacked.check_item_read(&"sensor:env/secret/t1".parse().unwrap()));
+        assert!(unpacked.check_item_write(&"unit:env/u1".parse().unwrap()));
+        assert!(!unpacked.check_item_write(&"unit:env/main".parse().unwrap()));
+        assert!(unpacked.check_pvt_write("dash/x"));
+        assert!(Acl::from_compact_bytes(&packed[..packed.len() / 2]).is_err());
+        let (mut allow, deny) = acl.get_items_allow_deny_reading();
+        allow.sort();
+        assert_eq!(allow, ["sensor:env/#", "unit:env/#"]);
+        assert_eq!(deny, ["sensor:env/secret/#"]);
+        assert!(serde_json::from_str::<Acl>(
+            r#"{"id":"op","deny_read":{"items":["!sensor:env/#"]},"from":[]}"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_mask_group_prefix() {
+        let mask: OIDMask = "unit:tests/#".parse().unwrap();
+        let prefixed = mask.with_group_prefix("tenant1").unwrap();
+        assert_eq!(prefixed.to_string(), "unit:tenant1/tests/#");
+        assert_eq!(prefixed.strip_group_prefix("tenant1").unwrap(), mask);
+        let mask: OIDMask = "#".parse().unwrap();
+        let prefixed = mask.with_group_prefix("tenant1").unwrap();
+        assert_eq!(prefixed.to_string(), "+:tenant1/#");
+        assert!(mask.strip_group_prefix("tenant1").is_err());
+        assert_eq!(prefixed.strip_group_prefix("tenant1").unwrap(), mask);
+        let mask: OIDMask = "sensor:+/s1".parse().unwrap();
+        assert!(mask.strip_group_prefix("tenant1").is_err());
+        let list = OIDMaskList::from_str_list(&["unit:a/#", "sensor:b/s1"]).unwrap();
+        let prefixed = list.with_group_prefix("t1").unwrap();
+        let mut masks = prefixed.as_string_vec();
+        masks.sort();
+        assert_eq!(masks, vec!["sensor:t1/b/s1", "unit:t1/a/#"]);
+        assert_eq!(prefixed.strip_group_prefix("t1").unwrap(), list);
+    }
+
+    #[test]
+    fn test_mask_algebra() {
+        let m = |s: &str| s.parse::<OIDMask>().unwrap();
+        let list = |masks: &[&str]| OIDMaskList::from_str_list(masks).unwrap();
+        let sorted = |l: OIDMaskList| {
+            let mut v = l.as_string_vec();
+            v.sort();
+            v
+        };
+        assert!(m("#").covers(&m("unit:a/b")));
+        assert!(m("unit:#").covers(&m("unit:a/b")));
+        assert!(!m("unit:#").covers(&m("+:a/b")));
+        assert!(m("+:a/#").covers(&m("unit:a/b/+")));
+        assert!(!m("+:a/#").covers(&m("unit:a")));
+        assert!(m("unit:a/+/c").covers(&m("unit:a/b/c")));
+        assert!(!m("unit:a/b/c").covers(&m("unit:a/+/c")));
+        assert!(!m("unit:a/+").covers(&m("unit:a/#")));
+        assert!(!m("unit:a/+").covers(&m("unit:a/b/c")));
+        assert_eq!(
+            m("+:a/#").intersect(&m("unit:+/b")).unwrap().to_string(),
+            "unit:a/b"
+        );
+        assert!(m("unit:a/#").intersect(&m("sensor:a/#")).is_none());
+        assert!(m("unit:a/b").intersect(&m("unit:a/c")).is_none());
+        assert!(m("unit:a/#").intersect(&m("unit:a")).is_none());
+        let acl = list(&["unit:a/#", "unit:a/b/c", "unit:a/+/d", "sensor:x/y"]);
+        let redundant: Vec<String> = acl.redundant().iter().map(ToString::to_string).collect();
+        assert_eq!(redundant, ["unit:a/+/d", "unit:a/b/c"]);
+        assert_eq!(sorted(acl.minimize()), ["sensor:x/y", "unit:a/#"]);
+        assert!(acl.covers(&m("unit:a/z")));
+        assert!(!acl.covers(&m("unit:#")));
+        assert!(list(&["unit:a/b", "sensor:x/y"]).is_subset_of(&acl));
+        assert!(!acl.is_subset_of(&list(&["unit:a/b"])));
+        assert!(acl.is_subset_of(&list(&["#"])));
+        assert_eq!(
+            sorted(acl.union(&list(&["unit:#", "lvar:x"]))),
+            ["lvar:x", "sensor:x/y", "unit:#"]
+        );
+        assert_eq!(
+            sorted(acl.intersection(&list(&["+:a/b/#", "+:x/#"]))),
+            ["sensor:x/y", "unit:a/b/#"]
+        );
+        assert!(acl.intersection(&list(&["lvar:#"])).is_empty());
+    }
+
+    #[test]
+    fn test_mask_captures() {
+        let mask: OIDMask = "sensor:+/temp".parse().unwrap();
+        let oid: OID = "sensor:room1/temp".parse().unwrap();
+        let captures = mask.match_captures(&oid).unwrap();
+        assert_eq!(captures.get(1), Some("room1"));
+        assert_eq!(captures.get(0), None);
+        assert_eq!(
+            captures.expand("site/{1}/temperature").unwrap(),
+            "site/room1/temperature"
+        );
+        assert!(captures.expand("site/{2}").is_err());
+        assert!(captures.expand("site/{1").is_err());
+        assert!(mask
+            .match_captures(&"unit:room1/temp".parse().unwrap())
+            .is_none());
+        assert!(mask
+            .match_captures(&"sensor:room1/hum".parse().unwrap())
+            .is_none());
+        assert!(mask
+            .match_captures(&"sensor:a/room1/temp".parse().unwrap())
+            .is_none());
+        let mask: OIDMask = "+:+/b/#".parse().unwrap();
+        let oid: OID = "unit:a/b/c/d".parse().unwrap();
+        let captures = mask.match_captures(&oid).unwrap();
+        assert_eq!(captures.iter().collect::<Vec<_>>(), ["a", "c/d"]);
+        assert!(mask.match_captures(&"unit:a/b".parse().unwrap()).is_none());
+        let mask: OIDMask = "#".parse().unwrap();
+        assert_eq!(mask.match_captures(&oid).unwrap().get(1), Some("a/b/c/d"));
+    }
+
+    #[test]
+    fn test_oid_mask_list_exclusions() {
+        let list: OIDMaskList =
+            serde_json::from_str(r#"["sensor:env/#", "!sensor:env/secret/#"]"#).unwrap();
+        assert!(list.matches(&"sensor:env/room1/t".parse().unwrap()));
+        assert!(!list.matches(&"sensor:env/secret/t".parse().unwrap()));
+        assert!(!list.matches(&"unit:env/room1/t".parse().unwrap()));
+        assert_eq!(list.exclusions().len(), 1);
+        let mut masks: Vec<String> =
+            serde_json::from_value(serde_json::to_value(&list).unwrap()).unwrap();
+        masks.sort();
+        assert_eq!(masks, ["!sensor:env/secret/#", "sensor:env/#"]);
+        assert_eq!(OIDMaskList::from_string_list(&masks).unwrap(), list);
+        assert_ne!(list, OIDMaskList::from_str_list(&["sensor:env/#"]).unwrap());
+        let prefixed = list.with_group_prefix("t1").unwrap();
+        assert!(!prefixed.matches(&"sensor:t1/env/secret/t".parse().unwrap()));
+        assert_eq!(prefixed.strip_group_prefix("t1").unwrap(), list);
+        assert!(list.covers(&"sensor:env/room1/#".parse().unwrap()));
+        assert!(!list.covers(&"sensor:env/+/t".parse().unwrap()));
+        let value = crate::value::Value::String("sensor:env/#,!sensor:env/secret/#".to_owned());
+        assert_eq!(OIDMaskList::try_from(value).unwrap(), list);
+        assert!(OIDMaskList::from_str_list(&["!"]).is_err());
+    }
 }
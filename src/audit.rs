@@ -0,0 +1,166 @@
+//! Audit log records (who did what and when), published to [`AUDIT_TOPIC`]
+use crate::events::AUDIT_TOPIC;
+use crate::payload::pack;
+use crate::{EResult, Error, ErrorKind, OID};
+use busrt::client::AsyncClient;
+use busrt::QoS;
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
+
+/// Operation result
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    Success,
+    Failed,
+    Denied,
+}
+
+impl Outcome {
+    /// Log level code (`LOG_LEVEL_*`) for the outcome
+    pub fn severity(self) -> u8 {
+        match self {
+            Outcome::Success => crate::LOG_LEVEL_INFO,
+            Outcome::Failed => crate::LOG_LEVEL_WARN,
+            Outcome::Denied => crate::LOG_LEVEL_ERROR,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Record {
+    /// user, API key or service id
+    pub actor: String,
+    /// ACL id the operation has been authorized with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub acl: Option<String>,
+    pub method: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub oid: Option<OID>,
+    /// resource path for non-item operations (e.g. a pvt file or a registry key)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    pub result: Outcome,
+    /// error code for failed and denied operations
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<i16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trace_id: Option<Uuid>,
+    pub t: f64,
+}
+
+impl Record {
+    /// Creates a successful operation record with the current time
+    pub fn new(actor: &str, method: &str) -> Self {
+        Self {
+            actor: actor.to_owned(),
+            acl: None,
+            method: method.to_owned(),
+            oid: None,
+            path: None,
+            result: Outcome::Success,
+            code: None,
+            message: None,
+            trace_id: None,
+            t: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0.0, |d| d.as_secs_f64()),
+        }
+    }
+    pub fn acl(mut self, acl: &str) -> Self {
+        self.acl = Some(acl.to_owned());
+        self
+    }
+    pub fn oid(mut self, oid: OID) -> Self {
+        self.oid = Some(oid);
+        self
+    }
+    pub fn path(mut self, path: &str) -> Self {
+        self.path = Some(path.to_owned());
+        self
+    }
+    pub fn trace_id(mut self, trace_id: Uuid) -> Self {
+        self.trace_id = Some(trace_id);
+        self
+    }
+    /// Sets the outcome from the operation result. Access errors are recorded as denied
+    pub fn result<T>(mut self, result: &EResult<T>) -> Self {
+        if let Err(e) = result {
+            self.set_error(e);
+        } else {
+            self.result = Outcome::Success;
+        }
+        self
+    }
+    pub fn error(mut self, error: &Error) -> Self {
+        self.set_error(error);
+        self
+    }
+    fn set_error(&mut self, error: &Error) {
+        self.result = match error.kind() {
+            ErrorKind::AccessDenied
+            | ErrorKind::AccessDeniedMoreDataRequired
+            | ErrorKind::TokenRestricted
+            | ErrorKind::BusAccess => Outcome::Denied,
+            _ => Outcome::Failed,
+        };
+        self.code = Some(error.kind() as i16);
+        self.message = error.message().map(ToOwned::to_owned);
+    }
+    #[inline]
+    pub fn severity(&self) -> u8 {
+        self.result.severity()
+    }
+    /// Bus topic for the record: `AUDIT/<method>`
+    pub fn topic(&self) -> String {
+        format!("{}{}", AUDIT_TOPIC, self.method)
+    }
+}
+
+/// Publishes the record to the bus
+pub async fn emit<C>(client: &mut C, record: &Record) -> EResult<()>
+where
+    C: AsyncClient + ?Sized,
+{
+    client
+        .publish(&record.topic(), pack(record)?.into(), QoS::No)
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Outcome, Record};
+    use crate::{EResult, Error, LOG_LEVEL_ERROR};
+
+    #[test]
+    fn test_audit_record() {
+        let res: EResult<()> = Err(Error::access("no write access"));
+        let rec = Record::new("operator", "action")
+            .acl("ops")
+            .oid("unit:tests/u1".parse().unwrap())
+            .result(&res);
+        assert_eq!(rec.result, Outcome::Denied);
+        assert_eq!(rec.severity(), LOG_LEVEL_ERROR);
+        assert_eq!(rec.topic(), "AUDIT/action");
+        let s = serde_json::to_value(&rec).unwrap();
+        assert_eq!(s["result"], "denied");
+        assert_eq!(s["oid"], "unit:tests/u1");
+        assert_eq!(s["message"], "no write access");
+        assert!(s.get("path").is_none());
+        let back: Record = serde_json::from_value(s).unwrap();
+        assert_eq!(back.code, rec.code);
+        let rec = Record::new("svc", "registry.key_set")
+            .path("eva/config")
+            .result(&Err::<(), _>(Error::timeout()));
+        assert_eq!(rec.result, Outcome::Failed);
+        assert_eq!(
+            Record::new("x", "y").result(&Ok(())).result,
+            Outcome::Success
+        );
+    }
+}
@@ -0,0 +1,285 @@
+//! Session primitives for HMI services
+use crate::{EResult, Error};
+use parking_lot::Mutex;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fmt;
+use std::net::IpAddr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Session tokens are prefixed to be distinguishable from API keys
+pub const SESSION_TOKEN_PREFIX: &str = "token:";
+const TOKEN_BYTES: usize = 32;
+
+#[inline]
+fn now() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0.0, |d| d.as_secs_f64())
+}
+
+/// Compares two byte strings in constant time (for the equal-length inputs)
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Generates a new random session token
+pub fn generate_token() -> String {
+    let mut buf = [0u8; TOKEN_BYTES];
+    rand::thread_rng().fill_bytes(&mut buf);
+    format!("{}{}", SESSION_TOKEN_PREFIX, hex::encode(buf))
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthMethod {
+    Password,
+    ApiKey,
+    Otp,
+    /// authenticated by an external provider (e.g. an authentication service)
+    External,
+}
+
+/// Session expiry policy. A session expires when it is idle for longer than `idle` or when it
+/// is older than `absolute`
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ExpiryPolicy {
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "crate::tools::serialize_opt_duration_as_f64",
+        deserialize_with = "crate::tools::de_opt_float_as_duration"
+    )]
+    pub idle: Option<Duration>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "crate::tools::serialize_opt_duration_as_f64",
+        deserialize_with = "crate::tools::de_opt_float_as_duration"
+    )]
+    pub absolute: Option<Duration>,
+}
+
+impl ExpiryPolicy {
+    pub fn new(idle: Option<Duration>, absolute: Option<Duration>) -> Self {
+        Self { idle, absolute }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Session {
+    token: String,
+    pub user: String,
+    pub acl_ids: Vec<String>,
+    /// UNIX timestamp
+    pub created: f64,
+    /// UNIX timestamp of the absolute expiration, None for sessions without absolute expiry
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires: Option<f64>,
+    /// UNIX timestamp of the last activity
+    pub last_active: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_ip: Option<IpAddr>,
+    pub auth_method: AuthMethod,
+}
+
+// the token is not displayed
+impl fmt::Debug for Session {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Session")
+            .field("user", &self.user)
+            .field("acl_ids", &self.acl_ids)
+            .field("created", &self.created)
+            .field("expires", &self.expires)
+            .field("last_active", &self.last_active)
+            .field("source_ip", &self.source_ip)
+            .field("auth_method", &self.auth_method)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Session {
+    /// Creates a new session with a random token
+    pub fn new(
+        user: &str,
+        acl_ids: Vec<String>,
+        auth_method: AuthMethod,
+        policy: &ExpiryPolicy,
+    ) -> Self {
+        let t = now();
+        Self {
+            token: generate_token(),
+            user: user.to_owned(),
+            acl_ids,
+            created: t,
+            expires: policy.absolute.map(|d| t + d.as_secs_f64()),
+            last_active: t,
+            source_ip: None,
+            auth_method,
+        }
+    }
+    pub fn source_ip(mut self, ip: IpAddr) -> Self {
+        self.source_ip = Some(ip);
+        self
+    }
+    #[inline]
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+    /// Checks the session against the policy at the specified time (UNIX timestamp)
+    pub fn is_expired_at(&self, policy: &ExpiryPolicy, t: f64) -> bool {
+        if self.expires.is_some_and(|e| t >= e) {
+            return true;
+        }
+        policy
+            .idle
+            .is_some_and(|idle| t - self.last_active >= idle.as_secs_f64())
+    }
+    #[inline]
+    pub fn is_expired(&self, policy: &ExpiryPolicy) -> bool {
+        self.is_expired_at(policy, now())
+    }
+    /// Marks the session active
+    #[inline]
+    pub fn touch(&mut self) {
+        self.last_active = now();
+    }
+}
+
+#[inline]
+fn token_digest(token: &str) -> [u8; 32] {
+    let mut digest = [0u8; 32];
+    digest.copy_from_slice(&Sha256::digest(token.as_bytes()));
+    digest
+}
+
+/// In-memory session store. Sessions are indexed by token digests and the tokens are compared in
+/// constant time, so lookups do not leak token contents via timing
+pub struct SessionStore {
+    policy: ExpiryPolicy,
+    sessions: Mutex<HashMap<[u8; 32], Session>>,
+}
+
+impl SessionStore {
+    pub fn new(policy: ExpiryPolicy) -> Self {
+        Self {
+            policy,
+            sessions: <_>::default(),
+        }
+    }
+    #[inline]
+    pub fn policy(&self) -> &ExpiryPolicy {
+        &self.policy
+    }
+    /// Creates and stores a new session
+    pub fn create(&self, user: &str, acl_ids: Vec<String>, auth_method: AuthMethod) -> Session {
+        let session = Session::new(user, acl_ids, auth_method, &self.policy);
+        self.insert(session.clone());
+        session
+    }
+    /// Stores a session (e.g. restored from a persistent store)
+    pub fn insert(&self, session: Session) {
+        self.sessions
+            .lock()
+            .insert(token_digest(&session.token), session);
+    }
+    /// Gets an active session by token and marks it active. Expired sessions are removed
+    pub fn get(&self, token: &str) -> EResult<Session> {
+        let digest = token_digest(token);
+        let mut sessions = self.sessions.lock();
+        let Some(session) = sessions.get_mut(&digest) else {
+            return Err(Error::access("invalid token"));
+        };
+        if !constant_time_eq(session.token.as_bytes(), token.as_bytes()) {
+            return Err(Error::access("invalid token"));
+        }
+        if session.is_expired(&self.policy) {
+            sessions.remove(&digest);
+            return Err(Error::access("session expired"));
+        }
+        session.touch();
+        Ok(session.clone())
+    }
+    /// Removes a session (logout)
+    pub fn remove(&self, token: &str) -> Option<Session> {
+        self.sessions.lock().remove(&token_digest(token))
+    }
+    /// Removes all sessions of the user
+    pub fn remove_user(&self, user: &str) {
+        self.sessions.lock().retain(|_, s| s.user != user);
+    }
+    /// Removes expired sessions, should be called periodically
+    pub fn cleanup(&self) {
+        let t = now();
+        self.sessions
+            .lock()
+            .retain(|_, s| !s.is_expired_at(&self.policy, t));
+    }
+    /// Session snapshot for persistent stores
+    pub fn sessions(&self) -> Vec<Session> {
+        self.sessions.lock().values().cloned().collect()
+    }
+    pub fn len(&self) -> usize {
+        self.sessions.lock().len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.sessions.lock().is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{constant_time_eq, AuthMethod, ExpiryPolicy, Session, SessionStore};
+    use crate::ErrorKind;
+    use std::time::Duration;
+
+    #[test]
+    fn test_sessions() {
+        assert!(constant_time_eq(b"abc", b"abc"));
+        assert!(!constant_time_eq(b"abc", b"abd"));
+        assert!(!constant_time_eq(b"abc", b"ab"));
+        let policy = ExpiryPolicy::new(
+            Some(Duration::from_secs(10)),
+            Some(Duration::from_secs(100)),
+        );
+        let s = Session::new(
+            "admin",
+            vec!["admin".to_owned()],
+            AuthMethod::Password,
+            &policy,
+        );
+        assert!(s.token().starts_with("token:"));
+        assert!(!s.is_expired_at(&policy, s.created + 5.0));
+        assert!(s.is_expired_at(&policy, s.created + 10.0));
+        assert!(s.is_expired_at(&ExpiryPolicy::default(), s.created + 100.0));
+        assert!(!s.is_expired_at(&ExpiryPolicy::default(), s.created + 99.0));
+        let store = SessionStore::new(policy);
+        let session = store.create("op", vec!["ops".to_owned()], AuthMethod::ApiKey);
+        assert_eq!(store.get(session.token()).unwrap().user, "op");
+        assert_eq!(
+            store.get("token:x").unwrap_err().kind(),
+            ErrorKind::AccessDenied
+        );
+        let mut expired = session.clone();
+        expired.last_active -= 60.0;
+        store.insert(expired);
+        assert!(store.get(session.token()).is_err());
+        assert!(store.is_empty());
+        let json = serde_json::to_string(&session).unwrap();
+        let restored: Session = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.token(), session.token());
+        assert_eq!(restored.auth_method, AuthMethod::ApiKey);
+        let p: ExpiryPolicy = serde_json::from_str(r#"{"idle":30}"#).unwrap();
+        assert_eq!(p.idle, Some(Duration::from_secs(30)));
+        assert!(serde_json::from_str::<ExpiryPolicy>(r#"{"idle":-1}"#).is_err());
+        assert!(serde_json::from_str::<ExpiryPolicy>(r#"{"absolute":1e30}"#).is_err());
+        assert!(!format!("{:?}", session).contains(session.token()));
+    }
+}
@@ -0,0 +1,250 @@
+//! Out-of-band storage for large binary values
+//!
+//! Large [`Value::Bytes`] payloads (camera frames, waveforms etc.) are put into a blob store and
+//! replaced in events with "blob://<sha256>" references, which are resolved back by consumers.
+use crate::value::Value;
+use crate::{EResult, Error};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+pub const BLOB_URI_PREFIX: &str = "blob://";
+
+/// Blob reference, the blob id is the SHA256 hash of its content (hex)
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct BlobRef {
+    sha256: String,
+}
+
+impl BlobRef {
+    /// Creates a reference for the data
+    pub fn for_data(data: &[u8]) -> Self {
+        Self {
+            sha256: hex::encode(Sha256::digest(data)),
+        }
+    }
+    #[inline]
+    pub fn sha256(&self) -> &str {
+        &self.sha256
+    }
+    /// Checks the data against the reference hash
+    pub fn verify(&self, data: &[u8]) -> EResult<()> {
+        if hex::encode(Sha256::digest(data)) == self.sha256 {
+            Ok(())
+        } else {
+            Err(Error::invalid_data(format!("blob {} hash mismatch", self)))
+        }
+    }
+}
+
+impl fmt::Display for BlobRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", BLOB_URI_PREFIX, self.sha256)
+    }
+}
+
+impl FromStr for BlobRef {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let sha256 = s
+            .strip_prefix(BLOB_URI_PREFIX)
+            .ok_or_else(|| Error::invalid_data(format!("invalid blob URI: {}", s)))?;
+        if sha256.len() != 64 || !sha256.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(Error::invalid_data(format!("invalid blob id: {}", sha256)));
+        }
+        Ok(Self {
+            sha256: sha256.to_ascii_lowercase(),
+        })
+    }
+}
+
+impl Serialize for BlobRef {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for BlobRef {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s: String = Deserialize::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+pub trait BlobStore {
+    /// Stores the data, returns its reference. Storing the same data twice must be a no-op
+    fn put(&self, data: &[u8]) -> EResult<BlobRef>;
+    /// Fetches the data, the implementation must verify its hash
+    fn get(&self, blob: &BlobRef) -> EResult<Vec<u8>>;
+    fn delete(&self, blob: &BlobRef) -> EResult<()>;
+    fn contains(&self, blob: &BlobRef) -> EResult<bool>;
+}
+
+/// File system blob store, blobs are stored as <dir>/<first 2 hash symbols>/<hash>
+#[derive(Debug, Clone)]
+pub struct FsBlobStore {
+    dir: PathBuf,
+}
+
+impl FsBlobStore {
+    /// Creates a store in the directory, the directory is created if missing
+    pub fn new(dir: &Path) -> EResult<Self> {
+        fs::create_dir_all(dir)?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+        })
+    }
+    fn path(&self, blob: &BlobRef) -> PathBuf {
+        let mut path = self.dir.join(&blob.sha256[..2]);
+        path.push(&blob.sha256);
+        path
+    }
+}
+
+impl BlobStore for FsBlobStore {
+    fn put(&self, data: &[u8]) -> EResult<BlobRef> {
+        let blob = BlobRef::for_data(data);
+        let path = self.path(&blob);
+        if !path.exists() {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let tmp_path = path.with_extension("tmp");
+            let mut f = fs::File::create(&tmp_path)?;
+            f.write_all(data)?;
+            f.sync_all()?;
+            fs::rename(tmp_path, path)?;
+        }
+        Ok(blob)
+    }
+    fn get(&self, blob: &BlobRef) -> EResult<Vec<u8>> {
+        let data = fs::read(self.path(blob)).map_err(|e| {
+            if e.kind() == std::io::ErrorKind::NotFound {
+                Error::not_found(format!("blob {} not found", blob))
+            } else {
+                e.into()
+            }
+        })?;
+        blob.verify(&data)?;
+        Ok(data)
+    }
+    fn delete(&self, blob: &BlobRef) -> EResult<()> {
+        match fs::remove_file(self.path(blob)) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+    fn contains(&self, blob: &BlobRef) -> EResult<bool> {
+        Ok(self.path(blob).exists())
+    }
+}
+
+impl Value {
+    /// Recursively moves byte values larger than the threshold into the blob store, replacing
+    /// them with blob references
+    pub fn offload_blobs<S: BlobStore + ?Sized>(
+        self,
+        store: &S,
+        threshold: usize,
+    ) -> EResult<Value> {
+        Ok(match self {
+            Value::Bytes(data) if data.len() > threshold => {
+                Value::String(store.put(&data)?.to_string())
+            }
+            Value::Seq(s) => Value::Seq(
+                s.into_iter()
+                    .map(|v| v.offload_blobs(store, threshold))
+                    .collect::<EResult<_>>()?,
+            ),
+            Value::Map(m) => Value::Map(
+                m.into_iter()
+                    .map(|(k, v)| Ok((k, v.offload_blobs(store, threshold)?)))
+                    .collect::<EResult<_>>()?,
+            ),
+            Value::Option(Some(v)) => {
+                Value::Option(Some(Box::new(v.offload_blobs(store, threshold)?)))
+            }
+            v => v,
+        })
+    }
+    /// Recursively replaces blob references with data fetched from the blob store
+    pub fn resolve_blobs<S: BlobStore + ?Sized>(self, store: &S) -> EResult<Value> {
+        Ok(match self {
+            Value::String(s) if s.starts_with(BLOB_URI_PREFIX) => {
+                Value::Bytes(store.get(&s.parse()?)?)
+            }
+            Value::Seq(s) => Value::Seq(
+                s.into_iter()
+                    .map(|v| v.resolve_blobs(store))
+                    .collect::<EResult<_>>()?,
+            ),
+            Value::Map(m) => Value::Map(
+                m.into_iter()
+                    .map(|(k, v)| Ok((k, v.resolve_blobs(store)?)))
+                    .collect::<EResult<_>>()?,
+            ),
+            Value::Option(Some(v)) => Value::Option(Some(Box::new(v.resolve_blobs(store)?))),
+            v => v,
+        })
+    }
+    /// Returns the blob reference if the value is a string with a valid blob URI
+    pub fn as_blob_ref(&self) -> Option<BlobRef> {
+        if let Value::String(s) = self {
+            s.parse().ok()
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BlobRef, BlobStore, FsBlobStore};
+    use crate::value::Value;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_fs_blob_store() {
+        let dir = std::env::temp_dir().join(format!("eva_blob_test_{}", std::process::id()));
+        let store = FsBlobStore::new(&dir).unwrap();
+        let frame = vec![7_u8; 1000];
+        let mut m = BTreeMap::new();
+        m.insert(
+            Value::String("frame".to_owned()),
+            Value::Bytes(frame.clone()),
+        );
+        m.insert(Value::String("small".to_owned()), Value::Bytes(vec![1, 2]));
+        let value = Value::Map(m);
+        let offloaded = value.clone().offload_blobs(&store, 100).unwrap();
+        let Value::Map(ref om) = offloaded else {
+            panic!("map expected")
+        };
+        let blob = om
+            .get(&Value::String("frame".to_owned()))
+            .unwrap()
+            .as_blob_ref()
+            .unwrap();
+        assert_eq!(blob, BlobRef::for_data(&frame));
+        assert_eq!(
+            om.get(&Value::String("small".to_owned())),
+            Some(&Value::Bytes(vec![1, 2]))
+        );
+        assert!(store.contains(&blob).unwrap());
+        assert_eq!(offloaded.resolve_blobs(&store).unwrap(), value);
+        store.delete(&blob).unwrap();
+        assert!(store.get(&blob).is_err());
+        assert!("blob://xyz".parse::<BlobRef>().is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
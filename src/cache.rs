@@ -1,12 +1,25 @@
+#[cfg(feature = "events")]
+use crate::acl::OIDMaskList;
+#[cfg(feature = "events")]
+use crate::events::{DbState, LocalStateEvent, RemoteStateEvent};
 use crate::payload::{pack, unpack};
+#[cfg(feature = "events")]
+use crate::value::Value;
 use crate::{EResult, Error};
+#[cfg(feature = "events")]
+use crate::{ItemStatus, IEID, OID};
 use log::{error, trace};
+use parking_lot::Mutex;
 use serde::{de::DeserializeOwned, Serialize};
 use sqlx::{
     sqlite::{SqliteConnectOptions, SqlitePoolOptions, SqliteSynchronous},
     ConnectOptions, Pool, Sqlite,
 };
+use std::collections::BTreeMap;
+#[cfg(feature = "events")]
+use std::collections::HashMap;
 use std::str::FromStr;
+use std::sync::Arc;
 use std::time::Duration;
 use std::time::{Instant, SystemTime, UNIX_EPOCH};
 use tokio::task::JoinHandle;
@@ -18,30 +31,139 @@ fn now() -> Duration {
         .expect("time went backwards")
 }
 
+const MAX_KEY_LEN: usize = 256;
+
+/// Cache storage backend
+#[derive(Debug, Clone)]
+pub enum CacheBackend {
+    /// SQLite database file
+    Sqlite {
+        path: String,
+        timeout: Duration,
+        pool_size: u32,
+        /// if set, the database is vacuumed with the interval
+        vacuum_interval: Option<Duration>,
+    },
+    /// Process memory, the data is lost on restart
+    Memory,
+}
+
+struct MemoryEntry {
+    value: Vec<u8>,
+    set: Duration,
+    ttl: Option<Duration>,
+}
+
+impl MemoryEntry {
+    #[inline]
+    fn is_expired(&self, default_ttl: Duration, now: Duration) -> bool {
+        self.set + self.ttl.unwrap_or(default_ttl) <= now
+    }
+}
+
+type MemoryStorage = Arc<Mutex<BTreeMap<String, MemoryEntry>>>;
+
+enum Storage {
+    Sqlite(Pool<Sqlite>),
+    Memory(MemoryStorage),
+}
+
 #[allow(clippy::module_name_repetitions)]
 pub struct TtlCache {
     path: String,
     ttl: Duration,
-    pool: Pool<Sqlite>,
-    fut_cleaner: JoinHandle<()>,
+    storage: Storage,
+    fut_cleaner: Option<JoinHandle<()>>,
 }
 
 impl Drop for TtlCache {
     fn drop(&mut self) {
-        self.fut_cleaner.abort();
+        if let Some(ref fut) = self.fut_cleaner {
+            fut.abort();
+        }
     }
 }
 
 const CLEANUP_INTERVAL: Duration = Duration::from_secs(60);
 
+// times and TTLs are stored as float seconds to keep sub-second TTLs, integer values of the
+// previous versions are compatible
+async fn sqlite_cleanup(pool: &Pool<Sqlite>, ttl: Duration) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM kv WHERE t + COALESCE(l, ?) < ?")
+        .bind(ttl.as_secs_f64())
+        .bind(now().as_secs_f64())
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Adds the per-entry TTL column to the databases, created by the previous versions
+async fn sqlite_migrate(pool: &Pool<Sqlite>) -> Result<(), sqlx::Error> {
+    let (has_ttl,): (i64,) =
+        sqlx::query_as("SELECT COUNT(*) FROM pragma_table_info('kv') WHERE name = 'l'")
+            .fetch_one(pool)
+            .await?;
+    if has_ttl == 0 {
+        sqlx::query("ALTER TABLE kv ADD COLUMN l INT")
+            .execute(pool)
+            .await?;
+    }
+    Ok(())
+}
+
 impl TtlCache {
-    #[allow(clippy::cast_possible_wrap)]
+    /// Creates a SQLite-backed cache
     pub async fn create(
         path: &str,
         ttl: Duration,
         timeout: Duration,
         pool_size: u32,
     ) -> EResult<Self> {
+        Self::open(
+            CacheBackend::Sqlite {
+                path: path.to_owned(),
+                timeout,
+                pool_size,
+                vacuum_interval: None,
+            },
+            ttl,
+        )
+        .await
+    }
+    /// Creates an in-memory cache. Expired keys are removed in the background if the cache is
+    /// created inside a Tokio runtime, otherwise [`TtlCache::cleanup()`] must be called manually
+    pub fn memory(ttl: Duration) -> Self {
+        let storage: MemoryStorage = <_>::default();
+        let st = storage.clone();
+        let fut_cleaner = tokio::runtime::Handle::try_current().ok().map(|rt| {
+            rt.spawn(async move {
+                let mut int = tokio::time::interval(CLEANUP_INTERVAL);
+                loop {
+                    int.tick().await;
+                    trace!("cleaning up memory cache");
+                    let t = now();
+                    st.lock().retain(|_, v| !v.is_expired(ttl, t));
+                }
+            })
+        });
+        Self {
+            path: "memory".to_owned(),
+            ttl,
+            storage: Storage::Memory(storage),
+            fut_cleaner,
+        }
+    }
+    /// Creates a cache with the chosen backend
+    pub async fn open(backend: CacheBackend, ttl: Duration) -> EResult<Self> {
+        let (path, timeout, pool_size, vacuum_interval) = match backend {
+            CacheBackend::Memory => return Ok(Self::memory(ttl)),
+            CacheBackend::Sqlite {
+                path,
+                timeout,
+                pool_size,
+                vacuum_interval,
+            } => (path, timeout, pool_size, vacuum_interval),
+        };
         let mut connection_options = SqliteConnectOptions::from_str(&format!("sqlite://{path}"))?
             .create_if_missing(true)
             .synchronous(SqliteSynchronous::Extra)
@@ -54,25 +176,34 @@ impl TtlCache {
             .acquire_timeout(timeout)
             .connect_with(connection_options)
             .await?;
-        sqlx::query("CREATE TABLE IF NOT EXISTS kv(k VARCHAR(256), v BLOB, t INT, PRIMARY KEY(k))")
-            .execute(&pool)
-            .await?;
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS kv(k VARCHAR(256), v BLOB, t INT, l INT, PRIMARY KEY(k))",
+        )
+        .execute(&pool)
+        .await?;
+        sqlite_migrate(&pool).await?;
         sqlx::query("CREATE INDEX IF NOT EXISTS kv_t ON kv(t)")
             .execute(&pool)
             .await?;
         let p = pool.clone();
-        let db_path = path.to_owned();
+        let db_path = path.clone();
         let fut_cleaner = tokio::spawn(async move {
             let mut next = Instant::now() + CLEANUP_INTERVAL;
+            let mut next_vacuum = vacuum_interval.map(|i| Instant::now() + i);
             loop {
                 trace!("cleaning up {} cache", db_path);
-                if let Err(e) = sqlx::query("DELETE FROM kv WHERE t < ?")
-                    .bind((now() - ttl).as_secs() as i64)
-                    .execute(&p)
-                    .await
-                {
+                if let Err(e) = sqlite_cleanup(&p, ttl).await {
                     error!("cache {} error: {}", db_path, e);
                 }
+                if let Some(nv) = next_vacuum {
+                    if nv <= Instant::now() {
+                        trace!("vacuuming {} cache", db_path);
+                        if let Err(e) = sqlx::query("VACUUM").execute(&p).await {
+                            error!("cache {} vacuum error: {}", db_path, e);
+                        }
+                        next_vacuum = vacuum_interval.map(|i| Instant::now() + i);
+                    }
+                }
                 let t = Instant::now();
                 if next > t {
                     tokio::time::sleep(next - t).await;
@@ -81,50 +212,455 @@ impl TtlCache {
             }
         });
         Ok(Self {
-            path: path.to_owned(),
+            path,
             ttl,
-            pool,
-            fut_cleaner,
+            storage: Storage::Sqlite(pool),
+            fut_cleaner: Some(fut_cleaner),
         })
     }
-    #[allow(clippy::cast_possible_wrap)]
+    #[inline]
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+    /// Returns a view to the cache, which prefixes all keys with `<prefix>/`
+    pub fn namespace(&self, prefix: &str) -> CacheNamespace<'_> {
+        CacheNamespace {
+            cache: self,
+            prefix: format!("{}/", prefix),
+        }
+    }
+    #[inline]
     pub async fn set<V: Serialize>(&self, key: &str, value: &V) -> EResult<()> {
+        self.set_raw(key, value, None).await
+    }
+    /// Sets the value with a custom TTL
+    #[inline]
+    pub async fn set_with_ttl<V: Serialize>(
+        &self,
+        key: &str,
+        value: &V,
+        ttl: Duration,
+    ) -> EResult<()> {
+        self.set_raw(key, value, Some(ttl)).await
+    }
+    async fn set_raw<V: Serialize>(
+        &self,
+        key: &str,
+        value: &V,
+        ttl: Option<Duration>,
+    ) -> EResult<()> {
         trace!("setting {} key {}", self.path, key);
-        if key.len() > 256 {
+        if key.len() > MAX_KEY_LEN {
             return Err(Error::invalid_data("key too long"));
         }
-        sqlx::query("INSERT OR REPLACE INTO kv (k, v, t) VALUES (?, ?, ?)")
-            .bind(key)
-            .bind(pack(value)?)
-            .bind(now().as_secs() as i64)
-            .execute(&self.pool)
-            .await?;
+        let value = pack(value)?;
+        match self.storage {
+            Storage::Sqlite(ref pool) => {
+                sqlx::query("INSERT OR REPLACE INTO kv (k, v, t, l) VALUES (?, ?, ?, ?)")
+                    .bind(key)
+                    .bind(value)
+                    .bind(now().as_secs_f64())
+                    .bind(ttl.map(|v| v.as_secs_f64()))
+                    .execute(pool)
+                    .await?;
+            }
+            Storage::Memory(ref storage) => {
+                storage.lock().insert(
+                    key.to_owned(),
+                    MemoryEntry {
+                        value,
+                        set: now(),
+                        ttl,
+                    },
+                );
+            }
+        }
         Ok(())
     }
+    #[inline]
     pub async fn get<V: DeserializeOwned>(&self, key: &str) -> EResult<Option<V>> {
+        self.get_as(key).await
+    }
+    pub async fn get_as<V: DeserializeOwned>(&self, key: &str) -> EResult<Option<V>> {
         trace!("getting {} key {}", self.path, key);
-        let val: Option<(Vec<u8>,)> = sqlx::query_as("SELECT v FROM kv WHERE k = ? AND t > ?")
-            .bind(key)
-            .bind((now() - self.ttl).as_secs_f64())
-            .fetch_optional(&self.pool)
-            .await?;
+        let val: Option<Vec<u8>> = match self.storage {
+            Storage::Sqlite(ref pool) => {
+                let val: Option<(Vec<u8>,)> =
+                    sqlx::query_as("SELECT v FROM kv WHERE k = ? AND t + COALESCE(l, ?) > ?")
+                        .bind(key)
+                        .bind(self.ttl.as_secs_f64())
+                        .bind(now().as_secs_f64())
+                        .fetch_optional(pool)
+                        .await?;
+                val.map(|v| v.0)
+            }
+            Storage::Memory(ref storage) => storage
+                .lock()
+                .get(key)
+                .filter(|v| !v.is_expired(self.ttl, now()))
+                .map(|v| v.value.clone()),
+        };
         if let Some(v) = val {
-            Ok(Some(unpack(&v.0)?))
+            Ok(Some(unpack(&v)?))
         } else {
             Ok(None)
         }
     }
     pub async fn delete(&self, key: &str) -> EResult<()> {
         trace!("deleting {} key {}", self.path, key);
-        sqlx::query("DELETE FROM kv WHERE k = ?")
-            .bind(key)
-            .execute(&self.pool)
-            .await?;
+        match self.storage {
+            Storage::Sqlite(ref pool) => {
+                sqlx::query("DELETE FROM kv WHERE k = ?")
+                    .bind(key)
+                    .execute(pool)
+                    .await?;
+            }
+            Storage::Memory(ref storage) => {
+                storage.lock().remove(key);
+            }
+        }
         Ok(())
     }
     pub async fn purge(&self) -> EResult<()> {
         trace!("deleting all keys in {}", self.path);
-        sqlx::query("DELETE FROM kv").execute(&self.pool).await?;
+        match self.storage {
+            Storage::Sqlite(ref pool) => {
+                sqlx::query("DELETE FROM kv").execute(pool).await?;
+            }
+            Storage::Memory(ref storage) => storage.lock().clear(),
+        }
         Ok(())
     }
+    /// Deletes all keys, starting with the prefix
+    pub async fn purge_prefix(&self, prefix: &str) -> EResult<()> {
+        trace!("deleting keys {}* in {}", prefix, self.path);
+        match self.storage {
+            Storage::Sqlite(ref pool) => {
+                sqlx::query("DELETE FROM kv WHERE instr(k, ?) = 1")
+                    .bind(prefix)
+                    .execute(pool)
+                    .await?;
+            }
+            Storage::Memory(ref storage) => {
+                storage.lock().retain(|k, _| !k.starts_with(prefix));
+            }
+        }
+        Ok(())
+    }
+    /// Deletes expired keys (performed automatically in the background as well)
+    pub async fn cleanup(&self) -> EResult<()> {
+        match self.storage {
+            Storage::Sqlite(ref pool) => sqlite_cleanup(pool, self.ttl).await?,
+            Storage::Memory(ref storage) => {
+                let t = now();
+                storage.lock().retain(|_, v| !v.is_expired(self.ttl, t));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Cache view with prefixed keys, see [`TtlCache::namespace()`]
+pub struct CacheNamespace<'a> {
+    cache: &'a TtlCache,
+    prefix: String,
+}
+
+impl CacheNamespace<'_> {
+    #[inline]
+    fn key(&self, key: &str) -> String {
+        format!("{}{}", self.prefix, key)
+    }
+    #[inline]
+    pub async fn set<V: Serialize>(&self, key: &str, value: &V) -> EResult<()> {
+        self.cache.set(&self.key(key), value).await
+    }
+    #[inline]
+    pub async fn set_with_ttl<V: Serialize>(
+        &self,
+        key: &str,
+        value: &V,
+        ttl: Duration,
+    ) -> EResult<()> {
+        self.cache.set_with_ttl(&self.key(key), value, ttl).await
+    }
+    #[inline]
+    pub async fn get_as<V: DeserializeOwned>(&self, key: &str) -> EResult<Option<V>> {
+        self.cache.get_as(&self.key(key)).await
+    }
+    #[inline]
+    pub async fn delete(&self, key: &str) -> EResult<()> {
+        self.cache.delete(&self.key(key)).await
+    }
+    /// Deletes all keys in the namespace
+    #[inline]
+    pub async fn purge(&self) -> EResult<()> {
+        self.cache.purge_prefix(&self.prefix).await
+    }
+}
+
+/// Source of a [`StateWarmCache`] state
+#[cfg(feature = "events")]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum StateSource {
+    /// loaded from the database on start
+    WarmUp,
+    /// received with a live state event
+    Live,
+}
+
+#[cfg(feature = "events")]
+#[derive(Debug, Clone)]
+pub struct CachedState {
+    pub status: ItemStatus,
+    pub value: Value,
+    pub ieid: Option<IEID>,
+    pub t: f64,
+    pub source: StateSource,
+    /// the state has not been confirmed for longer than the staleness threshold
+    pub stale: bool,
+}
+
+#[cfg(feature = "events")]
+struct WarmEntry {
+    status: ItemStatus,
+    value: Value,
+    ieid: Option<IEID>,
+    t: f64,
+    source: StateSource,
+    // the last time the state was confirmed (the database set time for warm-up states)
+    updated: f64,
+}
+
+/// Write-through item state cache. On start the last known states are loaded from the database,
+/// after the cache is updated with live state events. States, not confirmed for longer than the
+/// staleness threshold, are returned flagged
+#[cfg(feature = "events")]
+pub struct StateWarmCache {
+    masks: OIDMaskList,
+    max_age: Duration,
+    states: parking_lot::RwLock<HashMap<OID, WarmEntry>>,
+}
+
+#[cfg(feature = "events")]
+impl StateWarmCache {
+    pub fn new(masks: OIDMaskList, max_age: Duration) -> Self {
+        Self {
+            masks,
+            max_age,
+            states: <_>::default(),
+        }
+    }
+    fn put(&self, oid: &OID, entry: WarmEntry) -> bool {
+        if !self.masks.matches(oid) {
+            return false;
+        }
+        let mut states = self.states.write();
+        if let Some(current) = states.get(oid) {
+            if entry.source == StateSource::WarmUp && current.source == StateSource::Live {
+                return false;
+            }
+            if let (Some(current_ieid), Some(ieid)) = (current.ieid, entry.ieid) {
+                if ieid < current_ieid {
+                    return false;
+                }
+            }
+        }
+        states.insert(oid.clone(), entry);
+        true
+    }
+    /// Loads the states (e.g. received from the core), returns the number of states loaded
+    pub fn warm_up<I>(&self, states: I) -> usize
+    where
+        I: IntoIterator<Item = (OID, DbState)>,
+    {
+        states
+            .into_iter()
+            .filter(|(oid, state)| {
+                self.put(
+                    oid,
+                    WarmEntry {
+                        status: state.status,
+                        value: state.value.clone(),
+                        ieid: Some(state.ieid),
+                        t: state.t,
+                        source: StateSource::WarmUp,
+                        updated: state.t,
+                    },
+                )
+            })
+            .count()
+    }
+    /// Loads the states from a database table with the columns `oid` (VARCHAR), `status`
+    /// (SMALLINT), `value` (JSONB) and `t` (DOUBLE), returns the number of states loaded
+    #[cfg(feature = "db")]
+    pub async fn warm_up_db(&self, pool: &crate::db::DbPool, table: &str) -> EResult<usize> {
+        if table.is_empty()
+            || !table
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+        {
+            return Err(Error::invalid_params(format!(
+                "invalid table name: {}",
+                table
+            )));
+        }
+        let q = format!("SELECT oid, status, value, t FROM {}", table);
+        let rows: Vec<(OID, ItemStatus, Option<Value>, f64)> = match pool {
+            crate::db::DbPool::Sqlite(p) => sqlx::query_as(&q).fetch_all(p).await?,
+            crate::db::DbPool::Postgres(p) => sqlx::query_as(&q).fetch_all(p).await?,
+        };
+        Ok(rows
+            .into_iter()
+            .filter(|(oid, status, value, t)| {
+                self.put(
+                    oid,
+                    WarmEntry {
+                        status: *status,
+                        value: value.clone().unwrap_or_default(),
+                        ieid: None,
+                        t: *t,
+                        source: StateSource::WarmUp,
+                        updated: *t,
+                    },
+                )
+            })
+            .count())
+    }
+    /// Applies a live state event, returns false if the item does not match the masks or the
+    /// event is outdated
+    pub fn apply(&self, oid: &OID, event: &LocalStateEvent) -> bool {
+        self.put(
+            oid,
+            WarmEntry {
+                status: event.status,
+                value: event.value.clone(),
+                ieid: Some(event.ieid),
+                t: event.t,
+                source: StateSource::Live,
+                updated: now().as_secs_f64(),
+            },
+        )
+    }
+    /// Applies a live remote state event
+    pub fn apply_remote(&self, oid: &OID, event: &RemoteStateEvent) -> bool {
+        self.put(
+            oid,
+            WarmEntry {
+                status: event.status,
+                value: event.value.clone(),
+                ieid: Some(event.ieid),
+                t: event.t,
+                source: StateSource::Live,
+                updated: now().as_secs_f64(),
+            },
+        )
+    }
+    pub fn get(&self, oid: &OID) -> Option<CachedState> {
+        let max_age = self.max_age.as_secs_f64();
+        let t = now().as_secs_f64();
+        self.states.read().get(oid).map(|entry| CachedState {
+            status: entry.status,
+            value: entry.value.clone(),
+            ieid: entry.ieid,
+            t: entry.t,
+            source: entry.source,
+            stale: t - entry.updated > max_age,
+        })
+    }
+    /// Marks the item state confirmed (e.g. when the source is known to be alive)
+    pub fn touch(&self, oid: &OID) {
+        if let Some(entry) = self.states.write().get_mut(oid) {
+            entry.updated = now().as_secs_f64();
+        }
+    }
+    pub fn remove(&self, oid: &OID) {
+        self.states.write().remove(oid);
+    }
+    pub fn len(&self) -> usize {
+        self.states.read().len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.states.read().is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TtlCache;
+    use std::time::Duration;
+
+    #[cfg(feature = "events")]
+    #[test]
+    fn test_state_warm_cache() {
+        use super::{StateSource, StateWarmCache};
+        use crate::acl::OIDMaskList;
+        use crate::events::{DbState, LocalStateEvent};
+        use crate::value::Value;
+        use crate::{IEID, OID};
+        let cache = StateWarmCache::new(
+            OIDMaskList::from_str_list(&["sensor:tests/#"]).unwrap(),
+            Duration::from_secs(5),
+        );
+        let oid: OID = "sensor:tests/s1".parse().unwrap();
+        let state = |i, t| DbState {
+            status: 1,
+            value: Value::U8(i),
+            ieid: IEID::new(1, u64::from(i)),
+            t,
+        };
+        let t = super::now().as_secs_f64();
+        assert_eq!(
+            cache.warm_up([
+                (oid.clone(), state(1, t - 60.0)),
+                ("sensor:other/s1".parse().unwrap(), state(1, t)),
+            ]),
+            1
+        );
+        let s = cache.get(&oid).unwrap();
+        assert_eq!(s.source, StateSource::WarmUp);
+        assert!(s.stale);
+        let ev = |i| LocalStateEvent {
+            status: 1,
+            value: Value::U8(i),
+            act: None,
+            ieid: IEID::new(1, u64::from(i)),
+            t,
+        };
+        assert!(cache.apply(&oid, &ev(3)));
+        // outdated
+        assert!(!cache.apply(&oid, &ev(2)));
+        // warm-up states never override live ones
+        assert_eq!(cache.warm_up([(oid.clone(), state(5, t))]), 0);
+        let s = cache.get(&oid).unwrap();
+        assert_eq!(s.value, Value::U8(3));
+        assert_eq!(s.source, StateSource::Live);
+        assert!(!s.stale);
+    }
+
+    #[test]
+    fn test_memory_cache() {
+        // no runtime, no background cleaner
+        drop(TtlCache::memory(Duration::from_secs(10)));
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async {
+                let cache = TtlCache::memory(Duration::from_secs(10));
+                cache.set("a", &1u32).await.unwrap();
+                cache.set_with_ttl("b", &"x", Duration::ZERO).await.unwrap();
+                assert_eq!(cache.get_as::<u32>("a").await.unwrap(), Some(1));
+                assert_eq!(cache.get_as::<String>("b").await.unwrap(), None);
+                let ns = cache.namespace("svc1");
+                ns.set("a", &2u32).await.unwrap();
+                ns.set("b", &3u32).await.unwrap();
+                assert_eq!(ns.get_as::<u32>("a").await.unwrap(), Some(2));
+                assert_eq!(cache.get_as::<u32>("svc1/b").await.unwrap(), Some(3));
+                ns.purge().await.unwrap();
+                assert_eq!(ns.get_as::<u32>("a").await.unwrap(), None);
+                assert_eq!(cache.get_as::<u32>("a").await.unwrap(), Some(1));
+                assert!(cache.set(&"k".repeat(300), &1).await.is_err());
+            });
+    }
 }
@@ -0,0 +1,363 @@
+//! Concurrent collections
+#[cfg(feature = "acl")]
+use crate::acl::OIDMaskList;
+use crate::OID;
+use parking_lot::{Mutex, RwLock};
+use serde::{Serialize, Serializer};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+
+pub const DEFAULT_OID_MAP_SHARDS: usize = 16;
+
+/// Sharded concurrent map, keyed by OID
+///
+/// Each shard is protected by its own read-write lock, so concurrent updates of different items
+/// rarely contend. Snapshot and query methods clone values shard-by-shard and never hold more
+/// than one lock at a time, so values should be cheap to clone (e.g. wrapped in Arc).
+pub struct OidMap<V> {
+    shards: Vec<RwLock<HashMap<OID, V>>>,
+}
+
+impl<V> Default for OidMap<V> {
+    fn default() -> Self {
+        Self::with_shards(DEFAULT_OID_MAP_SHARDS)
+    }
+}
+
+impl<V> OidMap<V> {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// # Panics
+    ///
+    /// Will panic if shards is zero
+    pub fn with_shards(shards: usize) -> Self {
+        assert!(shards > 0, "shard count must be positive");
+        Self {
+            shards: (0..shards).map(|_| <_>::default()).collect(),
+        }
+    }
+    #[allow(clippy::cast_possible_truncation)]
+    fn shard(&self, oid: &OID) -> &RwLock<HashMap<OID, V>> {
+        let mut hasher = DefaultHasher::new();
+        oid.hash(&mut hasher);
+        &self.shards[(hasher.finish() as usize) % self.shards.len()]
+    }
+    pub fn insert(&self, oid: OID, value: V) -> Option<V> {
+        self.shard(&oid).write().insert(oid, value)
+    }
+    pub fn remove(&self, oid: &OID) -> Option<V> {
+        self.shard(oid).write().remove(oid)
+    }
+    pub fn contains_key(&self, oid: &OID) -> bool {
+        self.shard(oid).read().contains_key(oid)
+    }
+    /// Calls the function with a reference to the value, the shard is read-locked during the call
+    pub fn with<F, R>(&self, oid: &OID, f: F) -> Option<R>
+    where
+        F: FnOnce(&V) -> R,
+    {
+        self.shard(oid).read().get(oid).map(f)
+    }
+    /// Calls the function with a mutable reference to the value, the shard is write-locked
+    /// during the call
+    pub fn with_mut<F, R>(&self, oid: &OID, f: F) -> Option<R>
+    where
+        F: FnOnce(&mut V) -> R,
+    {
+        self.shard(oid).write().get_mut(oid).map(f)
+    }
+    /// Calls the function with a mutable reference to the value, inserting the default one first
+    /// if missing
+    pub fn upsert<F, R>(&self, oid: &OID, default: impl FnOnce() -> V, f: F) -> R
+    where
+        F: FnOnce(&mut V) -> R,
+    {
+        let mut shard = self.shard(oid).write();
+        if let Some(v) = shard.get_mut(oid) {
+            f(v)
+        } else {
+            let mut v = default();
+            let result = f(&mut v);
+            shard.insert(oid.clone(), v);
+            result
+        }
+    }
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|s| s.read().len()).sum()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.shards.iter().all(|s| s.read().is_empty())
+    }
+    pub fn clear(&self) {
+        for shard in &self.shards {
+            shard.write().clear();
+        }
+    }
+    pub fn retain<F>(&self, mut f: F)
+    where
+        F: FnMut(&OID, &mut V) -> bool,
+    {
+        for shard in &self.shards {
+            shard.write().retain(|k, v| f(k, v));
+        }
+    }
+    pub fn keys(&self) -> Vec<OID> {
+        let mut result = Vec::with_capacity(self.len());
+        for shard in &self.shards {
+            result.extend(shard.read().keys().cloned());
+        }
+        result
+    }
+}
+
+impl<V: Clone> OidMap<V> {
+    pub fn get(&self, oid: &OID) -> Option<V> {
+        self.shard(oid).read().get(oid).cloned()
+    }
+    /// Collects entries, matching the filter. The result is not a point-in-time snapshot of the
+    /// whole map, as shards are processed one-by-one
+    pub fn snapshot_filtered<F>(&self, mut f: F) -> Vec<(OID, V)>
+    where
+        F: FnMut(&OID, &V) -> bool,
+    {
+        let mut result = Vec::new();
+        for shard in &self.shards {
+            result.extend(
+                shard
+                    .read()
+                    .iter()
+                    .filter(|(k, v)| f(k, v))
+                    .map(|(k, v)| (k.clone(), v.clone())),
+            );
+        }
+        result
+    }
+    #[inline]
+    pub fn snapshot(&self) -> Vec<(OID, V)> {
+        self.snapshot_filtered(|_, _| true)
+    }
+    /// Returns entries with OIDs matching the mask list
+    #[cfg(feature = "acl")]
+    #[inline]
+    pub fn iter_matching(&self, masks: &OIDMaskList) -> std::vec::IntoIter<(OID, V)> {
+        self.snapshot_filtered(|oid, _| masks.matches(oid))
+            .into_iter()
+    }
+}
+
+impl<V> FromIterator<(OID, V)> for OidMap<V> {
+    fn from_iter<I: IntoIterator<Item = (OID, V)>>(iter: I) -> Self {
+        let map = Self::default();
+        for (oid, v) in iter {
+            map.insert(oid, v);
+        }
+        map
+    }
+}
+
+/// Copy-on-write inventory
+///
+/// Readers get consistent [`InventorySnapshot`]s, which are cheap to obtain and keep, while
+/// writers apply changes to a new copy of the map. Values are stored in Arc, so copying the map
+/// costs one pointer per item. The copy is made only if a snapshot is held by someone, so
+/// writers should group changes with [`CowInventory::update()`]. Copies are made and changed
+/// without blocking readers, writers are serialized.
+pub struct CowInventory<V> {
+    current: RwLock<Arc<BTreeMap<OID, Arc<V>>>>,
+    writer: Mutex<()>,
+}
+
+impl<V> Default for CowInventory<V> {
+    fn default() -> Self {
+        Self {
+            current: <_>::default(),
+            writer: <_>::default(),
+        }
+    }
+}
+
+impl<V> CowInventory<V> {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Returns the current snapshot
+    #[inline]
+    pub fn snapshot(&self) -> InventorySnapshot<V> {
+        InventorySnapshot {
+            items: self.current.read().clone(),
+        }
+    }
+    /// Applies a batch of changes atomically, readers see either all or none of them
+    pub fn update<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut BTreeMap<OID, Arc<V>>) -> R,
+    {
+        let _writer = self.writer.lock();
+        let mut next = self.current.read().clone();
+        // the map is referenced by the inventory and the local copy only, no snapshots are held
+        if Arc::strong_count(&next) == 2 {
+            drop(next);
+            return f(Arc::make_mut(&mut self.current.write()));
+        }
+        let result = f(Arc::make_mut(&mut next));
+        *self.current.write() = next;
+        result
+    }
+    pub fn insert(&self, oid: OID, value: V) -> Option<Arc<V>> {
+        self.update(|m| m.insert(oid, Arc::new(value)))
+    }
+    pub fn remove(&self, oid: &OID) -> Option<Arc<V>> {
+        self.update(|m| m.remove(oid))
+    }
+    #[inline]
+    pub fn get(&self, oid: &OID) -> Option<Arc<V>> {
+        self.current.read().get(oid).cloned()
+    }
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.current.read().len()
+    }
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.current.read().is_empty()
+    }
+}
+
+impl<V> FromIterator<(OID, V)> for CowInventory<V> {
+    fn from_iter<I: IntoIterator<Item = (OID, V)>>(iter: I) -> Self {
+        Self {
+            current: RwLock::new(Arc::new(
+                iter.into_iter().map(|(k, v)| (k, Arc::new(v))).collect(),
+            )),
+            writer: <_>::default(),
+        }
+    }
+}
+
+/// Immutable point-in-time inventory view, serialized as a sequence of values
+pub struct InventorySnapshot<V> {
+    items: Arc<BTreeMap<OID, Arc<V>>>,
+}
+
+impl<V> Clone for InventorySnapshot<V> {
+    fn clone(&self) -> Self {
+        Self {
+            items: self.items.clone(),
+        }
+    }
+}
+
+impl<V> InventorySnapshot<V> {
+    #[inline]
+    pub fn get(&self, oid: &OID) -> Option<&Arc<V>> {
+        self.items.get(oid)
+    }
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+    #[inline]
+    pub fn iter(&self) -> std::collections::btree_map::Iter<'_, OID, Arc<V>> {
+        self.items.iter()
+    }
+    /// Iterates over items with OIDs matching the mask list
+    #[cfg(feature = "acl")]
+    pub fn iter_matching<'a>(
+        &'a self,
+        masks: &'a OIDMaskList,
+    ) -> impl Iterator<Item = (&'a OID, &'a Arc<V>)> + 'a {
+        self.items.iter().filter(|(oid, _)| masks.matches(oid))
+    }
+}
+
+impl<'a, V> IntoIterator for &'a InventorySnapshot<V> {
+    type Item = (&'a OID, &'a Arc<V>);
+    type IntoIter = std::collections::btree_map::Iter<'a, OID, Arc<V>>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.items.iter()
+    }
+}
+
+impl<V: Serialize> Serialize for InventorySnapshot<V> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_seq(self.items.values())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CowInventory, OidMap};
+    use crate::OID;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_oid_map() {
+        let map: OidMap<u32> = (0..100)
+            .map(|i| (format!("sensor:tests/s{}", i).parse::<OID>().unwrap(), i))
+            .collect();
+        assert_eq!(map.len(), 100);
+        let oid: OID = "sensor:tests/s5".parse().unwrap();
+        assert_eq!(map.get(&oid), Some(5));
+        map.with_mut(&oid, |v| *v = 500);
+        assert_eq!(map.with(&oid, |v| *v + 1), Some(501));
+        let new_oid: OID = "unit:tests/u1".parse().unwrap();
+        map.upsert(&new_oid, || 0, |v| *v += 1);
+        map.upsert(&new_oid, || 0, |v| *v += 1);
+        assert_eq!(map.get(&new_oid), Some(2));
+        map.retain(|_, v| *v < 50 || *v == 500);
+        assert_eq!(map.len(), 51);
+        #[cfg(feature = "acl")]
+        {
+            let masks = crate::acl::OIDMaskList::from_str_list(&["unit:#"]).unwrap();
+            let matched: Vec<(OID, u32)> = map.iter_matching(&masks).collect();
+            assert_eq!(matched, vec![(new_oid.clone(), 2)]);
+        }
+        assert_eq!(map.remove(&new_oid), Some(2));
+        map.clear();
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn test_cow_inventory() {
+        let inv: CowInventory<u32> = (0..10)
+            .map(|i| (format!("sensor:tests/s{}", i).parse::<OID>().unwrap(), i))
+            .collect();
+        let snapshot = inv.snapshot();
+        let oid: OID = "sensor:tests/s1".parse().unwrap();
+        inv.update(|m| {
+            m.remove(&oid);
+            m.insert("unit:tests/u1".parse().unwrap(), 100.into());
+        });
+        assert_eq!(snapshot.len(), 10);
+        assert_eq!(snapshot.get(&oid).map(|v| **v), Some(1));
+        assert_eq!(inv.len(), 10);
+        assert!(inv.get(&oid).is_none());
+        let snapshot = inv.snapshot();
+        let values: Vec<u32> =
+            serde_json::from_value(serde_json::to_value(&snapshot).unwrap()).unwrap();
+        assert_eq!(values.len(), 10);
+        assert_eq!(values.iter().sum::<u32>(), 144);
+        // readers are not blocked while the copy is changed
+        let inv = Arc::new(inv);
+        let reader = inv.clone();
+        let len = inv.update(|m| {
+            m.insert("unit:tests/u2".parse().unwrap(), 200.into());
+            std::thread::spawn(move || reader.len()).join().unwrap()
+        });
+        assert_eq!(len, 10);
+        assert_eq!(inv.len(), 11);
+        assert_eq!(snapshot.len(), 10);
+    }
+}
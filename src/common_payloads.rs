@@ -1,6 +1,7 @@
+use crate::acl::{OIDMask, OIDMaskList};
 use crate::events::NodeInfo;
 use crate::value::Value;
-use crate::OID;
+use crate::{ItemStatus, IEID, OID};
 use rand::seq::SliceRandom;
 use rand::thread_rng;
 use serde::{Deserialize, Deserializer, Serialize};
@@ -235,3 +236,423 @@ impl<T> Iterator for SingleIter<T> {
         self.0.take()
     }
 }
+
+/// Common pagination parameters for list methods. "total" is set in replies only
+#[derive(Serialize, Deserialize, Debug, Clone, Default, Eq, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Paging {
+    #[serde(default)]
+    pub offset: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total: Option<usize>,
+}
+
+impl Paging {
+    #[inline]
+    pub fn new(offset: usize, limit: Option<usize>) -> Self {
+        Self {
+            offset,
+            limit,
+            total: None,
+        }
+    }
+    /// Skips/limits items of the iterator
+    pub fn apply<I>(&self, iter: I) -> impl Iterator<Item = I::Item>
+    where
+        I: Iterator,
+    {
+        iter.skip(self.offset)
+            .take(self.limit.unwrap_or(usize::MAX))
+    }
+    /// Returns the requested page and the paging info with the total number of items set
+    pub fn paginate<T>(&self, items: Vec<T>) -> (Vec<T>, Paging) {
+        let total = items.len();
+        let page = self.apply(items.into_iter()).collect();
+        (
+            page,
+            Paging {
+                offset: self.offset,
+                limit: self.limit,
+                total: Some(total),
+            },
+        )
+    }
+}
+
+/// Items which can be filtered with [`Filter`]
+pub trait Filterable {
+    fn oid(&self) -> &OID;
+    /// Source node, None for local items
+    fn node(&self) -> Option<&str> {
+        None
+    }
+    fn enabled(&self) -> bool {
+        true
+    }
+}
+
+/// Common item filter parameters for list methods. Empty masks match all items
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Filter {
+    #[serde(default, skip_serializing_if = "OIDMaskList::is_empty")]
+    pub masks: OIDMaskList,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub node: Option<String>,
+    #[serde(default)]
+    pub include_disabled: bool,
+}
+
+impl Filter {
+    pub fn matches<F: Filterable + ?Sized>(&self, item: &F) -> bool {
+        if !self.include_disabled && !item.enabled() {
+            return false;
+        }
+        if let Some(ref node) = self.node {
+            if item.node() != Some(node.as_str()) {
+                return false;
+            }
+        }
+        self.masks.is_empty() || self.masks.matches(item.oid())
+    }
+    pub fn apply<'a, I, F>(&'a self, iter: I) -> impl Iterator<Item = I::Item> + 'a
+    where
+        I: Iterator<Item = F> + 'a,
+        F: std::ops::Deref,
+        F::Target: Filterable,
+    {
+        iter.filter(move |item| self.matches(&**item))
+    }
+}
+
+/// Deserializes a mask list from a single mask or a list
+pub fn deserialize_oid_mask_list<'de, D>(deserializer: D) -> Result<OIDMaskList, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let masks: ValueOrList<OIDMask> = Deserialize::deserialize(deserializer)?;
+    Ok(masks.into_iter().collect())
+}
+
+/// Item state fields to output
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct StateFields {
+    pub status: bool,
+    pub value: bool,
+    pub act: bool,
+    pub ieid: bool,
+    pub t: bool,
+    pub meta: bool,
+    pub enabled: bool,
+}
+
+impl Default for StateFields {
+    fn default() -> Self {
+        Self {
+            status: true,
+            value: true,
+            act: true,
+            ieid: true,
+            t: true,
+            meta: false,
+            enabled: false,
+        }
+    }
+}
+
+impl StateFields {
+    const NAMES: [&'static str; 7] = ["status", "value", "act", "ieid", "t", "meta", "enabled"];
+    #[inline]
+    pub fn full() -> Self {
+        Self {
+            meta: true,
+            enabled: true,
+            ..Self::default()
+        }
+    }
+    #[inline]
+    pub fn none() -> Self {
+        Self {
+            status: false,
+            value: false,
+            act: false,
+            ieid: false,
+            t: false,
+            meta: false,
+            enabled: false,
+        }
+    }
+    fn field_mut(&mut self, name: &str) -> Option<&mut bool> {
+        Some(match name {
+            "status" => &mut self.status,
+            "value" => &mut self.value,
+            "act" => &mut self.act,
+            "ieid" => &mut self.ieid,
+            "t" => &mut self.t,
+            "meta" => &mut self.meta,
+            "enabled" => &mut self.enabled,
+            _ => return None,
+        })
+    }
+    fn get(self, name: &str) -> bool {
+        match name {
+            "status" => self.status,
+            "value" => self.value,
+            "act" => self.act,
+            "ieid" => self.ieid,
+            "t" => self.t,
+            "meta" => self.meta,
+            "enabled" => self.enabled,
+            _ => false,
+        }
+    }
+}
+
+impl Serialize for StateFields {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_seq(Self::NAMES.iter().filter(|n| self.get(n)))
+    }
+}
+
+/// Deserialized from "full" or a list of field names
+impl<'de> Deserialize<'de> for StateFields {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let fields: ValueOrList<String> = Deserialize::deserialize(deserializer)?;
+        if let ValueOrList::Single(ref s) = fields {
+            if s == "full" {
+                return Ok(Self::full());
+            }
+        }
+        let mut result = Self::none();
+        for name in fields {
+            *result.field_mut(&name).ok_or_else(|| {
+                serde::de::Error::custom(format!("invalid state field: {}", name))
+            })? = true;
+        }
+        Ok(result)
+    }
+}
+
+/// Common params for item.state methods
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ParamsState {
+    #[serde(
+        default,
+        alias = "oid",
+        alias = "items",
+        deserialize_with = "deserialize_oid_mask_list"
+    )]
+    pub i: OIDMaskList,
+    #[serde(default)]
+    pub include: StateFields,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub precision: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
+}
+
+impl ParamsState {
+    #[inline]
+    pub fn matches(&self, oid: &OID) -> bool {
+        self.i.is_empty() || self.i.matches(oid)
+    }
+    /// Filters the states, applies the field selection, precision and limit
+    pub fn apply<I>(&self, states: I) -> Vec<ItemState>
+    where
+        I: IntoIterator<Item = ItemState>,
+    {
+        states
+            .into_iter()
+            .filter(|s| self.matches(&s.oid))
+            .take(self.limit.unwrap_or(usize::MAX))
+            .map(|s| s.with_fields(self.include, self.precision))
+            .collect()
+    }
+}
+
+/// Item state in replies of item.state methods
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ItemState {
+    pub oid: OID,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<ItemStatus>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub act: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ieid: Option<IEID>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub t: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub meta: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+}
+
+impl ItemState {
+    pub fn new(oid: OID) -> Self {
+        Self {
+            oid,
+            status: None,
+            value: None,
+            act: None,
+            ieid: None,
+            t: None,
+            meta: None,
+            enabled: None,
+        }
+    }
+    /// Removes the fields, not selected, rounds the value if required
+    pub fn with_fields(mut self, fields: StateFields, precision: Option<u32>) -> Self {
+        macro_rules! strip {
+            ($($f: ident),*) => {
+                $(if !fields.$f {
+                    self.$f = None;
+                })*
+            };
+        }
+        strip!(status, value, act, ieid, t, meta, enabled);
+        if precision.is_some() {
+            if let Some(value) = self.value.take() {
+                self.value = Some(value.clone().rounded(precision).unwrap_or(value));
+            }
+        }
+        self
+    }
+}
+
+/// Reply of item.state methods
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(transparent)]
+pub struct ReplyState {
+    pub items: Vec<ItemState>,
+}
+
+impl From<Vec<ItemState>> for ReplyState {
+    #[inline]
+    fn from(items: Vec<ItemState>) -> Self {
+        Self { items }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Filter, Filterable, ItemState, Paging, ParamsState, StateFields};
+    use crate::value::Value;
+    use crate::OID;
+
+    #[test]
+    fn test_params_state() {
+        for params in [
+            r#"{"i":"sensor:tests/#"}"#,
+            r#"{"oid":["sensor:tests/#"]}"#,
+            r#"{"items":["sensor:tests/#"],"include":["value"],"precision":1,"limit":1}"#,
+        ] {
+            let p: ParamsState = serde_json::from_str(params).unwrap();
+            assert!(p.matches(&"sensor:tests/s1".parse().unwrap()));
+            assert!(!p.matches(&"unit:tests/u1".parse().unwrap()));
+        }
+        let p: ParamsState = serde_json::from_str(
+            r#"{"i":"sensor:tests/#","include":["value"],"precision":1,"limit":1}"#,
+        )
+        .unwrap();
+        let state = |oid: &str| ItemState {
+            status: Some(1),
+            value: Some(Value::F64(1.2345)),
+            t: Some(1.0),
+            ..ItemState::new(oid.parse().unwrap())
+        };
+        let res = p.apply([
+            state("unit:tests/u1"),
+            state("sensor:tests/s1"),
+            state("sensor:tests/s2"),
+        ]);
+        assert_eq!(res.len(), 1);
+        assert_eq!(
+            serde_json::to_string(&res[0]).unwrap(),
+            r#"{"oid":"sensor:tests/s1","value":1.2}"#
+        );
+        assert_eq!(ParamsState::default().include, StateFields::default());
+        let p: ParamsState = serde_json::from_str(r#"{"include":"full"}"#).unwrap();
+        assert_eq!(p.include, StateFields::full());
+        assert_eq!(
+            serde_json::to_string(&StateFields::default()).unwrap(),
+            r#"["status","value","act","ieid","t"]"#
+        );
+        assert!(serde_json::from_str::<ParamsState>(r#"{"include":["x"]}"#).is_err());
+    }
+
+    struct Item(OID, Option<&'static str>, bool);
+
+    impl Filterable for Item {
+        fn oid(&self) -> &OID {
+            &self.0
+        }
+        fn node(&self) -> Option<&str> {
+            self.1
+        }
+        fn enabled(&self) -> bool {
+            self.2
+        }
+    }
+
+    #[test]
+    fn test_paging_filter() {
+        let p: Paging = serde_json::from_str(r#"{"offset":2,"limit":0}"#).unwrap();
+        assert_eq!(p, Paging::new(2, Some(0)));
+        let (page, info) = p.paginate(vec![1, 2, 3, 4]);
+        assert!(page.is_empty());
+        assert_eq!(info.total, Some(4));
+        assert_eq!(
+            serde_json::to_string(&info).unwrap(),
+            r#"{"offset":2,"limit":0,"total":4}"#
+        );
+        assert_eq!(
+            serde_json::to_string(&Paging::default()).unwrap(),
+            r#"{"offset":0}"#
+        );
+        let (page, info) = Paging::new(3, Some(5)).paginate(vec![1, 2, 3, 4]);
+        assert_eq!(page, [4]);
+        assert_eq!(info.total, Some(4));
+        let (page, _) = Paging::new(usize::MAX, Some(usize::MAX)).paginate(vec![1, 2]);
+        assert!(page.is_empty());
+        let (page, _) = Paging::new(1, None).paginate(vec![1, 2, 3]);
+        assert_eq!(page, [2, 3]);
+        assert!(serde_json::from_str::<Paging>(r#"{"offset":-1}"#).is_err());
+        assert!(serde_json::from_str::<Paging>(r#"{"offset":1,"page":2}"#).is_err());
+        let items = [
+            Item("sensor:tests/s1".parse().unwrap(), None, true),
+            Item("sensor:tests/s2".parse().unwrap(), Some("n1"), true),
+            Item("sensor:tests/s3".parse().unwrap(), Some("n1"), false),
+            Item("unit:tests/u1".parse().unwrap(), Some("n1"), true),
+        ];
+        let names = |filter: &Filter| -> Vec<String> {
+            filter
+                .apply(items.iter())
+                .map(|i| i.0.to_string())
+                .collect()
+        };
+        assert_eq!(names(&Filter::default()).len(), 3);
+        let filter: Filter =
+            serde_json::from_str(r#"{"node":"n1","include_disabled":true}"#).unwrap();
+        assert_eq!(names(&filter).len(), 3);
+        let filter: Filter = serde_json::from_str(r#"{"masks":["sensor:#"],"node":"n1"}"#).unwrap();
+        assert_eq!(names(&filter), ["sensor:tests/s2"]);
+        assert_eq!(
+            serde_json::to_string(&Filter::default()).unwrap(),
+            r#"{"include_disabled":false}"#
+        );
+        assert!(serde_json::from_str::<Filter>(r#"{"mask":"sensor:#"}"#).is_err());
+    }
+}
@@ -0,0 +1,473 @@
+//! Config deserialization with error locations
+//!
+//! Plain serde errors do not tell where in the config the problem is. [`ConfigError`] carries
+//! the key path (e.g. "pull[2].map[0].reg"), the expected type and the value got, if known.
+//!
+//! [`Loader`] merges configuration layers from various sources with provenance tracking.
+use crate::value::{DeserializerError, Value};
+use crate::{EResult, Error};
+use serde::de::DeserializeOwned;
+use serde::{Deserializer, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigError {
+    path: String,
+    expected: Option<String>,
+    got: Option<String>,
+    message: String,
+}
+
+impl ConfigError {
+    /// Key path, "." for the config root
+    #[inline]
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+    #[inline]
+    pub fn expected(&self) -> Option<&str> {
+        self.expected.as_deref()
+    }
+    #[inline]
+    pub fn got(&self) -> Option<&str> {
+        self.got.as_deref()
+    }
+    #[inline]
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid config at {}: {}", self.path, self.message)
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl From<ConfigError> for Error {
+    fn from(e: ConfigError) -> Self {
+        Error::invalid_data(e)
+    }
+}
+
+impl From<serde_path_to_error::Error<DeserializerError>> for ConfigError {
+    fn from(e: serde_path_to_error::Error<DeserializerError>) -> Self {
+        let path = e.path().to_string();
+        let inner = e.into_inner();
+        let message = inner.to_string();
+        let (expected, got) = match inner {
+            DeserializerError::InvalidType(unexp, exp)
+            | DeserializerError::InvalidValue(unexp, exp) => {
+                (Some(exp), Some(unexp.to_unexpected().to_string()))
+            }
+            DeserializerError::InvalidLength(len, exp) => {
+                (Some(exp), Some(format!("length {}", len)))
+            }
+            DeserializerError::UnknownVariant(v, exp) | DeserializerError::UnknownField(v, exp) => {
+                (Some(format!("one of {}", exp.join(", "))), Some(v))
+            }
+            DeserializerError::MissingField(field) => (Some(format!("field {}", field)), None),
+            DeserializerError::Custom(_) | DeserializerError::DuplicateField(_) => (None, None),
+        };
+        Self {
+            path,
+            expected,
+            got,
+            message,
+        }
+    }
+}
+
+/// Deserializes a config value
+pub fn from_value<T: DeserializeOwned>(value: Value) -> Result<T, ConfigError> {
+    serde_path_to_error::deserialize(value).map_err(Into::into)
+}
+
+/// Deserializes a config with an arbitrary deserializer (JSON, YAML etc.). Only the key path and
+/// the message are available in errors
+pub fn deserialize<'de, D, T>(deserializer: D) -> Result<T, ConfigError>
+where
+    D: Deserializer<'de>,
+    T: serde::Deserialize<'de>,
+{
+    serde_path_to_error::deserialize(deserializer).map_err(|e| ConfigError {
+        path: e.path().to_string(),
+        expected: None,
+        got: None,
+        message: e.into_inner().to_string(),
+    })
+}
+
+/// Configuration source, in priority order (the last one wins)
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Source {
+    Default,
+    Registry,
+    File,
+    Env,
+    Override,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Source::Default => "default",
+            Source::Registry => "registry",
+            Source::File => "file",
+            Source::Env => "env",
+            Source::Override => "override",
+        })
+    }
+}
+
+/// Merges configuration layers
+///
+/// Layers are merged in [`Source`] priority order, no matter in which order they are added:
+/// maps are merged recursively, other values (including arrays) are replaced. Keys of env
+/// variables and overrides are dot-separated paths ("bus.timeout"), values are parsed with type
+/// guessing (see `FromStr` for [`Value`])
+#[derive(Debug, Default, Clone)]
+pub struct Loader {
+    layers: Vec<(Source, Value)>,
+}
+
+impl Loader {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+    #[inline]
+    pub fn defaults(self, value: Value) -> Self {
+        self.layer(Source::Default, value)
+    }
+    /// Sets a registry layer, e.g. a value, fetched with [`Loader::load_registry`]
+    #[inline]
+    pub fn registry(self, value: Value) -> Self {
+        self.layer(Source::Registry, value)
+    }
+    /// Loads a registry subtree
+    #[cfg(feature = "registry")]
+    pub async fn load_registry(
+        self,
+        path: &crate::registry::KeyPath,
+        rpc: &busrt::rpc::RpcClient,
+    ) -> EResult<Self> {
+        let mut value = Value::Map(BTreeMap::new());
+        for (key, v) in crate::registry::key_get_recursive_path(path, rpc).await? {
+            set_path(&mut value, &key.split('/').collect::<Vec<_>>(), v)?;
+        }
+        Ok(self.registry(value))
+    }
+    #[inline]
+    pub fn file_value(self, value: Value) -> Self {
+        self.layer(Source::File, value)
+    }
+    /// Loads a config file. JSON files must have ".json" extension, others are parsed as YAML
+    /// (requires "extended-value" feature)
+    pub fn file<P: AsRef<Path>>(self, path: P) -> EResult<Self> {
+        let path = path.as_ref();
+        let content = std::fs::read(path)
+            .map_err(|e| Error::io(format!("unable to read {}: {}", path.display(), e)))?;
+        let value: Value = if path.extension().is_some_and(|ext| ext == "json") {
+            serde_json::from_slice(&content).map_err(Error::invalid_data)?
+        } else {
+            #[cfg(feature = "extended-value")]
+            {
+                serde_yaml::from_slice(&content).map_err(Error::invalid_data)?
+            }
+            #[cfg(not(feature = "extended-value"))]
+            {
+                return Err(Error::unsupported(
+                    "YAML config files require extended-value feature",
+                ));
+            }
+        };
+        Ok(self.file_value(value))
+    }
+    /// Sets env variables with the prefix, e.g. with the prefix "SVC_" the variable
+    /// "SVC_BUS__TIMEOUT" sets "bus.timeout" ("__" separates levels, names are lowercased).
+    /// Variables with non-UTF-8 names or values are skipped
+    #[inline]
+    pub fn env(self, prefix: &str) -> EResult<Self> {
+        self.env_vars(
+            prefix,
+            std::env::vars_os()
+                .filter_map(|(name, v)| Some((name.into_string().ok()?, v.into_string().ok()?))),
+        )
+    }
+    /// Same as [`Loader::env`] but for the provided variables. A variable, named exactly as the
+    /// prefix, is skipped
+    pub fn env_vars<I>(self, prefix: &str, vars: I) -> EResult<Self>
+    where
+        I: IntoIterator<Item = (String, String)>,
+    {
+        let mut value = Value::Map(BTreeMap::new());
+        for (name, v) in vars {
+            if let Some(key) = name.strip_prefix(prefix).filter(|key| !key.is_empty()) {
+                let key = key.to_lowercase();
+                set_path(
+                    &mut value,
+                    &key.split("__").collect::<Vec<_>>(),
+                    v.parse().unwrap(),
+                )?;
+            }
+        }
+        Ok(self.layer(Source::Env, value))
+    }
+    /// Sets CLI-style overrides, e.g. "bus.timeout=5"
+    pub fn overrides<S: AsRef<str>>(self, overrides: &[S]) -> EResult<Self> {
+        let mut value = Value::Map(BTreeMap::new());
+        for o in overrides {
+            let o = o.as_ref();
+            let (key, v) = o
+                .split_once('=')
+                .ok_or_else(|| Error::invalid_params(format!("invalid override: {}", o)))?;
+            set_path(
+                &mut value,
+                &key.trim().split('.').collect::<Vec<_>>(),
+                v.parse().unwrap(),
+            )?;
+        }
+        Ok(self.layer(Source::Override, value))
+    }
+    fn layer(mut self, source: Source, value: Value) -> Self {
+        self.layers.push((source, value));
+        self
+    }
+    /// Merges all layers
+    pub fn load(mut self) -> Loaded {
+        // stable, so layers of the same source keep their order
+        self.layers.sort_by_key(|(source, _)| *source);
+        let mut loaded = Loaded {
+            value: Value::Map(BTreeMap::new()),
+            provenance: BTreeMap::new(),
+        };
+        for (source, value) in self.layers {
+            merge(&mut loaded.value, value, "", source, &mut loaded.provenance);
+        }
+        loaded
+    }
+}
+
+/// Merged configuration
+#[derive(Debug, Clone)]
+pub struct Loaded {
+    value: Value,
+    provenance: BTreeMap<String, Source>,
+}
+
+impl Loaded {
+    #[inline]
+    pub fn value(&self) -> &Value {
+        &self.value
+    }
+    #[inline]
+    pub fn into_value(self) -> Value {
+        self.value
+    }
+    /// Source of a key (dot-separated path). For maps, returns the source if all keys of the
+    /// map are set by the same one
+    pub fn source(&self, key: &str) -> Option<Source> {
+        if let Some(source) = self.provenance.get(key) {
+            return Some(*source);
+        }
+        let prefix = format!("{}.", key);
+        let mut sources = self
+            .provenance
+            .range(prefix.clone()..)
+            .take_while(|(k, _)| k.starts_with(&prefix))
+            .map(|(_, s)| *s);
+        let first = sources.next()?;
+        sources.all(|s| s == first).then_some(first)
+    }
+    /// Sources of all keys (dot-separated paths of leaf values)
+    #[inline]
+    pub fn provenance(&self) -> &BTreeMap<String, Source> {
+        &self.provenance
+    }
+    #[inline]
+    pub fn deserialize<T: DeserializeOwned>(&self) -> Result<T, ConfigError> {
+        from_value(self.value.clone())
+    }
+}
+
+fn set_path(target: &mut Value, path: &[&str], value: Value) -> EResult<()> {
+    let Some((key, rest)) = path.split_first().filter(|(k, _)| !k.is_empty()) else {
+        return Err(Error::invalid_params("invalid config key"));
+    };
+    let Value::Map(map) = target else {
+        return Err(Error::invalid_params(format!(
+            "config key {} conflicts with a value",
+            key
+        )));
+    };
+    if rest.is_empty() {
+        map.insert(Value::String((*key).to_owned()), value);
+        Ok(())
+    } else {
+        let entry = map
+            .entry(Value::String((*key).to_owned()))
+            .or_insert_with(|| Value::Map(BTreeMap::new()));
+        set_path(entry, rest, value)
+    }
+}
+
+fn join_key(path: &str, key: &Value) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+fn record(value: &Value, path: &str, source: Source, provenance: &mut BTreeMap<String, Source>) {
+    match value {
+        Value::Map(m) if !m.is_empty() => {
+            for (k, v) in m {
+                record(v, &join_key(path, k), source, provenance);
+            }
+        }
+        _ => {
+            provenance.insert(path.to_owned(), source);
+        }
+    }
+}
+
+fn merge(
+    target: &mut Value,
+    value: Value,
+    path: &str,
+    source: Source,
+    provenance: &mut BTreeMap<String, Source>,
+) {
+    match (target, value) {
+        (Value::Map(target), Value::Map(map)) => {
+            if !path.is_empty() {
+                // an empty map could be recorded as a leaf
+                provenance.remove(path);
+            }
+            for (k, v) in map {
+                let key_path = join_key(path, &k);
+                if let Some(t) = target.get_mut(&k) {
+                    merge(t, v, &key_path, source, provenance);
+                } else {
+                    record(&v, &key_path, source, provenance);
+                    target.insert(k, v);
+                }
+            }
+            if target.is_empty() && !path.is_empty() {
+                provenance.entry(path.to_owned()).or_insert(source);
+            }
+        }
+        (target, value) => {
+            let prefix = format!("{}.", path);
+            provenance.retain(|k, _| k != path && !k.starts_with(&prefix));
+            record(&value, path, source, provenance);
+            *target = value;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{from_value, Loader, Source};
+    use serde::Deserialize;
+
+    #[derive(Deserialize, Debug)]
+    #[serde(deny_unknown_fields)]
+    #[allow(dead_code)]
+    struct Reg {
+        reg: u16,
+        oid: String,
+    }
+
+    #[derive(Deserialize, Debug)]
+    #[allow(dead_code)]
+    struct Config {
+        pull: Vec<Reg>,
+    }
+
+    #[test]
+    fn test_config_error() {
+        let config: crate::value::Value = serde_json::from_str(
+            r#"{"pull":[{"reg":1,"oid":"sensor:s1"},{"reg":"x","oid":"sensor:s2"}]}"#,
+        )
+        .unwrap();
+        let e = from_value::<Config>(config).unwrap_err();
+        assert_eq!(e.path(), "pull[1].reg");
+        assert_eq!(e.expected(), Some("u16"));
+        assert_eq!(e.got(), Some("string \"x\""));
+        assert!(e.to_string().starts_with("invalid config at pull[1].reg: "));
+        let config: crate::value::Value =
+            serde_json::from_str(r#"{"pull":[{"reg":1,"oid":"sensor:s1","x":2}]}"#).unwrap();
+        let e = from_value::<Config>(config).unwrap_err();
+        assert_eq!(e.path(), "pull[0].x");
+        assert_eq!(e.got(), Some("x"));
+        let e = super::deserialize::<_, Config>(&mut serde_json::Deserializer::from_str(
+            r#"{"pull":[{"oid":"sensor:s1"}]}"#,
+        ))
+        .unwrap_err();
+        assert_eq!(e.path(), "pull[0]");
+        assert_eq!(crate::Error::from(e).kind(), crate::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_loader() {
+        #[derive(Deserialize)]
+        struct Bus {
+            path: String,
+            timeout: f64,
+        }
+        #[derive(Deserialize)]
+        struct Config {
+            bus: Bus,
+            tags: Vec<String>,
+        }
+        let dir = std::env::temp_dir().join(format!("eva-config-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("config.json");
+        std::fs::write(
+            &file,
+            r#"{"bus":{"path":"/tmp/bus.sock"},"tags":["a","b"]}"#,
+        )
+        .unwrap();
+        let loaded = Loader::new()
+            .overrides(&["bus.timeout=10"])
+            .unwrap()
+            .env_vars(
+                "SVC_",
+                [
+                    ("SVC_BUS__TIMEOUT".to_owned(), "5".to_owned()),
+                    ("OTHER_X".to_owned(), "1".to_owned()),
+                    ("SVC_".to_owned(), "1".to_owned()),
+                ],
+            )
+            .unwrap()
+            .file(&file)
+            .unwrap()
+            .registry(serde_json::from_str(r#"{"bus":{"path":"/reg.sock"},"x":1}"#).unwrap())
+            .defaults(serde_json::from_str(r#"{"bus":{"timeout":1.0},"tags":[]}"#).unwrap())
+            .load();
+        std::fs::remove_dir_all(&dir).unwrap();
+        let config: Config = loaded.deserialize().unwrap();
+        assert_eq!(config.bus.path, "/tmp/bus.sock");
+        assert!((config.bus.timeout - 10.0).abs() < f64::EPSILON);
+        assert_eq!(config.tags, ["a", "b"]);
+        assert_eq!(loaded.source("bus.path"), Some(Source::File));
+        assert_eq!(loaded.source("bus.timeout"), Some(Source::Override));
+        assert_eq!(loaded.source("tags"), Some(Source::File));
+        assert_eq!(loaded.source("x"), Some(Source::Registry));
+        assert_eq!(loaded.source("bus"), None);
+        assert_eq!(loaded.source("other_x"), None);
+        assert_eq!(loaded.provenance().len(), 4);
+        assert!(Loader::new().overrides(&["bus.timeout"]).is_err());
+        assert!(Loader::new()
+            .overrides(&["bus=1", "bus.timeout=2"])
+            .is_err());
+    }
+}
@@ -0,0 +1,763 @@
+//! Leader election for redundant service instances
+//!
+//! Instances of a group announce themselves to [`ELECTION_TOPIC`]`<group>` every renew interval.
+//! The alive instance with the highest priority (the lowest id on equal priorities) becomes the
+//! leader. A leader steps down as soon as it sees a better candidate, a candidate takes over only
+//! when no other instance claims the leadership (or its lease has expired).
+//!
+//! Each new leader gets a fencing token, which is greater than any token seen before, so
+//! resources can reject actions of stale leaders.
+//!
+//! [`Lock`] provides mutual exclusion across nodes, [`IdAllocator`] provides cluster-wide unique
+//! numeric ids, both are backed by the registry.
+use crate::payload::{pack, unpack};
+use crate::registry::{self, KeyPath};
+use crate::value::{to_value, Value};
+use crate::{EResult, Error, ErrorKind};
+use busrt::client::AsyncClient;
+use busrt::rpc::RpcClient;
+use busrt::{Frame, QoS};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::sync::atomic;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::watch;
+
+pub const ELECTION_TOPIC: &str = "SVC/ELECT/";
+/// Leases, announced by peers, are clamped to the own lease multiplied by this factor
+pub const MAX_PEER_LEASE_FACTOR: u32 = 10;
+/// Minimum lock TTL, the lease is renewed every TTL/3
+pub const MIN_LOCK_TTL: Duration = Duration::from_millis(100);
+
+/// Instance announcement
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Announce {
+    pub id: String,
+    pub priority: i32,
+    pub leader: bool,
+    /// The leader fencing token or the max token seen by a follower
+    pub token: u64,
+    /// Lease duration (seconds)
+    pub lease: f64,
+}
+
+/// Instance role change
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Role {
+    Follower { leader: Option<String> },
+    Leader { token: u64 },
+}
+
+#[derive(Debug)]
+struct Peer {
+    priority: i32,
+    leader: bool,
+    expires: Instant,
+}
+
+#[derive(Debug)]
+struct State {
+    peers: BTreeMap<String, Peer>,
+    token: u64,
+    leader: bool,
+    started: Instant,
+}
+
+/// Leader elector, cheap to clone
+#[derive(Clone)]
+pub struct Elector {
+    group: String,
+    id: String,
+    priority: i32,
+    lease: Duration,
+    state: Arc<Mutex<State>>,
+    tx: Arc<watch::Sender<Role>>,
+}
+
+/// Compares candidates: higher priority is better, on equal priorities the lower id is better
+fn cmp_candidates(a: (i32, &str), b: (i32, &str)) -> Ordering {
+    a.0.cmp(&b.0).then_with(|| b.1.cmp(a.1))
+}
+
+impl Elector {
+    /// # Panics
+    ///
+    /// Will panic if the lease is zero
+    pub fn new(group: &str, id: &str, priority: i32, lease: Duration) -> Self {
+        assert!(!lease.is_zero(), "lease must be non-zero");
+        let (tx, _) = watch::channel(Role::Follower { leader: None });
+        Self {
+            group: group.to_owned(),
+            id: id.to_owned(),
+            priority,
+            lease,
+            state: Arc::new(Mutex::new(State {
+                peers: BTreeMap::new(),
+                token: 0,
+                leader: false,
+                started: Instant::now(),
+            })),
+            tx: Arc::new(tx),
+        }
+    }
+    /// Subscribes the bus client to the group announcements
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the subscription failed
+    pub async fn subscribe<C>(&self, client: &mut C) -> EResult<()>
+    where
+        C: AsyncClient + ?Sized,
+    {
+        client
+            .subscribe(&self.topic(), QoS::Processed)
+            .await?
+            .ok_or_else(|| Error::io("no subscription confirmation"))?
+            .await??;
+        Ok(())
+    }
+    #[inline]
+    pub fn topic(&self) -> String {
+        format!("{}{}", ELECTION_TOPIC, self.group)
+    }
+    /// Announcements must be published (with [`Elector::announce()`]) at this interval
+    #[inline]
+    pub fn renew_interval(&self) -> Duration {
+        self.lease / 3
+    }
+    #[inline]
+    pub fn is_leader(&self) -> bool {
+        self.state.lock().leader
+    }
+    /// Returns the fencing token if the instance is the leader
+    pub fn token(&self) -> Option<u64> {
+        let state = self.state.lock();
+        state.leader.then_some(state.token)
+    }
+    /// The current leader, as known by the instance
+    pub fn leader(&self) -> Option<String> {
+        match &*self.tx.borrow() {
+            Role::Leader { .. } => Some(self.id.clone()),
+            Role::Follower { leader } => leader.clone(),
+        }
+    }
+    /// Subscribes to role changes
+    pub fn changes(&self) -> watch::Receiver<Role> {
+        self.tx.subscribe()
+    }
+    /// Processes a bus frame, returns false if the frame is not an announcement of the group
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the frame payload is invalid
+    pub fn process_frame(&self, frame: &Frame) -> EResult<bool> {
+        if frame.topic() != Some(self.topic().as_str()) {
+            return Ok(false);
+        }
+        let announce: Announce = unpack(frame.payload())?;
+        self.process_at(&announce, Instant::now());
+        Ok(true)
+    }
+    fn process_at(&self, announce: &Announce, now: Instant) {
+        if announce.id == self.id {
+            return;
+        }
+        let mut state = self.state.lock();
+        state.token = state.token.max(announce.token);
+        let lease = Duration::try_from_secs_f64(announce.lease)
+            .unwrap_or(self.lease)
+            .min(self.lease.saturating_mul(MAX_PEER_LEASE_FACTOR));
+        let Some(expires) = now.checked_add(lease) else {
+            return;
+        };
+        state.peers.insert(
+            announce.id.clone(),
+            Peer {
+                priority: announce.priority,
+                leader: announce.leader,
+                expires,
+            },
+        );
+        self.elect(&mut state, now);
+    }
+    /// Re-evaluates the role and publishes the announcement
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the announcement can not be published
+    pub async fn announce<C>(&self, client: &mut C) -> EResult<()>
+    where
+        C: AsyncClient + ?Sized,
+    {
+        let announce = self.tick_at(Instant::now());
+        client
+            .publish(&self.topic(), pack(&announce)?.into(), QoS::No)
+            .await?;
+        Ok(())
+    }
+    /// Steps down (e.g. before shutdown), the announcement should be published after
+    pub fn resign(&self) -> Announce {
+        let mut state = self.state.lock();
+        state.leader = false;
+        // prevent re-election until the next announcement
+        state.started = Instant::now();
+        self.notify(&state, Instant::now());
+        self.announcement(&state)
+    }
+    fn tick_at(&self, now: Instant) -> Announce {
+        let mut state = self.state.lock();
+        state.peers.retain(|_, peer| peer.expires > now);
+        self.elect(&mut state, now);
+        self.announcement(&state)
+    }
+    fn announcement(&self, state: &State) -> Announce {
+        Announce {
+            id: self.id.clone(),
+            priority: self.priority,
+            leader: state.leader,
+            token: state.token,
+            lease: self.lease.as_secs_f64(),
+        }
+    }
+    fn elect(&self, state: &mut State, now: Instant) {
+        let alive = state.peers.iter().filter(|(_, peer)| peer.expires > now);
+        let is_best = alive.clone().all(|(id, peer)| {
+            cmp_candidates((self.priority, &self.id), (peer.priority, id)) == Ordering::Greater
+        });
+        let other_leader = alive.clone().any(|(_, peer)| peer.leader);
+        let was_leader = state.leader;
+        if state.leader {
+            state.leader = is_best;
+        } else if is_best && !other_leader && now >= state.started + self.lease {
+            state.leader = true;
+            state.token += 1;
+        }
+        if was_leader != state.leader || !state.leader {
+            self.notify(state, now);
+        }
+    }
+    fn notify(&self, state: &State, now: Instant) {
+        let role = if state.leader {
+            Role::Leader { token: state.token }
+        } else {
+            Role::Follower {
+                leader: state
+                    .peers
+                    .iter()
+                    .find(|(_, peer)| peer.leader && peer.expires > now)
+                    .map(|(id, _)| id.clone()),
+            }
+        };
+        if *self.tx.borrow() != role {
+            self.tx.send_replace(role);
+        }
+    }
+}
+
+/// Lock lease record
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Lease {
+    generation: u64,
+    owner: String,
+    /// UNIX timestamp
+    expires: f64,
+}
+
+impl Lease {
+    fn is_expired(&self, now: f64) -> bool {
+        self.expires < now
+    }
+}
+
+fn unix_now() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs_f64())
+        .unwrap_or_default()
+}
+
+/// Registry keys of a lock
+#[derive(Debug, Clone)]
+struct LockKeys {
+    generation: KeyPath,
+    lease: KeyPath,
+    base: KeyPath,
+}
+
+impl LockKeys {
+    fn new(name: &str) -> EResult<Self> {
+        let base = KeyPath::new(registry::R_DATA)?.join("lock")?.join(name)?;
+        Ok(Self {
+            generation: base.join("generation")?,
+            lease: base.join("lease")?,
+            base,
+        })
+    }
+    fn counter(&self, generation: u64) -> EResult<KeyPath> {
+        self.base.join(&format!("counter/{}", generation))
+    }
+}
+
+fn check_ttl(ttl: Duration) -> EResult<()> {
+    if ttl < MIN_LOCK_TTL {
+        return Err(Error::invalid_params(format!(
+            "lock TTL must be at least {:?}",
+            MIN_LOCK_TTL
+        )));
+    }
+    Ok(())
+}
+
+async fn get_generation(keys: &LockKeys, rpc: &RpcClient) -> EResult<u64> {
+    match registry::key_get_path(&keys.generation, rpc).await? {
+        Value::Unit => Ok(0),
+        v => u64::try_from(v),
+    }
+}
+
+async fn get_lease(keys: &LockKeys, rpc: &RpcClient) -> EResult<Option<Lease>> {
+    match registry::key_get_path(&keys.lease, rpc).await? {
+        Value::Unit => Ok(None),
+        v => Ok(Some(Lease::deserialize(v)?)),
+    }
+}
+
+/// Distributed lock, backed by the registry
+///
+/// The registry provides no compare-and-swap, so the lock is built on atomic key increments:
+/// each lock generation has a counter and the instance which increments it first owns the
+/// generation. Generations are used as fencing tokens. A lock with an expired lease (the owner
+/// has crashed or lost the registry connection) is broken by moving to the next generation, so
+/// node clocks must be synchronized.
+///
+/// The lease is renewed by a background task, the lock is released on drop (requires a Tokio
+/// runtime) or with [`Lock::release()`].
+pub struct Lock {
+    inner: Arc<LockInner>,
+    renewer: tokio::task::JoinHandle<()>,
+}
+
+struct LockInner {
+    rpc: Arc<RpcClient>,
+    name: String,
+    owner: String,
+    keys: LockKeys,
+    generation: u64,
+    ttl: Duration,
+    held: atomic::AtomicBool,
+}
+
+impl LockInner {
+    async fn write_lease(&self) -> EResult<()> {
+        let lease = Lease {
+            generation: self.generation,
+            owner: self.owner.clone(),
+            expires: unix_now() + self.ttl.as_secs_f64(),
+        };
+        registry::key_set_path(&self.keys.lease, to_value(lease)?, &self.rpc).await?;
+        Ok(())
+    }
+    async fn renew(&self) -> EResult<()> {
+        if get_generation(&self.keys, &self.rpc).await? != self.generation
+            || !get_lease(&self.keys, &self.rpc)
+                .await?
+                .is_some_and(|l| l.generation == self.generation && l.owner == self.owner)
+        {
+            return Err(Error::new(
+                ErrorKind::Aborted,
+                format!("lock {} has been lost", self.name),
+            ));
+        }
+        self.write_lease().await
+    }
+    async fn release(&self) -> EResult<()> {
+        if !self.held.swap(false, atomic::Ordering::SeqCst) {
+            return Ok(());
+        }
+        if get_generation(&self.keys, &self.rpc).await? == self.generation {
+            registry::key_set_path(&self.keys.generation, self.generation + 1, &self.rpc).await?;
+        }
+        // the counter of the released generation is kept, otherwise an instance which has read
+        // the generation before the release could own it again
+        if let Some(prev) = self.generation.checked_sub(1) {
+            registry::key_delete_path(&self.keys.counter(prev)?, &self.rpc).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Result of a single lock attempt
+enum Attempt {
+    Acquired(u64),
+    Busy(u64, Option<Lease>),
+}
+
+async fn attempt(keys: &LockKeys, owner: &str, ttl: Duration, rpc: &RpcClient) -> EResult<Attempt> {
+    let lease = get_lease(keys, rpc).await?;
+    // the generation may go back if a slow instance breaks an already released lock
+    let generation = get_generation(keys, rpc)
+        .await?
+        .max(lease.as_ref().map_or(0, |l| l.generation));
+    let counter = registry::key_increment_path(&keys.counter(generation)?, rpc).await?;
+    if counter == 1 {
+        // the generation has been released or broken after it has been read
+        let current = get_generation(keys, rpc).await?;
+        if current > generation {
+            return Ok(Attempt::Busy(current, None));
+        }
+        let lease = Lease {
+            generation,
+            owner: owner.to_owned(),
+            expires: unix_now() + ttl.as_secs_f64(),
+        };
+        registry::key_set_path(&keys.lease, to_value(lease)?, rpc).await?;
+        Ok(Attempt::Acquired(generation))
+    } else {
+        Ok(Attempt::Busy(
+            generation,
+            lease.filter(|l| l.generation == generation),
+        ))
+    }
+}
+
+impl Lock {
+    /// Tries to acquire the lock once
+    ///
+    /// # Errors
+    ///
+    /// Will return [`ErrorKind::ResourceBusy`] if the lock is held by another owner,
+    /// [`ErrorKind::InvalidParameter`] if the TTL is less than [`MIN_LOCK_TTL`]
+    pub async fn try_acquire(
+        rpc: &Arc<RpcClient>,
+        name: &str,
+        owner: &str,
+        ttl: Duration,
+    ) -> EResult<Self> {
+        check_ttl(ttl)?;
+        let keys = LockKeys::new(name)?;
+        match attempt(&keys, owner, ttl, rpc).await? {
+            Attempt::Acquired(generation) => {
+                Ok(Self::start(rpc, name, owner, keys, generation, ttl))
+            }
+            Attempt::Busy(generation, lease) => {
+                if lease.as_ref().is_some_and(|l| l.is_expired(unix_now())) {
+                    registry::key_set_path(&keys.generation, generation + 1, rpc).await?;
+                }
+                Err(Error::busy(format!(
+                    "lock {} is held by {}",
+                    name,
+                    lease.map_or_else(|| "unknown".to_owned(), |l| l.owner)
+                )))
+            }
+        }
+    }
+    /// Acquires the lock, waiting for it up to the timeout. A lock generation without a lease
+    /// (the owner has crashed right after acquiring) is broken after the TTL
+    ///
+    /// # Errors
+    ///
+    /// Will return [`ErrorKind::Timeout`] if the lock has not been acquired in time,
+    /// [`ErrorKind::InvalidParameter`] if the TTL is less than [`MIN_LOCK_TTL`]
+    pub async fn acquire(
+        rpc: &Arc<RpcClient>,
+        name: &str,
+        owner: &str,
+        ttl: Duration,
+        timeout: Duration,
+    ) -> EResult<Self> {
+        check_ttl(ttl)?;
+        let keys = LockKeys::new(name)?;
+        let retry = (ttl / 10).clamp(Duration::from_millis(10), Duration::from_secs(1));
+        tokio::time::timeout(timeout, async {
+            let mut no_lease_since: Option<(u64, Instant)> = None;
+            loop {
+                let (generation, lease) = match attempt(&keys, owner, ttl, rpc).await? {
+                    Attempt::Acquired(generation) => {
+                        return Ok(Self::start(rpc, name, owner, keys.clone(), generation, ttl));
+                    }
+                    Attempt::Busy(generation, lease) => (generation, lease),
+                };
+                let stale = if let Some(lease) = lease {
+                    no_lease_since = None;
+                    lease.is_expired(unix_now())
+                } else {
+                    match no_lease_since {
+                        Some((g, since)) if g == generation => since.elapsed() > ttl,
+                        _ => {
+                            no_lease_since = Some((generation, Instant::now()));
+                            false
+                        }
+                    }
+                };
+                if stale {
+                    registry::key_set_path(&keys.generation, generation + 1, rpc).await?;
+                } else {
+                    tokio::time::sleep(retry).await;
+                }
+            }
+        })
+        .await?
+    }
+    fn start(
+        rpc: &Arc<RpcClient>,
+        name: &str,
+        owner: &str,
+        keys: LockKeys,
+        generation: u64,
+        ttl: Duration,
+    ) -> Self {
+        let inner = Arc::new(LockInner {
+            rpc: rpc.clone(),
+            name: name.to_owned(),
+            owner: owner.to_owned(),
+            keys,
+            generation,
+            ttl,
+            held: atomic::AtomicBool::new(true),
+        });
+        let lock = inner.clone();
+        let renewer = tokio::spawn(async move {
+            let mut int = tokio::time::interval(lock.ttl / 3);
+            int.tick().await;
+            loop {
+                int.tick().await;
+                if let Err(e) = lock.renew().await {
+                    if e.kind() == ErrorKind::Aborted {
+                        log::error!("{}", e);
+                        lock.held.store(false, atomic::Ordering::SeqCst);
+                        break;
+                    }
+                    log::warn!("lock {} renewal failed: {}", lock.name, e);
+                }
+            }
+        });
+        Self { inner, renewer }
+    }
+    /// Fencing token, increases with every new lock owner
+    #[inline]
+    pub fn token(&self) -> u64 {
+        self.inner.generation
+    }
+    /// Returns false if the lock has been lost (e.g. the lease has expired and the lock has been
+    /// acquired by another owner) or released
+    #[inline]
+    pub fn is_held(&self) -> bool {
+        self.inner.held.load(atomic::Ordering::SeqCst)
+    }
+    /// Releases the lock
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the registry is not available, the lock is released by the lease
+    /// expiration in this case
+    pub async fn release(self) -> EResult<()> {
+        self.renewer.abort();
+        self.inner.release().await
+    }
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        self.renewer.abort();
+        if self.is_held() {
+            if let Ok(handle) = tokio::runtime::Handle::try_current() {
+                let inner = self.inner.clone();
+                handle.spawn(async move {
+                    if let Err(e) = inner.release().await {
+                        log::warn!("lock {} release failed: {}", inner.name, e);
+                    }
+                });
+            }
+        }
+    }
+}
+
+/// Cluster-wide unique numeric id allocator
+///
+/// Ids are leased from a registry counter in blocks, so most ids are allocated locally. All
+/// allocators of the same sequence must use the same block size, which is stored in the registry
+/// with the first block lease. Ids are unique but not ordered across allocators, ids of
+/// partially used blocks are lost on restarts.
+pub struct IdAllocator {
+    rpc: Arc<RpcClient>,
+    name: String,
+    counter: KeyPath,
+    block_size_key: KeyPath,
+    block_size: u64,
+    block: tokio::sync::Mutex<Option<Range<u64>>>,
+}
+
+impl IdAllocator {
+    pub const DEFAULT_BLOCK_SIZE: u64 = 1000;
+    /// # Errors
+    ///
+    /// Will return `Err` if the sequence name is not a valid registry key
+    pub fn new(rpc: &Arc<RpcClient>, name: &str) -> EResult<Self> {
+        let base = KeyPath::new(registry::R_DATA)?.join("seq")?.join(name)?;
+        Ok(Self {
+            rpc: rpc.clone(),
+            name: name.to_owned(),
+            counter: base.join("block")?,
+            block_size_key: base.join("block_size")?,
+            block_size: Self::DEFAULT_BLOCK_SIZE,
+            block: <_>::default(),
+        })
+    }
+    /// # Panics
+    ///
+    /// Will panic if the value is zero
+    pub fn block_size(mut self, block_size: u64) -> Self {
+        assert!(block_size > 0, "block size must be positive");
+        self.block_size = block_size;
+        self
+    }
+    /// Allocates a new id, starting from 1
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if a new block can not be leased
+    pub async fn next(&self) -> EResult<u64> {
+        let mut block = self.block.lock().await;
+        if let Some(id) = block.as_mut().and_then(Iterator::next) {
+            return Ok(id);
+        }
+        let mut range = self.lease_block().await?;
+        let id = range.next().ok_or_else(|| Error::core("empty id block"))?;
+        block.replace(range);
+        Ok(id)
+    }
+    async fn lease_block(&self) -> EResult<Range<u64>> {
+        match registry::key_get_path(&self.block_size_key, &self.rpc).await? {
+            Value::Unit => {
+                registry::key_set_path(&self.block_size_key, self.block_size, &self.rpc).await?;
+            }
+            v => {
+                let block_size = u64::try_from(v)?;
+                if block_size != self.block_size {
+                    return Err(Error::invalid_params(format!(
+                        "id sequence {} block size mismatch: {}, the registry has {}",
+                        self.name, self.block_size, block_size
+                    )));
+                }
+            }
+        }
+        let block = registry::key_increment_path(&self.counter, &self.rpc).await?;
+        block_range(block, self.block_size)
+    }
+}
+
+/// Id range of a block number (starting from 1), returned by the registry counter
+fn block_range(block: i64, block_size: u64) -> EResult<Range<u64>> {
+    let n = u64::try_from(block)
+        .ok()
+        .filter(|n| *n > 0)
+        .ok_or_else(|| Error::invalid_data(format!("invalid id block number: {}", block)))?;
+    let start = (n - 1)
+        .checked_mul(block_size)
+        .and_then(|v| v.checked_add(1))
+        .ok_or_else(|| Error::invalid_data("id sequence overflow"))?;
+    let end = start
+        .checked_add(block_size)
+        .ok_or_else(|| Error::invalid_data("id sequence overflow"))?;
+    Ok(start..end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        block_range, check_ttl, Elector, Lease, LockKeys, Role, MAX_PEER_LEASE_FACTOR, MIN_LOCK_TTL,
+    };
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_elector() {
+        let lease = Duration::from_secs(3);
+        let a = Elector::new("sched", "a", 10, lease);
+        let b = Elector::new("sched", "b", 10, lease);
+        let t0 = Instant::now();
+        // no leader until peers are discovered
+        let ann_a = a.tick_at(t0 + lease / 2);
+        let ann_b = b.tick_at(t0 + lease / 2);
+        assert!(!ann_a.leader && !ann_b.leader);
+        b.process_at(&ann_a, t0 + lease / 2);
+        a.process_at(&ann_b, t0 + lease / 2);
+        let t1 = t0 + lease;
+        assert!(!b.tick_at(t1).leader, "a has the lower id");
+        let ann_a = a.tick_at(t1);
+        assert!(ann_a.leader);
+        assert_eq!(a.token(), Some(1));
+        b.process_at(&ann_a, t1);
+        assert_eq!(b.leader().as_deref(), Some("a"));
+        assert_eq!(
+            *b.changes().borrow(),
+            Role::Follower {
+                leader: Some("a".to_owned())
+            }
+        );
+        // a higher priority instance takes over after the current leader steps down
+        let c = Elector::new("sched", "c", 20, lease);
+        let t2 = t1 + lease;
+        c.process_at(&a.tick_at(t2), t2);
+        let ann_c = c.tick_at(t2);
+        assert!(!ann_c.leader, "a still claims the leadership");
+        a.process_at(&ann_c, t2);
+        let ann_a = a.tick_at(t2);
+        assert!(!ann_a.leader);
+        c.process_at(&ann_a, t2);
+        let ann_c = c.tick_at(t2);
+        assert!(ann_c.leader);
+        assert_eq!(c.token(), Some(2), "fencing token is increased");
+        a.process_at(&ann_c, t2);
+        assert_eq!(a.leader().as_deref(), Some("c"));
+        assert!(a.token().is_none());
+        // the leader is gone, a takes over after its lease expires
+        let t3 = t2 + lease * 2;
+        assert!(a.tick_at(t3).leader);
+        assert_eq!(a.token(), Some(3));
+        assert_eq!(*a.changes().borrow(), Role::Leader { token: 3 });
+        // huge remote leases are clamped
+        let mut ann_d = c.tick_at(t3);
+        ann_d.id = "d".to_owned();
+        ann_d.leader = true;
+        ann_d.lease = f64::MAX;
+        a.process_at(&ann_d, t3);
+        assert!(!a.tick_at(t3).leader);
+        assert!(a.tick_at(t3 + lease * MAX_PEER_LEASE_FACTOR).leader);
+    }
+    #[test]
+    fn test_lock_keys() {
+        let keys = LockKeys::new("maintenance/vacuum").unwrap();
+        assert_eq!(
+            keys.generation.to_full_key(),
+            "eva/data/lock/maintenance/vacuum/generation"
+        );
+        assert_eq!(
+            keys.counter(5).unwrap().as_str(),
+            "data/lock/maintenance/vacuum/counter/5"
+        );
+        assert!(LockKeys::new("../x").is_err());
+        let lease = Lease {
+            generation: 1,
+            owner: "node1".to_owned(),
+            expires: 100.0,
+        };
+        assert!(!lease.is_expired(99.0));
+        assert!(lease.is_expired(100.5));
+        assert!(check_ttl(Duration::ZERO).is_err());
+        assert!(check_ttl(Duration::from_nanos(2)).is_err());
+        check_ttl(MIN_LOCK_TTL).unwrap();
+    }
+    #[test]
+    fn test_id_block_range() {
+        assert_eq!(block_range(1, 100).unwrap(), 1..101);
+        assert_eq!(block_range(3, 100).unwrap(), 201..301);
+        assert_eq!(block_range(2, 1).unwrap(), 2..3);
+        assert!(block_range(0, 100).is_err());
+        assert!(block_range(-5, 100).is_err());
+        assert!(block_range(i64::MAX, u64::MAX / 2).is_err());
+    }
+}
@@ -0,0 +1,470 @@
+//! Deployment manifest types
+use crate::inventory::ItemConfig;
+use crate::secret::Secret;
+use crate::tools::check_svc_id;
+use crate::value::{to_value, Value};
+use crate::{EResult, Error};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+
+/// The current deployment manifest schema version
+pub const DEPLOY_VERSION: u16 = 4;
+
+pub const LOCAL_NODE: &str = ".local";
+
+#[inline]
+fn default_node() -> String {
+    LOCAL_NODE.to_owned()
+}
+
+#[inline]
+fn default_version() -> u16 {
+    DEPLOY_VERSION
+}
+
+/// Deployment manifest
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DeploymentManifest {
+    #[serde(default = "default_version")]
+    pub version: u16,
+    #[serde(default)]
+    pub content: Vec<NodeDeployment>,
+}
+
+impl Default for DeploymentManifest {
+    fn default() -> Self {
+        Self {
+            version: DEPLOY_VERSION,
+            content: Vec::new(),
+        }
+    }
+}
+
+impl DeploymentManifest {
+    /// Checks the schema version and validates all node sections
+    pub fn validate(&self) -> EResult<()> {
+        if self.version != DEPLOY_VERSION {
+            return Err(Error::unsupported(format!(
+                "unsupported deployment manifest version: {}",
+                self.version
+            )));
+        }
+        for node in &self.content {
+            node.validate()?;
+        }
+        Ok(())
+    }
+}
+
+/// File to upload
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct UploadFile {
+    /// Local file path or URL
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub src: Option<String>,
+    /// Inline file content
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    /// The target path, relative to the node runtime directory
+    pub target: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<u32>,
+}
+
+impl UploadFile {
+    pub fn validate(&self) -> EResult<()> {
+        if self.src.is_some() == self.text.is_some() {
+            return Err(Error::invalid_params(format!(
+                "either src or text must be specified for the upload target {}",
+                self.target
+            )));
+        }
+        if self.target.is_empty() {
+            return Err(Error::invalid_params("upload target not specified"));
+        }
+        Ok(())
+    }
+}
+
+#[inline]
+fn default_workers() -> u32 {
+    1
+}
+
+#[allow(clippy::trivially_copy_pass_by_ref)]
+#[inline]
+fn is_false(val: &bool) -> bool {
+    !val
+}
+
+/// Service bus config overrides, the core defaults are used for fields not set
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct SvcBusParams {
+    #[serde(default, rename = "type", skip_serializing_if = "Option::is_none")]
+    pub tp: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub buf_size: Option<usize>,
+    /// microseconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub buf_ttl: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub queue_size: Option<usize>,
+}
+
+/// Service timeouts (seconds), the core defaults are used for fields not set
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct SvcTimeoutParams {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub startup: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shutdown: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default: Option<f64>,
+}
+
+/// Service parameters, as expected by the core "svc.deploy" method
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SvcParams {
+    pub command: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prepare_command: Option<String>,
+    #[serde(default)]
+    pub bus: SvcBusParams,
+    #[serde(default = "default_workers")]
+    pub workers: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    #[serde(default)]
+    pub timeout: SvcTimeoutParams,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub react_to_fail: bool,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub call_tracing: bool,
+    /// Service-specific configuration
+    #[serde(default, skip_serializing_if = "Value::is_unit")]
+    pub config: Value,
+}
+
+/// Service deployment spec
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct SvcDeployment {
+    pub id: String,
+    pub params: SvcParams,
+}
+
+impl SvcDeployment {
+    pub fn new(id: &str, command: &str) -> Self {
+        Self {
+            id: id.to_owned(),
+            params: SvcParams {
+                command: command.to_owned(),
+                prepare_command: None,
+                bus: <_>::default(),
+                workers: default_workers(),
+                user: None,
+                timeout: <_>::default(),
+                react_to_fail: false,
+                call_tracing: false,
+                config: Value::Unit,
+            },
+        }
+    }
+    pub fn prepare_command(mut self, command: &str) -> Self {
+        self.params.prepare_command = Some(command.to_owned());
+        self
+    }
+    pub fn bus(mut self, bus: SvcBusParams) -> Self {
+        self.params.bus = bus;
+        self
+    }
+    pub fn bus_path(mut self, path: &str) -> Self {
+        self.params.bus.path = Some(path.to_owned());
+        self
+    }
+    pub fn workers(mut self, workers: u32) -> Self {
+        self.params.workers = workers;
+        self
+    }
+    pub fn user(mut self, user: &str) -> Self {
+        self.params.user = Some(user.to_owned());
+        self
+    }
+    pub fn timeout(mut self, timeout: SvcTimeoutParams) -> Self {
+        self.params.timeout = timeout;
+        self
+    }
+    /// Sets the default timeout
+    pub fn default_timeout(mut self, timeout: f64) -> Self {
+        self.params.timeout.default = Some(timeout);
+        self
+    }
+    pub fn react_to_fail(mut self) -> Self {
+        self.params.react_to_fail = true;
+        self
+    }
+    pub fn call_tracing(mut self) -> Self {
+        self.params.call_tracing = true;
+        self
+    }
+    pub fn config(mut self, config: Value) -> Self {
+        self.params.config = config;
+        self
+    }
+    pub fn validate(&self) -> EResult<()> {
+        check_svc_id(&self.id)?;
+        let params = &self.params;
+        if params.command.is_empty() {
+            return Err(Error::invalid_params(format!(
+                "service command not specified: {}",
+                self.id
+            )));
+        }
+        if params.workers == 0 {
+            return Err(Error::invalid_params(format!(
+                "service workers must be positive: {}",
+                self.id
+            )));
+        }
+        let t = &params.timeout;
+        if [params.bus.timeout, t.startup, t.shutdown, t.default]
+            .into_iter()
+            .flatten()
+            .any(|v| v <= 0.0 || !v.is_finite())
+        {
+            return Err(Error::invalid_params(format!(
+                "service timeouts must be positive: {}",
+                self.id
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// ACL deployment. The rules are specific to the authentication service
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AclDeployment {
+    pub id: String,
+    #[serde(flatten)]
+    pub rules: BTreeMap<String, Value>,
+}
+
+/// User deployment
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct UserDeployment {
+    pub login: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password: Option<Secret<String>>,
+    #[serde(default)]
+    pub acls: Vec<String>,
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, Value>,
+}
+
+/// API key deployment
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct KeyDeployment {
+    pub id: String,
+    pub key: Secret<String>,
+    #[serde(default)]
+    pub acls: Vec<String>,
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, Value>,
+}
+
+/// Node section of the manifest
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NodeDeployment {
+    #[serde(default = "default_node")]
+    pub node: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub upload: Vec<UploadFile>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub cvars: BTreeMap<String, Value>,
+    /// Authentication service, used to deploy ACLs, users and keys
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth_svc: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub acls: Vec<AclDeployment>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub users: Vec<UserDeployment>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub keys: Vec<KeyDeployment>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub svcs: Vec<SvcDeployment>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub items: Vec<ItemConfig>,
+}
+
+impl Default for NodeDeployment {
+    fn default() -> Self {
+        Self {
+            node: default_node(),
+            upload: Vec::new(),
+            cvars: BTreeMap::new(),
+            auth_svc: None,
+            acls: Vec::new(),
+            users: Vec::new(),
+            keys: Vec::new(),
+            svcs: Vec::new(),
+            items: Vec::new(),
+        }
+    }
+}
+
+macro_rules! check_duplicates {
+    ($src: expr, $field: ident, $kind: expr) => {{
+        let mut ids = HashSet::new();
+        for v in &$src {
+            if !ids.insert(&v.$field) {
+                return Err(Error::duplicate(format!(
+                    "duplicate {}: {}",
+                    $kind, v.$field
+                )));
+            }
+        }
+    }};
+}
+
+impl NodeDeployment {
+    #[inline]
+    pub fn is_local(&self) -> bool {
+        self.node == LOCAL_NODE
+    }
+    pub fn validate(&self) -> EResult<()> {
+        for upload in &self.upload {
+            upload.validate()?;
+        }
+        for item in &self.items {
+            item.validate()?;
+        }
+        for svc in &self.svcs {
+            svc.validate()?;
+        }
+        check_duplicates!(self.items, oid, "item");
+        check_duplicates!(self.svcs, id, "service");
+        check_duplicates!(self.acls, id, "ACL");
+        check_duplicates!(self.users, login, "user");
+        check_duplicates!(self.keys, id, "key");
+        if self.auth_svc.is_none()
+            && (!self.acls.is_empty() || !self.users.is_empty() || !self.keys.is_empty())
+        {
+            return Err(Error::invalid_params(format!(
+                "auth_svc is required to deploy ACLs, users and keys (node {})",
+                self.node
+            )));
+        }
+        Ok(())
+    }
+    /// Payload for the core "item.deploy" method
+    pub fn items_payload(&self) -> EResult<Option<Value>> {
+        payload("items", &self.items)
+    }
+    /// Payload for the core "svc.deploy" method
+    pub fn svcs_payload(&self) -> EResult<Option<Value>> {
+        payload("svcs", &self.svcs)
+    }
+    /// Payload for the auth service "acl.deploy" method
+    pub fn acls_payload(&self) -> EResult<Option<Value>> {
+        payload("acls", &self.acls)
+    }
+    /// Payload for the auth service "user.deploy" method
+    pub fn users_payload(&self) -> EResult<Option<Value>> {
+        payload("users", &self.users)
+    }
+    /// Payload for the auth service "key.deploy" method
+    pub fn keys_payload(&self) -> EResult<Option<Value>> {
+        payload("keys", &self.keys)
+    }
+}
+
+fn payload<T: Serialize>(field: &str, data: &[T]) -> EResult<Option<Value>> {
+    if data.is_empty() {
+        Ok(None)
+    } else {
+        let mut m = BTreeMap::new();
+        m.insert(Value::String(field.to_owned()), to_value(data)?);
+        Ok(Some(Value::Map(m)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DeploymentManifest, SvcDeployment};
+    use crate::value::Value;
+
+    #[test]
+    fn test_manifest() {
+        let manifest: DeploymentManifest = serde_json::from_str(
+            r##"{"version":4,"content":[{
+                "upload":[{"text":"test","target":"xc/test.txt"}],
+                "auth_svc":"eva.aaa.localauth",
+                "acls":[{"id":"ops","read":{"items":["#"]}}],
+                "users":[{"login":"operator","password":"secret","acls":["ops"]}],
+                "svcs":[{"id":"eva.controller.v1","params":{"command":"svc/eva-controller-virtual"}}],
+                "items":[{"oid":"sensor:tests/s1"}]
+            }]}"##,
+        )
+        .unwrap();
+        manifest.validate().unwrap();
+        let node = &manifest.content[0];
+        assert!(node.is_local());
+        assert!(node.acls[0].rules.contains_key("read"));
+        let password = node.users[0].password.as_ref().unwrap();
+        assert_eq!(password.expose(), "secret");
+        assert!(!format!("{:?}", node.users[0]).contains("secret"));
+        let payload = node.items_payload().unwrap().unwrap();
+        let Value::Map(m) = payload else {
+            panic!("invalid payload")
+        };
+        assert!(m.contains_key(&Value::String("items".to_owned())));
+        assert!(node.keys_payload().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_invalid() {
+        let manifest: DeploymentManifest =
+            serde_json::from_str(r#"{"version":3,"content":[]}"#).unwrap();
+        assert!(manifest.validate().is_err());
+        let manifest: DeploymentManifest =
+            serde_json::from_str(r#"{"content":[{"users":[{"login":"x"}]}]}"#).unwrap();
+        assert!(manifest.validate().is_err());
+        let manifest: DeploymentManifest = serde_json::from_str(
+            r#"{"content":[{"items":[{"oid":"sensor:tests/s1"},{"oid":"sensor:tests/s1"}]}]}"#,
+        )
+        .unwrap();
+        assert!(manifest.validate().is_err());
+    }
+
+    #[test]
+    fn test_svc() {
+        let svc = SvcDeployment::new("eva.controller.v1", "svc/eva-controller-virtual")
+            .bus_path("var/bus.ipc")
+            .workers(2)
+            .default_timeout(5.0);
+        svc.validate().unwrap();
+        let payload = serde_json::to_value(&svc).unwrap();
+        assert_eq!(payload["params"]["bus"]["path"], "var/bus.ipc");
+        assert_eq!(payload["params"]["workers"], 2);
+        assert!(payload["params"].get("config").is_none());
+        let svc: SvcDeployment = serde_json::from_value(payload).unwrap();
+        assert_eq!(svc.params.timeout.default, Some(5.0));
+        assert!(SvcDeployment::new("eva..v1", "x").validate().is_err());
+        assert!(SvcDeployment::new("eva.v1 ", "x").validate().is_err());
+        assert!(SvcDeployment::new("eva.v1", "").validate().is_err());
+        assert!(SvcDeployment::new("eva.v1", "x")
+            .workers(0)
+            .validate()
+            .is_err());
+        assert!(serde_json::from_str::<SvcDeployment>(r#"{"id":"eva.v1","params":{}}"#).is_err());
+    }
+}
@@ -0,0 +1,911 @@
+//! Field-bus driver tools
+//!
+//! [`ConnectionSupervisor`] owns a device connection: connects on demand and in background,
+//! reconnects with an exponential backoff, runs health checks and publishes the connection
+//! status to a status item (e.g. "sensor:plc1/status"). Drivers implement the protocol
+//! specifics only, with [`Connector`].
+//!
+//! [`PollScheduler`] plans device polling with per-point intervals and priorities,
+//! [`CommandPipeline`] executes unit actions with device writes.
+use crate::actions::{
+    ActionEvent, ACTION_COMPLETED, ACTION_FAILED, ACTION_RUNNING, ACTION_TERMINATED, ACTION_TOPIC,
+};
+use crate::events::{RawStateEvent, RAW_STATE_TOPIC};
+use crate::fsm::{Definition, StateMachine};
+use crate::payload::pack;
+use crate::value::Value;
+use crate::{EResult, Error, ErrorKind, OID};
+use busrt::client::AsyncClient;
+use busrt::QoS;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::sync::atomic;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{watch, Mutex, Notify, OwnedSemaphorePermit, Semaphore};
+use uuid::Uuid;
+
+pub const DEFAULT_RECONNECT_MIN: Duration = Duration::from_secs(1);
+pub const DEFAULT_RECONNECT_MAX: Duration = Duration::from_secs(30);
+pub const DEFAULT_HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Device protocol implementation
+pub trait Connector: Send + Sync {
+    type Connection: Send;
+    /// Register/tag address
+    type Address: Sync;
+    type Data: Send;
+    fn connect(&self) -> impl Future<Output = EResult<Self::Connection>> + Send;
+    fn read(
+        &self,
+        conn: &mut Self::Connection,
+        addr: &Self::Address,
+    ) -> impl Future<Output = EResult<Self::Data>> + Send;
+    fn write(
+        &self,
+        conn: &mut Self::Connection,
+        addr: &Self::Address,
+        data: Self::Data,
+    ) -> impl Future<Output = EResult<()>> + Send;
+    /// Checks the connection, called by the supervisor in background
+    fn check(&self, _conn: &mut Self::Connection) -> impl Future<Output = EResult<()>> + Send {
+        async { Ok(()) }
+    }
+    fn disconnect(&self, conn: Self::Connection) -> impl Future<Output = ()> + Send {
+        drop(conn);
+        async {}
+    }
+    /// Returns true if the connection must be re-established after the error. By default, I/O
+    /// errors and timeouts are fatal, other errors (e.g. protocol exceptions) are not
+    fn is_fatal(&self, error: &Error) -> bool {
+        matches!(error.kind(), ErrorKind::IOError | ErrorKind::Timeout)
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConnectionState {
+    Disconnected,
+    Connecting,
+    Connected,
+}
+
+impl ConnectionState {
+    /// Status item value: 1 for connected, 0 for connecting, -1 for disconnected
+    pub fn as_status_value(self) -> i64 {
+        match self {
+            ConnectionState::Disconnected => -1,
+            ConnectionState::Connecting => 0,
+            ConnectionState::Connected => 1,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+enum ConnectionEvent {
+    Connect,
+    Established,
+    Failed,
+}
+
+fn definition() -> Definition<ConnectionState, ConnectionEvent> {
+    Definition::new(ConnectionState::Disconnected)
+        .transition(
+            ConnectionState::Disconnected,
+            ConnectionEvent::Connect,
+            ConnectionState::Connecting,
+        )
+        .transition(
+            ConnectionState::Connecting,
+            ConnectionEvent::Established,
+            ConnectionState::Connected,
+        )
+        .transition(
+            ConnectionState::Connecting,
+            ConnectionEvent::Failed,
+            ConnectionState::Disconnected,
+        )
+        .transition(
+            ConnectionState::Connected,
+            ConnectionEvent::Failed,
+            ConnectionState::Disconnected,
+        )
+}
+
+/// Reconnect delay after the specified number of failed attempts (exponential, capped)
+fn reconnect_delay(failures: u32, min: Duration, max: Duration) -> Duration {
+    if failures == 0 {
+        return Duration::ZERO;
+    }
+    min.saturating_mul(1 << (failures - 1).min(16)).min(max)
+}
+
+/// Device connection supervisor
+///
+/// Read/write calls connect on demand (unless the reconnect delay is active, in which case
+/// [`ErrorKind::NotReady`] is returned), the background task [`ConnectionSupervisor::run()`]
+/// reconnects, checks the connection and publishes its status
+pub struct ConnectionSupervisor<C: Connector> {
+    connector: C,
+    conn: Mutex<Option<C::Connection>>,
+    machine: parking_lot::Mutex<StateMachine<ConnectionState, ConnectionEvent>>,
+    state_tx: watch::Sender<ConnectionState>,
+    failures: atomic::AtomicU32,
+    retry_at: parking_lot::Mutex<Option<Instant>>,
+    timeout: Duration,
+    reconnect_min: Duration,
+    reconnect_max: Duration,
+    health_check_interval: Duration,
+    status_oid: Option<OID>,
+}
+
+impl<C: Connector> ConnectionSupervisor<C> {
+    /// The timeout is applied to all connector calls
+    pub fn new(connector: C, timeout: Duration) -> Self {
+        Self {
+            connector,
+            conn: <_>::default(),
+            machine: parking_lot::Mutex::new(StateMachine::new(Arc::new(definition()))),
+            state_tx: watch::channel(ConnectionState::Disconnected).0,
+            failures: <_>::default(),
+            retry_at: <_>::default(),
+            timeout,
+            reconnect_min: DEFAULT_RECONNECT_MIN,
+            reconnect_max: DEFAULT_RECONNECT_MAX,
+            health_check_interval: DEFAULT_HEALTH_CHECK_INTERVAL,
+            status_oid: None,
+        }
+    }
+    pub fn reconnect_delay(mut self, min: Duration, max: Duration) -> Self {
+        self.reconnect_min = min;
+        self.reconnect_max = max.max(min);
+        self
+    }
+    pub fn health_check_interval(mut self, interval: Duration) -> Self {
+        self.health_check_interval = interval;
+        self
+    }
+    /// Status item to publish the connection state to (see
+    /// [`ConnectionState::as_status_value()`])
+    pub fn status_oid(mut self, oid: OID) -> Self {
+        self.status_oid = Some(oid);
+        self
+    }
+    #[inline]
+    pub fn connector(&self) -> &C {
+        &self.connector
+    }
+    #[inline]
+    pub fn state(&self) -> ConnectionState {
+        self.machine.lock().state()
+    }
+    #[inline]
+    pub fn is_connected(&self) -> bool {
+        self.state() == ConnectionState::Connected
+    }
+    /// Subscribes to connection state changes
+    #[inline]
+    pub fn changes(&self) -> watch::Receiver<ConnectionState> {
+        self.state_tx.subscribe()
+    }
+    /// Number of failures since the last successful connection
+    #[inline]
+    pub fn failures(&self) -> u32 {
+        self.failures.load(atomic::Ordering::SeqCst)
+    }
+    /// # Errors
+    ///
+    /// Will return `Err` if not connected or the connector has failed
+    pub async fn read(&self, addr: &C::Address) -> EResult<C::Data> {
+        let mut conn = self.connected().await?;
+        let Some(c) = conn.as_mut() else {
+            return Err(Error::not_ready("device is not connected"));
+        };
+        let result = with_timeout(self.timeout, self.connector.read(c, addr)).await;
+        if let Err(ref e) = result {
+            self.handle_error(&mut conn, e).await;
+        }
+        result
+    }
+    /// # Errors
+    ///
+    /// Will return `Err` if not connected or the connector has failed
+    pub async fn write(&self, addr: &C::Address, data: C::Data) -> EResult<()> {
+        let mut conn = self.connected().await?;
+        let Some(c) = conn.as_mut() else {
+            return Err(Error::not_ready("device is not connected"));
+        };
+        let result = with_timeout(self.timeout, self.connector.write(c, addr, data)).await;
+        if let Err(ref e) = result {
+            self.handle_error(&mut conn, e).await;
+        }
+        result
+    }
+    /// Closes the connection, the next read/write call or the background task reconnects
+    pub async fn disconnect(&self) {
+        if let Some(c) = self.conn.lock().await.take() {
+            self.connector.disconnect(c).await;
+            self.transition(ConnectionEvent::Failed);
+        }
+    }
+    async fn connected(&self) -> EResult<tokio::sync::MutexGuard<'_, Option<C::Connection>>> {
+        let mut conn = self.conn.lock().await;
+        if conn.is_none() {
+            if self
+                .retry_at
+                .lock()
+                .is_some_and(|retry_at| Instant::now() < retry_at)
+            {
+                return Err(Error::not_ready("device is not connected"));
+            }
+            self.transition(ConnectionEvent::Connect);
+            match with_timeout(self.timeout, self.connector.connect()).await {
+                Ok(c) => {
+                    conn.replace(c);
+                    self.failures.store(0, atomic::Ordering::SeqCst);
+                    self.retry_at.lock().take();
+                    self.transition(ConnectionEvent::Established);
+                }
+                Err(e) => {
+                    self.fail();
+                    return Err(e);
+                }
+            }
+        }
+        Ok(conn)
+    }
+    async fn handle_error(&self, conn: &mut Option<C::Connection>, error: &Error) {
+        // a timed out call has been dropped in the middle of I/O, the connection state is unknown
+        if error.kind() == ErrorKind::Timeout || self.connector.is_fatal(error) {
+            log::warn!("device connection failed: {}", error);
+            if let Some(c) = conn.take() {
+                self.connector.disconnect(c).await;
+            }
+            self.fail();
+        }
+    }
+    fn fail(&self) {
+        let failures = self.failures.fetch_add(1, atomic::Ordering::SeqCst) + 1;
+        self.retry_at.lock().replace(
+            Instant::now() + reconnect_delay(failures, self.reconnect_min, self.reconnect_max),
+        );
+        self.transition(ConnectionEvent::Failed);
+    }
+    fn transition(&self, event: ConnectionEvent) {
+        let mut machine = self.machine.lock();
+        match machine.handle(event, &mut ()) {
+            Ok(state) => {
+                self.state_tx.send_replace(state);
+            }
+            Err(e) => log::debug!("device connection: {}", e),
+        }
+    }
+    /// Connects the device or checks the connection
+    async fn check(&self) {
+        let mut conn = match self.connected().await {
+            Ok(conn) => conn,
+            Err(e) => {
+                if e.kind() != ErrorKind::NotReady {
+                    log::warn!("device connection failed: {}", e);
+                }
+                return;
+            }
+        };
+        if let Some(c) = conn.as_mut() {
+            if let Err(e) = with_timeout(self.timeout, self.connector.check(c)).await {
+                self.handle_error(&mut conn, &e).await;
+            }
+        }
+    }
+    /// Keeps the device connected and publishes the connection status (if the status item is
+    /// set). Must be spawned as a background task
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` on bus errors
+    pub async fn run<B>(&self, client: Arc<Mutex<B>>) -> EResult<()>
+    where
+        B: AsyncClient + ?Sized,
+    {
+        let mut rx = self.state_tx.subscribe();
+        let mut published = None;
+        loop {
+            self.check().await;
+            let state = *rx.borrow_and_update();
+            if published != Some(state) {
+                if let Some(ref oid) = self.status_oid {
+                    let value = Value::I64(state.as_status_value());
+                    let event = RawStateEvent::new(1, &value);
+                    client
+                        .lock()
+                        .await
+                        .publish(
+                            &format!("{}{}", RAW_STATE_TOPIC, oid.as_path()),
+                            pack(&event)?.into(),
+                            QoS::No,
+                        )
+                        .await?;
+                }
+                published = Some(state);
+            }
+            let delay = if state == ConnectionState::Connected {
+                self.health_check_interval
+            } else {
+                self.retry_at.lock().map_or(self.reconnect_min, |retry_at| {
+                    retry_at.saturating_duration_since(Instant::now())
+                })
+            };
+            tokio::select! {
+                () = tokio::time::sleep(delay) => {}
+                _ = rx.changed() => {}
+            }
+        }
+    }
+}
+
+async fn with_timeout<T>(timeout: Duration, f: impl Future<Output = EResult<T>>) -> EResult<T> {
+    tokio::time::timeout(timeout, f).await?
+}
+
+#[derive(Debug, Clone)]
+struct PollPoint {
+    interval: Duration,
+    priority: u8,
+    next: Instant,
+}
+
+impl PollPoint {
+    /// Priority, raised by one for each missed poll period, so low-priority points are not
+    /// starved by high-priority ones
+    fn effective_priority(&self, now: Instant) -> u32 {
+        let overdue = now.saturating_duration_since(self.next);
+        let missed = overdue.as_nanos() / self.interval.as_nanos().max(1);
+        u32::from(self.priority).saturating_add(u32::try_from(missed).unwrap_or(u32::MAX))
+    }
+}
+
+/// Batch of points to poll, the concurrency slot is released when the job is dropped
+#[derive(Debug)]
+pub struct PollJob {
+    pub priority: u8,
+    pub oids: Vec<OID>,
+    _permit: Option<OwnedSemaphorePermit>,
+}
+
+/// Polling scheduler
+///
+/// Points are registered with individual poll intervals and priorities (higher are polled
+/// first). Due points are emitted in batches of the same priority, the most overdue first, the
+/// number of jobs in progress is limited by the device concurrency
+pub struct PollScheduler {
+    points: parking_lot::Mutex<BTreeMap<OID, PollPoint>>,
+    batch_size: usize,
+    semaphore: Arc<Semaphore>,
+    changed: Notify,
+}
+
+impl Default for PollScheduler {
+    fn default() -> Self {
+        Self::new(1, 1)
+    }
+}
+
+impl PollScheduler {
+    /// # Panics
+    ///
+    /// Will panic if the batch size or the concurrency is zero
+    pub fn new(batch_size: usize, concurrency: usize) -> Self {
+        assert!(batch_size > 0, "batch size must be positive");
+        assert!(concurrency > 0, "concurrency must be positive");
+        Self {
+            points: <_>::default(),
+            batch_size,
+            semaphore: Arc::new(Semaphore::new(concurrency)),
+            changed: Notify::new(),
+        }
+    }
+    /// Registers a point or updates its interval/priority, new points are due immediately
+    pub fn register(&self, oid: OID, interval: Duration, priority: u8) {
+        let mut points = self.points.lock();
+        if let Some(point) = points.get_mut(&oid) {
+            point.interval = interval;
+            point.priority = priority;
+        } else {
+            points.insert(
+                oid,
+                PollPoint {
+                    interval,
+                    priority,
+                    next: Instant::now(),
+                },
+            );
+        }
+        drop(points);
+        self.changed.notify_one();
+    }
+    pub fn unregister(&self, oid: &OID) {
+        self.points.lock().remove(oid);
+    }
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.points.lock().len()
+    }
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.points.lock().is_empty()
+    }
+    /// Takes a batch of due points and reschedules them. If no points are due, returns the time
+    /// when the next point is due (None if there are no points)
+    fn take_due(&self, now: Instant) -> Result<(u8, Vec<OID>), Option<Instant>> {
+        let mut points = self.points.lock();
+        let mut due: Vec<(u32, Instant, &OID)> = Vec::new();
+        let mut next_due: Option<Instant> = None;
+        for (oid, point) in points.iter() {
+            if point.next <= now {
+                due.push((point.effective_priority(now), point.next, oid));
+            } else {
+                next_due = Some(next_due.map_or(point.next, |t| t.min(point.next)));
+            }
+        }
+        if due.is_empty() {
+            return Err(next_due);
+        }
+        due.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+        let priority = points[due[0].2].priority;
+        let oids: Vec<OID> = due
+            .into_iter()
+            .map(|(_, _, oid)| oid)
+            .filter(|oid| points[*oid].priority == priority)
+            .take(self.batch_size)
+            .cloned()
+            .collect();
+        for oid in &oids {
+            if let Some(point) = points.get_mut(oid) {
+                point.next += point.interval;
+                if point.next <= now {
+                    // missed periods are skipped
+                    point.next = now + point.interval;
+                }
+            }
+        }
+        Ok((priority, oids))
+    }
+    /// Waits for a free concurrency slot and the next batch of due points
+    ///
+    /// # Errors
+    ///
+    /// Should not return errors in normal conditions
+    pub async fn next(&self) -> EResult<PollJob> {
+        let permit = self
+            .semaphore
+            .clone()
+            .acquire_owned()
+            .await
+            .map_err(Error::core)?;
+        loop {
+            let changed = self.changed.notified();
+            match self.take_due(Instant::now()) {
+                Ok((priority, oids)) => {
+                    return Ok(PollJob {
+                        priority,
+                        oids,
+                        _permit: Some(permit),
+                    });
+                }
+                Err(Some(next_due)) => {
+                    tokio::select! {
+                        () = tokio::time::sleep_until(next_due.into()) => {}
+                        () = changed => {}
+                    }
+                }
+                Err(None) => changed.await,
+            }
+        }
+    }
+}
+
+/// Device write command, created from a unit action
+pub struct Command<C: Connector> {
+    pub uuid: Uuid,
+    pub oid: OID,
+    pub addr: C::Address,
+    pub data: C::Data,
+    /// Action timeout, including retries and verification
+    pub timeout: Duration,
+}
+
+/// Executes unit actions: writes data to the device, retries failed writes, optionally verifies
+/// the readback and reports action status transitions (running, then completed, failed or
+/// terminated) to [`ACTION_TOPIC`]
+///
+/// An action is completed when the data is written and, if verification is enabled, read back
+/// from the device. Failed writes are retried (if set), a readback mismatch is not: the device
+/// is read until the value matches or the action times out
+///
+/// Timeouts and termination are cooperative: device I/O in progress is never interrupted (it is
+/// limited by the supervisor timeout), the action is stopped before the next device call
+pub struct CommandPipeline<C: Connector> {
+    supervisor: Arc<ConnectionSupervisor<C>>,
+    retries: u32,
+    retry_delay: Duration,
+    verify_interval: Option<Duration>,
+    running: parking_lot::Mutex<HashMap<Uuid, Arc<Notify>>>,
+}
+
+impl<C> CommandPipeline<C>
+where
+    C: Connector,
+    C::Data: Clone + PartialEq,
+{
+    pub fn new(supervisor: Arc<ConnectionSupervisor<C>>) -> Self {
+        Self {
+            supervisor,
+            retries: 0,
+            retry_delay: Duration::ZERO,
+            verify_interval: None,
+            running: <_>::default(),
+        }
+    }
+    pub fn retries(mut self, retries: u32, delay: Duration) -> Self {
+        self.retries = retries;
+        self.retry_delay = delay;
+        self
+    }
+    /// Enables the readback verification with the specified read interval
+    pub fn verify(mut self, interval: Duration) -> Self {
+        self.verify_interval = Some(interval);
+        self
+    }
+    #[inline]
+    pub fn supervisor(&self) -> &Arc<ConnectionSupervisor<C>> {
+        &self.supervisor
+    }
+    #[inline]
+    pub fn is_running(&self, uuid: &Uuid) -> bool {
+        self.running.lock().contains_key(uuid)
+    }
+    /// Terminates a running action, returns false if not found
+    pub fn terminate(&self, uuid: &Uuid) -> bool {
+        if let Some(terminated) = self.running.lock().get(uuid) {
+            terminated.notify_one();
+            true
+        } else {
+            false
+        }
+    }
+    /// Executes the command and returns the final action event
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the action is already running or on bus errors. Action failures are
+    /// reported in the action event
+    pub async fn execute<B>(&self, command: Command<C>, client: &Mutex<B>) -> EResult<ActionEvent>
+    where
+        B: AsyncClient + ?Sized,
+    {
+        let terminated = Arc::new(Notify::new());
+        {
+            let mut running = self.running.lock();
+            if running.contains_key(&command.uuid) {
+                return Err(Error::duplicate(format!(
+                    "action {} is already running",
+                    command.uuid
+                )));
+            }
+            running.insert(command.uuid, terminated.clone());
+        }
+        let result = self.run_action(&command, &terminated, client).await;
+        self.running.lock().remove(&command.uuid);
+        result
+    }
+    async fn run_action<B>(
+        &self,
+        command: &Command<C>,
+        terminated: &Notify,
+        client: &Mutex<B>,
+    ) -> EResult<ActionEvent>
+    where
+        B: AsyncClient + ?Sized,
+    {
+        let topic = format!("{}{}", ACTION_TOPIC, command.oid.as_path());
+        let running = action_event(command.uuid, ACTION_RUNNING, None);
+        publish_action(client, &topic, &running).await?;
+        let deadline = tokio::time::Instant::now() + command.timeout;
+        let event = match self.write(command, terminated, deadline).await {
+            Ok(Outcome::Completed) => action_event(command.uuid, ACTION_COMPLETED, None),
+            Ok(Outcome::Terminated) => action_event(command.uuid, ACTION_TERMINATED, None),
+            Err(e) => {
+                log::warn!("action {} for {} failed: {}", command.uuid, command.oid, e);
+                action_event(command.uuid, ACTION_FAILED, Some(e))
+            }
+        };
+        publish_action(client, &topic, &event).await?;
+        Ok(event)
+    }
+    async fn write(
+        &self,
+        command: &Command<C>,
+        terminated: &Notify,
+        deadline: tokio::time::Instant,
+    ) -> EResult<Outcome> {
+        let mut attempt = 0;
+        loop {
+            if let Some(outcome) = pause(Duration::ZERO, terminated, deadline).await? {
+                return Ok(outcome);
+            }
+            match self
+                .supervisor
+                .write(&command.addr, command.data.clone())
+                .await
+            {
+                Ok(()) => break,
+                Err(e) if attempt < self.retries => {
+                    attempt += 1;
+                    log::warn!(
+                        "action {} write failed ({}), retrying ({}/{})",
+                        command.uuid,
+                        e,
+                        attempt,
+                        self.retries
+                    );
+                    if let Some(outcome) = pause(self.retry_delay, terminated, deadline).await? {
+                        return Ok(outcome);
+                    }
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        if let Some(interval) = self.verify_interval {
+            loop {
+                if let Some(outcome) = pause(Duration::ZERO, terminated, deadline).await? {
+                    return Ok(outcome);
+                }
+                if self.supervisor.read(&command.addr).await? == command.data {
+                    break;
+                }
+                if let Some(outcome) = pause(interval, terminated, deadline).await? {
+                    return Ok(outcome);
+                }
+            }
+        }
+        Ok(Outcome::Completed)
+    }
+}
+
+enum Outcome {
+    Completed,
+    Terminated,
+}
+
+/// Waits between device calls. Returns the outcome if the action has been terminated or an
+/// error if the deadline has been reached
+async fn pause(
+    duration: Duration,
+    terminated: &Notify,
+    deadline: tokio::time::Instant,
+) -> EResult<Option<Outcome>> {
+    if tokio::time::Instant::now() >= deadline {
+        return Err(Error::timeout());
+    }
+    let wait = async {
+        if !duration.is_zero() {
+            tokio::time::sleep(duration).await;
+        }
+    };
+    tokio::select! {
+        biased;
+        () = terminated.notified() => Ok(Some(Outcome::Terminated)),
+        () = tokio::time::sleep_until(deadline) => Err(Error::timeout()),
+        () = wait => Ok(None),
+    }
+}
+
+fn action_event(uuid: Uuid, status: u8, err: Option<Error>) -> ActionEvent {
+    let exitcode = match status {
+        ACTION_COMPLETED => Some(0),
+        ACTION_FAILED => Some(1),
+        ACTION_TERMINATED => Some(-15),
+        _ => None,
+    };
+    ActionEvent {
+        uuid,
+        status,
+        out: None,
+        err: err.map(|e| Value::String(e.to_string())),
+        exitcode,
+    }
+}
+
+async fn publish_action<B>(client: &Mutex<B>, topic: &str, event: &ActionEvent) -> EResult<()>
+where
+    B: AsyncClient + ?Sized,
+{
+    client
+        .lock()
+        .await
+        .publish(topic, pack(event)?.into(), QoS::No)
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{reconnect_delay, ConnectionState, ConnectionSupervisor, Connector, PollScheduler};
+    use crate::OID;
+    use crate::{EResult, Error, ErrorKind};
+    use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+    use std::time::{Duration, Instant};
+
+    #[derive(Default)]
+    struct Device {
+        online: AtomicBool,
+        connects: AtomicU32,
+    }
+
+    impl Connector for Device {
+        type Connection = u32;
+        type Address = u16;
+        type Data = u16;
+        async fn connect(&self) -> EResult<u32> {
+            if self.online.load(Ordering::SeqCst) {
+                Ok(self.connects.fetch_add(1, Ordering::SeqCst) + 1)
+            } else {
+                Err(Error::io("connection refused"))
+            }
+        }
+        async fn read(&self, conn: &mut u32, addr: &u16) -> EResult<u16> {
+            match addr {
+                0 => Err(Error::io("broken pipe")),
+                1..=99 => Ok(addr * 10 + u16::try_from(*conn).unwrap()),
+                _ => Err(Error::invalid_params("illegal address")),
+            }
+        }
+        async fn write(&self, _conn: &mut u32, _addr: &u16, _data: u16) -> EResult<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_connection_supervisor() {
+        assert_eq!(
+            reconnect_delay(3, Duration::from_secs(1), Duration::from_secs(3)),
+            Duration::from_secs(3)
+        );
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async move {
+                let sup = ConnectionSupervisor::new(Device::default(), Duration::from_secs(1))
+                    .reconnect_delay(Duration::from_millis(50), Duration::from_millis(100));
+                assert_eq!(sup.read(&1).await.unwrap_err().kind(), ErrorKind::IOError);
+                assert_eq!(sup.state(), ConnectionState::Disconnected);
+                sup.connector().online.store(true, Ordering::SeqCst);
+                // reconnect delay
+                assert_eq!(sup.read(&1).await.unwrap_err().kind(), ErrorKind::NotReady);
+                tokio::time::sleep(Duration::from_millis(60)).await;
+                assert_eq!(sup.read(&1).await.unwrap(), 11);
+                assert!(sup.is_connected());
+                assert_eq!(sup.failures(), 0);
+                // protocol errors keep the connection
+                assert!(sup.read(&100).await.is_err());
+                assert!(sup.is_connected());
+                // fatal errors break it
+                assert!(sup.read(&0).await.is_err());
+                assert_eq!(sup.state(), ConnectionState::Disconnected);
+                assert_eq!(sup.failures(), 1);
+                tokio::time::sleep(Duration::from_millis(60)).await;
+                sup.write(&1, 5).await.unwrap();
+                assert_eq!(sup.read(&2).await.unwrap(), 22);
+                assert_eq!(*sup.changes().borrow(), ConnectionState::Connected);
+            });
+    }
+    #[test]
+    fn test_poll_scheduler() {
+        let sched = PollScheduler::new(2, 1);
+        let oid = |s: &str| -> OID { s.parse().unwrap() };
+        let sec = Duration::from_secs(1);
+        sched.register(oid("sensor:fast1"), sec, 10);
+        sched.register(oid("sensor:fast2"), sec, 10);
+        sched.register(oid("sensor:fast3"), sec, 10);
+        sched.register(oid("sensor:slow"), sec * 10, 0);
+        let t0 = Instant::now();
+        let (priority, oids) = sched.take_due(t0).unwrap();
+        assert_eq!(priority, 10);
+        assert_eq!(oids, [oid("sensor:fast1"), oid("sensor:fast2")]);
+        let (_, oids) = sched.take_due(t0).unwrap();
+        assert_eq!(oids, [oid("sensor:fast3")]);
+        let (priority, oids) = sched.take_due(t0).unwrap();
+        assert_eq!(priority, 0);
+        assert_eq!(oids, [oid("sensor:slow")]);
+        // nothing is due, the next point is due in a second
+        let next_due = sched.take_due(t0).unwrap_err().unwrap();
+        assert!(next_due > t0 && next_due <= t0 + sec + sec / 10);
+        // the device handles one job per second only, which is not enough for the fast points,
+        // the slow point is still polled as it gets overdue
+        let mut slow_polled = None;
+        for step in 1..=300 {
+            let (_, oids) = sched.take_due(t0 + sec * step).unwrap();
+            if oids.contains(&oid("sensor:slow")) {
+                slow_polled = Some(step);
+                break;
+            }
+        }
+        assert!(slow_polled.is_some());
+        let t = t0 + sec * 1000;
+        while sched.take_due(t).is_ok() {}
+        sched.unregister(&oid("sensor:fast3"));
+        assert_eq!(sched.len(), 3);
+        assert!(sched.take_due(t).is_err());
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async move {
+                let sched = PollScheduler::new(10, 1);
+                sched.register(oid("sensor:s1"), sec, 0);
+                let job = sched.next().await.unwrap();
+                assert_eq!(job.oids, [oid("sensor:s1")]);
+                // the only concurrency slot is taken
+                assert!(
+                    tokio::time::timeout(Duration::from_millis(10), sched.next())
+                        .await
+                        .is_err()
+                );
+                drop(job);
+                sched.register(oid("sensor:s2"), sec, 0);
+                let job = tokio::time::timeout(Duration::from_millis(100), sched.next())
+                    .await
+                    .unwrap()
+                    .unwrap();
+                assert_eq!(job.oids, [oid("sensor:s2")]);
+            });
+    }
+    #[cfg(feature = "testkit")]
+    #[test]
+    fn test_command_pipeline() {
+        use super::{Command, CommandPipeline};
+        use crate::actions::{ActionEvent, ACTION_COMPLETED, ACTION_FAILED, ACTION_RUNNING};
+        use crate::testkit::MockClient;
+        use std::sync::Arc;
+        use tokio::sync::Mutex;
+
+        let client = MockClient::new("test");
+        let sent = client.sent();
+        let client = Mutex::new(client);
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async move {
+                let device = Device::default();
+                device.online.store(true, Ordering::SeqCst);
+                let sup = Arc::new(ConnectionSupervisor::new(device, Duration::from_secs(1)));
+                let pipeline = CommandPipeline::new(sup).verify(Duration::from_millis(10));
+                let command = |addr: u16, data: u16| Command::<Device> {
+                    uuid: uuid::Uuid::new_v4(),
+                    oid: "unit:tests/u1".parse().unwrap(),
+                    addr,
+                    data,
+                    timeout: Duration::from_millis(100),
+                };
+                // address 1 reads back 11 after the connection
+                let event = pipeline.execute(command(1, 11), &client).await.unwrap();
+                assert_eq!(event.status, ACTION_COMPLETED);
+                assert_eq!(event.exitcode, Some(0));
+                // readback mismatch, the action times out
+                let event = pipeline.execute(command(1, 12), &client).await.unwrap();
+                assert_eq!(event.status, ACTION_FAILED);
+                assert!(event.err.is_some());
+                let sent = sent.lock();
+                assert_eq!(sent.len(), 4);
+                assert_eq!(sent[0].target, "ACT/unit/tests/u1");
+                assert_eq!(
+                    sent[0].unpack::<ActionEvent>().unwrap().status,
+                    ACTION_RUNNING
+                );
+                assert!(!pipeline.is_running(&event.uuid));
+            });
+    }
+}
@@ -0,0 +1,303 @@
+//! Energy metering: counter accumulation, interval energy and tariff period splitting
+use crate::events::OnNegativeDelta;
+use crate::time::{Time, TimeWindow};
+use crate::{EResult, Error};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+const NS_IN_HOUR: f64 = 3_600_000_000_000.0;
+
+/// Energy consumed over a time interval
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Interval {
+    pub start: Time,
+    pub end: Time,
+    pub energy: f64,
+}
+
+/// Accumulates consumption from meter counter readings
+///
+/// Negative deltas are handled according to [`OnNegativeDelta`]: "skip" ignores the interval
+/// (e.g. the meter has been replaced), "reset" treats the reading as consumption since the
+/// counter restarted from zero, "process" accepts negative deltas (bidirectional meters) and
+/// "overflow" handles the counter rollover from ceil to floor
+#[derive(Debug, Clone)]
+pub struct Accumulator {
+    on_negative: OnNegativeDelta,
+    last: Option<(Time, f64)>,
+    total: f64,
+}
+
+impl Accumulator {
+    pub fn new(on_negative: OnNegativeDelta) -> Self {
+        Self {
+            on_negative,
+            last: None,
+            total: 0.0,
+        }
+    }
+    /// Restores the accumulated total (e.g. after a service restart)
+    #[inline]
+    pub fn initial_total(mut self, total: f64) -> Self {
+        self.total = total;
+        self
+    }
+    /// Processes a counter reading and returns the consumption since the previous one. None is
+    /// returned for the first reading and skipped intervals
+    pub fn push(&mut self, t: Time, reading: f64) -> EResult<Option<Interval>> {
+        if !reading.is_finite() {
+            return Err(Error::invalid_data(format!(
+                "invalid meter reading: {}",
+                reading
+            )));
+        }
+        let Some((last_t, last)) = self.last else {
+            self.last = Some((t, reading));
+            return Ok(None);
+        };
+        if t.timestamp_ns() <= last_t.timestamp_ns() {
+            return Err(Error::invalid_data(
+                "meter readings must be ordered by time",
+            ));
+        }
+        self.last = Some((t, reading));
+        let delta = reading - last;
+        let energy = if delta >= 0.0 {
+            delta
+        } else {
+            match self.on_negative {
+                OnNegativeDelta::Skip => return Ok(None),
+                OnNegativeDelta::Reset => reading,
+                OnNegativeDelta::Process => delta,
+                OnNegativeDelta::Overflow { floor, ceil } => (ceil - last) + (reading - floor),
+            }
+        };
+        self.total += energy;
+        Ok(Some(Interval {
+            start: last_t,
+            end: t,
+            energy,
+        }))
+    }
+    #[inline]
+    pub fn total(&self) -> f64 {
+        self.total
+    }
+    /// The last reading
+    #[inline]
+    pub fn last(&self) -> Option<(Time, f64)> {
+        self.last
+    }
+}
+
+/// Integrates power samples (trapezoidal rule). The result is in power units multiplied by
+/// hours (e.g. kW -> kWh). The samples MUST be sorted by time
+#[allow(clippy::cast_precision_loss)]
+pub fn integrate_power(samples: &[(Time, f64)]) -> f64 {
+    samples
+        .windows(2)
+        .map(|w| {
+            let (t1, p1) = w[0];
+            let (t2, p2) = w[1];
+            let dt = t2.timestamp_ns().saturating_sub(t1.timestamp_ns()) as f64 / NS_IN_HOUR;
+            (p1 + p2) * dt / 2.0
+        })
+        .sum()
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Tariff {
+    pub name: String,
+    pub windows: Vec<TimeWindow>,
+}
+
+/// Energy attributed to a tariff period
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TariffShare {
+    pub tariff: String,
+    pub start: Time,
+    pub end: Time,
+    pub energy: f64,
+}
+
+/// Tariff schedule: the first tariff with a window containing the time applies, otherwise the
+/// default one
+///
+/// Interval energy is split proportionally to time (uniform consumption is assumed). As time
+/// windows are half-open, the energy at a boundary is attributed to the period which starts
+/// there
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TariffSchedule {
+    default: String,
+    #[serde(default)]
+    tariffs: Vec<Tariff>,
+}
+
+impl TariffSchedule {
+    pub fn new(default: &str) -> Self {
+        Self {
+            default: default.to_owned(),
+            tariffs: Vec::new(),
+        }
+    }
+    pub fn tariff(mut self, name: &str, windows: Vec<TimeWindow>) -> Self {
+        self.tariffs.push(Tariff {
+            name: name.to_owned(),
+            windows,
+        });
+        self
+    }
+    /// The tariff, applied at the given time
+    pub fn tariff_at(&self, t: Time) -> &str {
+        self.tariffs
+            .iter()
+            .find(|tariff| tariff.windows.iter().any(|w| w.contains(t)))
+            .map_or(self.default.as_str(), |tariff| tariff.name.as_str())
+    }
+    fn next_boundary(&self, t: Time) -> Option<Time> {
+        self.tariffs
+            .iter()
+            .flat_map(|tariff| tariff.windows.iter())
+            .map(|w| w.next_boundary(t))
+            .min_by_key(Time::timestamp_ns)
+    }
+    /// Splits the interval energy by tariff periods
+    #[allow(clippy::cast_precision_loss)]
+    pub fn split(&self, interval: &Interval) -> Vec<TariffShare> {
+        let start_ns = interval.start.timestamp_ns();
+        let end_ns = interval.end.timestamp_ns();
+        if end_ns <= start_ns {
+            return vec![TariffShare {
+                tariff: self.tariff_at(interval.start).to_owned(),
+                start: interval.start,
+                end: interval.end,
+                energy: interval.energy,
+            }];
+        }
+        let duration = (end_ns - start_ns) as f64;
+        let mut result: Vec<TariffShare> = Vec::new();
+        let mut cur = interval.start;
+        while cur.timestamp_ns() < end_ns {
+            let next = self
+                .next_boundary(cur)
+                .filter(|b| b.timestamp_ns() < end_ns)
+                .unwrap_or(interval.end);
+            let tariff = self.tariff_at(cur);
+            let energy =
+                interval.energy * (next.timestamp_ns() - cur.timestamp_ns()) as f64 / duration;
+            match result.last_mut() {
+                Some(share) if share.tariff == tariff => {
+                    share.end = next;
+                    share.energy += energy;
+                }
+                _ => result.push(TariffShare {
+                    tariff: tariff.to_owned(),
+                    start: cur,
+                    end: next,
+                    energy,
+                }),
+            }
+            cur = next;
+        }
+        result
+    }
+    /// Total energy by tariffs, e.g. for billing exports
+    pub fn totals<'a, I>(&self, intervals: I) -> BTreeMap<String, f64>
+    where
+        I: IntoIterator<Item = &'a Interval>,
+    {
+        let mut totals = BTreeMap::new();
+        for interval in intervals {
+            for share in self.split(interval) {
+                *totals.entry(share.tariff).or_default() += share.energy;
+            }
+        }
+        totals
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::float_cmp)]
+mod tests {
+    use super::{integrate_power, Accumulator, Interval, TariffSchedule};
+    use crate::events::OnNegativeDelta;
+    use crate::time::{Time, TimeWindow};
+
+    // Monday, 2024-01-01 00:00:00 UTC
+    const MONDAY: f64 = 1_704_067_200.0;
+
+    fn at(hours: f64) -> Time {
+        Time::from_timestamp(MONDAY + hours * 3600.0)
+    }
+
+    #[test]
+    fn test_energy() {
+        let mut acc = Accumulator::new(OnNegativeDelta::Overflow {
+            floor: 0.0,
+            ceil: 10_000.0,
+        })
+        .initial_total(100.0);
+        assert!(acc.push(at(0.0), 9_990.0).unwrap().is_none());
+        let interval = acc.push(at(1.0), 5.0).unwrap().unwrap();
+        assert_eq!(interval.energy, 15.0);
+        assert_eq!(interval.start, at(0.0));
+        assert_eq!(acc.total(), 115.0);
+        assert!(acc.push(at(0.5), 10.0).is_err());
+        let mut acc = Accumulator::new(OnNegativeDelta::Skip);
+        acc.push(at(0.0), 50.0).unwrap();
+        assert!(acc.push(at(1.0), 3.0).unwrap().is_none());
+        assert_eq!(acc.push(at(2.0), 4.0).unwrap().unwrap().energy, 1.0);
+        let mut acc = Accumulator::new(OnNegativeDelta::Reset);
+        acc.push(at(0.0), 50.0).unwrap();
+        assert_eq!(acc.push(at(1.0), 3.0).unwrap().unwrap().energy, 3.0);
+        assert_eq!(
+            integrate_power(&[(at(0.0), 2.0), (at(0.5), 4.0), (at(1.5), 4.0)]),
+            5.5
+        );
+        let schedule = TariffSchedule::new("night").tariff(
+            "day",
+            vec![TimeWindow::new("07:00", "23:00")
+                .unwrap()
+                .days(&[1, 2, 3, 4, 5])
+                .unwrap()
+                .utc_offset(0)],
+        );
+        assert_eq!(schedule.tariff_at(at(7.0)), "day");
+        assert_eq!(schedule.tariff_at(at(23.0)), "night");
+        // Saturday
+        assert_eq!(schedule.tariff_at(at(5.0 * 24.0 + 12.0)), "night");
+        let shares = schedule.split(&Interval {
+            start: at(6.0),
+            end: at(8.0),
+            energy: 10.0,
+        });
+        assert_eq!(shares.len(), 2);
+        assert_eq!(shares[0].tariff, "night");
+        assert_eq!(shares[0].end, at(7.0));
+        assert_eq!(shares[0].energy, 5.0);
+        assert_eq!(shares[1].tariff, "day");
+        let totals = schedule.totals(&[
+            Interval {
+                start: at(22.0),
+                end: at(31.0),
+                energy: 9.0,
+            },
+            Interval {
+                start: at(31.0),
+                end: at(32.0),
+                energy: 2.0,
+            },
+        ]);
+        assert_eq!(totals["day"], 3.0);
+        assert_eq!(totals["night"], 8.0);
+        let s: TariffSchedule = serde_json::from_str(
+            r#"{"default":"night","tariffs":[{"name":"day","windows":[
+                {"start":"07:00","end":"23:00","days":[1,2,3,4,5],"utc_offset":0}]}]}"#,
+        )
+        .unwrap();
+        assert_eq!(s, schedule);
+        assert!(TimeWindow::new("07:60", "23:00").is_err());
+    }
+}
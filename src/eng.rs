@@ -0,0 +1,398 @@
+//! Typed engineering values for building automation: percents, colors and enumerations
+//!
+//! The wrappers are convertible from [`Value`] with validation and back, so drivers and logic
+//! share the same clamping and parsing rules
+use crate::value::Value;
+use crate::{EResult, Error};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::str::FromStr;
+
+/// A percentage, clamped to 0..=100
+#[derive(Debug, Copy, Clone, PartialEq, PartialOrd, Default, Serialize, Deserialize)]
+#[serde(try_from = "Value", into = "f64")]
+pub struct Percent(f64);
+
+impl Percent {
+    pub const MIN: Percent = Percent(0.0);
+    pub const MAX: Percent = Percent(100.0);
+
+    /// Creates a percentage, out-of-range values are clamped, NaN is not allowed
+    pub fn new(value: f64) -> EResult<Self> {
+        if value.is_nan() {
+            return Err(Error::invalid_data("percent value is NaN"));
+        }
+        Ok(Self(value.clamp(0.0, 100.0)))
+    }
+    /// Creates a percentage from a 0..=1 fraction
+    #[inline]
+    pub fn from_fraction(fraction: f64) -> EResult<Self> {
+        Self::new(fraction * 100.0)
+    }
+    /// Creates a percentage from a raw device level in 0..=max (e.g. 0..=254 for DALI)
+    #[allow(clippy::cast_precision_loss)]
+    pub fn from_scaled(raw: u32, max: u32) -> EResult<Self> {
+        if max == 0 {
+            return Err(Error::invalid_params("scale max must be positive"));
+        }
+        Self::new(f64::from(raw) * 100.0 / f64::from(max))
+    }
+    #[inline]
+    pub fn value(self) -> f64 {
+        self.0
+    }
+    #[inline]
+    pub fn fraction(self) -> f64 {
+        self.0 / 100.0
+    }
+    /// Converts the percentage to a raw device level in 0..=max (rounded)
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn scaled(self, max: u32) -> u32 {
+        (self.fraction() * f64::from(max)).round() as u32
+    }
+}
+
+impl TryFrom<Value> for Percent {
+    type Error = Error;
+
+    /// Accepts numbers and numeric strings, optionally suffixed with "%"
+    fn try_from(value: Value) -> EResult<Self> {
+        let value = match value {
+            Value::String(s) => Value::String(s.trim().trim_end_matches('%').trim_end().to_owned()),
+            v => v,
+        };
+        Percent::new(value.try_into()?)
+    }
+}
+
+impl From<Percent> for f64 {
+    #[inline]
+    fn from(p: Percent) -> f64 {
+        p.0
+    }
+}
+
+impl From<Percent> for Value {
+    #[inline]
+    fn from(p: Percent) -> Value {
+        Value::F64(p.0)
+    }
+}
+
+impl fmt::Display for Percent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}%", self.0)
+    }
+}
+
+/// RGB color with an optional white channel (RGBW)
+///
+/// Serialized as a hex string ("#rrggbb" or "#rrggbbww"). Values are also accepted as short
+/// hex strings ("#rgb"), `[r, g, b(, w)]` sequences, `{"r", "g", "b"(, "w")}` maps and
+/// 0xRRGGBB integers
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Default, Serialize, Deserialize)]
+#[serde(try_from = "Value", into = "String")]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub w: Option<u8>,
+}
+
+impl Color {
+    #[inline]
+    pub fn rgb(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b, w: None }
+    }
+    #[inline]
+    pub fn rgbw(r: u8, g: u8, b: u8, w: u8) -> Self {
+        Self {
+            r,
+            g,
+            b,
+            w: Some(w),
+        }
+    }
+    /// 0xRRGGBB (the white channel is ignored)
+    pub fn to_u32(self) -> u32 {
+        u32::from(self.r) << 16 | u32::from(self.g) << 8 | u32::from(self.b)
+    }
+    pub fn from_u32(value: u32) -> EResult<Self> {
+        if value > 0xff_ffff {
+            return Err(Error::invalid_data(format!("invalid RGB color: {}", value)));
+        }
+        let [_, r, g, b] = value.to_be_bytes();
+        Ok(Self::rgb(r, g, b))
+    }
+    /// Brightness of the color channels as a percentage (the maximum channel)
+    pub fn brightness(self) -> Percent {
+        let max = self
+            .r
+            .max(self.g)
+            .max(self.b)
+            .max(self.w.unwrap_or_default());
+        Percent(f64::from(max) * 100.0 / 255.0)
+    }
+}
+
+impl FromStr for Color {
+    type Err = Error;
+
+    fn from_str(s: &str) -> EResult<Self> {
+        let hex = s.trim();
+        let hex = hex.strip_prefix('#').unwrap_or(hex);
+        let err = || Error::invalid_data(format!("invalid color: {}", s));
+        if !hex.is_ascii() {
+            return Err(err());
+        }
+        let channel = |i: usize, len: usize| -> EResult<u8> {
+            let c = u8::from_str_radix(&hex[i * len..(i + 1) * len], 16).map_err(|_| err())?;
+            Ok(if len == 1 { c * 17 } else { c })
+        };
+        match hex.len() {
+            3 => Ok(Self::rgb(channel(0, 1)?, channel(1, 1)?, channel(2, 1)?)),
+            6 => Ok(Self::rgb(channel(0, 2)?, channel(1, 2)?, channel(2, 2)?)),
+            8 => Ok(Self::rgbw(
+                channel(0, 2)?,
+                channel(1, 2)?,
+                channel(2, 2)?,
+                channel(3, 2)?,
+            )),
+            _ => Err(err()),
+        }
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}", self.r, self.g, self.b)?;
+        if let Some(w) = self.w {
+            write!(f, "{:02x}", w)?;
+        }
+        Ok(())
+    }
+}
+
+fn color_channel(value: Value) -> EResult<u8> {
+    let c: i64 = value.try_into()?;
+    u8::try_from(c).map_err(|_| Error::invalid_data(format!("invalid color channel: {}", c)))
+}
+
+impl TryFrom<Value> for Color {
+    type Error = Error;
+
+    fn try_from(value: Value) -> EResult<Self> {
+        match value {
+            Value::String(s) => s.parse(),
+            Value::Seq(s) if s.len() == 3 || s.len() == 4 => {
+                let mut c = s.into_iter().map(color_channel);
+                let mut color =
+                    Self::rgb(c.next().unwrap()?, c.next().unwrap()?, c.next().unwrap()?);
+                color.w = c.next().transpose()?;
+                Ok(color)
+            }
+            Value::Map(mut map) => {
+                let mut channel = |name: &str| {
+                    map.remove(&Value::String(name.to_owned()))
+                        .map(color_channel)
+                        .transpose()
+                };
+                let (Some(r), Some(g), Some(b)) = (channel("r")?, channel("g")?, channel("b")?)
+                else {
+                    return Err(Error::invalid_data("color channels missing"));
+                };
+                let w = channel("w")?;
+                Ok(Self { r, g, b, w })
+            }
+            Value::Option(Some(v)) | Value::Newtype(v) => (*v).try_into(),
+            v if v.is_numeric_type() => {
+                let v: i64 = v.try_into()?;
+                Self::from_u32(
+                    u32::try_from(v)
+                        .map_err(|_| Error::invalid_data(format!("invalid RGB color: {}", v)))?,
+                )
+            }
+            _ => Err(Error::invalid_data("unsupported color value")),
+        }
+    }
+}
+
+impl From<Color> for String {
+    #[inline]
+    fn from(c: Color) -> String {
+        c.to_string()
+    }
+}
+
+impl From<Color> for Value {
+    #[inline]
+    fn from(c: Color) -> Value {
+        Value::String(c.to_string())
+    }
+}
+
+/// An enumeration value: a numeric code with its name
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct EnumValue {
+    pub code: i64,
+    pub name: String,
+}
+
+impl From<EnumValue> for Value {
+    /// Enumerations are stored in item states as codes
+    #[inline]
+    fn from(v: EnumValue) -> Value {
+        Value::I64(v.code)
+    }
+}
+
+/// A code-name mapping table (e.g. HVAC modes: 0 - off, 1 - heat, 2 - cool). Names are matched
+/// case-insensitively. Serialized as a code-name map
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "BTreeMap<i64, String>", into = "BTreeMap<i64, String>")]
+pub struct EnumTable {
+    names: BTreeMap<i64, String>,
+    codes: HashMap<String, i64>,
+}
+
+impl EnumTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Adds a variant (builder), panics if the code or the name is already used
+    pub fn variant(mut self, code: i64, name: &str) -> Self {
+        self.insert(code, name).unwrap();
+        self
+    }
+    /// Adds a variant
+    pub fn insert(&mut self, code: i64, name: &str) -> EResult<()> {
+        let key = name.to_lowercase();
+        if self.names.contains_key(&code) || self.codes.contains_key(&key) {
+            return Err(Error::duplicate(format!(
+                "enum variant already defined: {} ({})",
+                name, code
+            )));
+        }
+        self.names.insert(code, name.to_owned());
+        self.codes.insert(key, code);
+        Ok(())
+    }
+    pub fn name(&self, code: i64) -> Option<&str> {
+        self.names.get(&code).map(String::as_str)
+    }
+    pub fn code(&self, name: &str) -> Option<i64> {
+        self.codes.get(&name.to_lowercase()).copied()
+    }
+    /// Resolves a value: integer codes, numeric strings or variant names
+    pub fn resolve(&self, value: &Value) -> EResult<EnumValue> {
+        let code = match value {
+            Value::String(s) => match s.parse::<i64>() {
+                Ok(code) => code,
+                Err(_) => self
+                    .code(s)
+                    .ok_or_else(|| Error::invalid_data(format!("invalid enum name: {}", s)))?,
+            },
+            Value::F32(_) | Value::F64(_) => {
+                let f: f64 = value.try_into()?;
+                if f.fract() != 0.0 {
+                    return Err(Error::invalid_data(format!("invalid enum code: {}", f)));
+                }
+                value.try_into()?
+            }
+            Value::Option(Some(v)) | Value::Newtype(v) => return self.resolve(v),
+            v => v.try_into()?,
+        };
+        let name = self
+            .name(code)
+            .ok_or_else(|| Error::invalid_data(format!("invalid enum code: {}", code)))?;
+        Ok(EnumValue {
+            code,
+            name: name.to_owned(),
+        })
+    }
+    pub fn iter(&self) -> impl Iterator<Item = (i64, &str)> {
+        self.names.iter().map(|(code, name)| (*code, name.as_str()))
+    }
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+}
+
+impl TryFrom<BTreeMap<i64, String>> for EnumTable {
+    type Error = Error;
+
+    fn try_from(variants: BTreeMap<i64, String>) -> EResult<Self> {
+        let mut table = Self::new();
+        for (code, name) in variants {
+            table.insert(code, &name)?;
+        }
+        Ok(table)
+    }
+}
+
+impl From<EnumTable> for BTreeMap<i64, String> {
+    #[inline]
+    fn from(table: EnumTable) -> Self {
+        table.names
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Color, EnumTable, Percent};
+    use crate::value::Value;
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn test_eng_values() {
+        assert_eq!(Percent::new(120.0).unwrap(), Percent::MAX);
+        assert!(Percent::new(f64::NAN).is_err());
+        let p = Percent::try_from(Value::String("45.5 %".to_owned())).unwrap();
+        assert_eq!(p.value(), 45.5);
+        assert_eq!(Percent::try_from(Value::I64(-3)).unwrap(), Percent::MIN);
+        assert_eq!(Percent::from_scaled(127, 254).unwrap().value(), 50.0);
+        assert_eq!(Percent::new(50.0).unwrap().scaled(255), 128);
+        let c: Color = "#FF8000".parse().unwrap();
+        assert_eq!(c, Color::rgb(255, 128, 0));
+        assert_eq!(c.to_u32(), 0xff_8000);
+        assert_eq!("#f80".parse::<Color>().unwrap(), Color::rgb(255, 136, 0));
+        assert_eq!(
+            serde_json::to_string(&Color::rgbw(1, 2, 3, 4)).unwrap(),
+            r##""#01020304""##
+        );
+        let from_seq: Color = serde_json::from_str("[255, 128, 0]").unwrap();
+        let from_map: Color = serde_json::from_str(r#"{"r":255,"g":128,"b":0}"#).unwrap();
+        let from_int = Color::try_from(Value::U32(0xff_8000)).unwrap();
+        assert_eq!(from_seq, c);
+        assert_eq!(from_map, c);
+        assert_eq!(from_int, c);
+        assert!(serde_json::from_str::<Color>("[256, 0, 0]").is_err());
+        assert!("#12345".parse::<Color>().is_err());
+        let modes = EnumTable::new()
+            .variant(0, "off")
+            .variant(1, "heat")
+            .variant(2, "cool");
+        assert_eq!(
+            modes
+                .resolve(&Value::String("HEAT".to_owned()))
+                .unwrap()
+                .code,
+            1
+        );
+        assert_eq!(modes.resolve(&Value::U8(2)).unwrap().name, "cool");
+        assert_eq!(
+            modes.resolve(&Value::String("0".to_owned())).unwrap().name,
+            "off"
+        );
+        assert!(modes.resolve(&Value::U8(3)).is_err());
+        assert!(modes.resolve(&Value::F64(1.5)).is_err());
+        let json = serde_json::to_string(&modes).unwrap();
+        assert_eq!(json, r#"{"0":"off","1":"heat","2":"cool"}"#);
+        assert_eq!(serde_json::from_str::<EnumTable>(&json).unwrap(), modes);
+        assert!(serde_json::from_str::<EnumTable>(r#"{"0":"off","1":"OFF"}"#).is_err());
+    }
+}
@@ -0,0 +1,472 @@
+//! Stateful raw event processors, used by drivers to filter chatty sources before raw events are
+//! sent to the bus
+use super::{Force, RawStateEventOwned};
+use crate::value::{Value, ValueOptionOwned};
+use crate::{ItemStatus, ITEM_STATUS_ERROR, OID};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Processing decision
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Decision {
+    /// Emit the event (may be modified by the processor)
+    Emit(RawStateEventOwned),
+    /// Drop the event
+    Suppress,
+}
+
+impl Decision {
+    #[inline]
+    pub fn is_emit(&self) -> bool {
+        matches!(self, Decision::Emit(_))
+    }
+    #[inline]
+    pub fn into_event(self) -> Option<RawStateEventOwned> {
+        match self {
+            Decision::Emit(ev) => Some(ev),
+            Decision::Suppress => None,
+        }
+    }
+}
+
+/// Event filter
+///
+/// Filters are used in [`FilterChain`]: an event may be passed by a filter and suppressed by
+/// the next one, so the state which depends on emitted events (e.g. the last emitted value) must
+/// be updated in [`EventFilter::commit()`] only
+pub trait EventFilter: Send {
+    /// Processes an event. "t" is the event time: either the event own time or the current one
+    fn process(&mut self, oid: &OID, event: RawStateEventOwned, t: f64) -> Decision;
+    /// Called for every event, emitted by the chain (the final version of the event)
+    fn commit(&mut self, _oid: &OID, _event: &RawStateEventOwned, _t: f64) {}
+    /// Called periodically, may generate events (e.g. held values or stale items)
+    fn tick(&mut self, _t: f64) -> Vec<(OID, RawStateEventOwned)> {
+        Vec::new()
+    }
+}
+
+#[inline]
+fn now() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs_f64())
+        .unwrap_or_default()
+}
+
+#[inline]
+fn numeric_value(event: &RawStateEventOwned) -> Option<f64> {
+    event.value.as_ref().and_then(|v| f64::try_from(v).ok())
+}
+
+#[derive(Debug, Clone)]
+struct LastState {
+    status: ItemStatus,
+    value: ValueOptionOwned,
+    t: f64,
+}
+
+impl LastState {
+    fn new(event: &RawStateEventOwned, t: f64) -> Self {
+        Self {
+            status: event.status,
+            value: event.value.clone(),
+            t,
+        }
+    }
+}
+
+/// Suppresses events if the numeric value has changed less than the band. Status changes,
+/// non-numeric value changes and forced events are always emitted. If max_interval is set,
+/// events are emitted at least once per the interval (seconds), the last suppressed value is
+/// emitted on tick
+#[derive(Debug, Clone, Default)]
+pub struct DeadBand {
+    band: f64,
+    max_interval: Option<f64>,
+    last: HashMap<OID, LastState>,
+    held: HashMap<OID, RawStateEventOwned>,
+}
+
+impl DeadBand {
+    pub fn new(band: f64, max_interval: Option<f64>) -> Self {
+        Self {
+            band,
+            max_interval,
+            last: <_>::default(),
+            held: <_>::default(),
+        }
+    }
+}
+
+impl EventFilter for DeadBand {
+    fn process(&mut self, oid: &OID, event: RawStateEventOwned, t: f64) -> Decision {
+        if event.force == Force::None {
+            if let Some(last) = self.last.get(oid) {
+                let expired = self.max_interval.is_some_and(|i| t - last.t >= i);
+                if !expired && last.status == event.status {
+                    let within_band = match (
+                        last.value.as_ref().and_then(|v| f64::try_from(v).ok()),
+                        numeric_value(&event),
+                    ) {
+                        (Some(prev), Some(current)) => (current - prev).abs() < self.band,
+                        _ => last.value == event.value,
+                    };
+                    if within_band {
+                        if self.max_interval.is_some() {
+                            self.held.insert(oid.clone(), event);
+                        }
+                        return Decision::Suppress;
+                    }
+                }
+            }
+        }
+        Decision::Emit(event)
+    }
+    fn commit(&mut self, oid: &OID, event: &RawStateEventOwned, t: f64) {
+        self.held.remove(oid);
+        self.last.insert(oid.clone(), LastState::new(event, t));
+    }
+    fn tick(&mut self, t: f64) -> Vec<(OID, RawStateEventOwned)> {
+        let Some(max_interval) = self.max_interval else {
+            return Vec::new();
+        };
+        let last = &self.last;
+        let expired: Vec<OID> = self
+            .held
+            .keys()
+            .filter(|oid| !last.get(*oid).is_some_and(|l| t - l.t < max_interval))
+            .cloned()
+            .collect();
+        expired
+            .into_iter()
+            .filter_map(|oid| self.held.remove(&oid).map(|ev| (oid, ev)))
+            .collect()
+    }
+}
+
+/// Emits events for each OID not more frequently than the given interval (seconds). Status
+/// changes and forced events are always emitted. The last suppressed event is emitted on tick
+/// when the interval has passed
+#[derive(Debug, Clone, Default)]
+pub struct RateLimitPerOid {
+    interval: f64,
+    last: HashMap<OID, (ItemStatus, f64)>,
+    held: HashMap<OID, RawStateEventOwned>,
+}
+
+impl RateLimitPerOid {
+    pub fn new(interval: f64) -> Self {
+        Self {
+            interval,
+            last: <_>::default(),
+            held: <_>::default(),
+        }
+    }
+}
+
+impl EventFilter for RateLimitPerOid {
+    fn process(&mut self, oid: &OID, event: RawStateEventOwned, t: f64) -> Decision {
+        if event.force == Force::None {
+            if let Some((status, last_t)) = self.last.get(oid) {
+                if *status == event.status && t - last_t < self.interval {
+                    self.held.insert(oid.clone(), event);
+                    return Decision::Suppress;
+                }
+            }
+        }
+        Decision::Emit(event)
+    }
+    fn commit(&mut self, oid: &OID, event: &RawStateEventOwned, t: f64) {
+        self.held.remove(oid);
+        self.last.insert(oid.clone(), (event.status, t));
+    }
+    fn tick(&mut self, t: f64) -> Vec<(OID, RawStateEventOwned)> {
+        let last = &self.last;
+        let expired: Vec<OID> = self
+            .held
+            .keys()
+            .filter(|oid| !last.get(*oid).is_some_and(|(_, l)| t - l < self.interval))
+            .cloned()
+            .collect();
+        expired
+            .into_iter()
+            .filter_map(|oid| self.held.remove(&oid).map(|ev| (oid, ev)))
+            .collect()
+    }
+}
+
+/// Rate-of-change limiter action
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RocAction {
+    /// Drop the event
+    #[default]
+    Suppress,
+    /// Limit the value change to the max rate
+    Clamp,
+    /// Emit the event with the error status
+    Error,
+}
+
+/// Limits the numeric value rate of change (units per second)
+#[derive(Debug, Clone, Default)]
+pub struct RocLimiter {
+    max_rate: f64,
+    action: RocAction,
+    last: HashMap<OID, (f64, f64)>,
+}
+
+impl RocLimiter {
+    pub fn new(max_rate: f64, action: RocAction) -> Self {
+        Self {
+            max_rate,
+            action,
+            last: <_>::default(),
+        }
+    }
+}
+
+impl EventFilter for RocLimiter {
+    fn process(&mut self, oid: &OID, mut event: RawStateEventOwned, t: f64) -> Decision {
+        let Some(value) = numeric_value(&event) else {
+            return Decision::Emit(event);
+        };
+        if let Some((prev, prev_t)) = self.last.get(oid).copied() {
+            let dt = t - prev_t;
+            let max_delta = self.max_rate * dt.max(0.0);
+            let delta = value - prev;
+            if delta.abs() > max_delta {
+                match self.action {
+                    RocAction::Suppress => return Decision::Suppress,
+                    RocAction::Clamp => {
+                        let clamped = prev + max_delta.copysign(delta);
+                        event.value = ValueOptionOwned::Value(Value::F64(clamped));
+                    }
+                    RocAction::Error => {
+                        event.status = ITEM_STATUS_ERROR;
+                        event.value = ValueOptionOwned::No;
+                    }
+                }
+                return Decision::Emit(event);
+            }
+        }
+        Decision::Emit(event)
+    }
+    fn commit(&mut self, oid: &OID, event: &RawStateEventOwned, t: f64) {
+        if let Some(value) = numeric_value(event) {
+            self.last.insert(oid.clone(), (value, t));
+        }
+    }
+}
+
+/// Passes all events, generates error status events on tick for OIDs which have not been
+/// updated for longer than the timeout (seconds). Each stale OID is reported once until updated
+#[derive(Debug, Clone, Default)]
+pub struct StaleDetector {
+    timeout: f64,
+    last: HashMap<OID, (f64, bool)>,
+}
+
+impl StaleDetector {
+    pub fn new(timeout: f64) -> Self {
+        Self {
+            timeout,
+            last: <_>::default(),
+        }
+    }
+}
+
+impl EventFilter for StaleDetector {
+    fn process(&mut self, oid: &OID, event: RawStateEventOwned, t: f64) -> Decision {
+        self.last.insert(oid.clone(), (t, false));
+        Decision::Emit(event)
+    }
+    fn tick(&mut self, t: f64) -> Vec<(OID, RawStateEventOwned)> {
+        let mut result = Vec::new();
+        for (oid, (last_t, reported)) in &mut self.last {
+            if !*reported && t - *last_t > self.timeout {
+                *reported = true;
+                result.push((oid.clone(), RawStateEventOwned::new0(ITEM_STATUS_ERROR)));
+            }
+        }
+        result
+    }
+}
+
+/// Serializable filter configuration
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum FilterConfig {
+    DeadBand {
+        band: f64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        max_interval: Option<f64>,
+    },
+    RateLimit {
+        interval: f64,
+    },
+    Roc {
+        max_rate: f64,
+        #[serde(default)]
+        action: RocAction,
+    },
+    Stale {
+        timeout: f64,
+    },
+}
+
+impl FilterConfig {
+    pub fn build(&self) -> Box<dyn EventFilter> {
+        match *self {
+            FilterConfig::DeadBand { band, max_interval } => {
+                Box::new(DeadBand::new(band, max_interval))
+            }
+            FilterConfig::RateLimit { interval } => Box::new(RateLimitPerOid::new(interval)),
+            FilterConfig::Roc { max_rate, action } => Box::new(RocLimiter::new(max_rate, action)),
+            FilterConfig::Stale { timeout } => Box::new(StaleDetector::new(timeout)),
+        }
+    }
+}
+
+/// Event filter chain, an event is passed through filters until suppressed. Events, emitted by
+/// the chain, are committed to all filters
+#[derive(Default)]
+pub struct FilterChain {
+    filters: Vec<Box<dyn EventFilter>>,
+}
+
+impl FilterChain {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn from_config(config: &[FilterConfig]) -> Self {
+        Self {
+            filters: config.iter().map(FilterConfig::build).collect(),
+        }
+    }
+    pub fn push(mut self, filter: impl EventFilter + 'static) -> Self {
+        self.filters.push(Box::new(filter));
+        self
+    }
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.filters.is_empty()
+    }
+    /// Processes an event, the event time is taken from the event or set to the current time
+    pub fn process(&mut self, oid: &OID, event: RawStateEventOwned) -> Decision {
+        let t = event.t.unwrap_or_else(now);
+        self.process_at(oid, event, t)
+    }
+    pub fn process_at(&mut self, oid: &OID, event: RawStateEventOwned, t: f64) -> Decision {
+        let decision = pass(&mut self.filters, oid, event, t);
+        if let Decision::Emit(ref ev) = decision {
+            self.commit(oid, ev, t);
+        }
+        decision
+    }
+    fn commit(&mut self, oid: &OID, event: &RawStateEventOwned, t: f64) {
+        for filter in &mut self.filters {
+            filter.commit(oid, event, t);
+        }
+    }
+    /// Should be called periodically, returns held values and events, generated by filters
+    pub fn tick(&mut self) -> Vec<(OID, RawStateEventOwned)> {
+        self.tick_at(now())
+    }
+    /// Events, generated by a filter, are passed through the next ones
+    pub fn tick_at(&mut self, t: f64) -> Vec<(OID, RawStateEventOwned)> {
+        let mut result = Vec::new();
+        for i in 0..self.filters.len() {
+            for (oid, event) in self.filters[i].tick(t) {
+                if let Decision::Emit(ev) = pass(&mut self.filters[i + 1..], &oid, event, t) {
+                    self.commit(&oid, &ev, t);
+                    result.push((oid, ev));
+                }
+            }
+        }
+        result
+    }
+}
+
+fn pass(
+    filters: &mut [Box<dyn EventFilter>],
+    oid: &OID,
+    mut event: RawStateEventOwned,
+    t: f64,
+) -> Decision {
+    for filter in filters {
+        match filter.process(oid, event, t) {
+            Decision::Emit(ev) => event = ev,
+            Decision::Suppress => return Decision::Suppress,
+        }
+    }
+    Decision::Emit(event)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Decision, FilterChain, FilterConfig, RocAction};
+    use crate::events::RawStateEventOwned;
+    use crate::value::{Value, ValueOptionOwned};
+    use crate::{ITEM_STATUS_ERROR, OID};
+
+    fn ev(value: f64) -> RawStateEventOwned {
+        RawStateEventOwned::new(1, Value::F64(value))
+    }
+
+    #[test]
+    fn test_dead_band_chain() {
+        let config: Vec<FilterConfig> = serde_json::from_str(
+            r#"[{"type":"dead_band","band":0.5},{"type":"rate_limit","interval":10}]"#,
+        )
+        .unwrap();
+        let mut chain = FilterChain::from_config(&config);
+        let oid: OID = "sensor:tests/s1".parse().unwrap();
+        assert!(chain.process_at(&oid, ev(1.0), 0.0).is_emit());
+        // passed by the dead band, suppressed by the rate limiter
+        assert_eq!(chain.process_at(&oid, ev(2.0), 1.0), Decision::Suppress);
+        // compared with the last emitted value
+        assert_eq!(chain.process_at(&oid, ev(1.2), 2.0), Decision::Suppress);
+        assert!(chain.tick_at(5.0).is_empty());
+        // the held value is emitted when the rate limit interval has passed
+        let held = chain.tick_at(11.0);
+        assert_eq!(held.len(), 1);
+        assert_eq!(held[0].1.value, ValueOptionOwned::Value(Value::F64(2.0)));
+        assert!(chain.tick_at(30.0).is_empty());
+        assert_eq!(chain.process_at(&oid, ev(2.2), 31.0), Decision::Suppress);
+        assert!(chain.process_at(&oid, ev(3.0), 32.0).is_emit());
+        assert!(chain
+            .process_at(&oid, RawStateEventOwned::new0(ITEM_STATUS_ERROR), 33.0)
+            .is_emit());
+        let mut chain = FilterChain::new().push(super::DeadBand::new(0.5, Some(5.0)));
+        assert!(chain.process_at(&oid, ev(1.0), 0.0).is_emit());
+        assert_eq!(chain.process_at(&oid, ev(1.1), 1.0), Decision::Suppress);
+        assert!(chain.tick_at(3.0).is_empty());
+        let held = chain.tick_at(5.0);
+        assert_eq!(held[0].1.value, ValueOptionOwned::Value(Value::F64(1.1)));
+        assert!(chain.tick_at(6.0).is_empty());
+    }
+
+    #[test]
+    fn test_roc_stale() {
+        let mut chain = FilterChain::from_config(&[
+            FilterConfig::Roc {
+                max_rate: 1.0,
+                action: RocAction::Clamp,
+            },
+            FilterConfig::Stale { timeout: 5.0 },
+        ]);
+        let oid: OID = "sensor:tests/s1".parse().unwrap();
+        chain.process_at(&oid, ev(0.0), 0.0);
+        let event = chain.process_at(&oid, ev(10.0), 2.0).into_event().unwrap();
+        assert_eq!(event.value, ValueOptionOwned::Value(Value::F64(2.0)));
+        assert!(chain.tick_at(5.0).is_empty());
+        let stale = chain.tick_at(10.0);
+        assert_eq!(stale.len(), 1);
+        assert_eq!(stale[0].1.status, ITEM_STATUS_ERROR);
+        assert!(chain.tick_at(20.0).is_empty());
+    }
+}
@@ -1,12 +1,22 @@
-use crate::acl::OIDMaskList;
+use crate::acl::{Acl, OIDMaskList};
 use crate::value::{Value, ValueOption, ValueOptionOwned};
 use crate::{EResult, Error};
 use crate::{ItemStatus, IEID, OID};
+use serde::ser::{SerializeMap, SerializeSeq};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::hash::{Hash, Hasher};
 use std::str::FromStr;
 use std::time::Duration;
 
+pub mod filters;
+pub mod reorder;
+#[cfg(feature = "services")]
+pub mod svc_status;
+pub mod time_sync;
+
+#[cfg(feature = "services")]
+pub use svc_status::svc_status_watcher;
+
 pub const RAW_STATE_TOPIC: &str = "RAW/";
 pub const RAW_STATE_BULK_TOPIC: &str = "RAW";
 pub const LOCAL_STATE_TOPIC: &str = "ST/LOC/";
@@ -23,6 +33,9 @@ pub const SERVICE_STATUS_TOPIC: &str = "SVC/ST";
 pub const AAA_ACL_TOPIC: &str = "AAA/ACL/";
 pub const AAA_KEY_TOPIC: &str = "AAA/KEY/";
 pub const AAA_USER_TOPIC: &str = "AAA/USER/";
+pub const AUDIT_TOPIC: &str = "AUDIT/";
+pub const NOTIFICATION_TOPIC: &str = "NOTIFY/";
+pub const TIME_SYNC_STATUS_TOPIC: &str = "SYS/TIMESYNC/";
 
 #[derive(Debug, Copy, Clone)]
 #[repr(i8)]
@@ -751,6 +764,163 @@ impl From<FullItemStateAndInfoOwned> for ReplicationInventoryItem {
     }
 }
 
+/// Meta fields, which must not be exposed to clients. Specified as dot-separated paths, e.g.
+/// "internal" or "plc.address"
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MetaDenyList {
+    paths: Vec<Vec<String>>,
+}
+
+impl MetaDenyList {
+    pub fn new<S: AsRef<str>>(paths: &[S]) -> Self {
+        Self {
+            paths: paths
+                .iter()
+                .map(|p| p.as_ref().split('.').map(ToOwned::to_owned).collect())
+                .collect(),
+        }
+    }
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.paths.is_empty()
+    }
+}
+
+impl Serialize for MetaDenyList {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_seq(self.paths.iter().map(|p| p.join(".")))
+    }
+}
+
+impl<'de> Deserialize<'de> for MetaDenyList {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let paths: Vec<String> = Deserialize::deserialize(deserializer)?;
+        Ok(Self::new(&paths))
+    }
+}
+
+/// Serializes a value, skipping denied map fields, without cloning
+struct MaskedValue<'a> {
+    value: &'a Value,
+    deny: Vec<&'a [String]>,
+}
+
+impl MaskedValue<'_> {
+    fn is_denied(&self, key: &Value) -> bool {
+        if let Value::String(k) = key {
+            self.deny.iter().any(|p| p.len() == 1 && &p[0] == k)
+        } else {
+            false
+        }
+    }
+}
+
+impl Serialize for MaskedValue<'_> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let Value::Map(m) = self.value else {
+            return self.value.serialize(serializer);
+        };
+        if self.deny.is_empty() {
+            return self.value.serialize(serializer);
+        }
+        let mut map =
+            serializer.serialize_map(Some(m.keys().filter(|k| !self.is_denied(k)).count()))?;
+        for (k, v) in m {
+            if self.is_denied(k) {
+                continue;
+            }
+            let deny: Vec<&[String]> = if let Value::String(key) = k {
+                self.deny
+                    .iter()
+                    .filter(|p| p.len() > 1 && &p[0] == key)
+                    .map(|p| &p[1..])
+                    .collect()
+            } else {
+                Vec::new()
+            };
+            map.serialize_entry(k, &MaskedValue { value: v, deny })?;
+        }
+        map.end()
+    }
+}
+
+#[derive(Serialize)]
+struct FilteredItemState<'a> {
+    #[serde(flatten)]
+    si: &'a ItemStateAndInfo<'a>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    meta: Option<MaskedValue<'a>>,
+    enabled: bool,
+}
+
+/// Serializes only items readable by the ACL, denied meta fields are skipped. The item data is
+/// not cloned
+pub struct AclFilteredItems<'a, I> {
+    items: I,
+    acl: &'a Acl,
+    meta_deny: Option<&'a MetaDenyList>,
+}
+
+impl<'a, 'b: 'a, I> AclFilteredItems<'a, I>
+where
+    I: IntoIterator<Item = &'a FullItemStateAndInfo<'b>> + Clone,
+{
+    #[inline]
+    pub fn new(items: I, acl: &'a Acl) -> Self {
+        Self {
+            items,
+            acl,
+            meta_deny: None,
+        }
+    }
+    #[inline]
+    pub fn meta_deny(mut self, meta_deny: &'a MetaDenyList) -> Self {
+        self.meta_deny = Some(meta_deny);
+        self
+    }
+}
+
+impl<'a, 'b: 'a, I> Serialize for AclFilteredItems<'a, I>
+where
+    I: IntoIterator<Item = &'a FullItemStateAndInfo<'b>> + Clone,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let items: Vec<&FullItemStateAndInfo> = self
+            .items
+            .clone()
+            .into_iter()
+            .filter(|i| self.acl.check_item_read(i.si.oid))
+            .collect();
+        let mut seq = serializer.serialize_seq(Some(items.len()))?;
+        for item in items {
+            seq.serialize_element(&FilteredItemState {
+                si: &item.si,
+                meta: item.meta.map(|value| MaskedValue {
+                    value,
+                    deny: self
+                        .meta_deny
+                        .map(|d| d.paths.iter().map(Vec::as_slice).collect())
+                        .unwrap_or_default(),
+                }),
+                enabled: item.enabled,
+            })?;
+        }
+        seq.end()
+    }
+}
+
 pub struct EventBuffer<T> {
     data: parking_lot::Mutex<Vec<T>>,
     size: usize,
@@ -813,3 +983,59 @@ pub struct ReplicationNodeInventoryItem {
     #[serde(flatten)]
     pub item: ReplicationInventoryItem,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{AclFilteredItems, FullItemStateAndInfo, ItemStateAndInfo, MetaDenyList};
+    use crate::acl::Acl;
+    use crate::value::{Value, ValueOptionOwned};
+    use crate::OID;
+
+    #[test]
+    fn test_acl_filtered_items() {
+        let oids: Vec<OID> = ["sensor:tests/s1", "unit:tests/u1", "sensor:secret/s2"]
+            .iter()
+            .map(|s| s.parse().unwrap())
+            .collect();
+        let meta: Value = serde_json::from_str(
+            r#"{"location":"room1","internal":1,"plc":{"address":"10.0.0.1","slot":1}}"#,
+        )
+        .unwrap();
+        let items: Vec<FullItemStateAndInfo> = oids
+            .iter()
+            .map(|oid| FullItemStateAndInfo {
+                si: ItemStateAndInfo {
+                    oid,
+                    status: Some(1),
+                    value: ValueOptionOwned::Value(Value::U8(1)),
+                    act: None,
+                    ieid: None,
+                    t: None,
+                    node: "test",
+                    connected: true,
+                },
+                meta: Some(&meta),
+                enabled: true,
+            })
+            .collect();
+        let acl: Acl = serde_json::from_str(
+            r#"{"id":"test","read":{"items":["sensor:#"]},
+                "deny_read":{"items":["sensor:secret/#"]},"from":["test"]}"#,
+        )
+        .unwrap();
+        let meta_deny: MetaDenyList =
+            serde_json::from_str(r#"["internal","plc.address"]"#).unwrap();
+        let result =
+            serde_json::to_value(AclFilteredItems::new(&items, &acl).meta_deny(&meta_deny))
+                .unwrap();
+        let result = result.as_array().unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0]["oid"], "sensor:tests/s1");
+        assert_eq!(result[0]["status"], 1);
+        assert_eq!(result[0]["enabled"], true);
+        assert_eq!(
+            result[0]["meta"],
+            serde_json::json!({"location":"room1","plc":{"slot":1}})
+        );
+    }
+}
@@ -0,0 +1,231 @@
+//! Replication event reordering
+use super::ReplicationStateEvent;
+use crate::{IEID, OID};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_HOLD: Duration = Duration::from_millis(100);
+pub const DEFAULT_MAX_PENDING_PER_OID: usize = 100;
+pub const DEFAULT_MAX_PENDING: usize = 100_000;
+
+/// Accepts possibly out-of-order replication events and emits them per OID in IEID order
+///
+/// Events are held for the hold window to let delayed ones arrive. Events with IEIDs less or
+/// equal to the last emitted one for the OID are discarded as stale. Events with force_accept
+/// are emitted immediately. When memory bounds are exceeded, pending events are released
+/// before the hold window ends.
+pub struct IeidReorderBuffer {
+    hold: Duration,
+    max_pending_per_oid: usize,
+    max_pending: usize,
+    last: HashMap<OID, IEID>,
+    pending: HashMap<OID, BTreeMap<IEID, (Instant, ReplicationStateEvent)>>,
+    pending_count: usize,
+    ready: VecDeque<(OID, ReplicationStateEvent)>,
+    dropped: u64,
+}
+
+impl Default for IeidReorderBuffer {
+    fn default() -> Self {
+        Self::new(DEFAULT_HOLD)
+    }
+}
+
+impl IeidReorderBuffer {
+    pub fn new(hold: Duration) -> Self {
+        Self {
+            hold,
+            max_pending_per_oid: DEFAULT_MAX_PENDING_PER_OID,
+            max_pending: DEFAULT_MAX_PENDING,
+            last: <_>::default(),
+            pending: <_>::default(),
+            pending_count: 0,
+            ready: <_>::default(),
+            dropped: 0,
+        }
+    }
+    pub fn max_pending_per_oid(mut self, max: usize) -> Self {
+        self.max_pending_per_oid = max;
+        self
+    }
+    pub fn max_pending(mut self, max: usize) -> Self {
+        self.max_pending = max;
+        self
+    }
+    /// Number of events, discarded as stale or duplicate
+    #[inline]
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+    #[inline]
+    pub fn pending(&self) -> usize {
+        self.pending_count
+    }
+    /// The last emitted IEID for the OID
+    #[inline]
+    pub fn last_ieid(&self, oid: &OID) -> Option<IEID> {
+        self.last.get(oid).copied()
+    }
+    #[inline]
+    pub fn push(&mut self, oid: OID, event: ReplicationStateEvent) -> bool {
+        self.push_at(oid, event, Instant::now())
+    }
+    /// Pushes an event, returns false if the event has been discarded
+    pub fn push_at(&mut self, oid: OID, event: ReplicationStateEvent, now: Instant) -> bool {
+        if event.force_accept {
+            let ieid = event.ieid;
+            self.release(&oid, Some(ieid));
+            if let Some(pending) = self.pending.get_mut(&oid) {
+                let newer = pending.split_off(&ieid);
+                self.pending_count -= pending.len();
+                self.dropped += pending.len() as u64;
+                *pending = newer;
+            }
+            self.last.insert(oid.clone(), ieid);
+            self.ready.push_back((oid, event));
+            return true;
+        }
+        if self.last.get(&oid).is_some_and(|l| event.ieid <= *l) {
+            self.dropped += 1;
+            return false;
+        }
+        let pending = self.pending.entry(oid.clone()).or_default();
+        if pending.contains_key(&event.ieid) {
+            self.dropped += 1;
+            return false;
+        }
+        pending.insert(event.ieid, (now, event));
+        self.pending_count += 1;
+        if pending.len() > self.max_pending_per_oid {
+            let first = pending.keys().next().copied();
+            self.release(&oid, first);
+        } else if self.pending_count > self.max_pending {
+            self.release(&oid, None);
+        }
+        true
+    }
+    /// Moves pending events of the OID up to (including) the IEID to the ready queue. If no IEID
+    /// specified, all pending events of the OID are moved
+    fn release(&mut self, oid: &OID, up_to: Option<IEID>) {
+        let Some(pending) = self.pending.get_mut(oid) else {
+            return;
+        };
+        let released = if let Some(ieid) = up_to {
+            let rest = pending.split_off(&ieid);
+            let mut released = std::mem::replace(pending, rest);
+            if let Some(ev) = pending.remove(&ieid) {
+                released.insert(ieid, ev);
+            }
+            released
+        } else {
+            std::mem::take(pending)
+        };
+        if pending.is_empty() {
+            self.pending.remove(oid);
+        }
+        self.pending_count -= released.len();
+        for (ieid, (_, event)) in released {
+            self.last.insert(oid.clone(), ieid);
+            self.ready.push_back((oid.clone(), event));
+        }
+    }
+    #[inline]
+    pub fn pop_ready(&mut self) -> Vec<(OID, ReplicationStateEvent)> {
+        self.pop_ready_at(Instant::now())
+    }
+    /// Returns events which hold window is over, in IEID order per OID. If a newer event is
+    /// due, all older pending events of the OID are released as well
+    pub fn pop_ready_at(&mut self, now: Instant) -> Vec<(OID, ReplicationStateEvent)> {
+        let mut due: Vec<(OID, IEID)> = Vec::new();
+        for (oid, pending) in &self.pending {
+            if let Some(ieid) = pending
+                .iter()
+                .rev()
+                .find(|(_, (t, _))| now.saturating_duration_since(*t) >= self.hold)
+                .map(|(ieid, _)| *ieid)
+            {
+                due.push((oid.clone(), ieid));
+            }
+        }
+        for (oid, ieid) in due {
+            self.release(&oid, Some(ieid));
+        }
+        self.ready.drain(..).collect()
+    }
+    /// Releases all pending events
+    pub fn flush(&mut self) -> Vec<(OID, ReplicationStateEvent)> {
+        let oids: Vec<OID> = self.pending.keys().cloned().collect();
+        for oid in oids {
+            self.release(&oid, None);
+        }
+        self.ready.drain(..).collect()
+    }
+    /// Forgets the OID state (e.g. when the item is destroyed)
+    pub fn remove(&mut self, oid: &OID) {
+        self.last.remove(oid);
+        if let Some(pending) = self.pending.remove(oid) {
+            self.pending_count -= pending.len();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::IeidReorderBuffer;
+    use crate::events::ReplicationStateEvent;
+    use crate::value::Value;
+    use crate::{IEID, OID};
+    use std::time::{Duration, Instant};
+
+    fn ev(i: u64) -> ReplicationStateEvent {
+        ReplicationStateEvent {
+            status: 1,
+            value: Value::U64(i),
+            act: None,
+            ieid: IEID::new(1, i),
+            t: 0.0,
+            node: "node1".to_owned(),
+            force_accept: false,
+        }
+    }
+
+    fn ieids(events: &[(OID, ReplicationStateEvent)]) -> Vec<IEID> {
+        events.iter().map(|(_, e)| e.ieid).collect()
+    }
+
+    #[test]
+    fn test_reorder() {
+        let hold = Duration::from_millis(100);
+        let mut buf = IeidReorderBuffer::new(hold);
+        let oid: OID = "sensor:tests/s1".parse().unwrap();
+        let t = Instant::now();
+        assert!(buf.push_at(oid.clone(), ev(3), t));
+        assert!(buf.push_at(oid.clone(), ev(1), t + Duration::from_millis(10)));
+        assert!(!buf.push_at(oid.clone(), ev(3), t));
+        assert!(buf.pop_ready_at(t + Duration::from_millis(50)).is_empty());
+        // ev 3 is due, ev 1 is older and is released as well
+        let ready = buf.pop_ready_at(t + hold);
+        assert_eq!(ieids(&ready), vec![IEID::new(1, 1), IEID::new(1, 3)]);
+        assert!(!buf.push_at(oid.clone(), ev(2), t + hold));
+        assert_eq!(buf.dropped(), 2);
+        buf.push_at(oid.clone(), ev(5), t + hold);
+        let mut forced = ev(4);
+        forced.force_accept = true;
+        buf.push_at(oid.clone(), forced, t + hold);
+        assert_eq!(ieids(&buf.flush()), vec![IEID::new(1, 4), IEID::new(1, 5)]);
+        assert_eq!(buf.pending(), 0);
+    }
+
+    #[test]
+    fn test_reorder_bounds() {
+        let mut buf = IeidReorderBuffer::new(Duration::from_secs(10)).max_pending_per_oid(2);
+        let oid: OID = "sensor:tests/s1".parse().unwrap();
+        let t = Instant::now();
+        for i in [4, 2, 3] {
+            buf.push_at(oid.clone(), ev(i), t);
+        }
+        assert_eq!(ieids(&buf.pop_ready_at(t)), vec![IEID::new(1, 2)]);
+        assert_eq!(buf.pending(), 2);
+        assert!(!buf.push_at(oid, ev(1), t));
+    }
+}
@@ -0,0 +1,205 @@
+//! Service status watcher, tracks statuses announced by services to [`SERVICE_STATUS_TOPIC`]
+use super::SERVICE_STATUS_TOPIC;
+use crate::payload::unpack;
+use crate::services::{ServiceStatusBroadcast, ServiceStatusBroadcastEvent};
+use crate::{EResult, Error};
+use busrt::client::AsyncClient;
+use busrt::{Frame, QoS};
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast;
+
+const CHANGES_CHANNEL_CAPACITY: usize = 1024;
+
+/// Service status with the time (UNIX timestamp) it has been received
+#[derive(Debug, Copy, Clone, PartialEq, Serialize)]
+pub struct SvcStatusInfo {
+    pub status: ServiceStatusBroadcast,
+    pub t: f64,
+}
+
+/// Service status change notification
+#[derive(Debug, Clone, Serialize)]
+pub struct SvcStatusChange {
+    pub svc: String,
+    pub status: ServiceStatusBroadcast,
+    pub prev: Option<ServiceStatusBroadcast>,
+    pub t: f64,
+}
+
+/// Subscribes the bus client to service status announcements and creates a watcher. Frames,
+/// received by the client, must be passed to [`SvcStatusWatcher::process_frame()`]
+///
+/// # Errors
+///
+/// Will return `Err` if the subscription failed
+pub async fn svc_status_watcher<C>(client: &mut C) -> EResult<SvcStatusWatcher>
+where
+    C: AsyncClient + ?Sized,
+{
+    client
+        .subscribe(SERVICE_STATUS_TOPIC, QoS::Processed)
+        .await?
+        .ok_or_else(|| Error::io("no subscription confirmation"))?
+        .await??;
+    Ok(SvcStatusWatcher::new())
+}
+
+/// Service status watcher, cheap to clone
+#[derive(Clone)]
+pub struct SvcStatusWatcher {
+    statuses: Arc<Mutex<BTreeMap<String, SvcStatusInfo>>>,
+    tx: broadcast::Sender<SvcStatusChange>,
+}
+
+impl Default for SvcStatusWatcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SvcStatusWatcher {
+    pub fn new() -> Self {
+        let (tx, _) = broadcast::channel(CHANGES_CHANNEL_CAPACITY);
+        Self {
+            statuses: <_>::default(),
+            tx,
+        }
+    }
+    /// Processes a bus frame, returns false if the frame is not a service status announcement
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the frame payload is invalid
+    pub fn process_frame(&self, frame: &Frame) -> EResult<bool> {
+        if frame.topic() != Some(SERVICE_STATUS_TOPIC) {
+            return Ok(false);
+        }
+        let event: ServiceStatusBroadcastEvent = unpack(frame.payload())?;
+        self.set(frame.primary_sender(), event.status);
+        Ok(true)
+    }
+    /// Manually sets a service status
+    pub fn set(&self, svc: &str, status: ServiceStatusBroadcast) {
+        let t = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs_f64())
+            .unwrap_or_default();
+        let prev = self
+            .statuses
+            .lock()
+            .insert(svc.to_owned(), SvcStatusInfo { status, t })
+            .map(|v| v.status);
+        if prev != Some(status) {
+            // no receivers is not an error
+            let _ = self.tx.send(SvcStatusChange {
+                svc: svc.to_owned(),
+                status,
+                prev,
+                t,
+            });
+        }
+    }
+    pub fn get(&self, svc: &str) -> Option<SvcStatusInfo> {
+        self.statuses.lock().get(svc).copied()
+    }
+    pub fn statuses(&self) -> BTreeMap<String, SvcStatusInfo> {
+        self.statuses.lock().clone()
+    }
+    /// Removes a service from the map (e.g. when it has been undeployed)
+    pub fn remove(&self, svc: &str) -> Option<SvcStatusInfo> {
+        self.statuses.lock().remove(svc)
+    }
+    /// Subscribes to status changes
+    pub fn changes(&self) -> broadcast::Receiver<SvcStatusChange> {
+        self.tx.subscribe()
+    }
+    /// Waits until a service reports the given status
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` on timeout
+    pub async fn wait_for(
+        &self,
+        svc: &str,
+        status: ServiceStatusBroadcast,
+        timeout: Duration,
+    ) -> EResult<()> {
+        let mut rx = self.changes();
+        if self.get(svc).is_some_and(|s| s.status == status) {
+            return Ok(());
+        }
+        tokio::time::timeout(timeout, async move {
+            loop {
+                match rx.recv().await {
+                    Ok(change) if change.svc == svc && change.status == status => break Ok(()),
+                    Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {
+                        if self.get(svc).is_some_and(|s| s.status == status) {
+                            break Ok(());
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Closed) => {
+                        break Err(Error::failed("status watcher closed"))
+                    }
+                }
+            }
+        })
+        .await?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SvcStatusWatcher;
+    use crate::services::ServiceStatusBroadcast;
+    use std::time::Duration;
+
+    #[test]
+    fn test_svc_status_watcher() {
+        let watcher = SvcStatusWatcher::new();
+        let mut rx = watcher.changes();
+        watcher.set("eva.svc.test", ServiceStatusBroadcast::Starting);
+        watcher.set("eva.svc.test", ServiceStatusBroadcast::Starting);
+        watcher.set("eva.svc.test", ServiceStatusBroadcast::Ready);
+        let change = rx.try_recv().unwrap();
+        assert_eq!(change.status, ServiceStatusBroadcast::Starting);
+        assert!(change.prev.is_none());
+        let change = rx.try_recv().unwrap();
+        assert_eq!(change.prev, Some(ServiceStatusBroadcast::Starting));
+        assert!(rx.try_recv().is_err());
+        assert_eq!(
+            watcher.get("eva.svc.test").unwrap().status,
+            ServiceStatusBroadcast::Ready
+        );
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let w = watcher.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                w.set("eva.svc.test2", ServiceStatusBroadcast::Ready);
+            });
+            watcher
+                .wait_for(
+                    "eva.svc.test2",
+                    ServiceStatusBroadcast::Ready,
+                    Duration::from_secs(5),
+                )
+                .await
+                .unwrap();
+            assert!(watcher
+                .wait_for(
+                    "eva.svc.test",
+                    ServiceStatusBroadcast::Terminating,
+                    Duration::from_millis(10)
+                )
+                .await
+                .is_err());
+        });
+    }
+}
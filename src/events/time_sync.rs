@@ -0,0 +1,232 @@
+//! Node clock synchronization status, announced to `SYS/TIMESYNC/<node>`
+use super::TIME_SYNC_STATUS_TOPIC;
+use crate::{EResult, Error};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+#[cfg(all(feature = "services", target_os = "linux"))]
+use std::time::Duration;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[cfg(all(feature = "services", target_os = "linux"))]
+const TIMESYNCD_SYNC_FLAG: &str = "/run/systemd/timesync/synchronized";
+
+/// Time synchronization daemon the status has been sampled from
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TimeSyncSource {
+    Chrony,
+    Timesyncd,
+    Other,
+}
+
+impl fmt::Display for TimeSyncSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            TimeSyncSource::Chrony => "chrony",
+            TimeSyncSource::Timesyncd => "timesyncd",
+            TimeSyncSource::Other => "other",
+        })
+    }
+}
+
+/// Node clock synchronization status
+///
+/// All times are UNIX timestamps, offsets and drifts are in seconds. Fields, which are not
+/// provided by the source, are set to None
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TimeSyncStatus {
+    pub source: TimeSyncSource,
+    pub synchronized: bool,
+    /// Reference server name or address
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stratum: Option<u8>,
+    /// Estimated offset of the system clock from the reference, positive if the system clock is
+    /// ahead
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub offset: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_sync: Option<f64>,
+    /// Maximum estimated error of the system clock
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_drift: Option<f64>,
+    /// Sample time
+    pub t: f64,
+}
+
+impl TimeSyncStatus {
+    #[inline]
+    pub fn new(source: TimeSyncSource, synchronized: bool) -> Self {
+        Self {
+            source,
+            synchronized,
+            server: None,
+            stratum: None,
+            offset: None,
+            last_sync: None,
+            max_drift: None,
+            t: unix_timestamp(SystemTime::now()).unwrap_or_default(),
+        }
+    }
+    /// Bus topic for the node status: `SYS/TIMESYNC/<node>`
+    pub fn topic(node: &str) -> String {
+        format!("{}{}", TIME_SYNC_STATUS_TOPIC, node)
+    }
+    /// Returns true if the clock is synchronized and its error is within the limit (if known)
+    pub fn is_healthy(&self, max_drift: f64) -> bool {
+        self.synchronized
+            && !self.max_drift.is_some_and(|d| d > max_drift)
+            && !self.offset.is_some_and(|o| o.abs() > max_drift)
+    }
+    /// Parses the output of "chronyc -c tracking"
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the output is not a valid CSV tracking report
+    pub fn from_chrony_tracking(output: &str) -> EResult<Self> {
+        fn field<T: std::str::FromStr>(fields: &[&str], n: usize) -> EResult<T> {
+            fields
+                .get(n)
+                .ok_or_else(|| Error::invalid_data("chrony tracking: too few fields"))?
+                .parse()
+                .map_err(|_| Error::invalid_data(format!("chrony tracking: invalid field {}", n)))
+        }
+        let fields: Vec<&str> = output.trim().split(',').collect();
+        let stratum: u8 = field(&fields, 2)?;
+        let ref_time: f64 = field(&fields, 3)?;
+        // chrony reports the correction, which is positive if the system clock is behind
+        let correction: f64 = field(&fields, 4)?;
+        let root_delay: f64 = field(&fields, 10)?;
+        let root_dispersion: f64 = field(&fields, 11)?;
+        let leap: &str = fields
+            .get(13)
+            .ok_or_else(|| Error::invalid_data("chrony tracking: too few fields"))?;
+        let mut status = Self::new(
+            TimeSyncSource::Chrony,
+            stratum > 0 && ref_time > 0.0 && leap != "Not synchronised",
+        );
+        status.server = Some(fields[1].to_owned()).filter(|s| !s.is_empty());
+        status.stratum = Some(stratum);
+        status.offset = Some(-correction);
+        status.last_sync = Some(ref_time).filter(|t| *t > 0.0);
+        status.max_drift = Some(correction.abs() + root_dispersion + root_delay / 2.0);
+        Ok(status)
+    }
+    /// Parses properties, returned by "timedatectl show" / "timedatectl show-timesync"
+    /// (NTPSynchronized, ServerName, ServerAddress)
+    pub fn from_timesyncd_properties(output: &str) -> Self {
+        let mut status = Self::new(TimeSyncSource::Timesyncd, false);
+        let mut address = None;
+        for (name, value) in output.lines().filter_map(|l| l.trim().split_once('=')) {
+            match name {
+                "NTPSynchronized" => status.synchronized = value == "yes",
+                "ServerName" if !value.is_empty() => status.server = Some(value.to_owned()),
+                "ServerAddress" if !value.is_empty() => address = Some(value.to_owned()),
+                _ => {}
+            }
+        }
+        if status.server.is_none() {
+            status.server = address;
+        }
+        status
+    }
+}
+
+/// Samples the clock synchronization status from chrony or systemd-timesyncd, whichever is
+/// available
+///
+/// # Errors
+///
+/// Will return `Err` if no supported time synchronization daemon responds
+#[cfg(all(feature = "services", target_os = "linux"))]
+pub async fn sample_time_sync(timeout: Duration) -> EResult<TimeSyncStatus> {
+    if let Ok(output) = run("chronyc", &["-c", "tracking"], timeout).await {
+        return TimeSyncStatus::from_chrony_tracking(&output);
+    }
+    let mut output = run("timedatectl", &["show"], timeout)
+        .await
+        .map_err(|e| Error::unsupported(format!("no supported time sync daemon found: {}", e)))?;
+    if let Ok(timesync) = run("timedatectl", &["show-timesync"], timeout).await {
+        output.push('\n');
+        output.push_str(&timesync);
+    }
+    let mut status = TimeSyncStatus::from_timesyncd_properties(&output);
+    // timesyncd touches the flag file on every successful synchronization
+    if let Ok(modified) = tokio::fs::metadata(TIMESYNCD_SYNC_FLAG)
+        .await
+        .and_then(|m| m.modified())
+    {
+        status.last_sync = unix_timestamp(modified);
+    }
+    Ok(status)
+}
+
+fn unix_timestamp(t: SystemTime) -> Option<f64> {
+    t.duration_since(UNIX_EPOCH).ok().map(|d| d.as_secs_f64())
+}
+
+#[cfg(all(feature = "services", target_os = "linux"))]
+async fn run(program: &str, args: &[&str], timeout: Duration) -> EResult<String> {
+    let output = tokio::time::timeout(
+        timeout,
+        tokio::process::Command::new(program)
+            .args(args)
+            .stdin(std::process::Stdio::null())
+            .kill_on_drop(true)
+            .output(),
+    )
+    .await??;
+    if !output.status.success() {
+        return Err(Error::failed(format!(
+            "{} exited with {}",
+            program, output.status
+        )));
+    }
+    String::from_utf8(output.stdout).map_err(Error::invalid_data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TimeSyncSource, TimeSyncStatus};
+
+    #[test]
+    fn test_time_sync_status() {
+        let status = TimeSyncStatus::from_chrony_tracking(
+            "A29FC87B,time.example.com,3,1700000000.500000000,0.000100000,-0.000003,\
+            0.000020,-12.345,0.001,0.050,0.020000000,0.000400000,64.5,Normal\n",
+        )
+        .unwrap();
+        assert_eq!(status.source, TimeSyncSource::Chrony);
+        assert!(status.synchronized);
+        assert_eq!(status.server.as_deref(), Some("time.example.com"));
+        assert_eq!(status.stratum, Some(3));
+        assert_eq!(status.offset, Some(-0.0001));
+        assert_eq!(status.last_sync, Some(1_700_000_000.5));
+        assert!((status.max_drift.unwrap() - 0.0105).abs() < 1e-9);
+        assert!(status.is_healthy(0.1));
+        assert!(!status.is_healthy(0.001));
+        assert!(TimeSyncStatus::from_chrony_tracking("invalid").is_err());
+        let unsynced = TimeSyncStatus::from_chrony_tracking(
+            "7F7F0101,,10,0.0,0.0,0.0,0.0,0.0,0.0,0.0,1.0,1.0,0.0,Not synchronised",
+        )
+        .unwrap();
+        assert!(!unsynced.synchronized);
+        assert!(unsynced.server.is_none());
+        assert!(unsynced.last_sync.is_none());
+        let status = TimeSyncStatus::from_timesyncd_properties(
+            "Timezone=UTC\nNTPSynchronized=yes\nServerName=\nServerAddress=192.168.1.1\n",
+        );
+        assert_eq!(status.source, TimeSyncSource::Timesyncd);
+        assert!(status.synchronized);
+        assert_eq!(status.server.as_deref(), Some("192.168.1.1"));
+        assert!(status.is_healthy(0.1));
+        let value = crate::value::to_value(&status).unwrap();
+        assert_eq!(
+            value.jp_lookup("$.source").unwrap(),
+            Some(&crate::value::Value::String("timesyncd".to_owned()))
+        );
+        assert!(value.jp_lookup("$.offset").unwrap().is_none());
+        assert_eq!(TimeSyncStatus::topic("node1"), "SYS/TIMESYNC/node1");
+    }
+}
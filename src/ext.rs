@@ -1,127 +1,68 @@
-/// experimental shared lib extensions, not used yet
-use crate::EvaError;
-use serde::{Deserialize, Serialize};
+//! Shared library extensions
+//!
+//! An extension is a `cdylib` crate, which implements [`Phi`], [`AuthModule`] or the generic
+//! [`Extension`] trait and exports the C ABI with [`ext_phi!`](crate::ext_phi),
+//! [`ext_auth_module!`](crate::ext_auth_module) or [`ext_generic!`](crate::ext_generic). The
+//! host loads extensions with [`ExtensionHost::load`].
+//!
+//! Data is exchanged as MessagePack frames. A frame, returned by an extension, points to its
+//! thread-local buffer and is valid until the next call in the same thread. Panics are caught at
+//! the ABI boundary, an extension which has panicked is poisoned and refuses further calls.
+use crate::payload::{pack, unpack};
+use crate::secret::Secret;
+use crate::value::Value;
+use crate::{EResult, Error, ErrorKind};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_repr::{Deserialize_repr, Serialize_repr};
+use std::cell::RefCell;
+use std::ffi::OsStr;
+use std::sync::atomic;
+use std::time::Duration;
+
+/// Comm protocol version, must be equal for the host and extensions
+pub const COMM_VERSION: u16 = 2;
+
+pub const RESULT_OK: i16 = 0;
+/// The extension has panicked during the call
+pub const RESULT_PANIC: i16 = 1;
+
+pub const ERR_POISONED: &str = "extension is poisoned";
+const ERR_PANICKED: &str = "extension panicked";
+pub const ERR_NOT_INITIALIZED: &str = "extension is not initialized";
+
+pub const METHOD_PHI_GET: &str = "phi.get";
+pub const METHOD_PHI_SET: &str = "phi.set";
+pub const METHOD_AUTH: &str = "auth.authenticate";
 
 const CLASS_PHI: u16 = 10;
 const CLASS_GENERIC_PLUGIN: u16 = 20;
 const CLASS_AUTH_MODULE: u16 = 30;
 
-#[derive(Serialize_repr, Deserialize_repr, PartialEq, Debug)]
+#[derive(Serialize_repr, Deserialize_repr, Eq, PartialEq, Debug, Copy, Clone)]
 #[repr(u16)]
-pub enum EeExtensionClass {
+pub enum ExtensionClass {
     Phi = CLASS_PHI,
     GenericPlugin = CLASS_GENERIC_PLUGIN,
     AuthModule = CLASS_AUTH_MODULE,
 }
 
-pub mod prelude {
-    pub use super::comm::ee_async_id;
-    pub use super::comm::ee_decode;
-    pub use super::comm::ee_encode;
-    pub use super::comm::ee_send_async_result;
-    pub use super::comm::EeFrame;
-    pub use super::comm::EeFrameConv;
-    pub use super::comm::EeResult;
-    pub use super::comm::EeResultAsync;
-    pub use super::EeExtensionClass;
-    pub use super::EeMetadata;
-    pub use crate::ee_commons;
-    pub use crate::ee_get_option;
-    pub use crate::ee_result;
-    pub use crate::ee_unwrap_or;
-}
-
-impl From<libloading::Error> for EvaError {
-    fn from(err: libloading::Error) -> EvaError {
-        EvaError::failed(err)
-    }
-}
-
-#[macro_export]
-macro_rules! ee_unwrap_or {
-    ($result: expr) => {
-        match $result {
-            Ok(v) => v,
-            Err(e) => {
-                return e.into();
-            }
-        }
-    };
-}
-
-#[macro_export]
-macro_rules! ee_get_option {
-    ($data: expr, $key: expr) => {
-        match $data.remove($key) {
-            Some(v) => ee_unwrap_or!(v.try_into()),
-            None => {
-                return EvaError::invalid_data(format!("The parameter is missing: {}", $key))
-                    .into();
-            }
-        }
-    };
-    ($data: expr, $key: expr, $v: expr) => {
-        match $data.remove($key) {
-            Some(v) => ee_unwrap_or!(v.try_into()),
-            None => $v,
-        }
-    };
-}
-
-#[macro_export]
-macro_rules! ee_commons {
-    () => {
-        #[no_mangle]
-        pub unsafe fn set_logger_fn(func: fn($crate::ext::comm::EeFrame), level: u8) -> EeResult {
-            $crate::ext::logger::set_logger_fn(func, level)
-        }
-
-        #[no_mangle]
-        pub unsafe fn set_async_result_fn(
-            func: fn(u32, u32, $crate::ext::comm::EeFrame),
-        ) -> EeResult {
-            $crate::ext::comm::set_async_result_fn(func)
-        }
-
-        #[no_mangle]
-        pub unsafe fn free_result() -> EeResult {
-            $crate::ext::free_result()
-        }
-
-        #[no_mangle]
-        pub unsafe fn get_comm_version() -> u16 {
-            $crate::ext::comm::COMM_VERSION
-        }
-
-        #[no_mangle]
-        pub fn set_id(id: u32) -> EeResult {
-            $crate::ext::comm::set_id(id);
-            EeResult::ok()
-        }
-
-        #[no_mangle]
-        pub unsafe fn set_name(data: EeFrame) -> EeResult {
-            let name = ee_unwrap_or!(data.decode());
-            $crate::ext::logger::set_name(name);
-            EeResult::ok()
-        }
-    };
-}
-
-#[macro_export]
-macro_rules! ee_result {
-    ($result: expr) => {
-        match $result {
-            Ok(v) => v.to_eeframe(),
-            Err(e) => e.into(),
+impl TryFrom<u16> for ExtensionClass {
+    type Error = Error;
+    fn try_from(code: u16) -> EResult<Self> {
+        match code {
+            CLASS_PHI => Ok(ExtensionClass::Phi),
+            CLASS_GENERIC_PLUGIN => Ok(ExtensionClass::GenericPlugin),
+            CLASS_AUTH_MODULE => Ok(ExtensionClass::AuthModule),
+            _ => Err(Error::unsupported(format!(
+                "unsupported extension class: {}",
+                code
+            ))),
         }
-    };
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct EeMetadata {
-    pub class: EeExtensionClass,
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Metadata {
     #[serde(default)]
     pub author: String,
     #[serde(default)]
@@ -131,422 +72,600 @@ pub struct EeMetadata {
     #[serde(default)]
     pub description: String,
     #[serde(default)]
-    pub version: u32,
-    pub api: u32,
+    pub version: String,
 }
 
-pub unsafe fn free_result() -> comm::EeResult {
-    comm::clear_data();
-    comm::EeResult::ok()
+/// Data frame, passed through the ABI
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct Frame {
+    code: i16,
+    len: usize,
+    data: *const u8,
 }
 
-pub mod comm {
-
-    use crate::{EvaError, EvaErrorKind};
-    use serde::{Deserialize, Serialize};
-    use std::cell::RefCell;
-    use std::sync::{atomic, Mutex};
-
-    pub const RESULT_OK: i16 = 0;
-    pub const COMM_VERSION: u16 = 1;
-
-    const ERR_UNSUPPORTED_COMM_VERSION: &str = "Unsupported comm version";
-
-    pub fn version_supported(ver: u16) -> Result<(), EvaError> {
-        if ver > COMM_VERSION {
-            Err(EvaError::unsupported(ERR_UNSUPPORTED_COMM_VERSION))
-        } else {
-            Ok(())
+impl Frame {
+    #[inline]
+    pub fn new(code: i16, data: &[u8]) -> Self {
+        Self {
+            code,
+            len: data.len(),
+            data: data.as_ptr(),
         }
     }
-
-    #[repr(C)]
-    pub struct EeFrame {
-        code: i16,           // code (used for errors only)
-        length: u32,         // data length
-        data_ptr: *const u8, // raw pointer to the frame data
+    #[inline]
+    pub fn code(&self) -> i16 {
+        self.code
     }
-
-    impl EeFrame {
-        pub fn decode<'a, T: Deserialize<'a>>(self) -> Result<T, EvaError> {
-            unsafe { ee_decode(COMM_VERSION, self) }
-        }
-        pub fn decode_ver<'a, T: Deserialize<'a>>(self, comm_ver: u16) -> Result<T, EvaError> {
-            unsafe { ee_decode(comm_ver, self) }
-        }
-        pub fn ok() -> EeFrame {
-            unsafe { ee_encode(COMM_VERSION, RESULT_OK, &None::<u32>).unwrap() }
-        }
-    }
-
-    impl From<EvaError> for EeFrame {
-        fn from(err: EvaError) -> EeFrame {
-            err.to_eeframe()
+    /// # Safety
+    ///
+    /// The frame data must be valid
+    pub unsafe fn as_slice<'a>(&self) -> &'a [u8] {
+        if self.len == 0 || self.data.is_null() {
+            &[]
+        } else {
+            std::slice::from_raw_parts(self.data, self.len)
         }
     }
-
-    #[repr(C)]
-    pub struct EeResult(i16);
-
-    impl EeResult {
-        pub fn decode_ver(&self, comm_ver: u16) -> Result<(), EvaError> {
-            if comm_ver != 1 {
-                return Err(EvaError::unsupported(ERR_UNSUPPORTED_COMM_VERSION));
-            }
-            if self.0 == RESULT_OK {
-                Ok(())
-            } else {
-                Err(EvaError::new0(self.0.into()))
+    /// Decodes a result frame
+    ///
+    /// # Safety
+    ///
+    /// The frame data must be valid
+    pub unsafe fn decode<T: DeserializeOwned>(&self) -> EResult<T> {
+        match self.code {
+            RESULT_OK => unpack(self.as_slice()),
+            RESULT_PANIC => Err(Error::core(ERR_PANICKED)),
+            code => {
+                let message: Option<String> = unpack(self.as_slice()).unwrap_or_default();
+                Err(Error::newc(code.into(), message))
             }
         }
-        pub fn decode(&self) -> Result<(), EvaError> {
-            self.decode_ver(COMM_VERSION)
-        }
-        pub fn ok() -> Self {
-            Self(RESULT_OK)
-        }
     }
+}
 
-    impl From<EvaError> for EeResult {
-        fn from(err: EvaError) -> EeResult {
-            EeResult(err.kind as i16)
-        }
-    }
+pub type LogFn = extern "C" fn(level: u8, message: *const u8, len: usize);
 
-    impl From<EvaErrorKind> for EeResult {
-        fn from(kind: EvaErrorKind) -> EeResult {
-            EeResult(kind as i16)
-        }
+/// Extension side
+pub trait Extension: Send + Sized + 'static {
+    const CLASS: ExtensionClass;
+    fn metadata() -> Metadata;
+    fn init(config: Value) -> EResult<Self>;
+    /// Generic method call, the methods of typed extensions are dispatched automatically
+    fn call(&mut self, method: &str, _params: Value) -> EResult<Value> {
+        Err(Error::new(ErrorKind::MethodNotFound, method))
     }
+}
 
-    #[repr(C)]
-    pub struct EeResultAsync(i16, u32);
+pub trait Phi: Extension {
+    fn get(&mut self, port: Option<&str>) -> EResult<Value>;
+    fn set(&mut self, port: &str, value: Value) -> EResult<()>;
+}
 
-    impl EeResultAsync {
-        pub fn decode_ver(&self, comm_ver: u16) -> Result<u32, EvaError> {
-            if comm_ver != 1 {
-                return Err(EvaError::unsupported(ERR_UNSUPPORTED_COMM_VERSION));
-            }
-            if self.0 == RESULT_OK {
-                Ok(self.1)
-            } else {
-                Err(EvaError::new0(self.0.into()))
-            }
-        }
-        pub fn decode(&self) -> Result<u32, EvaError> {
-            self.decode_ver(COMM_VERSION)
-        }
-        pub fn ok(async_id: u32) -> Self {
-            Self(RESULT_OK, async_id)
-        }
-    }
+pub trait AuthModule: Extension {
+    /// Returns authentication data (e.g. ACL ids) on success
+    fn authenticate(&mut self, login: &str, password: &str, timeout: Duration) -> EResult<Value>;
+}
 
-    impl From<EvaError> for EeResultAsync {
-        fn from(err: EvaError) -> EeResultAsync {
-            EeResultAsync(err.kind as i16, 0)
-        }
-    }
+#[derive(Serialize, Deserialize)]
+struct PhiGetParams {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    port: Option<String>,
+}
 
-    impl From<EvaErrorKind> for EeResultAsync {
-        fn from(kind: EvaErrorKind) -> EeResultAsync {
-            EeResultAsync(kind as i16, 0)
-        }
-    }
+#[derive(Serialize, Deserialize)]
+struct PhiSetParams {
+    port: String,
+    value: Value,
+}
 
-    #[repr(C)]
-    pub struct DataFrame {
-        data: Vec<u8>,
-    }
+#[derive(Serialize, Deserialize)]
+struct AuthParams {
+    login: String,
+    password: Secret<String>,
+    #[serde(
+        serialize_with = "crate::tools::serialize_duration_as_nanos",
+        deserialize_with = "crate::tools::deserialize_duration_from_nanos"
+    )]
+    timeout: Duration,
+}
 
-    trait EeErrorConv {
-        fn to_eeframe(&self) -> EeFrame;
-        fn to_eeframe_ver(&self, comm_ver: u16) -> EeFrame;
-    }
+fn params<T: DeserializeOwned>(params: Value) -> EResult<T> {
+    T::deserialize(params).map_err(Error::invalid_data)
+}
+
+#[doc(hidden)]
+pub fn dispatch_generic<T: Extension>(ext: &mut T, method: &str, params: Value) -> EResult<Value> {
+    ext.call(method, params)
+}
 
-    impl EeErrorConv for EvaError {
-        fn to_eeframe(&self) -> EeFrame {
-            unsafe { ee_encode(COMM_VERSION, self.kind as i16, &self.message) }.unwrap()
+#[doc(hidden)]
+pub fn dispatch_phi<T: Phi>(ext: &mut T, method: &str, p: Value) -> EResult<Value> {
+    match method {
+        METHOD_PHI_GET => {
+            let p: PhiGetParams = params(p)?;
+            ext.get(p.port.as_deref())
         }
-        fn to_eeframe_ver(&self, comm_ver: u16) -> EeFrame {
-            unsafe { ee_encode(comm_ver, self.kind as i16, &self.message) }.unwrap()
+        METHOD_PHI_SET => {
+            let p: PhiSetParams = params(p)?;
+            ext.set(&p.port, p.value).map(|()| Value::Unit)
         }
+        _ => ext.call(method, p),
     }
+}
 
-    pub trait EeFrameConv {
-        fn ee_encode(&self) -> Result<EeFrame, EvaError>;
-        fn ee_encode_ver(&self, comm_ver: u16) -> Result<EeFrame, EvaError>;
-        fn to_eeframe(&self) -> EeFrame;
-        fn to_eeframe_ver(&self, comm_ver: u16) -> EeFrame;
+#[doc(hidden)]
+pub fn dispatch_auth_module<T: AuthModule>(ext: &mut T, method: &str, p: Value) -> EResult<Value> {
+    if method == METHOD_AUTH {
+        let p: AuthParams = params(p)?;
+        ext.authenticate(&p.login, p.password.as_str(), p.timeout)
+    } else {
+        ext.call(method, p)
     }
+}
 
-    macro_rules! unwrap_or_err_frame {
-        ($comm_ver: expr, $result: expr) => {
-            $result.unwrap_or_else(|e| e.to_eeframe_ver($comm_ver))
-        };
+/// Extension-side runtime, used by the export macros
+#[doc(hidden)]
+pub mod rt {
+    use super::{Frame, LogFn, RESULT_OK, RESULT_PANIC};
+    use crate::payload::{pack, unpack};
+    use crate::value::Value;
+    use crate::EResult;
+    use serde::Serialize;
+    use std::cell::RefCell;
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    thread_local! {
+        static RESULT: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
     }
 
-    impl<T> EeFrameConv for T
+    fn result_frame(code: i16, data: Vec<u8>) -> Frame {
+        RESULT.with(|cell| {
+            let mut buf = cell.borrow_mut();
+            *buf = data;
+            Frame::new(code, &buf)
+        })
+    }
+
+    /// Runs the function, catching panics, and stores the result into the thread-local buffer
+    pub fn guarded<T, F>(f: F) -> Frame
     where
         T: Serialize,
+        F: FnOnce() -> EResult<T>,
     {
-        fn ee_encode(&self) -> Result<EeFrame, EvaError> {
-            unsafe { ee_encode(COMM_VERSION, 0, self) }
-        }
-        fn ee_encode_ver(&self, comm_ver: u16) -> Result<EeFrame, EvaError> {
-            unsafe { ee_encode(comm_ver, 0, self) }
-        }
-        fn to_eeframe(&self) -> EeFrame {
-            unwrap_or_err_frame!(COMM_VERSION, unsafe { ee_encode(COMM_VERSION, 0, self) })
-        }
-        fn to_eeframe_ver(&self, comm_ver: u16) -> EeFrame {
-            unwrap_or_err_frame!(comm_ver, unsafe { ee_encode(comm_ver, 0, self) })
+        match catch_unwind(AssertUnwindSafe(|| f().and_then(|v| pack(&v)))) {
+            Ok(Ok(data)) => result_frame(RESULT_OK, data),
+            Ok(Err(e)) => result_frame(e.kind() as i16, pack(&e.message()).unwrap_or_default()),
+            Err(_) => result_frame(RESULT_PANIC, Vec::new()),
         }
     }
 
-    impl DataFrame {
-        pub fn with_data(data: Vec<u8>) -> Self {
-            Self { data }
-        }
-        pub fn set_data(&mut self, data: Vec<u8>) {
-            self.data = data;
-        }
-        pub fn as_frame(&self, code: i16) -> EeFrame {
-            EeFrame {
-                code,
-                length: self.data.len() as u32,
-                data_ptr: self.data.as_ptr(),
-            }
-        }
-        pub fn clear(&mut self) {
-            self.data.clear();
+    /// # Safety
+    ///
+    /// The frame data must be valid
+    pub unsafe fn frame_value(frame: Frame) -> EResult<Value> {
+        let data = frame.as_slice();
+        if data.is_empty() {
+            Ok(Value::Unit)
+        } else {
+            unpack(data)
         }
     }
 
-    static mut DATA: DataFrame = DataFrame { data: Vec::new() };
-    static mut ASYNC_ID: u32 = 0;
+    /// # Safety
+    ///
+    /// The frame data must be valid
+    pub unsafe fn frame_str<'a>(frame: Frame) -> EResult<&'a str> {
+        Ok(std::str::from_utf8(frame.as_slice())?)
+    }
 
-    static ID: atomic::AtomicU32 = atomic::AtomicU32::new(0);
+    struct ExtLogger(LogFn);
 
-    pub fn ee_async_id() -> u32 {
-        unsafe {
-            if ASYNC_ID == std::u32::MAX {
-                ASYNC_ID = 0;
-            }
-            ASYNC_ID += 1;
-            ASYNC_ID
+    impl log::Log for ExtLogger {
+        fn enabled(&self, _metadata: &log::Metadata) -> bool {
+            true
+        }
+        fn log(&self, record: &log::Record) {
+            let message = record.args().to_string();
+            (self.0)(
+                crate::log_level_code(record.level()),
+                message.as_ptr(),
+                message.len(),
+            );
         }
+        fn flush(&self) {}
     }
 
-    thread_local! {
-        pub static TDATA: RefCell<DataFrame> = RefCell::new(DataFrame { data: Vec::new() });
-    }
-
-    lazy_static! {
-        static ref ASYNC_RESULT: Mutex<Option<fn(u32, u32, EeFrame)>> = Mutex::new(None);
-    }
-
-    pub unsafe fn set_async_result_fn(func: fn(u32, u32, EeFrame)) -> EeResult {
-        ASYNC_RESULT.lock().unwrap().replace(func);
-        EeResult::ok()
-    }
-
-    pub fn ee_send_async_result<T: Serialize>(async_id: u32, result: Result<T, EvaError>) {
-        if let Some(async_result) = ASYNC_RESULT.lock().unwrap().as_ref() {
-            TDATA.with(|cell| {
-                let id = ID.load(atomic::Ordering::SeqCst);
-                let mut tdata = cell.borrow_mut();
-                match result {
-                    Ok(ref v) => {
-                        tdata.data = rmp_serde::to_vec_named(v).unwrap();
-                        async_result(id, async_id, tdata.as_frame(RESULT_OK));
-                    }
-                    Err(e) => {
-                        tdata.data = rmp_serde::to_vec_named(&e.message).unwrap();
-                        async_result(id, async_id, tdata.as_frame(e.kind as i16));
-                    }
-                }
-                tdata.clear();
+    /// Forwards log records of the extension to the host
+    pub fn set_logger(func: LogFn, level: u8) {
+        if log::set_boxed_logger(Box::new(ExtLogger(func))).is_ok() {
+            log::set_max_level(match level {
+                crate::LOG_LEVEL_TRACE => log::LevelFilter::Trace,
+                crate::LOG_LEVEL_DEBUG => log::LevelFilter::Debug,
+                crate::LOG_LEVEL_WARN => log::LevelFilter::Warn,
+                crate::LOG_LEVEL_ERROR => log::LevelFilter::Error,
+                crate::LOG_LEVEL_OFF => log::LevelFilter::Off,
+                _ => log::LevelFilter::Info,
             });
-        } else {
-            panic!("async result function is not set");
         }
     }
+}
 
-    pub fn set_id(id: u32) {
-        ID.store(id, atomic::Ordering::SeqCst);
-    }
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __ext_exports {
+    ($t: ty, $dispatch: path) => {
+        static __EVA_EXT: ::std::sync::Mutex<Option<$t>> = ::std::sync::Mutex::new(None);
 
-    pub unsafe fn ee_encode<T: Serialize>(
-        comm_ver: u16,
-        code: i16,
-        data: &T,
-    ) -> Result<EeFrame, EvaError> {
-        if comm_ver != 1 {
-            return Err(EvaError::unsupported(ERR_UNSUPPORTED_COMM_VERSION));
+        fn __eva_ext_with<R>(
+            f: impl FnOnce(&mut Option<$t>) -> $crate::EResult<R>,
+        ) -> $crate::EResult<R> {
+            let mut ext = __EVA_EXT
+                .lock()
+                .map_err(|_| $crate::Error::core($crate::ext::ERR_POISONED))?;
+            f(&mut ext)
         }
-        DATA = DataFrame::with_data(rmp_serde::to_vec_named(data)?);
-        Ok(DATA.as_frame(code))
-    }
 
-    pub unsafe fn ee_decode<'de, T: Deserialize<'de>>(
-        comm_ver: u16,
-        frame: EeFrame,
-    ) -> Result<T, EvaError> {
-        if comm_ver != 1 {
-            return Err(EvaError::unsupported(ERR_UNSUPPORTED_COMM_VERSION));
+        #[no_mangle]
+        pub extern "C" fn eva_ext_comm_version() -> u16 {
+            $crate::ext::COMM_VERSION
         }
-        let data_slice = std::slice::from_raw_parts(frame.data_ptr, frame.length as usize);
-        if frame.code == RESULT_OK {
-            Ok(rmp_serde::from_slice(data_slice)?)
-        } else {
-            let message: Option<String> = rmp_serde::from_slice(data_slice)?;
-            Err(EvaError::newc(frame.code.into(), message))
+
+        #[no_mangle]
+        pub extern "C" fn eva_ext_class() -> u16 {
+            <$t as $crate::ext::Extension>::CLASS as u16
         }
-    }
 
-    pub unsafe fn clear_data() {
-        DATA.clear();
-    }
-}
+        #[no_mangle]
+        pub extern "C" fn eva_ext_metadata() -> $crate::ext::Frame {
+            $crate::ext::rt::guarded(|| Ok(<$t as $crate::ext::Extension>::metadata()))
+        }
 
-pub mod logger {
-    use super::comm::{EeFrame, EeResult, RESULT_OK};
-    use log::{debug, error, info, trace, warn};
-    use log::{Level, LevelFilter, Log, Record};
-    use serde::{Deserialize, Serialize};
-
-    #[derive(Serialize, Deserialize)]
-    pub struct LogRecord {
-        #[serde(rename = "l")]
-        level: u8,
-        #[serde(rename = "m")]
-        message: String,
-    }
-
-    impl LogRecord {
-        pub fn send(&self) {
-            match self.level {
-                crate::LOG_LEVEL_TRACE => trace!("{}", self.message),
-                crate::LOG_LEVEL_DEBUG => debug!("{}", self.message),
-                crate::LOG_LEVEL_WARN => warn!("{}", self.message),
-                crate::LOG_LEVEL_ERROR => error!("{}", self.message),
-                _ => info!("{}", self.message),
-            }
+        #[no_mangle]
+        pub extern "C" fn eva_ext_set_logger(func: $crate::ext::LogFn, level: u8) {
+            $crate::ext::rt::set_logger(func, level);
         }
-    }
 
-    static mut LOGGER: Logger = Logger {
-        name: String::new(),
-        logger_function: None,
+        /// # Safety
+        ///
+        /// The frame data must be valid
+        #[no_mangle]
+        pub unsafe extern "C" fn eva_ext_init(config: $crate::ext::Frame) -> $crate::ext::Frame {
+            $crate::ext::rt::guarded(|| {
+                let config = $crate::ext::rt::frame_value(config)?;
+                let ext = <$t as $crate::ext::Extension>::init(config)?;
+                __eva_ext_with(|e| {
+                    e.replace(ext);
+                    Ok(())
+                })
+            })
+        }
+
+        /// # Safety
+        ///
+        /// The frame data must be valid
+        #[no_mangle]
+        pub unsafe extern "C" fn eva_ext_call(
+            method: $crate::ext::Frame,
+            params: $crate::ext::Frame,
+        ) -> $crate::ext::Frame {
+            $crate::ext::rt::guarded(|| {
+                let method = $crate::ext::rt::frame_str(method)?;
+                let params = $crate::ext::rt::frame_value(params)?;
+                __eva_ext_with(|e| {
+                    let ext = e.as_mut().ok_or_else(|| {
+                        $crate::Error::not_ready($crate::ext::ERR_NOT_INITIALIZED)
+                    })?;
+                    $dispatch(ext, method, params)
+                })
+            })
+        }
     };
+}
 
-    pub struct Logger {
-        logger_function: Option<fn(EeFrame)>,
-        name: String,
-    }
+/// Exports the C ABI for a [`Phi`](crate::ext::Phi) extension
+#[macro_export]
+macro_rules! ext_phi {
+    ($t: ty) => {
+        $crate::__ext_exports!($t, $crate::ext::dispatch_phi);
+    };
+}
 
-    impl Logger {
-        fn set_logger_function(&mut self, func: fn(EeFrame)) {
-            self.logger_function = Some(func);
-        }
-        fn set_name(&mut self, name: &str) {
-            self.name = name.to_owned();
-        }
-    }
+/// Exports the C ABI for an [`AuthModule`](crate::ext::AuthModule) extension
+#[macro_export]
+macro_rules! ext_auth_module {
+    ($t: ty) => {
+        $crate::__ext_exports!($t, $crate::ext::dispatch_auth_module);
+    };
+}
 
-    impl Log for Logger {
-        fn enabled(&self, _metadata: &log::Metadata) -> bool {
-            true
+/// Exports the C ABI for a generic [`Extension`](crate::ext::Extension)
+#[macro_export]
+macro_rules! ext_generic {
+    ($t: ty) => {
+        $crate::__ext_exports!($t, $crate::ext::dispatch_generic);
+    };
+}
+
+/// Extension C ABI functions
+#[derive(Copy, Clone)]
+pub struct ExtensionApi {
+    pub comm_version: extern "C" fn() -> u16,
+    pub class: extern "C" fn() -> u16,
+    pub metadata: extern "C" fn() -> Frame,
+    pub set_logger: extern "C" fn(LogFn, u8),
+    pub init: unsafe extern "C" fn(Frame) -> Frame,
+    pub call: unsafe extern "C" fn(Frame, Frame) -> Frame,
+}
+
+extern "C" fn host_log(level: u8, message: *const u8, len: usize) {
+    // safety: the pointer is provided by ExtLogger from a valid string
+    let Ok(message) = std::str::from_utf8(unsafe { std::slice::from_raw_parts(message, len) })
+    else {
+        return;
+    };
+    let level = match level {
+        crate::LOG_LEVEL_TRACE => log::Level::Trace,
+        crate::LOG_LEVEL_DEBUG => log::Level::Debug,
+        crate::LOG_LEVEL_WARN => log::Level::Warn,
+        crate::LOG_LEVEL_ERROR => log::Level::Error,
+        _ => log::Level::Info,
+    };
+    log::log!(target: "ext", level, "{}", message);
+}
+
+thread_local! {
+    static PARAMS: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+}
+
+/// A loaded extension
+pub struct ExtensionHost {
+    api: ExtensionApi,
+    class: ExtensionClass,
+    metadata: Metadata,
+    poisoned: atomic::AtomicBool,
+    // must be dropped last
+    _lib: Option<libloading::Library>,
+}
+
+impl ExtensionHost {
+    /// Loads an extension shared library
+    ///
+    /// The library must be built with the export macros of this module, its initialization
+    /// routines are executed on load
+    ///
+    /// # Safety
+    ///
+    /// Loading a library runs its code in the host process, so the library must come from a
+    /// trusted source. The exported symbols are taken as-is: the library must be built with the
+    /// export macros of this module and a compatible version of this crate (the communication
+    /// version is checked, but function signatures and frame layouts can not be verified)
+    pub unsafe fn load<P: AsRef<OsStr>>(path: P) -> EResult<Self> {
+        let lib = libloading::Library::new(path)?;
+        let api = ExtensionApi {
+            comm_version: *lib.get(b"eva_ext_comm_version\0")?,
+            class: *lib.get(b"eva_ext_class\0")?,
+            metadata: *lib.get(b"eva_ext_metadata\0")?,
+            set_logger: *lib.get(b"eva_ext_set_logger\0")?,
+            init: *lib.get(b"eva_ext_init\0")?,
+            call: *lib.get(b"eva_ext_call\0")?,
+        };
+        Self::create(api, Some(lib))
+    }
+    /// Creates a host for an extension, which is linked statically
+    ///
+    /// # Safety
+    ///
+    /// The functions must be the ones, exported by the macros of this module. Frames, returned
+    /// by the extension, are decoded as-is, so the functions must return frames which point to
+    /// valid buffers
+    pub unsafe fn from_api(api: ExtensionApi) -> EResult<Self> {
+        Self::create(api, None)
+    }
+    unsafe fn create(api: ExtensionApi, lib: Option<libloading::Library>) -> EResult<Self> {
+        let comm_version = (api.comm_version)();
+        if comm_version != COMM_VERSION {
+            return Err(Error::unsupported(format!(
+                "unsupported extension comm version: {} (required: {})",
+                comm_version, COMM_VERSION
+            )));
+        }
+        let class = (api.class)().try_into()?;
+        // safety: the frame points to the extension thread-local buffer
+        let metadata = unsafe { (api.metadata)().decode()? };
+        let log_level = log::max_level()
+            .to_level()
+            .map_or(crate::LOG_LEVEL_OFF, crate::log_level_code);
+        (api.set_logger)(host_log, log_level);
+        Ok(Self {
+            api,
+            class,
+            metadata,
+            poisoned: <_>::default(),
+            _lib: lib,
+        })
+    }
+    #[inline]
+    pub fn class(&self) -> ExtensionClass {
+        self.class
+    }
+    #[inline]
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+    #[inline]
+    pub fn is_poisoned(&self) -> bool {
+        self.poisoned.load(atomic::Ordering::SeqCst)
+    }
+    fn invoke<T, F>(&self, params: &Value, f: F) -> EResult<T>
+    where
+        T: DeserializeOwned,
+        F: FnOnce(Frame) -> Frame,
+    {
+        if self.is_poisoned() {
+            return Err(Error::core(ERR_POISONED));
         }
-        fn log(&self, record: &Record) {
-            crate::ext::comm::TDATA.with(|cell| {
-                let mut tdata = cell.borrow_mut();
-                let log_record = LogRecord {
-                    level: {
-                        match record.level() {
-                            Level::Trace => crate::LOG_LEVEL_TRACE,
-                            Level::Debug => crate::LOG_LEVEL_DEBUG,
-                            Level::Info => crate::LOG_LEVEL_INFO,
-                            Level::Warn => crate::LOG_LEVEL_WARN,
-                            Level::Error => crate::LOG_LEVEL_ERROR,
-                        }
-                    },
-                    message: format!("{}: {}", self.name, record.args()),
-                };
-                match rmp_serde::to_vec_named(&log_record) {
-                    Ok(data) => {
-                        tdata.set_data(data);
-                        (self.logger_function.unwrap())(tdata.as_frame(RESULT_OK));
-                        tdata.clear();
-                    }
-                    Err(e) => eprintln!("{} unable to send log record frame: {}", self.name, e),
-                }
-            });
+        let data = if *params == Value::Unit {
+            Vec::new()
+        } else {
+            pack(params)?
+        };
+        PARAMS.with(|cell| {
+            let mut buf = cell.borrow_mut();
+            *buf = data;
+            let frame = f(Frame::new(RESULT_OK, &buf));
+            if frame.code() == RESULT_PANIC {
+                self.poisoned.store(true, atomic::Ordering::SeqCst);
+            }
+            // safety: the frame is valid until the next call in the current thread
+            unsafe { frame.decode() }
+        })
+    }
+    /// Initializes the extension, must be called before any other method
+    pub fn init(&self, config: &Value) -> EResult<()> {
+        let _: Value = self.invoke(config, |c| unsafe { (self.api.init)(c) })?;
+        Ok(())
+    }
+    /// Calls an extension method
+    pub fn call(&self, method: &str, params: &Value) -> EResult<Value> {
+        let method = Frame::new(RESULT_OK, method.as_bytes());
+        self.invoke(params, |p| unsafe { (self.api.call)(method, p) })
+    }
+    fn check_class(&self, class: ExtensionClass) -> EResult<()> {
+        if self.class == class {
+            Ok(())
+        } else {
+            Err(Error::unsupported(format!(
+                "the extension class is {:?}, {:?} required",
+                self.class, class
+            )))
         }
-        fn flush(&self) {}
     }
-
-    fn get_level_filter(level: u8) -> LevelFilter {
-        match level {
-            crate::LOG_LEVEL_TRACE => LevelFilter::Trace,
-            crate::LOG_LEVEL_DEBUG => LevelFilter::Debug,
-            crate::LOG_LEVEL_WARN => LevelFilter::Warn,
-            crate::LOG_LEVEL_ERROR => LevelFilter::Error,
-            _ => LevelFilter::Info,
-        }
+    pub fn phi(&self) -> EResult<PhiClient<'_>> {
+        self.check_class(ExtensionClass::Phi)?;
+        Ok(PhiClient { host: self })
     }
+    pub fn auth_module(&self) -> EResult<AuthModuleClient<'_>> {
+        self.check_class(ExtensionClass::AuthModule)?;
+        Ok(AuthModuleClient { host: self })
+    }
+}
+
+pub struct PhiClient<'a> {
+    host: &'a ExtensionHost,
+}
 
-    pub unsafe fn set_logger_fn(func: fn(EeFrame), level: u8) -> EeResult {
-        LOGGER.set_logger_function(func);
-        LOGGER.set_name("noname_extension");
-        log::set_logger(&LOGGER)
-            .map(|()| log::set_max_level(get_level_filter(level)))
-            .unwrap();
-        EeResult::ok()
+impl PhiClient<'_> {
+    pub fn get(&self, port: Option<&str>) -> EResult<Value> {
+        let params = crate::value::to_value(PhiGetParams {
+            port: port.map(ToOwned::to_owned),
+        })?;
+        self.host.call(METHOD_PHI_GET, &params)
+    }
+    pub fn set(&self, port: &str, value: Value) -> EResult<()> {
+        let params = crate::value::to_value(PhiSetParams {
+            port: port.to_owned(),
+            value,
+        })?;
+        self.host.call(METHOD_PHI_SET, &params).map(|_| ())
     }
+}
 
-    pub unsafe fn set_name(name: &str) {
-        LOGGER.set_name(name);
+pub struct AuthModuleClient<'a> {
+    host: &'a ExtensionHost,
+}
+
+impl AuthModuleClient<'_> {
+    pub fn authenticate(&self, login: &str, password: &str, timeout: Duration) -> EResult<Value> {
+        let params = crate::value::to_value(AuthParams {
+            login: login.to_owned(),
+            password: password.into(),
+            timeout,
+        })?;
+        self.host.call(METHOD_AUTH, &params)
     }
 }
 
-pub mod auth {
+#[cfg(test)]
+mod tests {
+    use super::{Extension, ExtensionApi, ExtensionClass, ExtensionHost, Metadata, Phi};
+    use crate::value::{to_value, Value};
+    use crate::{EResult, Error, ErrorKind};
+    use std::collections::BTreeMap;
 
-    use serde::{Deserialize, Serialize};
-    use std::time::Duration;
+    struct TestPhi {
+        ports: BTreeMap<String, Value>,
+    }
 
-    /// Authentication request
-    ///
-    /// Timeout is in nanoseconds
-    #[derive(Debug, Serialize, Deserialize, Clone)]
-    pub struct Request {
-        #[serde(rename = "l")]
-        login: Option<String>,
-        #[serde(rename = "p")]
-        password: Option<String>,
-        #[serde(rename = "t")]
-        timeout: u64, // nanoseconds
-    }
-
-    impl Request {
-        pub fn new(login: String, password: String, timeout: Duration) -> Self {
-            Self {
-                login: Some(login),
-                password: Some(password),
-                timeout: timeout.as_nanos() as u64,
+    impl Extension for TestPhi {
+        const CLASS: ExtensionClass = ExtensionClass::Phi;
+        fn metadata() -> Metadata {
+            Metadata {
+                description: "test PHI".to_owned(),
+                ..<_>::default()
             }
         }
-        pub fn take_login(&mut self) -> String {
-            self.login.take().unwrap()
-        }
-        pub fn take_password(&mut self) -> String {
-            self.password.take().unwrap()
+        fn init(config: Value) -> EResult<Self> {
+            Ok(Self {
+                ports: config.deserialize_into().map_err(Error::invalid_data)?,
+            })
         }
-        pub fn timeout_as_duration(&self) -> Duration {
-            Duration::from_nanos(self.timeout)
+    }
+
+    impl Phi for TestPhi {
+        fn get(&mut self, port: Option<&str>) -> EResult<Value> {
+            if let Some(port) = port {
+                self.ports
+                    .get(port)
+                    .cloned()
+                    .ok_or_else(|| Error::not_found(port))
+            } else {
+                Ok(to_value(&self.ports)?)
+            }
         }
-        pub fn timeout_as_nanos(&self) -> u64 {
-            self.timeout
+        fn set(&mut self, port: &str, value: Value) -> EResult<()> {
+            assert_ne!(port, "panic");
+            self.ports.insert(port.to_owned(), value);
+            Ok(())
         }
     }
+
+    crate::ext_phi!(TestPhi);
+
+    #[test]
+    fn test_ext() {
+        // safety: the functions are exported by the macro
+        let host = unsafe {
+            ExtensionHost::from_api(ExtensionApi {
+                comm_version: eva_ext_comm_version,
+                class: eva_ext_class,
+                metadata: eva_ext_metadata,
+                set_logger: eva_ext_set_logger,
+                init: eva_ext_init,
+                call: eva_ext_call,
+            })
+        }
+        .unwrap();
+        assert_eq!(host.class(), ExtensionClass::Phi);
+        assert_eq!(host.metadata().description, "test PHI");
+        assert!(host.auth_module().is_err());
+        let phi = host.phi().unwrap();
+        assert_eq!(phi.get(Some("1")).unwrap_err().kind(), ErrorKind::NotReady);
+        let config: BTreeMap<&str, u8> = [("1", 10)].into();
+        host.init(&to_value(config).unwrap()).unwrap();
+        assert_eq!(phi.get(Some("1")).unwrap(), Value::U8(10));
+        phi.set("2", Value::String("x".to_owned())).unwrap();
+        assert_eq!(phi.get(Some("2")).unwrap(), Value::String("x".to_owned()));
+        assert_eq!(
+            phi.get(Some("3")).unwrap_err().kind(),
+            ErrorKind::ResourceNotFound
+        );
+        assert!(!host.is_poisoned());
+        assert_eq!(
+            phi.set("panic", Value::Unit).unwrap_err().kind(),
+            ErrorKind::CoreError
+        );
+        assert!(host.is_poisoned());
+        assert!(phi.get(None).is_err());
+    }
 }
@@ -0,0 +1,273 @@
+//! Stable C ABI for drivers, written in C/C++
+//!
+//! The functions are exported when the crate is linked into a `cdylib` or `staticlib`, the
+//! matching declarations are provided in `include/eva_common.h`.
+//!
+//! Conventions:
+//!
+//! * all strings are UTF-8, passed as pointer + length, output strings are not NUL-terminated
+//!
+//! * output is written into caller buffers. A non-negative return value is the number of bytes
+//!   required. If it is greater than the buffer length, nothing is written and the call should
+//!   be repeated with a larger buffer (a NULL buffer with zero length can be used to query the
+//!   size)
+//!
+//! * a negative return value is an EVA error code (`ERR_CODE_*`)
+use crate::value::Value;
+use crate::{EResult, Error, ErrorKind, ItemKind, OID};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::slice;
+
+/// The current ABI version
+pub const EVA_FFI_VERSION: u16 = 1;
+/// The oldest ABI version, still supported
+pub const EVA_FFI_VERSION_MIN: u16 = 1;
+
+fn ffi_call<F>(f: F) -> isize
+where
+    F: FnOnce() -> EResult<isize>,
+{
+    match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(v)) => v,
+        Ok(Err(e)) => e.kind() as isize,
+        Err(_) => ErrorKind::CoreError as isize,
+    }
+}
+
+unsafe fn input<'a>(data: *const u8, len: usize) -> EResult<&'a [u8]> {
+    if len == 0 {
+        Ok(&[])
+    } else if data.is_null() {
+        Err(Error::new0(ErrorKind::InvalidParameter))
+    } else {
+        Ok(slice::from_raw_parts(data, len))
+    }
+}
+
+unsafe fn input_str<'a>(data: *const u8, len: usize) -> EResult<&'a str> {
+    Ok(std::str::from_utf8(input(data, len)?)?)
+}
+
+unsafe fn output(data: &[u8], buf: *mut u8, buf_len: usize) -> EResult<isize> {
+    let len = isize::try_from(data.len())?;
+    if data.len() <= buf_len && !data.is_empty() {
+        if buf.is_null() {
+            return Err(Error::new0(ErrorKind::InvalidParameter));
+        }
+        buf.copy_from_nonoverlapping(data.as_ptr(), data.len());
+    }
+    Ok(len)
+}
+
+/// Returns the ABI version of the library
+#[no_mangle]
+pub extern "C" fn eva_ffi_version() -> u16 {
+    EVA_FFI_VERSION
+}
+
+/// Negotiates the ABI version: a driver passes the max version it supports and gets the version
+/// to use or `ERR_CODE_UNSUPPORTED`
+#[no_mangle]
+pub extern "C" fn eva_ffi_negotiate(max_version: u16) -> i32 {
+    if max_version < EVA_FFI_VERSION_MIN {
+        i32::from(ErrorKind::Unsupported as i16)
+    } else {
+        i32::from(max_version.min(EVA_FFI_VERSION))
+    }
+}
+
+/// Parses an OID, writes its canonical form into the buffer and the item kind code into `kind`
+/// (if not NULL)
+///
+/// # Safety
+///
+/// The pointers must be either NULL or valid for the specified lengths
+#[no_mangle]
+pub unsafe extern "C" fn eva_oid_parse(
+    s: *const u8,
+    s_len: usize,
+    kind: *mut u16,
+    buf: *mut u8,
+    buf_len: usize,
+) -> isize {
+    ffi_call(|| {
+        let oid: OID = input_str(s, s_len)?.parse()?;
+        if !kind.is_null() {
+            *kind = oid.kind() as u16;
+        }
+        output(oid.as_str().as_bytes(), buf, buf_len)
+    })
+}
+
+/// Formats an OID from the item kind code and the full item id (`group/id`)
+///
+/// # Safety
+///
+/// The pointers must be either NULL or valid for the specified lengths
+#[no_mangle]
+pub unsafe extern "C" fn eva_oid_format(
+    kind: u16,
+    full_id: *const u8,
+    full_id_len: usize,
+    buf: *mut u8,
+    buf_len: usize,
+) -> isize {
+    ffi_call(|| {
+        let oid = OID::new0(ItemKind::try_from(kind)?, input_str(full_id, full_id_len)?)?;
+        output(oid.as_str().as_bytes(), buf, buf_len)
+    })
+}
+
+/// Converts an OID into its path form (`kind/group/id`)
+///
+/// # Safety
+///
+/// The pointers must be either NULL or valid for the specified lengths
+#[no_mangle]
+pub unsafe extern "C" fn eva_oid_to_path(
+    s: *const u8,
+    s_len: usize,
+    buf: *mut u8,
+    buf_len: usize,
+) -> isize {
+    ffi_call(|| {
+        let oid: OID = input_str(s, s_len)?.parse()?;
+        output(oid.as_path().as_bytes(), buf, buf_len)
+    })
+}
+
+/// Writes the error description for the error code
+///
+/// # Safety
+///
+/// The buffer must be either NULL or valid for the specified length
+#[no_mangle]
+pub unsafe extern "C" fn eva_error_message(code: i16, buf: *mut u8, buf_len: usize) -> isize {
+    ffi_call(|| output(ErrorKind::from(code).to_string().as_bytes(), buf, buf_len))
+}
+
+/// Packs a JSON document into MessagePack, as used by the bus
+///
+/// # Safety
+///
+/// The pointers must be either NULL or valid for the specified lengths
+#[no_mangle]
+pub unsafe extern "C" fn eva_pack_json(
+    json: *const u8,
+    json_len: usize,
+    buf: *mut u8,
+    buf_len: usize,
+) -> isize {
+    ffi_call(|| {
+        let value = Value::from_json_slice(input(json, json_len)?)?;
+        output(&crate::payload::pack(&value)?, buf, buf_len)
+    })
+}
+
+/// Unpacks a MessagePack payload into a JSON document
+///
+/// # Safety
+///
+/// The pointers must be either NULL or valid for the specified lengths
+#[no_mangle]
+pub unsafe extern "C" fn eva_unpack_json(
+    data: *const u8,
+    data_len: usize,
+    buf: *mut u8,
+    buf_len: usize,
+) -> isize {
+    ffi_call(|| {
+        let value: Value = crate::payload::unpack(input(data, data_len)?)?;
+        output(&serde_json::to_vec(&value)?, buf, buf_len)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ERR_CODE_INVALID_DATA, ERR_CODE_NOT_FOUND, ERR_CODE_UNSUPPORTED};
+    use std::collections::BTreeMap;
+
+    fn header_defines() -> BTreeMap<&'static str, i64> {
+        include_str!("../include/eva_common.h")
+            .lines()
+            .filter_map(|l| {
+                let mut sp = l.strip_prefix("#define ")?.split_whitespace();
+                let name = sp.next()?;
+                let value = sp.next()?.trim_matches(|c| c == '(' || c == ')');
+                Some((name, value.parse().ok()?))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_ffi_header() {
+        let defines = header_defines();
+        assert_eq!(defines["EVA_FFI_VERSION"], i64::from(EVA_FFI_VERSION));
+        assert_eq!(
+            defines["EVA_FFI_VERSION_MIN"],
+            i64::from(EVA_FFI_VERSION_MIN)
+        );
+        for kind in ItemKind::ALL {
+            let name = format!("EVA_ITEM_KIND_{}", kind.as_str().to_uppercase());
+            assert_eq!(defines[name.as_str()], kind as i64, "{}", name);
+        }
+        let mut errors = 0;
+        for (name, value) in &defines {
+            if name.starts_with("EVA_ERR_") {
+                let code = i16::try_from(*value).unwrap();
+                assert_eq!(ErrorKind::from(code) as i16, code, "{}", name);
+                errors += 1;
+            }
+        }
+        assert_eq!(errors, 29);
+    }
+
+    #[test]
+    fn test_ffi() {
+        assert_eq!(eva_ffi_negotiate(5), 1);
+        assert_eq!(eva_ffi_negotiate(0), i32::from(ERR_CODE_UNSUPPORTED));
+        let mut buf = [0u8; 64];
+        let mut kind = 0u16;
+        let s = "sensor:tests/s1";
+        unsafe {
+            assert_eq!(
+                eva_oid_parse(
+                    s.as_ptr(),
+                    s.len(),
+                    std::ptr::addr_of_mut!(kind),
+                    std::ptr::null_mut(),
+                    0
+                ),
+                15
+            );
+            let n = eva_oid_to_path(s.as_ptr(), s.len(), buf.as_mut_ptr(), buf.len());
+            assert_eq!(&buf[..usize::try_from(n).unwrap()], b"sensor/tests/s1");
+            assert_eq!(kind, ItemKind::Sensor as u16);
+            let n = eva_oid_format(kind, b"a/b".as_ptr(), 3, buf.as_mut_ptr(), buf.len());
+            assert_eq!(&buf[..usize::try_from(n).unwrap()], b"sensor:a/b");
+            assert_eq!(
+                eva_oid_parse(
+                    b"x".as_ptr(),
+                    1,
+                    std::ptr::addr_of_mut!(kind),
+                    buf.as_mut_ptr(),
+                    buf.len()
+                ),
+                isize::from(ERR_CODE_INVALID_DATA)
+            );
+            let n = eva_error_message(ERR_CODE_NOT_FOUND, buf.as_mut_ptr(), buf.len());
+            assert_eq!(&buf[..usize::try_from(n).unwrap()], b"Resource not found");
+            let json = br#"{"status":1,"value":[1,"x"]}"#;
+            let n = eva_pack_json(json.as_ptr(), json.len(), buf.as_mut_ptr(), buf.len());
+            let packed = buf[..usize::try_from(n).unwrap()].to_vec();
+            // the buffer is too small, the required size is returned
+            assert_eq!(
+                eva_unpack_json(packed.as_ptr(), packed.len(), buf.as_mut_ptr(), 2),
+                isize::try_from(json.len()).unwrap()
+            );
+            let n = eva_unpack_json(packed.as_ptr(), packed.len(), buf.as_mut_ptr(), buf.len());
+            assert_eq!(&buf[..usize::try_from(n).unwrap()], json);
+        }
+    }
+}
@@ -0,0 +1,360 @@
+//! Chunked file transfer payloads, used to send files over the bus within frame size limits
+//!
+//! A transfer consists of a [`Begin`] frame, one or more [`Chunk`] frames and an [`End`] frame.
+//! The receiver writes chunks into a temporary file, which is verified and moved to the target
+//! on completion.
+use crate::tools::{deserialize_bytes, serialize_bytes};
+use crate::{EResult, Error};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+
+/// Default chunk size, fits the default bus buffers
+pub const DEFAULT_CHUNK_SIZE: usize = 65536;
+/// Max transfer id length
+pub const MAX_ID_LEN: usize = 64;
+
+/// Permission bits, applied to received files (setuid, setgid and sticky bits are dropped)
+#[cfg(unix)]
+const PERMISSION_MASK: u32 = 0o777;
+
+/// Transfer start
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct Begin {
+    pub id: String,
+    /// Target path, relative to the receiver base directory
+    pub target: String,
+    pub size: u64,
+    /// SHA256 of the file content (hex)
+    pub sha256: String,
+    /// Unix permissions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<u32>,
+}
+
+/// File data chunk
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct Chunk {
+    pub id: String,
+    pub offset: u64,
+    #[serde(
+        serialize_with = "serialize_bytes",
+        deserialize_with = "deserialize_bytes"
+    )]
+    pub data: Vec<u8>,
+}
+
+/// Transfer end
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct End {
+    pub id: String,
+    pub chunks: u64,
+}
+
+/// File transfer frame payload
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum FileTransfer {
+    Begin(Begin),
+    Chunk(Chunk),
+    End(End),
+}
+
+impl FileTransfer {
+    pub fn id(&self) -> &str {
+        match self {
+            FileTransfer::Begin(v) => &v.id,
+            FileTransfer::Chunk(v) => &v.id,
+            FileTransfer::End(v) => &v.id,
+        }
+    }
+}
+
+#[inline]
+fn sha256_hex(data: &[u8]) -> String {
+    hex::encode(Sha256::digest(data))
+}
+
+/// Splits the file content into transfer frames
+///
+/// # Panics
+///
+/// Will panic if chunk_size is zero
+pub fn prepare(
+    id: &str,
+    target: &str,
+    data: &[u8],
+    mode: Option<u32>,
+    chunk_size: usize,
+) -> Vec<FileTransfer> {
+    assert!(chunk_size > 0, "chunk size must be positive");
+    let mut result = Vec::with_capacity(data.len() / chunk_size + 3);
+    result.push(FileTransfer::Begin(Begin {
+        id: id.to_owned(),
+        target: target.to_owned(),
+        size: data.len() as u64,
+        sha256: sha256_hex(data),
+        mode,
+    }));
+    let mut offset = 0;
+    for chunk in data.chunks(chunk_size) {
+        result.push(FileTransfer::Chunk(Chunk {
+            id: id.to_owned(),
+            offset,
+            data: chunk.to_vec(),
+        }));
+        offset += chunk.len() as u64;
+    }
+    result.push(FileTransfer::End(End {
+        id: id.to_owned(),
+        chunks: (result.len() - 1) as u64,
+    }));
+    result
+}
+
+/// Reads a local file and splits it into transfer frames, the file permissions are preserved
+///
+/// # Errors
+///
+/// Will return `Err` if the file can not be read
+pub fn prepare_file(
+    id: &str,
+    path: &Path,
+    target: &str,
+    chunk_size: usize,
+) -> EResult<Vec<FileTransfer>> {
+    let data = fs::read(path)?;
+    #[cfg(unix)]
+    let mode = {
+        use std::os::unix::fs::PermissionsExt;
+        Some(fs::metadata(path)?.permissions().mode() & PERMISSION_MASK)
+    };
+    #[cfg(not(unix))]
+    let mode = None;
+    Ok(prepare(id, target, &data, mode, chunk_size))
+}
+
+/// Transfer ids are used in temporary file names, so only plain tokens are allowed
+fn check_id(id: &str) -> EResult<()> {
+    if id.is_empty()
+        || id.len() > MAX_ID_LEN
+        || !id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        return Err(Error::invalid_params(format!(
+            "invalid transfer id: {}",
+            id
+        )));
+    }
+    Ok(())
+}
+
+fn safe_target(base: &Path, target: &str) -> EResult<PathBuf> {
+    let target = Path::new(target);
+    if target
+        .components()
+        .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir))
+    {
+        return Err(Error::access(format!(
+            "invalid transfer target: {}",
+            target.display()
+        )));
+    }
+    Ok(base.join(target))
+}
+
+/// Reassembles a file from transfer frames. The data is written to a temporary file next to the
+/// target, which is removed if the transfer is not finished
+pub struct Receiver {
+    begin: Begin,
+    target: PathBuf,
+    tmp_path: PathBuf,
+    file: Option<fs::File>,
+    hasher: Sha256,
+    received: u64,
+    chunks: u64,
+    done: bool,
+}
+
+impl Receiver {
+    /// Starts receiving a file into the base directory
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the transfer id is not a plain token (letters, digits, "_" and "-"),
+    /// the target is outside of the base directory or the temporary file can not be created
+    pub fn new(begin: Begin, base: &Path) -> EResult<Self> {
+        check_id(&begin.id)?;
+        let target = safe_target(base, &begin.target)?;
+        let file_name = target
+            .file_name()
+            .ok_or_else(|| Error::invalid_params("transfer target has no file name"))?
+            .to_string_lossy()
+            .into_owned();
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let tmp_path = target.with_file_name(format!(".{}.{}.part", file_name, begin.id));
+        let file = fs::File::create(&tmp_path)?;
+        Ok(Self {
+            begin,
+            target,
+            tmp_path,
+            file: Some(file),
+            hasher: Sha256::new(),
+            received: 0,
+            chunks: 0,
+            done: false,
+        })
+    }
+    #[inline]
+    pub fn id(&self) -> &str {
+        &self.begin.id
+    }
+    #[inline]
+    pub fn received(&self) -> u64 {
+        self.received
+    }
+    /// Writes a chunk, chunks must be received in order
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the chunk does not belong to the transfer, is out of order or
+    /// exceeds the declared size
+    pub fn write_chunk(&mut self, chunk: &Chunk) -> EResult<()> {
+        if chunk.id != self.begin.id {
+            return Err(Error::invalid_data(format!(
+                "chunk transfer id mismatch: {}",
+                chunk.id
+            )));
+        }
+        if chunk.offset != self.received {
+            return Err(Error::invalid_data(format!(
+                "chunk offset mismatch: {}, expected: {}",
+                chunk.offset, self.received
+            )));
+        }
+        if self.received + chunk.data.len() as u64 > self.begin.size {
+            return Err(Error::invalid_data("chunk exceeds the declared file size"));
+        }
+        let file = self
+            .file
+            .as_mut()
+            .ok_or_else(|| Error::core("transfer is already finished"))?;
+        file.write_all(&chunk.data)?;
+        self.hasher.update(&chunk.data);
+        self.received += chunk.data.len() as u64;
+        self.chunks += 1;
+        Ok(())
+    }
+    /// Verifies the received file and moves it to the target, returns the target path
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the file size, chunk count or checksum do not match
+    pub fn finish(mut self, end: &End) -> EResult<PathBuf> {
+        if end.id != self.begin.id {
+            return Err(Error::invalid_data(format!(
+                "end transfer id mismatch: {}",
+                end.id
+            )));
+        }
+        let mut file = self
+            .file
+            .take()
+            .ok_or_else(|| Error::core("transfer is already finished"))?;
+        if end.chunks != self.chunks || self.received != self.begin.size {
+            return Err(Error::invalid_data(format!(
+                "incomplete transfer: {}/{} bytes, {}/{} chunks",
+                self.received, self.begin.size, self.chunks, end.chunks
+            )));
+        }
+        let sha256 = hex::encode(std::mem::replace(&mut self.hasher, Sha256::new()).finalize());
+        if !sha256.eq_ignore_ascii_case(&self.begin.sha256) {
+            return Err(Error::invalid_data("transfer checksum mismatch"));
+        }
+        file.flush()?;
+        file.sync_all()?;
+        drop(file);
+        #[cfg(unix)]
+        if let Some(mode) = self.begin.mode {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(
+                &self.tmp_path,
+                fs::Permissions::from_mode(mode & PERMISSION_MASK),
+            )?;
+        }
+        fs::rename(&self.tmp_path, &self.target)?;
+        self.done = true;
+        Ok(self.target.clone())
+    }
+}
+
+impl Drop for Receiver {
+    fn drop(&mut self) {
+        if !self.done {
+            let _ = fs::remove_file(&self.tmp_path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{prepare, FileTransfer, Receiver};
+    use crate::payload::{pack, unpack};
+
+    #[test]
+    fn test_transfer() {
+        let base = std::env::temp_dir().join(format!("eva_ft_test_{}", std::process::id()));
+        let data: Vec<u8> = (0..1000_u32).map(|v| (v % 251) as u8).collect();
+        let frames = prepare("t1", "sub/test.bin", &data, Some(0o600), 300);
+        assert_eq!(frames.len(), 6);
+        let mut receiver = None;
+        let mut target = None;
+        for frame in frames {
+            let frame: FileTransfer = unpack(&pack(&frame).unwrap()).unwrap();
+            match frame {
+                FileTransfer::Begin(b) => receiver = Some(Receiver::new(b, &base).unwrap()),
+                FileTransfer::Chunk(c) => receiver.as_mut().unwrap().write_chunk(&c).unwrap(),
+                FileTransfer::End(e) => target = Some(receiver.take().unwrap().finish(&e).unwrap()),
+            }
+        }
+        let target = target.unwrap();
+        assert_eq!(std::fs::read(&target).unwrap(), data);
+        // corrupted transfer
+        let mut frames = prepare("t2", "sub/test2.bin", &data, None, 300);
+        if let FileTransfer::Chunk(ref mut c) = frames[1] {
+            c.data[0] ^= 0xff;
+        }
+        let FileTransfer::Begin(ref b) = frames[0] else {
+            panic!()
+        };
+        let mut receiver = Receiver::new(b.clone(), &base).unwrap();
+        for frame in &frames[1..frames.len() - 1] {
+            let FileTransfer::Chunk(c) = frame else {
+                panic!()
+            };
+            receiver.write_chunk(c).unwrap();
+        }
+        let FileTransfer::End(ref e) = frames[frames.len() - 1] else {
+            panic!()
+        };
+        assert!(receiver.finish(e).is_err());
+        assert_eq!(std::fs::read_dir(base.join("sub")).unwrap().count(), 1);
+        let mut b = b.clone();
+        b.target = "../x".to_owned();
+        assert!(Receiver::new(b.clone(), &base).is_err());
+        b.target = "sub/test3.bin".to_owned();
+        for id in ["../../x", "a/b", "", "t.1"] {
+            b.id = id.to_owned();
+            assert!(Receiver::new(b.clone(), &base).is_err());
+        }
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+}
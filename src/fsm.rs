@@ -0,0 +1,373 @@
+//! Typed finite state machines for service workflows (device connection lifecycles, batch jobs,
+//! alarm states etc.)
+//!
+//! A [`Definition`] lists states, guarded transitions and entry/exit hooks and is shared between
+//! [`StateMachine`] instances, which keep the current state only. The current state can be
+//! saved/restored with [`Snapshot`] and the definition can be exported as a Mermaid diagram.
+//!
+//! ```rust
+//! use eva_common::fsm::{Definition, StateMachine};
+//! use std::sync::Arc;
+//!
+//! #[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+//! enum Conn { Offline, Connecting, Online }
+//! #[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+//! enum Ev { Connect, Connected, Lost }
+//!
+//! let def = Arc::new(
+//!     Definition::<Conn, Ev, u32>::new(Conn::Offline)
+//!         .transition(Conn::Offline, Ev::Connect, Conn::Connecting)
+//!         .guarded(Conn::Connecting, Ev::Connected, Conn::Online, |attempts| *attempts < 3)
+//!         .transition(Conn::Online, Ev::Lost, Conn::Offline)
+//!         .on_enter(Conn::Connecting, |attempts, _| *attempts += 1),
+//! );
+//! let mut conn = StateMachine::new(def);
+//! let mut attempts = 0;
+//! conn.handle(Ev::Connect, &mut attempts).unwrap();
+//! conn.handle(Ev::Connected, &mut attempts).unwrap();
+//! assert_eq!(conn.state(), Conn::Online);
+//! assert!(conn.handle(Ev::Connect, &mut attempts).is_err());
+//! ```
+use crate::{EResult, Error};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fmt::Write as _;
+use std::sync::Arc;
+
+type Guard<C> = Box<dyn Fn(&C) -> bool + Send + Sync>;
+/// Hook arguments: the context and the other state (the previous one for entry hooks, the next
+/// one for exit hooks)
+type Hook<S, C> = Box<dyn Fn(&mut C, S) + Send + Sync>;
+
+struct Transition<S, C> {
+    to: S,
+    guard: Option<Guard<C>>,
+}
+
+impl<S, C> Transition<S, C> {
+    fn is_allowed(&self, ctx: &C) -> bool {
+        match self.guard {
+            Some(ref guard) => guard(ctx),
+            None => true,
+        }
+    }
+}
+
+/// State machine definition
+pub struct Definition<S, E, C = ()> {
+    initial: S,
+    states: BTreeSet<S>,
+    transitions: BTreeMap<(S, E), Transition<S, C>>,
+    on_enter: BTreeMap<S, Vec<Hook<S, C>>>,
+    on_exit: BTreeMap<S, Vec<Hook<S, C>>>,
+}
+
+impl<S, E, C> Definition<S, E, C>
+where
+    S: Copy + Ord + fmt::Debug,
+    E: Copy + Ord + fmt::Debug,
+{
+    pub fn new(initial: S) -> Self {
+        Self {
+            initial,
+            states: [initial].into(),
+            transitions: BTreeMap::new(),
+            on_enter: BTreeMap::new(),
+            on_exit: BTreeMap::new(),
+        }
+    }
+    /// Adds a state without transitions (e.g. a terminal one, entered with
+    /// [`StateMachine::force()`])
+    pub fn state(mut self, state: S) -> Self {
+        self.states.insert(state);
+        self
+    }
+    /// Adds a transition, replacing an existing one for the same state and event
+    pub fn transition(self, from: S, event: E, to: S) -> Self {
+        self.add_transition(from, event, to, None)
+    }
+    /// Adds a transition, which is allowed only if the guard returns true
+    pub fn guarded<G>(self, from: S, event: E, to: S, guard: G) -> Self
+    where
+        G: Fn(&C) -> bool + Send + Sync + 'static,
+    {
+        self.add_transition(from, event, to, Some(Box::new(guard)))
+    }
+    fn add_transition(mut self, from: S, event: E, to: S, guard: Option<Guard<C>>) -> Self {
+        self.states.insert(from);
+        self.states.insert(to);
+        self.transitions
+            .insert((from, event), Transition { to, guard });
+        self
+    }
+    /// Adds a hook, called when the state is entered (the previous state is passed)
+    pub fn on_enter<H>(mut self, state: S, hook: H) -> Self
+    where
+        H: Fn(&mut C, S) + Send + Sync + 'static,
+    {
+        self.states.insert(state);
+        self.on_enter.entry(state).or_default().push(Box::new(hook));
+        self
+    }
+    /// Adds a hook, called when the state is left (the next state is passed)
+    pub fn on_exit<H>(mut self, state: S, hook: H) -> Self
+    where
+        H: Fn(&mut C, S) + Send + Sync + 'static,
+    {
+        self.states.insert(state);
+        self.on_exit.entry(state).or_default().push(Box::new(hook));
+        self
+    }
+    #[inline]
+    pub fn initial(&self) -> S {
+        self.initial
+    }
+    #[inline]
+    pub fn states(&self) -> impl Iterator<Item = S> + '_ {
+        self.states.iter().copied()
+    }
+    /// Events, accepted in the state (guards are not checked)
+    pub fn events(&self, state: S) -> impl Iterator<Item = E> + '_ {
+        self.transitions
+            .keys()
+            .filter(move |(from, _)| *from == state)
+            .map(|(_, event)| *event)
+    }
+    /// Exports the definition as a Mermaid state diagram. States and events are formatted with
+    /// [`fmt::Debug`], guarded transitions are marked with "[guard]"
+    pub fn to_mermaid(&self) -> String {
+        let mut out = "stateDiagram-v2\n".to_owned();
+        let _ = writeln!(out, "    [*] --> {:?}", self.initial);
+        for ((from, event), transition) in &self.transitions {
+            let _ = writeln!(
+                out,
+                "    {:?} --> {:?}: {:?}{}",
+                from,
+                transition.to,
+                event,
+                if transition.guard.is_some() {
+                    " [guard]"
+                } else {
+                    ""
+                }
+            );
+        }
+        for state in &self.states {
+            if !self.transitions.keys().any(|(from, _)| from == state)
+                && self.transitions.values().any(|t| t.to == *state)
+            {
+                let _ = writeln!(out, "    {:?} --> [*]", state);
+            }
+        }
+        out
+    }
+}
+
+/// Current state of a machine, can be stored and restored
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields, bound(deserialize = "S: Deserialize<'de>"))]
+pub struct Snapshot<S> {
+    pub state: S,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous: Option<S>,
+    /// Number of transitions made
+    #[serde(default)]
+    pub transitions: u64,
+}
+
+/// State machine instance
+pub struct StateMachine<S, E, C = ()> {
+    definition: Arc<Definition<S, E, C>>,
+    state: S,
+    previous: Option<S>,
+    transitions: u64,
+}
+
+impl<S, E, C> StateMachine<S, E, C>
+where
+    S: Copy + Ord + fmt::Debug,
+    E: Copy + Ord + fmt::Debug,
+{
+    /// Creates a machine in the initial state (entry hooks are not called)
+    pub fn new(definition: Arc<Definition<S, E, C>>) -> Self {
+        Self {
+            state: definition.initial,
+            definition,
+            previous: None,
+            transitions: 0,
+        }
+    }
+    /// Restores a machine from a snapshot (hooks are not called)
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the snapshot state is not defined
+    pub fn restore(definition: Arc<Definition<S, E, C>>, snapshot: Snapshot<S>) -> EResult<Self> {
+        if !definition.states.contains(&snapshot.state) {
+            return Err(Error::invalid_data(format!(
+                "unknown state: {:?}",
+                snapshot.state
+            )));
+        }
+        Ok(Self {
+            definition,
+            state: snapshot.state,
+            previous: snapshot.previous,
+            transitions: snapshot.transitions,
+        })
+    }
+    #[inline]
+    pub fn state(&self) -> S {
+        self.state
+    }
+    #[inline]
+    pub fn previous(&self) -> Option<S> {
+        self.previous
+    }
+    #[inline]
+    pub fn definition(&self) -> &Arc<Definition<S, E, C>> {
+        &self.definition
+    }
+    pub fn snapshot(&self) -> Snapshot<S> {
+        Snapshot {
+            state: self.state,
+            previous: self.previous,
+            transitions: self.transitions,
+        }
+    }
+    /// Returns true if the event is accepted in the current state
+    pub fn can(&self, event: E, ctx: &C) -> bool {
+        self.definition
+            .transitions
+            .get(&(self.state, event))
+            .is_some_and(|t| t.is_allowed(ctx))
+    }
+    /// Handles an event: checks the transition guard, calls exit hooks of the current state and
+    /// entry hooks of the new one. Returns the new state
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the transition is not defined or rejected by the guard, the state is
+    /// not changed in this case
+    pub fn handle(&mut self, event: E, ctx: &mut C) -> EResult<S> {
+        let Some(transition) = self.definition.transitions.get(&(self.state, event)) else {
+            return Err(Error::invalid_params(format!(
+                "invalid transition: {:?} in state {:?}",
+                event, self.state
+            )));
+        };
+        if !transition.is_allowed(ctx) {
+            return Err(Error::invalid_params(format!(
+                "transition rejected: {:?} in state {:?}",
+                event, self.state
+            )));
+        }
+        let to = transition.to;
+        self.switch(to, ctx);
+        Ok(to)
+    }
+    /// Switches to the state, bypassing transitions (e.g. on fatal errors), hooks are called
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the state is not defined
+    pub fn force(&mut self, state: S, ctx: &mut C) -> EResult<()> {
+        if !self.definition.states.contains(&state) {
+            return Err(Error::invalid_params(format!("unknown state: {:?}", state)));
+        }
+        self.switch(state, ctx);
+        Ok(())
+    }
+    fn switch(&mut self, to: S, ctx: &mut C) {
+        let from = self.state;
+        let definition = self.definition.clone();
+        for hook in definition.on_exit.get(&from).into_iter().flatten() {
+            hook(ctx, to);
+        }
+        self.previous = Some(from);
+        self.state = to;
+        self.transitions += 1;
+        for hook in definition.on_enter.get(&to).into_iter().flatten() {
+            hook(ctx, from);
+        }
+    }
+}
+
+impl<S, E, C> fmt::Debug for StateMachine<S, E, C>
+where
+    S: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("StateMachine")
+            .field("state", &self.state)
+            .field("previous", &self.previous)
+            .field("transitions", &self.transitions)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Definition, Snapshot, StateMachine};
+    use serde::{Deserialize, Serialize};
+    use std::sync::Arc;
+
+    #[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Serialize, Deserialize)]
+    enum Job {
+        Queued,
+        Running,
+        Done,
+        Failed,
+    }
+
+    #[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+    enum Ev {
+        Start,
+        Finish,
+        Retry,
+    }
+
+    #[test]
+    fn test_state_machine() {
+        let def = Arc::new(
+            Definition::<Job, Ev, Vec<String>>::new(Job::Queued)
+                .transition(Job::Queued, Ev::Start, Job::Running)
+                .transition(Job::Running, Ev::Finish, Job::Done)
+                .guarded(Job::Failed, Ev::Retry, Job::Queued, |log| log.len() < 10)
+                .on_enter(Job::Running, |log, from| {
+                    log.push(format!("run <- {:?}", from));
+                })
+                .on_exit(Job::Running, |log, to| log.push(format!("run -> {:?}", to))),
+        );
+        let mut log = Vec::new();
+        let mut job = StateMachine::new(def.clone());
+        assert!(job.can(Ev::Start, &log));
+        assert!(!job.can(Ev::Finish, &log));
+        assert!(job.handle(Ev::Finish, &mut log).is_err());
+        assert_eq!(job.state(), Job::Queued);
+        assert_eq!(job.handle(Ev::Start, &mut log).unwrap(), Job::Running);
+        job.force(Job::Failed, &mut log).unwrap();
+        assert_eq!(log, ["run <- Queued", "run -> Failed"]);
+        let snapshot = job.snapshot();
+        let json = serde_json::to_string(&snapshot).unwrap();
+        assert_eq!(
+            json,
+            r#"{"state":"Failed","previous":"Running","transitions":2}"#
+        );
+        let snapshot: Snapshot<Job> = serde_json::from_str(&json).unwrap();
+        let mut job = StateMachine::restore(def.clone(), snapshot).unwrap();
+        log.resize(10, String::new());
+        assert!(!job.can(Ev::Retry, &log));
+        assert!(job.handle(Ev::Retry, &mut log).is_err());
+        log.clear();
+        assert_eq!(job.handle(Ev::Retry, &mut log).unwrap(), Job::Queued);
+        assert!(log.is_empty());
+        assert_eq!(def.events(Job::Running).collect::<Vec<_>>(), [Ev::Finish]);
+        assert_eq!(
+            def.to_mermaid(),
+            "stateDiagram-v2\n    [*] --> Queued\n    Queued --> Running: Start\n    \
+            Running --> Done: Finish\n    Failed --> Queued: Retry [guard]\n    Done --> [*]\n"
+        );
+    }
+}
@@ -0,0 +1,84 @@
+//! Fuzzing entry points for parsers of untrusted input
+//!
+//! Every function accepts raw bytes, discards the parse result and must never panic. Used by
+//! cargo-fuzz targets, e.g. `fuzz_target!(|data: &[u8]| eva_common::fuzz::parse_oid(data));`
+use crate::value::Value;
+use crate::OID;
+
+#[inline]
+fn as_str(data: &[u8]) -> Option<&str> {
+    core::str::from_utf8(data).ok()
+}
+
+pub fn parse_oid(data: &[u8]) {
+    if let Some(s) = as_str(data) {
+        let _ = s.parse::<OID>();
+        let _ = OID::from_path(s);
+    }
+}
+
+#[cfg(feature = "acl")]
+pub fn parse_oid_mask(data: &[u8]) {
+    if let Some(s) = as_str(data) {
+        let _ = s.parse::<crate::acl::OIDMask>();
+        let _ = crate::acl::OIDMask::from_path(s);
+    }
+}
+
+#[cfg(feature = "logic")]
+pub fn parse_range(data: &[u8]) {
+    if let Some(s) = as_str(data) {
+        let _ = s.parse::<crate::logic::Range>();
+    }
+}
+
+#[cfg(feature = "payload")]
+pub fn unpack_payload(data: &[u8]) {
+    let _ = crate::payload::unpack::<Value>(data);
+}
+
+/// The first line is the path, the rest is a JSON value to look up in
+pub fn jp_lookup(data: &[u8]) {
+    let Some(s) = as_str(data) else { return };
+    let (path, json) = s.split_once('\n').unwrap_or((s, ""));
+    let value: Value = serde_json::from_str(json).unwrap_or_default();
+    let _ = value.jp_lookup(path);
+    let mut value = value;
+    let _ = value.jp_insert(path, Value::Unit);
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_fuzz_inputs() {
+        let inputs: &[&[u8]] = &[
+            b"",
+            b":",
+            b"/",
+            b"sensor:",
+            b"+:",
+            b"4/\xc3\xbc.8}X }",
+            b"1<x=2",
+            b"x=",
+            b"<x<",
+            b"$.",
+            b"$.[]",
+            b"$..a[",
+            b"$.a[99999999999999999999]\n{\"a\":[1]}",
+            b"\xff\xfe\x00",
+            b"\x93\xcc",
+        ];
+        for input in inputs {
+            super::parse_oid(input);
+            #[cfg(feature = "acl")]
+            super::parse_oid_mask(input);
+            #[cfg(feature = "logic")]
+            super::parse_range(input);
+            #[cfg(feature = "payload")]
+            super::unpack_payload(input);
+            super::jp_lookup(input);
+        }
+        #[cfg(feature = "logic")]
+        assert!("1<x=2".parse::<crate::logic::Range>().is_err());
+    }
+}
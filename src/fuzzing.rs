@@ -0,0 +1,345 @@
+//! Property-based testing and fuzzing generators for core types
+//!
+//! Generated data always respects the type invariants: OIDs and OID masks are valid and
+//! parseable, floats are finite (so values are comparable after roundtrips), map keys are strings
+//! and nesting is limited, time nanoseconds are below one second.
+use crate::{ItemKind, OID};
+
+const ITEM_KINDS: [ItemKind; 7] = ItemKind::ALL;
+const MAX_GROUP_DEPTH: usize = 3;
+const MAX_VALUE_DEPTH: u32 = 3;
+const MAX_COLLECTION_SIZE: usize = 8;
+#[allow(dead_code)]
+const NANOS_MAX: u64 = 999_999_999;
+
+fn oid_from_parts(kind: ItemKind, group: &[String], id: &str) -> OID {
+    OID::new(kind, &group.join("/"), id).expect("generated OID must be valid")
+}
+
+/// Builds an OID mask string: kind (or "+"), path segments (a segment can be "+"), optional
+/// trailing "#"
+#[allow(dead_code)]
+fn oid_mask_string(kind: Option<ItemKind>, path: &[Option<String>], wildcard: bool) -> String {
+    let mut s = kind.map_or_else(|| "+".to_owned(), |k| k.to_string());
+    for (i, segment) in path.iter().enumerate() {
+        s.push(if i == 0 { ':' } else { '/' });
+        s.push_str(segment.as_deref().unwrap_or("+"));
+    }
+    if wildcard {
+        s.push(if path.is_empty() { ':' } else { '/' });
+        s.push('#');
+    } else if path.is_empty() {
+        s.push_str(":#");
+    }
+    s
+}
+
+#[cfg(feature = "proptest")]
+mod prop {
+    use super::{
+        oid_from_parts, ITEM_KINDS, MAX_COLLECTION_SIZE, MAX_GROUP_DEPTH, MAX_VALUE_DEPTH,
+    };
+    use crate::value::Value;
+    use crate::{ItemKind, OID};
+    use proptest::collection::{btree_map, vec};
+    use proptest::prelude::*;
+
+    const SEGMENT_REGEX: &str = "[a-zA-Z0-9_-]{1,12}";
+    const KEY_REGEX: &str = "[a-z0-9_]{1,8}";
+
+    impl Arbitrary for ItemKind {
+        type Parameters = ();
+        type Strategy = BoxedStrategy<Self>;
+
+        fn arbitrary_with((): ()) -> Self::Strategy {
+            proptest::sample::select(ITEM_KINDS.to_vec()).boxed()
+        }
+    }
+
+    impl Arbitrary for OID {
+        type Parameters = ();
+        type Strategy = BoxedStrategy<Self>;
+
+        fn arbitrary_with((): ()) -> Self::Strategy {
+            (
+                any::<ItemKind>(),
+                vec(SEGMENT_REGEX, 1..=MAX_GROUP_DEPTH),
+                SEGMENT_REGEX,
+            )
+                .prop_map(|(kind, group, id)| oid_from_parts(kind, &group, &id))
+                .boxed()
+        }
+    }
+
+    #[cfg(feature = "acl")]
+    impl Arbitrary for crate::acl::OIDMask {
+        type Parameters = ();
+        type Strategy = BoxedStrategy<Self>;
+
+        fn arbitrary_with((): ()) -> Self::Strategy {
+            (
+                proptest::option::of(any::<ItemKind>()),
+                vec(proptest::option::of(SEGMENT_REGEX), 0..=MAX_GROUP_DEPTH),
+                any::<bool>(),
+            )
+                .prop_map(|(kind, path, wildcard)| {
+                    super::oid_mask_string(kind, &path, wildcard)
+                        .parse()
+                        .expect("generated OID mask must be valid")
+                })
+                .boxed()
+        }
+    }
+
+    fn value_int() -> impl Strategy<Value = Value> {
+        prop_oneof![
+            any::<u8>().prop_map(Value::U8),
+            any::<u16>().prop_map(Value::U16),
+            any::<u32>().prop_map(Value::U32),
+            any::<u64>().prop_map(Value::U64),
+            any::<i8>().prop_map(Value::I8),
+            any::<i16>().prop_map(Value::I16),
+            any::<i32>().prop_map(Value::I32),
+            any::<i64>().prop_map(Value::I64),
+        ]
+    }
+
+    fn value_leaf() -> impl Strategy<Value = Value> {
+        prop_oneof![
+            Just(Value::Unit),
+            any::<bool>().prop_map(Value::Bool),
+            value_int(),
+            proptest::num::f32::NORMAL.prop_map(Value::F32),
+            proptest::num::f64::NORMAL.prop_map(Value::F64),
+            any::<String>().prop_map(Value::String),
+            vec(any::<u8>(), 0..MAX_COLLECTION_SIZE).prop_map(Value::Bytes),
+        ]
+    }
+
+    impl Arbitrary for Value {
+        type Parameters = ();
+        type Strategy = BoxedStrategy<Self>;
+
+        #[allow(clippy::cast_possible_truncation)]
+        fn arbitrary_with((): ()) -> Self::Strategy {
+            value_leaf()
+                .prop_recursive(
+                    MAX_VALUE_DEPTH,
+                    (MAX_COLLECTION_SIZE * MAX_COLLECTION_SIZE) as u32,
+                    MAX_COLLECTION_SIZE as u32,
+                    |inner| {
+                        prop_oneof![
+                            vec(inner.clone(), 0..MAX_COLLECTION_SIZE).prop_map(Value::Seq),
+                            btree_map(
+                                KEY_REGEX.prop_map(Value::String),
+                                inner,
+                                0..MAX_COLLECTION_SIZE
+                            )
+                            .prop_map(Value::Map),
+                        ]
+                    },
+                )
+                .boxed()
+        }
+    }
+
+    #[cfg(feature = "time")]
+    impl Arbitrary for crate::time::Time {
+        type Parameters = ();
+        type Strategy = BoxedStrategy<Self>;
+
+        fn arbitrary_with((): ()) -> Self::Strategy {
+            (0..=u64::from(u32::MAX), 0..=super::NANOS_MAX)
+                .prop_map(|(sec, nsec)| crate::time::Time::new(sec, nsec))
+                .boxed()
+        }
+    }
+
+    #[cfg(feature = "events")]
+    impl Arbitrary for crate::events::RawStateEventOwned {
+        type Parameters = ();
+        type Strategy = BoxedStrategy<Self>;
+
+        fn arbitrary_with((): ()) -> Self::Strategy {
+            use crate::events::{Force, RawStateEventOwned};
+            (
+                any::<i16>(),
+                proptest::option::of(any::<Value>()),
+                proptest::sample::select(vec![Force::None, Force::Update, Force::Full]),
+                proptest::option::of(0.0..4_102_444_800.0_f64),
+            )
+                .prop_map(|(status, value, force, t)| RawStateEventOwned {
+                    status,
+                    value: value.into(),
+                    force,
+                    t,
+                    ..RawStateEventOwned::default()
+                })
+                .boxed()
+        }
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+mod arb {
+    use super::{
+        oid_from_parts, ITEM_KINDS, MAX_COLLECTION_SIZE, MAX_GROUP_DEPTH, MAX_VALUE_DEPTH,
+    };
+    use crate::value::Value;
+    use crate::{ItemKind, OID};
+    use arbitrary::{Arbitrary, Result, Unstructured};
+    use std::collections::BTreeMap;
+
+    const SEGMENT_SYMBOLS: &[u8] =
+        b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789_-";
+
+    fn segment(u: &mut Unstructured<'_>) -> Result<String> {
+        let len = u.int_in_range(1..=12)?;
+        (0..len)
+            .map(|_| u.choose(SEGMENT_SYMBOLS).map(|c| char::from(*c)))
+            .collect()
+    }
+
+    fn key(u: &mut Unstructured<'_>) -> Result<Value> {
+        Ok(Value::String(segment(u)?))
+    }
+
+    fn finite(v: f64) -> f64 {
+        if v.is_finite() {
+            v
+        } else {
+            0.0
+        }
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn value(u: &mut Unstructured<'_>, depth: u32) -> Result<Value> {
+        let max_tag = if depth < MAX_VALUE_DEPTH { 15 } else { 13 };
+        Ok(match u.int_in_range(0..=max_tag)? {
+            0 => Value::Unit,
+            1 => Value::Bool(u.arbitrary()?),
+            2 => Value::U8(u.arbitrary()?),
+            3 => Value::U16(u.arbitrary()?),
+            4 => Value::U32(u.arbitrary()?),
+            5 => Value::U64(u.arbitrary()?),
+            6 => Value::I8(u.arbitrary()?),
+            7 => Value::I16(u.arbitrary()?),
+            8 => Value::I32(u.arbitrary()?),
+            9 => Value::I64(u.arbitrary()?),
+            10 => Value::F32(finite(f64::from(u.arbitrary::<f32>()?)) as f32),
+            11 => Value::F64(finite(u.arbitrary()?)),
+            12 => Value::String(u.arbitrary()?),
+            13 => Value::Bytes(u.arbitrary()?),
+            14 => {
+                let len = u.int_in_range(0..=MAX_COLLECTION_SIZE)?;
+                Value::Seq(
+                    (0..len)
+                        .map(|_| value(u, depth + 1))
+                        .collect::<Result<Vec<Value>>>()?,
+                )
+            }
+            _ => {
+                let len = u.int_in_range(0..=MAX_COLLECTION_SIZE)?;
+                let mut m = BTreeMap::new();
+                for _ in 0..len {
+                    m.insert(key(u)?, value(u, depth + 1)?);
+                }
+                Value::Map(m)
+            }
+        })
+    }
+
+    impl<'a> Arbitrary<'a> for ItemKind {
+        fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+            u.choose(&ITEM_KINDS).copied()
+        }
+    }
+
+    impl<'a> Arbitrary<'a> for OID {
+        fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+            let kind = ItemKind::arbitrary(u)?;
+            let depth = u.int_in_range(1..=MAX_GROUP_DEPTH)?;
+            let group = (0..depth)
+                .map(|_| segment(u))
+                .collect::<Result<Vec<String>>>()?;
+            Ok(oid_from_parts(kind, &group, &segment(u)?))
+        }
+    }
+
+    #[cfg(feature = "acl")]
+    impl<'a> Arbitrary<'a> for crate::acl::OIDMask {
+        fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+            let kind: Option<ItemKind> = u.arbitrary()?;
+            let depth = u.int_in_range(0..=MAX_GROUP_DEPTH)?;
+            let mut path = Vec::with_capacity(depth);
+            for _ in 0..depth {
+                path.push(if u.arbitrary()? {
+                    Some(segment(u)?)
+                } else {
+                    None
+                });
+            }
+            let wildcard = u.arbitrary()?;
+            super::oid_mask_string(kind, &path, wildcard)
+                .parse()
+                .map_err(|_| arbitrary::Error::IncorrectFormat)
+        }
+    }
+
+    impl<'a> Arbitrary<'a> for Value {
+        fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+            value(u, 0)
+        }
+    }
+
+    #[cfg(feature = "time")]
+    impl<'a> Arbitrary<'a> for crate::time::Time {
+        fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+            Ok(crate::time::Time::new(
+                u.int_in_range(0..=u64::from(u32::MAX))?,
+                u.int_in_range(0..=super::NANOS_MAX)?,
+            ))
+        }
+    }
+
+    #[cfg(feature = "events")]
+    impl<'a> Arbitrary<'a> for crate::events::RawStateEventOwned {
+        fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+            use crate::events::{Force, RawStateEventOwned};
+            let value: Option<Value> = if u.arbitrary()? {
+                Some(value(u, 0)?)
+            } else {
+                None
+            };
+            let t: Option<f64> = if u.arbitrary()? {
+                Some(f64::from(u.int_in_range(0..=u32::MAX)?))
+            } else {
+                None
+            };
+            Ok(RawStateEventOwned {
+                status: u.arbitrary()?,
+                value: value.into(),
+                force: *u.choose(&[Force::None, Force::Update, Force::Full])?,
+                t,
+                ..RawStateEventOwned::default()
+            })
+        }
+    }
+}
+
+#[cfg(all(test, feature = "proptest", feature = "payload"))]
+mod tests {
+    use crate::value::Value;
+    use crate::OID;
+    use proptest::prelude::*;
+
+    proptest! {
+        #[test]
+        fn test_roundtrip(oid in any::<OID>(), value in any::<Value>()) {
+            prop_assert_eq!(oid.as_str().parse::<OID>().unwrap(), oid.clone());
+            let packed = crate::payload::pack(&value).unwrap();
+            let unpacked: Value = crate::payload::unpack(&packed).unwrap();
+            prop_assert_eq!(unpacked.to_canonical_bytes(), value.to_canonical_bytes());
+        }
+    }
+}
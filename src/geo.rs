@@ -0,0 +1,171 @@
+//! Geographic coordinates and geofences for mobile assets
+//!
+//! Coordinates are WGS 84 degrees, distances are in meters. Coordinates are serialized as
+//! `{"lat": .., "lon": ..}` maps, `[lat, lon]` pairs are accepted as well
+use crate::value::Value;
+use crate::{EResult, Error};
+use serde::{Deserialize, Serialize};
+
+/// Mean Earth radius (meters)
+pub const EARTH_RADIUS: f64 = 6_371_008.8;
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum LatLonRepr {
+    Map { lat: f64, lon: f64 },
+    Pair(f64, f64),
+}
+
+impl TryFrom<LatLonRepr> for LatLon {
+    type Error = Error;
+
+    fn try_from(repr: LatLonRepr) -> EResult<Self> {
+        match repr {
+            LatLonRepr::Map { lat, lon } | LatLonRepr::Pair(lat, lon) => LatLon::new(lat, lon),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "LatLonRepr")]
+pub struct LatLon {
+    lat: f64,
+    lon: f64,
+}
+
+impl LatLon {
+    /// Creates a coordinate, latitude must be in -90..=90, longitude in -180..=180
+    pub fn new(lat: f64, lon: f64) -> EResult<Self> {
+        if !(-90.0..=90.0).contains(&lat) {
+            return Err(Error::invalid_params(format!("invalid latitude: {}", lat)));
+        }
+        if !(-180.0..=180.0).contains(&lon) {
+            return Err(Error::invalid_params(format!("invalid longitude: {}", lon)));
+        }
+        Ok(Self { lat, lon })
+    }
+    #[inline]
+    pub fn lat(&self) -> f64 {
+        self.lat
+    }
+    #[inline]
+    pub fn lon(&self) -> f64 {
+        self.lon
+    }
+    /// Great-circle distance (haversine)
+    pub fn distance(&self, other: &LatLon) -> f64 {
+        let (lat1, lat2) = (self.lat.to_radians(), other.lat.to_radians());
+        let d_lat = lat2 - lat1;
+        let d_lon = (other.lon - self.lon).to_radians();
+        let a = (d_lat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (d_lon / 2.0).sin().powi(2);
+        2.0 * EARTH_RADIUS * a.sqrt().min(1.0).asin()
+    }
+    /// Initial bearing to the other point in degrees (0..360, clockwise from north)
+    pub fn bearing(&self, other: &LatLon) -> f64 {
+        let (lat1, lat2) = (self.lat.to_radians(), other.lat.to_radians());
+        let d_lon = (other.lon - self.lon).to_radians();
+        let y = d_lon.sin() * lat2.cos();
+        let x = lat1.cos() * lat2.sin() - lat1.sin() * lat2.cos() * d_lon.cos();
+        (y.atan2(x).to_degrees() + 360.0) % 360.0
+    }
+}
+
+impl TryFrom<Value> for LatLon {
+    type Error = Error;
+
+    fn try_from(value: Value) -> EResult<Self> {
+        Ok(value.deserialize_into()?)
+    }
+}
+
+/// A geofence. Polygons are treated as planar in lat/lon degrees, which is accurate enough for
+/// areas up to tens of kilometers, and must not cross the antimeridian
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase", deny_unknown_fields)]
+pub enum Geofence {
+    Circle { center: LatLon, radius: f64 },
+    Polygon { points: Vec<LatLon> },
+}
+
+impl Geofence {
+    pub fn circle(center: LatLon, radius: f64) -> EResult<Self> {
+        if radius.is_nan() || radius < 0.0 {
+            return Err(Error::invalid_params(format!("invalid radius: {}", radius)));
+        }
+        Ok(Geofence::Circle { center, radius })
+    }
+    pub fn polygon(points: Vec<LatLon>) -> EResult<Self> {
+        if points.len() < 3 {
+            return Err(Error::invalid_params(
+                "a polygon geofence must have at least 3 points",
+            ));
+        }
+        Ok(Geofence::Polygon { points })
+    }
+    /// Returns true if the point is inside the geofence (polygons: ray casting, points exactly
+    /// on edges may be reported either way)
+    pub fn contains(&self, point: &LatLon) -> bool {
+        match self {
+            Geofence::Circle { center, radius } => center.distance(point) <= *radius,
+            Geofence::Polygon { points } => {
+                if points.len() < 3 {
+                    return false;
+                }
+                let mut inside = false;
+                let mut prev = points[points.len() - 1];
+                for p in points {
+                    if (p.lat > point.lat) != (prev.lat > point.lat)
+                        && point.lon
+                            < (prev.lon - p.lon) * (point.lat - p.lat) / (prev.lat - p.lat) + p.lon
+                    {
+                        inside = !inside;
+                    }
+                    prev = *p;
+                }
+                inside
+            }
+        }
+    }
+}
+
+impl TryFrom<Value> for Geofence {
+    type Error = Error;
+
+    fn try_from(value: Value) -> EResult<Self> {
+        Ok(value.deserialize_into()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Geofence, LatLon};
+    use crate::value::Value;
+
+    #[test]
+    fn test_geofence() {
+        let berlin = LatLon::new(52.5200, 13.4050).unwrap();
+        let paris = LatLon::new(48.8566, 2.3522).unwrap();
+        let d = berlin.distance(&paris);
+        assert!((d - 877_500.0).abs() < 1_000.0, "{}", d);
+        assert!((berlin.bearing(&paris) - 246.0).abs() < 1.0);
+        assert!(LatLon::new(91.0, 0.0).is_err());
+        let p: LatLon = serde_json::from_str("[52.52, 13.405]").unwrap();
+        assert_eq!(
+            serde_json::to_string(&p).unwrap(),
+            r#"{"lat":52.52,"lon":13.405}"#
+        );
+        assert!(serde_json::from_str::<LatLon>(r#"{"lat":0,"lon":200}"#).is_err());
+        let circle = Geofence::circle(berlin, 1_000.0).unwrap();
+        assert!(circle.contains(&LatLon::new(52.525, 13.41).unwrap()));
+        assert!(!circle.contains(&paris));
+        let fence: Value = serde_json::from_str(
+            r#"{"kind":"polygon","points":[[52.0,13.0],[53.0,13.0],[53.0,14.0],[52.0,14.0]]}"#,
+        )
+        .unwrap();
+        let square = Geofence::try_from(fence).unwrap();
+        assert!(square.contains(&berlin));
+        assert!(!square.contains(&paris));
+        assert!(!square.contains(&LatLon::new(52.5, 14.5).unwrap()));
+        assert!(Geofence::polygon(vec![berlin, paris]).is_err());
+    }
+}
@@ -0,0 +1,434 @@
+use crate::value::Value;
+use crate::{EResult, Error, ItemStatus, OID};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Maximum number of fill intervals in a time range
+pub const MAX_FILL_POINTS: usize = 100_000;
+
+/// Fill interval, parsed from time frames (N<S|T|H|D|W>, e.g. 5T for 5 minutes) or seconds
+#[derive(Debug, Copy, Clone, PartialEq, PartialOrd)]
+pub struct Fill(f64);
+
+impl Fill {
+    /// # Panics
+    ///
+    /// Will panic if the interval is not positive
+    #[inline]
+    pub fn new(interval: Duration) -> Self {
+        assert!(!interval.is_zero(), "fill interval must be positive");
+        Self(interval.as_secs_f64())
+    }
+    #[inline]
+    pub fn as_secs_f64(&self) -> f64 {
+        self.0
+    }
+    #[inline]
+    pub fn as_duration(&self) -> Duration {
+        Duration::from_secs_f64(self.0)
+    }
+    /// Number of interval start points in the time range
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the number exceeds [`MAX_FILL_POINTS`]
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        clippy::cast_precision_loss
+    )]
+    pub fn point_count(&self, t_start: f64, t_end: f64) -> EResult<usize> {
+        if t_end < t_start {
+            return Ok(0);
+        }
+        let n = ((t_end - t_start) / self.0).floor();
+        if n.is_nan() || n >= MAX_FILL_POINTS as f64 {
+            return Err(Error::invalid_params(format!(
+                "too many fill intervals in the time range (max: {})",
+                MAX_FILL_POINTS
+            )));
+        }
+        Ok(n as usize + 1)
+    }
+    /// Splits the time range into interval start points
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the number of points exceeds [`MAX_FILL_POINTS`]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn points(&self, t_start: f64, t_end: f64) -> EResult<Vec<f64>> {
+        let count = self.point_count(t_start, t_end)?;
+        Ok((0..count).map(|i| t_start + self.0 * i as f64).collect())
+    }
+}
+
+impl FromStr for Fill {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let secs = if let Some(v) = crate::value::parse_time_frame(s) {
+            v
+        } else {
+            s.parse::<f64>()
+                .map_err(|_| Error::invalid_params(format!("invalid fill interval: {}", s)))?
+        };
+        if secs > 0.0 && secs.is_finite() {
+            Ok(Self(secs))
+        } else {
+            Err(Error::invalid_params(format!(
+                "invalid fill interval: {}",
+                s
+            )))
+        }
+    }
+}
+
+impl fmt::Display for Fill {
+    #[allow(clippy::float_cmp)]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (unit, secs) in [
+            ("W", 604_800.0),
+            ("D", 86_400.0),
+            ("H", 3_600.0),
+            ("T", 60.0),
+        ] {
+            let v = self.0 / secs;
+            if v >= 1.0 && v.trunc() == v {
+                return write!(f, "{}{}", v, unit);
+            }
+        }
+        write!(f, "{}S", self.0)
+    }
+}
+
+impl Serialize for Fill {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for Fill {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let val = Value::deserialize(deserializer)?;
+        if let Value::String(s) = val {
+            s.parse().map_err(serde::de::Error::custom)
+        } else {
+            let secs = f64::try_from(val).map_err(serde::de::Error::custom)?;
+            format!("{}", secs)
+                .parse()
+                .map_err(serde::de::Error::custom)
+        }
+    }
+}
+
+/// How to fill intervals which have no data
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GapFill {
+    /// Set value to null
+    #[default]
+    Null,
+    /// Use the last known state
+    Previous,
+    /// Skip the interval
+    Skip,
+}
+
+impl FromStr for GapFill {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "null" => Ok(GapFill::Null),
+            "previous" | "prev" => Ok(GapFill::Previous),
+            "skip" => Ok(GapFill::Skip),
+            _ => Err(Error::invalid_params(format!("invalid gap fill: {}", s))),
+        }
+    }
+}
+
+/// Fill mode, can be deserialized from either a structure or a string "<interval>[:<gaps>]",
+/// e.g. "5T" or "1H:previous"
+#[derive(Debug, Copy, Clone, PartialEq, Serialize)]
+pub struct FillMode {
+    pub interval: Fill,
+    pub gaps: GapFill,
+}
+
+impl FillMode {
+    #[inline]
+    pub fn new(interval: Fill) -> Self {
+        Self {
+            interval,
+            gaps: <_>::default(),
+        }
+    }
+    #[inline]
+    pub fn gaps(mut self, gaps: GapFill) -> Self {
+        self.gaps = gaps;
+        self
+    }
+}
+
+impl FromStr for FillMode {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut sp = s.splitn(2, ':');
+        let interval = sp.next().unwrap().parse()?;
+        let gaps = if let Some(g) = sp.next() {
+            g.parse()?
+        } else {
+            <_>::default()
+        };
+        Ok(Self { interval, gaps })
+    }
+}
+
+impl<'de> Deserialize<'de> for FillMode {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(deny_unknown_fields)]
+        struct FillModeFull {
+            interval: Fill,
+            #[serde(default)]
+            gaps: GapFill,
+        }
+        let val = Value::deserialize(deserializer)?;
+        if let Value::String(s) = val {
+            s.parse().map_err(serde::de::Error::custom)
+        } else {
+            let f = FillModeFull::deserialize(val).map_err(serde::de::Error::custom)?;
+            Ok(Self {
+                interval: f.interval,
+                gaps: f.gaps,
+            })
+        }
+    }
+}
+
+/// Aggregation function, applied to states inside fill intervals
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Agg {
+    Mean,
+    Min,
+    Max,
+    Sum,
+    Count,
+    First,
+    Last,
+}
+
+impl FromStr for Agg {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mean" | "avg" => Ok(Agg::Mean),
+            "min" => Ok(Agg::Min),
+            "max" => Ok(Agg::Max),
+            "sum" => Ok(Agg::Sum),
+            "count" => Ok(Agg::Count),
+            "first" => Ok(Agg::First),
+            "last" => Ok(Agg::Last),
+            _ => Err(Error::invalid_params(format!("invalid aggregation: {}", s))),
+        }
+    }
+}
+
+fn de_oid_or_list<'de, D>(deserializer: D) -> Result<Vec<OID>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OidOrList {
+        Single(OID),
+        Multiple(Vec<OID>),
+    }
+    Ok(match OidOrList::deserialize(deserializer)? {
+        OidOrList::Single(oid) => vec![oid],
+        OidOrList::Multiple(oids) => oids,
+    })
+}
+
+/// State history request
+///
+/// t_start/t_end accept everything supported by [`Value::as_timestamp`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HistoryRequest {
+    #[serde(alias = "oid", deserialize_with = "de_oid_or_list")]
+    pub i: Vec<OID>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub t_start: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub t_end: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fill: Option<FillMode>,
+    /// Round numeric values to N digits after comma
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub precision: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub agg: Option<Agg>,
+}
+
+impl HistoryRequest {
+    #[inline]
+    pub fn new(oids: Vec<OID>) -> Self {
+        Self {
+            i: oids,
+            t_start: None,
+            t_end: None,
+            fill: None,
+            precision: None,
+            limit: None,
+            agg: None,
+        }
+    }
+    pub fn t_start(&self) -> EResult<Option<f64>> {
+        self.t_start.as_ref().map(Value::as_timestamp).transpose()
+    }
+    pub fn t_end(&self) -> EResult<Option<f64>> {
+        self.t_end.as_ref().map(Value::as_timestamp).transpose()
+    }
+    /// Checks the request parameters. The number of fill intervals is checked if both t_start
+    /// and t_end are specified, otherwise [`Fill::points`] fails on too wide ranges
+    pub fn validate(&self) -> EResult<()> {
+        if self.i.is_empty() {
+            return Err(Error::invalid_params("no OIDs specified"));
+        }
+        let t_start = self.t_start()?;
+        let t_end = self.t_end()?;
+        if let (Some(t_start), Some(t_end)) = (t_start, t_end) {
+            if t_end < t_start {
+                return Err(Error::invalid_params("t_end is less than t_start"));
+            }
+        }
+        if self.agg.is_some() && self.fill.is_none() {
+            return Err(Error::invalid_params("aggregation requires fill"));
+        }
+        if let (Some(fill), Some(t_start), Some(t_end)) = (self.fill, t_start, t_end) {
+            fill.interval.point_count(t_start, t_end)?;
+        }
+        Ok(())
+    }
+}
+
+/// Dense history frame, all arrays have the same length
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct HistoryFrame {
+    pub t: Vec<f64>,
+    pub status: Vec<Option<ItemStatus>>,
+    pub value: Vec<Value>,
+}
+
+impl HistoryFrame {
+    #[inline]
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            t: Vec::with_capacity(capacity),
+            status: Vec::with_capacity(capacity),
+            value: Vec::with_capacity(capacity),
+        }
+    }
+    #[inline]
+    pub fn push(&mut self, t: f64, status: Option<ItemStatus>, value: Value) {
+        self.t.push(t);
+        self.status.push(status);
+        self.value.push(value);
+    }
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.t.len()
+    }
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.t.is_empty()
+    }
+    /// Rounds numeric values
+    pub fn round(&mut self, precision: u32) {
+        for v in &mut self.value {
+            if let Ok(r) = v.clone().rounded(Some(precision)) {
+                *v = r;
+            }
+        }
+    }
+    /// Checks that all arrays have the same length
+    pub fn validate(&self) -> EResult<()> {
+        if self.status.len() == self.t.len() && self.value.len() == self.t.len() {
+            Ok(())
+        } else {
+            Err(Error::invalid_data("history frame arrays length mismatch"))
+        }
+    }
+}
+
+/// History of a single item
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ItemHistory {
+    pub oid: OID,
+    #[serde(flatten)]
+    pub frame: HistoryFrame,
+}
+
+pub type HistoryResponse = Vec<ItemHistory>;
+
+#[cfg(test)]
+mod tests {
+    use super::{Agg, Fill, FillMode, GapFill, HistoryRequest};
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn test_fill() {
+        let fill: Fill = "5T".parse().unwrap();
+        assert_eq!(fill.as_secs_f64(), 300.0);
+        assert_eq!(fill.to_string(), "5T");
+        assert_eq!("90".parse::<Fill>().unwrap().to_string(), "90S");
+        assert!("0S".parse::<Fill>().is_err());
+        assert!("5é".parse::<Fill>().is_err());
+        assert!("é".parse::<Fill>().is_err());
+        assert_eq!(fill.points(0.0, 600.0).unwrap(), vec![0.0, 300.0, 600.0]);
+        assert!(fill.points(600.0, 0.0).unwrap().is_empty());
+        let tiny: Fill = "1e-300".parse().unwrap();
+        assert!(tiny.points(0.0, 86_400.0).is_err());
+        assert!(tiny.point_count(0.0, f64::INFINITY).is_err());
+        let mode: FillMode = "1H:previous".parse().unwrap();
+        assert_eq!(mode.interval.as_secs_f64(), 3600.0);
+        assert_eq!(mode.gaps, GapFill::Previous);
+    }
+
+    #[test]
+    fn test_request() {
+        let req: HistoryRequest = serde_json::from_str(
+            r#"{"i":"sensor:tests/s1","t_start":100,"fill":"5T","agg":"mean","precision":2}"#,
+        )
+        .unwrap();
+        assert_eq!(req.i.len(), 1);
+        assert_eq!(req.agg, Some(Agg::Mean));
+        assert_eq!(req.fill.unwrap().gaps, GapFill::Null);
+        assert_eq!(req.t_start().unwrap(), Some(100.0));
+        req.validate().unwrap();
+        let req: HistoryRequest = serde_json::from_str(
+            r#"{"i":["sensor:tests/s1","sensor:tests/s2"],"t_start":100,"t_end":50}"#,
+        )
+        .unwrap();
+        assert_eq!(req.i.len(), 2);
+        assert!(req.validate().is_err());
+        let req: HistoryRequest = serde_json::from_str(
+            r#"{"i":"sensor:tests/s1","t_start":0,"t_end":1000000000,"fill":"0.001"}"#,
+        )
+        .unwrap();
+        assert!(req.validate().is_err());
+    }
+}
@@ -1,3 +1,4 @@
+use crate::problem::{Problem, PROBLEM_JSON_MIME};
 use crate::value::{to_value, Value};
 use crate::ErrorKind;
 use hyper::{http, Body, HeaderMap, Response, StatusCode};
@@ -22,9 +23,17 @@ pub type HResult = std::result::Result<HContent, crate::Error>;
 
 pub trait HResultX {
     fn into_hyper_response(self) -> Result<Response<Body>, http::Error>;
+    /// Same as into_hyper_response but errors are rendered as RFC 7807 problem details
+    fn into_hyper_problem_response(self) -> Result<Response<Body>, http::Error>;
 }
 
 impl HResultX for HResult {
+    fn into_hyper_problem_response(self) -> Result<Response<Body>, http::Error> {
+        match self {
+            Ok(_) => self.into_hyper_response(),
+            Err(e) => Problem::from(e).into_hyper_response(),
+        }
+    }
     fn into_hyper_response(self) -> Result<Response<Body>, http::Error> {
         match self {
             Ok(resp) => match resp {
@@ -79,6 +88,18 @@ impl HResultX for HResult {
     }
 }
 
+impl Problem {
+    pub fn into_hyper_response(self) -> Result<Response<Body>, http::Error> {
+        match self.to_vec() {
+            Ok(body) => Response::builder()
+                .status(self.http_status())
+                .header(hyper::header::CONTENT_TYPE, PROBLEM_JSON_MIME)
+                .body(Body::from(body)),
+            Err(e) => hyper_response!(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+        }
+    }
+}
+
 pub enum HContent {
     Data(Vec<u8>, Option<&'static str>, Option<HeaderMap>),
     Value(Value),
@@ -0,0 +1,141 @@
+//! User-facing message catalog with per-locale overrides
+//!
+//! Messages are keyed by [`ErrorKind`] names (e.g. "ResourceNotFound"), item status keys
+//! ("status.<code>") or arbitrary strings. Lookups fall back from the full language tag to the
+//! primary language ("de-AT" -> "de") and then to the built-in English messages.
+use crate::value::Value;
+use crate::{EResult, Error, ErrorKind, ItemStatus, ITEM_STATUS_ERROR};
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+type Messages = HashMap<String, String>;
+
+/// Catalog key for the error kind
+#[inline]
+pub fn error_key(kind: ErrorKind) -> String {
+    format!("{:?}", kind)
+}
+
+/// Catalog key for the item status
+#[inline]
+pub fn status_key(status: ItemStatus) -> String {
+    format!("status.{}", status)
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Catalog {
+    locales: HashMap<String, Messages>,
+}
+
+impl Catalog {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Loads locales from a map lang -> (key -> message), merging with the existing ones
+    pub fn load(&mut self, locales: Value) -> EResult<()> {
+        let locales: HashMap<String, Messages> = locales.deserialize_into()?;
+        for (lang, messages) in locales {
+            self.add_locale(&lang, messages);
+        }
+        Ok(())
+    }
+    pub fn add_locale(&mut self, lang: &str, messages: HashMap<String, String>) {
+        self.locales
+            .entry(lang.to_lowercase())
+            .or_default()
+            .extend(messages);
+    }
+    pub fn set(&mut self, lang: &str, key: &str, message: &str) {
+        self.locales
+            .entry(lang.to_lowercase())
+            .or_default()
+            .insert(key.to_owned(), message.to_owned());
+    }
+    #[inline]
+    pub fn has_locale(&self, lang: &str) -> bool {
+        self.locales.contains_key(&lang.to_lowercase())
+    }
+    /// Gets the message override for the language (with the primary language fallback)
+    pub fn message(&self, key: &str, lang: &str) -> Option<&str> {
+        let lang = lang.to_lowercase();
+        let primary = lang.split(['-', '_']).next().unwrap_or_default();
+        for l in [lang.as_str(), primary] {
+            if let Some(msg) = self.locales.get(l).and_then(|m| m.get(key)) {
+                return Some(msg);
+            }
+        }
+        None
+    }
+    /// Localized error kind title
+    pub fn error_kind(&self, kind: ErrorKind, lang: &str) -> Cow<'_, str> {
+        self.message(&error_key(kind), lang)
+            .map_or_else(|| Cow::Owned(kind.to_string()), Cow::Borrowed)
+    }
+    /// Localized item status name
+    pub fn status(&self, status: ItemStatus, lang: &str) -> Cow<'_, str> {
+        if let Some(msg) = self.message(&status_key(status), lang) {
+            return Cow::Borrowed(msg);
+        }
+        match status {
+            1 => Cow::Borrowed("OK"),
+            ITEM_STATUS_ERROR => Cow::Borrowed("Error"),
+            v => Cow::Owned(v.to_string()),
+        }
+    }
+}
+
+impl Error {
+    /// User-facing error message: the localized kind title and the error message (if set). The
+    /// message itself is not translated, as it usually contains item ids and technical details
+    pub fn localized(&self, catalog: &Catalog, lang: &str) -> String {
+        let title = catalog.error_kind(self.kind(), lang);
+        if let Some(msg) = self.message() {
+            format!("{}: {}", title, msg)
+        } else {
+            title.into_owned()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Catalog;
+    use crate::value::Value;
+    use crate::{Error, ErrorKind};
+
+    #[test]
+    fn test_catalog() {
+        let mut catalog = Catalog::new();
+        let locales: Value = serde_json::from_str(
+            r#"{"de":{"ResourceNotFound":"Ressource nicht gefunden","status.-1":"Fehler"},
+            "de-AT":{"AccessDenied":"Zugriff verweigert!"}}"#,
+        )
+        .unwrap();
+        catalog.load(locales).unwrap();
+        assert!(catalog.has_locale("DE"));
+        assert!(catalog.load(Value::U8(1)).is_err());
+        assert_eq!(
+            Error::not_found("unit:tests/u1").localized(&catalog, "de"),
+            "Ressource nicht gefunden: unit:tests/u1"
+        );
+        assert_eq!(
+            Error::not_found("unit:tests/u1").localized(&catalog, "de_AT"),
+            "Ressource nicht gefunden: unit:tests/u1"
+        );
+        assert_eq!(
+            Error::new0(ErrorKind::AccessDenied).localized(&catalog, "de-at"),
+            "Zugriff verweigert!"
+        );
+        assert_eq!(
+            Error::new0(ErrorKind::AccessDenied).localized(&catalog, "de"),
+            "Access denied"
+        );
+        assert_eq!(Error::timeout().localized(&catalog, "fr"), "Timed out");
+        assert_eq!(catalog.status(-1, "de-CH"), "Fehler");
+        assert_eq!(catalog.status(1, "de"), "OK");
+        assert_eq!(catalog.status(5, "de"), "5");
+        catalog.set("fr", "status.1", "OK!");
+        assert_eq!(catalog.status(1, "fr"), "OK!");
+    }
+}
@@ -0,0 +1,237 @@
+//! Item configuration structures, matching the core registry inventory layout
+use crate::logic::{de_opt_range, Range};
+use crate::tools::{
+    check_svc_id, de_opt_float_as_duration, default_true, serialize_opt_duration_as_f64,
+};
+use crate::value::Value;
+use crate::{EResult, Error, ItemKind, OID};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::time::Duration;
+
+/// Item action configuration
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct ActionConfig {
+    /// The service which executes actions
+    pub svc: String,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_opt_duration_as_f64",
+        deserialize_with = "de_opt_float_as_duration"
+    )]
+    pub timeout: Option<Duration>,
+    /// Service-specific action configuration
+    #[serde(default, skip_serializing_if = "Value::is_unit")]
+    pub config: Value,
+}
+
+impl ActionConfig {
+    #[inline]
+    pub fn new(svc: &str) -> Self {
+        Self {
+            svc: svc.to_owned(),
+            timeout: None,
+            config: Value::Unit,
+        }
+    }
+    #[inline]
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+    #[inline]
+    pub fn config(mut self, config: Value) -> Self {
+        self.config = config;
+        self
+    }
+    pub fn validate(&self) -> EResult<()> {
+        check_svc_id(&self.svc)?;
+        if self.timeout.is_some_and(|t| t.is_zero()) {
+            return Err(Error::invalid_params("action timeout must be positive"));
+        }
+        Ok(())
+    }
+}
+
+/// Item logic configuration
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct LogicConfig {
+    /// Logical value range, values outside are considered as errors
+    #[serde(
+        default,
+        deserialize_with = "de_opt_range",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub range: Option<Range>,
+}
+
+/// Item configuration
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ItemConfig {
+    pub oid: OID,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub meta: Option<Value>,
+    /// Engineering units (informational)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub units: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub logic: Option<LogicConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub action: Option<ActionConfig>,
+}
+
+impl ItemConfig {
+    #[inline]
+    pub fn new(oid: OID) -> Self {
+        Self {
+            oid,
+            enabled: true,
+            meta: None,
+            units: None,
+            logic: None,
+            action: None,
+        }
+    }
+    #[inline]
+    pub fn disabled(mut self) -> Self {
+        self.enabled = false;
+        self
+    }
+    #[inline]
+    pub fn meta(mut self, meta: Value) -> Self {
+        self.meta = Some(meta);
+        self
+    }
+    #[inline]
+    pub fn units(mut self, units: &str) -> Self {
+        self.units = Some(units.to_owned());
+        self
+    }
+    #[inline]
+    pub fn range(mut self, range: Range) -> Self {
+        self.logic = Some(LogicConfig { range: Some(range) });
+        self
+    }
+    #[inline]
+    pub fn action(mut self, action: ActionConfig) -> Self {
+        self.action = Some(action);
+        self
+    }
+    /// Checks the configuration against the item kind
+    pub fn validate(&self) -> EResult<()> {
+        if self.oid.is_wildcard() {
+            return Err(Error::invalid_params(format!(
+                "item OID can not be a wildcard: {}",
+                self.oid
+            )));
+        }
+        let kind = self.oid.kind();
+        if let Some(ref action) = self.action {
+            if kind != ItemKind::Unit && kind != ItemKind::Lmacro {
+                return Err(Error::invalid_params(format!(
+                    "{} items can not have actions: {}",
+                    kind, self.oid
+                )));
+            }
+            action.validate()?;
+        } else if kind == ItemKind::Lmacro {
+            return Err(Error::invalid_params(format!(
+                "lmacro action not configured: {}",
+                self.oid
+            )));
+        }
+        if self.logic.is_some() && kind == ItemKind::Lmacro {
+            return Err(Error::invalid_params(format!(
+                "lmacro items can not have logic: {}",
+                self.oid
+            )));
+        }
+        Ok(())
+    }
+    /// The registry key of the item configuration
+    #[cfg(feature = "registry")]
+    #[inline]
+    pub fn registry_key(&self) -> String {
+        crate::registry::format_key(crate::registry::R_INVENTORY, self.oid.as_path())
+    }
+}
+
+/// Item inventory
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Inventory {
+    #[serde(default)]
+    pub items: Vec<ItemConfig>,
+}
+
+impl Inventory {
+    /// Validates all items and checks for duplicate OIDs
+    pub fn validate(&self) -> EResult<()> {
+        let mut oids = HashSet::new();
+        for item in &self.items {
+            item.validate()?;
+            if !oids.insert(&item.oid) {
+                return Err(Error::duplicate(format!("duplicate item: {}", item.oid)));
+            }
+        }
+        Ok(())
+    }
+    pub fn get(&self, oid: &OID) -> Option<&ItemConfig> {
+        self.items.iter().find(|i| &i.oid == oid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Inventory, ItemConfig};
+    use crate::ErrorKind;
+
+    #[test]
+    fn test_inventory() {
+        let inv: Inventory = serde_json::from_str(
+            r#"{"items":[
+            {"oid":"unit:tests/u1","action":{"svc":"eva.controller.virtual","timeout":5}},
+            {"oid":"sensor:tests/s1","enabled":false,"units":"C",
+                "logic":{"range":"0 <= x <= 100"}},
+            {"oid":"lmacro:tests/m1","action":{"svc":"eva.controller.py"}}
+        ]}"#,
+        )
+        .unwrap();
+        inv.validate().unwrap();
+        let item = inv.get(&"unit:tests/u1".parse().unwrap()).unwrap();
+        assert!(item.enabled);
+        assert_eq!(item.action.as_ref().unwrap().timeout.unwrap().as_secs(), 5);
+        let item = inv.get(&"sensor:tests/s1".parse().unwrap()).unwrap();
+        assert!(!item.enabled);
+        let range = item.logic.as_ref().unwrap().range.unwrap();
+        assert!(range.matches(100.0));
+        assert!(!range.matches(101.0));
+    }
+
+    #[test]
+    fn test_validate() {
+        let item: ItemConfig =
+            serde_json::from_str(r#"{"oid":"sensor:tests/s1","action":{"svc":"x"}}"#).unwrap();
+        assert!(item.validate().is_err());
+        let item: ItemConfig = serde_json::from_str(r#"{"oid":"lmacro:tests/m1"}"#).unwrap();
+        assert!(item.validate().is_err());
+        let item: ItemConfig =
+            serde_json::from_str(r#"{"oid":"unit:tests/u1","action":{"svc":"bad svc"}}"#).unwrap();
+        assert!(item.validate().is_err());
+        let mut inv = Inventory::default();
+        inv.items
+            .push(ItemConfig::new("unit:tests/u1".parse().unwrap()));
+        inv.items
+            .push(ItemConfig::new("unit:tests/u1".parse().unwrap()));
+        assert_eq!(
+            inv.validate().unwrap_err().kind(),
+            ErrorKind::ResourceAlreadyExists
+        );
+    }
+}
@@ -0,0 +1,459 @@
+//! Durable journals of bus events, published or consumed by services
+//!
+//! A [`Journal`] stores entries with monotonic sequence numbers (never reused, even after
+//! truncation) and committed positions of named consumers, so a service can record events
+//! before processing them, commit the last processed entry and replay the rest after a crash.
+//!
+//! Backends: [`FileJournal`] (an append-only file with checksummed records) and
+//! `SqliteJournal` (feature "journal-sqlite").
+use crate::payload::{pack, unpack};
+use crate::tools::{deserialize_bytes, serialize_bytes};
+use crate::{EResult, Error};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+
+/// Max record size, accepted by the file journal
+pub const MAX_RECORD_SIZE: usize = 64 * 1024 * 1024;
+
+const JOURNAL_FILE: &str = "journal";
+const STATE_FILE: &str = "state";
+
+/// Journal entry
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    pub seq: u64,
+    /// Append time (UNIX timestamp)
+    pub t: f64,
+    pub topic: String,
+    #[serde(
+        serialize_with = "serialize_bytes",
+        deserialize_with = "deserialize_bytes"
+    )]
+    pub payload: Vec<u8>,
+}
+
+/// Event journal
+pub trait Journal {
+    /// Appends an entry, returns its sequence number
+    fn append(&mut self, topic: &str, payload: &[u8]) -> impl Future<Output = EResult<u64>> + Send;
+    /// Returns up to `limit` entries, starting from the sequence number
+    fn range(&self, from: u64, limit: usize) -> impl Future<Output = EResult<Vec<Entry>>> + Send;
+    /// Deletes entries with sequence numbers less than the specified one
+    fn truncate(&mut self, before: u64) -> impl Future<Output = EResult<()>> + Send;
+    /// Sequence number of the last appended entry
+    fn last_seq(&self) -> impl Future<Output = EResult<Option<u64>>> + Send;
+    /// Stores the sequence number of the last entry, processed by the consumer
+    fn commit(&mut self, consumer: &str, seq: u64) -> impl Future<Output = EResult<()>> + Send;
+    /// Sequence number of the last entry, processed by the consumer
+    fn committed(&self, consumer: &str) -> impl Future<Output = EResult<Option<u64>>> + Send;
+}
+
+fn now() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs_f64())
+        .unwrap_or_default()
+}
+
+/// FNV-1a, detects torn and corrupted records
+fn checksum(data: &[u8]) -> u32 {
+    data.iter().fold(0x811c_9dc5_u32, |h, b| {
+        (h ^ u32::from(*b)).wrapping_mul(0x0100_0193)
+    })
+}
+
+fn encode_record(entry: &Entry) -> EResult<Vec<u8>> {
+    let data = pack(entry)?;
+    let len = u32::try_from(data.len())
+        .ok()
+        .filter(|len| *len as usize <= MAX_RECORD_SIZE)
+        .ok_or_else(|| Error::invalid_data("journal record is too large"))?;
+    let mut buf = Vec::with_capacity(data.len() + 8);
+    buf.extend(len.to_le_bytes());
+    buf.extend(&data);
+    buf.extend(checksum(&data).to_le_bytes());
+    Ok(buf)
+}
+
+/// Decodes a record at the beginning of the buffer, returns the entry and the record size. None
+/// is returned for incomplete or corrupted records
+fn decode_record(buf: &[u8]) -> Option<(Entry, usize)> {
+    let len = u32::from_le_bytes(buf.get(..4)?.try_into().ok()?) as usize;
+    if len > MAX_RECORD_SIZE {
+        return None;
+    }
+    let data = buf.get(4..4 + len)?;
+    let sum = u32::from_le_bytes(buf.get(4 + len..8 + len)?.try_into().ok()?);
+    if checksum(data) != sum {
+        return None;
+    }
+    Some((unpack(data).ok()?, len + 8))
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct FileJournalState {
+    /// The next sequence number must not be less than this value
+    next_seq: u64,
+    offsets: BTreeMap<String, u64>,
+}
+
+/// Append-only file journal
+///
+/// Entries are kept in a single file, its index is kept in memory. A torn or corrupted tail
+/// (e.g. after a power loss) is cut off when the journal is opened. Truncation rewrites the
+/// file, so it should be called periodically rather than after each commit
+pub struct FileJournal {
+    dir: PathBuf,
+    file: tokio::fs::File,
+    /// sequence number -> record offset
+    index: BTreeMap<u64, u64>,
+    size: u64,
+    state: FileJournalState,
+    sync: bool,
+}
+
+impl FileJournal {
+    /// Opens or creates a journal in the directory
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` on I/O errors
+    pub async fn open(dir: impl AsRef<Path>) -> EResult<Self> {
+        let dir = dir.as_ref().to_owned();
+        tokio::fs::create_dir_all(&dir).await?;
+        let mut state: FileJournalState = match tokio::fs::read(dir.join(STATE_FILE)).await {
+            Ok(data) => unpack(&data)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => <_>::default(),
+            Err(e) => return Err(e.into()),
+        };
+        let path = dir.join(JOURNAL_FILE);
+        let data = match tokio::fs::read(&path).await {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+        let mut index = BTreeMap::new();
+        let mut pos = 0;
+        while pos < data.len() {
+            let Some((entry, size)) = decode_record(&data[pos..]) else {
+                log::warn!(
+                    "journal {}: corrupted tail at {}, {} bytes cut off",
+                    path.display(),
+                    pos,
+                    data.len() - pos
+                );
+                break;
+            };
+            state.next_seq = state.next_seq.max(entry.seq + 1);
+            index.insert(entry.seq, pos as u64);
+            pos += size;
+        }
+        let file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(false)
+            .open(&path)
+            .await?;
+        file.set_len(pos as u64).await?;
+        Ok(Self {
+            dir,
+            file,
+            index,
+            size: pos as u64,
+            state,
+            sync: true,
+        })
+    }
+    /// Sync the file after each append (default: true). If disabled, recent entries may be lost
+    /// on power failures
+    pub fn sync(mut self, sync: bool) -> Self {
+        self.sync = sync;
+        self
+    }
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+    async fn write_state(&self) -> EResult<()> {
+        let tmp = self.dir.join(format!("{}.tmp", STATE_FILE));
+        write_synced(&tmp, &pack(&self.state)?).await?;
+        tokio::fs::rename(&tmp, self.dir.join(STATE_FILE)).await?;
+        Ok(())
+    }
+}
+
+async fn write_synced(path: &Path, data: &[u8]) -> EResult<()> {
+    let mut f = tokio::fs::File::create(path).await?;
+    f.write_all(data).await?;
+    f.sync_all().await?;
+    Ok(())
+}
+
+impl Journal for FileJournal {
+    async fn append(&mut self, topic: &str, payload: &[u8]) -> EResult<u64> {
+        let seq = self.state.next_seq;
+        let record = encode_record(&Entry {
+            seq,
+            t: now(),
+            topic: topic.to_owned(),
+            payload: payload.to_vec(),
+        })?;
+        self.file.seek(SeekFrom::Start(self.size)).await?;
+        if let Err(e) = self.file.write_all(&record).await {
+            // cut off a partially written record
+            let _ = self.file.set_len(self.size).await;
+            return Err(e.into());
+        }
+        if self.sync {
+            self.file.sync_data().await?;
+        }
+        self.index.insert(seq, self.size);
+        self.size += record.len() as u64;
+        self.state.next_seq = seq + 1;
+        Ok(seq)
+    }
+    async fn range(&self, from: u64, limit: usize) -> EResult<Vec<Entry>> {
+        let Some((_, &start)) = self.index.range(from..).next() else {
+            return Ok(Vec::new());
+        };
+        let end = self
+            .index
+            .range(from..)
+            .nth(limit)
+            .map_or(self.size, |(_, pos)| *pos);
+        let mut f = tokio::fs::File::open(self.dir.join(JOURNAL_FILE)).await?;
+        f.seek(SeekFrom::Start(start)).await?;
+        let mut buf = vec![0; usize::try_from(end - start).map_err(Error::invalid_data)?];
+        f.read_exact(&mut buf).await?;
+        let mut result = Vec::new();
+        let mut pos = 0;
+        while pos < buf.len() && result.len() < limit {
+            let (entry, size) = decode_record(&buf[pos..])
+                .ok_or_else(|| Error::invalid_data("journal record is corrupted"))?;
+            result.push(entry);
+            pos += size;
+        }
+        Ok(result)
+    }
+    async fn truncate(&mut self, before: u64) -> EResult<()> {
+        let Some((_, &start)) = self.index.range(before..).next() else {
+            // all entries are deleted
+            self.file.set_len(0).await?;
+            self.file.sync_all().await?;
+            self.index.clear();
+            self.size = 0;
+            return self.write_state().await;
+        };
+        if start == 0 {
+            return Ok(());
+        }
+        let path = self.dir.join(JOURNAL_FILE);
+        let tmp = self.dir.join(format!("{}.tmp", JOURNAL_FILE));
+        let mut f = tokio::fs::File::open(&path).await?;
+        f.seek(SeekFrom::Start(start)).await?;
+        let mut data = Vec::new();
+        f.read_to_end(&mut data).await?;
+        // the next sequence number must survive if all entries are deleted later
+        self.write_state().await?;
+        write_synced(&tmp, &data).await?;
+        tokio::fs::rename(&tmp, &path).await?;
+        self.file = tokio::fs::OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .await?;
+        self.index = self
+            .index
+            .range(before..)
+            .map(|(seq, pos)| (*seq, pos - start))
+            .collect();
+        self.size -= start;
+        Ok(())
+    }
+    async fn last_seq(&self) -> EResult<Option<u64>> {
+        Ok(self.index.keys().next_back().copied())
+    }
+    async fn commit(&mut self, consumer: &str, seq: u64) -> EResult<()> {
+        self.state.offsets.insert(consumer.to_owned(), seq);
+        self.write_state().await
+    }
+    async fn committed(&self, consumer: &str) -> EResult<Option<u64>> {
+        Ok(self.state.offsets.get(consumer).copied())
+    }
+}
+
+#[cfg(feature = "journal-sqlite")]
+pub use sqlite_journal::SqliteJournal;
+
+#[cfg(feature = "journal-sqlite")]
+mod sqlite_journal {
+    use super::{now, Entry, Journal};
+    use crate::EResult;
+    use sqlx::{
+        sqlite::{SqliteConnectOptions, SqlitePoolOptions, SqliteSynchronous},
+        ConnectOptions, Pool, Sqlite,
+    };
+    use std::str::FromStr;
+    use std::time::Duration;
+
+    /// SQLite journal
+    pub struct SqliteJournal {
+        pool: Pool<Sqlite>,
+    }
+
+    impl SqliteJournal {
+        /// Opens or creates a journal database
+        ///
+        /// # Errors
+        ///
+        /// Will return `Err` if the database can not be opened
+        pub async fn open(path: &str, timeout: Duration) -> EResult<Self> {
+            let mut connection_options =
+                SqliteConnectOptions::from_str(&format!("sqlite://{path}"))?
+                    .create_if_missing(true)
+                    .synchronous(SqliteSynchronous::Extra)
+                    .busy_timeout(timeout);
+            connection_options
+                .log_statements(log::LevelFilter::Trace)
+                .log_slow_statements(log::LevelFilter::Warn, Duration::from_secs(2));
+            let pool = SqlitePoolOptions::new()
+                .max_connections(1)
+                .acquire_timeout(timeout)
+                .connect_with(connection_options)
+                .await?;
+            // AUTOINCREMENT guarantees sequence numbers are never reused
+            sqlx::query(
+                "CREATE TABLE IF NOT EXISTS journal(seq INTEGER PRIMARY KEY AUTOINCREMENT, \
+                t REAL, topic TEXT, payload BLOB)",
+            )
+            .execute(&pool)
+            .await?;
+            sqlx::query(
+                "CREATE TABLE IF NOT EXISTS journal_offsets(consumer TEXT PRIMARY KEY, seq INTEGER)",
+            )
+            .execute(&pool)
+            .await?;
+            Ok(Self { pool })
+        }
+    }
+
+    #[allow(clippy::cast_possible_wrap, clippy::cast_sign_loss)]
+    impl Journal for SqliteJournal {
+        async fn append(&mut self, topic: &str, payload: &[u8]) -> EResult<u64> {
+            let result = sqlx::query("INSERT INTO journal (t, topic, payload) VALUES (?, ?, ?)")
+                .bind(now())
+                .bind(topic)
+                .bind(payload)
+                .execute(&self.pool)
+                .await?;
+            Ok(result.last_insert_rowid() as u64)
+        }
+        async fn range(&self, from: u64, limit: usize) -> EResult<Vec<Entry>> {
+            let rows: Vec<(i64, f64, String, Vec<u8>)> = sqlx::query_as(
+                "SELECT seq, t, topic, payload FROM journal WHERE seq >= ? ORDER BY seq LIMIT ?",
+            )
+            .bind(from as i64)
+            .bind(i64::try_from(limit).unwrap_or(i64::MAX))
+            .fetch_all(&self.pool)
+            .await?;
+            Ok(rows
+                .into_iter()
+                .map(|(seq, t, topic, payload)| Entry {
+                    seq: seq as u64,
+                    t,
+                    topic,
+                    payload,
+                })
+                .collect())
+        }
+        async fn truncate(&mut self, before: u64) -> EResult<()> {
+            sqlx::query("DELETE FROM journal WHERE seq < ?")
+                .bind(before as i64)
+                .execute(&self.pool)
+                .await?;
+            Ok(())
+        }
+        async fn last_seq(&self) -> EResult<Option<u64>> {
+            let row: (Option<i64>,) = sqlx::query_as("SELECT MAX(seq) FROM journal")
+                .fetch_one(&self.pool)
+                .await?;
+            Ok(row.0.map(|seq| seq as u64))
+        }
+        async fn commit(&mut self, consumer: &str, seq: u64) -> EResult<()> {
+            sqlx::query("INSERT OR REPLACE INTO journal_offsets (consumer, seq) VALUES (?, ?)")
+                .bind(consumer)
+                .bind(seq as i64)
+                .execute(&self.pool)
+                .await?;
+            Ok(())
+        }
+        async fn committed(&self, consumer: &str) -> EResult<Option<u64>> {
+            let row: Option<(i64,)> =
+                sqlx::query_as("SELECT seq FROM journal_offsets WHERE consumer = ?")
+                    .bind(consumer)
+                    .fetch_optional(&self.pool)
+                    .await?;
+            Ok(row.map(|r| r.0 as u64))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FileJournal, Journal, JOURNAL_FILE};
+
+    #[test]
+    fn test_file_journal() {
+        let dir = std::env::temp_dir().join(format!("eva-journal-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async {
+                let mut journal = FileJournal::open(&dir).await.unwrap();
+                for i in 0..10_u8 {
+                    assert_eq!(
+                        journal.append("ST/test", &[i; 3]).await.unwrap(),
+                        u64::from(i)
+                    );
+                }
+                let entries = journal.range(3, 4).await.unwrap();
+                assert_eq!(entries.len(), 4);
+                assert_eq!(entries[0].seq, 3);
+                assert_eq!(entries[3].payload, [6; 3]);
+                journal.commit("proc", 5).await.unwrap();
+                journal.truncate(5).await.unwrap();
+                assert_eq!(journal.len(), 5);
+                assert_eq!(journal.range(0, 100).await.unwrap()[0].seq, 5);
+                drop(journal);
+                // torn tail
+                let path = dir.join(JOURNAL_FILE);
+                let len = std::fs::metadata(&path).unwrap().len();
+                let f = std::fs::OpenOptions::new().write(true).open(&path).unwrap();
+                f.set_len(len - 3).unwrap();
+                let mut journal = FileJournal::open(&dir).await.unwrap();
+                assert_eq!(journal.len(), 4);
+                assert_eq!(journal.last_seq().await.unwrap(), Some(8));
+                assert_eq!(journal.committed("proc").await.unwrap(), Some(5));
+                // the lost entry number is not reused, as it has been persisted with the state
+                assert_eq!(journal.append("ST/test", b"x").await.unwrap(), 10);
+                // sequence numbers are not reused after full truncation
+                journal.truncate(100).await.unwrap();
+                assert!(journal.is_empty());
+                drop(journal);
+                let mut journal = FileJournal::open(&dir).await.unwrap();
+                assert_eq!(journal.append("ST/test", b"y").await.unwrap(), 11);
+                assert!(journal.committed("other").await.unwrap().is_none());
+            });
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
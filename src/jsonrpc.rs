@@ -0,0 +1,399 @@
+//! JSON-RPC 2.0 envelopes, error codes are mapped directly to ERR_CODE_* constants
+use crate::value::Value;
+use crate::{EResult, Error, ErrorKind};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+
+pub const JSONRPC_VERSION: &str = "2.0";
+
+/// Protocol version marker, serialized as "2.0", any other value is rejected on deserialization
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub struct Version;
+
+impl Serialize for Version {
+    #[inline]
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(JSONRPC_VERSION)
+    }
+}
+
+impl<'de> Deserialize<'de> for Version {
+    fn deserialize<D>(deserializer: D) -> Result<Version, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s: String = Deserialize::deserialize(deserializer)?;
+        if s == JSONRPC_VERSION {
+            Ok(Version)
+        } else {
+            Err(serde::de::Error::custom(format!(
+                "unsupported JSON-RPC version: {}",
+                s
+            )))
+        }
+    }
+}
+
+/// Request id. Requests without id are notifications, requests with null id are not
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Id {
+    Number(i64),
+    String(String),
+    Null,
+}
+
+/// Deserializes a field which may be null, distinguishing it from a missing one (None)
+fn de_present<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+impl fmt::Display for Id {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Id::Number(v) => write!(f, "{}", v),
+            Id::String(v) => write!(f, "{}", v),
+            Id::Null => write!(f, "null"),
+        }
+    }
+}
+
+impl From<i64> for Id {
+    #[inline]
+    fn from(v: i64) -> Self {
+        Id::Number(v)
+    }
+}
+
+impl From<i32> for Id {
+    #[inline]
+    fn from(v: i32) -> Self {
+        Id::Number(i64::from(v))
+    }
+}
+
+impl From<String> for Id {
+    #[inline]
+    fn from(v: String) -> Self {
+        Id::String(v)
+    }
+}
+
+impl From<&str> for Id {
+    #[inline]
+    fn from(v: &str) -> Self {
+        Id::String(v.to_owned())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Request {
+    jsonrpc: Version,
+    #[serde(
+        default,
+        deserialize_with = "de_present",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub id: Option<Id>,
+    pub method: String,
+    #[serde(default, skip_serializing_if = "Value::is_unit")]
+    pub params: Value,
+}
+
+impl Request {
+    #[inline]
+    pub fn new(id: impl Into<Id>, method: &str, params: Value) -> Self {
+        Self {
+            jsonrpc: Version,
+            id: Some(id.into()),
+            method: method.to_owned(),
+            params,
+        }
+    }
+    #[inline]
+    pub fn notification(method: &str, params: Value) -> Self {
+        Self {
+            jsonrpc: Version,
+            id: None,
+            method: method.to_owned(),
+            params,
+        }
+    }
+    #[inline]
+    pub fn is_notification(&self) -> bool {
+        self.id.is_none()
+    }
+    /// Creates a successful response for the request, returns None for notifications
+    #[inline]
+    pub fn respond(&self, result: Value) -> Option<Response> {
+        self.id
+            .as_ref()
+            .map(|id| Response::ok(Some(id.clone()), result))
+    }
+    /// Creates an error response for the request, returns None for notifications
+    #[inline]
+    pub fn respond_err(&self, err: impl Into<ResponseError>) -> Option<Response> {
+        self.id
+            .as_ref()
+            .map(|id| Response::err(Some(id.clone()), err))
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum BatchRequestRepr {
+    Single(Request),
+    Batch(Vec<Request>),
+}
+
+impl TryFrom<BatchRequestRepr> for BatchRequest {
+    type Error = Error;
+
+    fn try_from(repr: BatchRequestRepr) -> EResult<Self> {
+        match repr {
+            BatchRequestRepr::Single(r) => Ok(BatchRequest::Single(r)),
+            BatchRequestRepr::Batch(v) if v.is_empty() => {
+                Err(Error::invalid_data("empty JSON-RPC batch"))
+            }
+            BatchRequestRepr::Batch(v) => Ok(BatchRequest::Batch(v)),
+        }
+    }
+}
+
+/// A single request or a batch, empty batches are rejected on deserialization
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged, try_from = "BatchRequestRepr")]
+pub enum BatchRequest {
+    Single(Request),
+    Batch(Vec<Request>),
+}
+
+impl BatchRequest {
+    #[inline]
+    pub fn is_batch(&self) -> bool {
+        matches!(self, BatchRequest::Batch(_))
+    }
+    pub fn into_vec(self) -> Vec<Request> {
+        match self {
+            BatchRequest::Single(r) => vec![r],
+            BatchRequest::Batch(v) => v,
+        }
+    }
+    /// Packs responses into the same shape as the request was. Returns None if there is nothing
+    /// to reply (notifications only)
+    pub fn shape_response(&self, mut responses: Vec<Response>) -> Option<BatchResponse> {
+        if responses.is_empty() {
+            None
+        } else if self.is_batch() {
+            Some(BatchResponse::Batch(responses))
+        } else {
+            responses.pop().map(BatchResponse::Single)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum BatchResponse {
+    Single(Response),
+    Batch(Vec<Response>),
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ResponseError {
+    pub code: i16,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+}
+
+impl ResponseError {
+    #[inline]
+    pub fn new(code: i16, message: impl fmt::Display) -> Self {
+        Self {
+            code,
+            message: message.to_string(),
+            data: None,
+        }
+    }
+    #[inline]
+    pub fn parse(message: impl fmt::Display) -> Self {
+        Self::new(crate::ERR_CODE_PARSE, message)
+    }
+    #[inline]
+    pub fn invalid_request(message: impl fmt::Display) -> Self {
+        Self::new(crate::ERR_CODE_INVALID_REQUEST, message)
+    }
+    #[inline]
+    pub fn internal(message: impl fmt::Display) -> Self {
+        Self::new(crate::ERR_CODE_INTERNAL_RPC, message)
+    }
+    pub fn with_data(mut self, data: Value) -> Self {
+        self.data = Some(data);
+        self
+    }
+}
+
+impl From<Error> for ResponseError {
+    fn from(err: Error) -> Self {
+        Self {
+            code: err.code(),
+            message: err
+                .message()
+                .map_or_else(|| err.kind().to_string(), ToOwned::to_owned),
+            data: None,
+        }
+    }
+}
+
+impl From<ResponseError> for Error {
+    fn from(err: ResponseError) -> Self {
+        Error::new(ErrorKind::from(err.code), err.message)
+    }
+}
+
+impl fmt::Display for ResponseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.message, self.code)
+    }
+}
+
+/// Either result or error field is always present, id is serialized as null if unknown. A null
+/// result is deserialized as `Some(Value::Unit)`, a missing one as None
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Response {
+    jsonrpc: Version,
+    pub id: Option<Id>,
+    #[serde(
+        default,
+        deserialize_with = "de_present",
+        skip_serializing_if = "Option::is_none"
+    )]
+    result: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error: Option<ResponseError>,
+}
+
+impl Response {
+    #[inline]
+    pub fn ok(id: Option<Id>, result: Value) -> Self {
+        Self {
+            jsonrpc: Version,
+            id,
+            result: Some(result),
+            error: None,
+        }
+    }
+    #[inline]
+    pub fn err(id: Option<Id>, err: impl Into<ResponseError>) -> Self {
+        Self {
+            jsonrpc: Version,
+            id,
+            result: None,
+            error: Some(err.into()),
+        }
+    }
+    #[inline]
+    pub fn is_ok(&self) -> bool {
+        self.error.is_none()
+    }
+    #[inline]
+    pub fn result(&self) -> Option<&Value> {
+        self.result.as_ref()
+    }
+    #[inline]
+    pub fn error(&self) -> Option<&ResponseError> {
+        self.error.as_ref()
+    }
+    /// # Errors
+    ///
+    /// Will return `Err` with the response error or if neither result nor error is present
+    pub fn into_result(self) -> EResult<Value> {
+        if let Some(err) = self.error {
+            Err(err.into())
+        } else {
+            self.result
+                .ok_or_else(|| Error::invalid_data("JSON-RPC response has no result"))
+        }
+    }
+}
+
+impl From<(Option<Id>, EResult<Value>)> for Response {
+    fn from((id, res): (Option<Id>, EResult<Value>)) -> Self {
+        match res {
+            Ok(v) => Response::ok(id, v),
+            Err(e) => Response::err(id, e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BatchRequest, Id, Response, ResponseError};
+    use crate::{Error, ErrorKind, Value};
+
+    #[test]
+    fn test_request() {
+        let req: BatchRequest =
+            serde_json::from_str(r#"{"jsonrpc":"2.0","id":1,"method":"test","params":{"i":"x"}}"#)
+                .unwrap();
+        assert!(!req.is_batch());
+        let reqs = req.into_vec();
+        assert_eq!(reqs[0].id, Some(Id::Number(1)));
+        assert_eq!(reqs[0].method, "test");
+        let req: BatchRequest = serde_json::from_str(
+            r#"[{"jsonrpc":"2.0","id":"a","method":"test"},{"jsonrpc":"2.0","method":"n"}]"#,
+        )
+        .unwrap();
+        assert!(req.is_batch());
+        let reqs = req.into_vec();
+        assert_eq!(reqs[0].id, Some(Id::String("a".to_owned())));
+        assert!(reqs[1].is_notification());
+        assert!(reqs[1].respond(Value::Unit).is_none());
+        assert!(serde_json::from_str::<BatchRequest>(r#"{"jsonrpc":"1.0","method":"x"}"#).is_err());
+        let req: BatchRequest =
+            serde_json::from_str(r#"{"jsonrpc":"2.0","id":null,"method":"test"}"#).unwrap();
+        let req = &req.into_vec()[0];
+        assert_eq!(req.id, Some(Id::Null));
+        assert!(!req.is_notification());
+        assert_eq!(
+            serde_json::to_string(&req.respond(Value::Unit).unwrap()).unwrap(),
+            r#"{"jsonrpc":"2.0","id":null,"result":null}"#
+        );
+        assert!(serde_json::from_str::<BatchRequest>("[]").is_err());
+    }
+
+    #[test]
+    fn test_response() {
+        let resp = Response::ok(Some(1.into()), Value::Unit);
+        assert_eq!(
+            serde_json::to_string(&resp).unwrap(),
+            r#"{"jsonrpc":"2.0","id":1,"result":null}"#
+        );
+        let resp = Response::err(None, Error::not_found("no such item"));
+        assert_eq!(
+            serde_json::to_string(&resp).unwrap(),
+            r#"{"jsonrpc":"2.0","id":null,"error":{"code":-32001,"message":"no such item"}}"#
+        );
+        let err = resp.into_result().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ResourceNotFound);
+        let resp = Response::err(Some(2.into()), ResponseError::parse("bad payload"));
+        assert_eq!(resp.error().unwrap().code, crate::ERR_CODE_PARSE);
+        let resp: Response =
+            serde_json::from_str(r#"{"jsonrpc":"2.0","id":1,"result":null}"#).unwrap();
+        assert_eq!(resp.result(), Some(&Value::Unit));
+        assert_eq!(resp.into_result().unwrap(), Value::Unit);
+        let resp: Response = serde_json::from_str(r#"{"jsonrpc":"2.0","id":1}"#).unwrap();
+        assert!(resp.result().is_none());
+        assert!(resp.into_result().is_err());
+    }
+}
@@ -1,22 +1,29 @@
-//#![cfg_attr(feature = "nostd", no_std)]
+#![cfg_attr(not(feature = "std"), no_std)]
 
-//#[cfg(feature = "ext")]
-//#[macro_use]
-//extern crate lazy_static;
+extern crate alloc;
+// generated code of the derive macros refers to the crate by its name
+#[cfg(feature = "derive")]
+extern crate self as eva_common;
 
+#[allow(unused_imports)]
+use crate::alloc_prelude::*;
 use crate::value::{to_value, Value};
+use alloc::borrow::Cow;
+use alloc::collections::BTreeMap;
 #[cfg(feature = "axum")]
 use axum::http::StatusCode;
+use core::cmp::Ordering;
+use core::convert::{TryFrom, TryInto};
+use core::fmt;
+use core::hash::{Hash, Hasher};
+use core::str::FromStr;
+use core::time::Duration;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use serde_repr::{Deserialize_repr, Serialize_repr};
-use std::borrow::Cow;
-use std::cmp::Ordering;
-use std::collections::{BTreeMap, HashSet};
-use std::convert::{TryFrom, TryInto};
-use std::fmt;
-use std::hash::{BuildHasher, Hash, Hasher};
-use std::str::FromStr;
-use std::time::Duration;
+#[cfg(feature = "std")]
+use std::collections::HashSet;
+#[cfg(feature = "std")]
+use std::hash::BuildHasher;
 
 pub const LOG_LEVEL_TRACE: u8 = 0;
 pub const LOG_LEVEL_DEBUG: u8 = 10;
@@ -38,48 +45,142 @@ pub fn log_level_code(level: log::Level) -> u8 {
 
 pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
 
+#[cfg(feature = "std")]
+pub mod collections;
+#[cfg(feature = "std")]
+pub mod jsonrpc;
+#[cfg(feature = "std")]
+pub mod mapping;
+#[cfg(feature = "std")]
+pub mod oid;
+#[cfg(feature = "std")]
 pub mod op;
-mod runtime_tests;
+#[cfg(feature = "std")]
+pub mod runtime_tests;
+pub mod secret;
+#[cfg(feature = "std")]
 pub mod tools;
 
+#[cfg(feature = "std")]
 #[allow(unused_imports)]
 pub use runtime_tests::self_test;
 
+/// Items, which are not included into the core prelude in no_std builds
+mod alloc_prelude {
+    #[allow(unused_imports)]
+    pub use alloc::{
+        borrow::ToOwned,
+        boxed::Box,
+        format,
+        string::{String, ToString},
+        vec,
+        vec::Vec,
+    };
+}
+
 #[cfg(feature = "acl")]
 pub mod acl;
 #[cfg(feature = "actions")]
 pub mod actions;
+#[cfg(feature = "audit")]
+pub mod audit;
+#[cfg(feature = "auth")]
+pub mod auth;
+#[cfg(feature = "blob")]
+pub mod blob;
 #[cfg(feature = "cache")]
 pub mod cache;
 #[cfg(feature = "common-payloads")]
 pub mod common_payloads;
+#[cfg(feature = "config")]
+pub mod config;
 #[cfg(feature = "console-logger")]
 pub mod console_logger;
+#[cfg(feature = "services")]
+pub mod coordination;
 #[cfg(feature = "db")]
 pub mod db;
+#[cfg(feature = "deploy")]
+pub mod deploy;
 #[cfg(feature = "data-objects")]
 pub mod dobj;
+#[cfg(feature = "driver")]
+pub mod driver;
+#[cfg(feature = "energy")]
+pub mod energy;
+#[cfg(feature = "std")]
+pub mod eng;
 #[cfg(any(feature = "events", feature = "common-payloads", feature = "logger"))]
 pub mod events;
-//#[cfg(feature = "ext")]
-//pub mod ext;
+#[cfg(feature = "ext")]
+pub mod ext;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "file-transfer")]
+pub mod file_transfer;
+#[cfg(feature = "std")]
+pub mod fsm;
+#[doc(hidden)]
+pub mod fuzz;
+#[cfg(any(feature = "proptest", feature = "arbitrary"))]
+mod fuzzing;
+#[cfg(feature = "std")]
+pub mod geo;
+#[cfg(feature = "history")]
+pub mod history;
 #[cfg(feature = "hyper-tools")]
 pub mod hyper_tools;
+#[cfg(feature = "std")]
+pub mod i18n;
+#[cfg(feature = "inventory")]
+pub mod inventory;
+#[cfg(feature = "journal")]
+pub mod journal;
 #[cfg(feature = "logger")]
 pub mod logger;
 #[cfg(feature = "logic")]
 pub mod logic;
+#[cfg(feature = "std")]
+pub mod netfilter;
+#[cfg(feature = "notification")]
+pub mod notification;
 #[cfg(feature = "payload")]
 pub mod payload;
+pub mod problem;
+#[cfg(feature = "python")]
+pub mod python;
+#[cfg(feature = "std")]
+pub mod rate_limit;
 #[cfg(feature = "registry")]
 pub mod registry;
+#[cfg(feature = "history")]
+pub mod retention;
+#[cfg(feature = "services")]
+pub mod rpc_broadcast;
+#[cfg(feature = "services")]
+pub mod rpc_limiter;
 #[cfg(feature = "serde-keyvalue")]
 pub mod serde_keyvalue;
 #[cfg(feature = "services")]
 pub mod services;
+#[cfg(feature = "sim")]
+pub mod sim;
+#[cfg(feature = "services")]
+pub mod singleflight;
+#[cfg(feature = "snapshot")]
+pub mod snapshot;
+#[cfg(feature = "time")]
+pub mod spc;
+#[cfg(feature = "testkit")]
+pub mod testkit;
 #[cfg(feature = "time")]
 pub mod time;
+#[cfg(feature = "history")]
+pub mod timeseries;
+#[cfg(feature = "std")]
 pub mod transform;
+#[cfg(feature = "webhook")]
+pub mod webhook;
 #[cfg(feature = "workers")]
 pub mod workers;
 
@@ -109,7 +210,7 @@ pub fn get_default_sleep_step() -> Duration {
     SLEEP_STEP
 }
 
-pub type EResult<T> = std::result::Result<T, Error>;
+pub type EResult<T> = core::result::Result<T, Error>;
 
 pub type ItemStatus = i16;
 
@@ -239,7 +340,7 @@ impl From<i16> for ErrorKind {
     }
 }
 
-impl std::fmt::Display for ErrorKind {
+impl fmt::Display for ErrorKind {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
@@ -285,6 +386,7 @@ pub struct Error {
     message: Option<Cow<'static, str>>,
 }
 
+#[cfg(feature = "std")]
 impl std::error::Error for Error {}
 
 macro_rules! impl_err_error {
@@ -297,14 +399,18 @@ macro_rules! impl_err_error {
     };
 }
 
-impl_err_error!(std::string::FromUtf8Error, Error::invalid_data);
-impl_err_error!(std::fmt::Error, Error::failed);
-impl_err_error!(std::str::Utf8Error, Error::invalid_data);
-impl_err_error!(std::num::ParseIntError, Error::invalid_data);
-impl_err_error!(std::num::ParseFloatError, Error::invalid_data);
-impl_err_error!(std::num::TryFromIntError, Error::invalid_data);
+impl_err_error!(alloc::string::FromUtf8Error, Error::invalid_data);
+impl_err_error!(fmt::Error, Error::failed);
+impl_err_error!(core::str::Utf8Error, Error::invalid_data);
+impl_err_error!(core::num::ParseIntError, Error::invalid_data);
+impl_err_error!(core::num::ParseFloatError, Error::invalid_data);
+impl_err_error!(core::num::TryFromIntError, Error::invalid_data);
+#[cfg(feature = "std")]
 impl_err_error!(ipnetwork::IpNetworkError, Error::invalid_data);
 impl_err_error!(serde_json::Error, Error::invalid_data);
+#[cfg(feature = "json-fast")]
+impl_err_error!(simd_json::Error, Error::invalid_data);
+#[cfg(feature = "std")]
 impl_err_error!(std::io::Error, Error::io);
 #[cfg(feature = "bus-rpc")]
 impl_err_error!(busrt::Error, Error::io);
@@ -316,7 +422,9 @@ impl_err_error!(tokio::sync::TryLockError, Error::core);
 impl_err_error!(rmp_serde::encode::Error, Error::invalid_data);
 #[cfg(feature = "payload")]
 impl_err_error!(rmp_serde::decode::Error, Error::invalid_data);
-impl_err_error!(std::array::TryFromSliceError, Error::invalid_data);
+impl_err_error!(core::array::TryFromSliceError, Error::invalid_data);
+#[cfg(feature = "ext")]
+impl_err_error!(libloading::Error, Error::failed);
 #[cfg(feature = "db")]
 impl_err_error!(yedb::Error, Error::registry);
 #[cfg(any(feature = "db", feature = "cache"))]
@@ -386,8 +494,8 @@ impl From<tokio::task::JoinError> for Error {
     }
 }
 
-impl From<std::convert::Infallible> for Error {
-    fn from(_err: std::convert::Infallible) -> Error {
+impl From<core::convert::Infallible> for Error {
+    fn from(_err: core::convert::Infallible) -> Error {
         panic!();
     }
 }
@@ -566,7 +674,7 @@ impl Error {
     }
 }
 
-impl std::fmt::Display for Error {
+impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         if let Some(msg) = self.message.as_ref() {
             write!(f, "{}: {}", self.kind, msg)
@@ -579,26 +687,13 @@ impl std::fmt::Display for Error {
 #[cfg(feature = "axum")]
 impl From<Error> for (StatusCode, String) {
     fn from(e: Error) -> Self {
-        let code = match e.kind() {
-            ErrorKind::NotReady => StatusCode::SERVICE_UNAVAILABLE,
-            ErrorKind::ResourceNotFound => StatusCode::NOT_FOUND,
-            ErrorKind::ResourceBusy => StatusCode::LOCKED,
-            ErrorKind::ResourceAlreadyExists => StatusCode::CONFLICT,
-            ErrorKind::AccessDenied
-            | ErrorKind::AccessDeniedMoreDataRequired
-            | ErrorKind::EvaHIAuthenticationRequired
-            | ErrorKind::TokenRestricted => StatusCode::FORBIDDEN,
-            ErrorKind::MethodNotFound
-            | ErrorKind::MethodNotImplemented
-            | ErrorKind::InvalidParameter => StatusCode::BAD_REQUEST,
-            ErrorKind::Timeout => StatusCode::REQUEST_TIMEOUT,
-            _ => StatusCode::INTERNAL_SERVER_ERROR,
-        };
+        let code = StatusCode::from_u16(problem::http_status(e.kind()))
+            .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
         (code, e.message.map(|v| v.to_string()).unwrap_or_default())
     }
 }
 
-#[derive(Debug, Eq, PartialEq, Copy, Clone, Serialize, Deserialize)]
+#[derive(Debug, Eq, PartialEq, Copy, Clone, Hash, Serialize, Deserialize)]
 pub struct IEID(u64, u64);
 
 impl IEID {
@@ -672,15 +767,92 @@ impl TryFrom<&Value> for IEID {
 }
 
 impl PartialOrd for IEID {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for IEID {
+    fn cmp(&self, other: &Self) -> Ordering {
         match self.0.cmp(&other.0) {
-            Ordering::Less => Some(Ordering::Less),
-            Ordering::Greater => Some(Ordering::Greater),
-            Ordering::Equal => self.1.partial_cmp(&other.1),
+            Ordering::Equal => self.1.cmp(&other.1),
+            v => v,
+        }
+    }
+}
+
+/// How OID parsers treat item kinds, unknown to this build
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub enum KindParseMode {
+    /// Unknown kinds are rejected
+    #[default]
+    Strict,
+    /// Unknown kinds are accepted as [`ItemKind::Other`]
+    Lenient,
+}
+
+#[inline]
+fn is_valid_kind_name(s: &str) -> bool {
+    !s.is_empty()
+        && s.len() <= 32
+        && s.chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+}
+
+/// OID validation profile
+///
+/// IDs, valid for profiles other than [`OidProfile::Strict`], can be converted to the strict form
+/// with [`OID::to_strict`]
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub enum OidProfile {
+    /// Alphanumeric characters and [`OID_ALLOWED_SYMBOLS`]
+    #[default]
+    Strict,
+    /// Strict plus [`OID_LEGACY_SYMBOLS`]
+    Legacy,
+    /// Any characters except control ones and colons
+    Relaxed,
+}
+
+impl OidProfile {
+    #[inline]
+    fn allows(self, c: char) -> bool {
+        if c.is_alphanumeric() || OID_ALLOWED_SYMBOLS.contains(c) {
+            return true;
+        }
+        match self {
+            OidProfile::Strict => false,
+            OidProfile::Legacy => OID_LEGACY_SYMBOLS.contains(c),
+            OidProfile::Relaxed => !c.is_control() && c != ':',
         }
     }
 }
 
+/// Escapes symbols, not allowed by [`OidProfile::Strict`], except slashes
+///
+/// Each UTF-8 byte of such symbol is replaced with `\xHH` (lowercase hex), e.g. `room 1%` becomes
+/// `room\x201\x25`. Backslashes are escaped as well (`\x5c`), so different IDs are never
+/// escaped to the same one. As a result, escaping is not idempotent for IDs with backslashes
+pub fn escape_oid_id(s: &str) -> Cow<'_, str> {
+    use core::fmt::Write as _;
+    let keep = |c: char| c == '/' || (c != '\\' && OidProfile::Strict.allows(c));
+    if s.chars().all(keep) {
+        return Cow::Borrowed(s);
+    }
+    let mut result = String::with_capacity(s.len() + 8);
+    let mut buf = [0; 4];
+    for c in s.chars() {
+        if keep(c) {
+            result.push(c);
+        } else {
+            for b in c.encode_utf8(&mut buf).bytes() {
+                write!(result, "\\x{:02x}", b).unwrap();
+            }
+        }
+    }
+    Cow::Owned(result)
+}
+
 #[derive(Clone, Eq)]
 pub struct OID {
     kind: ItemKind,
@@ -699,7 +871,9 @@ impl PartialEq for OID {
 impl Ord for OID {
     fn cmp(&self, other: &Self) -> Ordering {
         if self.kind == other.kind {
-            self.full_id().cmp(other.full_id())
+            self.kind_str()
+                .cmp(other.kind_str())
+                .then_with(|| self.full_id().cmp(other.full_id()))
         } else {
             self.kind.cmp(&other.kind)
         }
@@ -713,6 +887,8 @@ impl PartialOrd for OID {
 }
 
 pub const OID_ALLOWED_SYMBOLS: &str = "_.()[]-\\";
+/// Additional symbols, allowed in OIDs with [`OidProfile::Legacy`]
+pub const OID_LEGACY_SYMBOLS: &str = " %@,=&";
 pub const OID_MASK_ALLOWED_SYMBOLS: &str = "^$~_.(){}|[]-+?#*\\";
 
 pub const OID_MASK_PREFIX_FORMULA: &str = "f~";
@@ -721,11 +897,14 @@ pub const OID_MASK_PREFIX_REGEX: &str = "r~";
 impl OID {
     #[inline]
     fn check(s: &str, is_path: bool) -> EResult<()> {
+        OID::check_with(s, is_path, OidProfile::Strict)
+    }
+    fn check_with(s: &str, is_path: bool, profile: OidProfile) -> EResult<()> {
         if s.len() > 65000 {
             return Err(Error::invalid_data("OID too long"));
         }
         for c in s.chars() {
-            if !(c.is_alphanumeric() || OID_ALLOWED_SYMBOLS.contains(c) || (is_path && c == '/')) {
+            if !(profile.allows(c) || (is_path && c == '/')) {
                 return Err(Error::invalid_data(format!("Invalid symbol in OID: {}", c)));
             }
         }
@@ -733,6 +912,7 @@ impl OID {
     }
     #[allow(clippy::cast_possible_truncation)]
     pub fn new(kind: ItemKind, group: &str, id: &str) -> EResult<Self> {
+        OID::check_kind(kind)?;
         OID::check(group, true)?;
         OID::check(id, false)?;
         if group == "+" || id == "+" {
@@ -757,27 +937,38 @@ impl OID {
         }
     }
     #[inline]
+    fn check_kind(kind: ItemKind) -> EResult<()> {
+        if kind == ItemKind::Other {
+            Err(Error::invalid_data(
+                "OID can not be created for an unknown kind",
+            ))
+        } else {
+            Ok(())
+        }
+    }
+    #[inline]
     pub fn new0(kind: ItemKind, id: &str) -> EResult<Self> {
-        Self::_new0(kind, id, true)
+        OID::check_kind(kind)?;
+        Self::_new0(kind, kind.as_str(), id, Some(OidProfile::Strict))
     }
     #[inline]
     pub fn new0_unchecked(kind: ItemKind, id: &str) -> EResult<Self> {
-        Self::_new0(kind, id, false)
+        OID::check_kind(kind)?;
+        Self::_new0(kind, kind.as_str(), id, None)
     }
     #[allow(clippy::cast_possible_truncation)]
-    fn _new0(kind: ItemKind, id: &str, need_check: bool) -> EResult<Self> {
-        if need_check {
-            OID::check(id, true)?;
+    fn _new0(kind: ItemKind, tp_str: &str, id: &str, check: Option<OidProfile>) -> EResult<Self> {
+        if let Some(profile) = check {
+            OID::check_with(id, true, profile)?;
         }
-        let tp_str = kind.to_string();
         if id.is_empty() {
             Err(Error::invalid_data(ERR_INVALID_OID))
         } else if id.len() + tp_str.len() >= u16::MAX as usize {
             Err(Error::invalid_data(ERR_OID_TOO_LONG))
         } else {
             let grp_pos = id.rfind('/').map(|p| p as u16 + tp_str.len() as u16 + 1);
-            let oid_str = format!("{}:{}", kind, id);
-            let path_str = format!("{}/{}", kind, id);
+            let oid_str = format!("{}:{}", tp_str, id);
+            let path_str = format!("{}/{}", tp_str, id);
             Ok(Self {
                 kind,
                 oid_str,
@@ -807,6 +998,11 @@ impl OID {
     pub fn kind(&self) -> ItemKind {
         self.kind
     }
+    /// Kind name as in the OID string, differs from the kind for unknown kinds only
+    #[inline]
+    pub fn kind_str(&self) -> &str {
+        &self.oid_str[..self.tpos as usize - 1]
+    }
     #[inline]
     pub fn as_str(&self) -> &str {
         &self.oid_str
@@ -821,7 +1017,7 @@ impl OID {
     }
     #[inline]
     pub fn to_wildcard_str(&self, wildcard_suffix: &str) -> String {
-        let mut s = format!("{}:", self.kind);
+        let mut s = format!("{}:", self.kind_str());
         if let Some(group) = self.group() {
             s = s + group + "/";
         }
@@ -833,7 +1029,7 @@ impl OID {
         target.insert("full_id".into(), self.full_id().into());
         target.insert("id".into(), self.id().into());
         target.insert("group".into(), self.group().map_or(Value::Unit, Into::into));
-        target.insert("type".into(), self.kind.into());
+        target.insert("type".into(), self.kind_str().into());
     }
     pub fn from_str_type(tp: ItemKind, s: &str) -> EResult<Self> {
         if let Some(tpos) = s.find(':') {
@@ -854,15 +1050,139 @@ impl OID {
     pub fn from_path(s: &str) -> EResult<Self> {
         Self::parse_oid(s, '/')
     }
+    /// Re-roots the OID under the group prefix, e.g. sensor:tests/s1 with the prefix "tenant1"
+    /// becomes sensor:tenant1/tests/s1
+    pub fn with_group_prefix(&self, prefix: &str) -> EResult<Self> {
+        let prefix = OID::check_group_prefix(prefix)?;
+        Self::_new0(
+            self.kind,
+            self.kind_str(),
+            &format!("{}/{}", prefix, self.full_id()),
+            None,
+        )
+    }
+    /// Returns true if the OID group starts with the prefix
+    pub fn has_group_prefix(&self, prefix: &str) -> bool {
+        OID::check_group_prefix(prefix).is_ok_and(|prefix| {
+            self.full_id()
+                .strip_prefix(prefix)
+                .is_some_and(|s| s.starts_with('/'))
+        })
+    }
+    /// Strips the group prefix, the reverse operation for [`OID::with_group_prefix`]
+    pub fn strip_group_prefix(&self, prefix: &str) -> EResult<Self> {
+        let prefix = OID::check_group_prefix(prefix)?;
+        let full_id = self
+            .full_id()
+            .strip_prefix(prefix)
+            .and_then(|s| s.strip_prefix('/'))
+            .ok_or_else(|| {
+                Error::invalid_data(format!("OID {} is not under the prefix {}", self, prefix))
+            })?;
+        Self::_new0(self.kind, self.kind_str(), full_id, None)
+    }
+    /// Checks the group prefix and returns it with leading/trailing slashes trimmed
+    pub(crate) fn check_group_prefix(prefix: &str) -> EResult<&str> {
+        let prefix = prefix.trim_matches('/');
+        if prefix.is_empty() || prefix.split('/').any(str::is_empty) {
+            return Err(Error::invalid_data(format!(
+                "invalid group prefix: {}",
+                prefix
+            )));
+        }
+        OID::check(prefix, true)?;
+        Ok(prefix)
+    }
     #[inline]
     fn parse_oid(s: &str, c: char) -> EResult<Self> {
-        s.find(c).map_or(
-            Err(Error::invalid_data(format!("{}: {}", ERR_INVALID_OID, s))),
-            |tpos| {
-                let tp: ItemKind = s[..tpos].parse()?;
-                Self::new0(tp, &s[tpos + 1..])
-            },
-        )
+        Self::parse_oid_with_mode(s, c, KindParseMode::Strict)
+    }
+    #[inline]
+    fn parse_oid_with_mode(s: &str, c: char, mode: KindParseMode) -> EResult<Self> {
+        Self::parse_oid_with_profile(s, c, mode, OidProfile::Strict)
+    }
+    fn parse_oid_with_profile(
+        s: &str,
+        c: char,
+        mode: KindParseMode,
+        profile: OidProfile,
+    ) -> EResult<Self> {
+        let Some(tpos) = s.find(c) else {
+            return Err(Error::invalid_data(format!("{}: {}", ERR_INVALID_OID, s)));
+        };
+        let tp_str = &s[..tpos];
+        let id = &s[tpos + 1..];
+        match (tp_str.parse::<ItemKind>(), mode) {
+            (Ok(tp), _) => Self::_new0(tp, tp.as_str(), id, Some(profile)),
+            (Err(_), KindParseMode::Lenient) if is_valid_kind_name(tp_str) => {
+                Self::_new0(ItemKind::Other, tp_str, id, Some(profile))
+            }
+            (Err(e), _) => Err(e),
+        }
+    }
+    /// Parses the OID with the validation profile
+    #[inline]
+    pub fn parse_with(s: &str, profile: OidProfile) -> EResult<Self> {
+        Self::parse_oid_with_profile(s, ':', KindParseMode::Strict, profile)
+    }
+    /// Returns true if the OID is valid for [`OidProfile::Strict`]
+    pub fn is_strict(&self) -> bool {
+        self.full_id()
+            .chars()
+            .all(|c| c == '/' || OidProfile::Strict.allows(c))
+    }
+    /// Parses the OID, normalizing it to Unicode NFC. Must be used for sources which may
+    /// provide the same IDs in different normal forms
+    #[cfg(feature = "oid-nfc")]
+    pub fn parse_nfc(s: &str) -> EResult<Self> {
+        use unicode_normalization::UnicodeNormalization as _;
+        if unicode_normalization::is_nfc(s) {
+            s.parse()
+        } else {
+            s.nfc().collect::<String>().parse()
+        }
+    }
+    /// Returns the OID, normalized to Unicode NFC
+    #[cfg(feature = "oid-nfc")]
+    pub fn to_nfc(&self) -> EResult<Self> {
+        use unicode_normalization::UnicodeNormalization as _;
+        if unicode_normalization::is_nfc(self.full_id()) {
+            Ok(self.clone())
+        } else {
+            Self::_new0(
+                self.kind,
+                self.kind_str(),
+                &self.full_id().nfc().collect::<String>(),
+                Some(OidProfile::Relaxed),
+            )
+        }
+    }
+    /// Converts the OID to the strict form, escaping symbols with [`escape_oid_id`]
+    pub fn to_strict(&self) -> EResult<Self> {
+        match escape_oid_id(self.full_id()) {
+            Cow::Borrowed(_) => Ok(self.clone()),
+            Cow::Owned(full_id) => Self::_new0(
+                self.kind,
+                self.kind_str(),
+                &full_id,
+                Some(OidProfile::Strict),
+            ),
+        }
+    }
+    /// Parses the OID with the kind parse mode
+    #[inline]
+    pub fn parse_with_mode(s: &str, mode: KindParseMode) -> EResult<Self> {
+        Self::parse_oid_with_mode(s, ':', mode)
+    }
+    /// Parses the OID, accepting kinds, unknown to this build, as [`ItemKind::Other`]. Should be
+    /// used by services which forward or replicate items as-is
+    #[inline]
+    pub fn parse_lenient(s: &str) -> EResult<Self> {
+        Self::parse_oid_with_mode(s, ':', KindParseMode::Lenient)
+    }
+    #[inline]
+    pub fn from_path_with_mode(s: &str, mode: KindParseMode) -> EResult<Self> {
+        Self::parse_oid_with_mode(s, '/', mode)
     }
 }
 
@@ -966,6 +1286,87 @@ impl TryFrom<Value> for OID {
     }
 }
 
+/// OID wrapper, which is compared and hashed case-insensitively, e.g. for lookups of items,
+/// coming from sources with inconsistent casing. The wrapped OID is kept as-is
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct CaseInsensitiveOid(OID);
+
+impl CaseInsensitiveOid {
+    #[inline]
+    pub fn new(oid: OID) -> Self {
+        Self(oid)
+    }
+    #[inline]
+    pub fn oid(&self) -> &OID {
+        &self.0
+    }
+    #[inline]
+    pub fn into_oid(self) -> OID {
+        self.0
+    }
+    #[inline]
+    fn folded(&self) -> impl Iterator<Item = char> + '_ {
+        self.0.as_str().chars().flat_map(char::to_lowercase)
+    }
+}
+
+impl From<OID> for CaseInsensitiveOid {
+    #[inline]
+    fn from(oid: OID) -> Self {
+        Self(oid)
+    }
+}
+
+impl FromStr for CaseInsensitiveOid {
+    type Err = Error;
+    #[inline]
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse().map(Self)
+    }
+}
+
+impl PartialEq for CaseInsensitiveOid {
+    fn eq(&self, other: &Self) -> bool {
+        self.folded().eq(other.folded())
+    }
+}
+
+impl Eq for CaseInsensitiveOid {}
+
+impl Ord for CaseInsensitiveOid {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.folded().cmp(other.folded())
+    }
+}
+
+impl PartialOrd for CaseInsensitiveOid {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Hash for CaseInsensitiveOid {
+    fn hash<H: Hasher>(&self, hasher: &mut H) {
+        for c in self.folded() {
+            c.hash(hasher);
+        }
+    }
+}
+
+impl fmt::Display for CaseInsensitiveOid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl fmt::Debug for CaseInsensitiveOid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.0)
+    }
+}
+
+#[cfg(feature = "std")]
 impl<S: BuildHasher + Default> TryFrom<Value> for HashSet<OID, S> {
     type Error = Error;
     fn try_from(value: Value) -> EResult<HashSet<OID, S>> {
@@ -989,30 +1390,84 @@ impl<S: BuildHasher + Default> TryFrom<Value> for HashSet<OID, S> {
     }
 }
 
+#[cfg(feature = "std")]
 impl<S: BuildHasher> From<HashSet<OID, S>> for Value {
     fn from(v: HashSet<OID, S>) -> Value {
         Value::Seq(v.iter().map(|oid| to_value(oid).unwrap()).collect())
     }
 }
 
+/// Item kind. Numeric codes are stable and must never be reused
 #[derive(Debug, Eq, PartialEq, Copy, Clone, Ord, PartialOrd, Hash)]
 #[repr(u16)]
 pub enum ItemKind {
     Unit = 100,
     Sensor = 101,
     Lvar = 200,
+    Counter = 201,
     Lmacro = 300,
+    /// PLC program
+    Prog = 301,
+    Alarm = 400,
+    /// A kind, unknown to this build, accepted by [`OID::parse_lenient`]. The original kind name
+    /// is kept by the OID (see [`OID::kind_str`])
+    Other = 0,
 }
 
 impl ItemKind {
+    pub const ALL: [ItemKind; 7] = [
+        ItemKind::Unit,
+        ItemKind::Sensor,
+        ItemKind::Lvar,
+        ItemKind::Counter,
+        ItemKind::Lmacro,
+        ItemKind::Prog,
+        ItemKind::Alarm,
+    ];
     pub fn as_str(&self) -> &str {
         match self {
             ItemKind::Unit => "unit",
             ItemKind::Sensor => "sensor",
             ItemKind::Lvar => "lvar",
+            ItemKind::Counter => "counter",
             ItemKind::Lmacro => "lmacro",
+            ItemKind::Prog => "prog",
+            ItemKind::Alarm => "alarm",
+            ItemKind::Other => "other",
         }
     }
+    /// Short code
+    pub fn as_code(&self) -> &str {
+        match self {
+            ItemKind::Unit => "U",
+            ItemKind::Sensor => "S",
+            ItemKind::Lvar => "LV",
+            ItemKind::Counter => "CT",
+            ItemKind::Lmacro => "K",
+            ItemKind::Prog => "PR",
+            ItemKind::Alarm => "AL",
+            ItemKind::Other => "?",
+        }
+    }
+    /// Kinds, known by all peers (unit, sensor, lvar and lmacro). Items of other kinds should not
+    /// be sent to peers, which do not declare support for them
+    #[inline]
+    pub fn is_legacy(&self) -> bool {
+        matches!(
+            self,
+            ItemKind::Unit | ItemKind::Sensor | ItemKind::Lvar | ItemKind::Lmacro
+        )
+    }
+}
+
+impl TryFrom<u16> for ItemKind {
+    type Error = Error;
+    fn try_from(code: u16) -> Result<Self, Self::Error> {
+        ItemKind::ALL
+            .into_iter()
+            .find(|k| *k as u16 == code)
+            .ok_or_else(|| Error::invalid_data(format!("invalid item kind code: {}", code)))
+    }
 }
 
 impl fmt::Display for ItemKind {
@@ -1034,7 +1489,10 @@ impl FromStr for ItemKind {
             "unit" | "U" => Ok(ItemKind::Unit),
             "sensor" | "S" => Ok(ItemKind::Sensor),
             "lvar" | "LV" => Ok(ItemKind::Lvar),
+            "counter" | "CT" => Ok(ItemKind::Counter),
             "lmacro" | "K" => Ok(ItemKind::Lmacro),
+            "prog" | "PR" => Ok(ItemKind::Prog),
+            "alarm" | "AL" => Ok(ItemKind::Alarm),
             _ => Err(Error::new(
                 ErrorKind::InvalidData,
                 format!("Invalid item type: {}", s),
@@ -1087,6 +1545,128 @@ mod tests {
     use super::{Error, ItemKind, Value, IEID, OID};
     use std::convert::TryInto;
 
+    #[test]
+    fn test_item_kind() {
+        #[derive(serde::Deserialize)]
+        struct Payload {
+            #[serde(deserialize_with = "crate::tools::de_oids_skip_unknown_kinds")]
+            oids: Vec<OID>,
+        }
+        for kind in ItemKind::ALL {
+            assert_eq!(kind.as_str().parse::<ItemKind>().unwrap(), kind);
+            assert_eq!(kind.as_code().parse::<ItemKind>().unwrap(), kind);
+            assert_eq!(ItemKind::try_from(kind as u16).unwrap(), kind);
+        }
+        assert!(ItemKind::try_from(102).is_err());
+        let oid: OID = "prog:plc1/main".parse().unwrap();
+        assert_eq!(oid.kind(), ItemKind::Prog);
+        assert!(!oid.kind().is_legacy());
+        assert!(ItemKind::Lmacro.is_legacy());
+        let p: Payload =
+            serde_json::from_str(r#"{"oids":["sensor:s1","future:f1","alarm:a1"]}"#).unwrap();
+        assert_eq!(p.oids.len(), 2);
+        assert!(serde_json::from_str::<Payload>(r#"{"oids":["sensor:"]}"#).is_err());
+    }
+
+    #[test]
+    fn test_oid_profiles() {
+        use super::OidProfile;
+        assert!("sensor:room 1/t%".parse::<OID>().is_err());
+        assert!(OID::parse_with("sensor:room 1/t\u{2103}", OidProfile::Legacy).is_err());
+        let oid = OID::parse_with("sensor:room 1/t%", OidProfile::Legacy).unwrap();
+        assert!(!oid.is_strict());
+        assert_eq!(oid.group(), Some("room 1"));
+        let strict = oid.to_strict().unwrap();
+        assert!(strict.is_strict());
+        assert_eq!(strict.as_str(), "sensor:room\\x201/t\\x25");
+        assert_eq!(strict, strict.as_str().parse().unwrap());
+        assert_eq!(strict, oid.to_strict().unwrap());
+        let oid = OID::parse_with("sensor:g/t\u{2103}", OidProfile::Relaxed).unwrap();
+        assert_eq!(oid.to_strict().unwrap().id(), "t\\xe2\\x84\\x83");
+        assert!(OID::parse_with("sensor:g/t\n", OidProfile::Relaxed).is_err());
+        let oid: OID = "sensor:g/t1".parse().unwrap();
+        assert!(oid.is_strict());
+        assert_eq!(oid.to_strict().unwrap(), oid);
+        let escaped = OID::parse_with("sensor:g/t\\x25", OidProfile::Strict).unwrap();
+        let oid = OID::parse_with("sensor:g/t%", OidProfile::Legacy).unwrap();
+        assert_ne!(escaped.to_strict().unwrap(), oid.to_strict().unwrap());
+        assert_eq!(escaped.to_strict().unwrap().id(), "t\\x5cx25");
+    }
+
+    #[test]
+    fn test_oid_case_insensitive() {
+        use super::CaseInsensitiveOid;
+        use std::collections::HashSet;
+        let a: CaseInsensitiveOid = "sensor:OPC/Temp1".parse().unwrap();
+        let b: CaseInsensitiveOid = "sensor:opc/TEMP1".parse().unwrap();
+        assert_eq!(a, b);
+        assert_ne!(a.oid(), b.oid());
+        let set: HashSet<CaseInsensitiveOid> = [a.clone(), b].into_iter().collect();
+        assert_eq!(set.len(), 1);
+        assert!(set.contains(&"sensor:Opc/temp1".parse().unwrap()));
+        assert_ne!(a, "sensor:opc/temp2".parse().unwrap());
+        assert_eq!(a.into_oid().as_str(), "sensor:OPC/Temp1");
+    }
+
+    #[cfg(feature = "oid-nfc")]
+    #[test]
+    fn test_oid_nfc() {
+        // combining marks are not alphanumeric
+        assert!("sensor:g/cafe\u{301}".parse::<OID>().is_err());
+        let nfd = OID::parse_with("sensor:g/cafe\u{301}", super::OidProfile::Relaxed).unwrap();
+        let nfc: OID = "sensor:g/caf\u{e9}".parse().unwrap();
+        assert_ne!(nfd, nfc);
+        assert_eq!(nfd.to_nfc().unwrap(), nfc);
+        assert_eq!(OID::parse_nfc("sensor:g/cafe\u{301}").unwrap(), nfc);
+        assert_eq!(OID::parse_nfc("sensor:g/caf\u{e9}").unwrap(), nfc);
+    }
+
+    #[test]
+    fn test_oid_unknown_kind() {
+        assert!("future:tests/f1".parse::<OID>().is_err());
+        let oid = OID::parse_lenient("future:tests/f1").unwrap();
+        assert_eq!(oid.kind(), ItemKind::Other);
+        assert_eq!(oid.kind_str(), "future");
+        assert_eq!(oid.as_str(), "future:tests/f1");
+        assert_eq!(oid.as_path(), "future/tests/f1");
+        assert_eq!(oid.full_id(), "tests/f1");
+        assert_eq!(oid.to_wildcard_str("#"), "future:tests/#");
+        let prefixed = oid.with_group_prefix("t1").unwrap();
+        assert_eq!(prefixed.as_str(), "future:t1/tests/f1");
+        assert_eq!(prefixed.strip_group_prefix("t1").unwrap(), oid);
+        let oid =
+            OID::from_path_with_mode("future/tests/f1", super::KindParseMode::Lenient).unwrap();
+        assert_eq!(oid.as_str(), "future:tests/f1");
+        assert_eq!(
+            OID::parse_lenient("sensor:s1").unwrap().kind(),
+            ItemKind::Sensor
+        );
+        assert!(OID::parse_lenient("Fut ure:f1").is_err());
+        assert!(OID::new0(ItemKind::Other, "f1").is_err());
+        assert!(OID::parse_lenient("future:f1").unwrap() > OID::parse_lenient("alpha:f1").unwrap());
+    }
+
+    #[test]
+    fn test_oid_group_prefix() {
+        let oid: OID = "sensor:tests/s1".parse().unwrap();
+        let prefixed = oid.with_group_prefix("/tenant1/").unwrap();
+        assert_eq!(prefixed.as_str(), "sensor:tenant1/tests/s1");
+        assert_eq!(prefixed.group().unwrap(), "tenant1/tests");
+        assert!(prefixed.has_group_prefix("tenant1"));
+        assert!(!prefixed.has_group_prefix("tenant"));
+        assert_eq!(prefixed.strip_group_prefix("tenant1").unwrap(), oid);
+        assert!(oid.strip_group_prefix("tenant1").is_err());
+        let oid: OID = "unit:u1".parse().unwrap();
+        assert_eq!(
+            oid.with_group_prefix("t1/t2").unwrap().as_str(),
+            "unit:t1/t2/u1"
+        );
+        assert!(oid.with_group_prefix("").is_err());
+        assert!(oid.with_group_prefix("t1//t2").is_err());
+        assert!(oid.with_group_prefix("t+").is_err());
+        assert!(oid.with_group_prefix(&"x".repeat(70000)).is_err());
+    }
+
     #[test]
     fn test_oid() {
         let oid: OID = "sensor:env/room1/temp1".parse().unwrap();
@@ -1,11 +1,16 @@
 use crate::tools::default_true;
 use crate::value::Value;
-use crate::Error;
+use crate::{Error, ItemStatus, ITEM_STATUS_ERROR};
 use serde::de::{self, MapAccess, Visitor};
 use serde::{Deserialize, Deserializer, Serialize};
 use std::fmt;
 use std::marker::PhantomData;
 use std::str::FromStr;
+use std::sync::atomic::{self, AtomicU64};
+use std::sync::Arc;
+use std::time::Duration;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
 
 const ERR_INVALID_RANGE_CONDITION: &str = "Invalid range condition";
 
@@ -136,9 +141,11 @@ impl FromStr for Range {
                             if i > 1 {
                                 return Err(Error::invalid_data(ERR_INVALID_RANGE_CONDITION));
                             }
+                            // vals are split by ASCII symbols, so byte offsets are safe
                             let s = c
-                                .chars()
-                                .nth(vals[0].len())
+                                .as_bytes()
+                                .get(vals[0].len())
+                                .map(|b| char::from(*b))
                                 .ok_or_else(|| Error::invalid_data(ERR_INVALID_RANGE_CONDITION))?;
                             if s == '=' {
                                 r_inspected_min = Some(vals[1 - i].parse()?);
@@ -160,8 +167,11 @@ impl FromStr for Range {
                             if i != 1 {
                                 return Err(Error::invalid_data(ERR_INVALID_RANGE_CONDITION));
                             }
-                            let s1_ch = c.chars().nth(vals[0].len());
-                            let s2_ch = c.chars().nth(vals[0].len() + 2);
+                            let s1_ch = c.as_bytes().get(vals[0].len()).map(|b| char::from(*b));
+                            let s2_ch = c
+                                .as_bytes()
+                                .get(vals[0].len() + vals[1].len() + 1)
+                                .map(|b| char::from(*b));
                             if let Some(s1) = s1_ch {
                                 if let Some(s2) = s2_ch {
                                     if s2 == '}' || s2 == '>' {
@@ -177,7 +187,10 @@ impl FromStr for Range {
                                     }
                                 }
                             }
-                            if r_inspected_max.unwrap() <= r_inspected_min.unwrap() {
+                            let (Some(max), Some(min)) = (r_inspected_max, r_inspected_min) else {
+                                return Err(Error::invalid_data(ERR_INVALID_RANGE_CONDITION));
+                            };
+                            if max <= min {
                                 return Err(Error::invalid_data(ERR_INVALID_RANGE_CONDITION));
                             }
                         }
@@ -284,10 +297,205 @@ where
     deserializer.deserialize_any(StringOrStruct(PhantomData))
 }
 
+/// Clock source for timers, returns the monotonic time elapsed since an arbitrary point
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Duration;
+}
+
+/// The default clock, based on [`std::time::Instant`]. On wasm32, where `Instant` is not
+/// available, the clock uses performance.now() (or the real time if the performance API is not
+/// available)
+#[derive(Debug, Clone, Copy)]
+pub struct MonotonicClock {
+    #[cfg(not(target_arch = "wasm32"))]
+    start: Instant,
+    /// milliseconds
+    #[cfg(target_arch = "wasm32")]
+    start: f64,
+}
+
+#[cfg(target_arch = "wasm32")]
+fn performance_now() -> f64 {
+    use js_sys::{Function, Reflect};
+    use wasm_bindgen::JsCast;
+    Reflect::get(&js_sys::global(), &"performance".into())
+        .ok()
+        .filter(|perf| perf.is_object())
+        .and_then(|perf| {
+            let now = Reflect::get(&perf, &"now".into()).ok()?;
+            now.dyn_ref::<Function>()?.call0(&perf).ok()?.as_f64()
+        })
+        .unwrap_or_else(js_sys::Date::now)
+}
+
+impl Default for MonotonicClock {
+    fn default() -> Self {
+        Self {
+            #[cfg(not(target_arch = "wasm32"))]
+            start: Instant::now(),
+            #[cfg(target_arch = "wasm32")]
+            start: performance_now(),
+        }
+    }
+}
+
+impl Clock for MonotonicClock {
+    #[cfg(not(target_arch = "wasm32"))]
+    #[inline]
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+    #[cfg(target_arch = "wasm32")]
+    #[inline]
+    fn now(&self) -> Duration {
+        Duration::try_from_secs_f64((performance_now() - self.start) / 1000.0).unwrap_or_default()
+    }
+}
+
+/// Manually driven clock, useful for tests and simulations. Clones share the same time
+#[derive(Debug, Clone, Default)]
+pub struct ManualClock {
+    ns: Arc<AtomicU64>,
+}
+
+impl ManualClock {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+    #[allow(clippy::cast_possible_truncation)]
+    #[inline]
+    pub fn set(&self, t: Duration) {
+        self.ns.store(t.as_nanos() as u64, atomic::Ordering::SeqCst);
+    }
+    #[allow(clippy::cast_possible_truncation)]
+    #[inline]
+    pub fn advance(&self, d: Duration) {
+        self.ns
+            .fetch_add(d.as_nanos() as u64, atomic::Ordering::SeqCst);
+    }
+}
+
+impl Clock for ManualClock {
+    #[inline]
+    fn now(&self) -> Duration {
+        Duration::from_nanos(self.ns.load(atomic::Ordering::SeqCst))
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum LvarTimerState {
+    /// The timer is cleared (lvar status 0)
+    Stopped,
+    /// The timer is running (lvar status 1)
+    Running,
+    /// The timer is expired (lvar status -1)
+    Expired,
+}
+
+/// The standard lvar timer pattern: the timer is started/reset with status 1, the value holds
+/// the remaining time in seconds. Once the remaining time reaches zero (elapsed >= duration), the
+/// timer is expired, status is flipped to -1 and the value is set to 0. Clearing the timer sets
+/// status to 0 and the value to 0
+pub struct LvarTimer<C: Clock = MonotonicClock> {
+    duration: Duration,
+    clock: C,
+    started: Option<Duration>,
+    expired: bool,
+}
+
+impl LvarTimer<MonotonicClock> {
+    #[inline]
+    pub fn new(duration: Duration) -> Self {
+        Self::with_clock(duration, MonotonicClock::default())
+    }
+}
+
+impl<C: Clock> LvarTimer<C> {
+    pub fn with_clock(duration: Duration, clock: C) -> Self {
+        Self {
+            duration,
+            clock,
+            started: None,
+            expired: false,
+        }
+    }
+    #[inline]
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+    /// Changes the timer duration, a running timer is not restarted
+    #[inline]
+    pub fn set_duration(&mut self, duration: Duration) {
+        self.duration = duration;
+    }
+    /// Starts or restarts the timer
+    pub fn reset(&mut self) {
+        self.started = Some(self.clock.now());
+        self.expired = false;
+    }
+    /// Stops the timer
+    pub fn clear(&mut self) {
+        self.started = None;
+        self.expired = false;
+    }
+    /// Forces the timer expiration
+    pub fn expire(&mut self) {
+        self.started = None;
+        self.expired = true;
+    }
+    pub fn state(&self) -> LvarTimerState {
+        if self.expired {
+            LvarTimerState::Expired
+        } else if let Some(started) = self.started {
+            if self.clock.now().saturating_sub(started) >= self.duration {
+                LvarTimerState::Expired
+            } else {
+                LvarTimerState::Running
+            }
+        } else {
+            LvarTimerState::Stopped
+        }
+    }
+    #[inline]
+    pub fn is_running(&self) -> bool {
+        self.state() == LvarTimerState::Running
+    }
+    #[inline]
+    pub fn is_expired(&self) -> bool {
+        self.state() == LvarTimerState::Expired
+    }
+    /// Remaining time, zero if the timer is stopped or expired
+    pub fn remaining(&self) -> Duration {
+        if self.expired {
+            Duration::default()
+        } else if let Some(started) = self.started {
+            self.duration
+                .saturating_sub(self.clock.now().saturating_sub(started))
+        } else {
+            Duration::default()
+        }
+    }
+    pub fn status(&self) -> ItemStatus {
+        match self.state() {
+            LvarTimerState::Stopped => 0,
+            LvarTimerState::Running => 1,
+            LvarTimerState::Expired => ITEM_STATUS_ERROR,
+        }
+    }
+    /// The remaining time in seconds
+    #[inline]
+    pub fn value(&self) -> Value {
+        Value::F64(self.remaining().as_secs_f64())
+    }
+}
+
 #[cfg(test)]
 mod test {
-    use super::{de_opt_range, de_range, Range};
+    use super::{de_opt_range, de_range, LvarTimer, LvarTimerState, ManualClock, Range};
+    use crate::value::Value;
     use serde::Deserialize;
+    use std::time::Duration;
 
     #[test]
     fn test_de() {
@@ -407,4 +615,37 @@ mod test {
         assert_eq!(r, "x <= 100".parse().unwrap());
         assert_eq!(r, "100>=x".parse().unwrap());
     }
+
+    #[test]
+    fn test_lvar_timer() {
+        let clock = ManualClock::new();
+        let mut timer = LvarTimer::with_clock(Duration::from_secs(10), clock.clone());
+        assert_eq!(timer.state(), LvarTimerState::Stopped);
+        assert_eq!(timer.status(), 0);
+        assert_eq!(timer.value(), Value::F64(0.0));
+        timer.reset();
+        assert_eq!(timer.status(), 1);
+        assert_eq!(timer.value(), Value::F64(10.0));
+        clock.advance(Duration::from_millis(9_999));
+        assert!(timer.is_running());
+        assert_eq!(timer.remaining(), Duration::from_millis(1));
+        clock.advance(Duration::from_millis(1));
+        assert!(timer.is_expired());
+        assert_eq!(timer.status(), -1);
+        assert_eq!(timer.value(), Value::F64(0.0));
+        timer.reset();
+        assert!(timer.is_running());
+        clock.advance(Duration::from_secs(5));
+        timer.reset();
+        clock.advance(Duration::from_secs(9));
+        assert!(timer.is_running());
+        timer.expire();
+        assert!(timer.is_expired());
+        assert_eq!(timer.remaining(), Duration::default());
+        timer.clear();
+        assert_eq!(timer.state(), LvarTimerState::Stopped);
+        let mut timer = LvarTimer::with_clock(Duration::default(), clock);
+        timer.reset();
+        assert!(timer.is_expired());
+    }
 }
@@ -0,0 +1,179 @@
+//! Common driver config sections, mapping item OIDs to source/target ids (registers, tags,
+//! topics etc.)
+use crate::transform::{self, Task, Transform};
+use crate::{EResult, Error, ItemKind, OID};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// Maps a data source to an item
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct InputMapping {
+    pub oid: OID,
+    #[serde(alias = "source")]
+    pub source_id: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub transform: Vec<Task>,
+    /// numeric value changes less than the band are not reported
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dead_band: Option<f64>,
+}
+
+impl InputMapping {
+    pub fn new(oid: OID, source_id: &str) -> Self {
+        Self {
+            oid,
+            source_id: source_id.to_owned(),
+            transform: Vec::new(),
+            dead_band: None,
+        }
+    }
+    pub fn validate(&self) -> EResult<()> {
+        if self.oid.kind() == ItemKind::Lmacro {
+            return Err(Error::invalid_params(format!(
+                "{}: inputs can not be mapped to macros",
+                self.oid
+            )));
+        }
+        check_id(&self.oid, &self.source_id)?;
+        if let Some(band) = self.dead_band {
+            if !band.is_finite() || band < 0.0 {
+                return Err(Error::invalid_params(format!(
+                    "{}: invalid dead band: {}",
+                    self.oid, band
+                )));
+            }
+        }
+        Ok(())
+    }
+    /// Applies the transform tasks (if any) to a numeric value
+    pub fn transform<T: Transform>(&self, value: T) -> EResult<f64> {
+        transform::transform(&self.transform, &self.oid, value)
+    }
+    /// Checks if the value change is within the dead band (should not be reported)
+    pub fn within_dead_band(&self, prev: f64, current: f64) -> bool {
+        self.dead_band
+            .is_some_and(|band| (current - prev).abs() < band)
+    }
+}
+
+/// What a driver does when a unit action is executed
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Default, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum OnAction {
+    /// write the action value to the target
+    #[default]
+    Write,
+    /// write the action value and read it back to verify
+    WriteVerify,
+    /// the mapping is used for state replication only, actions are ignored
+    Ignore,
+}
+
+/// Maps an item to a data target
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct OutputMapping {
+    pub oid: OID,
+    #[serde(alias = "target")]
+    pub target_id: String,
+    #[serde(default)]
+    pub on_action: OnAction,
+}
+
+impl OutputMapping {
+    pub fn new(oid: OID, target_id: &str) -> Self {
+        Self {
+            oid,
+            target_id: target_id.to_owned(),
+            on_action: OnAction::default(),
+        }
+    }
+    pub fn validate(&self) -> EResult<()> {
+        check_id(&self.oid, &self.target_id)?;
+        if self.on_action != OnAction::Ignore && self.oid.kind() != ItemKind::Unit {
+            return Err(Error::invalid_params(format!(
+                "{}: actions are supported for units only",
+                self.oid
+            )));
+        }
+        Ok(())
+    }
+}
+
+fn check_id(oid: &OID, id: &str) -> EResult<()> {
+    if id.trim().is_empty() {
+        Err(Error::invalid_params(format!("{}: id not specified", oid)))
+    } else {
+        Ok(())
+    }
+}
+
+/// Validates input mappings, an item can be mapped only once
+pub fn validate_inputs(mappings: &[InputMapping]) -> EResult<()> {
+    let mut oids = HashSet::new();
+    for m in mappings {
+        m.validate()?;
+        if !oids.insert(&m.oid) {
+            return Err(Error::invalid_params(format!(
+                "{}: duplicate input mapping",
+                m.oid
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Validates output mappings, an item can be mapped only once
+pub fn validate_outputs(mappings: &[OutputMapping]) -> EResult<()> {
+    let mut oids = HashSet::new();
+    for m in mappings {
+        m.validate()?;
+        if !oids.insert(&m.oid) {
+            return Err(Error::invalid_params(format!(
+                "{}: duplicate output mapping",
+                m.oid
+            )));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{validate_inputs, validate_outputs, InputMapping, OnAction, OutputMapping};
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn test_mapping() {
+        let inputs: Vec<InputMapping> = serde_json::from_str(
+            r#"[{"oid":"sensor:tests/s1","source_id":"h0",
+            "transform":[{"func":"multiply","params":[10]}],"dead_band":0.5},
+            {"oid":"sensor:tests/s2","source":"h1"}]"#,
+        )
+        .unwrap();
+        validate_inputs(&inputs).unwrap();
+        assert_eq!(inputs[0].transform(2.5).unwrap(), 25.0);
+        assert!(inputs[0].within_dead_band(25.0, 25.3));
+        assert!(!inputs[0].within_dead_band(25.0, 25.5));
+        assert!(!inputs[1].within_dead_band(25.0, 25.0));
+        let mut dup = inputs.clone();
+        dup[1].oid = dup[0].oid.clone();
+        assert!(validate_inputs(&dup).is_err());
+        let outputs: Vec<OutputMapping> = serde_json::from_str(
+            r#"[{"oid":"unit:tests/u1","target_id":"c0"},
+            {"oid":"sensor:tests/s1","target":"c1","on_action":"ignore"}]"#,
+        )
+        .unwrap();
+        validate_outputs(&outputs).unwrap();
+        assert_eq!(outputs[0].on_action, OnAction::Write);
+        let mut o = outputs[1].clone();
+        o.on_action = OnAction::WriteVerify;
+        assert!(o.validate().is_err());
+        o.target_id = String::new();
+        assert!(o.validate().is_err());
+        let back: Vec<InputMapping> =
+            serde_json::from_str(&serde_json::to_string(&inputs).unwrap()).unwrap();
+        validate_inputs(&back).unwrap();
+    }
+}
@@ -0,0 +1,189 @@
+//! IP network filters and client address resolution for HTTP-facing services
+use ipnetwork::IpNetwork;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::net::{IpAddr, SocketAddr};
+
+pub const X_FORWARDED_FOR: &str = "x-forwarded-for";
+pub const X_REAL_IP: &str = "x-real-ip";
+
+#[inline]
+fn contains(nets: &HashSet<IpNetwork>, ip: IpAddr) -> bool {
+    nets.iter().any(|net| net.contains(ip))
+}
+
+/// Decision order
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Order {
+    /// allowed by default, the deny list is checked first, the allow list overrides it
+    #[default]
+    DenyAllow,
+    /// denied by default, the allow list is checked first, the deny list overrides it
+    AllowDeny,
+}
+
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NetFilter {
+    #[serde(default)]
+    allow: HashSet<IpNetwork>,
+    #[serde(default)]
+    deny: HashSet<IpNetwork>,
+    #[serde(default)]
+    order: Order,
+}
+
+impl NetFilter {
+    #[inline]
+    pub fn new(order: Order) -> Self {
+        Self {
+            order,
+            ..Self::default()
+        }
+    }
+    #[inline]
+    pub fn allow(mut self, net: IpNetwork) -> Self {
+        self.allow.insert(net);
+        self
+    }
+    #[inline]
+    pub fn deny(mut self, net: IpNetwork) -> Self {
+        self.deny.insert(net);
+        self
+    }
+    #[inline]
+    pub fn order(&self) -> Order {
+        self.order
+    }
+    pub fn is_allowed(&self, ip: IpAddr) -> bool {
+        match self.order {
+            Order::DenyAllow => !contains(&self.deny, ip) || contains(&self.allow, ip),
+            Order::AllowDeny => contains(&self.allow, ip) && !contains(&self.deny, ip),
+        }
+    }
+}
+
+/// Parses a forwarded address, which may contain a port (e.g. "10.0.0.1:8080" or "[::1]:80")
+fn parse_forwarded(s: &str) -> Option<IpAddr> {
+    let s = s.trim();
+    s.parse::<IpAddr>()
+        .ok()
+        .or_else(|| s.parse::<SocketAddr>().ok().map(|a| a.ip()))
+}
+
+/// Resolves real client addresses behind reverse proxies. Proxy headers are processed only when
+/// the connection comes from a trusted proxy, otherwise they are ignored as spoofable
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct TrustedProxies {
+    nets: HashSet<IpNetwork>,
+}
+
+impl From<HashSet<IpNetwork>> for TrustedProxies {
+    #[inline]
+    fn from(nets: HashSet<IpNetwork>) -> Self {
+        Self { nets }
+    }
+}
+
+impl TrustedProxies {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+    #[inline]
+    pub fn trust(mut self, net: IpNetwork) -> Self {
+        self.nets.insert(net);
+        self
+    }
+    #[inline]
+    pub fn is_trusted(&self, ip: IpAddr) -> bool {
+        contains(&self.nets, ip)
+    }
+    /// Returns the client address for the connection peer and X-Forwarded-For/X-Real-IP header
+    /// values.
+    ///
+    /// X-Forwarded-For is walked from right to left, trusted proxies are skipped and the first
+    /// untrusted hop is considered as the client. On an invalid hop, the last trusted hop is
+    /// returned. X-Real-IP is used only if X-Forwarded-For is absent
+    pub fn client_ip(
+        &self,
+        peer: IpAddr,
+        x_forwarded_for: Option<&str>,
+        x_real_ip: Option<&str>,
+    ) -> IpAddr {
+        if !self.is_trusted(peer) {
+            return peer;
+        }
+        if let Some(xff) = x_forwarded_for {
+            let mut client = peer;
+            for hop in xff.rsplit(',') {
+                let Some(ip) = parse_forwarded(hop) else {
+                    break;
+                };
+                client = ip;
+                if !self.is_trusted(ip) {
+                    break;
+                }
+            }
+            return client;
+        }
+        x_real_ip.and_then(parse_forwarded).unwrap_or(peer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{NetFilter, Order, TrustedProxies};
+    use std::net::IpAddr;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_netfilter() {
+        let f = NetFilter::new(Order::DenyAllow)
+            .deny("10.0.0.0/8".parse().unwrap())
+            .allow("10.1.0.0/16".parse().unwrap());
+        assert!(f.is_allowed(ip("192.168.1.1")));
+        assert!(!f.is_allowed(ip("10.2.0.1")));
+        assert!(f.is_allowed(ip("10.1.0.1")));
+        let f: NetFilter = serde_json::from_str(
+            r#"{"order":"allow_deny","allow":["10.0.0.0/8","::1/128"],"deny":["10.1.0.0/16"]}"#,
+        )
+        .unwrap();
+        assert!(!f.is_allowed(ip("192.168.1.1")));
+        assert!(f.is_allowed(ip("10.2.0.1")));
+        assert!(!f.is_allowed(ip("10.1.0.1")));
+        assert!(f.is_allowed(ip("::1")));
+        assert!(NetFilter::default().is_allowed(ip("1.2.3.4")));
+        let proxies = TrustedProxies::new().trust("127.0.0.1/32".parse().unwrap());
+        // untrusted peers can not spoof the headers
+        assert_eq!(
+            proxies.client_ip(ip("1.2.3.4"), Some("5.6.7.8"), Some("5.6.7.8")),
+            ip("1.2.3.4")
+        );
+        assert_eq!(
+            proxies.client_ip(
+                ip("127.0.0.1"),
+                Some("9.9.9.9, 5.6.7.8:4000, 127.0.0.1"),
+                None
+            ),
+            ip("5.6.7.8")
+        );
+        assert_eq!(
+            proxies.client_ip(ip("127.0.0.1"), Some("9.9.9.9, bad, 127.0.0.1"), None),
+            ip("127.0.0.1")
+        );
+        assert_eq!(
+            proxies.client_ip(ip("127.0.0.1"), None, Some("[::2]:80")),
+            ip("::2")
+        );
+        assert_eq!(
+            proxies.client_ip(ip("127.0.0.1"), None, None),
+            ip("127.0.0.1")
+        );
+    }
+}
@@ -0,0 +1,294 @@
+//! Notification payloads for mail, SMS and webhook services, published to [`NOTIFICATION_TOPIC`]
+use crate::events::NOTIFICATION_TOPIC;
+use crate::payload::pack;
+use crate::{EResult, OID};
+use busrt::client::AsyncClient;
+use busrt::QoS;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::{self, Write as _};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+#[derive(
+    Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Default, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    #[default]
+    Info,
+    Warning,
+    Error,
+    Critical,
+}
+
+impl Severity {
+    /// Log level code (`LOG_LEVEL_*`) for the severity
+    pub fn level(self) -> u8 {
+        match self {
+            Severity::Info => crate::LOG_LEVEL_INFO,
+            Severity::Warning => crate::LOG_LEVEL_WARN,
+            Severity::Error | Severity::Critical => crate::LOG_LEVEL_ERROR,
+        }
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+            Severity::Critical => "critical",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Notification {
+    #[serde(default)]
+    pub severity: Severity,
+    pub subject: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub body: String,
+    /// items the notification is about
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub items: Vec<OID>,
+    /// sender service id
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub origin: Option<String>,
+    /// notifications with the same key are considered repeats, if not set, severity, origin and
+    /// subject are used
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dedup_key: Option<String>,
+    pub t: f64,
+}
+
+impl Notification {
+    /// Creates a notification with the current time
+    pub fn new(severity: Severity, subject: &str) -> Self {
+        Self {
+            severity,
+            subject: subject.to_owned(),
+            body: String::new(),
+            items: Vec::new(),
+            origin: None,
+            dedup_key: None,
+            t: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0.0, |d| d.as_secs_f64()),
+        }
+    }
+    pub fn body(mut self, body: &str) -> Self {
+        body.clone_into(&mut self.body);
+        self
+    }
+    pub fn item(mut self, oid: OID) -> Self {
+        self.items.push(oid);
+        self
+    }
+    pub fn origin(mut self, origin: &str) -> Self {
+        self.origin = Some(origin.to_owned());
+        self
+    }
+    pub fn dedup_key(mut self, key: &str) -> Self {
+        self.dedup_key = Some(key.to_owned());
+        self
+    }
+    /// Deduplication key
+    pub fn key(&self) -> String {
+        self.dedup_key.clone().unwrap_or_else(|| {
+            format!(
+                "{}/{}/{}",
+                self.severity,
+                self.origin.as_deref().unwrap_or_default(),
+                self.subject
+            )
+        })
+    }
+    /// Bus topic for the notification: `NOTIFY/<severity>`
+    pub fn topic(&self) -> String {
+        format!("{}{}", NOTIFICATION_TOPIC, self.severity)
+    }
+}
+
+/// Publishes the notification to the bus
+pub async fn emit<C>(client: &mut C, notification: &Notification) -> EResult<()>
+where
+    C: AsyncClient + ?Sized,
+{
+    client
+        .publish(&notification.topic(), pack(notification)?.into(), QoS::No)
+        .await?;
+    Ok(())
+}
+
+struct Seen {
+    since: Instant,
+    suppressed: usize,
+}
+
+/// Suppresses repeated notifications within a window or aggregates them into digests
+///
+/// In the default mode, the first notification with a key passes and its repeats are dropped
+/// until the window expires. In the digest mode, all notifications are buffered and returned
+/// by [`Aggregator::flush`] as a single digest, once the window since the first one expires
+pub struct Aggregator {
+    window: Duration,
+    digest: bool,
+    seen: HashMap<String, Seen>,
+    buf: Vec<Notification>,
+    buf_since: Option<Instant>,
+}
+
+impl Aggregator {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            digest: false,
+            seen: HashMap::new(),
+            buf: Vec::new(),
+            buf_since: None,
+        }
+    }
+    /// Enables the digest mode
+    #[inline]
+    pub fn digest(mut self) -> Self {
+        self.digest = true;
+        self
+    }
+    /// Returns the notification if it must be sent immediately
+    pub fn push(&mut self, notification: Notification) -> Option<Notification> {
+        self.push_at(notification, Instant::now())
+    }
+    fn push_at(&mut self, notification: Notification, now: Instant) -> Option<Notification> {
+        self.cleanup(now);
+        let key = notification.key();
+        if let Some(seen) = self.seen.get_mut(&key) {
+            seen.suppressed += 1;
+            return None;
+        }
+        self.seen.insert(
+            key,
+            Seen {
+                since: now,
+                suppressed: 0,
+            },
+        );
+        if self.digest {
+            self.buf_since.get_or_insert(now);
+            self.buf.push(notification);
+            None
+        } else {
+            Some(notification)
+        }
+    }
+    /// Returns the digest (in the digest mode) if the window since the first buffered
+    /// notification is expired
+    pub fn flush(&mut self) -> Option<Notification> {
+        self.flush_at(Instant::now(), false)
+    }
+    /// Returns the digest of all buffered notifications, regardless of the window
+    pub fn flush_now(&mut self) -> Option<Notification> {
+        self.flush_at(Instant::now(), true)
+    }
+    fn flush_at(&mut self, now: Instant, force: bool) -> Option<Notification> {
+        let since = self.buf_since?;
+        if !force && now.duration_since(since) < self.window {
+            return None;
+        }
+        self.buf_since = None;
+        let mut buf = std::mem::take(&mut self.buf);
+        for n in &mut buf {
+            let suppressed = self.seen.get(&n.key()).map_or(0, |s| s.suppressed);
+            if suppressed > 0 {
+                n.subject = format!("{} (repeated {} times)", n.subject, suppressed + 1);
+            }
+        }
+        self.cleanup(now);
+        if buf.len() == 1 {
+            return buf.pop();
+        }
+        let mut digest = Notification::new(
+            buf.iter().map(|n| n.severity).max().unwrap_or_default(),
+            &format!("{} notifications", buf.len()),
+        );
+        let mut items = BTreeSet::new();
+        let mut body = String::new();
+        for n in buf {
+            let _ = writeln!(body, "[{}] {}", n.severity, n.subject);
+            if !n.body.is_empty() {
+                body.push_str(&n.body);
+                body.push('\n');
+            }
+            items.extend(n.items);
+            if digest.origin.is_none() {
+                digest.origin = n.origin;
+            }
+        }
+        digest.body = body;
+        digest.items = items.into_iter().collect();
+        Some(digest)
+    }
+    /// Number of dropped repeats for the key within the current window
+    pub fn suppressed(&self, key: &str) -> usize {
+        self.seen.get(key).map_or(0, |s| s.suppressed)
+    }
+    fn cleanup(&mut self, now: Instant) {
+        let window = self.window;
+        let buffered: BTreeSet<String> = self.buf.iter().map(Notification::key).collect();
+        self.seen
+            .retain(|k, s| now.duration_since(s.since) < window || buffered.contains(k));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Aggregator, Notification, Severity};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_notification_aggregator() {
+        let t = Instant::now();
+        let window = Duration::from_secs(30);
+        let n = Notification::new(Severity::Warning, "sensor offline")
+            .origin("eva.svc.mailer")
+            .item("sensor:tests/s1".parse().unwrap());
+        assert_eq!(n.topic(), "NOTIFY/warning");
+        let s = serde_json::to_value(&n).unwrap();
+        assert_eq!(s["severity"], "warning");
+        assert_eq!(s["items"][0], "sensor:tests/s1");
+        assert!(s.get("body").is_none());
+        let mut agg = Aggregator::new(window);
+        assert!(agg.push_at(n.clone(), t).is_some());
+        assert!(agg
+            .push_at(n.clone(), t + Duration::from_secs(10))
+            .is_none());
+        assert_eq!(agg.suppressed(&n.key()), 1);
+        assert!(agg.push_at(n.clone(), t + window).is_some());
+        assert!(agg.flush_at(t + window * 2, false).is_none());
+        let mut agg = Aggregator::new(window).digest();
+        let crit = Notification::new(Severity::Critical, "overheat")
+            .item("sensor:tests/s2".parse().unwrap())
+            .item("sensor:tests/s1".parse().unwrap());
+        assert!(agg.push_at(n.clone(), t).is_none());
+        assert!(agg.push_at(n.clone(), t).is_none());
+        assert!(agg.push_at(crit, t).is_none());
+        assert!(agg.flush_at(t + Duration::from_secs(1), false).is_none());
+        let digest = agg.flush_at(t + window, false).unwrap();
+        assert_eq!(digest.severity, Severity::Critical);
+        assert_eq!(digest.subject, "2 notifications");
+        assert_eq!(
+            digest.body,
+            "[warning] sensor offline (repeated 2 times)\n[critical] overheat\n"
+        );
+        assert_eq!(digest.items.len(), 2);
+        assert_eq!(digest.origin.as_deref(), Some("eva.svc.mailer"));
+        assert!(agg.flush_at(t + window * 2, true).is_none());
+        assert!(agg.push_at(n, t + window).is_none());
+        assert_eq!(
+            agg.flush_at(t + window, true).unwrap().subject,
+            "sensor offline"
+        );
+    }
+}
@@ -0,0 +1,211 @@
+//! OID tools
+use crate::OID;
+use serde::{Deserialize, Serialize};
+use std::collections::{btree_set, BTreeMap, BTreeSet};
+
+/// Hierarchical view of OID groups, e.g. for HMI tree views
+///
+/// Items of all kinds are placed into the same tree, ungrouped items are put into the root node.
+/// Groups are sorted by name, items are sorted by OID.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct GroupTree {
+    root: GroupNode,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct GroupNode {
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    groups: BTreeMap<String, GroupNode>,
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    items: BTreeSet<OID>,
+    /// Items in the subtree
+    count: usize,
+}
+
+impl GroupNode {
+    fn insert<'a>(&mut self, mut path: impl Iterator<Item = &'a str>, oid: OID) -> bool {
+        let inserted = match path.next() {
+            Some(group) => self
+                .groups
+                .entry(group.to_owned())
+                .or_default()
+                .insert(path, oid),
+            None => self.items.insert(oid),
+        };
+        if inserted {
+            self.count += 1;
+        }
+        inserted
+    }
+    /// Direct child groups
+    pub fn groups(&self) -> impl Iterator<Item = (&str, &GroupNode)> {
+        self.groups.iter().map(|(name, node)| (name.as_str(), node))
+    }
+    /// Items, placed directly in the group
+    pub fn items(&self) -> impl Iterator<Item = &OID> {
+        self.items.iter()
+    }
+    /// Number of items in the subtree
+    #[inline]
+    pub fn count(&self) -> usize {
+        self.count
+    }
+    /// Lazily iterates all items in the subtree, group items go before ones of subgroups
+    pub fn iter(&self) -> Iter<'_> {
+        Iter {
+            stack: self.groups.values().rev().collect(),
+            items: self.items.iter(),
+        }
+    }
+}
+
+impl<'a> IntoIterator for &'a GroupNode {
+    type Item = &'a OID;
+    type IntoIter = Iter<'a>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+pub struct Iter<'a> {
+    stack: Vec<&'a GroupNode>,
+    items: btree_set::Iter<'a, OID>,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = &'a OID;
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(oid) = self.items.next() {
+                return Some(oid);
+            }
+            let node = self.stack.pop()?;
+            self.items = node.items.iter();
+            self.stack.extend(node.groups.values().rev());
+        }
+    }
+}
+
+impl GroupTree {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Returns false if the OID is already in the tree
+    pub fn insert(&mut self, oid: OID) -> bool {
+        let group = oid.group().map(ToOwned::to_owned);
+        self.root
+            .insert(group.iter().flat_map(|g| g.split('/')), oid)
+    }
+    #[inline]
+    pub fn root(&self) -> &GroupNode {
+        &self.root
+    }
+    /// Gets a group node by its path, e.g. "env/room1". Empty path or "/" returns the root
+    pub fn get(&self, group: &str) -> Option<&GroupNode> {
+        let group = group.trim_matches('/');
+        if group.is_empty() {
+            return Some(&self.root);
+        }
+        group
+            .split('/')
+            .try_fold(&self.root, |node, name| node.groups.get(name))
+    }
+    /// Direct child groups of the group, empty for non-existing ones
+    pub fn children(&self, group: &str) -> impl Iterator<Item = (&str, &GroupNode)> {
+        self.get(group).into_iter().flat_map(GroupNode::groups)
+    }
+    /// Number of items in the group subtree
+    pub fn count(&self, group: &str) -> usize {
+        self.get(group).map_or(0, GroupNode::count)
+    }
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.root.count
+    }
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.root.count == 0
+    }
+    #[inline]
+    pub fn iter(&self) -> Iter<'_> {
+        self.root.iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a GroupTree {
+    type Item = &'a OID;
+    type IntoIter = Iter<'a>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl FromIterator<OID> for GroupTree {
+    fn from_iter<I: IntoIterator<Item = OID>>(iter: I) -> Self {
+        let mut tree = Self::new();
+        tree.extend(iter);
+        tree
+    }
+}
+
+impl Extend<OID> for GroupTree {
+    fn extend<I: IntoIterator<Item = OID>>(&mut self, iter: I) {
+        for oid in iter {
+            self.insert(oid);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::GroupTree;
+    use crate::OID;
+
+    #[test]
+    fn test_group_tree() {
+        let mut tree: GroupTree = [
+            "sensor:env/room1/temp",
+            "sensor:env/room1/hum",
+            "unit:env/room1/fan",
+            "sensor:env/room2/temp",
+            "unit:env/heater",
+            "sensor:outside",
+        ]
+        .iter()
+        .map(|s| s.parse::<OID>().unwrap())
+        .collect();
+        assert!(!tree.insert("unit:env/heater".parse().unwrap()));
+        assert_eq!(tree.len(), 6);
+        assert_eq!(tree.count("env"), 5);
+        assert_eq!(tree.count("/env/room1/"), 3);
+        assert_eq!(tree.count("env/room3"), 0);
+        let children: Vec<(&str, usize)> = tree
+            .children("env")
+            .map(|(name, node)| (name, node.count()))
+            .collect();
+        assert_eq!(children, [("room1", 3), ("room2", 1)]);
+        let env = tree.get("env").unwrap();
+        assert_eq!(
+            env.items().map(OID::as_str).collect::<Vec<_>>(),
+            ["unit:env/heater"]
+        );
+        assert_eq!(
+            tree.iter().map(OID::as_str).collect::<Vec<_>>(),
+            [
+                "sensor:outside",
+                "unit:env/heater",
+                "unit:env/room1/fan",
+                "sensor:env/room1/hum",
+                "sensor:env/room1/temp",
+                "sensor:env/room2/temp"
+            ]
+        );
+        let value = crate::value::to_value(&tree).unwrap();
+        assert_eq!(
+            value.jp_lookup("$.groups.env.groups.room1.count").unwrap(),
+            Some(&crate::value::Value::U64(3))
+        );
+    }
+}
@@ -1,23 +1,120 @@
 use crate::{EResult, Error};
+use std::fmt;
 use std::time::Duration;
 use std::time::Instant;
 
+/// Timing of an operation stage or a joined child operation
+#[derive(Debug, Clone)]
+pub struct Stage {
+    name: String,
+    elapsed: Duration,
+    children: Vec<Stage>,
+}
+
+impl Stage {
+    #[inline]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    #[inline]
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+    #[inline]
+    pub fn children(&self) -> &[Stage] {
+        &self.children
+    }
+}
+
+fn fmt_stages(f: &mut fmt::Formatter<'_>, stages: &[Stage]) -> fmt::Result {
+    for (i, stage) in stages.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{}={:?}", stage.name, stage.elapsed)?;
+        if !stage.children.is_empty() {
+            write!(f, " [")?;
+            fmt_stages(f, &stage.children)?;
+            write!(f, "]")?;
+        }
+    }
+    Ok(())
+}
+
 pub struct Op {
     t: Instant,
     timeout: Duration,
+    name: Option<String>,
+    mark: Instant,
+    stages: Vec<Stage>,
 }
 
 impl Op {
     #[inline]
     pub fn new(timeout: Duration) -> Self {
+        Self::for_instant(Instant::now(), timeout)
+    }
+    #[inline]
+    pub fn for_instant(t: Instant, timeout: Duration) -> Self {
         Self {
-            t: Instant::now(),
+            t,
             timeout,
+            name: None,
+            mark: t,
+            stages: Vec::new(),
         }
     }
+    /// Sets the operation name, used when the operation is joined to its parent
     #[inline]
-    pub fn for_instant(t: Instant, timeout: Duration) -> Self {
-        Self { t, timeout }
+    pub fn named(mut self, name: &str) -> Self {
+        self.name = Some(name.to_owned());
+        self
+    }
+    /// Creates a child operation, which deadline is the remaining time minus the reserve (kept
+    /// for the parent to process the result and reply)
+    pub fn child(&self, reserve: Duration) -> EResult<Op> {
+        match self.timeout()?.checked_sub(reserve) {
+            Some(timeout) if !timeout.is_zero() => Ok(Op::new(timeout)),
+            _ => Err(Error::timeout()),
+        }
+    }
+    #[inline]
+    pub fn deadline(&self) -> Instant {
+        self.t + self.timeout
+    }
+    #[inline]
+    pub fn elapsed(&self) -> Duration {
+        self.t.elapsed()
+    }
+    /// Records a stage, which has taken the time since the previous stage (or the operation
+    /// start)
+    pub fn stage(&mut self, name: &str) {
+        let now = Instant::now();
+        self.stages.push(Stage {
+            name: name.to_owned(),
+            elapsed: now.saturating_duration_since(self.mark),
+            children: Vec::new(),
+        });
+        self.mark = now;
+    }
+    /// Records a finished child operation as a stage, with its own stages nested
+    pub fn join(&mut self, child: Op) {
+        self.stages.push(Stage {
+            name: child.name.unwrap_or_else(|| "child".to_owned()),
+            elapsed: child.t.elapsed(),
+            children: child.stages,
+        });
+        self.mark = Instant::now();
+    }
+    #[inline]
+    pub fn stages(&self) -> &[Stage] {
+        &self.stages
+    }
+    /// Timing breakdown for logs and traces, e.g. "total=12ms: auth=1ms, call=11ms [pack=1ms,
+    /// send=10ms]"
+    #[inline]
+    pub fn breakdown(&self) -> Breakdown<'_> {
+        Breakdown(self)
     }
     pub fn is_timed_out(&self) -> bool {
         let el = self.t.elapsed();
@@ -53,3 +150,48 @@ impl Op {
         }
     }
 }
+
+pub struct Breakdown<'a>(&'a Op);
+
+impl fmt::Display for Breakdown<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "total={:?}", self.0.elapsed())?;
+        if !self.0.stages.is_empty() {
+            write!(f, ": ")?;
+            fmt_stages(f, &self.0.stages)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Op;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_op_child() {
+        let t = Instant::now();
+        let mut op = Op::for_instant(
+            t.checked_sub(Duration::from_millis(200)).unwrap(),
+            Duration::from_secs(1),
+        );
+        let mut child = op.child(Duration::from_millis(100)).unwrap().named("call");
+        assert!(child.deadline() + Duration::from_millis(99) < op.deadline());
+        child.stage("pack");
+        child.stage("send");
+        op.stage("auth");
+        op.join(child);
+        let stages = op.stages();
+        assert_eq!(stages.len(), 2);
+        assert_eq!(stages[0].name(), "auth");
+        assert!(stages[0].elapsed() >= Duration::from_millis(200));
+        assert_eq!(stages[1].name(), "call");
+        assert_eq!(stages[1].children().len(), 2);
+        let breakdown = op.breakdown().to_string();
+        assert!(breakdown.starts_with("total="));
+        assert!(breakdown.contains(", call="));
+        assert!(breakdown.contains(" [pack="));
+        assert!(op.child(Duration::from_secs(1)).is_err());
+    }
+}
@@ -0,0 +1,178 @@
+//! RFC 7807 problem details (`application/problem+json`) for HTTP error responses
+#[allow(unused_imports)]
+use crate::alloc_prelude::*;
+use crate::value::Value;
+use crate::{EResult, Error, ErrorKind};
+use serde::{Deserialize, Serialize};
+
+pub const PROBLEM_JSON_MIME: &str = "application/problem+json";
+
+const PROBLEM_TYPE_DEFAULT: &str = "about:blank";
+
+/// HTTP status code for the error kind
+pub fn http_status(kind: ErrorKind) -> u16 {
+    match kind {
+        ErrorKind::NotReady => 503,
+        ErrorKind::ResourceNotFound => 404,
+        ErrorKind::ResourceBusy => 423,
+        ErrorKind::ResourceAlreadyExists => 409,
+        ErrorKind::AccessDenied
+        | ErrorKind::AccessDeniedMoreDataRequired
+        | ErrorKind::EvaHIAuthenticationRequired
+        | ErrorKind::TokenRestricted => 403,
+        ErrorKind::MethodNotFound
+        | ErrorKind::MethodNotImplemented
+        | ErrorKind::InvalidParameter => 400,
+        ErrorKind::Timeout => 408,
+        _ => 500,
+    }
+}
+
+fn default_problem_type() -> String {
+    PROBLEM_TYPE_DEFAULT.to_owned()
+}
+
+/// Problem details object. Besides the standard members, carries the EVA error code, the error
+/// kind name and optional details
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Problem {
+    #[serde(rename = "type", default = "default_problem_type")]
+    tp: String,
+    title: String,
+    status: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    detail: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    instance: Option<String>,
+    code: i16,
+    kind: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    details: Option<Value>,
+}
+
+impl From<Error> for Problem {
+    #[inline]
+    fn from(e: Error) -> Self {
+        Self::new(&e)
+    }
+}
+
+impl From<Problem> for Error {
+    fn from(p: Problem) -> Self {
+        Error::newc(ErrorKind::from(p.code), p.detail)
+    }
+}
+
+impl Problem {
+    pub fn new(e: &Error) -> Self {
+        let kind = e.kind();
+        Self {
+            tp: default_problem_type(),
+            title: kind.to_string(),
+            status: http_status(kind),
+            detail: e.message().map(ToOwned::to_owned),
+            instance: None,
+            code: e.code(),
+            kind: format!("{:?}", kind),
+            details: None,
+        }
+    }
+    /// Problem type URI (the default is "about:blank")
+    pub fn problem_type(mut self, problem_type: &str) -> Self {
+        problem_type.clone_into(&mut self.tp);
+        self
+    }
+    /// Overrides the HTTP status code
+    pub fn status(mut self, status: u16) -> Self {
+        self.status = status;
+        self
+    }
+    pub fn instance(mut self, instance: &str) -> Self {
+        self.instance = Some(instance.to_owned());
+        self
+    }
+    pub fn details(mut self, details: Value) -> Self {
+        self.details = Some(details);
+        self
+    }
+    #[inline]
+    pub fn http_status(&self) -> u16 {
+        self.status
+    }
+    #[inline]
+    pub fn code(&self) -> i16 {
+        self.code
+    }
+    #[inline]
+    pub fn kind_name(&self) -> &str {
+        &self.kind
+    }
+    #[inline]
+    pub fn detail(&self) -> Option<&str> {
+        self.detail.as_deref()
+    }
+    /// # Errors
+    ///
+    /// Will return `Err` if the details can not be serialized to JSON (e.g. maps with non-string
+    /// keys)
+    pub fn to_vec(&self) -> EResult<Vec<u8>> {
+        Ok(serde_json::to_vec(self)?)
+    }
+}
+
+#[cfg(feature = "axum")]
+impl axum::response::IntoResponse for Problem {
+    fn into_response(self) -> axum::response::Response {
+        let status = axum::http::StatusCode::from_u16(self.status)
+            .unwrap_or(axum::http::StatusCode::INTERNAL_SERVER_ERROR);
+        match self.to_vec() {
+            Ok(body) => (
+                status,
+                [(axum::http::header::CONTENT_TYPE, PROBLEM_JSON_MIME)],
+                body,
+            )
+                .into_response(),
+            Err(e) => {
+                (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Problem;
+    use crate::value::Value;
+    use crate::{Error, ErrorKind};
+
+    #[test]
+    fn test_problem() {
+        let p = Problem::from(Error::not_found("unit:tests/u1"))
+            .instance("/api/item.state")
+            .details(Value::String("unit:tests/u1".to_owned()));
+        assert_eq!(p.http_status(), 404);
+        let v: serde_json::Value = serde_json::from_slice(&p.to_vec().unwrap()).unwrap();
+        assert_eq!(
+            v,
+            serde_json::json!({
+                "type": "about:blank",
+                "title": "Resource not found",
+                "status": 404,
+                "detail": "unit:tests/u1",
+                "instance": "/api/item.state",
+                "code": -32001,
+                "kind": "ResourceNotFound",
+                "details": "unit:tests/u1"
+            })
+        );
+        let p: Problem = serde_json::from_str(
+            r#"{"title":"Timed out","status":408,"code":-32008,"kind":"Timeout"}"#,
+        )
+        .unwrap();
+        let e = Error::from(p);
+        assert_eq!(e.kind(), ErrorKind::Timeout);
+        assert_eq!(e.message(), None);
+        assert_eq!(Problem::from(Error::busy("locked")).http_status(), 423);
+        assert_eq!(Problem::from(Error::core("x")).http_status(), 500);
+    }
+}
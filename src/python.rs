@@ -0,0 +1,343 @@
+//! Python bindings for OIDs, OID masks, ACLs and values
+//!
+//! The bindings are not a standalone extension module: an extension crate (e.g. the macro
+//! engine) calls [`register()`] from its own `#[pymodule]` function.
+// false positives in the code, generated by pymethods
+#![allow(clippy::useless_conversion)]
+use crate::acl::{Acl, OIDMask, OIDMaskList, Op};
+use crate::value::Value;
+use crate::{Error, ErrorKind, OID};
+use pyo3::exceptions::{
+    PyLookupError, PyOSError, PyPermissionError, PyRuntimeError, PyTimeoutError, PyTypeError,
+    PyValueError,
+};
+use pyo3::prelude::*;
+use pyo3::types::{
+    PyBool, PyByteArray, PyBytes, PyDict, PyFloat, PyInt, PyList, PyString, PyTuple,
+};
+use std::collections::BTreeMap;
+
+/// Max nesting level of Python objects, converted to values (protects from self-referencing
+/// containers and stack overflows)
+pub const MAX_VALUE_DEPTH: usize = 128;
+
+impl From<Error> for PyErr {
+    fn from(err: Error) -> Self {
+        let msg = err.to_string();
+        match err.kind() {
+            ErrorKind::InvalidData | ErrorKind::InvalidParameter => PyValueError::new_err(msg),
+            ErrorKind::ResourceNotFound => PyLookupError::new_err(msg),
+            ErrorKind::AccessDenied
+            | ErrorKind::AccessDeniedMoreDataRequired
+            | ErrorKind::TokenRestricted => PyPermissionError::new_err(msg),
+            ErrorKind::Timeout | ErrorKind::BusTimeout => PyTimeoutError::new_err(msg),
+            ErrorKind::IOError | ErrorKind::BusIo => PyOSError::new_err(msg),
+            _ => PyRuntimeError::new_err(msg),
+        }
+    }
+}
+
+impl ToPyObject for Value {
+    /// Options and newtypes are unwrapped, maps are converted to dicts (sequence keys become
+    /// tuples, map keys are converted to strings)
+    fn to_object(&self, py: Python<'_>) -> PyObject {
+        value_to_py(self, py, false)
+    }
+}
+
+impl IntoPy<PyObject> for Value {
+    #[inline]
+    fn into_py(self, py: Python<'_>) -> PyObject {
+        self.to_object(py)
+    }
+}
+
+fn value_to_py(value: &Value, py: Python<'_>, as_key: bool) -> PyObject {
+    match value {
+        Value::Bool(v) => v.to_object(py),
+        Value::U8(v) => v.to_object(py),
+        Value::U16(v) => v.to_object(py),
+        Value::U32(v) => v.to_object(py),
+        Value::U64(v) => v.to_object(py),
+        Value::I8(v) => v.to_object(py),
+        Value::I16(v) => v.to_object(py),
+        Value::I32(v) => v.to_object(py),
+        Value::I64(v) => v.to_object(py),
+        Value::F32(v) => v.to_object(py),
+        Value::F64(v) => v.to_object(py),
+        Value::Char(v) => v.to_object(py),
+        Value::String(v) => v.to_object(py),
+        Value::Unit | Value::Option(None) => py.None(),
+        Value::Option(Some(v)) | Value::Newtype(v) => value_to_py(v, py, as_key),
+        Value::Seq(v) => {
+            let items = v.iter().map(|v| value_to_py(v, py, as_key));
+            if as_key {
+                PyTuple::new_bound(py, items).into()
+            } else {
+                PyList::new_bound(py, items).into()
+            }
+        }
+        Value::Map(m) => {
+            if as_key {
+                return value.to_string().to_object(py);
+            }
+            let dict = PyDict::new_bound(py);
+            for (k, v) in m {
+                // keys are hashable, so the operation never fails
+                let _ = dict.set_item(value_to_py(k, py, true), value_to_py(v, py, false));
+            }
+            dict.into()
+        }
+        Value::Bytes(v) => PyBytes::new_bound(py, v).into(),
+    }
+}
+
+impl<'py> FromPyObject<'py> for Value {
+    #[inline]
+    fn extract_bound(ob: &Bound<'py, PyAny>) -> PyResult<Self> {
+        value_from_py(ob, 0)
+    }
+}
+
+fn value_from_py(ob: &Bound<'_, PyAny>, depth: usize) -> PyResult<Value> {
+    if depth > MAX_VALUE_DEPTH {
+        return Err(PyValueError::new_err(format!(
+            "max value nesting depth ({}) exceeded",
+            MAX_VALUE_DEPTH
+        )));
+    }
+    if ob.is_none() {
+        Ok(Value::Unit)
+    } else if ob.is_instance_of::<PyBool>() {
+        Ok(Value::Bool(ob.extract()?))
+    } else if ob.is_instance_of::<PyInt>() {
+        if let Ok(v) = ob.extract::<u64>() {
+            Ok(Value::U64(v))
+        } else {
+            Ok(Value::I64(ob.extract()?))
+        }
+    } else if ob.is_instance_of::<PyFloat>() {
+        Ok(Value::F64(ob.extract()?))
+    } else if let Ok(s) = ob.downcast::<PyString>() {
+        Ok(Value::String(s.to_str()?.to_owned()))
+    } else if let Ok(b) = ob.downcast::<PyBytes>() {
+        Ok(Value::Bytes(b.as_bytes().to_vec()))
+    } else if let Ok(b) = ob.downcast::<PyByteArray>() {
+        Ok(Value::Bytes(b.to_vec()))
+    } else if let Ok(l) = ob.downcast::<PyList>() {
+        l.iter()
+            .map(|v| value_from_py(&v, depth + 1))
+            .collect::<PyResult<_>>()
+            .map(Value::Seq)
+    } else if let Ok(t) = ob.downcast::<PyTuple>() {
+        t.iter()
+            .map(|v| value_from_py(&v, depth + 1))
+            .collect::<PyResult<_>>()
+            .map(Value::Seq)
+    } else if let Ok(d) = ob.downcast::<PyDict>() {
+        let mut m = BTreeMap::new();
+        for (k, v) in d {
+            m.insert(value_from_py(&k, depth + 1)?, value_from_py(&v, depth + 1)?);
+        }
+        Ok(Value::Map(m))
+    } else if let Ok(oid) = ob.downcast::<PyOid>() {
+        Ok(Value::String(oid.get().0.to_string()))
+    } else {
+        Err(PyTypeError::new_err(format!(
+            "unsupported value type: {}",
+            ob.get_type().name()?
+        )))
+    }
+}
+
+/// Accepts either OID objects or strings
+fn extract_oid(ob: &Bound<'_, PyAny>) -> PyResult<OID> {
+    if let Ok(oid) = ob.downcast::<PyOid>() {
+        Ok(oid.get().0.clone())
+    } else {
+        Ok(ob.extract::<&str>()?.parse()?)
+    }
+}
+
+#[pyclass(name = "OID", module = "eva_common", frozen, eq, hash)]
+#[derive(PartialEq, Hash)]
+pub struct PyOid(pub OID);
+
+#[pymethods]
+impl PyOid {
+    #[new]
+    fn new(s: &str) -> PyResult<Self> {
+        Ok(Self(s.parse()?))
+    }
+    #[staticmethod]
+    fn from_path(path: &str) -> PyResult<Self> {
+        Ok(Self(OID::from_path(path)?))
+    }
+    #[getter]
+    fn kind(&self) -> &str {
+        self.0.kind_str()
+    }
+    #[getter]
+    fn id(&self) -> &str {
+        self.0.id()
+    }
+    #[getter]
+    fn full_id(&self) -> &str {
+        self.0.full_id()
+    }
+    #[getter]
+    fn group(&self) -> Option<&str> {
+        self.0.group()
+    }
+    fn as_path(&self) -> &str {
+        self.0.as_path()
+    }
+    fn __str__(&self) -> &str {
+        self.0.as_str()
+    }
+    fn __repr__(&self) -> String {
+        format!("OID('{}')", self.0)
+    }
+}
+
+#[pyclass(name = "OIDMask", module = "eva_common", frozen, eq, hash)]
+#[derive(PartialEq, Hash)]
+pub struct PyOidMask(pub OIDMask);
+
+#[pymethods]
+impl PyOidMask {
+    #[new]
+    fn new(s: &str) -> PyResult<Self> {
+        Ok(Self(s.parse()?))
+    }
+    fn matches(&self, oid: &Bound<'_, PyAny>) -> PyResult<bool> {
+        Ok(self.0.matches(&extract_oid(oid)?))
+    }
+    fn __str__(&self) -> String {
+        self.0.to_string()
+    }
+    fn __repr__(&self) -> String {
+        format!("OIDMask('{}')", self.0)
+    }
+}
+
+#[pyclass(name = "OIDMaskList", module = "eva_common", frozen)]
+pub struct PyOidMaskList(pub OIDMaskList);
+
+#[pymethods]
+impl PyOidMaskList {
+    #[new]
+    fn new(masks: Vec<String>) -> PyResult<Self> {
+        Ok(Self(OIDMaskList::from_string_list(&masks)?))
+    }
+    fn matches(&self, oid: &Bound<'_, PyAny>) -> PyResult<bool> {
+        Ok(self.0.matches(&extract_oid(oid)?))
+    }
+    fn __len__(&self) -> usize {
+        self.0.oid_masks().len()
+    }
+}
+
+#[pyclass(name = "Acl", module = "eva_common", frozen)]
+pub struct PyAcl(pub Acl);
+
+#[pymethods]
+impl PyAcl {
+    /// Creates an ACL from a dict in the same format, as used by the core
+    #[new]
+    fn new(acl: Value) -> PyResult<Self> {
+        Ok(Self(acl.deserialize_into().map_err(Error::invalid_data)?))
+    }
+    #[getter]
+    fn id(&self) -> &str {
+        self.0.id()
+    }
+    fn check_admin(&self) -> bool {
+        self.0.check_admin()
+    }
+    fn check_op(&self, op: &str) -> PyResult<bool> {
+        let op: Op = Value::String(op.to_owned())
+            .deserialize_into()
+            .map_err(Error::invalid_data)?;
+        Ok(self.0.check_op(op))
+    }
+    fn check_item_read(&self, oid: &Bound<'_, PyAny>) -> PyResult<bool> {
+        Ok(self.0.check_item_read(&extract_oid(oid)?))
+    }
+    fn check_item_write(&self, oid: &Bound<'_, PyAny>) -> PyResult<bool> {
+        Ok(self.0.check_item_write(&extract_oid(oid)?))
+    }
+    fn check_pvt_read(&self, path: &str) -> bool {
+        self.0.check_pvt_read(path)
+    }
+    fn check_pvt_write(&self, path: &str) -> bool {
+        self.0.check_pvt_write(path)
+    }
+}
+
+/// Checks if the string is a valid OID
+#[pyfunction]
+fn is_valid_oid(s: &str) -> bool {
+    s.parse::<OID>().is_ok()
+}
+
+/// Checks if the string is a valid OID mask
+#[pyfunction]
+fn is_valid_oid_mask(s: &str) -> bool {
+    s.parse::<OIDMask>().is_ok()
+}
+
+/// Registers the classes and functions in a Python module
+pub fn register(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyOid>()?;
+    m.add_class::<PyOidMask>()?;
+    m.add_class::<PyOidMaskList>()?;
+    m.add_class::<PyAcl>()?;
+    m.add_function(wrap_pyfunction!(is_valid_oid, m)?)?;
+    m.add_function(wrap_pyfunction!(is_valid_oid_mask, m)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::register;
+    use crate::value::Value;
+    use pyo3::prelude::*;
+    use pyo3::types::PyDict;
+
+    #[test]
+    fn test_python() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let m = PyModule::new_bound(py, "eva_common").unwrap();
+            register(&m).unwrap();
+            let locals = PyDict::new_bound(py);
+            locals.set_item("e", &m).unwrap();
+            let check = |code: &str| -> bool {
+                py.eval_bound(code, None, Some(&locals))
+                    .unwrap()
+                    .extract()
+                    .unwrap()
+            };
+            assert!(check("e.OID('sensor:tests/s1').group == 'tests'"));
+            assert!(check("not e.is_valid_oid('sensor:tests/s 1')"));
+            assert!(check(
+                "e.OIDMask('sensor:tests/#').matches('sensor:tests/a/b')"
+            ));
+            assert!(check("e.OIDMaskList(['unit:#']).matches(e.OID('unit:u1'))"));
+            assert!(check(
+                "e.Acl({'id': 't', 'from': [], 'read': {'items': ['sensor:#']}, \
+                 'ops': ['log']}).check_item_read('sensor:x/y')"
+            ));
+            let v: Value = serde_json::from_str(r#"{"a":[1,-2,3.5,"x",null,true]}"#).unwrap();
+            let obj = v.to_object(py);
+            let back: Value = obj.extract(py).unwrap();
+            assert_eq!(back, v);
+            assert!(py.eval_bound("e.OID('x')", None, Some(&locals)).is_err());
+            let nested = py
+                .eval_bound("(lambda l: (l.append(l), l)[1])([])", None, None)
+                .unwrap();
+            assert!(nested.extract::<Value>().is_err());
+        });
+    }
+}
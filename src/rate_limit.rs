@@ -0,0 +1,382 @@
+//! Per-API key and per-IP request budgets for HTTP services
+//!
+//! [`RateLimiter`] uses token buckets: a budget of N requests per period allows bursts of up to N
+//! requests and refills continuously. Client addresses are resolved with [`TrustedProxies`], so
+//! proxy headers are honored only from trusted proxies.
+//!
+//! Hyper services wrap handlers with [`limit_hyper`], axum routers use [`axum_middleware`] with
+//! `axum::middleware::from_fn_with_state(limiter, axum_middleware)` and must be served with
+//! `into_make_service_with_connect_info::<SocketAddr>()`
+use crate::netfilter::{TrustedProxies, X_FORWARDED_FOR, X_REAL_IP};
+use crate::Error;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_KEY_HEADER: &str = "x-auth-key";
+/// Default maximum number of tracked keys (addresses) per budget
+pub const DEFAULT_MAX_KEYS: usize = 100_000;
+
+/// Request budget: N requests per period
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Budget {
+    pub requests: u32,
+    #[serde(
+        serialize_with = "crate::tools::serialize_duration_as_f64",
+        deserialize_with = "crate::tools::de_float_as_duration"
+    )]
+    pub period: Duration,
+}
+
+impl Budget {
+    #[inline]
+    pub fn new(requests: u32, period: Duration) -> Self {
+        Self { requests, period }
+    }
+    #[inline]
+    fn rate(&self) -> f64 {
+        f64::from(self.requests) / self.period.as_secs_f64()
+    }
+}
+
+/// Returned when the budget is exhausted
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Limited {
+    retry_after: Duration,
+}
+
+impl Limited {
+    #[inline]
+    pub fn retry_after(&self) -> Duration {
+        self.retry_after
+    }
+    /// Retry-After header value (seconds, rounded up)
+    pub fn retry_after_secs(&self) -> u64 {
+        let secs = self.retry_after.as_secs();
+        if self.retry_after.subsec_nanos() > 0 {
+            secs + 1
+        } else {
+            secs
+        }
+    }
+}
+
+impl From<Limited> for Error {
+    fn from(l: Limited) -> Self {
+        Error::busy(format!(
+            "rate limit exceeded, retry after {}s",
+            l.retry_after_secs()
+        ))
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+struct Buckets<K> {
+    budget: Budget,
+    max_keys: usize,
+    buckets: Mutex<HashMap<K, Bucket>>,
+}
+
+impl<K: Hash + Eq + Clone> Buckets<K> {
+    fn new(budget: Budget, max_keys: usize) -> Self {
+        Self {
+            budget,
+            max_keys,
+            buckets: <_>::default(),
+        }
+    }
+    /// Makes room for a new key: removes idle buckets, then the least recently used one
+    fn evict(&self, buckets: &mut HashMap<K, Bucket>, now: Instant) {
+        let period = self.budget.period;
+        buckets.retain(|_, b| now.saturating_duration_since(b.updated) < period);
+        if buckets.len() >= self.max_keys {
+            if let Some(key) = buckets
+                .iter()
+                .min_by_key(|(_, b)| b.updated)
+                .map(|(k, _)| k.clone())
+            {
+                buckets.remove(&key);
+            }
+        }
+    }
+    fn take(&self, key: K, now: Instant) -> Result<(), Limited> {
+        let capacity = f64::from(self.budget.requests);
+        let rate = self.budget.rate();
+        let mut buckets = self.buckets.lock();
+        if buckets.len() >= self.max_keys && !buckets.contains_key(&key) {
+            self.evict(&mut buckets, now);
+        }
+        let bucket = buckets.entry(key).or_insert(Bucket {
+            tokens: capacity,
+            updated: now,
+        });
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rate).min(capacity);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else if rate > 0.0 {
+            Err(Limited {
+                retry_after: Duration::from_secs_f64((1.0 - bucket.tokens) / rate),
+            })
+        } else {
+            Err(Limited {
+                retry_after: self.budget.period,
+            })
+        }
+    }
+    fn cleanup(&self, now: Instant) {
+        let period = self.budget.period;
+        self.buckets
+            .lock()
+            .retain(|_, b| now.saturating_duration_since(b.updated) < period);
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimitConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub per_key: Option<Budget>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub per_ip: Option<Budget>,
+    #[serde(default)]
+    pub trusted_proxies: TrustedProxies,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_header: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_keys: Option<usize>,
+}
+
+pub struct RateLimiter {
+    per_key: Option<Buckets<String>>,
+    per_ip: Option<Buckets<IpAddr>>,
+    proxies: TrustedProxies,
+    key_header: String,
+    max_keys: usize,
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        Self {
+            per_key: None,
+            per_ip: None,
+            proxies: <_>::default(),
+            key_header: DEFAULT_KEY_HEADER.to_owned(),
+            max_keys: DEFAULT_MAX_KEYS,
+        }
+    }
+}
+
+impl From<&RateLimitConfig> for RateLimiter {
+    fn from(config: &RateLimitConfig) -> Self {
+        let mut limiter = Self::new().trusted_proxies(config.trusted_proxies.clone());
+        if let Some(max_keys) = config.max_keys {
+            limiter = limiter.max_keys(max_keys);
+        }
+        if let Some(budget) = config.per_key {
+            limiter = limiter.per_key(budget);
+        }
+        if let Some(budget) = config.per_ip {
+            limiter = limiter.per_ip(budget);
+        }
+        if let Some(ref header) = config.key_header {
+            limiter = limiter.key_header(header);
+        }
+        limiter
+    }
+}
+
+impl RateLimiter {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Maximum number of tracked keys and addresses per budget (default: 100 000). When the
+    /// limit is reached, idle and then the least recently used buckets are evicted
+    pub fn max_keys(mut self, max_keys: usize) -> Self {
+        self.max_keys = max_keys.max(1);
+        if let Some(ref mut per_key) = self.per_key {
+            per_key.max_keys = self.max_keys;
+        }
+        if let Some(ref mut per_ip) = self.per_ip {
+            per_ip.max_keys = self.max_keys;
+        }
+        self
+    }
+    pub fn per_key(mut self, budget: Budget) -> Self {
+        self.per_key = Some(Buckets::new(budget, self.max_keys));
+        self
+    }
+    pub fn per_ip(mut self, budget: Budget) -> Self {
+        self.per_ip = Some(Buckets::new(budget, self.max_keys));
+        self
+    }
+    pub fn trusted_proxies(mut self, proxies: TrustedProxies) -> Self {
+        self.proxies = proxies;
+        self
+    }
+    /// The header, the API key is taken from (case-insensitive)
+    pub fn key_header(mut self, header: &str) -> Self {
+        self.key_header = header.to_lowercase();
+        self
+    }
+    /// Checks the budgets for the client address and the API key (if specified)
+    pub fn check(&self, ip: IpAddr, key: Option<&str>) -> Result<(), Limited> {
+        let now = Instant::now();
+        if let Some(ref per_ip) = self.per_ip {
+            per_ip.take(ip, now)?;
+        }
+        if let (Some(per_key), Some(key)) = (self.per_key.as_ref(), key) {
+            per_key.take(key.to_owned(), now)?;
+        }
+        Ok(())
+    }
+    /// Checks the budgets for the connection peer and request headers (lowercase name getter)
+    pub fn check_request<'a, F>(&self, peer: IpAddr, header: F) -> Result<(), Limited>
+    where
+        F: Fn(&str) -> Option<&'a str>,
+    {
+        let ip = self
+            .proxies
+            .client_ip(peer, header(X_FORWARDED_FOR), header(X_REAL_IP));
+        self.check(ip, header(&self.key_header))
+    }
+    /// Removes idle buckets, should be called periodically
+    pub fn cleanup(&self) {
+        let now = Instant::now();
+        if let Some(ref per_ip) = self.per_ip {
+            per_ip.cleanup(now);
+        }
+        if let Some(ref per_key) = self.per_key {
+            per_key.cleanup(now);
+        }
+    }
+}
+
+/// Calls the handler if the request is within the budgets, otherwise responds with 429
+#[cfg(feature = "hyper-tools")]
+pub async fn limit_hyper<F, Fut>(
+    limiter: &RateLimiter,
+    peer: IpAddr,
+    req: hyper::Request<hyper::Body>,
+    handler: F,
+) -> Result<hyper::Response<hyper::Body>, hyper::http::Error>
+where
+    F: FnOnce(hyper::Request<hyper::Body>) -> Fut,
+    Fut: std::future::Future<Output = Result<hyper::Response<hyper::Body>, hyper::http::Error>>,
+{
+    let checked = limiter.check_request(peer, |name| {
+        req.headers().get(name).and_then(|v| v.to_str().ok())
+    });
+    if let Err(limited) = checked {
+        return hyper::Response::builder()
+            .status(hyper::StatusCode::TOO_MANY_REQUESTS)
+            .header(hyper::header::RETRY_AFTER, limited.retry_after_secs())
+            .body(hyper::Body::from(Error::from(limited).to_string()));
+    }
+    handler(req).await
+}
+
+/// Axum middleware, responds with 429 if the request budget is exhausted
+#[cfg(feature = "axum")]
+pub async fn axum_middleware<B>(
+    axum::extract::State(limiter): axum::extract::State<std::sync::Arc<RateLimiter>>,
+    axum::extract::ConnectInfo(addr): axum::extract::ConnectInfo<std::net::SocketAddr>,
+    req: axum::http::Request<B>,
+    next: axum::middleware::Next<B>,
+) -> axum::response::Response {
+    use axum::response::IntoResponse;
+    let checked = limiter.check_request(addr.ip(), |name| {
+        req.headers().get(name).and_then(|v| v.to_str().ok())
+    });
+    if let Err(limited) = checked {
+        return (
+            axum::http::StatusCode::TOO_MANY_REQUESTS,
+            [(
+                axum::http::header::RETRY_AFTER,
+                limited.retry_after_secs().to_string(),
+            )],
+            Error::from(limited).to_string(),
+        )
+            .into_response();
+    }
+    next.run(req).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Buckets, Budget, RateLimitConfig, RateLimiter};
+    use crate::netfilter::TrustedProxies;
+    use crate::ErrorKind;
+    use std::net::IpAddr;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_rate_limit() {
+        let buckets = Buckets::new(Budget::new(2, Duration::from_secs(1)), 10);
+        let t = Instant::now();
+        assert!(buckets.take("k", t).is_ok());
+        assert!(buckets.take("k", t).is_ok());
+        let limited = buckets.take("k", t).unwrap_err();
+        assert_eq!(limited.retry_after(), Duration::from_millis(500));
+        assert_eq!(limited.retry_after_secs(), 1);
+        assert_eq!(crate::Error::from(limited).kind(), ErrorKind::ResourceBusy);
+        assert!(buckets.take("k", t + Duration::from_millis(500)).is_ok());
+        assert!(buckets.take("x", t).is_ok());
+        buckets.cleanup(t + Duration::from_secs(2));
+        assert!(buckets.buckets.lock().is_empty());
+        let buckets = Buckets::new(Budget::new(1, Duration::from_secs(30)), 2);
+        assert!(buckets.take("a", t).is_ok());
+        assert!(buckets.take("b", t + Duration::from_secs(1)).is_ok());
+        assert!(buckets.take("c", t + Duration::from_secs(2)).is_ok());
+        let keys = buckets.buckets.lock();
+        assert_eq!(keys.len(), 2);
+        assert!(!keys.contains_key("a"));
+        drop(keys);
+        assert!(serde_json::from_str::<Budget>(r#"{"requests":1,"period":-1}"#).is_err());
+        let config: RateLimitConfig = serde_json::from_str(
+            r#"{"per_key":{"requests":1,"period":60},"trusted_proxies":["127.0.0.1/32"]}"#,
+        )
+        .unwrap();
+        let key_limiter = RateLimiter::from(&config);
+        let proxy: IpAddr = "127.0.0.1".parse().unwrap();
+        let headers = |key: &'static str, xff: &'static str| {
+            move |name: &str| match name {
+                "x-auth-key" => Some(key),
+                "x-forwarded-for" => Some(xff),
+                _ => None,
+            }
+        };
+        assert!(key_limiter
+            .check_request(proxy, headers("k1", "10.0.0.1"))
+            .is_ok());
+        assert!(key_limiter
+            .check_request(proxy, headers("k1", "10.0.0.1"))
+            .is_err());
+        assert!(key_limiter
+            .check_request(proxy, headers("k2", "10.0.0.1"))
+            .is_ok());
+        let ip_limiter = RateLimiter::new()
+            .per_ip(Budget::new(1, Duration::from_secs(30)))
+            .trusted_proxies(TrustedProxies::new().trust("127.0.0.1/32".parse().unwrap()));
+        assert!(ip_limiter
+            .check_request(proxy, headers("k", "10.0.0.1"))
+            .is_ok());
+        assert!(ip_limiter
+            .check_request(proxy, headers("k", "10.0.0.2"))
+            .is_ok());
+        assert!(ip_limiter
+            .check_request(proxy, headers("k", "10.0.0.1"))
+            .is_err());
+    }
+}
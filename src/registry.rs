@@ -4,6 +4,9 @@ use crate::prelude::*;
 use busrt::rpc::{Rpc, RpcClient};
 use busrt::QoS;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
 
 err_logger!();
 
@@ -20,6 +23,100 @@ pub const R_DATA: &str = "data";
 pub const R_CACHE: &str = "cache";
 pub const R_DATA_OBJECT: &str = "dobj";
 
+/// Registry key path, relative to the global key prefix, e.g. "svc_data/eva.svc.x/key"
+///
+/// Paths are always valid: segments are non-empty, do not contain control characters and are
+/// not "." or "..". Displayed as full keys ("eva/svc_data/eva.svc.x/key")
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub struct KeyPath(String);
+
+impl KeyPath {
+    /// Creates a path, leading and trailing slashes are ignored
+    pub fn new(path: &str) -> EResult<Self> {
+        let path = path.trim_matches('/');
+        if path.is_empty() {
+            return Err(Error::invalid_params("registry key path is empty"));
+        }
+        for segment in path.split('/') {
+            if segment.is_empty()
+                || segment == "."
+                || segment == ".."
+                || segment.chars().any(char::is_control)
+            {
+                return Err(Error::invalid_params(format!(
+                    "invalid registry key path: {}",
+                    path
+                )));
+            }
+        }
+        Ok(Self(path.to_owned()))
+    }
+    /// Creates a path from a full key, e.g. "eva/config/x"
+    pub fn from_full_key(key: &str) -> EResult<Self> {
+        key.strip_prefix(GLOBAL_KEY_PREFIX)
+            .and_then(|k| k.strip_prefix('/'))
+            .ok_or_else(|| Error::invalid_params(format!("invalid registry key: {}", key)))
+            .and_then(Self::new)
+    }
+    /// Service data path
+    #[inline]
+    pub fn svc_data(svc_id: &str) -> EResult<Self> {
+        Self::new(R_SERVICE_DATA)?.join(svc_id)
+    }
+    /// Appends one or more segments, e.g. "a/b"
+    pub fn join(&self, path: &str) -> EResult<Self> {
+        let path = Self::new(path)?;
+        Ok(Self(format!("{}/{}", self.0, path.0)))
+    }
+    /// Returns None for single-segment paths
+    pub fn parent(&self) -> Option<Self> {
+        self.0
+            .rsplit_once('/')
+            .map(|(parent, _)| Self(parent.to_owned()))
+    }
+    /// Returns the rest of the path if it is under the prefix
+    pub fn strip_prefix(&self, prefix: &KeyPath) -> Option<&str> {
+        self.0
+            .strip_prefix(&prefix.0)
+            .and_then(|s| s.strip_prefix('/'))
+    }
+    #[inline]
+    pub fn starts_with(&self, prefix: &KeyPath) -> bool {
+        self == prefix || self.strip_prefix(prefix).is_some()
+    }
+    #[inline]
+    pub fn segments(&self) -> impl Iterator<Item = &str> {
+        self.0.split('/')
+    }
+    #[inline]
+    pub fn last(&self) -> &str {
+        self.0.rsplit('/').next().unwrap_or_default()
+    }
+    /// Relative path
+    #[inline]
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+    #[inline]
+    pub fn to_full_key(&self) -> String {
+        format_top_key(&self.0)
+    }
+}
+
+impl fmt::Display for KeyPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", GLOBAL_KEY_PREFIX, self.0)
+    }
+}
+
+impl FromStr for KeyPath {
+    type Err = Error;
+    #[inline]
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::new(s)
+    }
+}
+
 // the below methods are pub as the core access the registry directly as db during startup
 #[inline]
 pub fn format_top_key(key: &str) -> String {
@@ -83,13 +180,25 @@ struct PayloadKey {
     key: String,
 }
 
+#[inline]
+fn key_path(prefix: &str, key: &str) -> EResult<KeyPath> {
+    KeyPath::new(prefix)?.join(key)
+}
+
 #[inline]
 pub async fn key_set<V>(prefix: &str, key: &str, value: V, rpc: &RpcClient) -> EResult<Value>
+where
+    V: Serialize,
+{
+    key_set_path(&key_path(prefix, key)?, value, rpc).await
+}
+
+pub async fn key_set_path<V>(path: &KeyPath, value: V, rpc: &RpcClient) -> EResult<Value>
 where
     V: Serialize,
 {
     let payload = PayloadKeySet {
-        key: format_key(prefix, key),
+        key: path.to_full_key(),
         value: to_value(value)?,
     };
     call("key_set", payload, rpc).await
@@ -97,24 +206,36 @@ where
 
 #[inline]
 pub async fn key_get(prefix: &str, key: &str, rpc: &RpcClient) -> EResult<Value> {
+    key_get_path(&key_path(prefix, key)?, rpc).await
+}
+
+pub async fn key_get_path(path: &KeyPath, rpc: &RpcClient) -> EResult<Value> {
     let payload = PayloadKey {
-        key: format_key(prefix, key),
+        key: path.to_full_key(),
     };
     call("key_get", payload, rpc).await
 }
 
 #[inline]
 pub async fn key_increment(prefix: &str, key: &str, rpc: &RpcClient) -> EResult<i64> {
+    key_increment_path(&key_path(prefix, key)?, rpc).await
+}
+
+pub async fn key_increment_path(path: &KeyPath, rpc: &RpcClient) -> EResult<i64> {
     let payload = PayloadKey {
-        key: format_key(prefix, key),
+        key: path.to_full_key(),
     };
     TryInto::<i64>::try_into(call("key_increment", payload, rpc).await?).map_err(Into::into)
 }
 
 #[inline]
 pub async fn key_decrement(prefix: &str, key: &str, rpc: &RpcClient) -> EResult<i64> {
+    key_decrement_path(&key_path(prefix, key)?, rpc).await
+}
+
+pub async fn key_decrement_path(path: &KeyPath, rpc: &RpcClient) -> EResult<i64> {
     let payload = PayloadKey {
-        key: format_key(prefix, key),
+        key: path.to_full_key(),
     };
     TryInto::<i64>::try_into(call("key_decrement", payload, rpc).await?).map_err(Into::into)
 }
@@ -124,38 +245,256 @@ pub async fn key_get_recursive(
     prefix: &str,
     key: &str,
     rpc: &RpcClient,
+) -> EResult<Vec<(String, Value)>> {
+    key_get_recursive_path(&key_path(prefix, key)?, rpc).await
+}
+
+/// Returned key names are relative to the path
+pub async fn key_get_recursive_path(
+    path: &KeyPath,
+    rpc: &RpcClient,
 ) -> EResult<Vec<(String, Value)>> {
     let payload = PayloadKey {
-        key: format_key(prefix, key),
+        key: path.to_full_key(),
     };
-    let key_len = payload.key.len() + 1;
     let val = call("key_get_recursive", payload, rpc).await?;
     let res: Vec<(String, Value)> = Vec::deserialize(val)?;
     let mut result: Vec<(String, Value)> = Vec::new();
     for (k, v) in res {
-        if k.len() < key_len {
-            return Err(Error::invalid_data(format!(
-                "invalid key name returned by the registry: {}",
-                k
-            )));
-        }
-        result.push((k[key_len..].to_string(), v));
+        let rel = KeyPath::from_full_key(&k)
+            .ok()
+            .and_then(|p| p.strip_prefix(path).map(ToOwned::to_owned))
+            .ok_or_else(|| {
+                Error::invalid_data(format!("invalid key name returned by the registry: {}", k))
+            })?;
+        result.push((rel, v));
     }
     Ok(result)
 }
 
 #[inline]
 pub async fn key_delete(prefix: &str, key: &str, rpc: &RpcClient) -> EResult<Value> {
+    key_delete_path(&key_path(prefix, key)?, rpc).await
+}
+
+pub async fn key_delete_path(path: &KeyPath, rpc: &RpcClient) -> EResult<Value> {
     let payload = PayloadKey {
-        key: format_key(prefix, key),
+        key: path.to_full_key(),
     };
     call("key_delete", payload, rpc).await
 }
 
 #[inline]
 pub async fn key_delete_recursive(prefix: &str, key: &str, rpc: &RpcClient) -> EResult<Value> {
+    key_delete_recursive_path(&key_path(prefix, key)?, rpc).await
+}
+
+pub async fn key_delete_recursive_path(path: &KeyPath, rpc: &RpcClient) -> EResult<Value> {
     let payload = PayloadKey {
-        key: format_key(prefix, key),
+        key: path.to_full_key(),
     };
     call("key_delete_recursive", payload, rpc).await
 }
+
+/// Registry subtree in the canonical form: keys, relative to the subtree root, sorted
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Subtree(BTreeMap<String, Value>);
+
+impl Subtree {
+    /// Exports a registry subtree
+    pub async fn export(path: &KeyPath, rpc: &RpcClient) -> EResult<Self> {
+        Ok(key_get_recursive_path(path, rpc)
+            .await?
+            .into_iter()
+            .collect())
+    }
+    #[inline]
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.0.get(key)
+    }
+    #[inline]
+    pub fn insert(&mut self, key: String, value: Value) -> Option<Value> {
+        self.0.insert(key, value)
+    }
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Value)> {
+        self.0.iter().map(|(k, v)| (k.as_str(), v))
+    }
+    /// Computes changes, which turn the subtree into the target one
+    pub fn diff(&self, target: &Subtree) -> Diff {
+        let mut changes: Vec<Change> = target
+            .0
+            .iter()
+            .filter(|(key, value)| self.0.get(*key) != Some(*value))
+            .map(|(key, value)| Change::Set {
+                key: key.clone(),
+                value: value.clone(),
+            })
+            .collect();
+        changes.extend(
+            self.0
+                .keys()
+                .filter(|key| !target.0.contains_key(*key))
+                .map(|key| Change::Delete { key: key.clone() }),
+        );
+        changes.sort_by(|a, b| a.key().cmp(b.key()));
+        Diff(changes)
+    }
+    /// Applies changes locally, e.g. to preview the result
+    pub fn apply(&mut self, diff: &Diff) {
+        for change in &diff.0 {
+            match change {
+                Change::Set { key, value } => {
+                    self.0.insert(key.clone(), value.clone());
+                }
+                Change::Delete { key } => {
+                    self.0.remove(key);
+                }
+            }
+        }
+    }
+}
+
+impl FromIterator<(String, Value)> for Subtree {
+    fn from_iter<I: IntoIterator<Item = (String, Value)>>(iter: I) -> Self {
+        Self(iter.into_iter().collect())
+    }
+}
+
+/// Registry subtree change, keys are relative to the subtree root
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum Change {
+    Set {
+        key: String,
+        value: Value,
+    },
+    /// Deletion marker
+    Delete {
+        key: String,
+    },
+}
+
+impl Change {
+    #[inline]
+    pub fn key(&self) -> &str {
+        match self {
+            Change::Set { key, .. } | Change::Delete { key } => key,
+        }
+    }
+}
+
+/// Registry subtree diff, sorted by key
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Diff(Vec<Change>);
+
+impl Diff {
+    #[inline]
+    pub fn changes(&self) -> &[Change] {
+        &self.0
+    }
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+    /// Applies the diff to a registry subtree. All keys are validated before any change is made
+    pub async fn apply(&self, path: &KeyPath, rpc: &RpcClient) -> EResult<()> {
+        let keys = self
+            .0
+            .iter()
+            .map(|change| path.join(change.key()))
+            .collect::<EResult<Vec<KeyPath>>>()?;
+        for (change, key) in self.0.iter().zip(keys) {
+            match change {
+                Change::Set { value, .. } => {
+                    key_set_path(&key, value, rpc).await?;
+                }
+                Change::Delete { .. } => {
+                    key_delete_path(&key, rpc).await?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Change, KeyPath, Subtree};
+    use crate::value::Value;
+
+    #[test]
+    fn test_key_path() {
+        let path = KeyPath::svc_data("eva.svc.x").unwrap();
+        assert_eq!(path.as_str(), "svc_data/eva.svc.x");
+        assert_eq!(path.to_string(), "eva/svc_data/eva.svc.x");
+        let key = path.join("/cache/k1/").unwrap();
+        assert_eq!(key.to_full_key(), "eva/svc_data/eva.svc.x/cache/k1");
+        assert_eq!(key.strip_prefix(&path), Some("cache/k1"));
+        assert!(key.starts_with(&path));
+        assert!(!KeyPath::new("svc_data/eva.svc").unwrap().starts_with(&path));
+        assert_eq!(key.last(), "k1");
+        assert_eq!(key.parent().unwrap().as_str(), "svc_data/eva.svc.x/cache");
+        assert!(KeyPath::new("svc").unwrap().parent().is_none());
+        assert_eq!(key.segments().count(), 4);
+        assert_eq!(KeyPath::from_full_key(&key.to_full_key()).unwrap(), key);
+        assert!(KeyPath::from_full_key("evax/config").is_err());
+        for invalid in ["", "/", "a//b", "a/../b", "a/./b", "a/b\n"] {
+            assert!(KeyPath::new(invalid).is_err(), "{}", invalid);
+        }
+        assert!(path.join("").is_err());
+    }
+    #[test]
+    fn test_subtree_diff() {
+        let staging: Subtree = [
+            ("a".to_owned(), Value::U64(1)),
+            ("b/c".to_owned(), Value::String("new".to_owned())),
+            ("d".to_owned(), Value::Bool(true)),
+        ]
+        .into_iter()
+        .collect();
+        let mut production: Subtree = [
+            ("a".to_owned(), Value::U64(1)),
+            ("b/c".to_owned(), Value::String("old".to_owned())),
+            ("x".to_owned(), Value::Unit),
+        ]
+        .into_iter()
+        .collect();
+        let diff = production.diff(&staging);
+        assert_eq!(
+            diff.changes(),
+            [
+                Change::Set {
+                    key: "b/c".to_owned(),
+                    value: Value::String("new".to_owned())
+                },
+                Change::Set {
+                    key: "d".to_owned(),
+                    value: Value::Bool(true)
+                },
+                Change::Delete {
+                    key: "x".to_owned()
+                }
+            ]
+        );
+        let serialized = serde_json::to_string(&diff).unwrap();
+        assert!(serialized.ends_with(r#"{"op":"delete","key":"x"}]"#));
+        assert_eq!(
+            serde_json::from_str::<super::Diff>(&serialized).unwrap(),
+            diff
+        );
+        production.apply(&diff);
+        assert_eq!(production, staging);
+        assert!(production.diff(&staging).is_empty());
+    }
+}
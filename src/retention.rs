@@ -0,0 +1,300 @@
+//! Age-based data retention policies for history databases
+//!
+//! A policy keeps raw states for a while, rolls older data up into coarser tiers and finally
+//! deletes it. Ages are fixed periods (a day is always 86400 seconds), not calendar units. The storage-specific part is implemented by history services with [`Executor`].
+//!
+//! ```yaml
+//! keep_raw: 7D
+//! downsample:
+//!   - after: 1D
+//!     resolution: 1T
+//!   - after: 30D
+//!     resolution: 1H
+//!     agg: max
+//! delete_after: 365D
+//! ```
+use crate::history::{Agg, Fill};
+use crate::time::Time;
+use crate::{EResult, Error};
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+
+/// Maximum policy age (seconds), ages must fit nanosecond timestamps
+const MAX_AGE: f64 = 18_446_744_073.0;
+
+fn default_agg() -> Agg {
+    Agg::Mean
+}
+
+fn check_age(age: Fill) -> EResult<()> {
+    if age.as_secs_f64() > MAX_AGE {
+        return Err(Error::invalid_params(format!(
+            "retention: age {} is too large",
+            age
+        )));
+    }
+    Ok(())
+}
+
+/// Downsampling tier: data older than `after` is kept with the tier resolution only
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Tier {
+    pub after: Fill,
+    pub resolution: Fill,
+    #[serde(default = "default_agg")]
+    pub agg: Agg,
+}
+
+/// Retention policy
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Policy {
+    /// Raw states older than this age are deleted
+    keep_raw: Fill,
+    /// Roll-up tiers, sorted by age
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    downsample: Vec<Tier>,
+    /// All data older than this age is deleted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    delete_after: Option<Fill>,
+}
+
+/// Retention action, planned by [`Policy::plan()`]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Action {
+    /// Roll up data older than the time to the resolution. Roll-ups are calculated from raw
+    /// states or finer roll-ups, finer roll-ups older than the time are removed
+    Downsample {
+        before: Time,
+        resolution: Fill,
+        agg: Agg,
+    },
+    /// Delete raw states older than the time
+    DeleteRaw { before: Time },
+    /// Delete all data (raw states and roll-ups) older than the time
+    Delete { before: Time },
+}
+
+/// Storage-specific retention actions, implemented by history services
+pub trait Executor {
+    fn downsample(
+        &mut self,
+        before: Time,
+        resolution: Fill,
+        agg: Agg,
+    ) -> impl Future<Output = EResult<()>> + Send;
+    fn delete_raw(&mut self, before: Time) -> impl Future<Output = EResult<()>> + Send;
+    fn delete(&mut self, before: Time) -> impl Future<Output = EResult<()>> + Send;
+}
+
+impl Policy {
+    /// Creates a policy which keeps raw states only
+    #[inline]
+    pub fn new(keep_raw: Fill) -> Self {
+        Self {
+            keep_raw,
+            downsample: Vec::new(),
+            delete_after: None,
+        }
+    }
+    pub fn downsample(mut self, after: Fill, resolution: Fill, agg: Agg) -> Self {
+        self.downsample.push(Tier {
+            after,
+            resolution,
+            agg,
+        });
+        self
+    }
+    pub fn delete_after(mut self, delete_after: Fill) -> Self {
+        self.delete_after = Some(delete_after);
+        self
+    }
+    #[inline]
+    pub fn keep_raw(&self) -> Fill {
+        self.keep_raw
+    }
+    #[inline]
+    pub fn tiers(&self) -> &[Tier] {
+        &self.downsample
+    }
+    #[inline]
+    pub fn delete_after_age(&self) -> Option<Fill> {
+        self.delete_after
+    }
+    /// Checks the policy consistency: tiers must be sorted by both age and resolution, raw
+    /// states must not expire before the first roll-up and all data must not be deleted before
+    /// it reaches the last tier
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the policy is inconsistent
+    pub fn validate(&self) -> EResult<()> {
+        check_age(self.keep_raw)?;
+        for tier in &self.downsample {
+            check_age(tier.after)?;
+        }
+        if let Some(delete_after) = self.delete_after {
+            check_age(delete_after)?;
+        }
+        for pair in self.downsample.windows(2) {
+            if pair[1].after <= pair[0].after {
+                return Err(Error::invalid_params(format!(
+                    "retention: downsampling tier after {} must be older than {}",
+                    pair[1].after, pair[0].after
+                )));
+            }
+            if pair[1].resolution <= pair[0].resolution {
+                return Err(Error::invalid_params(format!(
+                    "retention: downsampling tier after {} must have coarser resolution than {}",
+                    pair[1].after, pair[0].resolution
+                )));
+            }
+        }
+        if let Some(first) = self.downsample.first() {
+            if first.after > self.keep_raw {
+                return Err(Error::invalid_params(format!(
+                    "retention: raw states (kept {}) expire before the first roll-up ({})",
+                    self.keep_raw, first.after
+                )));
+            }
+        }
+        if let Some(delete_after) = self.delete_after {
+            let last = self
+                .downsample
+                .last()
+                .map_or(self.keep_raw, |tier| tier.after);
+            if delete_after < last || delete_after < self.keep_raw {
+                return Err(Error::invalid_params(format!(
+                    "retention: data is deleted ({}) before the policy is fully applied",
+                    delete_after
+                )));
+            }
+        }
+        Ok(())
+    }
+    /// Plans retention actions for the given time: roll-ups (from the finest tier to the
+    /// coarsest), raw states cleanup and the final cleanup. Times older than the epoch are
+    /// clamped to it
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the policy is inconsistent
+    pub fn plan(&self, now: Time) -> EResult<Vec<Action>> {
+        self.validate()?;
+        let now_ns = now.timestamp_ns();
+        let before = |age: Fill| {
+            let age_ns = u64::try_from(age.as_duration().as_nanos()).unwrap_or(u64::MAX);
+            Time::from_timestamp_ns(now_ns.saturating_sub(age_ns))
+        };
+        let mut actions: Vec<Action> = self
+            .downsample
+            .iter()
+            .map(|tier| Action::Downsample {
+                before: before(tier.after),
+                resolution: tier.resolution,
+                agg: tier.agg,
+            })
+            .collect();
+        actions.push(Action::DeleteRaw {
+            before: before(self.keep_raw),
+        });
+        if let Some(delete_after) = self.delete_after {
+            actions.push(Action::Delete {
+                before: before(delete_after),
+            });
+        }
+        Ok(actions)
+    }
+    /// Plans and executes retention actions
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the policy is inconsistent or an action has failed
+    pub async fn apply<E: Executor>(&self, executor: &mut E, now: Time) -> EResult<()> {
+        for action in self.plan(now)? {
+            match action {
+                Action::Downsample {
+                    before,
+                    resolution,
+                    agg,
+                } => executor.downsample(before, resolution, agg).await?,
+                Action::DeleteRaw { before } => executor.delete_raw(before).await?,
+                Action::Delete { before } => executor.delete(before).await?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Action, Policy};
+    use crate::history::{Agg, Fill};
+    use crate::time::Time;
+
+    #[test]
+    fn test_retention_policy() {
+        let policy: Policy = serde_json::from_str(
+            r#"{"keep_raw":"7D","downsample":[{"after":"1D","resolution":"1T"},
+            {"after":"30D","resolution":"1H","agg":"max"}],"delete_after":"365D"}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            policy,
+            Policy::new("7D".parse().unwrap())
+                .downsample("1D".parse().unwrap(), "1T".parse().unwrap(), Agg::Mean)
+                .downsample("30D".parse().unwrap(), "1H".parse().unwrap(), Agg::Max)
+                .delete_after("365D".parse().unwrap())
+        );
+        let now = Time::from_timestamp(1_700_000_000.0);
+        let day = 86_400.0;
+        let hour: Fill = "1H".parse().unwrap();
+        let actions = policy.plan(now).unwrap();
+        assert_eq!(actions.len(), 4);
+        assert_eq!(
+            actions[1],
+            Action::Downsample {
+                before: Time::from_timestamp(1_700_000_000.0 - day * 30.0),
+                resolution: hour,
+                agg: Agg::Max
+            }
+        );
+        assert_eq!(
+            actions[2],
+            Action::DeleteRaw {
+                before: Time::from_timestamp(1_700_000_000.0 - day * 7.0)
+            }
+        );
+        assert_eq!(
+            actions[3],
+            Action::Delete {
+                before: Time::from_timestamp(1_700_000_000.0 - day * 365.0)
+            }
+        );
+        // raw states expire before the first roll-up
+        let p =
+            Policy::new("1D".parse().unwrap()).downsample("7D".parse().unwrap(), hour, Agg::Mean);
+        assert!(p.plan(now).is_err());
+        // tiers are not sorted
+        let p = Policy::new("7D".parse().unwrap())
+            .downsample("1D".parse().unwrap(), hour, Agg::Mean)
+            .downsample("2D".parse().unwrap(), "1T".parse().unwrap(), Agg::Mean);
+        assert!(p.validate().is_err());
+        // data deleted too early
+        let p = Policy::new("7D".parse().unwrap()).delete_after("1D".parse().unwrap());
+        assert!(p.validate().is_err());
+        assert!(serde_json::from_str::<Policy>(r#"{"keep_raw":"7D","keep":1}"#).is_err());
+        // ages, not representable as timestamps
+        let p = Policy::new("100000W".parse().unwrap());
+        assert!(p.plan(now).is_err());
+        // ages past the epoch
+        let actions = Policy::new("3000W".parse().unwrap()).plan(now).unwrap();
+        assert_eq!(
+            actions[0],
+            Action::DeleteRaw {
+                before: Time::from_timestamp_ns(0)
+            }
+        );
+    }
+}
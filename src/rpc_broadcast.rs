@@ -0,0 +1,226 @@
+//! Fan-out RPC calls to multiple targets (e.g. replication services of cluster nodes) with
+//! partial-result aggregation
+use crate::payload::unpack;
+use crate::value::Value;
+use crate::{EResult, Error};
+use busrt::rpc::{Rpc, RpcClient};
+use busrt::QoS;
+use serde::de::DeserializeOwned;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Broadcast call targets
+#[derive(Debug, Clone)]
+pub enum Targets {
+    List(Vec<String>),
+    /// Service id mask, e.g. "eva.repl.*", resolved with the broker client list
+    Mask(String),
+}
+
+impl From<Vec<String>> for Targets {
+    fn from(v: Vec<String>) -> Self {
+        Targets::List(v)
+    }
+}
+
+impl From<&[&str]> for Targets {
+    fn from(v: &[&str]) -> Self {
+        Targets::List(v.iter().map(|s| (*s).to_owned()).collect())
+    }
+}
+
+/// When the broadcast is finished
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub enum Mode {
+    /// Wait for all targets, partial errors are returned in the aggregate
+    #[default]
+    All,
+    /// Return as soon as one target replies successfully, fail if none does
+    FirstSuccess,
+    /// Return as soon as the specified number of targets reply successfully, fail if this
+    /// becomes impossible
+    Quorum(usize),
+}
+
+/// Broadcast call results
+#[derive(Debug)]
+pub struct Broadcast<T> {
+    pub replies: BTreeMap<String, T>,
+    pub errors: BTreeMap<String, Error>,
+    /// Targets, which have not replied when the broadcast was finished
+    pub pending: BTreeSet<String>,
+}
+
+impl<T> Broadcast<T> {
+    fn new(targets: &[String]) -> Self {
+        Self {
+            replies: BTreeMap::new(),
+            errors: BTreeMap::new(),
+            pending: targets.iter().cloned().collect(),
+        }
+    }
+    fn push(&mut self, target: String, result: EResult<T>) {
+        if !self.pending.remove(&target) {
+            return;
+        }
+        match result {
+            Ok(v) => {
+                self.replies.insert(target, v);
+            }
+            Err(e) => {
+                self.errors.insert(target, e);
+            }
+        }
+    }
+    fn is_done(&self, mode: Mode) -> bool {
+        if self.pending.is_empty() {
+            return true;
+        }
+        match mode {
+            Mode::All => false,
+            Mode::FirstSuccess => !self.replies.is_empty(),
+            Mode::Quorum(n) => {
+                self.replies.len() >= n || self.replies.len() + self.pending.len() < n
+            }
+        }
+    }
+    fn finish(self, mode: Mode) -> EResult<Self> {
+        let required = match mode {
+            Mode::All => return Ok(self),
+            Mode::FirstSuccess => 1,
+            Mode::Quorum(n) => n,
+        };
+        if self.replies.len() >= required {
+            Ok(self)
+        } else {
+            let errors: Vec<String> = self
+                .errors
+                .iter()
+                .map(|(target, e)| format!("{}: {}", target, e))
+                .chain(self.pending.iter().map(|t| format!("{}: no reply", t)))
+                .collect();
+            Err(Error::failed(format!(
+                "broadcast failed, {}/{} successful replies ({})",
+                self.replies.len(),
+                required,
+                errors.join(", ")
+            )))
+        }
+    }
+    /// The first successful reply (by target name)
+    pub fn first(&self) -> Option<(&str, &T)> {
+        self.replies.iter().next().map(|(k, v)| (k.as_str(), v))
+    }
+    /// Returns true if all targets have replied successfully
+    pub fn is_complete(&self) -> bool {
+        self.errors.is_empty() && self.pending.is_empty()
+    }
+}
+
+/// Calls the method on all targets concurrently. The params must be packed (empty payload for
+/// no params), empty replies are deserialized from [`Value::Unit`]
+///
+/// The timeout is applied to each call, so the broadcast is finished within it
+///
+/// # Errors
+///
+/// Will return `Err` if the targets can not be resolved or the mode requirement is not met
+pub async fn rpc_broadcast<T>(
+    rpc: &Arc<RpcClient>,
+    targets: Targets,
+    method: &str,
+    params: &[u8],
+    timeout: Duration,
+    mode: Mode,
+) -> EResult<Broadcast<T>>
+where
+    T: DeserializeOwned + Send + 'static,
+{
+    let targets = match targets {
+        Targets::List(v) => v,
+        Targets::Mask(mask) => crate::services::list_services(rpc, &mask, timeout).await?,
+    };
+    let mut result = Broadcast::new(&targets);
+    if targets.is_empty() {
+        return result.finish(mode);
+    }
+    let params = Arc::new(params.to_vec());
+    let (tx, mut rx) = tokio::sync::mpsc::channel(targets.len());
+    let mut tasks = Vec::with_capacity(targets.len());
+    for target in targets {
+        let rpc = rpc.clone();
+        let params = params.clone();
+        let method = method.to_owned();
+        let tx = tx.clone();
+        tasks.push(tokio::spawn(async move {
+            let res = match tokio::time::timeout(
+                timeout,
+                rpc.call(&target, &method, params.into(), QoS::Processed),
+            )
+            .await
+            {
+                Ok(Ok(ev)) => {
+                    if ev.payload().is_empty() {
+                        T::deserialize(Value::Unit).map_err(Error::invalid_data)
+                    } else {
+                        unpack(ev.payload())
+                    }
+                }
+                Ok(Err(e)) => Err(Error::from(e)),
+                Err(_) => Err(Error::timeout()),
+            };
+            let _ = tx.send((target, res)).await;
+        }));
+    }
+    drop(tx);
+    while !result.is_done(mode) {
+        let Some((target, res)) = rx.recv().await else {
+            break;
+        };
+        result.push(target, res);
+    }
+    for task in tasks {
+        task.abort();
+    }
+    result.finish(mode)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Broadcast, Mode};
+    use crate::Error;
+
+    #[test]
+    fn test_broadcast_aggregate() {
+        let targets: Vec<String> = ["n1", "n2", "n3"].iter().map(|s| (*s).to_owned()).collect();
+        let mut b: Broadcast<u32> = Broadcast::new(&targets);
+        assert!(!b.is_done(Mode::FirstSuccess));
+        b.push("n2".to_owned(), Err(Error::timeout()));
+        assert!(!b.is_done(Mode::FirstSuccess));
+        assert!(!b.is_done(Mode::Quorum(2)));
+        b.push("n3".to_owned(), Ok(3));
+        // a duplicate or unknown reply is ignored
+        b.push("n3".to_owned(), Ok(4));
+        b.push("n4".to_owned(), Ok(4));
+        assert!(b.is_done(Mode::FirstSuccess));
+        assert!(!b.is_done(Mode::All));
+        assert!(!b.is_done(Mode::Quorum(2)));
+        assert!(b.is_done(Mode::Quorum(3)), "quorum is not reachable");
+        assert_eq!(b.first(), Some(("n3", &3)));
+        assert!(!b.is_complete());
+        let err = Broadcast::<u32>::new(&targets)
+            .finish(Mode::Quorum(1))
+            .unwrap_err();
+        assert!(err.to_string().contains("n1: no reply"));
+        b.push("n1".to_owned(), Ok(1));
+        assert!(b.is_done(Mode::All));
+        let b = b.finish(Mode::Quorum(2)).unwrap();
+        assert_eq!(b.replies.len(), 2);
+        assert_eq!(b.errors.len(), 1);
+        assert!(b.pending.is_empty());
+        assert!(Broadcast::<u32>::new(&[])
+            .finish(Mode::FirstSuccess)
+            .is_err());
+    }
+}
@@ -0,0 +1,314 @@
+//! Back-pressure for bus RPC calls
+//!
+//! [`CallLimiter`] limits the number of in-flight calls per target and collects per-method
+//! statistics, [`LimitedRpcClient`] applies it to [`RpcClient`] calls.
+use crate::{EResult, Error};
+use busrt::borrow::Cow;
+use busrt::rpc::{Rpc, RpcClient, RpcEvent};
+use busrt::QoS;
+use parking_lot::Mutex;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+pub const DEFAULT_MAX_IN_FLIGHT: usize = 16;
+
+/// What to do with calls, exceeding the in-flight limit
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub enum Overflow {
+    /// wait for a free slot (up to the queue timeout, if set)
+    #[default]
+    Queue,
+    /// reject with [`crate::ErrorKind::ResourceBusy`]
+    Reject,
+}
+
+/// Per-method call statistics
+#[derive(Debug, Clone, Default)]
+pub struct MethodStats {
+    /// finished calls
+    pub calls: u64,
+    /// finished calls, returned errors
+    pub errors: u64,
+    /// calls, rejected or timed out in the queue
+    pub rejected: u64,
+    pub in_flight: usize,
+    pub in_flight_peak: usize,
+    pub latency_max: Duration,
+    latency_total: Duration,
+}
+
+impl MethodStats {
+    /// Average latency of the finished calls
+    #[allow(clippy::cast_precision_loss)]
+    pub fn latency_avg(&self) -> Duration {
+        if self.calls == 0 {
+            Duration::ZERO
+        } else {
+            self.latency_total.div_f64(self.calls as f64)
+        }
+    }
+}
+
+type StatsMap = HashMap<(String, String), MethodStats>;
+
+/// Limits in-flight calls per target
+pub struct CallLimiter {
+    max_in_flight: usize,
+    target_limits: HashMap<String, usize>,
+    overflow: Overflow,
+    queue_timeout: Option<Duration>,
+    semaphores: Mutex<HashMap<String, Arc<Semaphore>>>,
+    stats: Arc<Mutex<StatsMap>>,
+}
+
+impl Default for CallLimiter {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_IN_FLIGHT)
+    }
+}
+
+impl CallLimiter {
+    pub fn new(max_in_flight: usize) -> Self {
+        Self {
+            max_in_flight,
+            target_limits: <_>::default(),
+            overflow: Overflow::default(),
+            queue_timeout: None,
+            semaphores: <_>::default(),
+            stats: <_>::default(),
+        }
+    }
+    /// Overrides the in-flight limit for a target
+    pub fn target_limit(mut self, target: &str, max_in_flight: usize) -> Self {
+        self.target_limits.insert(target.to_owned(), max_in_flight);
+        self
+    }
+    pub fn overflow(mut self, overflow: Overflow) -> Self {
+        self.overflow = overflow;
+        self
+    }
+    /// Max time a queued call waits for a free slot
+    pub fn queue_timeout(mut self, timeout: Duration) -> Self {
+        self.queue_timeout = Some(timeout);
+        self
+    }
+    fn semaphore(&self, target: &str) -> Arc<Semaphore> {
+        self.semaphores
+            .lock()
+            .entry(target.to_owned())
+            .or_insert_with(|| {
+                let max = self
+                    .target_limits
+                    .get(target)
+                    .copied()
+                    .unwrap_or(self.max_in_flight);
+                Arc::new(Semaphore::new(max))
+            })
+            .clone()
+    }
+    fn reject(&self, target: &str, method: &str) -> Error {
+        with_stats(&self.stats, target, method, |s| s.rejected += 1);
+        Error::busy(format!("too many in-flight calls to {}", target))
+    }
+    /// Acquires a slot for a call. The returned permit must be finished when the call is
+    /// completed, if dropped unfinished, the call is counted as failed
+    pub async fn acquire(&self, target: &str, method: &str) -> EResult<CallPermit> {
+        let sem = self.semaphore(target);
+        let permit = match self.overflow {
+            Overflow::Reject => sem
+                .try_acquire_owned()
+                .map_err(|_| self.reject(target, method))?,
+            Overflow::Queue => if let Some(timeout) = self.queue_timeout {
+                tokio::time::timeout(timeout, sem.acquire_owned())
+                    .await
+                    .map_err(|_| self.reject(target, method))?
+            } else {
+                sem.acquire_owned().await
+            }
+            .map_err(Error::core)?,
+        };
+        with_stats(&self.stats, target, method, |s| {
+            s.in_flight += 1;
+            s.in_flight_peak = s.in_flight_peak.max(s.in_flight);
+        });
+        Ok(CallPermit {
+            _permit: permit,
+            stats: self.stats.clone(),
+            target: target.to_owned(),
+            method: method.to_owned(),
+            started: Instant::now(),
+            finished: false,
+        })
+    }
+    /// Current in-flight calls to the target
+    pub fn in_flight(&self, target: &str) -> usize {
+        self.stats
+            .lock()
+            .iter()
+            .filter(|((t, _), _)| t == target)
+            .map(|(_, s)| s.in_flight)
+            .sum()
+    }
+    /// Statistics snapshot, by (target, method)
+    pub fn stats(&self) -> BTreeMap<(String, String), MethodStats> {
+        self.stats
+            .lock()
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect()
+    }
+    pub fn reset_stats(&self) {
+        self.stats.lock().retain(|_, s| {
+            *s = MethodStats {
+                in_flight: s.in_flight,
+                ..MethodStats::default()
+            };
+            s.in_flight > 0
+        });
+    }
+}
+
+fn with_stats<F>(stats: &Mutex<StatsMap>, target: &str, method: &str, f: F)
+where
+    F: FnOnce(&mut MethodStats),
+{
+    let mut stats = stats.lock();
+    if let Some(s) = stats.get_mut(&(target.to_owned(), method.to_owned())) {
+        f(s);
+    } else {
+        let mut s = MethodStats::default();
+        f(&mut s);
+        stats.insert((target.to_owned(), method.to_owned()), s);
+    }
+}
+
+/// An in-flight call slot
+pub struct CallPermit {
+    _permit: OwnedSemaphorePermit,
+    stats: Arc<Mutex<StatsMap>>,
+    target: String,
+    method: String,
+    started: Instant,
+    finished: bool,
+}
+
+impl CallPermit {
+    /// Marks the call finished and records its result
+    pub fn finish(mut self, success: bool) {
+        self.record(success);
+    }
+    fn record(&mut self, success: bool) {
+        if self.finished {
+            return;
+        }
+        self.finished = true;
+        let elapsed = self.started.elapsed();
+        with_stats(&self.stats, &self.target, &self.method, |s| {
+            s.in_flight = s.in_flight.saturating_sub(1);
+            s.calls += 1;
+            if !success {
+                s.errors += 1;
+            }
+            s.latency_total += elapsed;
+            s.latency_max = s.latency_max.max(elapsed);
+        });
+    }
+}
+
+impl Drop for CallPermit {
+    fn drop(&mut self) {
+        // the call has been cancelled
+        self.record(false);
+    }
+}
+
+/// RPC client wrapper, which applies [`CallLimiter`] to the calls
+pub struct LimitedRpcClient {
+    rpc: Arc<RpcClient>,
+    limiter: CallLimiter,
+}
+
+impl LimitedRpcClient {
+    pub fn new(rpc: Arc<RpcClient>, limiter: CallLimiter) -> Self {
+        Self { rpc, limiter }
+    }
+    #[inline]
+    pub fn rpc(&self) -> &Arc<RpcClient> {
+        &self.rpc
+    }
+    #[inline]
+    pub fn limiter(&self) -> &CallLimiter {
+        &self.limiter
+    }
+    pub async fn call(
+        &self,
+        target: &str,
+        method: &str,
+        params: Cow<'_>,
+        qos: QoS,
+    ) -> EResult<RpcEvent> {
+        let permit = self.limiter.acquire(target, method).await?;
+        let result = self.rpc.call(target, method, params, qos).await;
+        permit.finish(result.is_ok());
+        Ok(result?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CallLimiter, Overflow};
+    use crate::ErrorKind;
+    use std::time::Duration;
+
+    #[test]
+    fn test_call_limiter() {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(call_limiter());
+    }
+
+    async fn call_limiter() {
+        let limiter = CallLimiter::new(2)
+            .target_limit("eva.svc.slow", 1)
+            .overflow(Overflow::Reject);
+        let p1 = limiter.acquire("eva.svc.fast", "m1").await.unwrap();
+        let p2 = limiter.acquire("eva.svc.fast", "m2").await.unwrap();
+        assert_eq!(limiter.in_flight("eva.svc.fast"), 2);
+        assert_eq!(
+            limiter
+                .acquire("eva.svc.fast", "m1")
+                .await
+                .err()
+                .unwrap()
+                .kind(),
+            ErrorKind::ResourceBusy
+        );
+        let s1 = limiter.acquire("eva.svc.slow", "m1").await.unwrap();
+        assert!(limiter.acquire("eva.svc.slow", "m1").await.is_err());
+        p1.finish(true);
+        // cancelled
+        drop(p2);
+        s1.finish(true);
+        assert_eq!(limiter.in_flight("eva.svc.fast"), 0);
+        let stats = limiter.stats();
+        let m1 = &stats[&("eva.svc.fast".to_owned(), "m1".to_owned())];
+        assert_eq!((m1.calls, m1.errors, m1.rejected), (1, 0, 1));
+        assert_eq!(m1.in_flight_peak, 1);
+        let m2 = &stats[&("eva.svc.fast".to_owned(), "m2".to_owned())];
+        assert_eq!((m2.calls, m2.errors), (1, 1));
+        let limiter = CallLimiter::new(1).queue_timeout(Duration::from_millis(10));
+        let p = limiter.acquire("t", "m").await.unwrap();
+        assert_eq!(
+            limiter.acquire("t", "m").await.err().unwrap().kind(),
+            ErrorKind::ResourceBusy
+        );
+        p.finish(true);
+        limiter.acquire("t", "m").await.unwrap().finish(true);
+        limiter.reset_stats();
+        assert!(limiter.stats().is_empty());
+    }
+}
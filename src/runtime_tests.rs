@@ -1,6 +1,31 @@
-use crate::Error;
+//! Runtime self-tests. Besides the built-in checks, services can register own named checks
+//! (bus connectivity, registry access, database pools etc.) and run them on demand, e.g. from an
+//! RPC method handler
+use crate::{EResult, Error};
+use parking_lot::Mutex;
 #[allow(unused_imports)]
 use serde::Deserialize;
+use serde::Serialize;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+type CheckFuture = Pin<Box<dyn Future<Output = EResult<()>> + Send>>;
+
+#[derive(Clone)]
+enum Check {
+    Sync(Arc<dyn Fn() -> EResult<()> + Send + Sync>),
+    Async(Arc<dyn Fn() -> CheckFuture + Send + Sync>),
+}
+
+lazy_static::lazy_static! {
+    static ref CHECKS: Mutex<Vec<(String, Check)>> = Mutex::new(vec![
+        #[cfg(not(feature = "skip_self_test_serde"))]
+        ("serde".to_owned(), Check::Sync(Arc::new(test_serde))),
+        ("clock".to_owned(), Check::Sync(Arc::new(test_clock))),
+    ]);
+}
 
 #[cfg(not(feature = "skip_self_test_serde"))]
 #[allow(clippy::unreadable_literal)]
@@ -21,6 +46,19 @@ fn test_serde() -> crate::EResult<()> {
     Ok(())
 }
 
+// 2020-01-01
+const MIN_SANE_TIME: u64 = 1_577_836_800;
+
+fn test_clock() -> EResult<()> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|_| Error::core("system clock is before UNIX epoch"))?;
+    if now.as_secs() < MIN_SANE_TIME {
+        return Err(Error::core("system clock is not set"));
+    }
+    Ok(())
+}
+
 #[allow(dead_code)]
 fn failed(test: &str, e: Error) {
     panic!(
@@ -37,3 +75,164 @@ pub fn self_test() {
     #[cfg(not(feature = "skip_self_test_serde"))]
     test_serde().map_err(|e| failed("serde", e)).unwrap();
 }
+
+/// Registers a named check, a check with the same name is replaced
+pub fn register_check<F>(name: &str, f: F)
+where
+    F: Fn() -> EResult<()> + Send + Sync + 'static,
+{
+    register(name, Check::Sync(Arc::new(f)));
+}
+
+/// Registers a named async check, a check with the same name is replaced
+pub fn register_async_check<F, Fut>(name: &str, f: F)
+where
+    F: Fn() -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = EResult<()>> + Send + 'static,
+{
+    register(name, Check::Async(Arc::new(move || Box::pin(f()))));
+}
+
+fn register(name: &str, check: Check) {
+    let mut checks = CHECKS.lock();
+    if let Some(c) = checks.iter_mut().find(|(n, _)| n == name) {
+        c.1 = check;
+    } else {
+        checks.push((name.to_owned(), check));
+    }
+}
+
+/// Unregisters a named check
+pub fn unregister_check(name: &str) {
+    CHECKS.lock().retain(|(n, _)| n != name);
+}
+
+/// Self-test check result
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CheckResult {
+    pub name: String,
+    pub ok: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Check duration (seconds)
+    pub duration: f64,
+}
+
+/// Self-test report
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct SelfTestReport {
+    pub ok: bool,
+    pub checks: Vec<CheckResult>,
+}
+
+impl SelfTestReport {
+    fn with_capacity(capacity: usize) -> Self {
+        Self {
+            ok: true,
+            checks: Vec::with_capacity(capacity),
+        }
+    }
+    fn push(&mut self, name: String, res: EResult<()>, op_start: Instant) {
+        let duration = op_start.elapsed().as_secs_f64();
+        if res.is_err() {
+            self.ok = false;
+        }
+        self.checks.push(CheckResult {
+            name,
+            ok: res.is_ok(),
+            error: res.err().map(|e| e.to_string()),
+            duration,
+        });
+    }
+    pub fn failed(&self) -> impl Iterator<Item = &CheckResult> {
+        self.checks.iter().filter(|c| !c.ok)
+    }
+    /// Packs the report as a RPC call result
+    #[cfg(feature = "payload")]
+    #[inline]
+    pub fn pack(&self) -> EResult<Vec<u8>> {
+        crate::payload::pack(self)
+    }
+}
+
+/// Runs all registered checks (including the built-in ones)
+pub async fn run_checks() -> SelfTestReport {
+    let checks = CHECKS.lock().clone();
+    let mut report = SelfTestReport::with_capacity(checks.len());
+    for (name, check) in checks {
+        let op_start = Instant::now();
+        let res = match check {
+            Check::Sync(f) => f(),
+            Check::Async(f) => f().await,
+        };
+        report.push(name, res, op_start);
+    }
+    report
+}
+
+/// Runs all registered checks (including the built-in ones), each check is limited with the
+/// timeout. Sync checks are run in the blocking thread pool of the Tokio runtime, a timed out
+/// check is reported as failed (a sync one keeps running in the background)
+#[cfg(feature = "services")]
+pub async fn run_checks_with_timeout(timeout: std::time::Duration) -> SelfTestReport {
+    let checks = CHECKS.lock().clone();
+    let mut report = SelfTestReport::with_capacity(checks.len());
+    for (name, check) in checks {
+        let op_start = Instant::now();
+        let res = match check {
+            Check::Sync(f) => {
+                match tokio::time::timeout(timeout, tokio::task::spawn_blocking(move || f())).await
+                {
+                    Ok(Ok(res)) => res,
+                    Ok(Err(e)) => Err(Error::failed(e)),
+                    Err(e) => Err(e.into()),
+                }
+            }
+            Check::Async(f) => tokio::time::timeout(timeout, f())
+                .await
+                .unwrap_or_else(|e| Err(e.into())),
+        };
+        report.push(name, res, op_start);
+    }
+    report
+}
+#[cfg(all(test, feature = "services"))]
+mod tests {
+    use super::{
+        register_async_check, register_check, run_checks, run_checks_with_timeout, unregister_check,
+    };
+    use crate::Error;
+    use std::time::Duration;
+
+    #[test]
+    fn test_checks() {
+        register_check("test_ok", || Ok(()));
+        register_async_check("test_failed", || async { Err(Error::failed("no bus")) });
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let report = rt.block_on(run_checks());
+        assert!(!report.ok);
+        assert!(report.checks.iter().any(|c| c.name == "clock" && c.ok));
+        let failed: Vec<&str> = report.failed().map(|c| c.name.as_str()).collect();
+        assert_eq!(failed, vec!["test_failed"]);
+        unregister_check("test_failed");
+        unregister_check("test_ok");
+        assert!(rt.block_on(run_checks()).ok);
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+        register_async_check("test_stuck", std::future::pending);
+        register_check("test_slow", || {
+            std::thread::sleep(Duration::from_millis(500));
+            Ok(())
+        });
+        let report = rt.block_on(run_checks_with_timeout(Duration::from_millis(50)));
+        let failed: Vec<&str> = report.failed().map(|c| c.name.as_str()).collect();
+        assert_eq!(failed, vec!["test_stuck", "test_slow"]);
+        assert!(report.checks.iter().all(|c| c.duration < 0.4));
+        unregister_check("test_stuck");
+        unregister_check("test_slow");
+    }
+}
@@ -0,0 +1,92 @@
+//! Secret value wrappers, which are (de)serialized transparently but never leak into logs
+#[allow(unused_imports)]
+use crate::alloc_prelude::*;
+use core::fmt;
+use serde::{Deserialize, Serialize};
+
+const MASK: &str = "***";
+
+/// Secret value (password, API key etc.). Display and Debug implementations print a mask only,
+/// the inner value is accessible with [`Secret::expose()`]
+#[derive(Clone, Default, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Secret<T>(T);
+
+impl<T> Secret<T> {
+    #[inline]
+    pub fn new(value: T) -> Self {
+        Self(value)
+    }
+    #[inline]
+    pub fn expose(&self) -> &T {
+        &self.0
+    }
+    #[inline]
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl Secret<String> {
+    #[inline]
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Secret<Vec<u8>> {
+    #[inline]
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl<T> From<T> for Secret<T> {
+    #[inline]
+    fn from(value: T) -> Self {
+        Self(value)
+    }
+}
+
+impl From<&str> for Secret<String> {
+    #[inline]
+    fn from(value: &str) -> Self {
+        Self(value.to_owned())
+    }
+}
+
+impl From<&[u8]> for Secret<Vec<u8>> {
+    #[inline]
+    fn from(value: &[u8]) -> Self {
+        Self(value.to_vec())
+    }
+}
+
+impl<T> fmt::Debug for Secret<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Secret").field(&MASK).finish()
+    }
+}
+
+impl<T> fmt::Display for Secret<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", MASK)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Secret;
+
+    #[test]
+    fn test_secret() {
+        let s: Secret<String> = serde_json::from_str(r#""qwerty""#).unwrap();
+        assert_eq!(s.expose(), "qwerty");
+        assert_eq!(s.to_string(), "***");
+        assert!(!format!("{:?}", s).contains("qwerty"));
+        assert_eq!(serde_json::to_string(&s).unwrap(), r#""qwerty""#);
+        let b: Secret<Vec<u8>> = serde_json::from_str("[1,2,3]").unwrap();
+        assert_eq!(b.as_bytes(), &[1, 2, 3]);
+        assert_eq!(format!("{:?}", Some(b)), r#"Some(Secret("***"))"#);
+    }
+}
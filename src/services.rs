@@ -1,11 +1,19 @@
+use crate::config::ConfigError;
 use crate::registry;
+#[cfg(feature = "extended-value")]
+use crate::value::XValueContext;
 use crate::Value;
 use crate::{EResult, Error};
-use busrt::rpc::{self, RpcClient, RpcHandlers};
+use busrt::client::AsyncClient;
+use busrt::rpc::{self, Rpc, RpcClient, RpcHandlers};
+use busrt::QoS;
+#[cfg(feature = "derive")]
+pub use eva_common_derive::{eapi_method, eapi_service, EapiParams};
 #[cfg(all(feature = "openssl3", feature = "fips"))]
 use once_cell::sync::OnceCell;
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::collections::HashMap;
+#[cfg(unix)]
 use std::ffi::CString;
 use std::fmt;
 #[cfg(feature = "extended-value")]
@@ -18,6 +26,7 @@ pub const SERVICE_CONFIG_VERSION: u16 = 4;
 
 pub const SERVICE_PAYLOAD_PING: u8 = 0;
 pub const SERVICE_PAYLOAD_INITIAL: u8 = 1;
+pub const SERVICE_PAYLOAD_RELOAD: u8 = 2;
 
 #[cfg(all(feature = "openssl3", feature = "fips"))]
 #[allow(dead_code)]
@@ -54,23 +63,35 @@ pub struct Registry {
     rpc: Arc<RpcClient>,
 }
 
+/// Recursive operations accept an empty key, which points to the service data root
+fn svc_data_key_path(svc_id: &str, key: &str, recursive: bool) -> EResult<registry::KeyPath> {
+    let path = registry::KeyPath::svc_data(svc_id)?;
+    if recursive && key.trim_matches('/').is_empty() {
+        Ok(path)
+    } else {
+        path.join(key)
+    }
+}
+
 impl Registry {
+    #[inline]
+    fn key_path(&self, key: &str) -> EResult<registry::KeyPath> {
+        svc_data_key_path(&self.id, key, false)
+    }
+    #[inline]
+    fn recursive_key_path(&self, key: &str) -> EResult<registry::KeyPath> {
+        svc_data_key_path(&self.id, key, true)
+    }
     #[inline]
     pub async fn key_set<V>(&self, key: &str, value: V) -> EResult<Value>
     where
         V: Serialize,
     {
-        registry::key_set(
-            &registry::format_svc_data_subkey(&self.id),
-            key,
-            value,
-            &self.rpc,
-        )
-        .await
+        registry::key_set_path(&self.key_path(key)?, value, &self.rpc).await
     }
     #[inline]
     pub async fn key_get(&self, key: &str) -> EResult<Value> {
-        registry::key_get(&registry::format_svc_data_subkey(&self.id), key, &self.rpc).await
+        registry::key_get_path(&self.key_path(key)?, &self.rpc).await
     }
     #[inline]
     pub async fn key_userdata_get(&self, key: &str) -> EResult<Value> {
@@ -78,26 +99,24 @@ impl Registry {
     }
     #[inline]
     pub async fn key_increment(&self, key: &str) -> EResult<i64> {
-        registry::key_increment(&registry::format_svc_data_subkey(&self.id), key, &self.rpc).await
+        registry::key_increment_path(&self.key_path(key)?, &self.rpc).await
     }
 
     #[inline]
     pub async fn key_decrement(&self, key: &str) -> EResult<i64> {
-        registry::key_decrement(&registry::format_svc_data_subkey(&self.id), key, &self.rpc).await
+        registry::key_decrement_path(&self.key_path(key)?, &self.rpc).await
     }
     #[inline]
     pub async fn key_get_recursive(&self, key: &str) -> EResult<Vec<(String, Value)>> {
-        registry::key_get_recursive(&registry::format_svc_data_subkey(&self.id), key, &self.rpc)
-            .await
+        registry::key_get_recursive_path(&self.recursive_key_path(key)?, &self.rpc).await
     }
     #[inline]
     pub async fn key_delete(&self, key: &str) -> EResult<Value> {
-        registry::key_delete(&registry::format_svc_data_subkey(&self.id), key, &self.rpc).await
+        registry::key_delete_path(&self.key_path(key)?, &self.rpc).await
     }
     #[inline]
     pub async fn key_delete_recursive(&self, key: &str) -> EResult<Value> {
-        registry::key_delete_recursive(&registry::format_svc_data_subkey(&self.id), key, &self.rpc)
-            .await
+        registry::key_delete_recursive_path(&self.recursive_key_path(key)?, &self.rpc).await
     }
 }
 
@@ -106,7 +125,8 @@ fn default_workers() -> u32 {
     1
 }
 
-#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+#[allow(clippy::unsafe_derive_deserialize)]
+#[derive(Default, Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct RealtimeConfig {
     #[serde(default)]
     pub priority: Option<i32>,
@@ -116,6 +136,79 @@ pub struct RealtimeConfig {
     pub prealloc_heap: Option<usize>,
 }
 
+impl RealtimeConfig {
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.priority.is_none() && self.cpu_ids.is_empty() && self.prealloc_heap.is_none()
+    }
+    /// Applies the realtime configuration to the current process: sets CPU affinity, switches
+    /// the scheduler to FIFO with the given priority, preallocates heap and locks the process
+    /// memory. Must be called before spawning threads
+    #[cfg(target_os = "linux")]
+    pub fn apply(&self) -> EResult<()> {
+        use nix::libc;
+        if !self.cpu_ids.is_empty() {
+            let mut cpu_set = nix::sched::CpuSet::new();
+            for cpu in &self.cpu_ids {
+                cpu_set.set(*cpu).map_err(|e| {
+                    Error::invalid_params(format!("unable to set CPU {}: {}", cpu, e))
+                })?;
+            }
+            nix::sched::sched_setaffinity(nix::unistd::Pid::from_raw(0), &cpu_set)
+                .map_err(|e| Error::failed(format!("unable to set CPU affinity: {}", e)))?;
+        }
+        if let Some(priority) = self.priority {
+            let param = libc::sched_param {
+                sched_priority: priority,
+            };
+            let res =
+                unsafe { libc::sched_setscheduler(0, libc::SCHED_FIFO, std::ptr::addr_of!(param)) };
+            if res == -1 {
+                return Err(Error::failed(format!(
+                    "unable to set the realtime priority {}: {}",
+                    priority,
+                    std::io::Error::last_os_error()
+                )));
+            }
+        }
+        if let Some(heap_size) = self.prealloc_heap {
+            #[cfg(target_env = "gnu")]
+            unsafe {
+                // keep the allocated memory in the process heap
+                if libc::mallopt(libc::M_MMAP_MAX, 0) != 1
+                    || libc::mallopt(libc::M_TRIM_THRESHOLD, -1) != 1
+                {
+                    return Err(Error::failed("unable to set malloc options"));
+                }
+            }
+            nix::sys::mman::mlockall(
+                nix::sys::mman::MlockAllFlags::MCL_CURRENT
+                    | nix::sys::mman::MlockAllFlags::MCL_FUTURE,
+            )
+            .map_err(|e| Error::failed(format!("unable to lock memory: {}", e)))?;
+            if heap_size > 0 {
+                let mut heap = vec![0_u8; heap_size];
+                // touch each page to make sure it is mapped
+                for b in heap.iter_mut().step_by(4096) {
+                    unsafe { std::ptr::write_volatile(b, 1) };
+                }
+                drop(heap);
+            }
+        }
+        Ok(())
+    }
+    #[cfg(not(target_os = "linux"))]
+    pub fn apply(&self) -> EResult<()> {
+        if self.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::not_implemented(
+                "realtime configuration is supported on Linux only",
+            ))
+        }
+    }
+}
+
 /// Initial properties for services
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Initial {
@@ -320,35 +413,23 @@ impl Initial {
     }
     #[inline]
     pub fn bus_config(&self) -> EResult<busrt::ipc::Config> {
-        if self.bus.tp == "native" {
-            Ok(busrt::ipc::Config::new(&self.bus.path, &self.id)
-                .buf_size(self.bus.buf_size)
-                .buf_ttl(Duration::from_micros(self.bus.buf_ttl))
-                .queue_size(self.bus.queue_size)
-                .timeout(self.bus_timeout()))
-        } else {
-            Err(Error::not_implemented(format!(
-                "bus type {} is not supported",
-                self.bus.tp
-            )))
-        }
+        self.bus_config_with_name(&self.id)
     }
     #[inline]
     pub fn bus_config_for_sub(&self, sub_id: &str) -> EResult<busrt::ipc::Config> {
-        if self.bus.tp == "native" {
-            Ok(
-                busrt::ipc::Config::new(&self.bus.path, &format!("{}::{}", self.id, sub_id))
-                    .buf_size(self.bus.buf_size)
-                    .buf_ttl(Duration::from_micros(self.bus.buf_ttl))
-                    .queue_size(self.bus.queue_size)
-                    .timeout(self.bus_timeout()),
-            )
-        } else {
-            Err(Error::not_implemented(format!(
-                "bus type {} is not supported",
-                self.bus.tp
-            )))
-        }
+        self.bus_config_with_name(&format!("{}::{}", self.id, sub_id))
+    }
+    fn bus_config_with_name(&self, name: &str) -> EResult<busrt::ipc::Config> {
+        Ok(busrt::ipc::Config::new(&self.bus.connection_path()?, name)
+            .buf_size(self.bus.buf_size)
+            .buf_ttl(Duration::from_micros(self.bus.buf_ttl))
+            .queue_size(self.bus.queue_size)
+            .timeout(self.bus_timeout()))
+    }
+    /// Bus keep-alive (ping) interval, if set
+    #[inline]
+    pub fn bus_keepalive(&self) -> Option<Duration> {
+        self.bus.keepalive.map(Duration::from_secs_f64)
     }
     pub fn set_bus_path(&mut self, path: &str) {
         path.clone_into(&mut self.bus.path);
@@ -361,16 +442,39 @@ impl Initial {
     pub fn config(&self) -> Option<&Value> {
         self.config.as_ref()
     }
+    /// Extends the service config. Template variables are not rendered and environment variables
+    /// are not accessible, use [`Initial::extend_config_with()`] to opt in
     #[cfg(feature = "extended-value")]
     #[inline]
     pub async fn extend_config(&mut self, timeout: Duration, base: &Path) -> EResult<()> {
+        self.extend_config_with(timeout, base, &XValueContext::new())
+            .await
+    }
+    /// Extends the service config with the context, e.g. with [`Initial::xvalue_context()`] to
+    /// render ${system_name}, ${id} and ${data_path} template variables ("$${" is kept as a
+    /// literal "${")
+    #[cfg(feature = "extended-value")]
+    pub async fn extend_config_with(
+        &mut self,
+        timeout: Duration,
+        base: &Path,
+        ctx: &XValueContext,
+    ) -> EResult<()> {
         self.config = if let Some(config) = self.config.take() {
-            Some(config.extend(timeout, base).await?)
+            Some(config.extend_with(timeout, base, ctx).await?)
         } else {
             None
         };
         Ok(())
     }
+    /// Extended value context with the service template variables and no environment access
+    #[cfg(feature = "extended-value")]
+    pub fn xvalue_context(&self) -> XValueContext {
+        XValueContext::new()
+            .var("system_name", &self.system_name)
+            .var("id", &self.id)
+            .var("data_path", &self.data_path)
+    }
     #[inline]
     pub fn workers(&self) -> u32 {
         self.workers
@@ -383,6 +487,11 @@ impl Initial {
     pub fn take_config(&mut self) -> Option<Value> {
         self.config.take()
     }
+    /// Deserializes the service config. Errors contain the key path, the expected type and the
+    /// value got. If the config is not specified, it is deserialized from the unit value
+    pub fn parse_config_as<T: DeserializeOwned>(&self) -> Result<T, ConfigError> {
+        crate::config::from_value(self.config.clone().unwrap_or_default())
+    }
     #[inline]
     pub async fn init_rpc<R>(&self, handlers: R) -> EResult<Arc<RpcClient>>
     where
@@ -418,6 +527,7 @@ impl Initial {
             .blocking_frames();
         let rpc = Arc::new(RpcClient::create(bus, handlers, opts.clone()));
         let rpc_secondary = Arc::new(RpcClient::create0(bus_secondary, opts));
+        self.spawn_bus_keepalive(&rpc);
         Ok((rpc, rpc_secondary))
     }
     pub async fn init_rpc_opts<R>(&self, handlers: R, opts: rpc::Options) -> EResult<Arc<RpcClient>>
@@ -425,8 +535,47 @@ impl Initial {
         R: RpcHandlers + Send + Sync + 'static,
     {
         let bus = self.init_bus_client().await?;
-        let rpc = RpcClient::create(bus, handlers, opts);
-        Ok(Arc::new(rpc))
+        let rpc = Arc::new(RpcClient::create(bus, handlers, opts));
+        self.spawn_bus_keepalive(&rpc);
+        Ok(rpc)
+    }
+    /// Creates a pool of secondary bus clients, named `<id>::<prefix>.<n>`, connects them and
+    /// starts the health check
+    pub async fn init_bus_pool(&self, prefix: &str, size: usize) -> EResult<Arc<BusPool>> {
+        let configs = (0..size)
+            .map(|n| self.bus_config_for_sub(&format!("{}.{}", prefix, n)))
+            .collect::<EResult<Vec<_>>>()?;
+        let pool = Arc::new(BusPool::new(configs, self.bus_timeout()));
+        pool.connect().await?;
+        pool.spawn_health_check(
+            self.bus_keepalive()
+                .unwrap_or(DEFAULT_BUS_POOL_CHECK_INTERVAL),
+        );
+        Ok(pool)
+    }
+    /// Pings the broker with the keep-alive interval, if set. The task stops when the client is
+    /// dropped or the ping fails
+    fn spawn_bus_keepalive(&self, rpc: &Arc<RpcClient>) {
+        let Some(interval) = self.bus_keepalive() else {
+            return;
+        };
+        let rpc = Arc::downgrade(rpc);
+        tokio::spawn(async move {
+            let mut int = tokio::time::interval(interval);
+            int.tick().await;
+            loop {
+                int.tick().await;
+                let Some(rpc) = rpc.upgrade() else {
+                    break;
+                };
+                let client = rpc.client();
+                let result = client.lock().await.ping().await;
+                if let Err(e) = result {
+                    log::error!("bus keep-alive ping failed: {}", e);
+                    break;
+                }
+            }
+        });
     }
     pub async fn init_bus_client(&self) -> EResult<busrt::ipc::Client> {
         let bus = tokio::time::timeout(
@@ -467,7 +616,7 @@ impl Initial {
     pub fn set_fail_mode(&self, mode: bool) {
         self.fail_mode.store(mode, atomic::Ordering::SeqCst);
     }
-    #[cfg(not(target_os = "windows"))]
+    #[cfg(unix)]
     #[inline]
     pub fn drop_privileges(&self) -> EResult<()> {
         if let Some(ref user) = self.user {
@@ -477,11 +626,7 @@ impl Initial {
                     let c_user = CString::new(user.as_str()).map_err(|e| {
                         Error::failed(format!("Failed to parse user {}: {}", user, e))
                     })?;
-
-                    let groups = nix::unistd::getgrouplist(&c_user, u.gid).map_err(|e| {
-                        Error::failed(format!("Failed to get groups for user {}: {}", user, e))
-                    })?;
-                    nix::unistd::setgroups(&groups).map_err(|e| {
+                    init_groups(&c_user, u.gid).map_err(|e| {
                         Error::failed(format!(
                             "Failed to switch the process groups for user {}: {}",
                             user, e
@@ -504,6 +649,22 @@ impl Initial {
         }
         Ok(())
     }
+    /// Windows services run under the service account, configured in the service manager, so
+    /// the privileges are never dropped. If the user is set, a warning is reported
+    #[cfg(target_os = "windows")]
+    #[inline]
+    pub fn drop_privileges(&self) -> EResult<()> {
+        if let Some(ref user) = self.user {
+            if !user.is_empty() {
+                log::warn!(
+                    "privilege drop is not supported on Windows, user {} ignored, \
+                    the service account is used",
+                    user
+                );
+            }
+        }
+        Ok(())
+    }
     pub fn into_legacy_compat(mut self) -> Self {
         self.data_path = self.data_path().unwrap_or_default().to_owned();
         let user = self.user.take().unwrap_or_default();
@@ -525,9 +686,844 @@ impl Initial {
         self.config.replace(config);
         self
     }
+    /// Applies hot-reloadable fields (config, log level, timeouts, call tracing) of the new
+    /// initial properties. Restart-only fields are not applied, but reported in the diff
+    pub fn apply_reload(&mut self, new: Initial) -> EResult<ReloadDiff> {
+        if new.config_version != self.config_version {
+            return Err(Error::invalid_params(format!(
+                "config version mismatch: {} != {}",
+                new.config_version, self.config_version
+            )));
+        }
+        if new.id != self.id {
+            return Err(Error::invalid_params(format!(
+                "service id mismatch: {} != {}",
+                new.id, self.id
+            )));
+        }
+        let mut diff = ReloadDiff::default();
+        macro_rules! check_restart {
+            ($($field: ident),+) => {
+                $(
+                    if new.$field != self.$field {
+                        diff.restart_required.push(stringify!($field));
+                    }
+                )+
+            };
+        }
+        check_restart!(
+            system_name,
+            command,
+            prepare_command,
+            data_path,
+            bus,
+            realtime,
+            workers,
+            user,
+            react_to_fail,
+            fips
+        );
+        if new.config != self.config {
+            self.config = new.config;
+            diff.reloaded.push("config");
+        }
+        if new.core.log_level != self.core.log_level {
+            self.core.log_level = new.core.log_level;
+            diff.reloaded.push("log_level");
+        }
+        if new.timeout != self.timeout {
+            self.timeout = new.timeout;
+            diff.reloaded.push("timeout");
+        }
+        if new.call_tracing != self.call_tracing {
+            self.call_tracing = new.call_tracing;
+            diff.reloaded.push("call_tracing");
+        }
+        Ok(diff)
+    }
+}
+
+/// Sent by the core to running services to push updated initial properties
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ReloadRequest {
+    pub initial: Initial,
+}
+
+impl ReloadRequest {
+    #[inline]
+    pub fn new(initial: Initial) -> Self {
+        Self { initial }
+    }
+}
+
+/// Result of [`Initial::apply_reload()`]
+#[derive(Debug, Clone, Default, Serialize, PartialEq, Eq)]
+pub struct ReloadDiff {
+    /// Fields applied without restart
+    pub reloaded: Vec<&'static str>,
+    /// Changed fields which require the service restart to be applied
+    pub restart_required: Vec<&'static str>,
+}
+
+impl ReloadDiff {
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.reloaded.is_empty() && self.restart_required.is_empty()
+    }
+    #[inline]
+    pub fn needs_restart(&self) -> bool {
+        !self.restart_required.is_empty()
+    }
+}
+
+/// Service launch mode, set with "--mode"
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub enum LaunchMode {
+    #[default]
+    Normal,
+    /// The service is started in the fail mode (react-to-fail)
+    Rtf,
+}
+
+impl std::str::FromStr for LaunchMode {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "normal" => Ok(LaunchMode::Normal),
+            "rtf" => Ok(LaunchMode::Rtf),
+            _ => Err(Error::invalid_params(format!("invalid launch mode: {}", s))),
+        }
+    }
+}
+
+/// Standard service command-line arguments: "--mode", "--data-path", "--bus" and "--id". Values
+/// may be given either as "--arg value" or "--arg=value"
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub struct LaunchArgs {
+    pub mode: LaunchMode,
+    pub data_path: Option<String>,
+    pub bus: Option<String>,
+    pub id: Option<String>,
+}
+
+impl LaunchArgs {
+    /// Parses arguments (without the program name)
+    pub fn parse<I, S>(args: I) -> EResult<Self>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut result = Self::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let arg = arg.as_ref();
+            let (name, value) = if let Some((name, value)) = arg.split_once('=') {
+                (name, value.to_owned())
+            } else {
+                let value = args
+                    .next()
+                    .ok_or_else(|| Error::invalid_params(format!("{}: value required", arg)))?;
+                (arg, value.as_ref().to_owned())
+            };
+            match name {
+                "--mode" => result.mode = value.parse()?,
+                "--data-path" => result.data_path = Some(value),
+                "--bus" => result.bus = Some(value),
+                "--id" => result.id = Some(value),
+                _ => {
+                    return Err(Error::invalid_params(format!(
+                        "unsupported argument: {}",
+                        name
+                    )))
+                }
+            }
+        }
+        Ok(result)
+    }
+    /// Parses the process arguments
+    #[inline]
+    pub fn from_env() -> EResult<Self> {
+        Self::parse(std::env::args().skip(1))
+    }
+    /// Overrides the initial properties with the arguments
+    pub fn apply(&self, initial: &mut Initial) {
+        if let Some(ref id) = self.id {
+            initial.set_id(id);
+        }
+        if let Some(ref path) = self.data_path {
+            initial.set_data_path(path);
+        }
+        if let Some(ref bus) = self.bus {
+            initial.set_bus_path(bus);
+        }
+        if self.mode == LaunchMode::Rtf {
+            initial.set_fail_mode(true);
+        }
+    }
+}
+
+/// A payload, sent by the launcher to the service stdin
+#[derive(Debug)]
+pub enum LauncherPayload {
+    Ping,
+    Initial(Box<Initial>),
+    Reload(Box<Initial>),
+}
+
+/// Reads a launcher payload: the payload kind byte, followed by u32 (little-endian) length and
+/// packed initial properties for [`SERVICE_PAYLOAD_INITIAL`] and [`SERVICE_PAYLOAD_RELOAD`].
+/// Returns None on EOF
+pub async fn read_launcher_payload<R>(reader: &mut R) -> EResult<Option<LauncherPayload>>
+where
+    R: tokio::io::AsyncRead + Unpin,
+{
+    use tokio::io::AsyncReadExt as _;
+    let mut buf = [0_u8; 1];
+    if reader.read(&mut buf).await? == 0 {
+        return Ok(None);
+    }
+    let kind = buf[0];
+    if kind == SERVICE_PAYLOAD_PING {
+        return Ok(Some(LauncherPayload::Ping));
+    }
+    if kind != SERVICE_PAYLOAD_INITIAL && kind != SERVICE_PAYLOAD_RELOAD {
+        return Err(Error::invalid_data(format!(
+            "invalid launcher payload kind: {}",
+            kind
+        )));
+    }
+    let mut len_buf = [0_u8; 4];
+    reader.read_exact(&mut len_buf).await?;
+    let mut data = vec![0_u8; usize::try_from(u32::from_le_bytes(len_buf))?];
+    reader.read_exact(&mut data).await?;
+    let initial: Initial = crate::payload::unpack(&data)?;
+    Ok(Some(if kind == SERVICE_PAYLOAD_INITIAL {
+        LauncherPayload::Initial(Box::new(initial))
+    } else {
+        LauncherPayload::Reload(Box::new(initial))
+    }))
+}
+
+#[derive(Default)]
+struct ReloadSlot {
+    latest: parking_lot::Mutex<Option<Initial>>,
+    closed: atomic::AtomicBool,
+    notify: tokio::sync::Notify,
+}
+
+struct ReloadSender {
+    slot: Arc<ReloadSlot>,
+}
+
+impl ReloadSender {
+    fn send(&self, initial: Initial) {
+        self.slot.latest.lock().replace(initial);
+        self.slot.notify.notify_one();
+    }
+}
+
+impl Drop for ReloadSender {
+    fn drop(&mut self) {
+        self.slot.closed.store(true, atomic::Ordering::SeqCst);
+        self.slot.notify.notify_one();
+    }
+}
+
+/// Receives initial properties, pushed by the launcher to running services. Reloads are
+/// coalesced: if the handler is busy, newer reloads replace the pending one, so the latest
+/// always wins
+pub struct ReloadReceiver {
+    slot: Arc<ReloadSlot>,
+}
+
+impl ReloadReceiver {
+    /// Waits for the next reload, returns None if the launcher is gone
+    pub async fn recv(&mut self) -> Option<Initial> {
+        loop {
+            if let Some(initial) = self.try_recv() {
+                return Some(initial);
+            }
+            if self.slot.closed.load(atomic::Ordering::SeqCst) {
+                return None;
+            }
+            self.slot.notify.notified().await;
+        }
+    }
+    /// Returns the pending reload, if any
+    pub fn try_recv(&mut self) -> Option<Initial> {
+        self.slot.latest.lock().take()
+    }
+}
+
+fn reload_channel() -> (ReloadSender, ReloadReceiver) {
+    let slot = Arc::new(ReloadSlot::default());
+    (ReloadSender { slot: slot.clone() }, ReloadReceiver { slot })
+}
+
+/// Standard service bootstrap
+///
+/// Parses [`LaunchArgs`], reads the initial properties from stdin, applies the arguments and
+/// runs the handler. Reloads are sent to the handler's receiver. The handler is stopped when
+/// stdin is closed (the launcher is gone)
+pub async fn launch<F, Fut>(handler: F) -> EResult<()>
+where
+    F: FnOnce(Initial, ReloadReceiver) -> Fut,
+    Fut: std::future::Future<Output = EResult<()>>,
+{
+    let args = LaunchArgs::from_env()?;
+    let mut stdin = tokio::io::stdin();
+    let mut initial = match read_launcher_payload(&mut stdin).await? {
+        Some(LauncherPayload::Initial(initial)) => *initial,
+        Some(_) => return Err(Error::invalid_data("initial payload expected")),
+        None => return Err(Error::io("stdin closed before the initial payload")),
+    };
+    args.apply(&mut initial);
+    let (tx, rx) = reload_channel();
+    let watcher = async move {
+        loop {
+            match read_launcher_payload(&mut stdin).await? {
+                Some(LauncherPayload::Ping) => {}
+                Some(LauncherPayload::Reload(initial)) => tx.send(*initial),
+                Some(LauncherPayload::Initial(_)) => {
+                    return Err(Error::invalid_data("duplicate initial payload"));
+                }
+                None => break Ok::<(), Error>(()),
+            }
+        }
+    };
+    tokio::select! {
+        result = handler(initial, rx) => result,
+        result = watcher => result,
+    }
+}
+
+pub const CORE_SVC_ID: &str = "eva.core";
+
+const WAIT_STEP: Duration = Duration::from_millis(200);
+const WAIT_REPORT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Core status, returned by the core "test" method
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CoreStatus {
+    pub active: bool,
+    pub build: u64,
+    pub version: String,
+    #[serde(default)]
+    pub system_name: String,
+    #[serde(default)]
+    pub boot_id: u64,
+    #[serde(default)]
+    pub pid: u32,
+    #[serde(default)]
+    pub time: f64,
+    #[serde(default)]
+    pub uptime: f64,
+    #[serde(default)]
+    pub workstation: bool,
+}
+
+/// Waits until the core is active. The core is polled with the "test" method, the progress is
+/// reported to the log. Services usually call it with [`Initial::startup_timeout()`]
+///
+/// # Errors
+///
+/// Will return `Err` on timeout or if the core status can not be parsed
+pub async fn wait_core<R>(rpc: &R, timeout: Duration) -> EResult<CoreStatus>
+where
+    R: Rpc + ?Sized,
+{
+    let mut waiter = Waiter::new(CORE_SVC_ID, timeout);
+    loop {
+        let res = tokio::time::timeout(
+            waiter.remaining(),
+            rpc.call(CORE_SVC_ID, "test", busrt::empty_payload!(), QoS::Processed),
+        )
+        .await;
+        let reason = match res {
+            Ok(Ok(event)) => {
+                let status: CoreStatus = crate::payload::unpack(event.payload())?;
+                if status.active {
+                    return Ok(status);
+                }
+                "core is not active".to_owned()
+            }
+            Ok(Err(e)) => Error::from(e).to_string(),
+            Err(e) => Error::from(e).to_string(),
+        };
+        waiter.step(&reason).await?;
+    }
+}
+
+/// Waits until the target service responds to the "test" method. Services which return
+/// NotReady or are not registered on the bus yet are polled until the timeout
+///
+/// # Errors
+///
+/// Will return `Err` on timeout
+pub async fn wait_for_service<R>(rpc: &R, target: &str, timeout: Duration) -> EResult<()>
+where
+    R: Rpc + ?Sized,
+{
+    let mut waiter = Waiter::new(target, timeout);
+    loop {
+        match tokio::time::timeout(
+            waiter.remaining(),
+            rpc.call(target, "test", busrt::empty_payload!(), QoS::Processed),
+        )
+        .await
+        {
+            // the service is alive but has no test method
+            Ok(Err(e)) if e.code() == rpc::RPC_ERROR_CODE_METHOD_NOT_FOUND => return Ok(()),
+            Ok(Ok(_)) => return Ok(()),
+            Ok(Err(e)) => waiter.step(&Error::from(e).to_string()).await?,
+            Err(e) => waiter.step(&Error::from(e).to_string()).await?,
+        }
+    }
+}
+
+struct Waiter<'a> {
+    target: &'a str,
+    timeout: Duration,
+    op_start: std::time::Instant,
+    next_report: Duration,
+}
+
+impl<'a> Waiter<'a> {
+    fn new(target: &'a str, timeout: Duration) -> Self {
+        Self {
+            target,
+            timeout,
+            op_start: std::time::Instant::now(),
+            next_report: WAIT_REPORT_INTERVAL,
+        }
+    }
+    /// Time left for a single poll call
+    fn remaining(&self) -> Duration {
+        self.timeout.saturating_sub(self.op_start.elapsed())
+    }
+    async fn step(&mut self, reason: &str) -> EResult<()> {
+        let elapsed = self.op_start.elapsed();
+        if elapsed >= self.timeout {
+            return Err(Error::new(
+                crate::ErrorKind::Timeout,
+                format!(
+                    "{} is not ready after {:.1}s: {}",
+                    self.target,
+                    elapsed.as_secs_f64(),
+                    reason
+                ),
+            ));
+        }
+        if elapsed >= self.next_report {
+            log::info!(
+                "waiting for {} ({:.0}s elapsed, timeout: {:.0}s): {}",
+                self.target,
+                elapsed.as_secs_f64(),
+                self.timeout.as_secs_f64(),
+                reason
+            );
+            self.next_report += WAIT_REPORT_INTERVAL;
+        }
+        tokio::time::sleep(WAIT_STEP).await;
+        Ok(())
+    }
+}
+
+pub const BROKER_ID: &str = ".broker";
+pub const DEFAULT_DISCOVERY_PARALLEL: usize = 16;
+
+/// A service, found by [`discover()`]
+#[derive(Debug, Clone)]
+pub struct DiscoveredService {
+    pub id: String,
+    pub info: ServiceInfo,
+    /// "info" call round-trip time
+    pub latency: Duration,
+}
+
+#[derive(Deserialize)]
+struct BrokerClientList {
+    clients: Vec<BrokerClient>,
+}
+
+#[derive(Deserialize)]
+struct BrokerClient {
+    name: String,
+}
+
+/// Matches a service id with a mask, "*" in the mask matches any number of characters
+fn service_id_matches(mask: &str, id: &str) -> bool {
+    let mut chunks = mask.split('*');
+    let Some(first) = chunks.next() else {
+        return false;
+    };
+    let Some(mut rest) = id.strip_prefix(first) else {
+        return false;
+    };
+    let mut chunks = chunks.peekable();
+    while let Some(chunk) = chunks.next() {
+        if chunks.peek().is_none() {
+            return rest.ends_with(chunk);
+        }
+        let Some(pos) = rest.find(chunk) else {
+            return false;
+        };
+        rest = &rest[pos + chunk.len()..];
+    }
+    rest.is_empty()
+}
+
+/// Enumerates services via the broker and calls their "info" methods concurrently
+///
+/// The filter is a service id mask ("*" matches any characters). Secondary bus clients are
+/// ignored, services which fail to respond are skipped. The result is sorted by service id.
+pub async fn discover(rpc: &Arc<RpcClient>, filter: &str) -> EResult<Vec<DiscoveredService>> {
+    discover_with(
+        rpc,
+        filter,
+        DEFAULT_DISCOVERY_PARALLEL,
+        crate::DEFAULT_TIMEOUT,
+    )
+    .await
+}
+
+async fn discover_with(
+    rpc: &Arc<RpcClient>,
+    filter: &str,
+    parallel: usize,
+    timeout: Duration,
+) -> EResult<Vec<DiscoveredService>> {
+    let semaphore = Arc::new(tokio::sync::Semaphore::new(parallel.max(1)));
+    let mut tasks = Vec::new();
+    for id in list_services(rpc, filter, timeout).await? {
+        let rpc = rpc.clone();
+        let semaphore = semaphore.clone();
+        tasks.push(tokio::spawn(async move {
+            let _permit = semaphore.acquire().await.map_err(Error::failed)?;
+            let op_start = std::time::Instant::now();
+            let ev = tokio::time::timeout(
+                timeout,
+                rpc.call(&id, "info", busrt::empty_payload!(), QoS::Processed),
+            )
+            .await??;
+            let latency = op_start.elapsed();
+            let info: ServiceInfo = crate::payload::unpack(ev.payload())?;
+            Ok::<_, Error>(DiscoveredService { id, info, latency })
+        }));
+    }
+    let mut result = Vec::with_capacity(tasks.len());
+    for task in tasks {
+        match task.await.map_err(Error::failed)? {
+            Ok(svc) => result.push(svc),
+            Err(e) => log::debug!("service discovery: {}", e),
+        }
+    }
+    result.sort_by(|a, b| a.id.cmp(&b.id));
+    Ok(result)
+}
+
+/// Lists bus clients, which are services and match the mask (e.g. "eva.repl.*")
+pub(crate) async fn list_services(
+    rpc: &RpcClient,
+    mask: &str,
+    timeout: Duration,
+) -> EResult<Vec<String>> {
+    let ev = tokio::time::timeout(
+        timeout,
+        rpc.call(
+            BROKER_ID,
+            "client.list",
+            busrt::empty_payload!(),
+            QoS::Processed,
+        ),
+    )
+    .await??;
+    let list: BrokerClientList = crate::payload::unpack(ev.payload())?;
+    Ok(list
+        .clients
+        .into_iter()
+        .map(|client| client.name)
+        .filter(|id| !id.starts_with('.') && !id.contains("::") && service_id_matches(mask, id))
+        .collect())
+}
+
+type DiscoveryCache = HashMap<String, (std::time::Instant, Arc<Vec<DiscoveredService>>)>;
+
+/// Service discovery client with result caching
+pub struct ServiceDiscovery {
+    rpc: Arc<RpcClient>,
+    parallel: usize,
+    timeout: Duration,
+    ttl: Duration,
+    cache: parking_lot::Mutex<DiscoveryCache>,
+}
+
+impl ServiceDiscovery {
+    pub fn new(rpc: Arc<RpcClient>) -> Self {
+        Self {
+            rpc,
+            parallel: DEFAULT_DISCOVERY_PARALLEL,
+            timeout: crate::DEFAULT_TIMEOUT,
+            ttl: Duration::from_secs(10),
+            cache: <_>::default(),
+        }
+    }
+    /// Max number of concurrent "info" calls
+    pub fn parallel(mut self, parallel: usize) -> Self {
+        self.parallel = parallel;
+        self
+    }
+    /// Timeout for a single call
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+    /// Cache time-to-live, zero disables caching
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+    /// Returns cached results if not expired, otherwise calls [`discover()`]
+    pub async fn discover(&self, filter: &str) -> EResult<Arc<Vec<DiscoveredService>>> {
+        if let Some((t, services)) = self.cache.lock().get(filter) {
+            if t.elapsed() < self.ttl {
+                return Ok(services.clone());
+            }
+        }
+        let services =
+            Arc::new(discover_with(&self.rpc, filter, self.parallel, self.timeout).await?);
+        if !self.ttl.is_zero() {
+            self.cache.lock().insert(
+                filter.to_owned(),
+                (std::time::Instant::now(), services.clone()),
+            );
+        }
+        Ok(services)
+    }
+    pub fn invalidate(&self) {
+        self.cache.lock().clear();
+    }
+}
+
+pub const DEFAULT_BUS_POOL_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+const BUS_POOL_MIN_BACKOFF: Duration = Duration::from_millis(100);
+const BUS_POOL_MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Reconnect delay after the specified number of failed attempts (exponential, capped)
+fn bus_pool_backoff(failures: u32) -> Duration {
+    if failures == 0 {
+        return Duration::ZERO;
+    }
+    BUS_POOL_MIN_BACKOFF
+        .saturating_mul(1 << (failures - 1).min(16))
+        .min(BUS_POOL_MAX_BACKOFF)
+}
+
+#[derive(Default)]
+struct BusPoolBackoff {
+    failures: u32,
+    retry_at: Option<std::time::Instant>,
+}
+
+struct BusPoolSlot {
+    n: usize,
+    config: busrt::ipc::Config,
+    client: Arc<tokio::sync::Mutex<Option<busrt::ipc::Client>>>,
+    backoff: parking_lot::Mutex<BusPoolBackoff>,
+}
+
+impl BusPoolSlot {
+    fn check_backoff(&self) -> EResult<()> {
+        let backoff = self.backoff.lock();
+        if let Some(retry_at) = backoff.retry_at {
+            if retry_at > std::time::Instant::now() {
+                return Err(Error::not_ready(format!(
+                    "bus pool client #{} is reconnecting",
+                    self.n
+                )));
+            }
+        }
+        Ok(())
+    }
+    fn register_failure(&self) {
+        let mut backoff = self.backoff.lock();
+        backoff.failures = backoff.failures.saturating_add(1);
+        backoff.retry_at = Some(std::time::Instant::now() + bus_pool_backoff(backoff.failures));
+    }
+    fn register_success(&self) {
+        *self.backoff.lock() = BusPoolBackoff::default();
+    }
+    /// Makes sure the client is connected, a stale client is dropped before reconnecting
+    async fn ensure_connected(
+        &self,
+        client: &mut Option<busrt::ipc::Client>,
+        timeout: Duration,
+    ) -> EResult<()> {
+        if client.as_ref().is_some_and(AsyncClient::is_connected) {
+            return Ok(());
+        }
+        client.take();
+        self.check_backoff()?;
+        let res: EResult<busrt::ipc::Client> =
+            match tokio::time::timeout(timeout, busrt::ipc::Client::connect(&self.config)).await {
+                Ok(v) => v.map_err(Into::into),
+                Err(e) => Err(e.into()),
+            };
+        match res {
+            Ok(c) => {
+                self.register_success();
+                client.replace(c);
+                Ok(())
+            }
+            Err(e) => {
+                self.register_failure();
+                Err(e)
+            }
+        }
+    }
+    async fn acquire(&self, timeout: Duration) -> EResult<BusPoolClient> {
+        let mut client = self.client.clone().lock_owned().await;
+        self.ensure_connected(&mut client, timeout).await?;
+        Ok(BusPoolClient(client))
+    }
+    async fn check(&self, timeout: Duration) {
+        // skip the clients, which are currently in use
+        let Ok(mut client) = self.client.try_lock() else {
+            return;
+        };
+        if let Some(c) = client.as_mut() {
+            if c.is_connected() {
+                if matches!(tokio::time::timeout(timeout, c.ping()).await, Ok(Ok(()))) {
+                    return;
+                }
+                log::warn!("bus pool client #{} is not responding", self.n);
+                client.take();
+            }
+        }
+        if let Err(e) = self.ensure_connected(&mut client, timeout).await {
+            if e.kind() != crate::ErrorKind::NotReady {
+                log::error!("bus pool client #{} reconnect failed: {}", self.n, e);
+            }
+        }
+    }
+}
+
+/// A client, taken from [`BusPool`], released back to the pool on drop
+pub struct BusPoolClient(tokio::sync::OwnedMutexGuard<Option<busrt::ipc::Client>>);
+
+impl std::ops::Deref for BusPoolClient {
+    type Target = busrt::ipc::Client;
+    fn deref(&self) -> &Self::Target {
+        // the pool always gives connected clients
+        self.0.as_ref().unwrap()
+    }
+}
+
+impl std::ops::DerefMut for BusPoolClient {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.0.as_mut().unwrap()
+    }
+}
+
+/// A pool of secondary bus clients for parallel bus pipelines (e.g. high-throughput
+/// publishers). Broken clients are dropped and reconnected with an exponential backoff, either
+/// on the next use or by the health check task
+pub struct BusPool {
+    slots: Vec<BusPoolSlot>,
+    next: atomic::AtomicUsize,
+    timeout: Duration,
+}
+
+impl BusPool {
+    /// Creates a pool of clients for the configs. No connections are made until the pool is
+    /// used or [`BusPool::connect()`] is called
+    pub fn new(configs: Vec<busrt::ipc::Config>, timeout: Duration) -> Self {
+        Self {
+            slots: configs
+                .into_iter()
+                .enumerate()
+                .map(|(n, config)| BusPoolSlot {
+                    n,
+                    config,
+                    client: <_>::default(),
+                    backoff: <_>::default(),
+                })
+                .collect(),
+            next: atomic::AtomicUsize::new(0),
+            timeout,
+        }
+    }
+    #[inline]
+    pub fn size(&self) -> usize {
+        self.slots.len()
+    }
+    /// Connects all clients of the pool
+    pub async fn connect(&self) -> EResult<()> {
+        for slot in &self.slots {
+            let mut client = slot.client.lock().await;
+            slot.ensure_connected(&mut client, self.timeout).await?;
+        }
+        Ok(())
+    }
+    /// Gets the next client in round-robin order. If the client can not be connected, the next
+    /// ones are tried
+    pub async fn get(&self) -> EResult<BusPoolClient> {
+        if self.slots.is_empty() {
+            return Err(Error::not_ready("the bus pool is empty"));
+        }
+        let start = self.next.fetch_add(1, atomic::Ordering::Relaxed);
+        let mut err = None;
+        for i in 0..self.slots.len() {
+            match self.slots[(start + i) % self.slots.len()]
+                .acquire(self.timeout)
+                .await
+            {
+                Ok(client) => return Ok(client),
+                Err(e) => err = Some(e),
+            }
+        }
+        Err(err.unwrap())
+    }
+    /// Checks the idle clients (pings the connected ones, reconnects the broken)
+    pub async fn check(&self) {
+        for slot in &self.slots {
+            slot.check(self.timeout).await;
+        }
+    }
+    /// Spawns the health check task. The task stops when the pool is dropped
+    pub fn spawn_health_check(self: &Arc<Self>, interval: Duration) {
+        let pool = Arc::downgrade(self);
+        tokio::spawn(async move {
+            let mut int = tokio::time::interval(interval);
+            int.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                int.tick().await;
+                let Some(pool) = pool.upgrade() else {
+                    break;
+                };
+                pool.check().await;
+            }
+        });
+    }
+}
+
+/// Sets the supplementary groups of the process to the groups of the user
+#[cfg(all(unix, not(any(target_os = "macos", target_os = "ios"))))]
+fn init_groups(user: &std::ffi::CStr, gid: nix::unistd::Gid) -> nix::Result<()> {
+    let groups = nix::unistd::getgrouplist(user, gid)?;
+    nix::unistd::setgroups(&groups)
 }
 
-#[cfg(not(target_os = "windows"))]
+/// nix provides neither getgrouplist nor setgroups on Apple platforms, libc initgroups is used
+#[cfg(any(target_os = "macos", target_os = "ios"))]
+#[allow(clippy::cast_possible_wrap)]
+fn init_groups(user: &std::ffi::CStr, gid: nix::unistd::Gid) -> nix::Result<()> {
+    let res = unsafe { nix::libc::initgroups(user.as_ptr(), gid.as_raw() as nix::libc::c_int) };
+    nix::errno::Errno::result(res).map(drop)
+}
+
+#[cfg(unix)]
 pub fn get_system_user(user: &str) -> EResult<nix::unistd::User> {
     let u = nix::unistd::User::from_name(user)
         .map_err(|e| Error::failed(format!("failed to get the system user {}: {}", user, e)))?
@@ -535,7 +1531,7 @@ pub fn get_system_user(user: &str) -> EResult<nix::unistd::User> {
     Ok(u)
 }
 
-#[cfg(not(target_os = "windows"))]
+#[cfg(unix)]
 pub fn get_system_group(group: &str) -> EResult<nix::unistd::Group> {
     let g = nix::unistd::Group::from_name(group)
         .map_err(|e| Error::failed(format!("failed to get the system group {}: {}", group, e)))?
@@ -543,7 +1539,7 @@ pub fn get_system_group(group: &str) -> EResult<nix::unistd::Group> {
     Ok(g)
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
 pub struct Timeout {
     startup: Option<f64>,
     shutdown: Option<f64>,
@@ -608,6 +1604,17 @@ fn default_bus_type() -> String {
     "native".to_owned()
 }
 
+/// Default bus named pipe on Windows
+pub const DEFAULT_BUS_PIPE: &str = r"\\.\pipe\eva4-bus";
+
+const PIPE_PREFIX: &str = r"\\.\pipe\";
+
+#[cfg(target_os = "windows")]
+#[inline]
+fn default_bus_path() -> String {
+    DEFAULT_BUS_PIPE.to_owned()
+}
+
 #[inline]
 fn default_bus_buf_size() -> usize {
     busrt::DEFAULT_BUF_SIZE
@@ -624,10 +1631,11 @@ fn default_bus_queue_size() -> usize {
     busrt::DEFAULT_QUEUE_SIZE
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
 pub struct BusConfig {
     #[serde(rename = "type", default = "default_bus_type")]
     tp: String,
+    #[cfg_attr(target_os = "windows", serde(default = "default_bus_path"))]
     path: String,
     timeout: Option<f64>,
     #[serde(default = "default_bus_buf_size")]
@@ -639,6 +1647,9 @@ pub struct BusConfig {
     // deprecated field, as BUS/RT RPC uses timeout as a ping interval
     #[serde(rename = "ping_interval", skip_serializing, default)]
     _ping_interval: f64,
+    /// extra broker ping interval (seconds), useful for TCP connections behind NAT/firewalls
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    keepalive: Option<f64>,
 }
 
 impl BusConfig {
@@ -653,12 +1664,176 @@ impl BusConfig {
             self.timeout.replace(timeout);
         }
     }
+    #[inline]
+    pub fn keepalive(&self) -> Option<f64> {
+        self.keepalive
+    }
+    #[inline]
+    pub fn is_tcp(&self) -> bool {
+        self.tp == "tcp" || self.path.starts_with("tcp://")
+    }
+    /// Returns true for Windows named pipes (`\\.\pipe\name`)
+    #[inline]
+    pub fn is_pipe(&self) -> bool {
+        self.tp == "native" && self.path.starts_with(PIPE_PREFIX)
+    }
+    /// Returns the path for the bus client: a UNIX socket path (named pipe on Windows) or
+    /// host:port for TCP
+    fn connection_path(&self) -> EResult<String> {
+        if self.tp != "native" && self.tp != "tcp" {
+            return Err(Error::not_implemented(format!(
+                "bus type {} is not supported",
+                self.tp
+            )));
+        }
+        if let Some(keepalive) = self.keepalive {
+            if !keepalive.is_finite() || keepalive <= 0.0 {
+                return Err(Error::invalid_data(format!(
+                    "invalid bus keepalive: {}",
+                    keepalive
+                )));
+            }
+        }
+        if self.is_pipe() && cfg!(not(target_os = "windows")) {
+            return Err(Error::unsupported(format!(
+                "named pipes are supported on Windows only: {}",
+                self.path
+            )));
+        }
+        if !self.is_tcp() {
+            return Ok(self.path.clone());
+        }
+        let addr = self.path.strip_prefix("tcp://").unwrap_or(&self.path);
+        match addr.rsplit_once(':') {
+            Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok() => {}
+            _ => {
+                return Err(Error::invalid_data(format!(
+                    "invalid bus TCP address: {}",
+                    self.path
+                )))
+            }
+        }
+        Ok(addr.to_owned())
+    }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Method parameter type
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ParamKind {
+    #[default]
+    Any,
+    String,
+    Number,
+    Bool,
+    Oid,
+    Mask,
+    /// Seconds as a number
+    Duration,
+}
+
+impl ParamKind {
+    #[inline]
+    pub fn is_any(&self) -> bool {
+        *self == ParamKind::Any
+    }
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ParamKind::Any => "any",
+            ParamKind::String => "string",
+            ParamKind::Number => "number",
+            ParamKind::Bool => "bool",
+            ParamKind::Oid => "oid",
+            ParamKind::Mask => "mask",
+            ParamKind::Duration => "duration",
+        }
+    }
+    fn matches(self, value: &Value) -> bool {
+        match self {
+            ParamKind::Any => true,
+            ParamKind::String => matches!(value, Value::String(_)),
+            ParamKind::Number => value.is_numeric_type(),
+            ParamKind::Bool => matches!(value, Value::Bool(_)),
+            ParamKind::Oid => {
+                matches!(value, Value::String(s) if s.parse::<crate::OID>().is_ok())
+            }
+            #[cfg(feature = "acl")]
+            ParamKind::Mask => {
+                matches!(value, Value::String(s) if s.parse::<crate::acl::OIDMask>().is_ok())
+            }
+            #[cfg(not(feature = "acl"))]
+            ParamKind::Mask => matches!(value, Value::String(_)),
+            ParamKind::Duration => {
+                value.is_numeric_type() && f64::try_from(value).is_ok_and(|v| v >= 0.0)
+            }
+        }
+    }
+}
+
+impl fmt::Display for ParamKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct MethodParamInfo {
     #[serde(default)]
     pub required: bool,
+    #[serde(rename = "type", default, skip_serializing_if = "ParamKind::is_any")]
+    pub kind: ParamKind,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default: Option<Value>,
+    /// Allowed values, empty for any
+    #[serde(rename = "enum", default, skip_serializing_if = "Vec::is_empty")]
+    pub choices: Vec<Value>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub description: String,
+}
+
+impl MethodParamInfo {
+    pub fn required(kind: ParamKind) -> Self {
+        Self {
+            required: true,
+            kind,
+            ..Self::default()
+        }
+    }
+    pub fn optional(kind: ParamKind) -> Self {
+        Self {
+            required: false,
+            kind,
+            ..Self::default()
+        }
+    }
+    pub fn default_value(mut self, value: impl Into<Value>) -> Self {
+        self.default = Some(value.into());
+        self
+    }
+    pub fn choices<V: Into<Value>>(mut self, choices: impl IntoIterator<Item = V>) -> Self {
+        self.choices = choices.into_iter().map(Into::into).collect();
+        self
+    }
+    pub fn description(mut self, desc: &str) -> Self {
+        desc.clone_into(&mut self.description);
+        self
+    }
+    /// Checks a param value against the type and the choices
+    pub fn validate(&self, name: &str, value: &Value) -> EResult<()> {
+        if !self.kind.matches(value) {
+            return Err(Error::invalid_params(format!(
+                "param {}: {} expected",
+                name, self.kind
+            )));
+        }
+        if !self.choices.is_empty() && !self.choices.contains(value) {
+            return Err(Error::invalid_params(format!(
+                "param {}: the value is not in the allowed list",
+                name
+            )));
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -668,6 +1843,30 @@ pub struct MethodInfo {
     pub params: HashMap<String, MethodParamInfo>,
 }
 
+impl MethodInfo {
+    /// Validates call params against the declared schema. Params, which are not declared, are
+    /// ignored, nulls are considered as missing values
+    pub fn validate(&self, params: &Value) -> EResult<()> {
+        let empty = std::collections::BTreeMap::new();
+        let map = match params {
+            Value::Map(m) => m,
+            Value::Unit => &empty,
+            _ => return Err(Error::invalid_params("params must be a map")),
+        };
+        for (name, info) in &self.params {
+            match map.get(&Value::String(name.clone())) {
+                None | Some(Value::Unit) => {
+                    if info.required {
+                        return Err(Error::invalid_params(format!("param {} is required", name)));
+                    }
+                }
+                Some(value) => info.validate(name, value)?,
+            }
+        }
+        Ok(())
+    }
+}
+
 /// info-structure only, can be used by clients for auto-completion
 pub struct ServiceMethod {
     pub name: String,
@@ -689,12 +1888,17 @@ impl ServiceMethod {
     }
     pub fn required(mut self, name: &str) -> Self {
         self.params
-            .insert(name.to_owned(), MethodParamInfo { required: true });
+            .insert(name.to_owned(), MethodParamInfo::required(ParamKind::Any));
         self
     }
     pub fn optional(mut self, name: &str) -> Self {
         self.params
-            .insert(name.to_owned(), MethodParamInfo { required: false });
+            .insert(name.to_owned(), MethodParamInfo::optional(ParamKind::Any));
+        self
+    }
+    /// Adds a typed param
+    pub fn param(mut self, name: &str, info: MethodParamInfo) -> Self {
+        self.params.insert(name.to_owned(), info);
         self
     }
 }
@@ -731,10 +1935,40 @@ impl ServiceInfo {
             },
         );
     }
+    pub fn add_methods(&mut self, methods: impl IntoIterator<Item = ServiceMethod>) {
+        for method in methods {
+            self.add_method(method);
+        }
+    }
+}
+
+/// Method params schema, can be derived with `#[derive(EapiParams)]` (requires "derive" feature)
+pub trait EapiParams {
+    fn eapi_params() -> HashMap<String, MethodParamInfo>;
+}
+
+#[doc(hidden)]
+pub fn eapi_unpack_params<P: DeserializeOwned>(payload: &[u8]) -> EResult<P> {
+    if payload.is_empty() {
+        P::deserialize(Value::Map(<_>::default())).map_err(Error::invalid_params)
+    } else {
+        crate::payload::unpack(payload)
+    }
+}
+
+#[doc(hidden)]
+pub fn eapi_pack_result<T: Serialize>(result: &T) -> EResult<Option<Vec<u8>>> {
+    let packed = crate::payload::pack(result)?;
+    // unit results are returned as empty payloads
+    if packed == [0xc0] {
+        Ok(None)
+    } else {
+        Ok(Some(packed))
+    }
 }
 
 /// Used by services to announce their status (for "*")
-#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ServiceStatusBroadcastEvent {
     pub status: ServiceStatusBroadcast,
 }
@@ -755,7 +1989,7 @@ impl ServiceStatusBroadcastEvent {
 }
 
 /// Used by services and the core to notify about its state
-#[derive(Serialize, Deserialize)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 #[repr(u8)]
 pub enum ServiceStatusBroadcast {
@@ -779,3 +2013,382 @@ impl fmt::Display for ServiceStatusBroadcast {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        bus_pool_backoff, read_launcher_payload, reload_channel, service_id_matches,
+        svc_data_key_path, BusConfig, BusPool, CoreInfo, Initial, LaunchArgs, LaunchMode,
+        LauncherPayload, MethodParamInfo, ParamKind, RealtimeConfig, ServiceInfo, ServiceMethod,
+        Timeout, BUS_POOL_MAX_BACKOFF, SERVICE_PAYLOAD_INITIAL, SERVICE_PAYLOAD_PING,
+    };
+    use crate::value::Value;
+    use std::time::Duration;
+
+    fn initial(log_level: u8, bus_path: &str, config: Value) -> Initial {
+        let bus: BusConfig =
+            serde_json::from_str(&format!(r#"{{"path":"{}"}}"#, bus_path)).unwrap();
+        Initial::new(
+            "eva.svc.test",
+            "node1",
+            "svc/test",
+            None,
+            "/tmp",
+            &Timeout::default(),
+            CoreInfo::new(1, "4.0.2", 1, "/opt/eva4", log_level, true),
+            bus,
+            Some(&config),
+            1,
+            None,
+            false,
+            false,
+            false,
+        )
+    }
+
+    #[test]
+    fn test_bus_pool_backoff() {
+        assert_eq!(bus_pool_backoff(0), Duration::ZERO);
+        assert_eq!(bus_pool_backoff(1), Duration::from_millis(100));
+        assert_eq!(bus_pool_backoff(4), Duration::from_millis(800));
+        assert_eq!(bus_pool_backoff(100), BUS_POOL_MAX_BACKOFF);
+        let pool = BusPool::new(
+            vec![busrt::ipc::Config::new(
+                "/nonexistent/bus.ipc",
+                "test::pool.0",
+            )],
+            Duration::from_secs(1),
+        );
+        assert_eq!(pool.size(), 1);
+        let slot = &pool.slots[0];
+        slot.check_backoff().unwrap();
+        slot.register_failure();
+        assert_eq!(
+            slot.check_backoff().unwrap_err().kind(),
+            crate::ErrorKind::NotReady
+        );
+        slot.register_success();
+        slot.check_backoff().unwrap();
+    }
+
+    #[test]
+    fn test_svc_data_key_path() {
+        let path = svc_data_key_path("eva.svc.test", "a/b", false).unwrap();
+        assert_eq!(path.as_str(), "svc_data/eva.svc.test/a/b");
+        assert!(svc_data_key_path("eva.svc.test", "", false).is_err());
+        let path = svc_data_key_path("eva.svc.test", "", true).unwrap();
+        assert_eq!(path.as_str(), "svc_data/eva.svc.test");
+        assert!(svc_data_key_path("eva.svc.test", "../x", true).is_err());
+    }
+
+    #[test]
+    fn test_bus_config() {
+        let bus = |s: &str| serde_json::from_str::<BusConfig>(s).unwrap();
+        let b = bus(r#"{"path":"var/bus.ipc"}"#);
+        assert!(!b.is_tcp());
+        assert_eq!(b.connection_path().unwrap(), "var/bus.ipc");
+        let b = bus(r#"{"path":"tcp://10.0.0.1:7777","keepalive":5}"#);
+        assert!(b.is_tcp());
+        assert_eq!(b.connection_path().unwrap(), "10.0.0.1:7777");
+        assert_eq!(b.keepalive(), Some(5.0));
+        let b = bus(r#"{"type":"tcp","path":"broker.local:7777"}"#);
+        assert_eq!(b.connection_path().unwrap(), "broker.local:7777");
+        let b = bus(r#"{"type":"tcp","path":"broker.local"}"#);
+        assert!(b.connection_path().is_err());
+        let b = bus(r#"{"path":"tcp://h:7777","keepalive":0}"#);
+        assert!(b.connection_path().is_err());
+        let b = bus(r#"{"type":"quic","path":"h:7777"}"#);
+        assert!(b.connection_path().is_err());
+        let b = bus(r#"{"path":"\\\\.\\pipe\\eva4-bus"}"#);
+        assert!(b.is_pipe());
+        assert!(!b.is_tcp());
+        if cfg!(target_os = "windows") {
+            assert_eq!(b.connection_path().unwrap(), super::DEFAULT_BUS_PIPE);
+            assert_eq!(
+                bus("{}").connection_path().unwrap(),
+                super::DEFAULT_BUS_PIPE
+            );
+        } else {
+            assert_eq!(
+                b.connection_path().unwrap_err().kind(),
+                crate::ErrorKind::Unsupported
+            );
+        }
+    }
+
+    #[test]
+    fn test_apply_reload() {
+        let mut current = initial(20, "var/bus.ipc", Value::U8(1));
+        let diff = current
+            .apply_reload(initial(20, "var/bus.ipc", Value::U8(1)))
+            .unwrap();
+        assert!(diff.is_empty());
+        let diff = current
+            .apply_reload(initial(10, "var/bus2.ipc", Value::U8(2)))
+            .unwrap();
+        assert_eq!(diff.reloaded, vec!["config", "log_level"]);
+        assert_eq!(diff.restart_required, vec!["bus"]);
+        assert!(diff.needs_restart());
+        assert_eq!(current.config(), Some(&Value::U8(2)));
+        assert_eq!(current.eva_log_level(), 10);
+        assert_eq!(current.bus_path(), "var/bus.ipc");
+    }
+
+    #[test]
+    fn test_launch_args() {
+        let args =
+            LaunchArgs::parse(["--mode", "rtf", "--data-path=/tmp/data", "--bus", "/b.sock"])
+                .unwrap();
+        assert_eq!(args.mode, LaunchMode::Rtf);
+        assert!(LaunchArgs::parse(["--mode", "x"]).is_err());
+        assert!(LaunchArgs::parse(["--id"]).is_err());
+        assert!(LaunchArgs::parse(["--debug=1"]).is_err());
+        let mut frame = vec![SERVICE_PAYLOAD_PING, SERVICE_PAYLOAD_INITIAL];
+        let data = crate::payload::pack(&initial(20, "/a.sock", Value::Unit)).unwrap();
+        frame.extend(u32::try_from(data.len()).unwrap().to_le_bytes());
+        frame.extend(data);
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async move {
+                let mut reader = frame.as_slice();
+                assert!(matches!(
+                    read_launcher_payload(&mut reader).await.unwrap(),
+                    Some(LauncherPayload::Ping)
+                ));
+                let Some(LauncherPayload::Initial(mut initial)) =
+                    read_launcher_payload(&mut reader).await.unwrap()
+                else {
+                    panic!("initial payload expected");
+                };
+                assert!(read_launcher_payload(&mut reader).await.unwrap().is_none());
+                args.apply(&mut initial);
+                assert_eq!(initial.data_path(), Some("/tmp/data"));
+                assert_eq!(initial.bus_path(), "/b.sock");
+                assert_eq!(initial.id(), "eva.svc.test");
+                assert!(initial.is_mode_rtf());
+                let mut reader: &[u8] = &[SERVICE_PAYLOAD_INITIAL, 1];
+                assert!(read_launcher_payload(&mut reader).await.is_err());
+            });
+    }
+
+    #[test]
+    fn test_reload_channel() {
+        let (tx, mut rx) = reload_channel();
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(async move {
+                // pending reloads are coalesced, the latest one wins
+                tx.send(initial(20, "/a.sock", Value::U8(1)));
+                tx.send(initial(20, "/a.sock", Value::U8(2)));
+                assert_eq!(rx.recv().await.unwrap().config(), Some(&Value::U8(2)));
+                assert!(rx.try_recv().is_none());
+                tx.send(initial(20, "/a.sock", Value::U8(3)));
+                drop(tx);
+                assert_eq!(rx.recv().await.unwrap().config(), Some(&Value::U8(3)));
+                assert!(rx.recv().await.is_none());
+            });
+    }
+
+    #[test]
+    fn test_realtime_empty() {
+        let rt = RealtimeConfig::default();
+        assert!(rt.is_empty());
+        rt.apply().unwrap();
+    }
+
+    #[test]
+    fn test_service_id_matches() {
+        assert!(service_id_matches("*", "eva.svc.x"));
+        assert!(service_id_matches(
+            "eva.controller.*",
+            "eva.controller.modbus1"
+        ));
+        assert!(!service_id_matches("eva.controller.*", "eva.db.default"));
+        assert!(service_id_matches("eva.*.default", "eva.hmi.default"));
+        assert!(!service_id_matches("eva.*.default", "eva.hmi.default2"));
+        assert!(service_id_matches("eva.core", "eva.core"));
+        assert!(!service_id_matches("eva.core", "eva.core2"));
+    }
+
+    #[test]
+    fn test_method_schema() {
+        let mut info = ServiceInfo::new("", "", "");
+        info.add_method(
+            ServiceMethod::new("set")
+                .required("i")
+                .param("oid", MethodParamInfo::required(ParamKind::Oid))
+                .param(
+                    "mode",
+                    MethodParamInfo::optional(ParamKind::String)
+                        .choices(["fast", "slow"])
+                        .default_value("fast"),
+                )
+                .param("timeout", MethodParamInfo::optional(ParamKind::Duration)),
+        );
+        let method = &info.methods["set"];
+        let params = |json: &str| -> Value { serde_json::from_str(json).unwrap() };
+        method
+            .validate(&params(r#"{"i":1,"oid":"sensor:tests/s1","mode":"slow"}"#))
+            .unwrap();
+        method
+            .validate(&params(
+                r#"{"i":null,"oid":"sensor:tests/s1","timeout":null}"#,
+            ))
+            .unwrap_err();
+        method
+            .validate(&params(r#"{"i":1,"oid":"sensor:tests/s1","mode":"x"}"#))
+            .unwrap_err();
+        method
+            .validate(&params(r#"{"i":1,"oid":"sensor","timeout":1.5}"#))
+            .unwrap_err();
+        method
+            .validate(&params(r#"{"i":1,"oid":"unit:u1","timeout":-1}"#))
+            .unwrap_err();
+        method.validate(&Value::Unit).unwrap_err();
+        let s = serde_json::to_string(&method.params["mode"]).unwrap();
+        assert_eq!(
+            s,
+            r#"{"required":false,"type":"string","default":"fast","enum":["fast","slow"]}"#
+        );
+    }
+
+    #[cfg(feature = "derive")]
+    #[test]
+    #[allow(clippy::unused_self, clippy::unnecessary_wraps, clippy::unused_async)]
+    fn test_eapi_derive() {
+        use super::{eapi_service, EapiParams};
+        use crate::{EResult, ErrorKind, OID};
+        use serde::Deserialize;
+
+        #[derive(Deserialize, EapiParams)]
+        #[allow(dead_code)]
+        struct SetParams {
+            /// Item OID
+            i: OID,
+            #[eapi(choices = ["fast", "slow"], default = "fast")]
+            mode: Option<String>,
+            #[serde(default)]
+            count: u32,
+        }
+
+        struct Handlers;
+
+        #[eapi_service]
+        impl Handlers {
+            /// Sets an item value
+            #[eapi_method(name = "item.set")]
+            async fn set(&self, params: SetParams) -> EResult<u32> {
+                Ok(params.count + 1)
+            }
+            #[eapi_method]
+            fn ping(&self) -> EResult<()> {
+                Ok(())
+            }
+        }
+
+        let mut info = ServiceInfo::new("", "", "");
+        info.add_methods(Handlers::eapi_methods());
+        let method = &info.methods["item.set"];
+        assert_eq!(method.description, "Sets an item value");
+        assert!(method.params["i"].required);
+        assert_eq!(method.params["i"].kind, ParamKind::Oid);
+        assert_eq!(method.params["i"].description, "Item OID");
+        assert!(!method.params["mode"].required);
+        assert_eq!(method.params["mode"].choices.len(), 2);
+        assert!(!method.params["count"].required);
+        assert_eq!(method.params["count"].kind, ParamKind::Number);
+        assert!(info.methods["ping"].params.is_empty());
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let h = Handlers;
+            assert_eq!(h.eapi_dispatch("ping", &[]).await.unwrap(), None);
+            let payload =
+                crate::payload::pack(&serde_json::json!({"i": "unit:u1", "count": 2})).unwrap();
+            let result = h
+                .eapi_dispatch("item.set", &payload)
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(crate::payload::unpack::<u32>(&result).unwrap(), 3);
+            assert_eq!(
+                h.eapi_dispatch("item.set", &[]).await.unwrap_err().kind(),
+                ErrorKind::InvalidParameter
+            );
+            assert_eq!(
+                h.eapi_dispatch("x", &[]).await.unwrap_err().kind(),
+                ErrorKind::MethodNotFound
+            );
+        });
+    }
+    #[cfg(feature = "testkit")]
+    #[test]
+    fn test_wait_core_timeout() {
+        use super::{wait_core, wait_for_service};
+        use crate::testkit::MockClient;
+        use crate::ErrorKind;
+        use busrt::borrow::Cow;
+        use busrt::client::AsyncClient;
+        use busrt::rpc::{Rpc, RpcError, RpcEvent};
+        use busrt::{OpConfirm, QoS};
+        use std::sync::Arc;
+
+        // never replies to calls
+        struct StuckRpc(Arc<tokio::sync::Mutex<MockClient>>);
+
+        #[async_trait::async_trait]
+        impl Rpc for StuckRpc {
+            fn client(&self) -> Arc<tokio::sync::Mutex<dyn AsyncClient + 'static>> {
+                self.0.clone()
+            }
+            async fn notify(
+                &self,
+                _target: &str,
+                _data: Cow<'async_trait>,
+                _qos: QoS,
+            ) -> Result<OpConfirm, busrt::Error> {
+                Ok(None)
+            }
+            async fn call0(
+                &self,
+                _target: &str,
+                _method: &str,
+                _params: Cow<'async_trait>,
+                _qos: QoS,
+            ) -> Result<OpConfirm, busrt::Error> {
+                Ok(None)
+            }
+            async fn call(
+                &self,
+                _target: &str,
+                _method: &str,
+                _params: Cow<'async_trait>,
+                _qos: QoS,
+            ) -> Result<RpcEvent, RpcError> {
+                std::future::pending().await
+            }
+            fn is_connected(&self) -> bool {
+                true
+            }
+        }
+
+        let rpc = StuckRpc(Arc::new(tokio::sync::Mutex::new(MockClient::new("test"))));
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async move {
+                let timeout = Duration::from_millis(100);
+                let err = wait_core(&rpc, timeout).await.unwrap_err();
+                assert_eq!(err.kind(), ErrorKind::Timeout);
+                let err = wait_for_service(&rpc, "eva.svc.x", timeout)
+                    .await
+                    .unwrap_err();
+                assert_eq!(err.kind(), ErrorKind::Timeout);
+            });
+    }
+}
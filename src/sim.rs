@@ -0,0 +1,587 @@
+//! Synthetic state event sources for demos, tests and load benchmarks
+//!
+//! A [`Generator`] produces raw state events for a list of OIDs, either from a waveform
+//! (sine, ramp, square, random walk) or by replaying recorded CSV data. Events are produced by
+//! the standard [`Iterator`] (as fast as consumed, with synthetic event times) or paced in real
+//! time with [`Generator::next_paced()`].
+//!
+//! [`LoadGenerator`] fabricates large payloads of realistic shapes (inventories, event streams,
+//! deep meta values) for benchmarks and soak tests.
+//!
+//! ```yaml
+//! kind: sine
+//! amplitude: 10
+//! offset: 20
+//! period: 60
+//! ```
+use crate::events::{RawStateBulkEventOwned, RawStateEventOwned, ReplicationInventoryItem};
+use crate::value::{Value, ValueOptionOwned};
+use crate::{EResult, Error, IEID, OID};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::f64::consts::PI;
+use std::io::BufRead;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Synthetic value waveform. Periods are in seconds
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Waveform {
+    Sine {
+        amplitude: f64,
+        #[serde(default)]
+        offset: f64,
+        period: f64,
+    },
+    /// Sawtooth from the first value to the second one
+    Ramp {
+        from: f64,
+        to: f64,
+        period: f64,
+    },
+    Square {
+        low: f64,
+        high: f64,
+        period: f64,
+        /// Fraction of the period with the high value
+        #[serde(default = "default_duty")]
+        duty: f64,
+    },
+    /// Random steps within the range, each OID walks independently
+    RandomWalk {
+        start: f64,
+        step: f64,
+        min: f64,
+        max: f64,
+    },
+    Replay(Replay),
+}
+
+fn default_duty() -> f64 {
+    0.5
+}
+
+impl Waveform {
+    /// Checks the waveform parameters
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if a period is not positive, a random walk step is negative or not
+    /// finite or the random walk range is empty
+    pub fn validate(&self) -> EResult<()> {
+        match *self {
+            Waveform::Sine { period, .. }
+            | Waveform::Ramp { period, .. }
+            | Waveform::Square { period, .. } => {
+                if !(period > 0.0 && period.is_finite()) {
+                    return Err(Error::invalid_params(format!(
+                        "invalid waveform period: {}",
+                        period
+                    )));
+                }
+            }
+            Waveform::RandomWalk { step, min, max, .. } => {
+                if !(step >= 0.0 && step.is_finite()) {
+                    return Err(Error::invalid_params(format!(
+                        "invalid random walk step: {}",
+                        step
+                    )));
+                }
+                if min.is_nan() || max.is_nan() || min > max {
+                    return Err(Error::invalid_params(format!(
+                        "invalid random walk range: {}..={}",
+                        min, max
+                    )));
+                }
+            }
+            Waveform::Replay(_) => {}
+        }
+        Ok(())
+    }
+    /// Value of a periodic waveform at the elapsed time, the phase is a fraction of the period
+    fn periodic_value(&self, elapsed: f64, phase: f64) -> f64 {
+        let frac = |period: f64| (elapsed / period + phase).rem_euclid(1.0);
+        match *self {
+            Waveform::Sine {
+                amplitude,
+                offset,
+                period,
+            } => offset + amplitude * (2.0 * PI * frac(period)).sin(),
+            Waveform::Ramp { from, to, period } => from + (to - from) * frac(period),
+            Waveform::Square {
+                low,
+                high,
+                period,
+                duty,
+            } => {
+                if frac(period) < duty {
+                    high
+                } else {
+                    low
+                }
+            }
+            Waveform::RandomWalk { start, .. } => start,
+            Waveform::Replay(_) => f64::NAN,
+        }
+    }
+}
+
+/// Recorded samples to replay
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Replay {
+    /// (time, value) pairs, sorted by time
+    pub samples: Vec<(f64, Value)>,
+    /// Time scale: 0.5 replays the data twice faster, 2.0 twice slower
+    #[serde(default = "default_time_scale")]
+    pub time_scale: f64,
+}
+
+fn default_time_scale() -> f64 {
+    1.0
+}
+
+impl Replay {
+    /// Reads "time,value" CSV lines. A header line, empty lines and lines starting with "#" are
+    /// skipped. Numeric values are replayed as floats, other values as strings
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` on I/O errors, invalid times or unsorted data
+    pub fn from_csv<R: BufRead>(reader: R) -> EResult<Self> {
+        let mut samples: Vec<(f64, Value)> = Vec::new();
+        for (n, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (t, value) = line.split_once(',').ok_or_else(|| {
+                Error::invalid_data(format!("CSV line {}: value not found", n + 1))
+            })?;
+            let Ok(t) = t.trim().parse::<f64>() else {
+                if samples.is_empty() {
+                    // header
+                    continue;
+                }
+                return Err(Error::invalid_data(format!(
+                    "CSV line {}: invalid time",
+                    n + 1
+                )));
+            };
+            if samples.last().is_some_and(|(prev, _)| *prev > t) {
+                return Err(Error::invalid_data(format!(
+                    "CSV line {}: samples are not sorted",
+                    n + 1
+                )));
+            }
+            let value = value.trim().trim_matches('"');
+            let value = value
+                .parse::<f64>()
+                .map_or_else(|_| Value::String(value.to_owned()), Value::F64);
+            samples.push((t, value));
+        }
+        Ok(Self {
+            samples,
+            time_scale: 1.0,
+        })
+    }
+    pub fn time_scale(mut self, time_scale: f64) -> Self {
+        self.time_scale = time_scale;
+        self
+    }
+}
+
+/// Synthetic state event generator
+///
+/// Each tick produces an event for every OID. Waveforms tick with the interval, replays tick
+/// with the recorded samples. Event times start from the current time, unless set
+pub struct Generator {
+    oids: Vec<OID>,
+    waveform: Waveform,
+    interval: f64,
+    start: f64,
+    ticks: Option<u64>,
+    phase_shift: f64,
+    rng: StdRng,
+    walk: Vec<f64>,
+    tick: u64,
+    pos: usize,
+    buf: Vec<Value>,
+    buf_t: f64,
+    paced_from: Option<(Instant, f64)>,
+}
+
+impl Generator {
+    /// # Errors
+    ///
+    /// Will return `Err` if the interval is zero or the waveform is invalid
+    pub fn new(oids: Vec<OID>, waveform: Waveform, interval: Duration) -> EResult<Self> {
+        if interval.is_zero() {
+            return Err(Error::invalid_params("interval must be positive"));
+        }
+        waveform.validate()?;
+        let walk = match waveform {
+            Waveform::RandomWalk { start, .. } => vec![start; oids.len()],
+            _ => Vec::new(),
+        };
+        Ok(Self {
+            oids,
+            waveform,
+            interval: interval.as_secs_f64(),
+            start: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs_f64())
+                .unwrap_or_default(),
+            ticks: None,
+            phase_shift: 0.0,
+            rng: StdRng::from_entropy(),
+            walk,
+            tick: 0,
+            pos: 0,
+            buf: Vec::new(),
+            buf_t: 0.0,
+            paced_from: None,
+        })
+    }
+    /// Time of the first event (UNIX timestamp)
+    pub fn start(mut self, start: f64) -> Self {
+        self.start = start;
+        self
+    }
+    /// Limits the number of ticks (waveforms are infinite by default)
+    pub fn ticks(mut self, ticks: u64) -> Self {
+        self.ticks = Some(ticks);
+        self
+    }
+    /// Phase shift between OIDs, a fraction of the waveform period
+    pub fn phase_shift(mut self, phase_shift: f64) -> Self {
+        self.phase_shift = phase_shift;
+        self
+    }
+    /// Seeds the random generator to get reproducible random walks
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.rng = StdRng::seed_from_u64(seed);
+        self
+    }
+    /// Time and values of the current tick, None if finished
+    #[allow(clippy::cast_precision_loss, clippy::cast_possible_truncation)]
+    fn current(&mut self) -> Option<(f64, Vec<Value>)> {
+        if self.ticks.is_some_and(|ticks| self.tick >= ticks) {
+            return None;
+        }
+        let elapsed = self.tick as f64 * self.interval;
+        let values = match self.waveform {
+            Waveform::Replay(ref replay) => {
+                let (t, value) = replay.samples.get(self.tick as usize)?;
+                let t0 = replay.samples[0].0;
+                return Some((
+                    self.start + (t - t0) * replay.time_scale,
+                    vec![value.clone(); self.oids.len()],
+                ));
+            }
+            Waveform::RandomWalk { step, min, max, .. } => {
+                if self.tick > 0 {
+                    for v in &mut self.walk {
+                        *v = (*v + self.rng.gen_range(-step..=step)).clamp(min, max);
+                    }
+                }
+                self.walk.iter().copied().map(Value::F64).collect()
+            }
+            _ => (0..self.oids.len())
+                .map(|i| {
+                    Value::F64(
+                        self.waveform
+                            .periodic_value(elapsed, i as f64 * self.phase_shift),
+                    )
+                })
+                .collect(),
+        };
+        Some((self.start + elapsed, values))
+    }
+    /// Waits until the next event is due in real time (the first event is produced
+    /// immediately), then returns it
+    pub async fn next_paced(&mut self) -> Option<RawStateBulkEventOwned> {
+        let event = self.next()?;
+        let t = event.raw.t.unwrap_or_default();
+        let (started, t0) = *self.paced_from.get_or_insert((Instant::now(), t));
+        let due = started + Duration::from_secs_f64((t - t0).max(0.0));
+        tokio::time::sleep_until(due.into()).await;
+        Some(event)
+    }
+}
+
+impl Iterator for Generator {
+    type Item = RawStateBulkEventOwned;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.oids.is_empty() {
+            return None;
+        }
+        if self.pos == 0 {
+            let (t, values) = self.current()?;
+            self.buf_t = t;
+            self.buf = values;
+        }
+        let oid = self.oids[self.pos].clone();
+        let value = std::mem::take(&mut self.buf[self.pos]);
+        self.pos += 1;
+        if self.pos == self.oids.len() {
+            self.pos = 0;
+            self.tick += 1;
+        }
+        Some(RawStateBulkEventOwned::new(
+            oid,
+            RawStateEventOwned::new(1, value).at(self.buf_t),
+        ))
+    }
+}
+
+/// Deterministic generator of large payloads for load tests
+///
+/// Item OIDs are spread over a plant/line tree (70% sensors, 20% units, 10% lvars), values are
+/// mostly floats with some integers, booleans and strings, as in typical setups. The same seed
+/// always produces the same payloads
+pub struct LoadGenerator {
+    rng: StdRng,
+}
+
+impl LoadGenerator {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+    /// OID of the Nth item
+    ///
+    /// # Panics
+    ///
+    /// Should not panic
+    pub fn oid(&mut self, n: usize) -> OID {
+        let kind = match self.rng.gen_range(0..10) {
+            0..=6 => "sensor",
+            7 | 8 => "unit",
+            _ => "lvar",
+        };
+        format!(
+            "{}:plant{}/line{}/{}{}",
+            kind,
+            n / 1000,
+            n / 50 % 20,
+            kind,
+            n
+        )
+        .parse()
+        .unwrap()
+    }
+    pub fn oids(&mut self, count: usize) -> Vec<OID> {
+        (0..count).map(|n| self.oid(n)).collect()
+    }
+    /// A random item value
+    pub fn value(&mut self) -> Value {
+        match self.rng.gen_range(0..20) {
+            0..=13 => Value::F64((self.rng.gen_range(-1000.0..1000.0_f64) * 100.0).round() / 100.0),
+            14..=16 => Value::I64(self.rng.gen_range(-100_000..100_000)),
+            17 | 18 => Value::Bool(self.rng.gen()),
+            _ => Value::String(format!("state{}", self.rng.gen_range(0..100))),
+        }
+    }
+    /// Nested meta map of the specified depth, each level has `width` scalar fields and one
+    /// nested map
+    pub fn meta(&mut self, depth: usize, width: usize) -> Value {
+        let mut map = BTreeMap::new();
+        for i in 0..width {
+            map.insert(Value::String(format!("field{}", i)), self.value());
+        }
+        map.insert(
+            Value::String("units".to_owned()),
+            Value::String(["C", "bar", "rpm", "%"][self.rng.gen_range(0..4)].to_owned()),
+        );
+        if depth > 1 {
+            map.insert(
+                Value::String("nested".to_owned()),
+                self.meta(depth - 1, width),
+            );
+        }
+        Value::Map(map)
+    }
+    /// Replication inventory of the specified size, every tenth item has meta
+    pub fn inventory(&mut self, count: usize) -> Vec<ReplicationInventoryItem> {
+        (0..count)
+            .map(|n| ReplicationInventoryItem {
+                oid: self.oid(n),
+                status: Some(if self.rng.gen_range(0..100) == 0 {
+                    -1
+                } else {
+                    1
+                }),
+                value: ValueOptionOwned::Value(self.value()),
+                act: None,
+                ieid: Some(IEID::new(1, self.rng.gen_range(1..1_000_000))),
+                t: Some(1_700_000_000.0 + self.rng.gen_range(0.0..86_400.0)),
+                meta: (n % 10 == 0).then(|| self.meta(3, 4)),
+                enabled: true,
+            })
+            .collect()
+    }
+    /// Endless stream of state events for random OIDs of the list, with the specified rate
+    /// (events per second, synthetic event times start from the specified time)
+    ///
+    /// # Panics
+    ///
+    /// Will panic if the OID list is empty or the rate is not positive
+    #[allow(clippy::cast_precision_loss)]
+    pub fn state_events(
+        mut self,
+        oids: Vec<OID>,
+        rate: f64,
+        start: f64,
+    ) -> impl Iterator<Item = RawStateBulkEventOwned> {
+        assert!(!oids.is_empty(), "OID list is empty");
+        assert!(rate > 0.0, "rate must be positive");
+        (0_u64..).map(move |n| {
+            let oid = oids[self.rng.gen_range(0..oids.len())].clone();
+            RawStateBulkEventOwned::new(
+                oid,
+                RawStateEventOwned::new(1, self.value()).at(start + n as f64 / rate),
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Generator, LoadGenerator, Replay, Waveform};
+    use crate::events::RawStateBulkEventOwned;
+    use crate::value::{Value, ValueOptionOwned};
+    use crate::OID;
+    use std::time::Duration;
+
+    fn value(event: &RawStateBulkEventOwned) -> Value {
+        let ValueOptionOwned::Value(ref value) = event.raw.value else {
+            panic!("no value");
+        };
+        value.clone()
+    }
+
+    #[test]
+    fn test_sim_generator() {
+        let oids: Vec<OID> = vec!["sensor:s1".parse().unwrap(), "sensor:s2".parse().unwrap()];
+        let waveform: Waveform =
+            serde_json::from_str(r#"{"kind":"square","low":0,"high":1,"period":4}"#).unwrap();
+        let events: Vec<_> = Generator::new(oids.clone(), waveform, Duration::from_secs(1))
+            .unwrap()
+            .start(1000.0)
+            .phase_shift(0.5)
+            .ticks(4)
+            .collect();
+        assert_eq!(events.len(), 8);
+        assert_eq!(events[0].oid, oids[0]);
+        assert_eq!(events[1].oid, oids[1]);
+        let values: Vec<Value> = events.iter().map(value).collect();
+        let f = |v: f64| Value::F64(v);
+        assert_eq!(
+            values,
+            [
+                f(1.0),
+                f(0.0),
+                f(1.0),
+                f(0.0),
+                f(0.0),
+                f(1.0),
+                f(0.0),
+                f(1.0)
+            ]
+        );
+        assert_eq!(events[7].raw.t, Some(1003.0));
+        let walk = Waveform::RandomWalk {
+            start: 50.0,
+            step: 5.0,
+            min: 0.0,
+            max: 55.0,
+        };
+        let a: Vec<_> = Generator::new(oids.clone(), walk.clone(), Duration::from_secs(1))
+            .unwrap()
+            .seed(42)
+            .take(100)
+            .map(|e| value(&e))
+            .collect();
+        let b: Vec<_> = Generator::new(oids.clone(), walk, Duration::from_secs(1))
+            .unwrap()
+            .seed(42)
+            .take(100)
+            .map(|e| value(&e))
+            .collect();
+        assert_eq!(a, b);
+        assert!(a
+            .iter()
+            .all(|v| matches!(v, Value::F64(x) if (0.0..=55.0).contains(x))));
+        for walk in [
+            r#"{"kind":"random_walk","start":0,"step":-1,"min":0,"max":10}"#,
+            r#"{"kind":"random_walk","start":0,"step":1,"min":10,"max":0}"#,
+            r#"{"kind":"sine","amplitude":1,"period":0}"#,
+        ] {
+            let walk: Waveform = serde_json::from_str(walk).unwrap();
+            assert!(Generator::new(oids.clone(), walk, Duration::from_secs(1)).is_err());
+        }
+        let walk = Waveform::RandomWalk {
+            start: 0.0,
+            step: f64::NAN,
+            min: 0.0,
+            max: 1.0,
+        };
+        assert!(walk.validate().is_err());
+        let replay =
+            Replay::from_csv("time,value\n# comment\n100,1.5\n102,on\n\n106,2\n".as_bytes())
+                .unwrap()
+                .time_scale(0.5);
+        let events: Vec<_> = Generator::new(
+            oids[..1].to_vec(),
+            Waveform::Replay(replay),
+            Duration::from_secs(1),
+        )
+        .unwrap()
+        .start(0.0)
+        .collect();
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[1].raw.t, Some(1.0));
+        assert_eq!(value(&events[1]), Value::String("on".to_owned()));
+        assert_eq!(events[2].raw.t, Some(3.0));
+        assert!(Replay::from_csv("1,1\n0,2\n".as_bytes()).is_err());
+    }
+    #[test]
+    fn test_load_generator() {
+        let inventory = LoadGenerator::new(1).inventory(500);
+        assert_eq!(inventory.len(), 500);
+        assert_eq!(inventory.iter().filter(|i| i.meta.is_some()).count(), 50);
+        let again = LoadGenerator::new(1).inventory(500);
+        assert!(inventory
+            .iter()
+            .zip(again.iter())
+            .all(|(a, b)| a.oid == b.oid && a.value == b.value && a.meta == b.meta));
+        assert_ne!(
+            LoadGenerator::new(2).inventory(500)[0..10],
+            inventory[0..10]
+        );
+        let path = inventory[123].oid.as_path();
+        assert!(path.contains("/plant0/line2/") && path.ends_with("123"));
+        let mut meta = LoadGenerator::new(1).meta(5, 2);
+        let mut depth = 0;
+        while let Value::Map(mut map) = meta {
+            depth += 1;
+            meta = map
+                .remove(&Value::String("nested".to_owned()))
+                .unwrap_or_default();
+        }
+        assert_eq!(depth, 5);
+        let mut gen = LoadGenerator::new(1);
+        let oids = gen.oids(10);
+        let events: Vec<_> = gen
+            .state_events(oids.clone(), 100.0, 0.0)
+            .take(200)
+            .collect();
+        assert!(events.iter().all(|e| oids.contains(&e.oid)));
+        assert_eq!(events[199].raw.t, Some(1.99));
+    }
+}
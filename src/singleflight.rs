@@ -0,0 +1,314 @@
+//! Request coalescing
+//!
+//! [`SingleFlight`] deduplicates concurrent identical calls: the first caller executes the call,
+//! the others wait and share its result. Successful results are also cached for the entry TTL.
+//! [`RpcSingleFlight`] applies it to bus RPC read calls, keyed by (target, method, params hash).
+//!
+//! [`IdempotencyCache`] protects non-idempotent methods (action dispatch, config writes) from
+//! retried calls: results are stored by client-provided request ids and returned on replays.
+use crate::{EResult, Error};
+use busrt::rpc::{Rpc, RpcClient};
+use busrt::QoS;
+use parking_lot::Mutex;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+type Slot<V> = Arc<tokio::sync::Mutex<Option<(Instant, EResult<V>)>>>;
+
+pub struct SingleFlight<K, V> {
+    ttl: Duration,
+    entries: Mutex<HashMap<K, Slot<V>>>,
+}
+
+impl<K, V> SingleFlight<K, V>
+where
+    K: Hash + Eq + Clone,
+    V: Clone,
+{
+    /// Creates a new helper. With zero TTL, only concurrent calls are coalesced
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: <_>::default(),
+        }
+    }
+    #[inline]
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+    /// Returns the shared result for the key, calling `f` if there is no in-flight call or cached
+    /// result. Errors are shared with the concurrent callers only
+    pub async fn call<F, Fut>(&self, key: K, f: F) -> EResult<V>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = EResult<V>>,
+    {
+        let requested = Instant::now();
+        let slot = self.entries.lock().entry(key).or_default().clone();
+        let mut entry = slot.lock().await;
+        if let Some((produced, result)) = entry.as_ref() {
+            if *produced >= requested || (result.is_ok() && produced.elapsed() < self.ttl) {
+                return result.clone();
+            }
+        }
+        let result = f().await;
+        entry.replace((Instant::now(), result.clone()));
+        result
+    }
+    /// Removes the cached result for the key
+    pub fn invalidate(&self, key: &K) {
+        self.entries.lock().remove(key);
+    }
+    /// Removes expired idle entries, should be called periodically
+    pub fn cleanup(&self) {
+        let ttl = self.ttl;
+        self.entries.lock().retain(|_, slot| {
+            slot.try_lock().map_or(true, |entry| {
+                entry
+                    .as_ref()
+                    .is_some_and(|(produced, _)| produced.elapsed() < ttl)
+            })
+        });
+    }
+    pub fn len(&self) -> usize {
+        self.entries.lock().len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.entries.lock().is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Hash, Eq, PartialEq)]
+pub struct RpcCallKey {
+    target: String,
+    method: String,
+    params_hash: u64,
+}
+
+impl RpcCallKey {
+    pub fn new(target: &str, method: &str, params: &[u8]) -> Self {
+        Self {
+            target: target.to_owned(),
+            method: method.to_owned(),
+            params_hash: params_hash(params),
+        }
+    }
+}
+
+/// Coalesces identical RPC read calls (e.g. `item.state` from multiple HMI sessions)
+pub struct RpcSingleFlight {
+    rpc: Arc<RpcClient>,
+    inner: SingleFlight<RpcCallKey, Arc<Vec<u8>>>,
+}
+
+impl RpcSingleFlight {
+    pub fn new(rpc: Arc<RpcClient>, ttl: Duration) -> Self {
+        Self {
+            rpc,
+            inner: SingleFlight::new(ttl),
+        }
+    }
+    /// Calls the method (or joins an identical in-flight call) and returns the shared reply
+    /// payload. Must be used for read-only methods only
+    pub async fn call(&self, target: &str, method: &str, params: &[u8]) -> EResult<Arc<Vec<u8>>> {
+        let key = RpcCallKey::new(target, method, params);
+        self.inner
+            .call(key, || async {
+                let ev = self
+                    .rpc
+                    .call(target, method, params.into(), QoS::Processed)
+                    .await
+                    .map_err(Error::from)?;
+                Ok(Arc::new(ev.payload().to_vec()))
+            })
+            .await
+    }
+    #[inline]
+    pub fn singleflight(&self) -> &SingleFlight<RpcCallKey, Arc<Vec<u8>>> {
+        &self.inner
+    }
+}
+
+type IdempotencySlot<V> = Arc<tokio::sync::Mutex<Option<(Instant, u64, EResult<V>)>>>;
+
+fn params_hash(params: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    params.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Stores results of non-idempotent calls by request ids for the retention period
+///
+/// Request ids are provided by clients and should be unique per client, so services should
+/// prefix them with the caller name. Results (including errors) are returned on replays, a
+/// replay, which arrives while the original call is in progress, waits for its result
+pub struct IdempotencyCache<V> {
+    retention: Duration,
+    entries: Mutex<HashMap<String, IdempotencySlot<V>>>,
+}
+
+impl<V: Clone> IdempotencyCache<V> {
+    pub fn new(retention: Duration) -> Self {
+        Self {
+            retention,
+            entries: <_>::default(),
+        }
+    }
+    #[inline]
+    pub fn retention(&self) -> Duration {
+        self.retention
+    }
+    /// Calls `f` once for the request id and returns the stored result on replays
+    ///
+    /// # Errors
+    ///
+    /// Returns the call result or [`crate::ErrorKind::InvalidParameter`] if the request id is
+    /// reused with different params within the retention period
+    pub async fn call<F, Fut>(&self, request_id: &str, params: &[u8], f: F) -> EResult<V>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = EResult<V>>,
+    {
+        let hash = params_hash(params);
+        let slot = self
+            .entries
+            .lock()
+            .entry(request_id.to_owned())
+            .or_default()
+            .clone();
+        let mut entry = slot.lock().await;
+        if let Some((produced, h, result)) = entry.as_ref() {
+            if produced.elapsed() < self.retention {
+                if *h != hash {
+                    return Err(Error::invalid_params(format!(
+                        "request id {} is reused with different params",
+                        request_id
+                    )));
+                }
+                return result.clone();
+            }
+        }
+        let result = f().await;
+        entry.replace((Instant::now(), hash, result.clone()));
+        result
+    }
+    /// Returns true if a result for the request id is stored or the call is in progress
+    pub fn contains(&self, request_id: &str) -> bool {
+        self.entries.lock().get(request_id).is_some_and(|slot| {
+            slot.try_lock().map_or(true, |entry| {
+                entry
+                    .as_ref()
+                    .is_some_and(|(produced, _, _)| produced.elapsed() < self.retention)
+            })
+        })
+    }
+    /// Removes expired entries, should be called periodically
+    pub fn cleanup(&self) {
+        let retention = self.retention;
+        self.entries.lock().retain(|_, slot| {
+            slot.try_lock().map_or(true, |entry| {
+                entry
+                    .as_ref()
+                    .is_some_and(|(produced, _, _)| produced.elapsed() < retention)
+            })
+        });
+    }
+    pub fn len(&self) -> usize {
+        self.entries.lock().len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.entries.lock().is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{IdempotencyCache, SingleFlight};
+    use crate::{EResult, Error, ErrorKind};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[test]
+    fn test_singleflight() {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(singleflight());
+    }
+
+    async fn singleflight() {
+        let sf: Arc<SingleFlight<&str, usize>> =
+            Arc::new(SingleFlight::new(Duration::from_secs(10)));
+        let calls = Arc::new(AtomicUsize::new(0));
+        let mut futs = Vec::new();
+        for _ in 0..10 {
+            let sf = sf.clone();
+            let calls = calls.clone();
+            futs.push(tokio::spawn(async move {
+                sf.call("item.state", || async {
+                    tokio::time::sleep(Duration::from_millis(20)).await;
+                    Ok(calls.fetch_add(1, Ordering::SeqCst) + 100)
+                })
+                .await
+            }));
+        }
+        for fut in futs {
+            assert_eq!(fut.await.unwrap().unwrap(), 100);
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        // cached
+        assert_eq!(
+            sf.call("item.state", || async { Ok(0) }).await.unwrap(),
+            100
+        );
+        sf.invalidate(&"item.state");
+        assert_eq!(sf.call("item.state", || async { Ok(1) }).await.unwrap(), 1);
+        // errors are not cached
+        let sf: SingleFlight<&str, usize> = SingleFlight::new(Duration::from_secs(10));
+        let res: EResult<usize> = sf.call("k", || async { Err(Error::timeout()) }).await;
+        assert!(res.is_err());
+        assert_eq!(sf.call("k", || async { Ok(2) }).await.unwrap(), 2);
+        let sf: SingleFlight<&str, usize> = SingleFlight::new(Duration::ZERO);
+        sf.call("k", || async { Ok(1) }).await.unwrap();
+        assert_eq!(sf.call("k", || async { Ok(2) }).await.unwrap(), 2);
+        sf.cleanup();
+        assert!(sf.is_empty());
+    }
+    #[test]
+    fn test_idempotency_cache() {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async {
+                let cache: IdempotencyCache<usize> = IdempotencyCache::new(Duration::from_secs(10));
+                let calls = AtomicUsize::new(0);
+                let dispatch = || async { Ok(calls.fetch_add(1, Ordering::SeqCst)) };
+                assert_eq!(cache.call("c1.r1", b"p", dispatch).await.unwrap(), 0);
+                // replay after a client timeout
+                assert_eq!(cache.call("c1.r1", b"p", dispatch).await.unwrap(), 0);
+                assert_eq!(calls.load(Ordering::SeqCst), 1);
+                assert!(cache.contains("c1.r1"));
+                let err = cache.call("c1.r1", b"q", dispatch).await.unwrap_err();
+                assert_eq!(err.kind(), ErrorKind::InvalidParameter);
+                // errors are replayed too
+                let res = cache
+                    .call("c1.r2", b"p", || async { Err(Error::failed("no device")) })
+                    .await;
+                assert!(res.is_err());
+                assert!(cache.call("c1.r2", b"p", dispatch).await.is_err());
+                assert_eq!(calls.load(Ordering::SeqCst), 1);
+                let cache: IdempotencyCache<usize> = IdempotencyCache::new(Duration::ZERO);
+                cache.call("r", b"", dispatch).await.unwrap();
+                assert_eq!(cache.call("r", b"x", dispatch).await.unwrap(), 2);
+                cache.cleanup();
+                assert!(cache.is_empty());
+            });
+    }
+}
@@ -0,0 +1,369 @@
+//! Node state snapshots, used for backup/restore and node migration
+//!
+//! A snapshot is a stream of length-prefixed (u32 LE) MessagePack records, preceded by
+//! [`SNAPSHOT_MAGIC`]: a [`Header`], item [`Chunk`]s and an [`End`] record. Each chunk carries
+//! a SHA256 checksum of its data, the end record carries the checksum of all chunks, so
+//! corrupted and truncated snapshots are detected.
+use crate::events::{NodeInfo, ReplicationInventoryItem};
+use crate::payload::{pack, unpack};
+use crate::tools::{deserialize_bytes, serialize_bytes};
+use crate::{EResult, Error};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::io::{Read, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const SNAPSHOT_MAGIC: &[u8; 8] = b"EVA4SNAP";
+pub const SNAPSHOT_VERSION: u16 = 1;
+/// Default number of items in a chunk
+pub const DEFAULT_CHUNK_ITEMS: usize = 1000;
+/// Max record size, accepted by the reader
+pub const MAX_RECORD_SIZE: usize = 64 * 1024 * 1024;
+
+/// Snapshot item: state, meta and the enabled flag
+pub type Item = ReplicationInventoryItem;
+
+/// Node the snapshot has been taken from
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Node {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub info: Option<NodeInfo>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Header {
+    pub version: u16,
+    pub node: Node,
+    /// Creation time (UNIX timestamp)
+    pub created: f64,
+}
+
+impl Header {
+    pub fn new(node: Node) -> Self {
+        Self {
+            version: SNAPSHOT_VERSION,
+            node,
+            created: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs_f64())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// Packed list of items
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Chunk {
+    pub seq: u64,
+    pub items: u64,
+    #[serde(
+        serialize_with = "serialize_bytes",
+        deserialize_with = "deserialize_bytes"
+    )]
+    pub data: Vec<u8>,
+    /// SHA256 of the data (hex)
+    pub sha256: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct End {
+    pub chunks: u64,
+    pub items: u64,
+    /// SHA256 of all chunk data (hex)
+    pub sha256: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum Record {
+    Header(Header),
+    Chunk(Chunk),
+    End(End),
+}
+
+fn write_record<W: Write>(writer: &mut W, record: &Record) -> EResult<()> {
+    let data = pack(record)?;
+    let len = u32::try_from(data.len())
+        .map_err(|_| Error::invalid_data("snapshot record is too large"))?;
+    writer.write_all(&len.to_le_bytes())?;
+    writer.write_all(&data)?;
+    Ok(())
+}
+
+fn read_record<R: Read>(reader: &mut R) -> EResult<Record> {
+    let mut len_buf = [0_u8; 4];
+    reader.read_exact(&mut len_buf)?;
+    let len = u32::from_le_bytes(len_buf) as usize;
+    if len > MAX_RECORD_SIZE {
+        return Err(Error::invalid_data(format!(
+            "snapshot record is too large: {}",
+            len
+        )));
+    }
+    let mut data = vec![0; len];
+    reader.read_exact(&mut data)?;
+    unpack(&data)
+}
+
+/// Streaming snapshot writer
+pub struct Writer<W: Write> {
+    inner: W,
+    chunk_items: usize,
+    buf: Vec<Item>,
+    chunks: u64,
+    items: u64,
+    hasher: Sha256,
+}
+
+impl<W: Write> Writer<W> {
+    /// Writes the snapshot magic and the header
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` on I/O errors
+    pub fn new(mut writer: W, header: Header) -> EResult<Self> {
+        writer.write_all(SNAPSHOT_MAGIC)?;
+        write_record(&mut writer, &Record::Header(header))?;
+        Ok(Self {
+            inner: writer,
+            chunk_items: DEFAULT_CHUNK_ITEMS,
+            buf: Vec::new(),
+            chunks: 0,
+            items: 0,
+            hasher: Sha256::new(),
+        })
+    }
+    /// # Panics
+    ///
+    /// Will panic if the value is zero
+    pub fn chunk_items(mut self, chunk_items: usize) -> Self {
+        assert!(chunk_items > 0, "chunk items must be positive");
+        self.chunk_items = chunk_items;
+        self
+    }
+    /// Adds an item, full chunks are written immediately
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` on I/O errors
+    pub fn push(&mut self, item: Item) -> EResult<()> {
+        self.buf.push(item);
+        if self.buf.len() >= self.chunk_items {
+            self.flush_chunk()?;
+        }
+        Ok(())
+    }
+    fn flush_chunk(&mut self) -> EResult<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        let data = pack(&self.buf)?;
+        self.hasher.update(&data);
+        let chunk = Chunk {
+            seq: self.chunks,
+            items: self.buf.len() as u64,
+            sha256: hex::encode(Sha256::digest(&data)),
+            data,
+        };
+        write_record(&mut self.inner, &Record::Chunk(chunk))?;
+        self.chunks += 1;
+        self.items += self.buf.len() as u64;
+        self.buf.clear();
+        Ok(())
+    }
+    /// Writes the remaining items and the end record, returns the inner writer
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` on I/O errors
+    pub fn finish(mut self) -> EResult<W> {
+        self.flush_chunk()?;
+        let end = End {
+            chunks: self.chunks,
+            items: self.items,
+            sha256: hex::encode(std::mem::replace(&mut self.hasher, Sha256::new()).finalize()),
+        };
+        write_record(&mut self.inner, &Record::End(end))?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+/// Streaming snapshot reader, iterates snapshot items
+///
+/// Items are verified chunk by chunk, so a corrupted or truncated snapshot may be partially
+/// read before an error is returned
+pub struct Reader<R: Read> {
+    inner: R,
+    header: Header,
+    items: std::vec::IntoIter<Item>,
+    chunks: u64,
+    total: u64,
+    hasher: Sha256,
+    finished: bool,
+}
+
+impl<R: Read> Reader<R> {
+    /// Reads and checks the snapshot magic and the header
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the stream is not a snapshot or its version is not supported
+    pub fn new(mut reader: R) -> EResult<Self> {
+        let mut magic = [0_u8; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != SNAPSHOT_MAGIC {
+            return Err(Error::invalid_data("not a node snapshot"));
+        }
+        let Record::Header(header) = read_record(&mut reader)? else {
+            return Err(Error::invalid_data("snapshot header not found"));
+        };
+        if header.version > SNAPSHOT_VERSION {
+            return Err(Error::unsupported(format!(
+                "unsupported snapshot version: {}",
+                header.version
+            )));
+        }
+        Ok(Self {
+            inner: reader,
+            header,
+            items: Vec::new().into_iter(),
+            chunks: 0,
+            total: 0,
+            hasher: Sha256::new(),
+            finished: false,
+        })
+    }
+    #[inline]
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+    /// Reads the next chunk, returns false if the end record has been reached
+    fn read_chunk(&mut self) -> EResult<bool> {
+        let record = read_record(&mut self.inner).map_err(|e| {
+            if e.kind() == crate::ErrorKind::IOError {
+                Error::invalid_data(format!("snapshot is truncated: {}", e))
+            } else {
+                e
+            }
+        })?;
+        match record {
+            Record::Chunk(chunk) => {
+                if chunk.seq != self.chunks {
+                    return Err(Error::invalid_data(format!(
+                        "snapshot chunk sequence mismatch: {}, expected: {}",
+                        chunk.seq, self.chunks
+                    )));
+                }
+                if hex::encode(Sha256::digest(&chunk.data)) != chunk.sha256 {
+                    return Err(Error::invalid_data(format!(
+                        "snapshot chunk {} checksum mismatch",
+                        chunk.seq
+                    )));
+                }
+                self.hasher.update(&chunk.data);
+                let items: Vec<Item> = unpack(&chunk.data)?;
+                if items.len() as u64 != chunk.items {
+                    return Err(Error::invalid_data(format!(
+                        "snapshot chunk {} item count mismatch",
+                        chunk.seq
+                    )));
+                }
+                self.chunks += 1;
+                self.total += chunk.items;
+                self.items = items.into_iter();
+                Ok(true)
+            }
+            Record::End(end) => {
+                let sha256 =
+                    hex::encode(std::mem::replace(&mut self.hasher, Sha256::new()).finalize());
+                if end.chunks != self.chunks || end.items != self.total || end.sha256 != sha256 {
+                    return Err(Error::invalid_data("snapshot checksum mismatch"));
+                }
+                Ok(false)
+            }
+            Record::Header(_) => Err(Error::invalid_data("unexpected snapshot header")),
+        }
+    }
+}
+
+impl<R: Read> Iterator for Reader<R> {
+    type Item = EResult<Item>;
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(item) = self.items.next() {
+                return Some(Ok(item));
+            }
+            if self.finished {
+                return None;
+            }
+            match self.read_chunk() {
+                Ok(true) => {}
+                Ok(false) => {
+                    self.finished = true;
+                    return None;
+                }
+                Err(e) => {
+                    self.finished = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Header, Item, Node, Reader, Writer};
+    use crate::value::{Value, ValueOptionOwned};
+    use crate::IEID;
+
+    #[test]
+    fn test_snapshot() {
+        let items: Vec<Item> = (0..25)
+            .map(|i| Item {
+                oid: format!("sensor:tests/s{}", i).parse().unwrap(),
+                status: Some(1),
+                value: ValueOptionOwned::Value(Value::U64(i)),
+                act: None,
+                ieid: Some(IEID::new(1, i)),
+                t: Some(1_700_000_000.0),
+                meta: (i == 3).then(|| Value::String("meta".to_owned())),
+                enabled: i % 2 == 0,
+            })
+            .collect();
+        let node = Node {
+            name: "node1".to_owned(),
+            info: None,
+        };
+        let mut writer = Writer::new(Vec::new(), Header::new(node))
+            .unwrap()
+            .chunk_items(10);
+        for item in items.clone() {
+            writer.push(item).unwrap();
+        }
+        let data = writer.finish().unwrap();
+        let reader = Reader::new(data.as_slice()).unwrap();
+        assert_eq!(reader.header().node.name, "node1");
+        let restored: Vec<Item> = reader.collect::<Result<_, _>>().unwrap();
+        assert_eq!(restored.len(), 25);
+        assert_eq!(restored[3].meta, items[3].meta);
+        assert_eq!(restored[24].value, items[24].value);
+        assert!(!restored[1].enabled);
+        // truncated
+        let result: Result<Vec<Item>, _> = Reader::new(&data[..data.len() - 10]).unwrap().collect();
+        assert!(result.is_err());
+        // corrupted
+        let mut corrupted = data.clone();
+        let pos = corrupted.len() / 2;
+        corrupted[pos] ^= 0xff;
+        let result: Result<Vec<Item>, _> = Reader::new(corrupted.as_slice()).unwrap().collect();
+        assert!(result.is_err());
+        assert!(Reader::new(&b"EVA4SNAQ"[..]).is_err());
+    }
+}
@@ -0,0 +1,280 @@
+//! Statistical process control: X-bar/R chart limits and Western Electric rules
+use crate::time::Time;
+use crate::{EResult, Error};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+/// Control chart constants (A2, D3, D4) for subgroup sizes 2..=10
+const CHART_CONSTANTS: [(f64, f64, f64); 9] = [
+    (1.880, 0.0, 3.267),
+    (1.023, 0.0, 2.574),
+    (0.729, 0.0, 2.282),
+    (0.577, 0.0, 2.114),
+    (0.483, 0.0, 2.004),
+    (0.419, 0.076, 1.924),
+    (0.373, 0.136, 1.864),
+    (0.337, 0.184, 1.816),
+    (0.308, 0.223, 1.777),
+];
+
+pub const MIN_SUBGROUP_SIZE: usize = 2;
+pub const MAX_SUBGROUP_SIZE: usize = 10;
+
+/// Points kept by the rule checker (the longest rule window)
+const RULE_WINDOW: usize = 8;
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Subgroup {
+    /// time of the last subgroup point
+    pub t: Time,
+    pub mean: f64,
+    pub range: f64,
+}
+
+/// Splits the series into consecutive subgroups of the given size, the incomplete tail is
+/// dropped
+#[allow(clippy::cast_precision_loss)]
+pub fn subgroups(series: &[(Time, f64)], size: usize) -> Vec<Subgroup> {
+    if size == 0 {
+        return Vec::new();
+    }
+    series
+        .chunks_exact(size)
+        .map(|chunk| {
+            let (min, max, sum) = chunk.iter().fold(
+                (f64::INFINITY, f64::NEG_INFINITY, 0.0),
+                |(min, max, sum), (_, v)| (min.min(*v), max.max(*v), sum + v),
+            );
+            Subgroup {
+                t: chunk[size - 1].0,
+                mean: sum / size as f64,
+                range: max - min,
+            }
+        })
+        .collect()
+}
+
+/// X-bar/R chart control limits
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct XbarR {
+    pub size: usize,
+    pub x_center: f64,
+    pub x_ucl: f64,
+    pub x_lcl: f64,
+    pub r_center: f64,
+    pub r_ucl: f64,
+    pub r_lcl: f64,
+}
+
+impl XbarR {
+    /// Calculates the limits from subgroups of the given size
+    #[allow(clippy::cast_precision_loss)]
+    pub fn from_subgroups(subgroups: &[Subgroup], size: usize) -> EResult<Self> {
+        if !(MIN_SUBGROUP_SIZE..=MAX_SUBGROUP_SIZE).contains(&size) {
+            return Err(Error::invalid_params(format!(
+                "subgroup size must be in {}..={}",
+                MIN_SUBGROUP_SIZE, MAX_SUBGROUP_SIZE
+            )));
+        }
+        if subgroups.is_empty() {
+            return Err(Error::invalid_data("no subgroups"));
+        }
+        let (a2, d3, d4) = CHART_CONSTANTS[size - MIN_SUBGROUP_SIZE];
+        let count = subgroups.len() as f64;
+        let x_center = subgroups.iter().map(|s| s.mean).sum::<f64>() / count;
+        let r_center = subgroups.iter().map(|s| s.range).sum::<f64>() / count;
+        Ok(Self {
+            size,
+            x_center,
+            x_ucl: x_center + a2 * r_center,
+            x_lcl: x_center - a2 * r_center,
+            r_center,
+            r_ucl: d4 * r_center,
+            r_lcl: d3 * r_center,
+        })
+    }
+    /// Calculates the limits from a raw series, split into subgroups
+    pub fn from_series(series: &[(Time, f64)], size: usize) -> EResult<Self> {
+        Self::from_subgroups(&subgroups(series, size), size)
+    }
+    /// Standard deviation of subgroup means
+    #[inline]
+    pub fn sigma(&self) -> f64 {
+        (self.x_ucl - self.x_center) / 3.0
+    }
+    /// Returns true if the subgroup range is within the R chart limits
+    pub fn range_in_control(&self, subgroup: &Subgroup) -> bool {
+        (self.r_lcl..=self.r_ucl).contains(&subgroup.range)
+    }
+    /// Western Electric rule checker for subgroup means
+    pub fn rules(&self) -> RuleChecker {
+        RuleChecker::new(self.x_center, self.sigma())
+    }
+}
+
+/// Western Electric rules
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[repr(u8)]
+pub enum Rule {
+    /// a point beyond 3 sigma
+    Beyond3Sigma = 1,
+    /// 2 of 3 consecutive points beyond 2 sigma on the same side
+    TwoOfThreeBeyond2Sigma = 2,
+    /// 4 of 5 consecutive points beyond 1 sigma on the same side
+    FourOfFiveBeyond1Sigma = 3,
+    /// 8 consecutive points on the same side of the center line
+    EightOnOneSide = 4,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Side {
+    Above,
+    Below,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Violation {
+    pub rule: Rule,
+    pub side: Side,
+    /// time of the point the violation is detected at
+    pub t: Time,
+    pub value: f64,
+}
+
+/// Detects Western Electric rule violations over a rolling window of points
+#[derive(Debug, Clone)]
+pub struct RuleChecker {
+    center: f64,
+    sigma: f64,
+    window: VecDeque<(Time, f64)>,
+}
+
+impl RuleChecker {
+    pub fn new(center: f64, sigma: f64) -> Self {
+        Self {
+            center,
+            sigma,
+            window: VecDeque::with_capacity(RULE_WINDOW),
+        }
+    }
+    fn zone(&self, value: f64) -> f64 {
+        if self.sigma > 0.0 {
+            (value - self.center) / self.sigma
+        } else {
+            0.0
+        }
+    }
+    /// Counts the last points beyond the given number of sigmas on the side
+    fn count_beyond(&self, points: usize, sigmas: f64, side: Side) -> usize {
+        self.window
+            .iter()
+            .rev()
+            .take(points)
+            .filter(|(_, v)| {
+                let z = self.zone(*v);
+                match side {
+                    Side::Above => z > sigmas,
+                    Side::Below => z < -sigmas,
+                }
+            })
+            .count()
+    }
+    /// Adds a point and returns violations, detected at it
+    pub fn push(&mut self, t: Time, value: f64) -> Vec<Violation> {
+        if self.window.len() == RULE_WINDOW {
+            self.window.pop_front();
+        }
+        self.window.push_back((t, value));
+        let z = self.zone(value);
+        let side = if z < 0.0 { Side::Below } else { Side::Above };
+        let mut result = Vec::new();
+        let mut violation = |rule| {
+            result.push(Violation {
+                rule,
+                side,
+                t,
+                value,
+            });
+        };
+        if z.abs() > 3.0 {
+            violation(Rule::Beyond3Sigma);
+        }
+        if z.abs() > 2.0 && self.window.len() >= 3 && self.count_beyond(3, 2.0, side) >= 2 {
+            violation(Rule::TwoOfThreeBeyond2Sigma);
+        }
+        if z.abs() > 1.0 && self.window.len() >= 5 && self.count_beyond(5, 1.0, side) >= 4 {
+            violation(Rule::FourOfFiveBeyond1Sigma);
+        }
+        if z != 0.0 && self.count_beyond(RULE_WINDOW, 0.0, side) == RULE_WINDOW {
+            violation(Rule::EightOnOneSide);
+        }
+        result
+    }
+    /// Checks a series point-by-point
+    pub fn check(&mut self, series: &[(Time, f64)]) -> Vec<Violation> {
+        series.iter().flat_map(|(t, v)| self.push(*t, *v)).collect()
+    }
+    /// Clears the window
+    pub fn reset(&mut self) {
+        self.window.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{subgroups, Rule, RuleChecker, Side, XbarR};
+    use crate::time::Time;
+
+    fn series(values: &[f64]) -> Vec<(Time, f64)> {
+        values
+            .iter()
+            .enumerate()
+            .map(|(i, v)| (Time::from_timestamp_ms(i as u64 * 1000), *v))
+            .collect()
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn test_spc() {
+        let data = series(&[10.0, 12.0, 11.0, 9.0, 11.0, 10.0, 10.5, 9.5, 11.5, 8.5]);
+        let groups = subgroups(&data, 2);
+        assert_eq!(groups.len(), 5);
+        assert_eq!(groups[0].mean, 11.0);
+        assert_eq!(groups[0].range, 2.0);
+        assert_eq!(groups[4].t.timestamp_sec(), 9);
+        let limits = XbarR::from_subgroups(&groups, 2).unwrap();
+        assert!((limits.x_center - 10.3).abs() < 1e-9);
+        assert!((limits.r_center - 1.8).abs() < 1e-9);
+        assert!((limits.x_ucl - (10.3 + 1.880 * 1.8)).abs() < 1e-9);
+        assert!((limits.r_ucl - 3.267 * 1.8).abs() < 1e-9);
+        assert!(limits.range_in_control(&groups[4]));
+        assert!(XbarR::from_series(&data, 11).is_err());
+        let mut checker = RuleChecker::new(0.0, 1.0);
+        let v = checker.check(&series(&[0.5, 3.5]));
+        assert_eq!(v.len(), 1);
+        assert_eq!(v[0].rule, Rule::Beyond3Sigma);
+        assert_eq!(v[0].t.timestamp_sec(), 1);
+        checker.reset();
+        let v = checker.check(&series(&[-2.5, -0.5, -2.2]));
+        assert_eq!(v.len(), 1);
+        assert_eq!(v[0].rule, Rule::TwoOfThreeBeyond2Sigma);
+        assert_eq!(v[0].side, Side::Below);
+        checker.reset();
+        let v = checker.check(&series(&[1.5, 1.2, 0.5, 1.8, 1.1]));
+        assert_eq!(
+            v.iter().map(|v| v.rule).collect::<Vec<_>>(),
+            [Rule::FourOfFiveBeyond1Sigma]
+        );
+        checker.reset();
+        let v = checker.check(&series(&[0.1, 0.2, 0.3, 0.1, 0.5, 0.2, 0.4, 0.1, 0.3]));
+        assert_eq!(v.len(), 2);
+        assert!(v.iter().all(|v| v.rule == Rule::EightOnOneSide));
+        assert_eq!(v[0].t.timestamp_sec(), 7);
+        assert_eq!(
+            serde_json::to_value(&v[0]).unwrap()["rule"],
+            "eight_on_one_side"
+        );
+    }
+}
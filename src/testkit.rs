@@ -0,0 +1,544 @@
+//! In-memory bus test utilities
+//!
+//! [`MockClient`] is a bus client without a broker: published frames are recorded and delivered
+//! back to the client subscriptions. [`MockRpc`] implements the bus RPC trait with scripted
+//! replies and call capture. [`EventCollector`] subscribes a client to topics and records
+//! decoded events for assertions.
+use crate::payload::{pack, unpack};
+use crate::{EResult, Error};
+use async_trait::async_trait;
+use busrt::borrow::Cow;
+use busrt::client::AsyncClient;
+use busrt::rpc::{Rpc, RpcError, RpcEvent, RPC_REPLY};
+use busrt::{EventChannel, Frame, FrameData, FrameKind, OpConfirm, QoS};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::sync::atomic;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const EVENT_QUEUE_SIZE: usize = 8192;
+const WAIT_STEP: Duration = Duration::from_millis(10);
+
+/// Matches a topic with a subscription mask ("+" matches a single level, "#" the rest)
+pub fn topic_matches(mask: &str, topic: &str) -> bool {
+    let mut m = mask.split('/');
+    let mut t = topic.split('/');
+    loop {
+        match (m.next(), t.next()) {
+            (Some("#"), _) | (None, None) => return true,
+            (Some(ms), Some(ts)) if ms == "+" || ms == ts => {}
+            _ => return false,
+        }
+    }
+}
+
+/// A frame, recorded by [`MockClient`]
+#[derive(Debug, Clone)]
+pub struct SentFrame {
+    pub kind: FrameKind,
+    /// topic for publications, target for other frames
+    pub target: String,
+    pub payload: Vec<u8>,
+}
+
+impl SentFrame {
+    pub fn unpack<T: DeserializeOwned>(&self) -> EResult<T> {
+        unpack(&self.payload)
+    }
+}
+
+pub struct MockClient {
+    name: String,
+    subscriptions: BTreeSet<String>,
+    sent: Arc<parking_lot::Mutex<Vec<SentFrame>>>,
+    tx: async_channel::Sender<Frame>,
+    rx: Option<EventChannel>,
+    connected: Arc<atomic::AtomicBool>,
+}
+
+impl MockClient {
+    pub fn new(name: &str) -> Self {
+        let (tx, rx) = async_channel::bounded(EVENT_QUEUE_SIZE);
+        Self {
+            name: name.to_owned(),
+            subscriptions: <_>::default(),
+            sent: <_>::default(),
+            tx,
+            rx: Some(rx),
+            connected: Arc::new(atomic::AtomicBool::new(true)),
+        }
+    }
+    /// Recorded outgoing frames (shared, can be inspected after the client is moved)
+    #[inline]
+    pub fn sent(&self) -> Arc<parking_lot::Mutex<Vec<SentFrame>>> {
+        self.sent.clone()
+    }
+    /// Delivers a publication frame to the client, if subscribed
+    pub fn inject(&self, topic: &str, sender: &str, payload: Vec<u8>) -> EResult<()> {
+        if self.subscriptions.iter().any(|m| topic_matches(m, topic)) {
+            let frame = FrameData::new(
+                FrameKind::Publish,
+                Some(sender.to_owned()),
+                Some(sender.to_owned()),
+                Some(topic.to_owned()),
+                None,
+                payload,
+                0,
+                false,
+            );
+            self.tx
+                .try_send(Arc::new(frame))
+                .map_err(|e| Error::io(format!("event queue: {}", e)))?;
+        }
+        Ok(())
+    }
+    /// Packs and delivers an event to the client, if subscribed
+    pub fn inject_event<T: Serialize>(&self, topic: &str, event: &T) -> EResult<()> {
+        self.inject(topic, ".test", pack(event)?)
+    }
+    /// Simulates connection loss or restore
+    pub fn set_connected(&self, connected: bool) {
+        self.connected.store(connected, atomic::Ordering::SeqCst);
+    }
+    fn record(&self, kind: FrameKind, target: &str, payload: &[u8]) {
+        self.sent.lock().push(SentFrame {
+            kind,
+            target: target.to_owned(),
+            payload: payload.to_vec(),
+        });
+    }
+}
+
+#[async_trait]
+impl AsyncClient for MockClient {
+    fn take_event_channel(&mut self) -> Option<EventChannel> {
+        self.rx.take()
+    }
+    async fn send(
+        &mut self,
+        target: &str,
+        payload: Cow<'async_trait>,
+        _qos: QoS,
+    ) -> Result<OpConfirm, busrt::Error> {
+        self.record(FrameKind::Message, target, payload.as_slice());
+        Ok(None)
+    }
+    async fn zc_send(
+        &mut self,
+        target: &str,
+        header: Cow<'async_trait>,
+        payload: Cow<'async_trait>,
+        _qos: QoS,
+    ) -> Result<OpConfirm, busrt::Error> {
+        let mut buf = header.to_vec();
+        buf.extend(payload.as_slice());
+        self.record(FrameKind::Message, target, &buf);
+        Ok(None)
+    }
+    async fn send_broadcast(
+        &mut self,
+        target: &str,
+        payload: Cow<'async_trait>,
+        _qos: QoS,
+    ) -> Result<OpConfirm, busrt::Error> {
+        self.record(FrameKind::Broadcast, target, payload.as_slice());
+        Ok(None)
+    }
+    async fn publish(
+        &mut self,
+        topic: &str,
+        payload: Cow<'async_trait>,
+        _qos: QoS,
+    ) -> Result<OpConfirm, busrt::Error> {
+        let payload = payload.to_vec();
+        self.inject(topic, &self.name, payload.clone())
+            .map_err(busrt::Error::io)?;
+        self.record(FrameKind::Publish, topic, &payload);
+        Ok(None)
+    }
+    async fn subscribe(&mut self, topic: &str, _qos: QoS) -> Result<OpConfirm, busrt::Error> {
+        self.subscriptions.insert(topic.to_owned());
+        Ok(None)
+    }
+    async fn unsubscribe(&mut self, topic: &str, _qos: QoS) -> Result<OpConfirm, busrt::Error> {
+        self.subscriptions.remove(topic);
+        Ok(None)
+    }
+    async fn subscribe_bulk(
+        &mut self,
+        topics: &[&str],
+        _qos: QoS,
+    ) -> Result<OpConfirm, busrt::Error> {
+        for topic in topics {
+            self.subscriptions.insert((*topic).to_owned());
+        }
+        Ok(None)
+    }
+    async fn unsubscribe_bulk(
+        &mut self,
+        topics: &[&str],
+        _qos: QoS,
+    ) -> Result<OpConfirm, busrt::Error> {
+        for topic in topics {
+            self.subscriptions.remove(*topic);
+        }
+        Ok(None)
+    }
+    async fn ping(&mut self) -> Result<(), busrt::Error> {
+        Ok(())
+    }
+    fn is_connected(&self) -> bool {
+        self.connected.load(atomic::Ordering::SeqCst)
+    }
+    fn get_connected_beacon(&self) -> Option<Arc<atomic::AtomicBool>> {
+        Some(self.connected.clone())
+    }
+    fn get_timeout(&self) -> Option<Duration> {
+        None
+    }
+    fn get_name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// An RPC call, recorded by [`MockRpc`]
+#[derive(Debug, Clone)]
+pub struct MockCall {
+    pub target: String,
+    pub method: String,
+    pub params: Vec<u8>,
+    /// false for notifications and calls without replies (call0)
+    pub reply_required: bool,
+}
+
+impl MockCall {
+    pub fn params<T: DeserializeOwned>(&self) -> EResult<T> {
+        unpack(&self.params)
+    }
+}
+
+type MockReply = Result<Vec<u8>, Error>;
+
+#[derive(Default)]
+struct Replies {
+    once: VecDeque<MockReply>,
+    always: Option<MockReply>,
+}
+
+/// Bus RPC client mock. Calls to methods without scripted replies fail with "method not found"
+pub struct MockRpc {
+    client: Arc<tokio::sync::Mutex<MockClient>>,
+    replies: parking_lot::Mutex<HashMap<(String, String), Replies>>,
+    calls: parking_lot::Mutex<Vec<MockCall>>,
+}
+
+impl Default for MockRpc {
+    fn default() -> Self {
+        Self::new(MockClient::new(".test"))
+    }
+}
+
+impl MockRpc {
+    pub fn new(client: MockClient) -> Self {
+        Self {
+            client: Arc::new(tokio::sync::Mutex::new(client)),
+            replies: <_>::default(),
+            calls: <_>::default(),
+        }
+    }
+    #[inline]
+    pub fn mock_client(&self) -> Arc<tokio::sync::Mutex<MockClient>> {
+        self.client.clone()
+    }
+    fn script(&self, target: &str, method: &str, reply: MockReply, once: bool) {
+        let mut replies = self.replies.lock();
+        let entry = replies
+            .entry((target.to_owned(), method.to_owned()))
+            .or_default();
+        if once {
+            entry.once.push_back(reply);
+        } else {
+            entry.always = Some(reply);
+        }
+    }
+    /// Sets a permanent reply for the method
+    pub fn respond<T: Serialize>(&self, target: &str, method: &str, reply: &T) -> EResult<()> {
+        self.script(target, method, Ok(pack(reply)?), false);
+        Ok(())
+    }
+    /// Queues a single reply for the method, single replies are used before the permanent one
+    pub fn respond_once<T: Serialize>(&self, target: &str, method: &str, reply: &T) -> EResult<()> {
+        self.script(target, method, Ok(pack(reply)?), true);
+        Ok(())
+    }
+    /// Sets a permanent error reply for the method
+    pub fn respond_err(&self, target: &str, method: &str, err: Error) {
+        self.script(target, method, Err(err), false);
+    }
+    /// Queues a single error reply for the method
+    pub fn respond_err_once(&self, target: &str, method: &str, err: Error) {
+        self.script(target, method, Err(err), true);
+    }
+    pub fn calls(&self) -> Vec<MockCall> {
+        self.calls.lock().clone()
+    }
+    pub fn calls_to(&self, target: &str, method: &str) -> Vec<MockCall> {
+        self.calls
+            .lock()
+            .iter()
+            .filter(|c| c.target == target && c.method == method)
+            .cloned()
+            .collect()
+    }
+    pub fn clear_calls(&self) {
+        self.calls.lock().clear();
+    }
+    fn record(&self, target: &str, method: &str, params: &[u8], reply_required: bool) {
+        self.calls.lock().push(MockCall {
+            target: target.to_owned(),
+            method: method.to_owned(),
+            params: params.to_vec(),
+            reply_required,
+        });
+    }
+    fn reply(&self, target: &str, method: &str) -> Option<MockReply> {
+        let mut replies = self.replies.lock();
+        let entry = replies.get_mut(&(target.to_owned(), method.to_owned()))?;
+        entry.once.pop_front().or_else(|| entry.always.clone())
+    }
+}
+
+#[async_trait]
+impl Rpc for MockRpc {
+    fn client(&self) -> Arc<tokio::sync::Mutex<dyn AsyncClient + 'static>> {
+        self.client.clone()
+    }
+    async fn notify(
+        &self,
+        target: &str,
+        data: Cow<'async_trait>,
+        _qos: QoS,
+    ) -> Result<OpConfirm, busrt::Error> {
+        self.record(target, "", data.as_slice(), false);
+        Ok(None)
+    }
+    async fn call0(
+        &self,
+        target: &str,
+        method: &str,
+        params: Cow<'async_trait>,
+        _qos: QoS,
+    ) -> Result<OpConfirm, busrt::Error> {
+        self.record(target, method, params.as_slice(), false);
+        Ok(None)
+    }
+    async fn call(
+        &self,
+        target: &str,
+        method: &str,
+        params: Cow<'async_trait>,
+        _qos: QoS,
+    ) -> Result<RpcEvent, RpcError> {
+        self.record(target, method, params.as_slice(), true);
+        match self.reply(target, method) {
+            Some(Ok(payload)) => {
+                // reply frame: type, call id (u32), payload
+                let mut buf = vec![RPC_REPLY, 0, 0, 0, 0];
+                buf.extend(payload);
+                let frame = FrameData::new(
+                    FrameKind::Message,
+                    Some(target.to_owned()),
+                    Some(target.to_owned()),
+                    None,
+                    None,
+                    buf,
+                    0,
+                    false,
+                );
+                RpcEvent::try_from(Arc::new(frame)).map_err(|e| Error::io(e).into())
+            }
+            Some(Err(e)) => Err(e.into()),
+            None => Err(RpcError::method(None)),
+        }
+    }
+    fn is_connected(&self) -> bool {
+        true
+    }
+}
+
+/// Records events, received on the subscribed topics
+pub struct EventCollector<T> {
+    events: Arc<parking_lot::Mutex<Vec<(String, T)>>>,
+    errors: Arc<atomic::AtomicUsize>,
+    fut: tokio::task::JoinHandle<()>,
+}
+
+impl<T> Drop for EventCollector<T> {
+    fn drop(&mut self) {
+        self.fut.abort();
+    }
+}
+
+impl<T> EventCollector<T>
+where
+    T: DeserializeOwned + Clone + Send + 'static,
+{
+    /// Subscribes the client to the topics and takes its event channel. Frames, which can not be
+    /// decoded as T are counted as errors
+    pub async fn subscribe<C>(client: &mut C, topics: &[&str]) -> EResult<Self>
+    where
+        C: AsyncClient + ?Sized,
+    {
+        let rx = client
+            .take_event_channel()
+            .ok_or_else(|| Error::core("the client event channel is already taken"))?;
+        client.subscribe_bulk(topics, QoS::No).await?;
+        let events: Arc<parking_lot::Mutex<Vec<(String, T)>>> = <_>::default();
+        let errors: Arc<atomic::AtomicUsize> = <_>::default();
+        let c_events = events.clone();
+        let c_errors = errors.clone();
+        let fut = tokio::spawn(async move {
+            while let Ok(frame) = rx.recv().await {
+                let topic = frame.topic().unwrap_or_default().to_owned();
+                if let Ok(event) = unpack::<T>(frame.payload()) {
+                    c_events.lock().push((topic, event));
+                } else {
+                    c_errors.fetch_add(1, atomic::Ordering::SeqCst);
+                }
+            }
+        });
+        Ok(Self {
+            events,
+            errors,
+            fut,
+        })
+    }
+    /// Recorded events (topic, event)
+    pub fn events(&self) -> Vec<(String, T)> {
+        self.events.lock().clone()
+    }
+    pub fn events_for(&self, topic: &str) -> Vec<T> {
+        self.events
+            .lock()
+            .iter()
+            .filter(|(t, _)| t == topic)
+            .map(|(_, e)| e.clone())
+            .collect()
+    }
+    pub fn len(&self) -> usize {
+        self.events.lock().len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.events.lock().is_empty()
+    }
+    /// Number of frames, failed to decode
+    pub fn errors(&self) -> usize {
+        self.errors.load(atomic::Ordering::SeqCst)
+    }
+    pub fn clear(&self) {
+        self.events.lock().clear();
+    }
+    /// Waits until at least the specified number of events is recorded
+    pub async fn wait_for(&self, count: usize, timeout: Duration) -> EResult<Vec<(String, T)>> {
+        let op_start = Instant::now();
+        loop {
+            if self.len() >= count {
+                return Ok(self.events());
+            }
+            if op_start.elapsed() >= timeout {
+                return Err(Error::timeout());
+            }
+            tokio::time::sleep(WAIT_STEP).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{topic_matches, EventCollector, MockClient, MockRpc};
+    use crate::events::{RawStateEventOwned, RAW_STATE_TOPIC};
+    use crate::payload::{pack, unpack};
+    use crate::value::{Value, ValueOptionOwned};
+    use crate::{Error, ErrorKind};
+    use busrt::client::AsyncClient;
+    use busrt::rpc::Rpc;
+    use busrt::QoS;
+    use std::time::Duration;
+
+    #[test]
+    fn test_testkit() {
+        assert!(topic_matches("RAW/#", "RAW/sensor/tests/s1"));
+        assert!(topic_matches("RAW/+/tests/s1", "RAW/sensor/tests/s1"));
+        assert!(!topic_matches("RAW/+", "RAW/sensor/tests/s1"));
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(testkit());
+    }
+
+    async fn testkit() {
+        let rpc = MockRpc::default();
+        rpc.respond("eva.core", "test", &Value::U8(1)).unwrap();
+        rpc.respond_once("eva.core", "test", &Value::U8(2)).unwrap();
+        rpc.respond_err("eva.core", "item.state", Error::not_found("no items"));
+        let params = pack(&Value::String("x".to_owned())).unwrap();
+        for expected in [2, 1, 1] {
+            let ev = rpc
+                .call("eva.core", "test", params.as_slice().into(), QoS::Processed)
+                .await
+                .unwrap();
+            assert_eq!(unpack::<Value>(ev.payload()).unwrap(), Value::U8(expected));
+        }
+        let err: Error = rpc
+            .call("eva.core", "item.state", (&[][..]).into(), QoS::Processed)
+            .await
+            .unwrap_err()
+            .into();
+        assert_eq!(err.kind(), ErrorKind::ResourceNotFound);
+        assert!(rpc
+            .call("eva.core", "unknown", (&[][..]).into(), QoS::Processed)
+            .await
+            .is_err());
+        let calls = rpc.calls_to("eva.core", "test");
+        assert_eq!(calls.len(), 3);
+        assert_eq!(
+            calls[0].params::<Value>().unwrap(),
+            Value::String("x".to_owned())
+        );
+        assert_eq!(rpc.calls().len(), 5);
+        let mut client = MockClient::new("test");
+        let collector: EventCollector<RawStateEventOwned> =
+            EventCollector::subscribe(&mut client, &[&format!("{}#", RAW_STATE_TOPIC)])
+                .await
+                .unwrap();
+        let topic = format!("{}sensor/tests/s1", RAW_STATE_TOPIC);
+        client
+            .inject_event(&topic, &RawStateEventOwned::new(1, Value::U8(10)))
+            .unwrap();
+        client
+            .publish(
+                &topic,
+                pack(&RawStateEventOwned::new0(-1)).unwrap().into(),
+                QoS::No,
+            )
+            .await
+            .unwrap();
+        client.inject("UNSUBSCRIBED/topic", "x", vec![]).unwrap();
+        client.inject(&topic, "x", vec![0xc1]).unwrap();
+        let events = collector.wait_for(2, Duration::from_secs(1)).await.unwrap();
+        assert!(matches!(
+            events[0].1.value,
+            ValueOptionOwned::Value(Value::U8(10))
+        ));
+        assert_eq!(collector.events_for(&topic)[1].status, -1);
+        assert!(collector
+            .wait_for(3, Duration::from_millis(50))
+            .await
+            .is_err());
+        assert_eq!(collector.errors(), 1);
+        assert_eq!(client.sent().lock().len(), 1);
+    }
+}
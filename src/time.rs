@@ -5,7 +5,7 @@ use std::str::FromStr;
 use std::time::{Duration, Instant};
 use std::time::{SystemTime, UNIX_EPOCH};
 
-#[cfg(target_os = "windows")]
+#[cfg(not(any(unix, target_arch = "wasm32")))]
 static STARTED_AT: once_cell::sync::Lazy<Instant> = once_cell::sync::Lazy::new(|| Instant::now());
 
 pub fn serialize_time_now<S>(_value: &(), serializer: S) -> Result<S::Ok, S::Error>
@@ -151,9 +151,9 @@ impl Time {
     /// # Panics
     ///
     /// Will panic if the system real-time clock is not available
-    /// Will panic on Windows if the clock is set before 1.1.1970
+    /// Will panic on non-unix platforms if the clock is set before 1.1.1970
     #[allow(clippy::cast_sign_loss)]
-    #[cfg(not(target_os = "windows"))]
+    #[cfg(unix)]
     #[inline]
     pub fn now() -> Self {
         let t = nix::time::clock_gettime(nix::time::ClockId::CLOCK_REALTIME).unwrap();
@@ -162,20 +162,28 @@ impl Time {
             nsec: t.tv_nsec() as u64,
         }
     }
-    #[cfg(target_os = "windows")]
+    #[cfg(not(any(unix, target_arch = "wasm32")))]
     #[inline]
     pub fn now() -> Self {
         let t = SystemTime::now();
         t.try_into().unwrap()
     }
-    /// On Windows returns time since the first access
+    /// On wasm32 the time is taken from JavaScript Date, which has millisecond precision
+    #[allow(clippy::cast_sign_loss, clippy::cast_possible_truncation)]
+    #[cfg(target_arch = "wasm32")]
+    #[inline]
+    pub fn now() -> Self {
+        Self::from_timestamp_ms(js_sys::Date::now() as u64)
+    }
+    /// On Windows and other non-unix platforms returns time since the first access, on wasm32 returns time since the
+    /// page/worker start (performance.now()) if available, or the real time otherwise
     ///
     /// # Panics
     ///
     /// Will panic if the system monotonic clock is not available
     #[inline]
     #[allow(clippy::cast_sign_loss)]
-    #[cfg(not(target_os = "windows"))]
+    #[cfg(unix)]
     pub fn now_monotonic() -> Self {
         let t = nix::time::clock_gettime(nix::time::ClockId::CLOCK_MONOTONIC).unwrap();
         Self {
@@ -183,11 +191,26 @@ impl Time {
             nsec: t.tv_nsec() as u64,
         }
     }
-    #[cfg(target_os = "windows")]
+    #[cfg(not(any(unix, target_arch = "wasm32")))]
     #[inline]
     pub fn now_monotonic() -> Self {
         STARTED_AT.elapsed().into()
     }
+    #[allow(clippy::cast_sign_loss, clippy::cast_possible_truncation)]
+    #[cfg(target_arch = "wasm32")]
+    pub fn now_monotonic() -> Self {
+        use js_sys::{Function, Reflect};
+        use wasm_bindgen::JsCast;
+        let ms = Reflect::get(&js_sys::global(), &"performance".into())
+            .ok()
+            .filter(|perf| perf.is_object())
+            .and_then(|perf| {
+                let now = Reflect::get(&perf, &"now".into()).ok()?;
+                now.dyn_ref::<Function>()?.call0(&perf).ok()?.as_f64()
+            })
+            .unwrap_or_else(js_sys::Date::now);
+        Self::from_timestamp_ns((ms * 1_000_000.0) as u64)
+    }
     #[inline]
     pub fn from_timestamp_ns(timestamp_ns: u64) -> Self {
         Self {
@@ -370,6 +393,140 @@ impl core::ops::Sub<Duration> for Time {
     }
 }
 
+/// Event time, captured from both the real-time and the monotonic clocks
+///
+/// Durations between hybrid times are calculated from the monotonic parts, so they are not
+/// affected by wall clock steps (e.g. NTP corrections). Monotonic parts are comparable only for
+/// times, captured on the same host since the same boot. Times may carry a boot id (e.g. the
+/// core boot id), times with different non-zero boot ids are never compared. Zero boot id means
+/// unknown.
+///
+/// Serialized as a map: "t" - real time (f64), "mt" - monotonic time (u64, nanoseconds), "b" -
+/// boot id (u64, optional)
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct HybridTime {
+    #[serde(rename = "t")]
+    real: Time,
+    #[serde(rename = "mt")]
+    monotonic_ns: u64,
+    #[serde(rename = "b", default, skip_serializing_if = "is_zero")]
+    boot_id: u64,
+}
+
+#[allow(clippy::trivially_copy_pass_by_ref)]
+#[inline]
+fn is_zero(val: &u64) -> bool {
+    *val == 0
+}
+
+impl HybridTime {
+    #[inline]
+    pub fn new(real: Time, monotonic: Time) -> Self {
+        Self {
+            real,
+            monotonic_ns: monotonic.timestamp_ns(),
+            boot_id: 0,
+        }
+    }
+    /// # Panics
+    ///
+    /// Will panic if the system real-time or monotonic clock is not available
+    #[inline]
+    pub fn now() -> Self {
+        Self::new(Time::now(), Time::now_monotonic())
+    }
+    #[inline]
+    pub fn with_boot_id(mut self, boot_id: u64) -> Self {
+        self.boot_id = boot_id;
+        self
+    }
+    #[inline]
+    pub fn real(&self) -> Time {
+        self.real
+    }
+    #[inline]
+    pub fn monotonic(&self) -> Time {
+        Time::from_timestamp_ns(self.monotonic_ns)
+    }
+    #[inline]
+    pub fn boot_id(&self) -> u64 {
+        self.boot_id
+    }
+    /// Returns false if the times have been captured since different boots
+    #[inline]
+    pub fn is_comparable(&self, other: &HybridTime) -> bool {
+        self.boot_id == 0 || other.boot_id == 0 || self.boot_id == other.boot_id
+    }
+    /// Duration since an earlier time, zero if the earlier time is actually later or the times
+    /// are not comparable
+    #[inline]
+    pub fn duration_since(&self, earlier: &HybridTime) -> Duration {
+        self.checked_duration_since(earlier).unwrap_or_default()
+    }
+    /// Duration since an earlier time, None if the earlier time is actually later or the times
+    /// are not comparable
+    #[inline]
+    pub fn checked_duration_since(&self, earlier: &HybridTime) -> Option<Duration> {
+        if !self.is_comparable(earlier) {
+            return None;
+        }
+        self.monotonic_ns
+            .checked_sub(earlier.monotonic_ns)
+            .map(Duration::from_nanos)
+    }
+    /// # Panics
+    ///
+    /// Will panic if the system monotonic clock is not available
+    #[inline]
+    pub fn elapsed(&self) -> Duration {
+        Self::now().duration_since(self)
+    }
+    /// Real time of a later event, estimated from the monotonic clock, immune to wall clock
+    /// steps between the events. None if the times are not comparable or the result is out of
+    /// range
+    pub fn project(&self, later: &HybridTime) -> Option<Time> {
+        if !self.is_comparable(later) {
+            return None;
+        }
+        let real_ns = self.real.timestamp_ns();
+        let t_ns = if later.monotonic_ns >= self.monotonic_ns {
+            real_ns.checked_add(later.monotonic_ns - self.monotonic_ns)?
+        } else {
+            real_ns.checked_sub(self.monotonic_ns - later.monotonic_ns)?
+        };
+        Some(Time::from_timestamp_ns(t_ns))
+    }
+    /// How far (in seconds) the wall clock has been stepped between the events, positive if
+    /// forward. None if the times are not comparable
+    #[allow(clippy::cast_precision_loss)]
+    pub fn wall_clock_step(&self, later: &HybridTime) -> Option<f64> {
+        self.project(later).map(|projected| {
+            (later.real.timestamp_ns() as f64 - projected.timestamp_ns() as f64) / 1_000_000_000.0
+        })
+    }
+}
+
+impl From<HybridTime> for Time {
+    #[inline]
+    fn from(t: HybridTime) -> Self {
+        t.real
+    }
+}
+
+/// # Panics
+///
+/// Will panic if duration in nanoseconds > u64::MAX
+impl core::ops::Add<Duration> for HybridTime {
+    type Output = HybridTime;
+    fn add(self, dur: Duration) -> HybridTime {
+        HybridTime {
+            real: self.real + dur,
+            monotonic_ns: self.monotonic_ns + u64::try_from(dur.as_nanos()).unwrap(),
+            boot_id: self.boot_id,
+        }
+    }
+}
+
 mod convert_chrono {
     use super::Time;
     use crate::{EResult, Error};
@@ -444,6 +601,258 @@ mod convert_chrono {
     }
 }
 
+const DAY_SEC: u32 = 86_400;
+const NS_IN_SEC: i64 = 1_000_000_000;
+
+fn parse_day_time(s: &str) -> EResult<u32> {
+    let err = || Error::invalid_data(format!("invalid time of day: {}", s));
+    let mut chunks = s.trim().split(':');
+    let mut sec = 0;
+    for (i, mul) in [3600, 60, 1].into_iter().enumerate() {
+        let Some(chunk) = chunks.next() else {
+            if i < 2 {
+                return Err(err());
+            }
+            break;
+        };
+        let v: u32 = chunk.parse().map_err(|_| err())?;
+        if (i > 0 && v > 59) || v > 24 {
+            return Err(err());
+        }
+        sec += v * mul;
+    }
+    if chunks.next().is_some() || sec > DAY_SEC {
+        return Err(err());
+    }
+    Ok(sec)
+}
+
+fn format_day_time(sec: u32) -> String {
+    let (h, m, s) = (sec / 3600, sec % 3600 / 60, sec % 60);
+    if s == 0 {
+        format!("{:02}:{:02}", h, m)
+    } else {
+        format!("{:02}:{:02}:{:02}", h, m, s)
+    }
+}
+
+mod day_time {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    #[allow(clippy::trivially_copy_pass_by_ref)]
+    pub fn serialize<S>(value: &u32, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&super::format_day_time(*value))
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<u32, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        super::parse_day_time(&s).map_err(serde::de::Error::custom)
+    }
+}
+
+/// A recurring daily time window in local time, e.g. 07:00-22:00 on working days
+///
+/// Windows are half-open [start, end): a point exactly at the end belongs to the next window.
+/// An end before the start wraps over midnight (the window belongs to the day it starts at),
+/// equal start and end cover the whole day. Days are ISO weekdays (Monday = 1), all days if
+/// not set. The local time is the system one, unless a fixed UTC offset is specified
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TimeWindow {
+    #[serde(with = "day_time")]
+    start: u32,
+    #[serde(with = "day_time")]
+    end: u32,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    days: Vec<u8>,
+    /// seconds east of UTC
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    utc_offset: Option<i32>,
+}
+
+impl TimeWindow {
+    /// Creates a window from "HH:MM[:SS]" times of day, the end may be "24:00"
+    pub fn new(start: &str, end: &str) -> EResult<Self> {
+        let start = parse_day_time(start)?;
+        if start == DAY_SEC {
+            return Err(Error::invalid_data("the window can not start at 24:00"));
+        }
+        Ok(Self {
+            start,
+            end: parse_day_time(end)?,
+            days: Vec::new(),
+            utc_offset: None,
+        })
+    }
+    /// Restricts the window to ISO weekdays (Monday = 1)
+    pub fn days(mut self, days: &[u8]) -> EResult<Self> {
+        if let Some(day) = days.iter().find(|d| !(1..=7).contains(*d)) {
+            return Err(Error::invalid_data(format!("invalid weekday: {}", day)));
+        }
+        self.days = days.to_vec();
+        Ok(self)
+    }
+    /// Uses a fixed UTC offset (seconds east of UTC) instead of the system local time
+    #[inline]
+    pub fn utc_offset(mut self, offset: i32) -> Self {
+        self.utc_offset = Some(offset);
+        self
+    }
+    fn offset_ns(&self, t: Time) -> i64 {
+        let offset = self.utc_offset.unwrap_or_else(|| {
+            chrono::DateTime::<chrono::Local>::try_from(t)
+                .map_or(0, |dt| chrono::Offset::fix(dt.offset()).local_minus_utc())
+        });
+        i64::from(offset) * NS_IN_SEC
+    }
+    fn day_allowed(&self, day: i64) -> bool {
+        // 1970-01-01 is Thursday
+        let weekday = (day + 3).rem_euclid(7) + 1;
+        self.days.is_empty() || self.days.iter().any(|d| i64::from(*d) == weekday)
+    }
+    /// Returns true if the time is inside the window
+    pub fn contains(&self, t: Time) -> bool {
+        let local = i64::try_from(t.timestamp_ns()).unwrap_or(i64::MAX) + self.offset_ns(t);
+        let day_ns = i64::from(DAY_SEC) * NS_IN_SEC;
+        let day = local.div_euclid(day_ns);
+        let sec = u32::try_from(local.rem_euclid(day_ns) / NS_IN_SEC).unwrap_or_default();
+        if self.start < self.end {
+            (self.start..self.end).contains(&sec) && self.day_allowed(day)
+        } else if self.start == self.end || sec >= self.start {
+            self.day_allowed(day)
+        } else {
+            sec < self.end && self.day_allowed(day - 1)
+        }
+    }
+    /// Returns the nearest time after the given one, when the window may be entered or left
+    pub fn next_boundary(&self, t: Time) -> Time {
+        let offset = self.offset_ns(t);
+        let local = i64::try_from(t.timestamp_ns()).unwrap_or(i64::MAX) + offset;
+        let day_ns = i64::from(DAY_SEC) * NS_IN_SEC;
+        let day_start = local - local.rem_euclid(day_ns);
+        let next = [self.start, self.end, DAY_SEC]
+            .into_iter()
+            .map(|sec| day_start + i64::from(sec) * NS_IN_SEC)
+            .filter(|b| *b > local)
+            .min()
+            .unwrap_or(day_start + day_ns);
+        Time::from_timestamp_ns(u64::try_from(next - offset).unwrap_or_default())
+    }
+}
+
+/// Ticker behavior when ticks are missed (the consumer is late or the wall clock is stepped
+/// forward)
+#[cfg(feature = "time-ticker")]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub enum MissedTicks {
+    /// Fire all missed ticks immediately, one by one
+    Burst,
+    /// Fire the latest missed tick only
+    #[default]
+    Skip,
+}
+
+/// Async ticker, which produces ticks aligned to wall-clock boundaries, e.g. every minute at :00
+///
+/// Unlike [`tokio::time::interval`], the ticker does not accumulate drift: the sleep time is
+/// re-calculated from the real-time clock, so wall clock steps are corrected as well
+#[cfg(feature = "time-ticker")]
+#[derive(Debug, Clone)]
+pub struct Ticker {
+    period_ns: u64,
+    offset_ns: u64,
+    missed: MissedTicks,
+    next_ns: Option<u64>,
+}
+
+#[cfg(feature = "time-ticker")]
+impl Ticker {
+    /// Max sleep step, to notice wall clock steps while waiting
+    const MAX_SLEEP: Duration = Duration::from_secs(1);
+    /// Creates a ticker, aligned to multiples of the period since UNIX epoch
+    ///
+    /// # Panics
+    ///
+    /// Will panic if the period is zero or does not fit into u64 nanoseconds
+    pub fn new(period: Duration) -> Self {
+        let period_ns = u64::try_from(period.as_nanos()).unwrap();
+        assert!(period_ns > 0, "ticker period must be non-zero");
+        Self {
+            period_ns,
+            offset_ns: 0,
+            missed: MissedTicks::default(),
+            next_ns: None,
+        }
+    }
+    /// Shifts the tick boundaries, e.g. a minute ticker with 5s offset fires at :05
+    ///
+    /// # Panics
+    ///
+    /// Will panic if the offset does not fit into u64 nanoseconds
+    pub fn offset(mut self, offset: Duration) -> Self {
+        self.offset_ns = u64::try_from(offset.as_nanos()).unwrap() % self.period_ns;
+        self
+    }
+    pub fn missed_ticks(mut self, missed: MissedTicks) -> Self {
+        self.missed = missed;
+        self
+    }
+    #[inline]
+    pub fn period(&self) -> Duration {
+        Duration::from_nanos(self.period_ns)
+    }
+    /// The latest boundary, which is not after the given time
+    fn floor(&self, t_ns: u64) -> u64 {
+        let shifted = t_ns.saturating_sub(self.offset_ns);
+        shifted - shifted % self.period_ns + self.offset_ns
+    }
+    /// The earliest boundary, which is not before the given time
+    fn ceil(&self, t_ns: u64) -> u64 {
+        let floor = self.floor(t_ns);
+        if floor >= t_ns {
+            floor
+        } else {
+            floor + self.period_ns
+        }
+    }
+    /// Waits for the next tick and returns its scheduled (aligned) time. The first tick fires at
+    /// the nearest boundary
+    pub async fn next(&mut self) -> Time {
+        let mut next = match self.next_ns {
+            Some(v) => v,
+            None => self.ceil(Time::now().timestamp_ns()),
+        };
+        let now = loop {
+            let now = Time::now().timestamp_ns();
+            if now >= next {
+                break now;
+            }
+            if next - now > self.period_ns {
+                // the wall clock has been stepped back
+                next = self.ceil(now);
+                continue;
+            }
+            tokio::time::sleep(Duration::from_nanos(next - now).min(Self::MAX_SLEEP)).await;
+        };
+        let tick = match self.missed {
+            MissedTicks::Burst => next,
+            MissedTicks::Skip => self.floor(now),
+        };
+        self.next_ns = Some(tick + self.period_ns);
+        Time::from_timestamp_ns(tick)
+    }
+    /// Resets the ticker, the next tick fires at the nearest boundary
+    pub fn reset(&mut self) {
+        self.next_ns = None;
+    }
+}
+
 /// Get monotonic time in seconds
 ///
 /// # Panics
@@ -510,7 +919,8 @@ pub fn ts_from_ns(ts: u64) -> f64 {
 #[cfg(test)]
 #[allow(clippy::float_cmp)]
 mod tests {
-    use super::Time;
+    use super::{HybridTime, Time};
+    use std::time::Duration;
     #[test]
     fn test_time() {
         let timestamp = 1_632_093_707.189_334_9;
@@ -536,4 +946,93 @@ mod tests {
         assert_eq!(time.timestamp_us(), timestamp_millis * 1_000);
         assert_eq!(time.timestamp_ns(), timestamp_millis * 1_000_000);
     }
+    #[test]
+    fn test_hybrid_time() {
+        let start = HybridTime::new(
+            Time::from_timestamp(1_700_000_000.0),
+            Time::from_timestamp(100.0),
+        );
+        // the wall clock is stepped 1 hour back during 5 seconds
+        let end = HybridTime::new(
+            Time::from_timestamp(1_699_996_405.0),
+            Time::from_timestamp(105.0),
+        );
+        assert_eq!(end.duration_since(&start), Duration::from_secs(5));
+        assert_eq!(start.duration_since(&end), Duration::ZERO);
+        assert!(start.checked_duration_since(&end).is_none());
+        assert_eq!(start.project(&end).unwrap().timestamp(), 1_700_000_005.0);
+        assert_eq!(end.project(&start).unwrap().timestamp(), 1_699_996_400.0);
+        assert_eq!(start.wall_clock_step(&end), Some(-3600.0));
+        // the monotonic clock is far ahead of the real one
+        let early = HybridTime::new(Time::from_timestamp(10.0), Time::from_timestamp(1_000.0));
+        assert!(early.project(&start).is_none());
+        assert!(early.wall_clock_step(&start).is_none());
+        // different boots
+        let before_reboot = start.with_boot_id(1);
+        let after_reboot = end.with_boot_id(2);
+        assert!(!before_reboot.is_comparable(&after_reboot));
+        assert!(after_reboot
+            .checked_duration_since(&before_reboot)
+            .is_none());
+        assert!(before_reboot.project(&after_reboot).is_none());
+        assert_eq!(
+            end.with_boot_id(1).duration_since(&before_reboot),
+            Duration::from_secs(5)
+        );
+        assert_eq!(
+            (start + Duration::from_secs(5)).monotonic(),
+            end.monotonic()
+        );
+        let value = crate::value::to_value(end).unwrap();
+        assert_eq!(
+            value.jp_lookup("$.mt").unwrap(),
+            Some(&crate::value::Value::U64(105_000_000_000))
+        );
+        let t: HybridTime =
+            serde_json::from_str(r#"{"t":1699996405.0,"mt":105000000000}"#).unwrap();
+        assert_eq!(t, end);
+        assert!(value.jp_lookup("$.b").unwrap().is_none());
+        let t: HybridTime =
+            serde_json::from_str(r#"{"t":1699996405.0,"mt":105000000000,"b":2}"#).unwrap();
+        assert_eq!(t, after_reboot);
+        let now = HybridTime::now();
+        assert!(now.elapsed() < Duration::from_secs(1));
+        assert!(Time::from(now).timestamp() > 1_700_000_000.0);
+    }
+    #[cfg(feature = "time-ticker")]
+    #[test]
+    fn test_ticker() {
+        use super::{MissedTicks, Ticker};
+        let period = 20_000_000;
+        let ticker = Ticker::new(Duration::from_millis(20)).offset(Duration::from_millis(25));
+        assert_eq!(ticker.floor(1_000_000_000), 985_000_000);
+        assert_eq!(ticker.ceil(1_000_000_000), 1_005_000_000);
+        assert_eq!(ticker.ceil(1_005_000_000), 1_005_000_000);
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async move {
+                let mut ticker = ticker;
+                let first = ticker.next().await.timestamp_ns();
+                assert_eq!(first % period, 5_000_000);
+                let second = ticker.next().await.timestamp_ns();
+                assert_eq!(second - first, period);
+                assert!(Time::now().timestamp_ns() >= second);
+                // three ticks missed
+                let late = ticker.floor(Time::now().timestamp_ns()) - period * 3;
+                ticker.next_ns = Some(late);
+                let tick = ticker.next().await.timestamp_ns();
+                assert!(tick >= late + period * 3);
+                let mut ticker = ticker.missed_ticks(MissedTicks::Burst);
+                ticker.next_ns = Some(late);
+                for i in 0..3 {
+                    assert_eq!(ticker.next().await.timestamp_ns(), late + period * i);
+                }
+                // the wall clock stepped back
+                ticker.next_ns = Some(Time::now().timestamp_ns() + 3_600_000_000_000);
+                let tick = ticker.next().await.timestamp_ns();
+                assert!(tick < Time::now().timestamp_ns() + period);
+            });
+    }
 }
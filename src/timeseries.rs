@@ -0,0 +1,245 @@
+use crate::history::{Agg, Fill, GapFill, MAX_FILL_POINTS};
+use crate::time::Time;
+use crate::value::Value;
+use crate::{EResult, Error};
+
+/// Aggregates values. Mean, min, max and sum are calculated for numeric values only, None is
+/// returned if there are no values to aggregate
+#[allow(clippy::cast_precision_loss)]
+pub fn aggregate<'a, I>(values: I, agg: Agg) -> Option<Value>
+where
+    I: IntoIterator<Item = &'a Value>,
+{
+    let mut iter = values.into_iter();
+    match agg {
+        Agg::First => iter.next().cloned(),
+        Agg::Last => iter.last().cloned(),
+        Agg::Count => Some(Value::U64(iter.count() as u64)),
+        Agg::Mean | Agg::Min | Agg::Max | Agg::Sum => {
+            let mut count = 0_usize;
+            let mut acc: Option<f64> = None;
+            for v in iter.filter_map(|v| f64::try_from(v).ok()) {
+                count += 1;
+                acc = Some(if let Some(a) = acc {
+                    match agg {
+                        Agg::Min => a.min(v),
+                        Agg::Max => a.max(v),
+                        _ => a + v,
+                    }
+                } else {
+                    v
+                });
+            }
+            acc.map(|a| {
+                if agg == Agg::Mean {
+                    Value::F64(a / count as f64)
+                } else {
+                    Value::F64(a)
+                }
+            })
+        }
+    }
+}
+
+/// Splits the time range into fill windows and aggregates data points inside each one
+///
+/// Windows are half-open [start, start + interval), except the last one which includes t_end.
+/// The output points are labeled with the window start times. The data MUST be sorted by time,
+/// points before t_start are used to seed the previous-state gap filling only.
+///
+/// # Errors
+///
+/// Will return `Err` if the number of windows exceeds [`MAX_FILL_POINTS`]
+#[allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss,
+    clippy::cast_precision_loss
+)]
+pub fn downsample<I>(
+    data: I,
+    t_start: Time,
+    t_end: Time,
+    fill: Fill,
+    agg: Agg,
+    gaps: GapFill,
+) -> EResult<Vec<(Time, Value)>>
+where
+    I: IntoIterator<Item = (Time, Value)>,
+{
+    let start = t_start.timestamp();
+    let end = t_end.timestamp();
+    let interval = fill.as_secs_f64();
+    if end <= start {
+        return Ok(Vec::new());
+    }
+    let windows = ((end - start) / interval).ceil();
+    if windows.is_nan() || windows > MAX_FILL_POINTS as f64 {
+        return Err(Error::invalid_params(format!(
+            "too many fill intervals in the time range (max: {})",
+            MAX_FILL_POINTS
+        )));
+    }
+    let windows = windows as usize;
+    let mut result = Vec::new();
+    let mut data = data.into_iter().peekable();
+    let mut prev: Option<Value> = None;
+    while let Some((t, _)) = data.peek() {
+        if t.timestamp() >= start {
+            break;
+        }
+        prev = data.next().map(|(_, v)| v);
+    }
+    let mut window = Vec::new();
+    for k in 0..windows {
+        let ws = start + interval * k as f64;
+        let last = k == windows - 1;
+        let we = if last {
+            end
+        } else {
+            start + interval * (k + 1) as f64
+        };
+        while let Some((t, _)) = data.peek() {
+            let ts = t.timestamp();
+            if ts < we || (last && ts <= we) {
+                window.push(data.next().unwrap().1);
+            } else {
+                break;
+            }
+        }
+        let t = Time::from_timestamp(ws);
+        if window.is_empty() {
+            match gaps {
+                GapFill::Skip => {}
+                _ if agg == Agg::Count => result.push((t, Value::U64(0))),
+                GapFill::Null => result.push((t, Value::Unit)),
+                GapFill::Previous => result.push((t, prev.clone().unwrap_or_default())),
+            }
+        } else {
+            result.push((t, aggregate(&window, agg).unwrap_or_default()));
+            prev = window.pop();
+            window.clear();
+        }
+    }
+    Ok(result)
+}
+
+/// Largest-Triangle-Three-Buckets decimation, keeps the visual shape of a series for charting.
+/// The data MUST be sorted by x. If threshold is less than 3 or not less than the data length,
+/// the data is returned as-is
+#[allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss,
+    clippy::cast_precision_loss
+)]
+pub fn lttb(data: &[(f64, f64)], threshold: usize) -> Vec<(f64, f64)> {
+    if threshold >= data.len() || threshold < 3 {
+        return data.to_vec();
+    }
+    let mut sampled = Vec::with_capacity(threshold);
+    let every = (data.len() - 2) as f64 / (threshold - 2) as f64;
+    let mut a = 0;
+    sampled.push(data[0]);
+    for i in 0..threshold - 2 {
+        // average point of the next bucket
+        let avg_start = ((i + 1) as f64 * every) as usize + 1;
+        let avg_end = (((i + 2) as f64 * every) as usize + 1).min(data.len());
+        let avg_len = (avg_end - avg_start) as f64;
+        let (avg_x, avg_y) = data[avg_start..avg_end]
+            .iter()
+            .fold((0.0, 0.0), |(sx, sy), (x, y)| (sx + x, sy + y));
+        let (avg_x, avg_y) = (avg_x / avg_len, avg_y / avg_len);
+        // the current bucket
+        let range_start = (i as f64 * every) as usize + 1;
+        let range_end = ((i + 1) as f64 * every) as usize + 1;
+        let (ax, ay) = data[a];
+        let mut max_area = -1.0;
+        let mut next_a = range_start;
+        for (n, (x, y)) in data.iter().enumerate().take(range_end).skip(range_start) {
+            let area = ((ax - avg_x) * (y - ay) - (ax - x) * (avg_y - ay)).abs();
+            if area > max_area {
+                max_area = area;
+                next_a = n;
+            }
+        }
+        sampled.push(data[next_a]);
+        a = next_a;
+    }
+    sampled.push(data[data.len() - 1]);
+    sampled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{aggregate, downsample, lttb};
+    use crate::history::{Agg, GapFill};
+    use crate::time::Time;
+    use crate::value::Value;
+
+    #[test]
+    fn test_aggregate() {
+        let values = vec![Value::U8(1), Value::F64(2.5), Value::Unit, Value::I32(-3)];
+        assert_eq!(aggregate(&values, Agg::Sum), Some(Value::F64(0.5)));
+        assert_eq!(aggregate(&values, Agg::Min), Some(Value::F64(-3.0)));
+        assert_eq!(aggregate(&values, Agg::Max), Some(Value::F64(2.5)));
+        assert_eq!(aggregate(&values, Agg::Count), Some(Value::U64(4)));
+        assert_eq!(aggregate(&values, Agg::Last), Some(Value::I32(-3)));
+        assert_eq!(aggregate(&[Value::Unit], Agg::Mean), None);
+    }
+
+    #[test]
+    fn test_downsample() {
+        let data: Vec<(Time, Value)> = [(5.0, 1), (10.0, 2), (19.9, 4), (20.0, 8), (40.0, 16)]
+            .into_iter()
+            .map(|(t, v)| (Time::from_timestamp(t), Value::U8(v)))
+            .collect();
+        let fill = "10S".parse().unwrap();
+        let res = downsample(
+            data.clone(),
+            Time::from_timestamp(10.0),
+            Time::from_timestamp(40.0),
+            fill,
+            Agg::Sum,
+            GapFill::Previous,
+        )
+        .unwrap();
+        let values: Vec<Value> = res.iter().map(|(_, v)| v.clone()).collect();
+        assert_eq!(
+            values,
+            vec![Value::F64(6.0), Value::F64(8.0), Value::F64(16.0)]
+        );
+        assert_eq!(res[0].0.timestamp_sec(), 10);
+        let res = downsample(
+            data.clone(),
+            Time::from_timestamp(0.0),
+            Time::from_timestamp(35.0),
+            fill,
+            Agg::Last,
+            GapFill::Previous,
+        )
+        .unwrap();
+        let values: Vec<Value> = res.into_iter().map(|(_, v)| v).collect();
+        assert_eq!(
+            values,
+            vec![Value::U8(1), Value::U8(4), Value::U8(8), Value::U8(8)]
+        );
+        assert!(downsample(
+            data,
+            Time::from_timestamp(0.0),
+            Time::from_timestamp(86_400.0),
+            "1e-300".parse().unwrap(),
+            Agg::Mean,
+            GapFill::Null,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_lttb() {
+        let data: Vec<(f64, f64)> = (0..100).map(|i| (f64::from(i), f64::from(i % 7))).collect();
+        let sampled = lttb(&data, 10);
+        assert_eq!(sampled.len(), 10);
+        assert_eq!(sampled[0], data[0]);
+        assert_eq!(sampled[9], data[99]);
+        assert_eq!(lttb(&data, 200).len(), 100);
+    }
+}
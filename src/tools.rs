@@ -1,5 +1,5 @@
-use crate::Error;
-use serde::{Deserialize, Deserializer, Serializer};
+use crate::{EResult, Error, ItemKind, OID};
+use serde::{de, Deserialize, Deserializer, Serializer};
 use std::str::FromStr;
 use std::sync::atomic;
 use std::sync::Arc;
@@ -20,6 +20,29 @@ pub fn arc_atomic_true() -> Arc<atomic::AtomicBool> {
     Arc::new(atomic::AtomicBool::new(true))
 }
 
+pub const SVC_ID_ALLOWED_SYMBOLS: &str = "_.-";
+
+/// Checks service id format: alphanumeric chunks separated with dots, symbols "_" and "-" are
+/// allowed as well
+pub fn check_svc_id(id: &str) -> EResult<()> {
+    if id.is_empty() {
+        return Err(Error::invalid_params("service id not specified"));
+    }
+    if let Some(c) = id
+        .chars()
+        .find(|c| !(c.is_alphanumeric() || SVC_ID_ALLOWED_SYMBOLS.contains(*c)))
+    {
+        return Err(Error::invalid_params(format!(
+            "invalid symbol in service id {}: {}",
+            id, c
+        )));
+    }
+    if id.split('.').any(str::is_empty) {
+        return Err(Error::invalid_params(format!("invalid service id: {}", id)));
+    }
+    Ok(())
+}
+
 #[derive(Debug)]
 pub enum SocketPath {
     Tcp(String),
@@ -197,6 +220,45 @@ where
     Ok(Arc::new(atomic::AtomicU64::new(val)))
 }
 
+/// Serializes a byte vector as bytes (msgpack bin) instead of a sequence
+pub fn serialize_bytes<S>(data: &[u8], serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.serialize_bytes(data)
+}
+
+/// Deserializes a byte vector from bytes or a sequence of integers
+pub fn deserialize_bytes<'de, D>(deserializer: D) -> Result<Vec<u8>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    struct BytesVisitor;
+    impl<'de> serde::de::Visitor<'de> for BytesVisitor {
+        type Value = Vec<u8>;
+        fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+            formatter.write_str("bytes")
+        }
+        fn visit_bytes<E>(self, v: &[u8]) -> Result<Self::Value, E> {
+            Ok(v.to_vec())
+        }
+        fn visit_byte_buf<E>(self, v: Vec<u8>) -> Result<Self::Value, E> {
+            Ok(v)
+        }
+        fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+        where
+            A: serde::de::SeqAccess<'de>,
+        {
+            let mut result = Vec::with_capacity(seq.size_hint().unwrap_or_default());
+            while let Some(b) = seq.next_element()? {
+                result.push(b);
+            }
+            Ok(result)
+        }
+    }
+    deserializer.deserialize_byte_buf(BytesVisitor)
+}
+
 pub fn serialize_duration_as_f64<S>(t: &Duration, s: S) -> Result<S::Ok, S::Error>
 where
     S: Serializer,
@@ -276,19 +338,27 @@ where
     Ok(Duration::from_nanos(u64::deserialize(deserializer)?))
 }
 
+/// # Errors
+///
+/// Will return `Err` if the value is negative, NaN or overflows [`Duration`]
 pub fn de_float_as_duration<'de, D>(deserializer: D) -> Result<Duration, D::Error>
 where
     D: Deserializer<'de>,
 {
-    Ok(Duration::from_secs_f64(f64::deserialize(deserializer)?))
+    Duration::try_from_secs_f64(f64::deserialize(deserializer)?).map_err(de::Error::custom)
 }
 
+/// # Errors
+///
+/// Will return `Err` if the value is negative, NaN or overflows [`Duration`]
 pub fn de_opt_float_as_duration<'de, D>(deserializer: D) -> Result<Option<Duration>, D::Error>
 where
     D: Deserializer<'de>,
 {
     let t: Option<f64> = Option::deserialize(deserializer)?;
-    Ok(t.map(Duration::from_secs_f64))
+    t.map(Duration::try_from_secs_f64)
+        .transpose()
+        .map_err(de::Error::custom)
 }
 
 #[allow(clippy::cast_possible_truncation)]
@@ -312,6 +382,51 @@ where
     Ok(t.map(|v| Duration::from_nanos((v * 1000.0) as u64)))
 }
 
+/// Deserializes an OID, accepting kinds unknown to this version (see [`OID::parse_lenient`])
+pub fn de_oid_lenient<'de, D>(deserializer: D) -> Result<OID, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    OID::parse_lenient(&s).map_err(de::Error::custom)
+}
+
+/// Deserializes a list of OIDs, accepting kinds unknown to this version
+pub fn de_oids_lenient<'de, D>(deserializer: D) -> Result<Vec<OID>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let oids: Vec<String> = Vec::deserialize(deserializer)?;
+    oids.iter()
+        .map(|s| OID::parse_lenient(s).map_err(de::Error::custom))
+        .collect()
+}
+
+/// Deserializes a list of OIDs, skipping ones of item kinds, unknown to this version (e.g. sent
+/// by newer peers), instead of failing the whole payload
+pub fn de_oids_skip_unknown_kinds<'de, D>(deserializer: D) -> Result<Vec<OID>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let oids: Vec<String> = Vec::deserialize(deserializer)?;
+    let mut result = Vec::with_capacity(oids.len());
+    for s in oids {
+        match s.parse::<OID>() {
+            Ok(oid) => result.push(oid),
+            Err(e) => {
+                if s.split_once(':')
+                    .is_some_and(|(kind, _)| kind.parse::<ItemKind>().is_err())
+                {
+                    log::warn!("skipping OID of unknown kind: {}", s);
+                } else {
+                    return Err(de::Error::custom(e));
+                }
+            }
+        }
+    }
+    Ok(result)
+}
+
 #[inline]
 pub fn default_true() -> bool {
     true
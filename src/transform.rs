@@ -16,7 +16,7 @@ use std::time::{Duration, Instant};
 use crate::value::{to_value, Value};
 use crate::{EResult, Error, OID};
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(deny_unknown_fields)]
 pub struct Task {
     func: Function,
@@ -233,7 +233,7 @@ impl_Transform_N!(u64, std::u64::MAX);
 impl_Transform_N!(f32, std::f32::MAX);
 impl_Transform_N!(f64, std::f64::MAX);
 
-#[derive(PartialEq, Eq, Clone, Copy, Debug, Deserialize)]
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Serialize, Deserialize)]
 pub enum Function {
     #[serde(rename = "multiply")]
     Multiply,
@@ -0,0 +1,363 @@
+//! Arena-allocated transient values
+//!
+//! [`ArenaValue`] trees are built in a [`ValueArena`] with bump allocation and are dropped all at
+//! once when the arena is reset or dropped, which cuts allocator pressure for short-lived data,
+//! e.g. parsed request bodies. Conversion to an owned [`Value`] is explicit, with
+//! [`ArenaValue::to_value()`].
+use crate::value::Value;
+use bumpalo::collections::Vec as BumpVec;
+use bumpalo::Bump;
+use serde::de::{DeserializeSeed, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::ser::{SerializeMap, SerializeSeq, Serializer};
+use serde::Serialize;
+use std::fmt;
+
+/// Max number of elements preallocated from a (possibly untrusted) sequence/map size hint
+const MAX_PREALLOC: usize = 4096;
+
+/// Borrowed value, allocated in a [`ValueArena`]. Maps keep the insertion order and are not
+/// checked for duplicate keys
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ArenaValue<'a> {
+    Bool(bool),
+
+    U8(u8),
+    U16(u16),
+    U32(u32),
+    U64(u64),
+
+    I8(i8),
+    I16(i16),
+    I32(i32),
+    I64(i64),
+
+    F32(f32),
+    F64(f64),
+
+    Char(char),
+    String(&'a str),
+
+    #[default]
+    Unit,
+    Option(Option<&'a ArenaValue<'a>>),
+    Newtype(&'a ArenaValue<'a>),
+    Seq(&'a [ArenaValue<'a>]),
+    Map(&'a [(ArenaValue<'a>, ArenaValue<'a>)]),
+    Bytes(&'a [u8]),
+}
+
+impl<'a> ArenaValue<'a> {
+    /// Converts the value into an owned one
+    pub fn to_value(&self) -> Value {
+        match *self {
+            ArenaValue::Bool(v) => Value::Bool(v),
+            ArenaValue::U8(v) => Value::U8(v),
+            ArenaValue::U16(v) => Value::U16(v),
+            ArenaValue::U32(v) => Value::U32(v),
+            ArenaValue::U64(v) => Value::U64(v),
+            ArenaValue::I8(v) => Value::I8(v),
+            ArenaValue::I16(v) => Value::I16(v),
+            ArenaValue::I32(v) => Value::I32(v),
+            ArenaValue::I64(v) => Value::I64(v),
+            ArenaValue::F32(v) => Value::F32(v),
+            ArenaValue::F64(v) => Value::F64(v),
+            ArenaValue::Char(v) => Value::Char(v),
+            ArenaValue::String(v) => Value::String(v.to_owned()),
+            ArenaValue::Unit => Value::Unit,
+            ArenaValue::Option(v) => Value::Option(v.map(|v| Box::new(v.to_value()))),
+            ArenaValue::Newtype(v) => Value::Newtype(Box::new(v.to_value())),
+            ArenaValue::Seq(v) => Value::Seq(v.iter().map(ArenaValue::to_value).collect()),
+            ArenaValue::Map(v) => Value::Map(
+                v.iter()
+                    .map(|(k, v)| (k.to_value(), v.to_value()))
+                    .collect(),
+            ),
+            ArenaValue::Bytes(v) => Value::Bytes(v.to_vec()),
+        }
+    }
+    #[inline]
+    pub fn is_unit(&self) -> bool {
+        *self == ArenaValue::Unit
+    }
+    #[inline]
+    pub fn as_str(&self) -> Option<&'a str> {
+        if let ArenaValue::String(s) = self {
+            Some(s)
+        } else {
+            None
+        }
+    }
+    /// Gets a map value by a string key (the last one if the key is duplicated)
+    pub fn get(&self, key: &str) -> Option<&'a ArenaValue<'a>> {
+        if let ArenaValue::Map(m) = self {
+            m.iter()
+                .rev()
+                .find(|(k, _)| k.as_str() == Some(key))
+                .map(|(_, v)| v)
+        } else {
+            None
+        }
+    }
+}
+
+impl Serialize for ArenaValue<'_> {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        match *self {
+            ArenaValue::Bool(v) => s.serialize_bool(v),
+            ArenaValue::U8(v) => s.serialize_u8(v),
+            ArenaValue::U16(v) => s.serialize_u16(v),
+            ArenaValue::U32(v) => s.serialize_u32(v),
+            ArenaValue::U64(v) => s.serialize_u64(v),
+            ArenaValue::I8(v) => s.serialize_i8(v),
+            ArenaValue::I16(v) => s.serialize_i16(v),
+            ArenaValue::I32(v) => s.serialize_i32(v),
+            ArenaValue::I64(v) => s.serialize_i64(v),
+            ArenaValue::F32(v) => s.serialize_f32(v),
+            ArenaValue::F64(v) => s.serialize_f64(v),
+            ArenaValue::Char(v) => s.serialize_char(v),
+            ArenaValue::String(v) => s.serialize_str(v),
+            ArenaValue::Unit => s.serialize_unit(),
+            ArenaValue::Option(None) => s.serialize_none(),
+            ArenaValue::Option(Some(v)) => s.serialize_some(v),
+            ArenaValue::Newtype(v) => s.serialize_newtype_struct("", v),
+            ArenaValue::Seq(v) => {
+                let mut seq = s.serialize_seq(Some(v.len()))?;
+                for el in v {
+                    seq.serialize_element(el)?;
+                }
+                seq.end()
+            }
+            ArenaValue::Map(v) => {
+                let mut map = s.serialize_map(Some(v.len()))?;
+                for (k, v) in v {
+                    map.serialize_entry(k, v)?;
+                }
+                map.end()
+            }
+            ArenaValue::Bytes(v) => s.serialize_bytes(v),
+        }
+    }
+}
+
+/// Bump allocation arena for [`ArenaValue`] trees
+#[derive(Default)]
+pub struct ValueArena {
+    bump: Bump,
+}
+
+impl ValueArena {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+    #[inline]
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            bump: Bump::with_capacity(capacity),
+        }
+    }
+    /// Total bytes allocated by the arena
+    #[inline]
+    pub fn allocated_bytes(&self) -> usize {
+        self.bump.allocated_bytes()
+    }
+    /// Frees all values at once, keeping the largest memory chunk for reuse
+    #[inline]
+    pub fn reset(&mut self) {
+        self.bump.reset();
+    }
+    /// Moves the value into the arena. Arena values contain no owned data, so nothing leaks
+    /// when the arena is reset
+    #[inline]
+    pub fn alloc<'a>(&'a self, value: ArenaValue<'a>) -> &'a ArenaValue<'a> {
+        self.bump.alloc(value)
+    }
+    #[inline]
+    pub fn alloc_str(&self, s: &str) -> &str {
+        self.bump.alloc_str(s)
+    }
+    pub fn string(&self, s: &str) -> ArenaValue<'_> {
+        ArenaValue::String(self.bump.alloc_str(s))
+    }
+    pub fn bytes(&self, data: &[u8]) -> ArenaValue<'_> {
+        ArenaValue::Bytes(self.bump.alloc_slice_copy(data))
+    }
+    pub fn seq<'a, I>(&'a self, values: I) -> ArenaValue<'a>
+    where
+        I: IntoIterator<Item = ArenaValue<'a>>,
+    {
+        let mut v = BumpVec::new_in(&self.bump);
+        v.extend(values);
+        ArenaValue::Seq(v.into_bump_slice())
+    }
+    pub fn map<'a, I>(&'a self, values: I) -> ArenaValue<'a>
+    where
+        I: IntoIterator<Item = (ArenaValue<'a>, ArenaValue<'a>)>,
+    {
+        let mut v = BumpVec::new_in(&self.bump);
+        v.extend(values);
+        ArenaValue::Map(v.into_bump_slice())
+    }
+    /// Copies an owned value into the arena
+    pub fn from_value(&self, value: &Value) -> ArenaValue<'_> {
+        match value {
+            Value::Bool(v) => ArenaValue::Bool(*v),
+            Value::U8(v) => ArenaValue::U8(*v),
+            Value::U16(v) => ArenaValue::U16(*v),
+            Value::U32(v) => ArenaValue::U32(*v),
+            Value::U64(v) => ArenaValue::U64(*v),
+            Value::I8(v) => ArenaValue::I8(*v),
+            Value::I16(v) => ArenaValue::I16(*v),
+            Value::I32(v) => ArenaValue::I32(*v),
+            Value::I64(v) => ArenaValue::I64(*v),
+            Value::F32(v) => ArenaValue::F32(*v),
+            Value::F64(v) => ArenaValue::F64(*v),
+            Value::Char(v) => ArenaValue::Char(*v),
+            Value::String(v) => self.string(v),
+            Value::Unit => ArenaValue::Unit,
+            Value::Option(v) => {
+                ArenaValue::Option(v.as_ref().map(|v| self.alloc(self.from_value(v))))
+            }
+            Value::Newtype(v) => ArenaValue::Newtype(self.alloc(self.from_value(v))),
+            Value::Seq(v) => self.seq(v.iter().map(|v| self.from_value(v))),
+            Value::Map(v) => self.map(
+                v.iter()
+                    .map(|(k, v)| (self.from_value(k), self.from_value(v))),
+            ),
+            Value::Bytes(v) => self.bytes(v),
+        }
+    }
+    /// Deserializes a value into the arena
+    pub fn deserialize<'a, 'de, D>(&'a self, deserializer: D) -> Result<ArenaValue<'a>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        ArenaSeed { arena: self }.deserialize(deserializer)
+    }
+    /// Parses a JSON slice into the arena
+    pub fn from_json_slice(&self, data: &[u8]) -> crate::EResult<ArenaValue<'_>> {
+        let mut deserializer = serde_json::Deserializer::from_slice(data);
+        let value = self.deserialize(&mut deserializer)?;
+        deserializer.end()?;
+        Ok(value)
+    }
+}
+
+#[derive(Clone, Copy)]
+struct ArenaSeed<'a> {
+    arena: &'a ValueArena,
+}
+
+impl<'a, 'de> DeserializeSeed<'de> for ArenaSeed<'a> {
+    type Value = ArenaValue<'a>;
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'a, 'de> Visitor<'de> for ArenaSeed<'a> {
+    type Value = ArenaValue<'a>;
+
+    fn expecting(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str("any value")
+    }
+
+    fn visit_bool<E>(self, value: bool) -> Result<Self::Value, E> {
+        Ok(ArenaValue::Bool(value))
+    }
+
+    fn visit_i8<E>(self, value: i8) -> Result<Self::Value, E> {
+        Ok(ArenaValue::I8(value))
+    }
+
+    fn visit_i16<E>(self, value: i16) -> Result<Self::Value, E> {
+        Ok(ArenaValue::I16(value))
+    }
+
+    fn visit_i32<E>(self, value: i32) -> Result<Self::Value, E> {
+        Ok(ArenaValue::I32(value))
+    }
+
+    fn visit_i64<E>(self, value: i64) -> Result<Self::Value, E> {
+        Ok(ArenaValue::I64(value))
+    }
+
+    fn visit_u8<E>(self, value: u8) -> Result<Self::Value, E> {
+        Ok(ArenaValue::U8(value))
+    }
+
+    fn visit_u16<E>(self, value: u16) -> Result<Self::Value, E> {
+        Ok(ArenaValue::U16(value))
+    }
+
+    fn visit_u32<E>(self, value: u32) -> Result<Self::Value, E> {
+        Ok(ArenaValue::U32(value))
+    }
+
+    fn visit_u64<E>(self, value: u64) -> Result<Self::Value, E> {
+        Ok(ArenaValue::U64(value))
+    }
+
+    fn visit_f32<E>(self, value: f32) -> Result<Self::Value, E> {
+        Ok(ArenaValue::F32(value))
+    }
+
+    fn visit_f64<E>(self, value: f64) -> Result<Self::Value, E> {
+        Ok(ArenaValue::F64(value))
+    }
+
+    fn visit_char<E>(self, value: char) -> Result<Self::Value, E> {
+        Ok(ArenaValue::Char(value))
+    }
+
+    fn visit_str<E>(self, value: &str) -> Result<Self::Value, E> {
+        Ok(self.arena.string(value))
+    }
+
+    fn visit_unit<E>(self) -> Result<Self::Value, E> {
+        Ok(ArenaValue::Unit)
+    }
+
+    fn visit_none<E>(self) -> Result<Self::Value, E> {
+        Ok(ArenaValue::Option(None))
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, d: D) -> Result<Self::Value, D::Error> {
+        let v = self.deserialize(d)?;
+        Ok(ArenaValue::Option(Some(self.arena.alloc(v))))
+    }
+
+    fn visit_newtype_struct<D: Deserializer<'de>>(self, d: D) -> Result<Self::Value, D::Error> {
+        let v = self.deserialize(d)?;
+        Ok(ArenaValue::Newtype(self.arena.alloc(v)))
+    }
+
+    fn visit_seq<V: SeqAccess<'de>>(self, mut visitor: V) -> Result<Self::Value, V::Error> {
+        let mut values = BumpVec::with_capacity_in(
+            visitor.size_hint().unwrap_or_default().min(MAX_PREALLOC),
+            &self.arena.bump,
+        );
+        while let Some(elem) = visitor.next_element_seed(self)? {
+            values.push(elem);
+        }
+        Ok(ArenaValue::Seq(values.into_bump_slice()))
+    }
+
+    fn visit_map<V: MapAccess<'de>>(self, mut visitor: V) -> Result<Self::Value, V::Error> {
+        let mut values = BumpVec::with_capacity_in(
+            visitor.size_hint().unwrap_or_default().min(MAX_PREALLOC),
+            &self.arena.bump,
+        );
+        while let Some((key, value)) = visitor.next_entry_seed(self, self)? {
+            values.push((key, value));
+        }
+        Ok(ArenaValue::Map(values.into_bump_slice()))
+    }
+
+    fn visit_bytes<E>(self, v: &[u8]) -> Result<Self::Value, E> {
+        Ok(self.arena.bytes(v))
+    }
+}
@@ -0,0 +1,189 @@
+//! Canonical binary form of values for signing and hashing
+//!
+//! The encoding is type-tagged and length-prefixed (big-endian):
+//!
+//! * integers of all widths are encoded as u64 (non-negative) or i64 (negative)
+//!
+//! * floats are encoded as f64 bits, negative zero is normalized to zero, NaNs to the single
+//!   quiet NaN
+//!
+//! * chars are encoded as strings, options and newtypes are unwrapped
+//!
+//! * map entries are sorted by the encoded keys
+use super::Value;
+#[allow(unused_imports)]
+use crate::alloc_prelude::*;
+
+const TAG_UNIT: u8 = b'n';
+const TAG_TRUE: u8 = b't';
+const TAG_FALSE: u8 = b'f';
+const TAG_UINT: u8 = b'u';
+const TAG_INT: u8 = b'i';
+const TAG_FLOAT: u8 = b'd';
+const TAG_STRING: u8 = b's';
+const TAG_BYTES: u8 = b'b';
+const TAG_SEQ: u8 = b'a';
+const TAG_MAP: u8 = b'm';
+
+#[inline]
+fn write_len(buf: &mut Vec<u8>, len: usize) {
+    buf.extend((len as u64).to_be_bytes());
+}
+
+fn write_float(buf: &mut Vec<u8>, v: f64) {
+    let v = if v.is_nan() {
+        f64::NAN
+    } else if v == 0.0 {
+        0.0
+    } else {
+        v
+    };
+    buf.push(TAG_FLOAT);
+    buf.extend(v.to_bits().to_be_bytes());
+}
+
+fn write_int(buf: &mut Vec<u8>, v: i64) {
+    if let Ok(u) = u64::try_from(v) {
+        buf.push(TAG_UINT);
+        buf.extend(u.to_be_bytes());
+    } else {
+        buf.push(TAG_INT);
+        buf.extend(v.to_be_bytes());
+    }
+}
+
+fn write_str(buf: &mut Vec<u8>, tag: u8, s: &[u8]) {
+    buf.push(tag);
+    write_len(buf, s.len());
+    buf.extend(s);
+}
+
+fn write_value(buf: &mut Vec<u8>, value: &Value) {
+    match value {
+        Value::Unit | Value::Option(None) => buf.push(TAG_UNIT),
+        Value::Bool(v) => buf.push(if *v { TAG_TRUE } else { TAG_FALSE }),
+        Value::U8(v) => write_int(buf, i64::from(*v)),
+        Value::U16(v) => write_int(buf, i64::from(*v)),
+        Value::U32(v) => write_int(buf, i64::from(*v)),
+        Value::U64(v) => {
+            buf.push(TAG_UINT);
+            buf.extend(v.to_be_bytes());
+        }
+        Value::I8(v) => write_int(buf, i64::from(*v)),
+        Value::I16(v) => write_int(buf, i64::from(*v)),
+        Value::I32(v) => write_int(buf, i64::from(*v)),
+        Value::I64(v) => write_int(buf, *v),
+        Value::F32(v) => write_float(buf, f64::from(*v)),
+        Value::F64(v) => write_float(buf, *v),
+        Value::Char(v) => {
+            let mut b = [0; 4];
+            write_str(buf, TAG_STRING, v.encode_utf8(&mut b).as_bytes());
+        }
+        Value::String(v) => write_str(buf, TAG_STRING, v.as_bytes()),
+        Value::Bytes(v) => write_str(buf, TAG_BYTES, v),
+        Value::Option(Some(v)) | Value::Newtype(v) => write_value(buf, v),
+        Value::Seq(v) => {
+            buf.push(TAG_SEQ);
+            write_len(buf, v.len());
+            for val in v {
+                write_value(buf, val);
+            }
+        }
+        Value::Map(m) => {
+            let mut entries: Vec<(Vec<u8>, &Value)> = m
+                .iter()
+                .map(|(k, v)| {
+                    let mut kb = Vec::new();
+                    write_value(&mut kb, k);
+                    (kb, v)
+                })
+                .collect();
+            entries.sort_by(|a, b| a.0.cmp(&b.0));
+            buf.push(TAG_MAP);
+            write_len(buf, entries.len());
+            for (k, v) in entries {
+                buf.extend(k);
+                write_value(buf, v);
+            }
+        }
+    }
+}
+
+impl Value {
+    /// Returns the canonical binary form of the value: equal data always produces the same
+    /// bytes, regardless of integer widths, float formatting and map construction order
+    pub fn to_canonical_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        write_value(&mut buf, self);
+        buf
+    }
+    /// Signs the canonical form of the value with HMAC-SHA256
+    #[cfg(feature = "openssl")]
+    pub fn sign(&self, key: &[u8]) -> crate::EResult<Vec<u8>> {
+        let pkey = openssl::pkey::PKey::hmac(key)?;
+        let mut signer = openssl::sign::Signer::new(openssl::hash::MessageDigest::sha256(), &pkey)?;
+        signer.update(&self.to_canonical_bytes())?;
+        Ok(signer.sign_to_vec()?)
+    }
+    /// Verifies HMAC-SHA256 signature of the value (constant-time)
+    #[cfg(feature = "openssl")]
+    pub fn verify(&self, key: &[u8], signature: &[u8]) -> crate::EResult<bool> {
+        let expected = self.sign(key)?;
+        Ok(expected.len() == signature.len() && openssl::memcmp::eq(&expected, signature))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::value::Value;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_canonical_bytes() {
+        assert_eq!(
+            Value::U8(5).to_canonical_bytes(),
+            Value::I64(5).to_canonical_bytes()
+        );
+        assert_ne!(
+            Value::I8(-5).to_canonical_bytes(),
+            Value::U8(5).to_canonical_bytes()
+        );
+        assert_ne!(
+            Value::U8(1).to_canonical_bytes(),
+            Value::F64(1.0).to_canonical_bytes()
+        );
+        assert_eq!(
+            Value::F32(0.5).to_canonical_bytes(),
+            Value::F64(0.5).to_canonical_bytes()
+        );
+        assert_eq!(
+            Value::F64(-0.0).to_canonical_bytes(),
+            Value::F64(0.0).to_canonical_bytes()
+        );
+        assert_eq!(
+            Value::Char('x').to_canonical_bytes(),
+            Value::String("x".to_owned()).to_canonical_bytes()
+        );
+        assert_ne!(
+            Value::String("x".to_owned()).to_canonical_bytes(),
+            Value::Bytes(b"x".to_vec()).to_canonical_bytes()
+        );
+        let a: Value = serde_json::from_str(r#"{"b":[1,2.5,null],"a":{"y":1,"x":"z"}}"#).unwrap();
+        let mut inner = BTreeMap::new();
+        inner.insert(Value::String("x".to_owned()), Value::String("z".to_owned()));
+        inner.insert(Value::String("y".to_owned()), Value::I32(1));
+        let mut m = BTreeMap::new();
+        m.insert(
+            Value::String("b".to_owned()),
+            Value::Seq(vec![Value::U16(1), Value::F64(2.5), Value::Option(None)]),
+        );
+        m.insert(Value::String("a".to_owned()), Value::Map(inner));
+        let b = Value::Map(m);
+        assert_eq!(a.to_canonical_bytes(), b.to_canonical_bytes());
+        // nested seq length is a part of the form
+        assert_ne!(
+            Value::Seq(vec![Value::Seq(vec![]), Value::Unit]).to_canonical_bytes(),
+            Value::Seq(vec![Value::Seq(vec![Value::Unit])]).to_canonical_bytes()
+        );
+    }
+}
@@ -1,8 +1,11 @@
+#[allow(unused_imports)]
+use crate::alloc_prelude::*;
+use alloc::collections::BTreeMap;
+use core::fmt;
+use core::marker::PhantomData;
 use serde::{de, forward_to_deserialize_any};
-use std::collections::BTreeMap;
+#[cfg(feature = "std")]
 use std::error::Error;
-use std::fmt;
-use std::marker::PhantomData;
 
 use crate::Value;
 
@@ -148,12 +151,16 @@ impl DeserializerError {
     }
 }
 
+#[cfg(feature = "std")]
 impl Error for DeserializerError {
     fn description(&self) -> &str {
         "Value deserializer error"
     }
 }
 
+#[cfg(not(feature = "std"))]
+impl de::StdError for DeserializerError {}
+
 impl fmt::Display for DeserializerError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
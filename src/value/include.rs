@@ -0,0 +1,228 @@
+use super::{Value, XValueContext};
+use crate::{EResult, Error};
+use std::str::FromStr;
+
+pub const DEFAULT_MAX_INCLUDE_SIZE: usize = 1_048_576;
+pub const DEFAULT_MAX_DEPTH: usize = 8;
+
+struct Authority<'a> {
+    scheme: &'a str,
+    host: &'a str,
+    port: Option<u16>,
+}
+
+fn default_port(scheme: &str) -> Option<u16> {
+    match scheme {
+        "http" => Some(80),
+        "https" => Some(443),
+        _ => None,
+    }
+}
+
+fn parse_authority(url: &str) -> Option<Authority<'_>> {
+    let (scheme, rest) = url.split_once("://")?;
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    // strip user info, "https://allowed.com@other.com" points to other.com
+    let authority = authority.rsplit('@').next().unwrap_or_default();
+    let (host, port) = if let Some(v6) = authority.strip_prefix('[') {
+        let (host, rest) = v6.split_once(']')?;
+        (host, rest.strip_prefix(':'))
+    } else if let Some((host, port)) = authority.split_once(':') {
+        (host, Some(port))
+    } else {
+        (authority, None)
+    };
+    if host.is_empty() {
+        return None;
+    }
+    let port = if let Some(p) = port {
+        Some(p.parse().ok()?)
+    } else {
+        None
+    };
+    Some(Authority { scheme, host, port })
+}
+
+/// Allowed "^include-url" scheme, host (or "*.domain") and port
+#[derive(Debug, Clone)]
+pub struct UrlAllow {
+    scheme: String,
+    host: String,
+    port: Option<u16>,
+}
+
+impl FromStr for UrlAllow {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let a = parse_authority(s)
+            .ok_or_else(|| Error::invalid_params(format!("invalid URL allow entry: {}", s)))?;
+        let scheme = a.scheme.to_lowercase();
+        if default_port(&scheme).is_none() {
+            return Err(Error::invalid_params(format!(
+                "unsupported URL scheme: {}",
+                a.scheme
+            )));
+        }
+        Ok(Self {
+            port: a.port.or_else(|| default_port(&scheme)),
+            scheme,
+            host: a.host.to_lowercase(),
+        })
+    }
+}
+
+impl UrlAllow {
+    pub fn matches(&self, url: &str) -> bool {
+        let Some(a) = parse_authority(url) else {
+            return false;
+        };
+        let scheme = a.scheme.to_lowercase();
+        if scheme != self.scheme || a.port.or_else(|| default_port(&scheme)) != self.port {
+            return false;
+        }
+        let host = a.host.to_lowercase();
+        if let Some(domain) = self.host.strip_prefix("*.") {
+            host.strip_suffix(domain)
+                .is_some_and(|sub| sub.len() > 1 && sub.ends_with('.'))
+        } else {
+            host == self.host
+        }
+    }
+}
+
+#[cfg(feature = "extended-value-http")]
+pub async fn fetch_url(url: &str, op: &crate::op::Op, ctx: &XValueContext) -> EResult<Vec<u8>> {
+    use hyper::body::HttpBody as _;
+    if !ctx.is_url_allowed(url) {
+        return Err(Error::access(format!(
+            "xvalue include-url: access to {} denied",
+            url
+        )));
+    }
+    let uri: hyper::Uri = url.parse().map_err(Error::invalid_params)?;
+    let client = hyper::Client::builder().build::<_, hyper::Body>(hyper_tls::HttpsConnector::new());
+    let res = tokio::time::timeout(op.timeout()?, client.get(uri))
+        .await?
+        .map_err(Error::io)?;
+    if !res.status().is_success() {
+        return Err(Error::failed(format!(
+            "xvalue include-url {}: HTTP {}",
+            url,
+            res.status()
+        )));
+    }
+    let mut body = res.into_body();
+    let mut content = Vec::new();
+    while let Some(chunk) = tokio::time::timeout(op.timeout()?, body.data()).await? {
+        let chunk = chunk.map_err(Error::io)?;
+        if content.len() + chunk.len() > ctx.max_include_size {
+            return Err(Error::invalid_data(format!(
+                "xvalue include-url {}: document is larger than {} bytes",
+                url, ctx.max_include_size
+            )));
+        }
+        content.extend_from_slice(&chunk);
+    }
+    Ok(content)
+}
+
+#[cfg(not(feature = "extended-value-http"))]
+pub async fn fetch_url(_url: &str, _op: &crate::op::Op, _ctx: &XValueContext) -> EResult<Vec<u8>> {
+    Err(Error::unsupported(
+        "xvalue include-url: extended-value-http feature is not enabled",
+    ))
+}
+
+/// The key is relative to the registry root, e.g. "config/shared/modbus"
+#[cfg(feature = "registry")]
+pub async fn fetch_registry(key: &str, op: &crate::op::Op, ctx: &XValueContext) -> EResult<Value> {
+    let rpc = ctx
+        .registry
+        .as_ref()
+        .ok_or_else(|| Error::unsupported("xvalue include-registry: registry client not set"))?;
+    let path = crate::registry::KeyPath::new(key)?;
+    tokio::time::timeout(op.timeout()?, crate::registry::key_get_path(&path, rpc)).await?
+}
+
+#[cfg(not(feature = "registry"))]
+pub async fn fetch_registry(
+    _key: &str,
+    _op: &crate::op::Op,
+    _ctx: &XValueContext,
+) -> EResult<Value> {
+    Err(Error::unsupported(
+        "xvalue include-registry: registry feature is not enabled",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::UrlAllow;
+    use crate::value::{Value, XValueContext};
+
+    #[test]
+    fn test_url_allow() {
+        let allow: UrlAllow = "https://config.example.com".parse().unwrap();
+        assert!(allow.matches("https://config.example.com/eva/svc.yml"));
+        assert!(allow.matches("HTTPS://Config.Example.com:443/x"));
+        assert!(!allow.matches("http://config.example.com/x"));
+        assert!(!allow.matches("https://config.example.com:8443/x"));
+        assert!(!allow.matches("https://config.example.com@evil.com/x"));
+        assert!(!allow.matches("https://config.example.com.evil.com/x"));
+        let allow: UrlAllow = "https://*.example.com".parse().unwrap();
+        assert!(allow.matches("https://a.b.example.com/x"));
+        assert!(!allow.matches("https://example.com/x"));
+        assert!(!allow.matches("https://badexample.com/x"));
+        let allow: UrlAllow = "http://[::1]:8080/".parse().unwrap();
+        assert!(allow.matches("http://[::1]:8080/cfg"));
+        assert!(!allow.matches("http://[::1]/cfg"));
+        assert!("ftp://example.com".parse::<UrlAllow>().is_err());
+        assert!("example.com".parse::<UrlAllow>().is_err());
+    }
+
+    #[test]
+    fn test_include_depth() {
+        let dir = std::env::temp_dir().join(format!("eva-xvalue-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.yml"), "b: ^include b.yml\n").unwrap();
+        std::fs::write(dir.join("b.yml"), "a: ^include a.yml\n").unwrap();
+        std::fs::write(dir.join("c.yml"), "x: 1\ny: ^env HOME\n").unwrap();
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async {
+                let timeout = std::time::Duration::from_secs(5);
+                let nested = XValueContext::new().nested_includes();
+                let err = Value::String("^include a.yml".to_owned())
+                    .extend_with(timeout, &dir, &nested)
+                    .await
+                    .unwrap_err();
+                assert!(err.to_string().contains("max depth"));
+                // nested includes are not extended by default
+                let val = Value::String("^include a.yml".to_owned())
+                    .extend(timeout, &dir)
+                    .await
+                    .unwrap();
+                assert_eq!(
+                    val.jp_lookup("$.b").unwrap(),
+                    Some(&Value::String("^include b.yml".to_owned()))
+                );
+                let val = Value::String("^include c.yml".to_owned())
+                    .extend(timeout, &dir)
+                    .await
+                    .unwrap();
+                assert_eq!(val.jp_lookup("$.x").unwrap(), Some(&Value::U64(1)));
+                assert_eq!(
+                    val.jp_lookup("$.y").unwrap(),
+                    Some(&Value::String("^env HOME".to_owned()))
+                );
+                let err = Value::String("^include-url https://example.com/x.yml".to_owned())
+                    .extend(timeout, &dir)
+                    .await
+                    .unwrap_err();
+                assert_eq!(err.kind(), crate::ErrorKind::AccessDenied);
+            });
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,3 +1,5 @@
+#[allow(unused_imports)]
+use crate::alloc_prelude::*;
 use crate::value::Value;
 use serde::{de, ser, ser::SerializeSeq, Deserialize, Deserializer, Serialize, Serializer};
 
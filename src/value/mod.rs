@@ -7,21 +7,28 @@
     clippy::cast_lossless
 )]
 
+#[allow(unused_imports)]
+use crate::alloc_prelude::*;
 use crate::{EResult, Error};
+use alloc::collections::BTreeMap;
+use core::cmp::Ordering;
+use core::convert::AsRef;
+use core::convert::{TryFrom, TryInto};
+use core::fmt;
+#[cfg(feature = "std")]
+use core::hash::BuildHasher;
+use core::hash::{Hash, Hasher};
+#[cfg(feature = "std")]
+use core::iter::FromIterator;
+#[cfg(feature = "extended-value")]
+use core::time::Duration;
 use ordered_float::OrderedFloat;
 use rust_decimal::prelude::*;
 use serde::{Deserialize, Deserializer, Serialize};
-use std::cmp::Ordering;
-use std::collections::{BTreeMap, HashSet};
-use std::convert::AsRef;
-use std::convert::{TryFrom, TryInto};
-use std::fmt;
-use std::hash::{BuildHasher, Hash, Hasher};
-use std::iter::FromIterator;
+#[cfg(feature = "std")]
+use std::collections::HashSet;
 #[cfg(feature = "extended-value")]
 use std::path::Path;
-#[cfg(feature = "extended-value")]
-use std::time::Duration;
 
 pub use de::*;
 pub use ser::*;
@@ -29,11 +36,31 @@ pub use ser::*;
 //pub use ser::SerializerError;
 //pub use de::DeserializerError;
 
+#[cfg(feature = "value-arena")]
+mod arena;
+mod canonical;
 mod de;
+#[cfg(feature = "extended-value")]
+mod include;
 mod index;
+mod patch;
+#[cfg(feature = "extended-value")]
+mod pipe;
+mod redact;
 mod ser;
+pub mod table;
+#[cfg(feature = "std")]
+mod template;
 
+#[cfg(feature = "value-arena")]
+pub use arena::{ArenaValue, ValueArena};
 pub use index::{Index, IndexSlice};
+pub use patch::{Patch, PatchOp};
+#[cfg(feature = "extended-value")]
+pub use pipe::ExtendPolicy;
+pub use redact::{Redactor, DEFAULT_REDACT_PATTERNS, REDACTED};
+#[cfg(feature = "std")]
+pub use template::Template;
 
 impl From<de::DeserializerError> for Error {
     fn from(err: de::DeserializerError) -> Error {
@@ -140,29 +167,45 @@ impl<'de> Deserialize<'de> for ValueOptionOwned {
 
 #[cfg(feature = "time")]
 #[inline]
-fn parse_time_frame(s: &str) -> Option<f64> {
-    if s.len() < 2 {
-        None
-    } else if let Ok(v) = s[..s.len() - 1].parse::<f64>() {
-        match &s[s.len() - 1..] {
-            "S" => Some(v),
-            "T" => Some(v * 60.0),
-            "H" => Some(v * 3_600.0),
-            "D" => Some(v * 86_400.0),
-            "W" => Some(v * 604_800.0),
-            _ => None,
-        }
-    } else {
-        None
+pub(crate) fn parse_time_frame(s: &str) -> Option<f64> {
+    let (pos, suffix) = s.char_indices().next_back()?;
+    if pos == 0 {
+        return None;
+    }
+    let v = s[..pos].parse::<f64>().ok()?;
+    match suffix {
+        'S' => Some(v),
+        'T' => Some(v * 60.0),
+        'H' => Some(v * 3_600.0),
+        'D' => Some(v * 86_400.0),
+        'W' => Some(v * 604_800.0),
+        _ => None,
     }
 }
 
+#[cfg(feature = "std")]
+#[inline]
+fn round_f64(v: f64) -> f64 {
+    v.round()
+}
+
+/// Float rounding is not a part of core
+#[cfg(not(feature = "std"))]
+fn round_f64(v: f64) -> f64 {
+    Decimal::from_f64_retain(v)
+        .and_then(|d| {
+            d.round_dp_with_strategy(0, RoundingStrategy::MidpointAwayFromZero)
+                .to_f64()
+        })
+        .unwrap_or(v)
+}
+
 const ERR_INVALID_JSON_PATH: &str = "invalid JSON path, does not start with $.";
 const ERR_UNSUPPORTED_JSON_PATH_DOUBLE_DOT: &str = "unsupported JSON path (..)";
 
 fn value_jp_lookup<'a>(
     value: &'a Value,
-    sp: &mut std::str::Split<'_, char>,
+    sp: &mut core::str::Split<'_, char>,
     allow_empty: bool,
 ) -> EResult<Option<&'a Value>> {
     macro_rules! abort {
@@ -213,7 +256,7 @@ fn value_jp_lookup<'a>(
 
 fn value_jp_insert(
     source: &mut Value,
-    sp: &mut std::str::Split<'_, char>,
+    sp: &mut core::str::Split<'_, char>,
     value: Value,
     allow_empty: bool,
 ) -> EResult<()> {
@@ -274,7 +317,7 @@ fn value_jp_insert(
 }
 
 #[inline]
-fn parse_jp(path: &str) -> EResult<std::str::Split<'_, char>> {
+fn parse_jp(path: &str) -> EResult<core::str::Split<'_, char>> {
     if let Some(p) = path.strip_prefix("$.") {
         Ok(p.split('.'))
     } else {
@@ -548,7 +591,7 @@ impl Value {
                         Error::invalid_data_static(ERR_UNABLE_CONVERT_FLOAT)
                     })?));
                 }
-                return Ok(Value::U64(vf.round() as u64));
+                return Ok(Value::U64(round_f64(vf) as u64));
             }
             if let Value::F32(vf) = self {
                 if precs > 0 {
@@ -559,7 +602,7 @@ impl Value {
                         Error::invalid_data_static(ERR_UNABLE_CONVERT_FLOAT)
                     })?));
                 }
-                return Ok(Value::U32(vf.round() as u32));
+                return Ok(Value::U32(round_f64(f64::from(vf)) as u32));
             }
         }
         Ok(self)
@@ -768,27 +811,211 @@ impl Value {
     }
     #[cfg(feature = "extended-value")]
     pub async fn extend(self, timeout: Duration, base: &Path) -> EResult<Value> {
+        self.extend_with(timeout, base, &XValueContext::default())
+            .await
+    }
+    /// Extends the value with template variables and environment access
+    #[cfg(feature = "extended-value")]
+    pub async fn extend_with(
+        self,
+        timeout: Duration,
+        base: &Path,
+        ctx: &XValueContext,
+    ) -> EResult<Value> {
         let op = crate::op::Op::new(timeout);
-        extend_value(self, &op, base).await
+        extend_value(self, &op, base, ctx, 0).await
+    }
+}
+
+/// Extended value context
+///
+/// Template variables are substituted in strings as ${name}, unknown variables are kept as-is,
+/// "$${" is rendered as a literal "${".
+/// Environment variables are accessed with "^env VAR" and must be explicitly allowed, either by
+/// name or by prefix with a trailing asterisk (e.g. "EVA_*"). Pipe commands are controlled by
+/// [`ExtendPolicy`]. URLs for "^include-url" must be
+/// allowed as well, see [`XValueContext::allow_url`]. "^include-registry" requires a registry
+/// RPC client
+///
+/// Documents, included from URLs and the registry, are never extended, so their sources can not
+/// run pipe commands, read environment variables or local files. Local "^include" documents are
+/// extended only if nested includes are enabled
+#[cfg(feature = "extended-value")]
+#[derive(Clone)]
+pub struct XValueContext {
+    vars: BTreeMap<String, String>,
+    env_allow: Vec<String>,
+    url_allow: Vec<include::UrlAllow>,
+    max_include_size: usize,
+    max_depth: usize,
+    nested_includes: bool,
+    policy: ExtendPolicy,
+    #[cfg(feature = "registry")]
+    registry: Option<std::sync::Arc<busrt::rpc::RpcClient>>,
+}
+
+#[cfg(feature = "extended-value")]
+impl Default for XValueContext {
+    fn default() -> Self {
+        Self {
+            vars: <_>::default(),
+            env_allow: <_>::default(),
+            url_allow: <_>::default(),
+            max_include_size: include::DEFAULT_MAX_INCLUDE_SIZE,
+            max_depth: include::DEFAULT_MAX_DEPTH,
+            nested_includes: false,
+            policy: <_>::default(),
+            #[cfg(feature = "registry")]
+            registry: None,
+        }
+    }
+}
+
+#[cfg(feature = "extended-value")]
+impl fmt::Debug for XValueContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("XValueContext")
+            .field("vars", &self.vars)
+            .field("env_allow", &self.env_allow)
+            .field("url_allow", &self.url_allow)
+            .field("max_include_size", &self.max_include_size)
+            .field("max_depth", &self.max_depth)
+            .field("nested_includes", &self.nested_includes)
+            .field("policy", &self.policy)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "extended-value")]
+impl XValueContext {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Allows "^include-url" for the scheme and the host, e.g. "https://config.example.com",
+    /// "https://*.example.com" (any subdomain) or "http://10.0.0.1:8080"
+    pub fn allow_url(mut self, allow: &str) -> EResult<Self> {
+        self.url_allow.push(allow.parse()?);
+        Ok(self)
+    }
+    /// Maximum size of "^include-url" documents (default: 1 MiB)
+    #[inline]
+    pub fn max_include_size(mut self, size: usize) -> Self {
+        self.max_include_size = size;
+        self
+    }
+    /// Maximum nesting level of includes (default: 8)
+    #[inline]
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = depth;
+        self
+    }
+    /// Extends documents, included with "^include", recursively (disabled by default)
+    #[inline]
+    pub fn nested_includes(mut self) -> Self {
+        self.nested_includes = true;
+        self
+    }
+    /// Policy for pipe commands
+    #[inline]
+    pub fn policy(mut self, policy: ExtendPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+    /// Registry RPC client for "^include-registry"
+    #[cfg(feature = "registry")]
+    #[inline]
+    pub fn registry(mut self, rpc: std::sync::Arc<busrt::rpc::RpcClient>) -> Self {
+        self.registry = Some(rpc);
+        self
+    }
+    pub fn is_url_allowed(&self, url: &str) -> bool {
+        self.url_allow.iter().any(|a| a.matches(url))
+    }
+    #[inline]
+    pub fn var(mut self, name: &str, value: impl fmt::Display) -> Self {
+        self.vars.insert(name.to_owned(), value.to_string());
+        self
+    }
+    #[inline]
+    pub fn allow_env(mut self, name: &str) -> Self {
+        self.env_allow.push(name.to_owned());
+        self
+    }
+    pub fn is_env_allowed(&self, name: &str) -> bool {
+        self.env_allow.iter().any(|a| {
+            if let Some(prefix) = a.strip_suffix('*') {
+                name.starts_with(prefix)
+            } else {
+                a == name
+            }
+        })
+    }
+    fn env(&self, name: &str) -> EResult<String> {
+        if !self.is_env_allowed(name) {
+            return Err(Error::access(format!(
+                "xvalue env: access to {} denied",
+                name
+            )));
+        }
+        std::env::var(name)
+            .map_err(|e| Error::invalid_params(format!("xvalue env {}: {}", name, e)))
+    }
+    fn render(&self, s: String) -> String {
+        if self.vars.is_empty() || !s.contains("${") {
+            return s;
+        }
+        let mut result = String::with_capacity(s.len());
+        let mut rest = s.as_str();
+        while let Some(pos) = rest.find("${") {
+            if rest[..pos].ends_with('$') {
+                // escaped
+                result.push_str(&rest[..pos - 1]);
+                result.push_str("${");
+                rest = &rest[pos + 2..];
+                continue;
+            }
+            result.push_str(&rest[..pos]);
+            let tail = &rest[pos + 2..];
+            if let Some(end) = tail.find('}') {
+                if let Some(v) = self.vars.get(&tail[..end]) {
+                    result.push_str(v);
+                } else {
+                    result.push_str(&rest[pos..pos + end + 3]);
+                }
+                rest = &tail[end + 1..];
+            } else {
+                result.push_str(&rest[pos..]);
+                rest = "";
+            }
+        }
+        result.push_str(rest);
+        result
     }
 }
 
 #[cfg(feature = "extended-value")]
 #[async_recursion::async_recursion]
-async fn extend_value(value: Value, op: &crate::op::Op, base: &Path) -> EResult<Value> {
+async fn extend_value(
+    value: Value,
+    op: &crate::op::Op,
+    base: &Path,
+    ctx: &XValueContext,
+    depth: usize,
+) -> EResult<Value> {
     match value {
-        Value::String(s) => Ok(extend_string_value(s, op, base).await?),
+        Value::String(s) => Ok(extend_string_value(ctx.render(s), op, base, ctx, depth).await?),
         Value::Seq(s) => {
             let mut result = Vec::with_capacity(s.len());
             for val in s {
-                result.push(extend_value(val, op, base).await?);
+                result.push(extend_value(val, op, base, ctx, depth).await?);
             }
             Ok(Value::Seq(result))
         }
         Value::Map(m) => {
             let mut result = BTreeMap::new();
             for (k, v) in m {
-                result.insert(k, extend_value(v, op, base).await?);
+                result.insert(k, extend_value(v, op, base, ctx, depth).await?);
             }
             Ok(Value::Map(result))
         }
@@ -796,8 +1023,26 @@ async fn extend_value(value: Value, op: &crate::op::Op, base: &Path) -> EResult<
     }
 }
 
+/// Extends values of local includes, nested includes are processed up to the context max depth
+#[cfg(feature = "extended-value")]
+async fn extend_included(
+    value: Value,
+    op: &crate::op::Op,
+    base: &Path,
+    ctx: &XValueContext,
+    depth: usize,
+) -> EResult<Value> {
+    if depth >= ctx.max_depth {
+        return Err(Error::invalid_params(format!(
+            "xvalue include: max depth ({}) exceeded",
+            ctx.max_depth
+        )));
+    }
+    extend_value(value, op, base, ctx, depth + 1).await
+}
+
 impl FromStr for Value {
-    type Err = std::convert::Infallible;
+    type Err = core::convert::Infallible;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         Ok(if let Ok(v) = s.parse::<u64>() {
             Value::U64(v)
@@ -820,7 +1065,13 @@ impl FromStr for Value {
 }
 
 #[cfg(feature = "extended-value")]
-async fn extend_string_value(val: String, op: &crate::op::Op, base: &Path) -> EResult<Value> {
+async fn extend_string_value(
+    val: String,
+    op: &crate::op::Op,
+    base: &Path,
+    ctx: &XValueContext,
+    depth: usize,
+) -> EResult<Value> {
     if let Some(s) = val.strip_prefix('^') {
         let mut sp = s.splitn(2, ' ');
         let cmd = sp.next().unwrap();
@@ -829,22 +1080,7 @@ async fn extend_string_value(val: String, op: &crate::op::Op, base: &Path) -> ER
                 let cmd = sp
                     .next()
                     .ok_or_else(|| Error::invalid_params("xvalue pipe: command not specified"))?;
-                let cd_cmd = format!("cd \"{}\" && {}", base.to_string_lossy(), cmd);
-                let res = bmart::process::command(
-                    "sh",
-                    &["-c", &cd_cmd],
-                    op.timeout()?,
-                    bmart::process::Options::default(),
-                )
-                .await?;
-                if res.ok() {
-                    res.out.join("\n")
-                } else {
-                    return Err(Error::failed(format!(
-                        "xvalue pipe command failed to execute: {}",
-                        res.err.join("\n")
-                    )));
-                }
+                ctx.policy.run(cmd, base, op.timeout()?).await?
             }};
         }
         match cmd {
@@ -856,7 +1092,25 @@ async fn extend_string_value(val: String, op: &crate::op::Op, base: &Path) -> ER
                 path.push(fname);
                 let content = tokio::time::timeout(op.timeout()?, tokio::fs::read(path)).await??;
                 let val: Value = serde_yaml::from_slice(&content).map_err(Error::invalid_data)?;
-                Ok(val)
+                if ctx.nested_includes {
+                    extend_included(val, op, base, ctx, depth).await
+                } else {
+                    Ok(val)
+                }
+            }
+            "include-url" => {
+                let url = sp
+                    .next()
+                    .ok_or_else(|| Error::invalid_params("xvalue include-url: URL not specified"))?
+                    .trim();
+                let content = include::fetch_url(url, op, ctx).await?;
+                serde_yaml::from_slice(&content).map_err(Error::invalid_data)
+            }
+            "include-registry" => {
+                let key = sp.next().ok_or_else(|| {
+                    Error::invalid_params("xvalue include-registry: key not specified")
+                })?;
+                include::fetch_registry(key.trim(), op, ctx).await
             }
             "include-text" => {
                 let fname = sp.next().ok_or_else(|| {
@@ -877,6 +1131,12 @@ async fn extend_string_value(val: String, op: &crate::op::Op, base: &Path) -> ER
                 let s = pipe!();
                 Ok(Value::String(s.trim_end().to_string()))
             }
+            "env" => {
+                let name = sp.next().ok_or_else(|| {
+                    Error::invalid_params("xvalue env: variable name not specified")
+                })?;
+                Ok(Value::String(ctx.env(name.trim())?))
+            }
             _ => Ok(Value::String(if s.starts_with('^') {
                 s.to_owned()
             } else {
@@ -1397,13 +1657,13 @@ impl TryFrom<Value> for f64 {
     }
 }
 
-impl TryFrom<Value> for Option<std::time::Duration> {
+impl TryFrom<Value> for Option<core::time::Duration> {
     type Error = Error;
 
-    fn try_from(v: Value) -> EResult<Option<std::time::Duration>> {
+    fn try_from(v: Value) -> EResult<Option<core::time::Duration>> {
         let t: f64 = v.try_into()?;
         if t > 0.0 {
-            Ok(Some(std::time::Duration::from_secs_f64(t)))
+            Ok(Some(core::time::Duration::from_secs_f64(t)))
         } else {
             Ok(None)
         }
@@ -1465,11 +1725,11 @@ impl TryFrom<Value> for Option<String> {
     }
 }
 
-impl TryFrom<Value> for std::time::Duration {
+impl TryFrom<Value> for core::time::Duration {
     type Error = Error;
 
-    fn try_from(v: Value) -> EResult<std::time::Duration> {
-        Ok(std::time::Duration::from_secs_f64(v.try_into()?))
+    fn try_from(v: Value) -> EResult<core::time::Duration> {
+        Ok(core::time::Duration::from_secs_f64(v.try_into()?))
     }
 }
 
@@ -1485,6 +1745,7 @@ impl TryFrom<Value> for Vec<Value> {
     }
 }
 
+#[cfg(feature = "std")]
 impl<S: BuildHasher + Default> TryFrom<Value> for HashSet<Value, S> {
     type Error = Error;
 
@@ -1497,12 +1758,14 @@ impl<S: BuildHasher + Default> TryFrom<Value> for HashSet<Value, S> {
     }
 }
 
+#[cfg(feature = "std")]
 impl From<HashSet<ipnetwork::IpNetwork>> for Value {
     fn from(v: HashSet<ipnetwork::IpNetwork>) -> Value {
         to_value(v).unwrap()
     }
 }
 
+#[cfg(feature = "std")]
 impl<S: BuildHasher + Default> TryFrom<Value> for HashSet<ipnetwork::IpNetwork, S> {
     type Error = Error;
 
@@ -1626,6 +1889,7 @@ impl From<Vec<Value>> for Value {
     }
 }
 
+#[cfg(feature = "std")]
 impl From<HashSet<Value>> for Value {
     fn from(v: HashSet<Value>) -> Value {
         Value::Seq(Vec::from_iter(v))
@@ -1644,14 +1908,14 @@ impl From<BTreeMap<Value, Value>> for Value {
     }
 }
 
-impl From<std::time::Duration> for Value {
-    fn from(v: std::time::Duration) -> Value {
+impl From<core::time::Duration> for Value {
+    fn from(v: core::time::Duration) -> Value {
         v.as_secs_f64().into()
     }
 }
 
-impl From<Option<std::time::Duration>> for Value {
-    fn from(v: Option<std::time::Duration>) -> Value {
+impl From<Option<core::time::Duration>> for Value {
+    fn from(v: Option<core::time::Duration>) -> Value {
         v.map_or(Value::Unit, |d| d.as_secs_f64().into())
     }
 }
@@ -1682,11 +1946,145 @@ impl TryFrom<serde_json::Value> for Value {
     }
 }
 
+impl Value {
+    /// Parses a JSON slice
+    pub fn from_json_slice(data: &[u8]) -> EResult<Value> {
+        serde_json::from_slice(data).map_err(Into::into)
+    }
+    /// Parses a JSON slice with the SIMD parser if the "json-fast" feature is enabled, otherwise
+    /// falls back to [`Value::from_json_slice()`]. The buffer is used as the parser scratch space
+    /// and its content is undefined after the call
+    pub fn from_json_slice_fast(data: &mut [u8]) -> EResult<Value> {
+        #[cfg(feature = "json-fast")]
+        {
+            simd_json::serde::from_slice(data).map_err(Into::into)
+        }
+        #[cfg(not(feature = "json-fast"))]
+        {
+            Self::from_json_slice(data)
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use crate::prelude::*;
     use serde::Serialize;
 
+    #[cfg(feature = "value-arena")]
+    #[test]
+    fn test_value_arena() {
+        use super::{ArenaValue, ValueArena};
+        let json = br#"{"id":"sensor:tests/s1","values":[1,-2,3.5,null,true],"meta":{"x":"y"}}"#;
+        let mut arena = ValueArena::new();
+        {
+            let val = arena.from_json_slice(json).unwrap();
+            assert_eq!(
+                val.get("id").and_then(ArenaValue::as_str),
+                Some("sensor:tests/s1")
+            );
+            let ArenaValue::Seq(values) = val.get("values").unwrap() else {
+                panic!("seq expected")
+            };
+            assert_eq!(values.len(), 5);
+            let owned = val.to_value();
+            assert_eq!(owned, Value::from_json_slice(json).unwrap());
+            assert_eq!(arena.from_value(&owned).to_value(), owned);
+            assert_eq!(
+                serde_json::to_value(val).unwrap(),
+                serde_json::from_slice::<serde_json::Value>(json).unwrap()
+            );
+        }
+        assert!(arena.allocated_bytes() > 0);
+        arena.reset();
+        let val = arena.seq([arena.string("a"), ArenaValue::U8(1)]);
+        assert_eq!(
+            val.to_value(),
+            Value::Seq(vec![Value::String("a".to_owned()), Value::U8(1)])
+        );
+        assert!(arena.from_json_slice(b"[1,").is_err());
+        // huge length headers of truncated payloads are not preallocated
+        #[cfg(feature = "payload")]
+        {
+            arena.reset();
+            let data = [0xdd, 0xff, 0xff, 0xff, 0xff];
+            assert!(arena
+                .deserialize(&mut rmp_serde::Deserializer::new(&data[..]))
+                .is_err());
+            assert!(arena.allocated_bytes() < 1_000_000);
+        }
+    }
+
+    #[test]
+    fn test_from_json_slice() {
+        let json = br#"{"a":[1,-2,3.5,"x",null,true],"b":{"c":"d"}}"#;
+        let expected = Value::from_json_slice(json).unwrap();
+        let mut buf = json.to_vec();
+        let val = Value::from_json_slice_fast(&mut buf).unwrap();
+        assert_eq!(val, expected);
+        assert_eq!(
+            serde_json::to_value(&val).unwrap(),
+            serde_json::from_slice::<serde_json::Value>(json).unwrap()
+        );
+        assert!(Value::from_json_slice_fast(&mut b"{\"a\":".to_vec()).is_err());
+    }
+
+    #[cfg(feature = "extended-value")]
+    #[test]
+    fn test_xvalue_context() {
+        let ctx = super::XValueContext::new()
+            .var("system_name", "node1")
+            .var("data_path", "/opt/eva4/runtime/svc_data/test")
+            .allow_env("EVA_*")
+            .allow_env("HOME");
+        assert_eq!(
+            ctx.render("${data_path}/db.sqlite".to_owned()),
+            "/opt/eva4/runtime/svc_data/test/db.sqlite"
+        );
+        assert_eq!(
+            ctx.render("${system_name}:${unknown}:${system_name".to_owned()),
+            "node1:${unknown}:${system_name"
+        );
+        assert_eq!(
+            ctx.render("$${system_name}=${system_name}, $$${system_name}".to_owned()),
+            "${system_name}=node1, $${system_name}"
+        );
+        assert!(ctx.is_env_allowed("EVA_DIR"));
+        assert!(ctx.is_env_allowed("HOME"));
+        assert!(!ctx.is_env_allowed("HOMEDIR"));
+        assert!(!ctx.is_env_allowed("PATH"));
+        assert!(ctx.env("PATH").is_err());
+    }
+
+    #[test]
+    fn test_redact() {
+        let val: Value = serde_json::from_str(
+            r#"{"host":"db","auth":{"user":"u","password":"p"},
+                "users":[{"login":"a","password":"x"},{"login":"b"}]}"#,
+        )
+        .unwrap();
+        let redacted = val
+            .clone()
+            .redact(&["auth.password", "$.users.password", "no.such"]);
+        let r: serde_json::Value = serde_json::to_value(&redacted).unwrap();
+        assert_eq!(r["auth"]["password"], super::REDACTED);
+        assert_eq!(r["auth"]["user"], "u");
+        assert_eq!(r["users"][0]["password"], super::REDACTED);
+        assert!(r["users"][1].get("password").is_none());
+        let redactor = super::Redactor::default();
+        let val: Value = serde_json::from_str(
+            r#"{"bus":{"path":"var/bus.ipc"},"API_Key":"k","tokens":[1,2],
+                "nested":[{"db_password":"x","token":null}]}"#,
+        )
+        .unwrap();
+        let r: serde_json::Value = serde_json::to_value(redactor.redact(&val)).unwrap();
+        assert_eq!(r["bus"]["path"], "var/bus.ipc");
+        assert_eq!(r["API_Key"], super::REDACTED);
+        assert_eq!(r["tokens"], super::REDACTED);
+        assert_eq!(r["nested"][0]["db_password"], super::REDACTED);
+        assert!(r["nested"][0]["token"].is_null());
+    }
+
     #[test]
     fn test_val_pack() -> EResult<()> {
         #[derive(Serialize)]
@@ -0,0 +1,403 @@
+use super::Value;
+#[allow(unused_imports)]
+use crate::alloc_prelude::*;
+use crate::{EResult, Error};
+use alloc::collections::BTreeMap;
+use serde::{Deserialize, Deserializer, Serialize};
+
+/// JSON Patch (RFC 6902) operation. Paths are JSON Pointers (RFC 6901)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum PatchOp {
+    Add { path: String, value: Value },
+    Remove { path: String },
+    Replace { path: String, value: Value },
+    Move { from: String, path: String },
+    Copy { from: String, path: String },
+    Test { path: String, value: Value },
+}
+
+/// A patch: JSON Patch operations (serialized as an array) or JSON Merge Patch (RFC 7386,
+/// serialized as an object)
+///
+/// When deserialized, arrays are always parsed as JSON Patch operations and only objects are
+/// accepted as merge patches
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum Patch {
+    Json(Vec<PatchOp>),
+    Merge(Value),
+}
+
+impl<'de> Deserialize<'de> for Patch {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        match Value::deserialize(deserializer)? {
+            v @ Value::Seq(_) => Ok(Patch::Json(
+                v.deserialize_into().map_err(serde::de::Error::custom)?,
+            )),
+            v @ Value::Map(_) => Ok(Patch::Merge(v)),
+            _ => Err(serde::de::Error::custom(
+                "patch must be an array of operations or a merge patch object",
+            )),
+        }
+    }
+}
+
+impl Patch {
+    /// Returns true if the patch changes nothing
+    pub fn is_empty(&self) -> bool {
+        match self {
+            Patch::Json(ops) => ops.is_empty(),
+            Patch::Merge(Value::Map(m)) => m.is_empty(),
+            Patch::Merge(_) => false,
+        }
+    }
+}
+
+fn escape_token(token: &str) -> String {
+    token.replace('~', "~0").replace('/', "~1")
+}
+
+fn parse_pointer(pointer: &str) -> EResult<Vec<String>> {
+    if pointer.is_empty() {
+        return Ok(Vec::new());
+    }
+    let Some(p) = pointer.strip_prefix('/') else {
+        return Err(Error::invalid_params(format!(
+            "invalid JSON pointer: {}",
+            pointer
+        )));
+    };
+    Ok(p.split('/')
+        .map(|t| t.replace("~1", "/").replace("~0", "~"))
+        .collect())
+}
+
+fn map_key(m: &BTreeMap<Value, Value>, token: &str) -> Value {
+    let key = Value::String(token.to_owned());
+    if m.contains_key(&key) {
+        return key;
+    }
+    // non-string keys are addressed by their string representation
+    m.keys()
+        .find(|k| k.to_string() == token)
+        .cloned()
+        .unwrap_or(key)
+}
+
+fn seq_index(token: &str, len: usize, allow_end: bool) -> EResult<usize> {
+    if allow_end && token == "-" {
+        return Ok(len);
+    }
+    let idx: usize = token
+        .parse()
+        .map_err(|_| Error::invalid_params(format!("invalid sequence index: {}", token)))?;
+    if idx > len || (idx == len && !allow_end) {
+        return Err(Error::not_found(format!(
+            "sequence index out of range: {}",
+            idx
+        )));
+    }
+    Ok(idx)
+}
+
+fn pointer_get<'a>(value: &'a Value, tokens: &[String]) -> Option<&'a Value> {
+    tokens.iter().try_fold(value, |v, token| match v {
+        Value::Map(m) => m.get(&map_key(m, token)),
+        Value::Seq(s) => token.parse::<usize>().ok().and_then(|i| s.get(i)),
+        _ => None,
+    })
+}
+
+fn pointer_get_mut<'a>(value: &'a mut Value, tokens: &[String]) -> Option<&'a mut Value> {
+    tokens.iter().try_fold(value, |v, token| match v {
+        Value::Map(m) => {
+            let key = map_key(m, token);
+            m.get_mut(&key)
+        }
+        Value::Seq(s) => token.parse::<usize>().ok().and_then(|i| s.get_mut(i)),
+        _ => None,
+    })
+}
+
+fn not_found(pointer: &[String]) -> Error {
+    let mut path = String::new();
+    for token in pointer {
+        path.push('/');
+        path.push_str(&escape_token(token));
+    }
+    Error::not_found(format!("path not found: {}", path))
+}
+
+fn parent_mut<'a>(value: &'a mut Value, tokens: &[String]) -> EResult<(&'a mut Value, String)> {
+    let (last, parent) = tokens
+        .split_last()
+        .ok_or_else(|| Error::invalid_params("the operation can not be applied to the root"))?;
+    let parent_value = pointer_get_mut(value, parent).ok_or_else(|| not_found(parent))?;
+    Ok((parent_value, last.clone()))
+}
+
+fn add(value: &mut Value, tokens: &[String], v: Value) -> EResult<()> {
+    if tokens.is_empty() {
+        *value = v;
+        return Ok(());
+    }
+    let (parent, token) = parent_mut(value, tokens)?;
+    match parent {
+        Value::Map(m) => {
+            let key = map_key(m, &token);
+            m.insert(key, v);
+        }
+        Value::Seq(s) => {
+            let idx = seq_index(&token, s.len(), true)?;
+            s.insert(idx, v);
+        }
+        _ => return Err(not_found(tokens)),
+    }
+    Ok(())
+}
+
+fn remove(value: &mut Value, tokens: &[String]) -> EResult<Value> {
+    let (parent, token) = parent_mut(value, tokens)?;
+    match parent {
+        Value::Map(m) => {
+            let key = map_key(m, &token);
+            m.remove(&key).ok_or_else(|| not_found(tokens))
+        }
+        Value::Seq(s) => {
+            let idx = seq_index(&token, s.len(), false)?;
+            Ok(s.remove(idx))
+        }
+        _ => Err(not_found(tokens)),
+    }
+}
+
+fn apply_op(value: &mut Value, op: &PatchOp) -> EResult<()> {
+    match op {
+        PatchOp::Add { path, value: v } => add(value, &parse_pointer(path)?, v.clone()),
+        PatchOp::Remove { path } => remove(value, &parse_pointer(path)?).map(|_| ()),
+        PatchOp::Replace { path, value: v } => {
+            let tokens = parse_pointer(path)?;
+            let target = pointer_get_mut(value, &tokens).ok_or_else(|| not_found(&tokens))?;
+            *target = v.clone();
+            Ok(())
+        }
+        PatchOp::Move { from, path } => {
+            let from_tokens = parse_pointer(from)?;
+            let tokens = parse_pointer(path)?;
+            if tokens.len() > from_tokens.len() && tokens.starts_with(&from_tokens) {
+                return Err(Error::invalid_params(format!(
+                    "unable to move {} into its child {}",
+                    from, path
+                )));
+            }
+            let v = remove(value, &from_tokens)?;
+            add(value, &tokens, v)
+        }
+        PatchOp::Copy { from, path } => {
+            let from_tokens = parse_pointer(from)?;
+            let v = pointer_get(value, &from_tokens)
+                .ok_or_else(|| not_found(&from_tokens))?
+                .clone();
+            add(value, &parse_pointer(path)?, v)
+        }
+        PatchOp::Test { path, value: v } => {
+            let tokens = parse_pointer(path)?;
+            if pointer_get(value, &tokens) == Some(v) {
+                Ok(())
+            } else {
+                Err(Error::invalid_data(format!("patch test failed: {}", path)))
+            }
+        }
+    }
+}
+
+#[inline]
+fn is_null(value: &Value) -> bool {
+    matches!(value, Value::Unit | Value::Option(None))
+}
+
+fn merge(target: &mut Value, patch: &Value) {
+    let Value::Map(pm) = patch else {
+        *target = patch.clone();
+        return;
+    };
+    if !matches!(target, Value::Map(_)) {
+        *target = Value::Map(BTreeMap::new());
+    }
+    if let Value::Map(tm) = target {
+        for (k, v) in pm {
+            if is_null(v) {
+                tm.remove(k);
+            } else {
+                merge(tm.entry(k.clone()).or_insert(Value::Unit), v);
+            }
+        }
+    }
+}
+
+fn merge_diff(from: &Value, to: &Value) -> Value {
+    let (Value::Map(fm), Value::Map(tm)) = (from, to) else {
+        return to.clone();
+    };
+    let mut result = BTreeMap::new();
+    for k in fm.keys() {
+        if !tm.contains_key(k) {
+            result.insert(k.clone(), Value::Unit);
+        }
+    }
+    for (k, v) in tm {
+        match fm.get(k) {
+            Some(prev) if prev == v => {}
+            Some(prev) => {
+                result.insert(k.clone(), merge_diff(prev, v));
+            }
+            None => {
+                result.insert(k.clone(), v.clone());
+            }
+        }
+    }
+    Value::Map(result)
+}
+
+fn diff_rec(from: &Value, to: &Value, path: &str, ops: &mut Vec<PatchOp>) {
+    if from == to {
+        return;
+    }
+    match (from, to) {
+        (Value::Map(fm), Value::Map(tm)) => {
+            for k in fm.keys() {
+                if !tm.contains_key(k) {
+                    ops.push(PatchOp::Remove {
+                        path: format!("{}/{}", path, escape_token(&k.to_string())),
+                    });
+                }
+            }
+            for (k, v) in tm {
+                let p = format!("{}/{}", path, escape_token(&k.to_string()));
+                if let Some(prev) = fm.get(k) {
+                    diff_rec(prev, v, &p, ops);
+                } else {
+                    ops.push(PatchOp::Add {
+                        path: p,
+                        value: v.clone(),
+                    });
+                }
+            }
+        }
+        (Value::Seq(fs), Value::Seq(ts)) => {
+            for (i, (prev, v)) in fs.iter().zip(ts).enumerate() {
+                diff_rec(prev, v, &format!("{}/{}", path, i), ops);
+            }
+            for (i, v) in ts.iter().enumerate().skip(fs.len()) {
+                ops.push(PatchOp::Add {
+                    path: format!("{}/{}", path, i),
+                    value: v.clone(),
+                });
+            }
+            for i in (ts.len()..fs.len()).rev() {
+                ops.push(PatchOp::Remove {
+                    path: format!("{}/{}", path, i),
+                });
+            }
+        }
+        _ => ops.push(PatchOp::Replace {
+            path: path.to_owned(),
+            value: to.clone(),
+        }),
+    }
+}
+
+impl Value {
+    /// JSON Patch operations, which turn the value into the other one
+    pub fn diff(&self, other: &Value) -> Patch {
+        let mut ops = Vec::new();
+        diff_rec(self, other, "", &mut ops);
+        Patch::Json(ops)
+    }
+    /// JSON Merge Patch, which turns the value into the other one. Note that merge patches can
+    /// not set map fields to null/unit and always replace sequences as a whole
+    pub fn merge_diff(&self, other: &Value) -> Patch {
+        Patch::Merge(merge_diff(self, other))
+    }
+    /// Applies the patch. JSON Patch operations are applied atomically: if any operation fails,
+    /// the value is left unchanged
+    pub fn apply_patch(&mut self, patch: &Patch) -> EResult<()> {
+        match patch {
+            Patch::Json(ops) => {
+                let mut value = self.clone();
+                for op in ops {
+                    apply_op(&mut value, op)?;
+                }
+                *self = value;
+            }
+            Patch::Merge(p) => merge(self, p),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Patch, PatchOp};
+    use crate::value::Value;
+
+    fn v(s: &str) -> Value {
+        serde_json::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn test_patch() {
+        let from = v(r#"{"a":1,"b":{"c":[1,2,3],"d":"x"},"e/f":true}"#);
+        let to = v(r#"{"a":2,"b":{"c":[1,5],"g":null},"e/f":true,"h":[1]}"#);
+        let patch = from.diff(&to);
+        let Patch::Json(ref ops) = patch else {
+            panic!()
+        };
+        assert!(ops.contains(&PatchOp::Remove {
+            path: "/b/c/2".to_owned()
+        }));
+        let mut value = from.clone();
+        value.apply_patch(&patch).unwrap();
+        assert_eq!(value, to);
+        let merge = from.merge_diff(&v(r#"{"a":2,"b":{"c":[1,5]},"e/f":true}"#));
+        assert_eq!(
+            serde_json::to_string(&merge).unwrap(),
+            r#"{"a":2,"b":{"c":[1,5],"d":null}}"#
+        );
+        let mut value = from.clone();
+        value.apply_patch(&merge).unwrap();
+        assert_eq!(value, v(r#"{"a":2,"b":{"c":[1,5]},"e/f":true}"#));
+        assert!(from.diff(&from).is_empty());
+        let patch: Patch = serde_json::from_str(
+            r#"[{"op":"test","path":"/a","value":1},{"op":"move","from":"/e~1f","path":"/b/z"},
+            {"op":"copy","from":"/b/c/0","path":"/b/c/-"}]"#,
+        )
+        .unwrap();
+        let mut value = from.clone();
+        value.apply_patch(&patch).unwrap();
+        assert_eq!(value, v(r#"{"a":1,"b":{"c":[1,2,3,1],"d":"x","z":true}}"#));
+        let patch: Patch = serde_json::from_str(
+            r#"[{"op":"replace","path":"/a","value":5},{"op":"test","path":"/a","value":1}]"#,
+        )
+        .unwrap();
+        let mut value = from.clone();
+        assert!(value.apply_patch(&patch).is_err());
+        assert_eq!(value, from);
+        // unknown operation fields are ignored (RFC 6902)
+        let patch: Patch =
+            serde_json::from_str(r#"[{"op":"remove","path":"/a","comment":"x"}]"#).unwrap();
+        assert!(matches!(patch, Patch::Json(_)));
+        // arrays are never treated as merge patches
+        assert!(serde_json::from_str::<Patch>(r#"[{"op":"drop","path":"/a"}]"#).is_err());
+        assert!(serde_json::from_str::<Patch>("[1]").is_err());
+        assert!(serde_json::from_str::<Patch>("5").is_err());
+        assert!(matches!(
+            serde_json::from_str::<Patch>("[]").unwrap(),
+            Patch::Json(_)
+        ));
+    }
+}
@@ -0,0 +1,219 @@
+use crate::tools::default_true;
+use crate::{EResult, Error};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::AsyncReadExt;
+
+const SHELL_META: &[char] = &[
+    ';', '|', '&', '`', '$', '<', '>', '(', ')', '{', '}', '\n', '\\', '"', '\'', '*', '?', '~',
+];
+
+/// Policy for "^pipe" and "^pipe-text" extended values
+///
+/// The default policy allows any shell command. If the allowed command list is set, commands
+/// are executed directly (without the shell) and may not contain shell meta characters
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ExtendPolicy {
+    #[serde(default = "default_true")]
+    pipe: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    commands: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_output: Option<usize>,
+    #[serde(default)]
+    scrub_env: bool,
+}
+
+impl Default for ExtendPolicy {
+    fn default() -> Self {
+        Self {
+            pipe: true,
+            commands: None,
+            max_output: None,
+            scrub_env: false,
+        }
+    }
+}
+
+impl ExtendPolicy {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Refuses all pipe commands
+    #[inline]
+    pub fn deny_pipe(mut self) -> Self {
+        self.pipe = false;
+        self
+    }
+    /// Adds a program to the allowed command list
+    pub fn allow_command(mut self, program: &str) -> Self {
+        self.commands
+            .get_or_insert_with(Vec::new)
+            .push(program.to_owned());
+        self
+    }
+    /// Maximum size of the command output in bytes
+    #[inline]
+    pub fn max_output(mut self, size: usize) -> Self {
+        self.max_output = Some(size);
+        self
+    }
+    /// Runs commands with the environment cleared (PATH is kept)
+    #[inline]
+    pub fn scrub_env(mut self) -> Self {
+        self.scrub_env = true;
+        self
+    }
+    fn command(&self, cmd: &str, base: &Path) -> EResult<tokio::process::Command> {
+        if !self.pipe {
+            return Err(Error::access("xvalue pipe: denied by the policy"));
+        }
+        let mut command = if let Some(ref allowed) = self.commands {
+            if cmd.contains(SHELL_META) {
+                return Err(Error::access(format!(
+                    "xvalue pipe: shell syntax is not allowed by the policy: {}",
+                    cmd
+                )));
+            }
+            let mut sp = cmd.split_whitespace();
+            let program = sp
+                .next()
+                .ok_or_else(|| Error::invalid_params("xvalue pipe: command not specified"))?;
+            if !allowed.iter().any(|a| a == program) {
+                return Err(Error::access(format!(
+                    "xvalue pipe: command {} is not allowed by the policy",
+                    program
+                )));
+            }
+            let mut command = tokio::process::Command::new(program);
+            command.args(sp);
+            command
+        } else {
+            let mut command = tokio::process::Command::new("sh");
+            command.args(["-c", cmd]);
+            command
+        };
+        if self.scrub_env {
+            command.env_clear();
+            if let Ok(path) = std::env::var("PATH") {
+                command.env("PATH", path);
+            }
+        }
+        command
+            .current_dir(base)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        Ok(command)
+    }
+    /// Executes the command and returns its output
+    pub(super) async fn run(&self, cmd: &str, base: &Path, timeout: Duration) -> EResult<String> {
+        let mut child = self.command(cmd, base)?.spawn()?;
+        let (Some(mut stdout), Some(mut stderr)) = (child.stdout.take(), child.stderr.take())
+        else {
+            return Err(Error::failed("xvalue pipe: unable to capture the output"));
+        };
+        let max_output = self.max_output;
+        let read_out = async move {
+            let mut out = Vec::new();
+            if let Some(max) = max_output {
+                (&mut stdout)
+                    .take(max as u64 + 1)
+                    .read_to_end(&mut out)
+                    .await?;
+                if out.len() > max {
+                    return Err(Error::invalid_data(format!(
+                        "xvalue pipe: output is larger than {} bytes",
+                        max
+                    )));
+                }
+            } else {
+                stdout.read_to_end(&mut out).await?;
+            }
+            Ok(out)
+        };
+        let read_err = async move {
+            let mut err = Vec::new();
+            stderr.read_to_end(&mut err).await?;
+            Ok::<_, Error>(err)
+        };
+        let fut = async move {
+            // stderr is read in parallel, so the child is never blocked on a full pipe
+            let err_reader = tokio::spawn(read_err);
+            let out = read_out.await?;
+            let err = err_reader.await.map_err(Error::failed)??;
+            if child.wait().await?.success() {
+                Ok(String::from_utf8_lossy(&out).into_owned())
+            } else {
+                Err(Error::failed(format!(
+                    "xvalue pipe command failed to execute: {}",
+                    String::from_utf8_lossy(&err).trim_end()
+                )))
+            }
+        };
+        tokio::time::timeout(timeout, fut).await?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ExtendPolicy;
+    use crate::value::{Value, XValueContext};
+    use crate::ErrorKind;
+    use std::path::Path;
+    use std::time::Duration;
+
+    async fn extend(s: &str, policy: ExtendPolicy) -> crate::EResult<Value> {
+        Value::String(s.to_owned())
+            .extend_with(
+                Duration::from_secs(5),
+                Path::new("/"),
+                &XValueContext::new().policy(policy),
+            )
+            .await
+    }
+
+    #[test]
+    fn test_extend_policy() {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async {
+                assert_eq!(
+                    extend("^pipe-text echo $((1+2))", ExtendPolicy::new())
+                        .await
+                        .unwrap(),
+                    Value::String("3".to_owned())
+                );
+                let err = extend("^pipe-text echo 1", ExtendPolicy::new().deny_pipe())
+                    .await
+                    .unwrap_err();
+                assert_eq!(err.kind(), ErrorKind::AccessDenied);
+                let policy = ExtendPolicy::new().allow_command("echo");
+                assert_eq!(
+                    extend("^pipe echo 1", policy.clone()).await.unwrap(),
+                    Value::U64(1)
+                );
+                for cmd in ["^pipe echo 1; id", "^pipe id", "^pipe echo $HOME"] {
+                    let err = extend(cmd, policy.clone()).await.unwrap_err();
+                    assert_eq!(err.kind(), ErrorKind::AccessDenied);
+                }
+                let err = extend("^pipe-text echo 12345", policy.max_output(4))
+                    .await
+                    .unwrap_err();
+                assert_eq!(err.kind(), ErrorKind::InvalidData);
+                assert_eq!(
+                    extend("^pipe-text echo ${HOME}x", ExtendPolicy::new().scrub_env())
+                        .await
+                        .unwrap(),
+                    Value::String("x".to_owned())
+                );
+            });
+    }
+}
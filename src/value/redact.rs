@@ -0,0 +1,105 @@
+use super::Value;
+#[allow(unused_imports)]
+use crate::alloc_prelude::*;
+
+/// Replacement for redacted values
+pub const REDACTED: &str = "***";
+
+pub const DEFAULT_REDACT_PATTERNS: &[&str] = &["password", "passwd", "secret", "token", "key"];
+
+fn redact_path(value: &mut Value, path: &[&str]) {
+    let Some((first, rest)) = path.split_first() else {
+        *value = Value::String(REDACTED.to_owned());
+        return;
+    };
+    match value {
+        Value::Map(m) => {
+            if let Some(v) = m.get_mut(&Value::String((*first).to_owned())) {
+                redact_path(v, rest);
+            }
+        }
+        Value::Seq(s) => {
+            for v in s {
+                redact_path(v, path);
+            }
+        }
+        Value::Option(Some(v)) | Value::Newtype(v) => redact_path(v, path),
+        _ => {}
+    }
+}
+
+impl Value {
+    /// Replaces values at the given paths with "***". Paths are dot-separated (an optional "$."
+    /// prefix is ignored), sequences are processed element-wise, e.g. "users.password" redacts
+    /// passwords of all users. Missing paths are ignored
+    pub fn redact(mut self, paths: &[&str]) -> Value {
+        self.redact_in_place(paths);
+        self
+    }
+    pub fn redact_in_place(&mut self, paths: &[&str]) {
+        for path in paths {
+            let path = path.strip_prefix("$.").unwrap_or(path);
+            if path.is_empty() {
+                continue;
+            }
+            let chunks: Vec<&str> = path.split('.').collect();
+            redact_path(self, &chunks);
+        }
+    }
+}
+
+/// Redacts map fields, which keys contain any of the patterns (case-insensitive), recursively
+#[derive(Debug, Clone)]
+pub struct Redactor {
+    patterns: Vec<String>,
+}
+
+impl Default for Redactor {
+    fn default() -> Self {
+        Self::new(DEFAULT_REDACT_PATTERNS)
+    }
+}
+
+impl Redactor {
+    pub fn new<S: AsRef<str>>(patterns: &[S]) -> Self {
+        Self {
+            patterns: patterns.iter().map(|p| p.as_ref().to_lowercase()).collect(),
+        }
+    }
+    pub fn add_pattern(mut self, pattern: &str) -> Self {
+        self.patterns.push(pattern.to_lowercase());
+        self
+    }
+    pub fn is_secret_key(&self, key: &str) -> bool {
+        let key = key.to_lowercase();
+        self.patterns.iter().any(|p| key.contains(p.as_str()))
+    }
+    /// Returns a redacted copy of the value
+    pub fn redact(&self, value: &Value) -> Value {
+        let mut value = value.clone();
+        self.redact_in_place(&mut value);
+        value
+    }
+    pub fn redact_in_place(&self, value: &mut Value) {
+        match value {
+            Value::Map(m) => {
+                for (k, v) in m.iter_mut() {
+                    if let Value::String(key) = k {
+                        if self.is_secret_key(key) && !v.is_unit() {
+                            *v = Value::String(REDACTED.to_owned());
+                            continue;
+                        }
+                    }
+                    self.redact_in_place(v);
+                }
+            }
+            Value::Seq(s) => {
+                for v in s {
+                    self.redact_in_place(v);
+                }
+            }
+            Value::Option(Some(v)) | Value::Newtype(v) => self.redact_in_place(v),
+            _ => {}
+        }
+    }
+}
@@ -1,7 +1,10 @@
+#[allow(unused_imports)]
+use crate::alloc_prelude::*;
+use alloc::collections::BTreeMap;
+use core::fmt;
 use serde::ser;
-use std::collections::BTreeMap;
+#[cfg(feature = "std")]
 use std::error::Error;
-use std::fmt;
 
 use crate::Value;
 
@@ -18,12 +21,16 @@ impl fmt::Display for SerializerError {
     }
 }
 
+#[cfg(feature = "std")]
 impl Error for SerializerError {
     fn description(&self) -> &str {
         "Value serializer error"
     }
 }
 
+#[cfg(not(feature = "std"))]
+impl ser::StdError for SerializerError {}
+
 impl ser::Error for SerializerError {
     fn custom<T: fmt::Display>(msg: T) -> SerializerError {
         SerializerError::Custom(msg.to_string())
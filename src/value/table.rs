@@ -0,0 +1,254 @@
+//! Helpers for tables: [`Value::Seq`] of [`Value::Map`] rows (e.g. RPC method results)
+use super::Value;
+#[allow(unused_imports)]
+use crate::alloc_prelude::*;
+use crate::{EResult, Error};
+use alloc::collections::BTreeMap;
+use core::cmp::Ordering;
+
+pub type Row = BTreeMap<Value, Value>;
+
+/// Aggregation functions for [`Table::group_by()`]. Sum and mean are calculated for numeric
+/// values only, missing and unit values are skipped
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Agg {
+    Count,
+    Sum,
+    Mean,
+    Min,
+    Max,
+    First,
+    Last,
+}
+
+#[derive(Debug, Clone)]
+pub struct SortKey {
+    column: String,
+    desc: bool,
+}
+
+impl SortKey {
+    pub fn asc(column: &str) -> Self {
+        Self {
+            column: column.to_owned(),
+            desc: false,
+        }
+    }
+    pub fn desc(column: &str) -> Self {
+        Self {
+            column: column.to_owned(),
+            desc: true,
+        }
+    }
+}
+
+#[inline]
+fn key(column: &str) -> Value {
+    Value::String(column.to_owned())
+}
+
+/// Gets a row field
+#[inline]
+pub fn field<'a>(row: &'a Row, column: &str) -> Option<&'a Value> {
+    row.get(&key(column))
+        .filter(|v| !matches!(v, Value::Unit | Value::Option(None)))
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Table {
+    rows: Vec<Row>,
+}
+
+impl TryFrom<Value> for Table {
+    type Error = Error;
+    fn try_from(value: Value) -> EResult<Self> {
+        let Value::Seq(s) = value else {
+            return Err(Error::invalid_data("table must be a sequence"));
+        };
+        let rows = s
+            .into_iter()
+            .map(|row| {
+                if let Value::Map(m) = row {
+                    Ok(m)
+                } else {
+                    Err(Error::invalid_data("table rows must be maps"))
+                }
+            })
+            .collect::<EResult<Vec<Row>>>()?;
+        Ok(Self { rows })
+    }
+}
+
+impl From<Table> for Value {
+    #[inline]
+    fn from(table: Table) -> Self {
+        table.into_value()
+    }
+}
+
+impl Table {
+    #[inline]
+    pub fn new(rows: Vec<Row>) -> Self {
+        Self { rows }
+    }
+    #[inline]
+    pub fn rows(&self) -> &[Row] {
+        &self.rows
+    }
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.rows.len()
+    }
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+    pub fn into_value(self) -> Value {
+        Value::Seq(self.rows.into_iter().map(Value::Map).collect())
+    }
+    /// Keeps the rows, matching the predicate
+    pub fn filter<F>(mut self, predicate: F) -> Self
+    where
+        F: Fn(&Row) -> bool,
+    {
+        self.rows.retain(predicate);
+        self
+    }
+    /// Keeps the rows, the column value of which matches the predicate (missing values are
+    /// passed as None)
+    pub fn filter_field<F>(self, column: &str, predicate: F) -> Self
+    where
+        F: Fn(Option<&Value>) -> bool,
+    {
+        self.filter(|row| predicate(field(row, column)))
+    }
+    /// Keeps the specified columns only
+    pub fn project(mut self, columns: &[&str]) -> Self {
+        let keys: Vec<Value> = columns.iter().map(|c| key(c)).collect();
+        for row in &mut self.rows {
+            row.retain(|k, _| keys.contains(k));
+        }
+        self
+    }
+    /// Sorts the rows by the keys. Rows with missing fields go first for ascending order
+    pub fn sort(mut self, keys: &[SortKey]) -> Self {
+        self.rows.sort_by(|a, b| {
+            for k in keys {
+                let ord = field(a, &k.column).cmp(&field(b, &k.column));
+                let ord = if k.desc { ord.reverse() } else { ord };
+                if ord != Ordering::Equal {
+                    return ord;
+                }
+            }
+            Ordering::Equal
+        });
+        self
+    }
+    #[inline]
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.rows.truncate(limit);
+        self
+    }
+    /// Groups the rows by the key columns. Output rows contain the key columns and the
+    /// aggregated columns, specified as (source column, function, output column)
+    pub fn group_by(self, keys: &[&str], aggs: &[(&str, Agg, &str)]) -> Self {
+        let mut groups: BTreeMap<Vec<Option<Value>>, Vec<Row>> = BTreeMap::new();
+        for row in self.rows {
+            let group_key = keys.iter().map(|k| field(&row, k).cloned()).collect();
+            groups.entry(group_key).or_default().push(row);
+        }
+        let rows = groups
+            .into_iter()
+            .map(|(group_key, rows)| {
+                let mut out = Row::new();
+                for (k, v) in keys.iter().zip(group_key) {
+                    out.insert(key(k), v.unwrap_or_default());
+                }
+                for (column, agg, name) in aggs {
+                    out.insert(key(name), aggregate(&rows, column, *agg));
+                }
+                out
+            })
+            .collect();
+        Self { rows }
+    }
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn aggregate(rows: &[Row], column: &str, agg: Agg) -> Value {
+    let mut values = rows.iter().filter_map(|row| field(row, column));
+    let numeric = || {
+        rows.iter()
+            .filter_map(|row| field(row, column))
+            .filter(|v| v.is_numeric_type())
+            .filter_map(|v| f64::try_from(v).ok())
+    };
+    match agg {
+        Agg::Count => Value::U64(values.count() as u64),
+        Agg::First => values.next().cloned().unwrap_or_default(),
+        Agg::Last => values.next_back().cloned().unwrap_or_default(),
+        Agg::Min => values.min().cloned().unwrap_or_default(),
+        Agg::Max => values.max().cloned().unwrap_or_default(),
+        Agg::Sum => Value::F64(numeric().sum()),
+        Agg::Mean => {
+            let (sum, count) = numeric().fold((0.0, 0_usize), |(s, c), v| (s + v, c + 1));
+            if count == 0 {
+                Value::Unit
+            } else {
+                Value::F64(sum / count as f64)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{field, Agg, SortKey, Table};
+    use crate::value::Value;
+
+    fn table() -> Table {
+        let v: Value = serde_json::from_str(
+            r#"[{"oid":"sensor:a/s1","status":1,"value":10,"node":"n1"},
+            {"oid":"sensor:a/s2","status":1,"value":20.5,"node":"n2"},
+            {"oid":"sensor:b/s3","status":-1,"value":null,"node":"n1"},
+            {"oid":"unit:a/u1","status":0,"value":"x","node":"n1"}]"#,
+        )
+        .unwrap();
+        Table::try_from(v).unwrap()
+    }
+
+    #[test]
+    fn test_table() {
+        assert!(Table::try_from(Value::U8(1)).is_err());
+        assert!(Table::try_from(Value::Seq(vec![Value::U8(1)])).is_err());
+        let t = table()
+            .filter_field("status", |v| v.is_some_and(|s| *s != Value::I8(-1)))
+            .project(&["oid", "value"])
+            .sort(&[SortKey::desc("oid")])
+            .limit(2);
+        assert_eq!(
+            serde_json::to_string(&t.into_value()).unwrap(),
+            r#"[{"oid":"unit:a/u1","value":"x"},{"oid":"sensor:a/s2","value":20.5}]"#
+        );
+        let t = table().group_by(
+            &["node"],
+            &[
+                ("value", Agg::Count, "count"),
+                ("value", Agg::Sum, "sum"),
+                ("value", Agg::Mean, "mean"),
+                ("oid", Agg::Max, "last_oid"),
+            ],
+        );
+        assert_eq!(t.len(), 2);
+        let n1 = &t.rows()[0];
+        assert_eq!(field(n1, "node"), Some(&Value::String("n1".to_owned())));
+        assert_eq!(field(n1, "count"), Some(&Value::U64(2)));
+        assert_eq!(field(n1, "sum"), Some(&Value::F64(10.0)));
+        assert_eq!(
+            field(n1, "last_oid"),
+            Some(&Value::String("unit:a/u1".to_owned()))
+        );
+        let n2 = &t.rows()[1];
+        assert_eq!(field(n2, "mean"), Some(&Value::F64(20.5)));
+    }
+}
@@ -0,0 +1,294 @@
+use super::Value;
+use crate::{EResult, Error};
+use std::str::FromStr;
+
+const OPEN: &str = "{{";
+const CLOSE: &str = "}}";
+/// Maximum precision of the round filter (f64 has no more significant decimal digits)
+const MAX_ROUND_DIGITS: u32 = 15;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Filter {
+    Upper,
+    Lower,
+    Trim,
+    Round(u32),
+    Default(Value),
+    Json,
+}
+
+impl Filter {
+    fn parse(s: &str) -> EResult<Self> {
+        let (name, arg) = if let Some(pos) = s.find('(') {
+            let Some(arg) = s[pos + 1..].strip_suffix(')') else {
+                return Err(Error::invalid_params(format!("invalid filter: {}", s)));
+            };
+            (s[..pos].trim(), Some(arg.trim()))
+        } else {
+            (s, None)
+        };
+        match (name, arg) {
+            ("upper", None) => Ok(Filter::Upper),
+            ("lower", None) => Ok(Filter::Lower),
+            ("trim", None) => Ok(Filter::Trim),
+            ("json", None) => Ok(Filter::Json),
+            ("round", None) => Ok(Filter::Round(0)),
+            ("round", Some(digits)) => match digits.parse() {
+                Ok(v) if v <= MAX_ROUND_DIGITS => Ok(Filter::Round(v)),
+                _ => Err(Error::invalid_params(format!(
+                    "invalid round precision: {} (max: {})",
+                    digits, MAX_ROUND_DIGITS
+                ))),
+            },
+            ("default", Some(arg)) => Ok(Filter::Default(parse_arg(arg))),
+            _ => Err(Error::invalid_params(format!("invalid filter: {}", s))),
+        }
+    }
+    fn apply(&self, value: Option<Value>) -> EResult<Option<Value>> {
+        let Some(value) = value else {
+            return Ok(if let Filter::Default(v) = self {
+                Some(v.clone())
+            } else {
+                None
+            });
+        };
+        Ok(Some(match self {
+            Filter::Upper => Value::String(to_text(&value)?.to_uppercase()),
+            Filter::Lower => Value::String(to_text(&value)?.to_lowercase()),
+            Filter::Trim => Value::String(to_text(&value)?.trim().to_owned()),
+            Filter::Json => Value::String(serde_json::to_string(&value)?),
+            Filter::Round(digits) => {
+                let n: f64 = value.try_into()?;
+                let factor = 10f64.powi(i32::try_from(*digits)?);
+                Value::String(format!(
+                    "{:.*}",
+                    *digits as usize,
+                    (n * factor).round() / factor
+                ))
+            }
+            Filter::Default(_) => value,
+        }))
+    }
+}
+
+/// Parses a filter argument: quoted strings are taken as-is, anything else is tried as JSON and
+/// falls back to a plain string
+fn parse_arg(arg: &str) -> Value {
+    for q in ['"', '\''] {
+        if let Some(s) = arg.strip_prefix(q).and_then(|s| s.strip_suffix(q)) {
+            if arg.len() > 1 {
+                return Value::String(s.to_owned());
+            }
+        }
+    }
+    serde_json::from_str(arg).unwrap_or_else(|_| Value::String(arg.to_owned()))
+}
+
+fn to_text(value: &Value) -> EResult<String> {
+    match value {
+        Value::Seq(_) | Value::Map(_) => Ok(serde_json::to_string(value)?),
+        Value::Option(Some(v)) | Value::Newtype(v) => to_text(v),
+        _ => Ok(value.to_string()),
+    }
+}
+
+/// Splits an expression by pipes, ignoring ones inside quoted filter arguments
+fn split_pipes(expr: &str) -> Vec<&str> {
+    let mut result = Vec::new();
+    let mut quote: Option<char> = None;
+    let mut start = 0;
+    for (i, c) in expr.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), _) if q == c => quote = None,
+            (None, '|') => {
+                result.push(expr[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    result.push(expr[start..].trim());
+    result
+}
+
+fn lookup<'a>(mut value: &'a Value, path: &[String]) -> Option<&'a Value> {
+    for chunk in path {
+        while let Value::Option(Some(v)) | Value::Newtype(v) = value {
+            value = v;
+        }
+        value = match value {
+            Value::Map(m) => m.get(&Value::String(chunk.clone()))?,
+            Value::Seq(s) => s.get(chunk.parse::<usize>().ok()?)?,
+            _ => return None,
+        };
+    }
+    match value {
+        Value::Unit | Value::Option(None) => None,
+        _ => Some(value),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Text(String),
+    Expr {
+        source: String,
+        path: Vec<String>,
+        filters: Vec<Filter>,
+    },
+}
+
+/// A minimal text template with `{{ path.to.field | filter }}` substitutions over a [`Value`]
+/// context
+///
+/// Paths are dot-separated (an optional "$." prefix is ignored, numeric chunks index
+/// sequences). Supported filters: upper, lower, trim, json, round(digits), default(value).
+/// Missing and null fields are rendered as empty strings unless the template is strict
+///
+/// ```
+/// use eva_common::value::{Template, to_value};
+///
+/// let ctx = to_value(serde_json::json!({"sensor": {"name": "t1", "value": 21.456}})).unwrap();
+/// let tpl: Template = "{{ sensor.name | upper }}: {{ sensor.value | round(1) }} {{ unit | default(\"C\") }}"
+///     .parse()
+///     .unwrap();
+/// assert_eq!(tpl.render(&ctx).unwrap(), "T1: 21.5 C");
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    parts: Vec<Part>,
+    strict: bool,
+}
+
+impl FromStr for Template {
+    type Err = Error;
+
+    fn from_str(s: &str) -> EResult<Self> {
+        let mut parts = Vec::new();
+        let mut rest = s;
+        while let Some(pos) = rest.find(OPEN) {
+            if pos > 0 {
+                parts.push(Part::Text(rest[..pos].to_owned()));
+            }
+            let expr_start = &rest[pos + OPEN.len()..];
+            let Some(end) = expr_start.find(CLOSE) else {
+                return Err(Error::invalid_params("unterminated template expression"));
+            };
+            let expr = &expr_start[..end];
+            let mut chunks = split_pipes(expr).into_iter();
+            let path = chunks.next().unwrap_or_default();
+            let path = path.strip_prefix("$.").unwrap_or(path);
+            if path.is_empty() {
+                return Err(Error::invalid_params("empty template expression"));
+            }
+            parts.push(Part::Expr {
+                source: expr.trim().to_owned(),
+                path: path.split('.').map(ToOwned::to_owned).collect(),
+                filters: chunks.map(Filter::parse).collect::<EResult<_>>()?,
+            });
+            rest = &expr_start[end + CLOSE.len()..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Text(rest.to_owned()));
+        }
+        Ok(Self {
+            parts,
+            strict: false,
+        })
+    }
+}
+
+impl Template {
+    /// Fails rendering if a field is missing and has no default
+    #[inline]
+    pub fn strict(mut self) -> Self {
+        self.strict = true;
+        self
+    }
+    /// Field paths, referenced by the template
+    pub fn fields(&self) -> impl Iterator<Item = String> + '_ {
+        self.parts.iter().filter_map(|p| match p {
+            Part::Expr { path, .. } => Some(path.join(".")),
+            Part::Text(_) => None,
+        })
+    }
+    /// Renders the template with the given context
+    pub fn render(&self, ctx: &Value) -> EResult<String> {
+        let mut result = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Text(s) => result.push_str(s),
+                Part::Expr {
+                    source,
+                    path,
+                    filters,
+                } => {
+                    let mut value = lookup(ctx, path).cloned();
+                    for filter in filters {
+                        value = filter.apply(value)?;
+                    }
+                    if let Some(value) = value {
+                        result.push_str(&to_text(&value)?);
+                    } else if self.strict {
+                        return Err(Error::not_found(format!(
+                            "template field not found: {}",
+                            source
+                        )));
+                    }
+                }
+            }
+        }
+        Ok(result)
+    }
+}
+
+impl Value {
+    /// Renders a template string with the value as the context
+    pub fn render_template(&self, template: &str) -> EResult<String> {
+        template.parse::<Template>()?.render(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Template;
+    use crate::value::Value;
+
+    #[test]
+    fn test_template() {
+        let ctx: Value = serde_json::from_str(
+            r#"{"item":{"oid":"sensor:t1","value":21.456,"tags":["a","b"]},"note":" Hi | there "}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            ctx.render_template("{{ item.oid|upper }} = {{ $.item.value | round(2) }}")
+                .unwrap(),
+            "SENSOR:T1 = 21.46"
+        );
+        assert_eq!(
+            ctx.render_template("{{item.tags.1}} {{ item.tags | json }} [{{ note | trim }}]")
+                .unwrap(),
+            r#"b ["a","b"] [Hi | there]"#
+        );
+        assert_eq!(
+            ctx.render_template("{{ missing | default(\"n|a\") | upper }}{{ item.x }}")
+                .unwrap(),
+            "N|A"
+        );
+        let tpl: Template = "{{ item.x }}".parse().unwrap();
+        assert!(tpl.clone().strict().render(&ctx).is_err());
+        assert_eq!(tpl.fields().collect::<Vec<_>>(), ["item.x"]);
+        assert!("{{ item".parse::<Template>().is_err());
+        assert!("{{ item | unknown }}".parse::<Template>().is_err());
+        assert!("{{ item | round(16) }}".parse::<Template>().is_err());
+        assert!("{{ item | round(4294967295) }}"
+            .parse::<Template>()
+            .is_err());
+        assert!("{{ item | round }}"
+            .parse::<Template>()
+            .unwrap()
+            .render(&ctx)
+            .is_err());
+    }
+}
@@ -0,0 +1,194 @@
+//! Webhook signing and verification
+//!
+//! The signature header has the form `t=<unix timestamp>,v1=<hex HMAC-SHA256>`, where the HMAC
+//! is calculated over `<timestamp>.<body>`. Verifiers may accept several secrets (for key
+//! rotation), reject requests with timestamps outside of the tolerance window and signatures
+//! already seen within the window (replays)
+use crate::auth::constant_time_eq;
+use crate::{EResult, Error};
+use parking_lot::Mutex;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// HTTP header for webhook signatures
+pub const SIGNATURE_HEADER: &str = "x-eva-signature";
+pub const DEFAULT_TOLERANCE: Duration = Duration::from_secs(300);
+
+const SIGNATURE_SCHEME: &str = "v1";
+const BLOCK_SIZE: usize = 64;
+
+#[inline]
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+#[inline]
+fn sha256(parts: &[&[u8]]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update(part);
+    }
+    let mut digest = [0u8; 32];
+    digest.copy_from_slice(&hasher.finalize());
+    digest
+}
+
+/// Calculates HMAC-SHA256 (RFC 2104)
+pub fn hmac_sha256(key: &[u8], data: &[u8]) -> [u8; 32] {
+    let mut block = [0u8; BLOCK_SIZE];
+    if key.len() > BLOCK_SIZE {
+        block[..32].copy_from_slice(&sha256(&[key]));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+    let mut ipad = [0x36u8; BLOCK_SIZE];
+    let mut opad = [0x5cu8; BLOCK_SIZE];
+    for ((i, o), k) in ipad.iter_mut().zip(opad.iter_mut()).zip(block) {
+        *i ^= k;
+        *o ^= k;
+    }
+    let inner = sha256(&[&ipad, data]);
+    sha256(&[&opad, &inner])
+}
+
+fn signature(secret: &[u8], t: u64, body: &[u8]) -> [u8; 32] {
+    let mut data = format!("{}.", t).into_bytes();
+    data.extend(body);
+    hmac_sha256(secret, &data)
+}
+
+/// Signs outgoing webhooks
+pub struct Signer {
+    secret: Vec<u8>,
+}
+
+impl Signer {
+    pub fn new(secret: &[u8]) -> Self {
+        Self {
+            secret: secret.to_vec(),
+        }
+    }
+    /// Returns the signature header value for the body with the current time
+    pub fn sign(&self, body: &[u8]) -> String {
+        self.sign_at(body, now())
+    }
+    pub fn sign_at(&self, body: &[u8], t: u64) -> String {
+        format!(
+            "t={},{}={}",
+            t,
+            SIGNATURE_SCHEME,
+            hex::encode(signature(&self.secret, t, body))
+        )
+    }
+}
+
+/// Verifies incoming webhooks
+pub struct Verifier {
+    secrets: Vec<Vec<u8>>,
+    tolerance: Duration,
+    seen: Mutex<HashMap<[u8; 32], u64>>,
+}
+
+impl Verifier {
+    pub fn new(secret: &[u8]) -> Self {
+        Self {
+            secrets: vec![secret.to_vec()],
+            tolerance: DEFAULT_TOLERANCE,
+            seen: <_>::default(),
+        }
+    }
+    /// Accepts an additional secret (e.g. the previous one during key rotation)
+    pub fn secret(mut self, secret: &[u8]) -> Self {
+        self.secrets.push(secret.to_vec());
+        self
+    }
+    /// Maximum allowed difference between the signature timestamp and the local clock
+    #[inline]
+    pub fn tolerance(mut self, tolerance: Duration) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+    /// Verifies the signature header value for the body
+    pub fn verify(&self, header: &str, body: &[u8]) -> EResult<()> {
+        self.verify_at(header, body, now())
+    }
+    pub fn verify_at(&self, header: &str, body: &[u8], now: u64) -> EResult<()> {
+        let mut t: Option<u64> = None;
+        let mut signatures = Vec::new();
+        for chunk in header.split(',') {
+            match chunk.trim().split_once('=') {
+                Some(("t", v)) => {
+                    t = Some(
+                        v.parse()
+                            .map_err(|_| Error::access("invalid signature timestamp"))?,
+                    );
+                }
+                Some((SIGNATURE_SCHEME, v)) => {
+                    signatures
+                        .push(hex::decode(v).map_err(|_| Error::access("invalid signature"))?);
+                }
+                _ => {}
+            }
+        }
+        let Some(t) = t else {
+            return Err(Error::access("signature timestamp missing"));
+        };
+        if now.abs_diff(t) > self.tolerance.as_secs() {
+            return Err(Error::access(
+                "signature timestamp is outside of the tolerance",
+            ));
+        }
+        let Some(valid) = self.secrets.iter().find_map(|secret| {
+            let expected = signature(secret, t, body);
+            signatures
+                .iter()
+                .any(|s| constant_time_eq(s, &expected))
+                .then_some(expected)
+        }) else {
+            return Err(Error::access("invalid signature"));
+        };
+        let mut seen = self.seen.lock();
+        let tolerance = self.tolerance.as_secs();
+        seen.retain(|_, seen_t| now.abs_diff(*seen_t) <= tolerance);
+        if seen.insert(valid, t).is_some() {
+            return Err(Error::access("webhook replay detected"));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{hmac_sha256, Signer, Verifier};
+    use std::time::Duration;
+
+    #[test]
+    fn test_webhook_signature() {
+        // RFC 4231, test case 2
+        assert_eq!(
+            hex::encode(hmac_sha256(b"Jefe", b"what do ya want for nothing?")),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        let t = 1_700_000_000;
+        let body = br#"{"status":"ok"}"#;
+        let header = Signer::new(b"new").sign_at(body, t);
+        assert!(header.starts_with("t=1700000000,v1="));
+        let verifier = Verifier::new(b"new")
+            .secret(b"old")
+            .tolerance(Duration::from_secs(50));
+        verifier.verify_at(&header, body, t + 10).unwrap();
+        assert!(verifier.verify_at(&header, body, t + 20).is_err());
+        let old = Signer::new(b"old").sign_at(body, t + 1);
+        assert!(verifier.verify_at(&old, b"{}", t + 1).is_err());
+        verifier.verify_at(&old, body, t + 1).unwrap();
+        let late = Signer::new(b"new").sign_at(body, t + 2);
+        assert!(verifier.verify_at(&late, body, t + 100).is_err());
+        assert!(verifier.verify_at("v1=00", body, t).is_err());
+        assert!(Verifier::new(b"other")
+            .verify_at(&Signer::new(b"new").sign_at(body, t), body, t)
+            .is_err());
+    }
+}
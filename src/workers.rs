@@ -1,3 +1,5 @@
+#[cfg(feature = "services")]
+use crate::services::Initial;
 use crate::EResult;
 use crate::{Error, ErrorKind};
 use std::sync::Arc;
@@ -71,3 +73,385 @@ pub async fn destroy_scheduler(worker_id: &str) -> EResult<()> {
         .destroy_scheduler(worker_id)
         .map_err(Into::into)
 }
+
+/// Returns the current process resident set size (bytes)
+///
+/// # Errors
+///
+/// Will return `Err` if procfs is not available
+pub fn process_rss() -> EResult<u64> {
+    let status = std::fs::read_to_string("/proc/self/status")?;
+    for line in status.lines() {
+        if let Some(v) = line.strip_prefix("VmRSS:") {
+            let kb: u64 = v
+                .trim()
+                .trim_end_matches("kB")
+                .trim_end()
+                .parse()
+                .map_err(Error::invalid_data)?;
+            return Ok(kb * 1024);
+        }
+    }
+    Err(Error::not_found("VmRSS not found in the process status"))
+}
+
+/// Memory guard hard limit action
+#[derive(Clone)]
+pub enum MemGuardAction {
+    /// Log an error only
+    Log,
+    /// Set the service fail mode
+    #[cfg(feature = "services")]
+    FailMode(Arc<Initial>),
+    /// Call a custom function with the current RSS
+    Callback(Arc<dyn Fn(u64) + Send + Sync>),
+}
+
+/// Memory guard state
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum MemGuardState {
+    Ok,
+    SoftLimit,
+    HardLimit,
+}
+
+/// Samples the process RSS with the given interval, warns when the soft limit is reached and
+/// executes the action when the hard limit is reached. Both are triggered once until RSS goes
+/// back below the limit
+pub struct MemGuard {
+    soft_limit: Option<u64>,
+    hard_limit: u64,
+    interval: Duration,
+    action: MemGuardAction,
+    state: MemGuardState,
+}
+
+impl MemGuard {
+    /// Creates a new guard with the hard limit (bytes)
+    pub fn new(hard_limit: u64) -> Self {
+        Self {
+            soft_limit: None,
+            hard_limit,
+            interval: Duration::from_secs(1),
+            action: MemGuardAction::Log,
+            state: MemGuardState::Ok,
+        }
+    }
+    pub fn soft_limit(mut self, soft_limit: u64) -> Self {
+        self.soft_limit = Some(soft_limit);
+        self
+    }
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+    pub fn action(mut self, action: MemGuardAction) -> Self {
+        self.action = action;
+        self
+    }
+    #[inline]
+    pub fn state(&self) -> MemGuardState {
+        self.state
+    }
+    /// Processes a RSS sample, returns the new state
+    pub fn check(&mut self, rss: u64) -> MemGuardState {
+        let state = if rss >= self.hard_limit {
+            MemGuardState::HardLimit
+        } else if self.soft_limit.is_some_and(|l| rss >= l) {
+            MemGuardState::SoftLimit
+        } else {
+            MemGuardState::Ok
+        };
+        if state != self.state {
+            match state {
+                MemGuardState::HardLimit => {
+                    log::error!(
+                        "memory hard limit reached: {} bytes (limit: {})",
+                        rss,
+                        self.hard_limit
+                    );
+                    match self.action {
+                        MemGuardAction::Log => {}
+                        #[cfg(feature = "services")]
+                        MemGuardAction::FailMode(ref initial) => initial.set_fail_mode(true),
+                        MemGuardAction::Callback(ref f) => f(rss),
+                    }
+                }
+                MemGuardState::SoftLimit if self.state == MemGuardState::Ok => {
+                    log::warn!(
+                        "memory soft limit reached: {} bytes (limit: {})",
+                        rss,
+                        self.soft_limit.unwrap_or_default()
+                    );
+                }
+                _ => {}
+            }
+            self.state = state;
+        }
+        state
+    }
+    /// Runs the guard loop
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if RSS can not be obtained
+    pub async fn run(mut self) -> EResult<()> {
+        let mut int = tokio::time::interval(self.interval);
+        loop {
+            int.tick().await;
+            self.check(process_rss()?);
+        }
+    }
+}
+
+pub const DEFAULT_BATCH_QUEUE_SIZE: usize = 100_000;
+
+/// Queues outgoing bus publications, coalescing them by topic: if a topic is already queued,
+/// its payload is replaced with the newer one, keeping the original queue position. The queue
+/// is flushed in batches, limited by the byte budget per tick. [`BatchPublisher::run()`] flushes
+/// the queue every interval or as soon as the queued payloads reach the budget
+///
+/// The number of queued topics is limited, publications for new topics are dropped while the
+/// queue is full
+pub struct BatchPublisher {
+    inner: parking_lot::Mutex<BatchQueue>,
+    budget: usize,
+    max_len: usize,
+    ready: Notify,
+}
+
+#[derive(Default)]
+struct BatchQueue {
+    order: std::collections::VecDeque<String>,
+    payloads: std::collections::HashMap<String, Vec<u8>>,
+    size: usize,
+    coalesced: u64,
+    dropped: u64,
+}
+
+impl BatchPublisher {
+    /// Creates a new publisher with the byte budget per flush
+    pub fn new(budget: usize) -> Self {
+        Self {
+            inner: <_>::default(),
+            budget,
+            max_len: DEFAULT_BATCH_QUEUE_SIZE,
+            ready: Notify::new(),
+        }
+    }
+    /// Max number of queued topics (the default is [`DEFAULT_BATCH_QUEUE_SIZE`])
+    #[inline]
+    pub fn max_len(mut self, max_len: usize) -> Self {
+        self.max_len = max_len;
+        self
+    }
+    /// Queues a publication, returns false if the queue is full and the publication is dropped
+    pub fn push(&self, topic: &str, payload: Vec<u8>) -> bool {
+        let mut q = self.inner.lock();
+        let len = payload.len();
+        if let Some(p) = q.payloads.get_mut(topic) {
+            let prev = std::mem::replace(p, payload).len();
+            q.size = q.size - prev + len;
+            q.coalesced += 1;
+        } else if q.order.len() >= self.max_len {
+            q.dropped += 1;
+            return false;
+        } else {
+            q.order.push_back(topic.to_owned());
+            q.payloads.insert(topic.to_owned(), payload);
+            q.size += len;
+        }
+        if q.size >= self.budget {
+            self.ready.notify_one();
+        }
+        true
+    }
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.inner.lock().order.len()
+    }
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.inner.lock().order.is_empty()
+    }
+    /// Total size of queued payloads
+    #[inline]
+    pub fn size(&self) -> usize {
+        self.inner.lock().size
+    }
+    /// Number of publications, superseded by newer ones
+    #[inline]
+    pub fn coalesced(&self) -> u64 {
+        self.inner.lock().coalesced
+    }
+    /// Number of publications, dropped because the queue is full
+    #[inline]
+    pub fn dropped(&self) -> u64 {
+        self.inner.lock().dropped
+    }
+    /// Takes the next batch in the queue order. The batch contains at least one publication,
+    /// even if it exceeds the budget
+    pub fn take_batch(&self) -> Vec<(String, Vec<u8>)> {
+        let mut q = self.inner.lock();
+        let mut result = Vec::new();
+        let mut size = 0;
+        while let Some(topic) = q.order.front() {
+            let len = q.payloads.get(topic).map_or(0, Vec::len);
+            if !result.is_empty() && size + len > self.budget {
+                break;
+            }
+            size += len;
+            let topic = q.order.pop_front().unwrap();
+            let payload = q.payloads.remove(&topic).unwrap_or_default();
+            result.push((topic, payload));
+        }
+        q.size -= size;
+        result
+    }
+    /// Returns unsent publications to the queue head. If a topic has been queued again in the
+    /// meantime, the newer payload is kept and moved to the head
+    fn requeue<I>(&self, batch: I)
+    where
+        I: DoubleEndedIterator<Item = (String, Vec<u8>)>,
+    {
+        let mut q = self.inner.lock();
+        for (topic, payload) in batch.rev() {
+            if q.payloads.contains_key(&topic) {
+                q.order.retain(|t| *t != topic);
+            } else {
+                q.size += payload.len();
+                q.payloads.insert(topic.clone(), payload);
+            }
+            q.order.push_front(topic);
+        }
+    }
+    /// Publishes the next batch to the bus, returns number of publications sent
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` on bus errors, unsent publications of the batch are returned to the
+    /// queue
+    #[cfg(feature = "bus-rpc")]
+    pub async fn flush<C>(&self, client: &mut C, qos: busrt::QoS) -> EResult<usize>
+    where
+        C: busrt::client::AsyncClient + ?Sized,
+    {
+        let mut batch = self.take_batch().into_iter();
+        let mut count = 0;
+        while let Some((topic, payload)) = batch.next() {
+            if let Err(e) = client.publish(&topic, payload.as_slice().into(), qos).await {
+                self.requeue(std::iter::once((topic, payload)).chain(batch));
+                return Err(e.into());
+            }
+            count += 1;
+        }
+        Ok(count)
+    }
+    /// Publishes batches to the bus with the given interval or as soon as the queue reaches the
+    /// budget, until a bus error occurs
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` on bus errors
+    #[cfg(feature = "bus-rpc")]
+    pub async fn run<C>(
+        &self,
+        client: Arc<Mutex<C>>,
+        interval: Duration,
+        qos: busrt::QoS,
+    ) -> EResult<()>
+    where
+        C: busrt::client::AsyncClient + ?Sized,
+    {
+        let mut int = tokio::time::interval(interval);
+        int.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            tokio::select! {
+                _ = int.tick() => {}
+                () = self.ready.notified() => {}
+            }
+            while !self.is_empty() {
+                self.flush(&mut *client.lock().await, qos).await?;
+                if self.size() < self.budget {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BatchPublisher, MemGuard, MemGuardAction, MemGuardState};
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_mem_guard() {
+        let triggered = Arc::new(AtomicU64::new(0));
+        let t = triggered.clone();
+        let mut guard = MemGuard::new(1000)
+            .soft_limit(500)
+            .action(MemGuardAction::Callback(Arc::new(move |rss| {
+                t.store(rss, Ordering::SeqCst);
+            })));
+        assert_eq!(guard.check(100), MemGuardState::Ok);
+        assert_eq!(guard.check(600), MemGuardState::SoftLimit);
+        assert_eq!(guard.check(1200), MemGuardState::HardLimit);
+        assert_eq!(triggered.load(Ordering::SeqCst), 1200);
+        guard.check(1300);
+        assert_eq!(triggered.load(Ordering::SeqCst), 1200);
+        guard.check(100);
+        guard.check(1100);
+        assert_eq!(triggered.load(Ordering::SeqCst), 1100);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_process_rss() {
+        assert!(super::process_rss().unwrap() > 0);
+    }
+
+    #[test]
+    fn test_batch_publisher() {
+        let publisher = BatchPublisher::new(10);
+        publisher.push("ST/a", vec![1; 4]);
+        publisher.push("ST/b", vec![2; 4]);
+        publisher.push("ST/a", vec![3; 4]);
+        publisher.push("ST/c", vec![4; 4]);
+        assert_eq!(publisher.len(), 3);
+        assert_eq!(publisher.coalesced(), 1);
+        let batch = publisher.take_batch();
+        assert_eq!(
+            batch,
+            vec![
+                ("ST/a".to_owned(), vec![3; 4]),
+                ("ST/b".to_owned(), vec![2; 4])
+            ]
+        );
+        publisher.push("ST/d", vec![5; 20]);
+        assert_eq!(publisher.take_batch().len(), 1);
+        assert_eq!(publisher.take_batch()[0].0, "ST/d");
+        assert!(publisher.is_empty());
+        assert_eq!(publisher.size(), 0);
+        // failed batches are returned to the queue head, newer payloads are kept
+        let publisher = BatchPublisher::new(100).max_len(3);
+        assert!(publisher.push("ST/a", vec![1; 4]));
+        assert!(publisher.push("ST/b", vec![2; 4]));
+        let batch = publisher.take_batch();
+        assert!(publisher.push("ST/c", vec![3; 4]));
+        assert!(publisher.push("ST/b", vec![4; 2]));
+        publisher.requeue(batch.into_iter());
+        assert_eq!(publisher.size(), 10);
+        assert!(!publisher.push("ST/d", vec![5; 4]));
+        assert_eq!(publisher.dropped(), 1);
+        assert_eq!(
+            publisher.take_batch(),
+            vec![
+                ("ST/a".to_owned(), vec![1; 4]),
+                ("ST/b".to_owned(), vec![4; 2]),
+                ("ST/c".to_owned(), vec![3; 4])
+            ]
+        );
+    }
+}